@@ -13,7 +13,20 @@ export interface FieldConfig<K, Flags> {
    *
    * `text` - Indexes field values as `string`.
    *
-   * `date` - Indexes field values as ints but serialized as ISO 80601 strings in transit.
+   * `date` - Indexes field values as ints but serialized as RFC 3339 strings in transit, e.g. `2022-11-14T21:30:04Z`.
+   * Dates without an offset, e.g. `2022-11-14`, are read in the index's `time_zone`.
+   *
+   * `i64`, `u64`, `f64` - Indexes field values as signed integers, unsigned integers or floats. Numeric strings
+   * such as `"12.5"` and integral floats such as `3.0` are converted to the field type.
+   *
+   * `json` - Indexes nested objects under the field name, queryable by dotted path, e.g. `metadata.author.name:smith`.
+   *
+   * `ip` - Indexes IPv4 and IPv6 addresses for exact, range and CIDR queries, e.g. `client_ip:[10.0.0.0 TO 10.0.255.255]`.
+   *
+   * `bytes` - Stores opaque binary values, e.g. thumbnails, given as base64 strings. `INDEXED` bytes match exact values only.
+   *
+   * `facet` - Indexes hierarchical paths such as `/books/fiction`, counted with `facet` aggregations. Facets are always indexed,
+   * and a query for a facet also matches the facets below it.
    */
   kind: K;
 
@@ -23,28 +36,480 @@ export interface FieldConfig<K, Flags> {
    * Flag descriptions:
    *
    *
-   * `TEXT`    - (only for `text`) Marks this field for full-text indexing.
+   * `TEXT`    - (only for `text` and `json`) Marks this field for full-text indexing.
    *
    * `STRING`  - (only for `text`) Marks this field for exact-string indexing.
    *
-   * `INDEXED` - (only for `date`) Marks this field for ordered search indexing.
+   * `INDEXED` - (only for `date`, numeric, `ip` and `bytes` fields) Marks this field for ordered search indexing.
+   *
+   * `STORED`  - (only for `date`, numeric, `ip`, `json`, `bytes` and `facet` fields) Keeps the value in the index so it is returned by `search_only` indexes.
+   *
+   * `FAST`    - Stores values column-wise, required to sort by a `date` or numeric field.
    */
   flags: Flags[];
 }
 
-export type TextFieldConfig = FieldConfig<"text", "STRING" | "TEXT" | "FAST">;
+export type TokenizerConfig =
+  | { type: "default" }
+  | { type: "raw" }
+  | { type: "whitespace" }
+  | {
+      type: "ngram";
+      min_gram: number;
+      max_gram: number;
+      /** Only emit n-grams that start at the beginning of the value, for autocompletion. */
+      prefix_only?: boolean;
+    };
 
-export type DateFieldConfig = FieldConfig<"date", "INDEXED" | "FAST">;
+export type Language =
+  | "arabic"
+  | "danish"
+  | "dutch"
+  | "english"
+  | "finnish"
+  | "french"
+  | "german"
+  | "greek"
+  | "hungarian"
+  | "italian"
+  | "norwegian"
+  | "portuguese"
+  | "romanian"
+  | "russian"
+  | "spanish"
+  | "swedish"
+  | "tamil"
+  | "turkish";
 
-export type IntegerFieldConfig = FieldConfig<"i64", "INDEXED" | "FAST">;
+export interface TextFieldConfig
+  extends FieldConfig<"text", "STRING" | "TEXT" | "FAST"> {
+  /**
+   * Tokenizer used to analyze values, and query strings searching this field.
+   *
+   * `default` - Splits on non-alphanumeric characters and lowercases. Used by `TEXT`.
+   *
+   * `raw` - The whole value as one token, for exact matches. Used by `STRING`.
+   *
+   * `whitespace` - Splits on whitespace only, preserving case and punctuation.
+   *
+   * `ngram` - Lowercased character n-grams of the whole value, so that query strings match part of a value,
+   * e.g. `handle:zen` matches `Zenmaster`. Snippets are not generated for `ngram` fields.
+   *
+   * When neither `TEXT` nor `STRING` is set, the field is indexed for full-text search with this tokenizer.
+   * Changing the tokenizer of an existing index requires a reindex.
+   *
+   * @example
+   * ```ts
+   * { tokenizer: { type: "ngram", min_gram: 2, max_gram: 10, prefix_only: true } }
+   * ```
+   */
+  tokenizer?: TokenizerConfig;
+
+  /**
+   * Stem terms in this language, on top of the `default` tokenizer, so that e.g. `fishing` matches `fished`.
+   *
+   * Documents and query strings are stemmed alike. Can't be combined with a `tokenizer` other than `default`.
+   * Changing the language of an existing index requires a reindex.
+   */
+  language?: Language;
 
-export type JsonFieldConfig = FieldConfig<"json", "TEXT">;
+  /**
+   * Words dropped from values and query strings, on top of the `default` tokenizer.
+   *
+   * Can't be combined with a `tokenizer` other than `default`. Changing stopwords of an existing index requires a
+   * reindex.
+   *
+   * @example
+   * ```ts
+   * { stopwords: { language: "english", words: ["inc", "ltd"] } }
+   * ```
+   */
+  stopwords?: StopwordsConfig;
+
+  /**
+   * Fold accented and other non-ASCII characters to their ASCII equivalents in values and query strings, so that
+   * `café` matches `cafe`.
+   *
+   * Can't be combined with a `tokenizer` other than `default`. Changing it for an existing index requires a reindex.
+   */
+  ascii_folding?: boolean;
+}
+
+export interface StopwordsConfig {
+  /**
+   * Start from the built-in list for this language. Built-in lists exist for `dutch`, `english`, `french`, `german`,
+   * `italian`, `portuguese` and `spanish`.
+   */
+  language?: Language;
+
+  /** Additional words, matched ignoring case. */
+  words?: string[];
+}
+
+export type DateFieldConfig = FieldConfig<"date", "INDEXED" | "STORED" | "FAST">;
+
+export type NumericFieldFlag = "INDEXED" | "STORED" | "FAST";
+
+export type IntegerFieldConfig = FieldConfig<"i64", NumericFieldFlag>;
+
+export type UnsignedIntegerFieldConfig = FieldConfig<"u64", NumericFieldFlag>;
+
+export type FloatFieldConfig = FieldConfig<"f64", NumericFieldFlag>;
+
+export type BytesFieldConfig = FieldConfig<
+  "bytes",
+  "INDEXED" | "STORED" | "FAST"
+>;
+
+export type IpFieldConfig = FieldConfig<"ip", "INDEXED" | "STORED">;
+
+export type JsonFieldConfig = FieldConfig<"json", "TEXT" | "STORED">;
+
+export type FacetFieldConfig = FieldConfig<"facet", "STORED">;
 
 export type IndexFieldConfig =
   | TextFieldConfig
   | DateFieldConfig
   | IntegerFieldConfig
-  | JsonFieldConfig;
+  | UnsignedIntegerFieldConfig
+  | FloatFieldConfig
+  | BytesFieldConfig
+  | IpFieldConfig
+  | JsonFieldConfig
+  | FacetFieldConfig;
+
+export interface IndexSettings {
+  /**
+   * Return only `__id` and score for each match, without fetching documents or generating snippets.
+   *
+   * Use this when canonical documents live in another database and hits are hydrated by the caller.
+   *
+   * @default false
+   */
+  search_only?: boolean;
+
+  /**
+   * Score multipliers for full-text queries against each field, keyed by field name.
+   *
+   * Boosts are applied at query time, so changing them takes effect on redeploy without reindexing.
+   * Changes to `fields` are not applied to existing indexes.
+   *
+   * @example
+   * ```ts
+   * { field_boosts: { title: 2.0 } }
+   * ```
+   */
+  field_boosts?: Record<string, number>;
+
+  /**
+   * Fields queries generate snippets for, unless a query picks its own `highlight.fields`.
+   *
+   * Leave out id-like and metadata fields to skip generating snippets nobody reads.
+   *
+   * @default every indexed text field
+   *
+   * @example
+   * ```ts
+   * { snippet_fields: ["title", "body"] }
+   * ```
+   */
+  snippet_fields?: string[];
+
+  /**
+   * Other names queries can use for fields, mapped to the field each stands for.
+   *
+   * Aliases are resolved in queries, query strings, sorts, facet filters and aggregations, so a
+   * field can be renamed without breaking existing queries. A field in the schema always wins over
+   * an alias of the same name. Aliases are applied at query time and need no reindexing.
+   *
+   * @example
+   * ```ts
+   * { field_aliases: { author: "creator" } }
+   * ```
+   */
+  field_aliases?: Record<string, string>;
+
+  /**
+   * IANA time zone, e.g. `America/New_York`, for dates given without an offset.
+   *
+   * Applies to `date` values in documents and queries such as `2022-11-14` or `2022-11-14T09:30:00`,
+   * which are read as local time in this zone. Dates with an offset are unaffected.
+   *
+   * Date histogram `calendar_interval` buckets start at local midnight in this zone.
+   *
+   * @default "UTC"
+   */
+  time_zone?: string;
+
+  /**
+   * Accept documents with fields that aren't in `fields`, adding them to the index schema as they
+   * arrive.
+   *
+   * The type of a new field comes from its first value: RFC 3339 strings become `date`, other
+   * strings full-text `text`, numbers `f64` and objects `json`. Fields with only boolean or null
+   * values are kept in the document but not indexed. Derived fields are indexed but not `FAST`.
+   *
+   * @default false
+   */
+  dynamic?: boolean;
+
+  /**
+   * Stop examining each segment after this many candidate matches, for latency-critical queries such
+   * as autocomplete that prefer speed over exhaustive recall.
+   *
+   * Matches past the limit are skipped in doc order, so the matches kept are the earliest indexed
+   * rather than the best, and aggregations and `track_total_hits` only see the kept matches. Queries
+   * can override it with `terminate_after`.
+   */
+  terminate_after?: number;
+
+  /**
+   * Reject documents larger than this many bytes of JSON.
+   */
+  max_doc_bytes?: number;
+
+  /**
+   * Most characters in each value of a text field. Applies to every text field without its own
+   * limit in `field_limits`.
+   */
+  max_field_chars?: number;
+
+  /**
+   * Most tokens, split on whitespace and punctuation, in each value of a text field. Applies to
+   * every text field without its own limit in `field_limits`.
+   */
+  max_field_tokens?: number;
+
+  /**
+   * Per-field overrides of `max_field_chars` and `max_field_tokens`, keyed by field name.
+   *
+   * @example
+   * ```ts
+   * { field_limits: { body: { max_field_chars: 100000 } } }
+   * ```
+   */
+  field_limits?: Record<string, { max_field_chars?: number; max_field_tokens?: number }>;
+
+  /**
+   * What to do with text values over their field's limits: `reject` fails the document with a 400,
+   * `truncate` indexes and stores the value cut to fit.
+   *
+   * @default "reject"
+   */
+  oversized_fields?: "reject" | "truncate";
+
+  /**
+   * Compress the stored documents of newly written segments with zstd.
+   *
+   * Cuts EFS storage for indexes that are mostly stored fields, at the cost of decompressing each
+   * segment's stored documents into memory when it's opened. Segments written before it's turned
+   * on, or after it's turned off, are read either way, and are rewritten as they merge.
+   *
+   * @default false
+   */
+  compress_stored_fields?: boolean;
+
+  /**
+   * Segment files downloaded at once when an index kept in S3 is opened, rather
+   * than one at a time as its first query reads them.
+   *
+   * @default 8
+   */
+  prefetch_concurrency?: number;
+
+  /**
+   * DynamoDB tables to join documents against as they're indexed, so that documents can be
+   * searched by values they only reference, such as the name of their category.
+   *
+   * Enriched fields are indexed but not stored: queries return documents as they were written.
+   * Looked up items are cached by the index writer for 5 minutes. The stack grants the index writer
+   * read access to the tables named here.
+   *
+   * @example
+   * ```ts
+   * {
+   *   enrich: [
+   *     {
+   *       field: "category_id",
+   *       table: "categories",
+   *       attributes: { name: "category_name" },
+   *     },
+   *   ],
+   * }
+   * ```
+   */
+  enrich?: EnrichConfig[];
+
+  /**
+   * How the index writer merges the segments each commit writes.
+   *
+   * Merges run in the index writer Lambda after each batch commits. High-churn indexes can bound
+   * them with a lower `max_docs_before_merge`, so that no batch is left waiting on a merge of the
+   * index's largest segments, or turn them off with `{ kind: "none" }` and rebuild the index from
+   * time to time instead.
+   *
+   * @default { kind: "log" }
+   */
+  merge_policy?: MergePolicyConfig;
+
+  /**
+   * `FAST` fields with document-level ranking signals, which queries boost scores by with a `boost.preset`,
+   * e.g. `{ "boost": { "preset": "recency_7d" } }`.
+   *
+   * @example
+   * ```ts
+   * { boost_signals: { popularity: "views", rating: "stars", recency: "published_at" } }
+   * ```
+   */
+  boost_signals?: BoostSignals;
+
+  /**
+   * An `INDEXED` date field holding when each document expires. A scheduled worker deletes
+   * documents once it's passed; documents without a value never expire.
+   *
+   * @example
+   * ```ts
+   * { ttl_field: "expires_at" }
+   * ```
+   */
+  ttl_field?: string;
+
+  /**
+   * Makes the index `prefix` a rolling index split into a partition per UTC day. Writes to the
+   * prefix are routed to `{prefix}-YYYY-MM-DD` partitions and queries to the prefix search the
+   * partitions in the retention window.
+   *
+   * @example
+   * ```ts
+   * { time_partition: { field: "timestamp", retention_days: 30 } }
+   * ```
+   */
+  time_partition?: TimePartitionConfig;
+
+  /**
+   * Maps the items of a DynamoDB table to documents, for an index kept in step with the table by
+   * the stack's `dynamodbStreams`. Inserted and modified items are indexed under an `__id` made of
+   * their key, and removed items are deleted.
+   *
+   * @example
+   * ```ts
+   * {
+   *   dynamodb_stream: {
+   *     id_attributes: ["pk", "sk"],
+   *     attributes: { Title: "title", "Author.Name": "author" },
+   *   },
+   * }
+   * ```
+   */
+  dynamodb_stream?: DynamoDbStreamConfig;
+}
+
+export interface TimePartitionConfig {
+  /**
+   * A date field whose value picks each document's partition. Documents without a value are
+   * written to the partition of the day they're indexed.
+   */
+  field?: string;
+
+  /**
+   * Days of partitions kept, counting today. Older partitions are dropped by the expire worker
+   * and left out of queries. Partitions are kept forever when unset.
+   */
+  retention_days?: number;
+}
+
+export interface BoostSignals {
+  /** A numeric field such as a view or sales count, for the `popularity` preset. */
+  popularity?: string;
+
+  /** A numeric field such as an average review score, for the `rating` preset. */
+  rating?: string;
+
+  /** A date field such as the publication date, for the `recency_*` presets. */
+  recency?: string;
+}
+
+export type MergePolicyConfig =
+  | ({ kind: "log" } & LogMergeSettings)
+  | { kind: "none" };
+
+export interface LogMergeSettings {
+  /**
+   * Fewest segments of a level merged together.
+   *
+   * @default 8
+   */
+  min_num_segments?: number;
+
+  /**
+   * Segments with more documents than this are left out of merges.
+   *
+   * @default 10000
+   */
+  max_docs_before_merge?: number;
+
+  /**
+   * Segments with fewer documents than this all belong to the lowest level.
+   *
+   * @default 10000
+   */
+  min_layer_size?: number;
+
+  /**
+   * Log of the ratio between the sizes of consecutive levels.
+   *
+   * @default 0.75
+   */
+  level_log_size?: number;
+
+  /**
+   * Share of deleted documents past which a segment is merged away even when its level isn't full.
+   *
+   * @default 1.0
+   */
+  del_docs_ratio_before_merge?: number;
+}
+
+export interface EnrichConfig {
+  /**
+   * Document field holding the key to look up. Documents without it aren't enriched, and each key
+   * of an array is looked up.
+   */
+  field: string;
+
+  /**
+   * Name of the DynamoDB table to look keys up in.
+   */
+  table: string;
+
+  /**
+   * Partition key attribute of the table.
+   *
+   * @default "id"
+   */
+  key?: string;
+
+  /**
+   * Attributes of the matching item to copy into the document, mapped to the field each is copied
+   * to. Copied values replace any the document already has.
+   */
+  attributes: Record<string, string>;
+}
+
+export interface DynamoDbStreamConfig {
+  /**
+   * Key attributes whose values, joined by `#`, make up each document's `__id`.
+   */
+  id_attributes: string[];
+
+  /**
+   * Attributes to index, mapped to the field each is indexed as. Attributes of maps are named by
+   * their path, e.g. `Author.Name`.
+   *
+   * @default every top-level attribute, under its own name
+   */
+  attributes?: Record<string, string>;
+}
 
 export interface IndexConfig {
   /**
@@ -68,6 +533,10 @@ export interface IndexConfig {
    * Documents must have fields that match the fields specified in this configuration in order to be indexed.
    * Fields which are not included in the list of fields will be ignored.
    *
+   * Each index records a version of the fields it was built with. Existing indexes keep their fields when these
+   * change, logging `schema_drift` when loaded, until rebuilt by sending `{ "index_id": "<index>" }` to the
+   * stack's `reindexQueue`. Search-only indexes can't be rebuilt.
+   *
    * @example
    * String text field config:
    *
@@ -93,6 +562,11 @@ export interface IndexConfig {
    * ```
    */
   fields: IndexFieldConfig[];
+
+  /**
+   * Index level settings.
+   */
+  settings?: IndexSettings;
 }
 
 export interface PatheryConfig {
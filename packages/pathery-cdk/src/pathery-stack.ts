@@ -18,9 +18,19 @@ import {
   Vpc,
 } from "aws-cdk-lib/aws-ec2";
 import { FileSystem } from "aws-cdk-lib/aws-efs";
-import { Function, LayerVersion } from "aws-cdk-lib/aws-lambda";
+import { Rule, Schedule } from "aws-cdk-lib/aws-events";
+import { LambdaFunction } from "aws-cdk-lib/aws-events-targets";
+import {
+  Function,
+  FunctionProps,
+  LayerVersion,
+  StartingPosition,
+} from "aws-cdk-lib/aws-lambda";
 import { Architecture, Code, Runtime } from "aws-cdk-lib/aws-lambda";
-import { SqsEventSource } from "aws-cdk-lib/aws-lambda-event-sources";
+import {
+  DynamoEventSource,
+  SqsEventSource,
+} from "aws-cdk-lib/aws-lambda-event-sources";
 import { IQueue, Queue } from "aws-cdk-lib/aws-sqs";
 import { Construct } from "constructs";
 import { PatheryConfig } from "./config";
@@ -34,6 +44,22 @@ import {
   ITable,
   Table,
 } from "aws-cdk-lib/aws-dynamodb";
+import { Secret } from "aws-cdk-lib/aws-secretsmanager";
+
+export interface DedicatedWriterQueue {
+  /**
+   * Jobs for indexes whose id starts with this prefix are sent to the
+   * dedicated queue. When several prefixes match, the longest wins.
+   */
+  indexPrefix: string;
+
+  /**
+   * Reserved concurrency for the dedicated IndexWriter Lambda.
+   *
+   * @default no reserved concurrency
+   */
+  reservedConcurrency?: number;
+}
 
 export interface PatheryStackProps extends StackProps {
   config: PatheryConfig;
@@ -55,6 +81,14 @@ export interface PatheryStackProps extends StackProps {
      * @default Duration.minutes(1)
      */
     timeout?: Duration;
+
+    /**
+     * Indexes which get their own writer queue and IndexWriter Lambda,
+     * isolating high-volume indexes from the shared queue.
+     *
+     * @default []
+     */
+    dedicatedQueues?: DedicatedWriterQueue[];
   };
 
   /**
@@ -67,6 +101,130 @@ export interface PatheryStackProps extends StackProps {
      * @default 3008
      */
     memorySize?: number;
+
+    /**
+     * Reserved concurrency for the QueryHandler Lambda, so that other functions can't use up the
+     * concurrency queries need.
+     *
+     * @default no reserved concurrency
+     */
+    reservedConcurrency?: number;
+  };
+
+  /**
+   * Configuration overrides for the Lambdas behind the write endpoints: indexing, batch and bulk
+   * indexing, patching and deleting documents, and deleting by query.
+   */
+  writeHandlers?: {
+    /**
+     * Write handler Lambda memorySize.
+     *
+     * @default 128
+     */
+    memorySize?: number;
+
+    /**
+     * Reserved concurrency for each write handler Lambda. Also caps it, so a burst of indexing
+     * can't use up the concurrency queries need.
+     *
+     * @default no reserved concurrency
+     */
+    reservedConcurrency?: number;
+  };
+
+  /**
+   * Background merge worker configuration.
+   */
+  mergeWorker?: {
+    /**
+     * How often the worker scans every index, merging those whose segments have piled up and
+     * deleting files no commit refers to.
+     *
+     * @default Schedule.rate(Duration.hours(1))
+     */
+    schedule?: Schedule;
+  };
+
+  /**
+   * Background expire worker configuration.
+   */
+  expireWorker?: {
+    /**
+     * How often the worker deletes the documents whose `ttl_field` has passed, from every index
+     * that sets one.
+     *
+     * @default Schedule.rate(Duration.hours(1))
+     */
+    schedule?: Schedule;
+  };
+
+  /**
+   * Background catalog worker configuration.
+   */
+  catalogWorker?: {
+    /**
+     * How often the worker rebuilds the `_catalog` index, which lists every index with its
+     * fields, tags and document count.
+     *
+     * @default Schedule.rate(Duration.minutes(15))
+     */
+    schedule?: Schedule;
+  };
+
+  /**
+   * DynamoDB tables whose items are indexed as they change, each into an index whose
+   * `dynamodb_stream` setting maps its items to documents. Tables must have a stream that
+   * includes new images.
+   *
+   * @default no tables are indexed
+   */
+  dynamodbStreams?: {
+    /** The table, with its `tableStreamArn`. */
+    table: ITable;
+
+    /** The index the table's items are indexed into. */
+    indexId: string;
+
+    /**
+     * Most stream records handled by each invocation.
+     *
+     * @default 100
+     */
+    batchSize?: number;
+  }[];
+
+  /**
+   * Tenants served by the deployment, each given its own API key. Requests with a tenant's key
+   * only see and change that tenant's indexes; requests with the default key address every index.
+   * APIs with an authorizer can instead supply the tenant as a `tenant_id` claim. With
+   * `apiKeyAuth` enabled, tenants are instead given by the `tenant_id` of the stored keys.
+   */
+  tenants?: { tenantId: string }[];
+
+  /**
+   * API key auth checked by the API handlers themselves, against keys stored in the data table
+   * with `read`, `write` or `admin` scopes.
+   */
+  apiKeyAuth?: {
+    /**
+     * Check the `x-api-key` header of every API request against the data table's keys instead
+     * of the API Gateway keys.
+     *
+     * @default false
+     */
+    enabled?: boolean;
+  };
+
+  /**
+   * GraphQL endpoint configuration.
+   */
+  graphql?: {
+    /**
+     * Deploy the `POST /graphql` endpoint.
+     *
+     * @default false
+     */
+    enabled?: boolean;
   };
 }
 
@@ -81,8 +239,30 @@ export class PatheryStack extends Stack {
 
   private indexWriterQueue: IQueue;
 
+  private dedicatedWriterQueues: {
+    route: DedicatedWriterQueue;
+    queue: IQueue;
+  }[];
+
   private deleteQueue: IQueue;
 
+  private updateByQueryQueue: IQueue;
+
+  private lookupTables: ITable[];
+
+  /**
+   * Queue of `{ "index_id": "..." }` jobs that rebuild an index under its configured schema. An
+   * index already built from the configured schema is skipped unless the job sets `"force": true`.
+   */
+  readonly reindexQueue: IQueue;
+
+  /**
+   * Queue of `{ "index_id": "...", "field": "..." }` jobs that report groups of documents sharing a
+   * value of `field`, or the same content when `field` is omitted. Reports are written to the data
+   * bucket as `reports/{index_id}/duplicates-{timestamp}.json`.
+   */
+  readonly duplicatesQueue: IQueue;
+
   constructor(scope: Construct, id: string, props: PatheryStackProps) {
     super(scope, id, props);
 
@@ -101,16 +281,48 @@ export class PatheryStack extends Stack {
 
     this.bucket = new Bucket(this, "DataBucket");
 
+    const lookupTableNames = new Set(
+      props.config.indexes.flatMap((index) =>
+        (index.settings?.enrich ?? []).map((enrich) => enrich.table)
+      )
+    );
+    this.lookupTables = [...lookupTableNames].map((tableName) =>
+      Table.fromTableName(this, `LookupTable-${tableName}`, tableName)
+    );
+
     this.deleteQueue = new Queue(this, "DeleteQueue", {
       deliveryDelay: Duration.minutes(15),
       visibilityTimeout: Duration.minutes(2),
     });
 
+    // Rebuilds can take up to the worker's whole timeout.
+    this.reindexQueue = new Queue(this, "ReindexQueue", {
+      visibilityTimeout: Duration.minutes(15),
+    });
+
+    this.duplicatesQueue = new Queue(this, "DuplicatesQueue", {
+      visibilityTimeout: Duration.minutes(15),
+    });
+
+    this.updateByQueryQueue = new Queue(this, "UpdateByQueryQueue", {
+      visibilityTimeout: Duration.minutes(15),
+    });
+
     this.indexWriterQueue = new Queue(this, "IndexWriterQueue", {
       fifo: true,
       contentBasedDeduplication: true,
     });
 
+    this.dedicatedWriterQueues = (
+      props.indexWriter?.dedicatedQueues ?? []
+    ).map((route) => ({
+      route,
+      queue: new Queue(this, `IndexWriterQueue-${route.indexPrefix}`, {
+        fifo: true,
+        contentBasedDeduplication: true,
+      }),
+    }));
+
     const vpc = new Vpc(this, "Vpc", {
       subnetConfiguration: [
         {
@@ -162,16 +374,55 @@ export class PatheryStack extends Stack {
       compatibleRuntimes: [Runtime.PROVIDED_AL2],
     });
 
-    const postIndex = new RustFunction(this, "post-index");
-    postIndex.addLayers(configLayer);
+    // Write handlers scale apart from queries, so heavy indexing doesn't starve query concurrency.
+    const writeHandlerProps = {
+      memorySize: props.writeHandlers?.memorySize,
+      reservedConcurrentExecutions: props.writeHandlers?.reservedConcurrency,
+    };
+
+    // Long enough for `refresh=wait_for` requests to wait on the index writer.
+    const postIndex = new RustFunction(this, "post-index", {
+      ...writeHandlerProps,
+      timeout: Duration.seconds(25),
+    });
+    this.configReader(postIndex, configLayer);
     this.indexWriterProducer(postIndex);
+    this.table.grantReadData(postIndex);
+
+    const createIndex = new RustFunction(this, "create-index");
+    this.configReader(createIndex, configLayer);
+    this.table.grantWriteData(createIndex);
 
-    const batchIndex = new RustFunction(this, "batch-index");
-    batchIndex.addLayers(configLayer);
+    const validateDoc = new RustFunction(this, "validate-doc");
+    this.configReader(validateDoc, configLayer);
+
+    const batchIndex = new RustFunction(this, "batch-index", writeHandlerProps);
+    this.configReader(batchIndex, configLayer);
     this.indexWriterProducer(batchIndex);
 
+    const bulkIndex = new RustFunction(this, "bulk-index", writeHandlerProps);
+    this.configReader(bulkIndex, configLayer);
+    this.indexWriterProducer(bulkIndex);
+
+    const esBulkIndex = new RustFunction(
+      this,
+      "es-bulk-index",
+      writeHandlerProps
+    );
+    this.configReader(esBulkIndex, configLayer);
+    this.indexWriterProducer(esBulkIndex);
+    // Creates and updates read the stored documents.
+    this.table.grantReadData(esBulkIndex);
+
+    // Signs the pagination cursors in query responses, shared by every function handing them out.
+    // Functions read it at cold start, so it stays out of their configuration.
+    const cursorSigningKey = new Secret(this, "cursor-signing-key", {
+      generateSecretString: { passwordLength: 64, excludePunctuation: true },
+    });
+
     const queryIndex = new RustFunction(this, "query-index", {
       memorySize: props.queryHandler?.memorySize ?? 3008,
+      reservedConcurrentExecutions: props.queryHandler?.reservedConcurrency,
       timeout: Duration.seconds(5),
       vpc,
       vpcSubnets: {
@@ -182,13 +433,49 @@ export class PatheryStack extends Stack {
         "/mnt/pathery-data"
       ),
     });
-    queryIndex.addLayers(configLayer);
-    this.table.grantReadData(queryIndex);
+    this.configReader(queryIndex, configLayer);
+    // Rate limit buckets are kept in the table.
+    this.table.grantReadWriteData(queryIndex);
     queryIndex.addEnvironment("DATA_TABLE_NAME", this.table.tableName);
     queryIndex.addEnvironment(
       "ASYNC_DELETE_QUEUE_URL",
       this.deleteQueue.queueUrl
     );
+    queryIndex.addEnvironment(
+      "CURSOR_SIGNING_KEY_SECRET_ARN",
+      cursorSigningKey.secretArn
+    );
+    cursorSigningKey.grantRead(queryIndex);
+    // Search analytics are written to the bucket.
+    this.bucket.grantPut(queryIndex, "analytics/*");
+    queryIndex.addEnvironment("DATA_BUCKET_NAME", this.bucket.bucketName);
+
+    const esSearchIndex = new RustFunction(this, "es-search-index", {
+      memorySize: props.queryHandler?.memorySize ?? 3008,
+      timeout: Duration.seconds(5),
+      vpc,
+      vpcSubnets: {
+        subnets: vpc.isolatedSubnets,
+      },
+      filesystem: aws_lambda.FileSystem.fromEfsAccessPoint(
+        accessPoint,
+        "/mnt/pathery-data"
+      ),
+    });
+    this.configReader(esSearchIndex, configLayer);
+    this.table.grantReadData(esSearchIndex);
+    esSearchIndex.addEnvironment("DATA_TABLE_NAME", this.table.tableName);
+    esSearchIndex.addEnvironment(
+      "ASYNC_DELETE_QUEUE_URL",
+      this.deleteQueue.queueUrl
+    );
+    esSearchIndex.addEnvironment(
+      "CURSOR_SIGNING_KEY_SECRET_ARN",
+      cursorSigningKey.secretArn
+    );
+    cursorSigningKey.grantRead(esSearchIndex);
+    // Only read by the query service's analytics, which `_search` doesn't record.
+    esSearchIndex.addEnvironment("DATA_BUCKET_NAME", this.bucket.bucketName);
 
     const statsIndex = new RustFunction(this, "stats-index", {
       vpc,
@@ -200,20 +487,254 @@ export class PatheryStack extends Stack {
         "/mnt/pathery-data"
       ),
     });
-    statsIndex.addLayers(configLayer);
+    this.configReader(statsIndex, configLayer);
+
+    const manifestIndex = new RustFunction(this, "manifest-index", {
+      vpc,
+      vpcSubnets: {
+        subnets: vpc.isolatedSubnets,
+      },
+      filesystem: aws_lambda.FileSystem.fromEfsAccessPoint(
+        accessPoint,
+        "/mnt/pathery-data"
+      ),
+    });
+    this.configReader(manifestIndex, configLayer);
+
+    const suggestIndex = new RustFunction(this, "suggest-index", {
+      vpc,
+      vpcSubnets: {
+        subnets: vpc.isolatedSubnets,
+      },
+      filesystem: aws_lambda.FileSystem.fromEfsAccessPoint(
+        accessPoint,
+        "/mnt/pathery-data"
+      ),
+    });
+    this.configReader(suggestIndex, configLayer);
+
+    const estimateQuery = new RustFunction(this, "estimate-query", {
+      vpc,
+      vpcSubnets: {
+        subnets: vpc.isolatedSubnets,
+      },
+      filesystem: aws_lambda.FileSystem.fromEfsAccessPoint(
+        accessPoint,
+        "/mnt/pathery-data"
+      ),
+    });
+    this.configReader(estimateQuery, configLayer);
+
+    // Snapshots respond once every file is copied, so large indexes can outlast API Gateway's
+    // timeout while the copy carries on.
+    const snapshotIndex = new RustFunction(this, "snapshot-index", {
+      memorySize: 2048,
+      timeout: Duration.minutes(15),
+      vpc,
+      vpcSubnets: {
+        subnets: vpc.isolatedSubnets,
+      },
+      filesystem: aws_lambda.FileSystem.fromEfsAccessPoint(
+        accessPoint,
+        "/mnt/pathery-data"
+      ),
+    });
+    this.configReader(snapshotIndex, configLayer);
+    this.bucket.grantWrite(snapshotIndex);
+    snapshotIndex.addEnvironment("DATA_BUCKET_NAME", this.bucket.bucketName);
+    this.table.grantReadWriteData(snapshotIndex);
+    snapshotIndex.addEnvironment("DATA_TABLE_NAME", this.table.tableName);
+    snapshotIndex.addEnvironment(
+      "ASYNC_DELETE_QUEUE_URL",
+      this.deleteQueue.queueUrl
+    );
+
+    const restoreIndex = new RustFunction(this, "restore-index", {
+      memorySize: 2048,
+      timeout: Duration.minutes(15),
+      vpc,
+      vpcSubnets: {
+        subnets: vpc.isolatedSubnets,
+      },
+      filesystem: aws_lambda.FileSystem.fromEfsAccessPoint(
+        accessPoint,
+        "/mnt/pathery-data"
+      ),
+    });
+    this.configReader(restoreIndex, configLayer);
+    this.bucket.grantRead(restoreIndex);
+    restoreIndex.addEnvironment("DATA_BUCKET_NAME", this.bucket.bucketName);
+    this.table.grantReadWriteData(restoreIndex);
+    restoreIndex.addEnvironment("DATA_TABLE_NAME", this.table.tableName);
+    restoreIndex.addEnvironment(
+      "ASYNC_DELETE_QUEUE_URL",
+      this.deleteQueue.queueUrl
+    );
+
+    const eraseIndex = new RustFunction(this, "erase-index", {
+      memorySize: 2048,
+      timeout: Duration.minutes(15),
+      vpc,
+      vpcSubnets: {
+        subnets: vpc.isolatedSubnets,
+      },
+      filesystem: aws_lambda.FileSystem.fromEfsAccessPoint(
+        accessPoint,
+        "/mnt/pathery-data"
+      ),
+    });
+    this.configReader(eraseIndex, configLayer);
+    this.table.grantReadWriteData(eraseIndex);
+    eraseIndex.addEnvironment("DATA_TABLE_NAME", this.table.tableName);
+    this.deleteQueue.grantSendMessages(eraseIndex);
+    eraseIndex.addEnvironment(
+      "ASYNC_DELETE_QUEUE_URL",
+      this.deleteQueue.queueUrl
+    );
+
+    const listIndexes = new RustFunction(this, "list-indexes", {
+      vpc,
+      vpcSubnets: {
+        subnets: vpc.isolatedSubnets,
+      },
+      filesystem: aws_lambda.FileSystem.fromEfsAccessPoint(
+        accessPoint,
+        "/mnt/pathery-data"
+      ),
+    });
+    this.configReader(listIndexes, configLayer);
+
+    const deleteByQuery = new RustFunction(this, "delete-by-query", {
+      ...writeHandlerProps,
+      vpc,
+      vpcSubnets: {
+        subnets: vpc.isolatedSubnets,
+      },
+      filesystem: aws_lambda.FileSystem.fromEfsAccessPoint(
+        accessPoint,
+        "/mnt/pathery-data"
+      ),
+    });
+    this.configReader(deleteByQuery, configLayer);
+    this.indexWriterProducer(deleteByQuery);
+    this.deleteQueue.grantSendMessages(deleteByQuery);
+    deleteByQuery.addEnvironment(
+      "ASYNC_DELETE_QUEUE_URL",
+      this.deleteQueue.queueUrl
+    );
+
+    const updateByQuery = new RustFunction(this, "update-by-query", {
+      ...writeHandlerProps,
+      vpc,
+      vpcSubnets: {
+        subnets: vpc.isolatedSubnets,
+      },
+      filesystem: aws_lambda.FileSystem.fromEfsAccessPoint(
+        accessPoint,
+        "/mnt/pathery-data"
+      ),
+    });
+    this.configReader(updateByQuery, configLayer);
+    this.table.grantWriteData(updateByQuery);
+    this.updateByQueryQueue.grantSendMessages(updateByQuery);
+    updateByQuery.addEnvironment(
+      "UPDATE_BY_QUERY_QUEUE_URL",
+      this.updateByQueryQueue.queueUrl
+    );
+    updateByQuery.addEnvironment(
+      "ASYNC_DELETE_QUEUE_URL",
+      this.deleteQueue.queueUrl
+    );
 
-    const deleteDoc = new RustFunction(this, "delete-doc");
-    deleteDoc.addLayers(configLayer);
+    // Reads the index for dry runs.
+    const deleteIndex = new RustFunction(this, "delete-index", {
+      vpc,
+      vpcSubnets: {
+        subnets: vpc.isolatedSubnets,
+      },
+      filesystem: aws_lambda.FileSystem.fromEfsAccessPoint(
+        accessPoint,
+        "/mnt/pathery-data"
+      ),
+    });
+    this.configReader(deleteIndex, configLayer);
+    this.indexWriterProducer(deleteIndex);
+
+    const deleteDoc = new RustFunction(this, "delete-doc", writeHandlerProps);
+    this.configReader(deleteDoc, configLayer);
     this.indexWriterProducer(deleteDoc);
 
+    const patchDoc = new RustFunction(this, "patch-doc", writeHandlerProps);
+    this.configReader(patchDoc, configLayer);
+    this.indexWriterProducer(patchDoc);
+    this.table.grantReadData(patchDoc);
+
+    const syncIndex = new RustFunction(this, "sync-index");
+    this.configReader(syncIndex, configLayer);
+
+    const eventsIndex = new RustFunction(this, "events-index");
+    this.configReader(eventsIndex, configLayer);
+    this.table.grantReadData(eventsIndex);
+    eventsIndex.addEnvironment("DATA_TABLE_NAME", this.table.tableName);
+
+    const deprecationsIndex = new RustFunction(this, "deprecations-index");
+    this.configReader(deprecationsIndex, configLayer);
+    this.table.grantReadData(deprecationsIndex);
+    deprecationsIndex.addEnvironment("DATA_TABLE_NAME", this.table.tableName);
+
+    const eraseReport = new RustFunction(this, "erase-report");
+    this.configReader(eraseReport, configLayer);
+
+    const jobStatus = new RustFunction(this, "job-status");
+    this.table.grantReadData(jobStatus);
+    jobStatus.addEnvironment("DATA_TABLE_NAME", this.table.tableName);
+
+    for (const handler of [
+      postIndex,
+      createIndex,
+      validateDoc,
+      batchIndex,
+      bulkIndex,
+      esBulkIndex,
+      queryIndex,
+      esSearchIndex,
+      statsIndex,
+      manifestIndex,
+      suggestIndex,
+      estimateQuery,
+      snapshotIndex,
+      restoreIndex,
+      eraseIndex,
+      listIndexes,
+      deleteByQuery,
+      updateByQuery,
+      deleteIndex,
+      deleteDoc,
+      patchDoc,
+      syncIndex,
+      eventsIndex,
+      deprecationsIndex,
+      eraseReport,
+      jobStatus,
+    ]) {
+      this.apiKeyChecker(handler, props);
+    }
+
     const api = new RestApi(this, "PatheryApi", {
       restApiName: id,
       endpointConfiguration: {
         types: [EndpointType.REGIONAL],
       },
+      // Passes request bodies to the Lambdas as is, so bodies sent with `Content-Encoding: gzip`
+      // reach them intact to be decompressed.
+      binaryMediaTypes: ["*/*"],
       defaultMethodOptions: {
         apiKeyRequired: true,
       },
+      // Starts the X-Ray traces that follow writes through the index writer queue.
+      deployOptions: {
+        tracingEnabled: true,
+      },
     });
 
     this.apiGateway = api;
@@ -233,31 +754,298 @@ export class PatheryStack extends Stack {
 
     this.apiKey = apiKey;
 
+    const tenantKeys = (props.tenants ?? []).map(({ tenantId }) => {
+      const tenantKey = new ApiKey(this, `TenantApiKey-${tenantId}`, {});
+      plan.addApiKey(tenantKey);
+      new CfnOutput(this, `TenantApiKeyOutput-${tenantId}`, {
+        value: tenantKey.keyId,
+      });
+      return { api_key_id: tenantKey.keyId, tenant_id: tenantId };
+    });
+
+    // Handlers that check keys themselves take keys API Gateway doesn't know.
+    const handlerAuthOptions = {
+      apiKeyRequired: !props.apiKeyAuth?.enabled,
+    };
+
+    const jobsRoute = api.root.addResource("jobs");
+
+    const jobSingleRoute = jobsRoute.addResource("{job_id}");
+
+    jobSingleRoute.addMethod(
+      "GET",
+      new LambdaIntegration(jobStatus),
+      handlerAuthOptions
+    );
+
     const indexRoute = api.root.addResource("index");
 
+    indexRoute.addMethod(
+      "GET",
+      new LambdaIntegration(listIndexes),
+      handlerAuthOptions
+    );
+
     const indexSingleRoute = indexRoute.addResource("{index_id}");
 
-    indexSingleRoute.addMethod("POST", new LambdaIntegration(postIndex));
+    indexSingleRoute.addMethod(
+      "POST",
+      new LambdaIntegration(postIndex),
+      handlerAuthOptions
+    );
+
+    indexSingleRoute.addMethod(
+      "PUT",
+      new LambdaIntegration(createIndex),
+      handlerAuthOptions
+    );
+
+    indexSingleRoute.addMethod(
+      "DELETE",
+      new LambdaIntegration(deleteIndex),
+      handlerAuthOptions
+    );
 
     const queryActionRoute = indexSingleRoute.addResource("query");
 
-    queryActionRoute.addMethod("POST", new LambdaIntegration(queryIndex));
+    queryActionRoute.addMethod(
+      "POST",
+      new LambdaIntegration(queryIndex),
+      handlerAuthOptions
+    );
+    queryActionRoute.addMethod(
+      "GET",
+      new LambdaIntegration(queryIndex),
+      handlerAuthOptions
+    );
+
+    const esSearchActionRoute = indexSingleRoute.addResource("_search");
+
+    esSearchActionRoute.addMethod(
+      "POST",
+      new LambdaIntegration(esSearchIndex),
+      handlerAuthOptions
+    );
 
     const statsActionRoute = indexSingleRoute.addResource("stats");
 
-    statsActionRoute.addMethod("GET", new LambdaIntegration(statsIndex));
+    statsActionRoute.addMethod(
+      "GET",
+      new LambdaIntegration(statsIndex),
+      handlerAuthOptions
+    );
+
+    const manifestActionRoute = indexSingleRoute.addResource("_manifest");
+
+    manifestActionRoute.addMethod(
+      "GET",
+      new LambdaIntegration(manifestIndex),
+      handlerAuthOptions
+    );
+
+    const suggestActionRoute = indexSingleRoute.addResource("suggest");
+
+    suggestActionRoute.addMethod(
+      "GET",
+      new LambdaIntegration(suggestIndex),
+      handlerAuthOptions
+    );
+
+    const estimateActionRoute = indexSingleRoute.addResource("_estimate");
+
+    estimateActionRoute.addMethod(
+      "POST",
+      new LambdaIntegration(estimateQuery),
+      handlerAuthOptions
+    );
+
+    const snapshotActionRoute = indexSingleRoute.addResource("snapshot");
+
+    snapshotActionRoute.addMethod(
+      "POST",
+      new LambdaIntegration(snapshotIndex),
+      handlerAuthOptions
+    );
+
+    const restoreActionRoute = indexSingleRoute.addResource("restore");
+
+    restoreActionRoute.addMethod(
+      "POST",
+      new LambdaIntegration(restoreIndex),
+      handlerAuthOptions
+    );
+
+    const eraseActionRoute = indexSingleRoute.addResource("_erase");
+
+    eraseActionRoute.addMethod(
+      "POST",
+      new LambdaIntegration(eraseIndex),
+      handlerAuthOptions
+    );
+
+    eraseActionRoute
+      .addResource("{erase_id}")
+      .addMethod("GET", new LambdaIntegration(eraseReport), handlerAuthOptions);
+
+    const syncActionRoute = indexSingleRoute.addResource("_sync");
+
+    syncActionRoute.addMethod(
+      "GET",
+      new LambdaIntegration(syncIndex),
+      handlerAuthOptions
+    );
+
+    const eventsActionRoute = indexSingleRoute.addResource("_events");
+
+    eventsActionRoute.addMethod(
+      "GET",
+      new LambdaIntegration(eventsIndex),
+      handlerAuthOptions
+    );
+
+    const deprecationsActionRoute =
+      indexSingleRoute.addResource("_deprecations");
+
+    deprecationsActionRoute.addMethod(
+      "GET",
+      new LambdaIntegration(deprecationsIndex),
+      handlerAuthOptions
+    );
+
+    const validateActionRoute = indexSingleRoute.addResource("validate");
+
+    validateActionRoute.addMethod(
+      "POST",
+      new LambdaIntegration(validateDoc),
+      handlerAuthOptions
+    );
 
     const batchIndexRoute = indexSingleRoute.addResource("batch");
 
-    batchIndexRoute.addMethod("POST", new LambdaIntegration(batchIndex));
+    batchIndexRoute.addMethod(
+      "POST",
+      new LambdaIntegration(batchIndex),
+      handlerAuthOptions
+    );
+
+    const updateByQueryRoute =
+      indexSingleRoute.addResource("_update_by_query");
+
+    updateByQueryRoute.addMethod(
+      "POST",
+      new LambdaIntegration(updateByQuery),
+      handlerAuthOptions
+    );
+
+    const bulkIndexRoute = indexSingleRoute.addResource("bulk");
+
+    bulkIndexRoute.addMethod(
+      "POST",
+      new LambdaIntegration(bulkIndex),
+      handlerAuthOptions
+    );
+
+    const esBulkIndexRoute = indexSingleRoute.addResource("_bulk");
+
+    esBulkIndexRoute.addMethod(
+      "POST",
+      new LambdaIntegration(esBulkIndex),
+      handlerAuthOptions
+    );
 
     const documentRoute = indexSingleRoute.addResource("doc");
 
     const documentSingleRoute = documentRoute.addResource("{doc_id}");
 
-    documentSingleRoute.addMethod("DELETE", new LambdaIntegration(deleteDoc));
+    documentSingleRoute.addMethod(
+      "DELETE",
+      new LambdaIntegration(deleteDoc),
+      handlerAuthOptions
+    );
+
+    documentSingleRoute.addMethod(
+      "PATCH",
+      new LambdaIntegration(patchDoc),
+      handlerAuthOptions
+    );
+
+    const documentsRoute = indexSingleRoute.addResource("docs");
 
-    const indexWriterWorker = new RustFunction(this, "index-writer-worker", {
+    documentsRoute.addMethod(
+      "DELETE",
+      new LambdaIntegration(deleteByQuery),
+      handlerAuthOptions
+    );
+
+    if (props.graphql?.enabled) {
+      const graphql = new RustFunction(this, "graphql", {
+        memorySize: props.queryHandler?.memorySize ?? 3008,
+        timeout: Duration.seconds(5),
+        vpc,
+        vpcSubnets: {
+          subnets: vpc.isolatedSubnets,
+        },
+        filesystem: aws_lambda.FileSystem.fromEfsAccessPoint(
+          accessPoint,
+          "/mnt/pathery-data"
+        ),
+      });
+      this.configReader(graphql, configLayer);
+      this.table.grantReadData(graphql);
+      graphql.addEnvironment("DATA_TABLE_NAME", this.table.tableName);
+      graphql.addEnvironment(
+        "ASYNC_DELETE_QUEUE_URL",
+        this.deleteQueue.queueUrl
+      );
+      graphql.addEnvironment(
+        "CURSOR_SIGNING_KEY_SECRET_ARN",
+        cursorSigningKey.secretArn
+      );
+      cursorSigningKey.grantRead(graphql);
+      this.apiKeyChecker(graphql, props);
+
+      const graphqlRoute = api.root.addResource("graphql");
+
+      graphqlRoute.addMethod(
+        "POST",
+        new LambdaIntegration(graphql),
+        handlerAuthOptions
+      );
+    }
+
+    const indexWriterWorkerProps = {
+      memorySize: props.indexWriter?.memorySize ?? 2048,
+      timeout: props.indexWriter?.timeout ?? Duration.minutes(1),
+      vpc,
+      vpcSubnets: {
+        subnets: vpc.isolatedSubnets,
+      },
+      filesystem: aws_lambda.FileSystem.fromEfsAccessPoint(
+        accessPoint,
+        "/mnt/pathery-data"
+      ),
+    };
+
+    const indexWriterWorker = this.indexWriterWorker(
+      this,
+      this.indexWriterQueue,
+      configLayer,
+      indexWriterWorkerProps
+    );
+
+    for (const { route, queue } of this.dedicatedWriterQueues) {
+      this.indexWriterWorker(
+        new Construct(this, `IndexWriter-${route.indexPrefix}`),
+        queue,
+        configLayer,
+        {
+          ...indexWriterWorkerProps,
+          reservedConcurrentExecutions: route.reservedConcurrency,
+        }
+      );
+    }
+
+    const asyncDeleteWorker = new RustFunction(this, "async-delete-worker", {
       memorySize: props.indexWriter?.memorySize ?? 2048,
       timeout: props.indexWriter?.timeout ?? Duration.minutes(1),
       vpc,
@@ -269,29 +1057,46 @@ export class PatheryStack extends Stack {
         "/mnt/pathery-data"
       ),
     });
-    indexWriterWorker.addLayers(configLayer);
-    indexWriterWorker.addEventSource(
-      new SqsEventSource(this.indexWriterQueue, {
+    this.configReader(asyncDeleteWorker, configLayer);
+    asyncDeleteWorker.addEventSource(
+      new SqsEventSource(this.deleteQueue, {
         batchSize: 10,
       })
     );
-    this.bucket.grantRead(indexWriterWorker);
-    this.bucket.grantDelete(indexWriterWorker);
-    indexWriterWorker.addEnvironment(
-      "DATA_BUCKET_NAME",
-      this.bucket.bucketName
+
+    const reindexWorker = new RustFunction(this, "reindex-worker", {
+      memorySize: props.indexWriter?.memorySize ?? 2048,
+      timeout: Duration.minutes(15),
+      vpc,
+      vpcSubnets: {
+        subnets: vpc.isolatedSubnets,
+      },
+      filesystem: aws_lambda.FileSystem.fromEfsAccessPoint(
+        accessPoint,
+        "/mnt/pathery-data"
+      ),
+    });
+    this.configReader(reindexWorker, configLayer);
+    this.lookupTableReader(reindexWorker);
+    reindexWorker.addEventSource(
+      new SqsEventSource(this.reindexQueue, {
+        batchSize: 1,
+        reportBatchItemFailures: true,
+      })
     );
-    this.table.grantReadWriteData(indexWriterWorker);
-    indexWriterWorker.addEnvironment("DATA_TABLE_NAME", this.table.tableName);
-    this.deleteQueue.grantSendMessages(indexWriterWorker);
-    indexWriterWorker.addEnvironment(
+    this.bucket.grantRead(reindexWorker);
+    reindexWorker.addEnvironment("DATA_BUCKET_NAME", this.bucket.bucketName);
+    this.table.grantReadWriteData(reindexWorker);
+    reindexWorker.addEnvironment("DATA_TABLE_NAME", this.table.tableName);
+    this.deleteQueue.grantSendMessages(reindexWorker);
+    reindexWorker.addEnvironment(
       "ASYNC_DELETE_QUEUE_URL",
       this.deleteQueue.queueUrl
     );
 
-    const asyncDeleteWorker = new RustFunction(this, "async-delete-worker", {
+    const duplicatesWorker = new RustFunction(this, "duplicates-worker", {
       memorySize: props.indexWriter?.memorySize ?? 2048,
-      timeout: props.indexWriter?.timeout ?? Duration.minutes(1),
+      timeout: Duration.minutes(15),
       vpc,
       vpcSubnets: {
         subnets: vpc.isolatedSubnets,
@@ -301,12 +1106,165 @@ export class PatheryStack extends Stack {
         "/mnt/pathery-data"
       ),
     });
-    asyncDeleteWorker.addLayers(configLayer);
-    asyncDeleteWorker.addEventSource(
-      new SqsEventSource(this.deleteQueue, {
-        batchSize: 10,
+    this.configReader(duplicatesWorker, configLayer);
+    duplicatesWorker.addEventSource(
+      new SqsEventSource(this.duplicatesQueue, {
+        batchSize: 1,
+      })
+    );
+    this.bucket.grantWrite(duplicatesWorker);
+    duplicatesWorker.addEnvironment("DATA_BUCKET_NAME", this.bucket.bucketName);
+    duplicatesWorker.addEnvironment(
+      "ASYNC_DELETE_QUEUE_URL",
+      this.deleteQueue.queueUrl
+    );
+
+    const updateByQueryWorker = new RustFunction(
+      this,
+      "update-by-query-worker",
+      {
+        memorySize: props.indexWriter?.memorySize ?? 2048,
+        timeout: Duration.minutes(15),
+        vpc,
+        vpcSubnets: {
+          subnets: vpc.isolatedSubnets,
+        },
+        filesystem: aws_lambda.FileSystem.fromEfsAccessPoint(
+          accessPoint,
+          "/mnt/pathery-data"
+        ),
+      }
+    );
+    this.configReader(updateByQueryWorker, configLayer);
+    this.indexWriterProducer(updateByQueryWorker);
+    this.table.grantReadWriteData(updateByQueryWorker);
+    updateByQueryWorker.addEventSource(
+      new SqsEventSource(this.updateByQueryQueue, {
+        batchSize: 1,
       })
     );
+    updateByQueryWorker.addEnvironment(
+      "ASYNC_DELETE_QUEUE_URL",
+      this.deleteQueue.queueUrl
+    );
+
+    const mergeWorker = new RustFunction(this, "merge-worker", {
+      memorySize: props.indexWriter?.memorySize ?? 2048,
+      timeout: Duration.minutes(15),
+      vpc,
+      vpcSubnets: {
+        subnets: vpc.isolatedSubnets,
+      },
+      filesystem: aws_lambda.FileSystem.fromEfsAccessPoint(
+        accessPoint,
+        "/mnt/pathery-data"
+      ),
+    });
+    this.configReader(mergeWorker, configLayer);
+    this.bucket.grantRead(mergeWorker);
+    this.bucket.grantDelete(mergeWorker);
+    mergeWorker.addEnvironment("DATA_BUCKET_NAME", this.bucket.bucketName);
+    this.table.grantReadWriteData(mergeWorker);
+    mergeWorker.addEnvironment("DATA_TABLE_NAME", this.table.tableName);
+    this.deleteQueue.grantSendMessages(mergeWorker);
+    mergeWorker.addEnvironment(
+      "ASYNC_DELETE_QUEUE_URL",
+      this.deleteQueue.queueUrl
+    );
+    new Rule(this, "MergeSchedule", {
+      schedule:
+        props.mergeWorker?.schedule ?? Schedule.rate(Duration.hours(1)),
+      targets: [new LambdaFunction(mergeWorker, { retryAttempts: 0 })],
+    });
+
+    const expireWorker = new RustFunction(this, "expire-worker", {
+      timeout: Duration.minutes(15),
+      vpc,
+      vpcSubnets: {
+        subnets: vpc.isolatedSubnets,
+      },
+      filesystem: aws_lambda.FileSystem.fromEfsAccessPoint(
+        accessPoint,
+        "/mnt/pathery-data"
+      ),
+    });
+    this.configReader(expireWorker, configLayer);
+    this.indexWriterProducer(expireWorker);
+    new Rule(this, "ExpireSchedule", {
+      schedule:
+        props.expireWorker?.schedule ?? Schedule.rate(Duration.hours(1)),
+      targets: [new LambdaFunction(expireWorker, { retryAttempts: 0 })],
+    });
+
+    const catalogWorker = new RustFunction(this, "catalog-worker", {
+      timeout: Duration.minutes(15),
+      vpc,
+      vpcSubnets: {
+        subnets: vpc.isolatedSubnets,
+      },
+      filesystem: aws_lambda.FileSystem.fromEfsAccessPoint(
+        accessPoint,
+        "/mnt/pathery-data"
+      ),
+    });
+    this.configReader(catalogWorker, configLayer);
+    // Leases and the configs of created indexes are kept in the table.
+    this.table.grantReadWriteData(catalogWorker);
+    catalogWorker.addEnvironment("DATA_TABLE_NAME", this.table.tableName);
+    this.deleteQueue.grantSendMessages(catalogWorker);
+    catalogWorker.addEnvironment(
+      "ASYNC_DELETE_QUEUE_URL",
+      this.deleteQueue.queueUrl
+    );
+    new Rule(this, "CatalogSchedule", {
+      schedule:
+        props.catalogWorker?.schedule ?? Schedule.rate(Duration.minutes(15)),
+      targets: [new LambdaFunction(catalogWorker, { retryAttempts: 0 })],
+    });
+
+    for (const stream of props.dynamodbStreams ?? []) {
+      const scope = new Construct(this, `DynamoDbStream-${stream.indexId}`);
+      // Mounts the indexes to list the partitions of rolling indexes, which
+      // deletes fan out to.
+      const streamWorker = new RustFunction(scope, "dynamodb-stream-worker", {
+        timeout: Duration.minutes(1),
+        vpc,
+        vpcSubnets: {
+          subnets: vpc.isolatedSubnets,
+        },
+        filesystem: aws_lambda.FileSystem.fromEfsAccessPoint(
+          accessPoint,
+          "/mnt/pathery-data"
+        ),
+      });
+      this.configReader(streamWorker, configLayer);
+      this.indexWriterProducer(streamWorker);
+      this.table.grantReadData(streamWorker);
+      this.deleteQueue.grantSendMessages(streamWorker);
+      streamWorker.addEnvironment(
+        "ASYNC_DELETE_QUEUE_URL",
+        this.deleteQueue.queueUrl
+      );
+      streamWorker.addEnvironment("STREAM_INDEX_ID", stream.indexId);
+      streamWorker.addEventSource(
+        new DynamoEventSource(stream.table, {
+          startingPosition: StartingPosition.TRIM_HORIZON,
+          batchSize: stream.batchSize ?? 100,
+          // Splits a failing batch until the record holding it up is found.
+          bisectBatchOnError: true,
+          retryAttempts: 10,
+        })
+      );
+    }
+
+    if (tenantKeys.length > 0) {
+      const tenantKeysJson = this.toJsonString(tenantKeys);
+      for (const child of this.node.findAll()) {
+        if (child instanceof Function) {
+          child.addEnvironment("PATHERY_TENANT_KEYS", tenantKeysJson);
+        }
+      }
+    }
 
     new PatheryDashboard(this, "Dashboard", {
       indexWriterWorker,
@@ -315,6 +1273,71 @@ export class PatheryStack extends Stack {
     new CfnOutput(this, "ApiKeyOutput", {
       value: apiKey.keyId,
     });
+
+    new CfnOutput(this, "ReindexQueueUrlOutput", {
+      value: this.reindexQueue.queueUrl,
+    });
+
+    new CfnOutput(this, "DuplicatesQueueUrlOutput", {
+      value: this.duplicatesQueue.queueUrl,
+    });
+  }
+
+  private indexWriterWorker(
+    scope: Construct,
+    queue: IQueue,
+    configLayer: LayerVersion,
+    props: Partial<FunctionProps>
+  ): Function {
+    const worker = new RustFunction(scope, "index-writer-worker", props);
+    this.configReader(worker, configLayer);
+    this.lookupTableReader(worker);
+    worker.addEventSource(
+      new SqsEventSource(queue, {
+        batchSize: 10,
+        reportBatchItemFailures: true,
+      })
+    );
+    this.bucket.grantRead(worker);
+    this.bucket.grantDelete(worker);
+    worker.addEnvironment("DATA_BUCKET_NAME", this.bucket.bucketName);
+    this.table.grantReadWriteData(worker);
+    worker.addEnvironment("DATA_TABLE_NAME", this.table.tableName);
+    this.deleteQueue.grantSendMessages(worker);
+    worker.addEnvironment("ASYNC_DELETE_QUEUE_URL", this.deleteQueue.queueUrl);
+
+    return worker;
+  }
+
+  /**
+   * Gives `lambda` the bundled config, and read access to the schemas of indexes created through
+   * the API.
+   */
+  /**
+   * Lets an API handler check request keys against the data table's, when `apiKeyAuth` is
+   * enabled.
+   */
+  private apiKeyChecker(lambda: Function, props: PatheryStackProps) {
+    this.table.grantReadData(lambda);
+    lambda.addEnvironment("DATA_TABLE_NAME", this.table.tableName);
+    if (props.apiKeyAuth?.enabled) {
+      lambda.addEnvironment("PATHERY_API_KEY_AUTH", "true");
+    }
+  }
+
+  private configReader(lambda: Function, configLayer: LayerVersion) {
+    lambda.addLayers(configLayer);
+    this.table.grantReadData(lambda);
+    lambda.addEnvironment("DATA_TABLE_NAME", this.table.tableName);
+  }
+
+  /**
+   * Grants read access to the tables that index settings enrich documents from.
+   */
+  private lookupTableReader(lambda: Function) {
+    for (const table of this.lookupTables) {
+      table.grantReadData(lambda);
+    }
   }
 
   private indexWriterProducer(lambda: Function) {
@@ -329,5 +1352,20 @@ export class PatheryStack extends Stack {
       "INDEX_WRITER_QUEUE_URL",
       this.indexWriterQueue.queueUrl
     );
+
+    if (this.dedicatedWriterQueues.length > 0) {
+      for (const { queue } of this.dedicatedWriterQueues) {
+        queue.grantSendMessages(lambda);
+      }
+      lambda.addEnvironment(
+        "INDEX_WRITER_QUEUE_ROUTES",
+        this.toJsonString(
+          this.dedicatedWriterQueues.map(({ route, queue }) => ({
+            prefix: route.indexPrefix,
+            queue_url: queue.queueUrl,
+          }))
+        )
+      );
+    }
   }
 }
@@ -4,6 +4,7 @@ import {
   Function,
   FunctionProps,
   Runtime,
+  Tracing,
 } from "aws-cdk-lib/aws-lambda";
 import { RetentionDays } from "aws-cdk-lib/aws-logs";
 import { Construct } from "constructs";
@@ -19,6 +20,7 @@ export class RustFunction extends Function {
       runtime: Runtime.PROVIDED_AL2,
       architecture: Architecture.ARM_64,
       logRetention: RetentionDays.THREE_DAYS,
+      tracing: props?.tracing ?? Tracing.ACTIVE,
     });
   }
 }
@@ -0,0 +1,119 @@
+//! Snippet generation for queries returning many hits, each highlighting many fields, where
+//! building a snippet generator per hit and field rather than per field would dominate.
+//!
+//! Run with `cargo bench --bench highlight`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use pathery::cursor::CursorKey;
+use pathery::index::RamIndexLoader;
+use pathery::schema::SchemaProvider;
+use pathery::service::index::{BatchIndexService, QueryIndexService, QueryRequest};
+use pathery::service::{ServiceHandler, ServiceRequest};
+use pathery::store::change::MemoryChangeStore;
+use pathery::store::document::MemoryDocumentStore;
+use pathery::store::event::MemoryEventStore;
+use pathery::store::job::MemoryJobStore;
+use pathery::worker::index_writer::client::LocalIndexWriterClient;
+use serde_json::json;
+use tokio::runtime::Runtime;
+
+/// Text fields of each document, all matching the query so every one is highlighted.
+const FIELDS: [&str; 8] = [
+    "title",
+    "subtitle",
+    "summary",
+    "body",
+    "notes",
+    "review",
+    "author",
+    "publisher",
+];
+
+const NUM_DOCS: usize = 1_000;
+
+async fn setup() -> QueryIndexService {
+    let fields: Vec<_> = FIELDS
+        .iter()
+        .map(|name| json!({ "name": name, "kind": "text", "flags": ["TEXT"] }))
+        .collect();
+    let schema_loader = SchemaProvider::from_json(json!({
+        "indexes": [{ "prefix": "bench", "fields": fields }]
+    }));
+    let index_loader = RamIndexLoader::create(schema_loader.clone());
+    let document_store = MemoryDocumentStore::create();
+    let writer_client = LocalIndexWriterClient::create(
+        index_loader.clone(),
+        schema_loader.clone(),
+        document_store.clone(),
+        MemoryJobStore::create(),
+        MemoryChangeStore::create(),
+        MemoryEventStore::create(),
+    );
+    let batch_index = BatchIndexService::new(
+        Box::new(schema_loader.clone()),
+        Box::new(document_store.clone()),
+        Box::new(writer_client),
+    );
+
+    let docs: Vec<_> = (0..NUM_DOCS)
+        .map(|n| {
+            let doc: serde_json::Map<_, _> = FIELDS
+                .iter()
+                .map(|name| {
+                    let text = format!(
+                        "The {} of book {} tells of a lighthouse keeper and the storm that cut \
+                         the island off from the mainland for a winter.",
+                        name, n
+                    );
+                    (name.to_string(), json!(text))
+                })
+                .collect();
+            serde_json::Value::Object(doc)
+        })
+        .collect();
+    for chunk in docs.chunks(100) {
+        let request = ServiceRequest::create(chunk.to_vec()).with_path_param("index_id", "bench");
+        batch_index.handle_request(request).await.unwrap();
+    }
+
+    QueryIndexService::new(
+        Box::new(schema_loader),
+        Box::new(index_loader),
+        Box::new(document_store),
+        CursorKey::generate(),
+    )
+}
+
+fn highlight(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let service = runtime.block_on(setup());
+
+    let mut group = c.benchmark_group("highlight");
+    for hits in [10, 100] {
+        for fields in [1, FIELDS.len()] {
+            let body: QueryRequest = serde_json::from_value(json!({
+                "query": "lighthouse storm winter",
+                "limit": hits,
+                "highlight": { "fields": &FIELDS[..fields] },
+            }))
+            .unwrap();
+
+            group.bench_with_input(
+                BenchmarkId::new(format!("{}_hits", hits), format!("{}_fields", fields)),
+                &body,
+                |b, body| {
+                    b.to_async(&runtime).iter(|| async {
+                        let request = ServiceRequest::create(body.clone())
+                            .with_path_param("index_id", "bench");
+                        let response = service.handle_request(request).await.unwrap();
+                        assert_eq!(hits, response.matches.len());
+                    })
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, highlight);
+criterion_main!(benches);
@@ -0,0 +1,737 @@
+//! Aggregations computed over the documents matching a query.
+//!
+//! ```json
+//! {
+//!   "aggs": {
+//!     "per_month": {
+//!       "date_histogram": { "field": "date_added", "calendar_interval": "month" }
+//!     },
+//!     "genres": {
+//!       "facet": { "field": "category", "path": "/books", "prefix": "sci" }
+//!     },
+//!     "prices": {
+//!       "range": {
+//!         "field": "price",
+//!         "ranges": [{ "to": 25 }, { "from": 25, "to": 50 }, { "from": 50, "key": "50+" }]
+//!       }
+//!     }
+//!   }
+//! }
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{Datelike, Duration, Months, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use tantivy::collector::{
+    Collector, FacetCollector, FacetCounts, FruitHandle, MultiCollector, MultiFruit,
+    SegmentCollector,
+};
+use tantivy::fastfield::{DynamicFastFieldReader, FastFieldReader, FastValue};
+use tantivy::query::Query as TantivyQuery;
+use tantivy::schema::{Facet, Field, FieldType, Schema, Type};
+use tantivy::{DateTime, DocId, Score, Searcher, SegmentOrdinal, SegmentReader};
+
+use crate::service::ServiceError;
+use crate::{json, util};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Aggregation {
+    /// Counts matching documents per time bucket of a `FAST` date field. Buckets without matches
+    /// are omitted.
+    DateHistogram(DateHistogram),
+
+    /// Counts matching documents per child of a path in a `facet` field, most frequent first.
+    Facet(FacetAggregation),
+
+    /// Counts matching documents per range of a `FAST` date or numeric field, in the order the
+    /// ranges are given. Ranges may overlap, counting a document in each range it falls in.
+    Range(RangeAggregation),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DateHistogram {
+    pub field: String,
+
+    /// Bucket width in milliseconds, aligned to the unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fixed_interval: Option<u64>,
+
+    /// Calendar unit per bucket, aligned to local time in the index's time zone.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub calendar_interval: Option<CalendarInterval>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FacetAggregation {
+    pub field: String,
+
+    /// Facet whose direct children are counted. Defaults to the root, `/`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+
+    /// Only count children whose last path segment starts with this, ignoring case. Lets a UI
+    /// filter a facet with thousands of values as the user types.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+
+    /// Maximum number of buckets returned. Defaults to 10.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RangeAggregation {
+    pub field: String,
+
+    pub ranges: Vec<Range>,
+}
+
+/// Values from `from`, inclusive, up to `to`, exclusive. Dates are RFC 3339 strings, or dates
+/// without an offset in the index's time zone.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Range {
+    /// Defaults to `from-to`, with `*` for a missing bound.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<json::Value>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<json::Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CalendarInterval {
+    Day,
+    /// ISO weeks, starting on Monday.
+    Week,
+    Month,
+    Quarter,
+    Year,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Bucket {
+    /// Start of the bucket as an RFC 3339 date for date histograms, the facet path for facet
+    /// counts, or the range's key for ranges.
+    pub key: String,
+    pub doc_count: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum AggregationResult {
+    Buckets { buckets: Vec<Bucket> },
+}
+
+fn invalid(message: String) -> ServiceError {
+    ServiceError::InvalidRequest(message)
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Interval {
+    Fixed(i64),
+    Calendar(CalendarInterval, Tz),
+}
+
+/// Local midnight on `date`, or the first instant after it when a daylight saving change skips
+/// midnight.
+fn start_of_day(date: NaiveDate, time_zone: Tz) -> chrono::DateTime<Tz> {
+    let midnight = date.and_hms_opt(0, 0, 0).expect("midnight is valid");
+
+    (0..24)
+        .find_map(|hour| {
+            time_zone
+                .from_local_datetime(&(midnight + Duration::hours(hour)))
+                .earliest()
+        })
+        .unwrap_or_else(|| time_zone.from_utc_datetime(&midnight))
+}
+
+impl Interval {
+    /// Millisecond bounds `[start, end)` of the bucket containing `timestamp_ms`.
+    fn bucket(&self, timestamp_ms: i64) -> (i64, i64) {
+        match *self {
+            Interval::Fixed(width) => {
+                let start = timestamp_ms.div_euclid(width) * width;
+                (start, start + width)
+            }
+            Interval::Calendar(unit, time_zone) => {
+                let local = time_zone
+                    .timestamp_millis_opt(timestamp_ms)
+                    .single()
+                    .expect("timestamp should be in range")
+                    .date_naive();
+
+                let start = match unit {
+                    CalendarInterval::Day => local,
+                    CalendarInterval::Week => {
+                        local - Duration::days(local.weekday().num_days_from_monday() as i64)
+                    }
+                    CalendarInterval::Month => local.with_day(1).expect("first of month"),
+                    CalendarInterval::Quarter => {
+                        let month = (local.month0() / 3) * 3 + 1;
+                        NaiveDate::from_ymd_opt(local.year(), month, 1).expect("first of quarter")
+                    }
+                    CalendarInterval::Year => {
+                        NaiveDate::from_ymd_opt(local.year(), 1, 1).expect("first of year")
+                    }
+                };
+
+                let end = match unit {
+                    CalendarInterval::Day => start + Duration::days(1),
+                    CalendarInterval::Week => start + Duration::days(7),
+                    CalendarInterval::Month => start + Months::new(1),
+                    CalendarInterval::Quarter => start + Months::new(3),
+                    CalendarInterval::Year => start + Months::new(12),
+                };
+
+                (
+                    start_of_day(start, time_zone).timestamp_millis(),
+                    start_of_day(end, time_zone).timestamp_millis(),
+                )
+            }
+        }
+    }
+
+    fn key(&self, start_ms: i64) -> String {
+        let start = Utc
+            .timestamp_millis_opt(start_ms)
+            .single()
+            .expect("bucket start should be in range");
+
+        match *self {
+            Interval::Fixed(_) => start.to_rfc3339(),
+            Interval::Calendar(_, time_zone) => start.with_timezone(&time_zone).to_rfc3339(),
+        }
+    }
+}
+
+struct DateHistogramCollector {
+    field: Field,
+    interval: Interval,
+}
+
+struct DateHistogramSegmentCollector {
+    reader: DynamicFastFieldReader<DateTime>,
+    interval: Interval,
+    // Matches arrive in doc id order, which often clusters by time, so the last bucket is
+    // checked before computing a new one.
+    current: Option<(i64, i64)>,
+    counts: BTreeMap<i64, u64>,
+}
+
+impl Collector for DateHistogramCollector {
+    type Fruit = BTreeMap<i64, u64>;
+
+    type Child = DateHistogramSegmentCollector;
+
+    fn for_segment(
+        &self,
+        _segment_local_id: SegmentOrdinal,
+        segment: &SegmentReader,
+    ) -> tantivy::Result<Self::Child> {
+        Ok(DateHistogramSegmentCollector {
+            reader: segment.fast_fields().date(self.field)?,
+            interval: self.interval,
+            current: None,
+            counts: BTreeMap::new(),
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        false
+    }
+
+    fn merge_fruits(&self, fruits: Vec<Self::Fruit>) -> tantivy::Result<Self::Fruit> {
+        let mut merged = BTreeMap::new();
+        for fruit in fruits {
+            for (start, count) in fruit {
+                *merged.entry(start).or_default() += count;
+            }
+        }
+        Ok(merged)
+    }
+}
+
+impl SegmentCollector for DateHistogramSegmentCollector {
+    type Fruit = BTreeMap<i64, u64>;
+
+    fn collect(&mut self, doc: DocId, _score: Score) {
+        let timestamp_ms = self.reader.get(doc).into_unix_timestamp() * 1000;
+
+        let (start, end) = match self.current {
+            Some((start, end)) if start <= timestamp_ms && timestamp_ms < end => (start, end),
+            _ => self.interval.bucket(timestamp_ms),
+        };
+
+        self.current = Some((start, end));
+        *self.counts.entry(start).or_default() += 1;
+    }
+
+    fn harvest(self) -> Self::Fruit {
+        self.counts
+    }
+}
+
+impl DateHistogram {
+    fn collector(
+        &self,
+        schema: &Schema,
+        time_zone: Tz,
+    ) -> Result<DateHistogramCollector, ServiceError> {
+        let field = schema
+            .get_field(&self.field)
+            .ok_or_else(|| invalid(format!("Field [{}] does not exist", self.field)))?;
+
+        let entry = schema.get_field_entry(field);
+        if !entry.is_fast() || entry.field_type().value_type() != Type::Date {
+            return Err(invalid(format!(
+                "Field [{}] must be a FAST date field for a date histogram",
+                self.field
+            )));
+        }
+
+        let interval = match (self.fixed_interval, self.calendar_interval) {
+            (Some(width), None) if width > 0 => Interval::Fixed(width as i64),
+            (None, Some(unit)) => Interval::Calendar(unit, time_zone),
+            _ => {
+                return Err(invalid(String::from(
+                    "Date histogram requires either a positive fixed_interval or a \
+                     calendar_interval",
+                )))
+            }
+        };
+
+        Ok(DateHistogramCollector { field, interval })
+    }
+}
+
+/// Fast field values in tantivy's order preserving `u64` encoding, so one collector covers every
+/// numeric and date type. Bounds are `[from, to)`, `None` being unbounded.
+type Bounds = (Option<u64>, Option<u64>);
+
+struct RangeCollector {
+    field: Field,
+    bounds: Vec<Bounds>,
+}
+
+struct RangeSegmentCollector {
+    reader: DynamicFastFieldReader<u64>,
+    bounds: Vec<Bounds>,
+    counts: Vec<u64>,
+}
+
+fn in_bounds((from, to): &Bounds, value: u64) -> bool {
+    from.is_none_or(|from| from <= value) && to.is_none_or(|to| value < to)
+}
+
+impl Collector for RangeCollector {
+    type Fruit = Vec<u64>;
+
+    type Child = RangeSegmentCollector;
+
+    fn for_segment(
+        &self,
+        _segment_local_id: SegmentOrdinal,
+        segment: &SegmentReader,
+    ) -> tantivy::Result<Self::Child> {
+        Ok(RangeSegmentCollector {
+            reader: segment.fast_fields().u64_lenient(self.field)?,
+            bounds: self.bounds.clone(),
+            counts: vec![0; self.bounds.len()],
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        false
+    }
+
+    fn merge_fruits(&self, fruits: Vec<Self::Fruit>) -> tantivy::Result<Self::Fruit> {
+        let mut merged = vec![0; self.bounds.len()];
+        for fruit in fruits {
+            for (total, count) in merged.iter_mut().zip(fruit) {
+                *total += count;
+            }
+        }
+        Ok(merged)
+    }
+}
+
+impl SegmentCollector for RangeSegmentCollector {
+    type Fruit = Vec<u64>;
+
+    fn collect(&mut self, doc: DocId, _score: Score) {
+        let value = self.reader.get(doc);
+        for (bounds, count) in self.bounds.iter().zip(&mut self.counts) {
+            if in_bounds(bounds, value) {
+                *count += 1;
+            }
+        }
+    }
+
+    fn harvest(self) -> Self::Fruit {
+        self.counts
+    }
+}
+
+fn bound_key(bound: &Option<json::Value>) -> String {
+    match bound {
+        Some(json::Value::String(value)) => value.clone(),
+        Some(value) => value.to_string(),
+        None => String::from("*"),
+    }
+}
+
+impl RangeAggregation {
+    /// `bound` in the fast field encoding of `value_type`.
+    fn encode(
+        &self,
+        value_type: Type,
+        bound: &json::Value,
+        time_zone: Tz,
+    ) -> Result<u64, ServiceError> {
+        let encoded = match value_type {
+            Type::U64 => bound.as_u64().map(|value| value.to_u64()),
+            Type::I64 => bound.as_i64().map(|value| value.to_u64()),
+            Type::F64 => bound.as_f64().map(|value| value.to_u64()),
+            Type::Date => bound
+                .as_str()
+                .and_then(|value| util::parse_date(value, time_zone))
+                .map(|date| DateTime::from_unix_timestamp(date.timestamp()).to_u64()),
+            _ => None,
+        };
+
+        encoded.ok_or_else(|| {
+            invalid(format!(
+                "Range bound [{}] is not a valid {} for field [{}]",
+                bound,
+                value_type.name(),
+                self.field
+            ))
+        })
+    }
+
+    fn collector(&self, schema: &Schema, time_zone: Tz) -> Result<RangeCollector, ServiceError> {
+        let field = schema
+            .get_field(&self.field)
+            .ok_or_else(|| invalid(format!("Field [{}] does not exist", self.field)))?;
+
+        let entry = schema.get_field_entry(field);
+        let value_type = entry.field_type().value_type();
+        if !entry.is_fast() || !matches!(value_type, Type::U64 | Type::I64 | Type::F64 | Type::Date)
+        {
+            return Err(invalid(format!(
+                "Field [{}] must be a FAST date or numeric field for a range aggregation",
+                self.field
+            )));
+        }
+
+        if self.ranges.is_empty() {
+            return Err(invalid(String::from(
+                "Range aggregation requires at least one range",
+            )));
+        }
+
+        let bounds = self
+            .ranges
+            .iter()
+            .map(|range| {
+                let encode = |bound: &Option<json::Value>| {
+                    bound
+                        .as_ref()
+                        .map(|bound| self.encode(value_type, bound, time_zone))
+                        .transpose()
+                };
+                Ok((encode(&range.from)?, encode(&range.to)?))
+            })
+            .collect::<Result<_, ServiceError>>()?;
+
+        Ok(RangeCollector { field, bounds })
+    }
+
+    fn buckets(&self, counts: Vec<u64>) -> Vec<Bucket> {
+        self.ranges
+            .iter()
+            .zip(counts)
+            .map(|(range, doc_count)| Bucket {
+                key: range.key.clone().unwrap_or_else(|| {
+                    format!("{}-{}", bound_key(&range.from), bound_key(&range.to))
+                }),
+                doc_count,
+            })
+            .collect()
+    }
+}
+
+impl Aggregation {
+    /// The field the aggregation groups by.
+    pub fn field(&self) -> &str {
+        match self {
+            Aggregation::DateHistogram(histogram) => &histogram.field,
+            Aggregation::Facet(facet) => &facet.field,
+            Aggregation::Range(range) => &range.field,
+        }
+    }
+
+    pub fn set_field(&mut self, field: String) {
+        match self {
+            Aggregation::DateHistogram(histogram) => histogram.field = field,
+            Aggregation::Facet(facet) => facet.field = field,
+            Aggregation::Range(range) => range.field = field,
+        }
+    }
+
+    /// Validates the aggregation against `schema` and adds its collector to `collectors`.
+    /// Calendar intervals are computed in `time_zone`.
+    fn prepare<'a>(
+        &'a self,
+        schema: &Schema,
+        time_zone: Tz,
+        collectors: &mut MultiCollector,
+    ) -> Result<Prepared<'a>, ServiceError> {
+        Ok(match self {
+            Aggregation::DateHistogram(histogram) => {
+                let collector = histogram.collector(schema, time_zone)?;
+                let interval = collector.interval;
+                Prepared::DateHistogram(collectors.add_collector(collector), interval)
+            }
+            Aggregation::Facet(facet) => {
+                let (collector, path) = facet.collector(schema)?;
+                Prepared::Facet(collectors.add_collector(collector), facet, path)
+            }
+            Aggregation::Range(range) => {
+                let collector = range.collector(schema, time_zone)?;
+                Prepared::Range(collectors.add_collector(collector), range)
+            }
+        })
+    }
+
+    /// Runs the aggregation over the documents matching `query`. Calendar intervals are computed
+    /// in `time_zone`.
+    pub fn run(
+        &self,
+        searcher: &Searcher,
+        query: &dyn TantivyQuery,
+        time_zone: Tz,
+    ) -> Result<AggregationResult, ServiceError> {
+        let mut collectors = MultiCollector::new();
+        let prepared = self.prepare(searcher.schema(), time_zone, &mut collectors)?;
+
+        let mut fruits = searcher
+            .search(query, &collectors)
+            .map_err(|err| invalid(err.to_string()))?;
+
+        Ok(prepared.result(&mut fruits))
+    }
+}
+
+/// An aggregation whose collector was added to a pass over the matches, waiting for its fruit.
+enum Prepared<'a> {
+    DateHistogram(FruitHandle<BTreeMap<i64, u64>>, Interval),
+
+    Facet(FruitHandle<FacetCounts>, &'a FacetAggregation, Facet),
+
+    Range(FruitHandle<Vec<u64>>, &'a RangeAggregation),
+}
+
+impl Prepared<'_> {
+    fn result(self, fruits: &mut MultiFruit) -> AggregationResult {
+        match self {
+            Prepared::DateHistogram(handle, interval) => AggregationResult::Buckets {
+                buckets: handle
+                    .extract(fruits)
+                    .into_iter()
+                    .map(|(start, doc_count)| Bucket {
+                        key: interval.key(start),
+                        doc_count,
+                    })
+                    .collect(),
+            },
+            Prepared::Facet(handle, facet, path) => AggregationResult::Buckets {
+                buckets: facet.buckets(handle.extract(fruits), path),
+            },
+            Prepared::Range(handle, range) => AggregationResult::Buckets {
+                buckets: range.buckets(handle.extract(fruits)),
+            },
+        }
+    }
+}
+
+impl FacetAggregation {
+    const DEFAULT_SIZE: usize = 10;
+
+    fn path(&self) -> Result<Facet, ServiceError> {
+        match &self.path {
+            Some(path) => Facet::from_text(path)
+                .map_err(|_| invalid(format!("Invalid facet path [{}]", path))),
+            None => Ok(Facet::root()),
+        }
+    }
+
+    fn collector(&self, schema: &Schema) -> Result<(FacetCollector, Facet), ServiceError> {
+        let field = schema
+            .get_field(&self.field)
+            .ok_or_else(|| invalid(format!("Field [{}] does not exist", self.field)))?;
+
+        if !matches!(
+            schema.get_field_entry(field).field_type(),
+            FieldType::Facet(_)
+        ) {
+            return Err(invalid(format!(
+                "Field [{}] must be a facet field for a facet aggregation",
+                self.field
+            )));
+        }
+
+        let path = self.path()?;
+        let mut collector = FacetCollector::for_field(field);
+        collector.add_facet(path.clone());
+
+        Ok((collector, path))
+    }
+
+    fn buckets(&self, counts: FacetCounts, path: Facet) -> Vec<Bucket> {
+        let prefix = self.prefix.as_deref().map(str::to_lowercase);
+
+        let mut buckets = counts
+            .get(path)
+            .filter(|(facet, _)| match &prefix {
+                Some(prefix) => facet
+                    .to_path()
+                    .last()
+                    .is_some_and(|segment| segment.to_lowercase().starts_with(prefix)),
+                None => true,
+            })
+            .map(|(facet, doc_count)| Bucket {
+                key: facet.to_path_string(),
+                doc_count,
+            })
+            .collect::<Vec<_>>();
+
+        buckets.sort_by(|a, b| b.doc_count.cmp(&a.doc_count).then(a.key.cmp(&b.key)));
+        buckets.truncate(self.size.unwrap_or(Self::DEFAULT_SIZE));
+
+        buckets
+    }
+}
+
+/// Runs each aggregation over the documents matching `query`, all in one pass over the matches.
+/// Calendar intervals are computed in `time_zone`.
+pub fn aggregate(
+    searcher: &Searcher,
+    query: &dyn TantivyQuery,
+    aggs: &HashMap<String, Aggregation>,
+    time_zone: Tz,
+) -> Result<HashMap<String, AggregationResult>, ServiceError> {
+    if aggs.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let mut collectors = MultiCollector::new();
+    let prepared = aggs
+        .iter()
+        .map(|(name, agg)| {
+            Ok((
+                name,
+                agg.prepare(searcher.schema(), time_zone, &mut collectors)?,
+            ))
+        })
+        .collect::<Result<Vec<_>, ServiceError>>()?;
+
+    let mut fruits = searcher
+        .search(query, &collectors)
+        .map_err(|err| invalid(err.to_string()))?;
+
+    Ok(prepared
+        .into_iter()
+        .map(|(name, prepared)| (name.clone(), prepared.result(&mut fruits)))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn millis(date: &str) -> i64 {
+        chrono::DateTime::parse_from_rfc3339(date)
+            .unwrap()
+            .timestamp_millis()
+    }
+
+    fn bucket(interval: Interval, date: &str) -> (String, String) {
+        let (start, end) = interval.bucket(millis(date));
+        (interval.key(start), interval.key(end))
+    }
+
+    #[test]
+    fn calendar_buckets_follow_local_time() {
+        let tz: Tz = "America/New_York".parse().unwrap();
+
+        // 03:00 UTC is still the previous day in New York.
+        assert_eq!(
+            (
+                String::from("2022-11-13T00:00:00-05:00"),
+                String::from("2022-11-14T00:00:00-05:00")
+            ),
+            bucket(
+                Interval::Calendar(CalendarInterval::Day, tz),
+                "2022-11-14T03:00:00Z"
+            )
+        );
+
+        // The day daylight saving ends is 25 hours long.
+        let (start, end) =
+            Interval::Calendar(CalendarInterval::Day, tz).bucket(millis("2022-11-06T12:00:00Z"));
+        assert_eq!(25 * 3_600_000, end - start);
+
+        assert_eq!(
+            (
+                String::from("2022-02-01T00:00:00-05:00"),
+                String::from("2022-03-01T00:00:00-05:00")
+            ),
+            bucket(
+                Interval::Calendar(CalendarInterval::Month, tz),
+                "2022-02-28T12:00:00Z"
+            )
+        );
+
+        assert_eq!(
+            (
+                String::from("2022-01-01T00:00:00-05:00"),
+                String::from("2022-04-01T00:00:00-04:00")
+            ),
+            bucket(
+                Interval::Calendar(CalendarInterval::Quarter, tz),
+                "2022-03-31T12:00:00Z"
+            )
+        );
+
+        assert_eq!(
+            (
+                String::from("2022-11-14T00:00:00-05:00"),
+                String::from("2022-11-21T00:00:00-05:00")
+            ),
+            bucket(
+                Interval::Calendar(CalendarInterval::Week, tz),
+                "2022-11-17T12:00:00Z"
+            )
+        );
+    }
+
+    #[test]
+    fn fixed_buckets_align_to_epoch() {
+        assert_eq!(
+            (
+                String::from("2022-11-14T00:00:00+00:00"),
+                String::from("2022-11-14T06:00:00+00:00")
+            ),
+            bucket(Interval::Fixed(6 * 3_600_000), "2022-11-14T03:00:00Z")
+        );
+    }
+}
@@ -0,0 +1,220 @@
+//! Search analytics for relevance tuning and auditing.
+//!
+//! Queries to indexes with `settings.analytics` are recorded with their top results as a
+//! [`QueryRecord`], and the records written in batches of newline delimited JSON to the data
+//! bucket, under `analytics/dt=YYYY-MM-DD/`. Batches are held in memory until they fill or get
+//! old, and written by the query that completes them, so queries aren't slowed by a write each.
+//! Records still held when Lambda shuts the function down are lost, which makes analytics a
+//! near-complete record rather than an exhaustive one.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::lambda::http::current_request_id;
+use crate::service::index::{QueryRequest, QueryResponse};
+use crate::store::analytics::{AnalyticsStore, S3AnalyticsStore};
+use crate::{json, util};
+
+/// Matches recorded of each query, best first.
+pub const TOP_RESULTS: usize = 10;
+
+/// Records written together, at most.
+const MAX_BATCH_RECORDS: usize = 500;
+
+/// How long a record is held before its batch is written, unless no query comes to write it.
+const MAX_BATCH_AGE: Duration = Duration::from_secs(60);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct QueryResult {
+    pub id: String,
+    pub score: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct QueryRecord {
+    /// When the query was answered, as an RFC 3339 timestamp.
+    pub at: String,
+
+    pub index_id: String,
+
+    pub request_id: Option<String>,
+
+    /// Name of the API key the query was made with, or else its user agent.
+    pub client: String,
+
+    /// The query as requested.
+    pub query: json::Value,
+
+    pub took_ms: u64,
+
+    pub total_hits: Option<u64>,
+
+    pub timed_out: bool,
+
+    /// The first [`TOP_RESULTS`] matches returned.
+    pub results: Vec<QueryResult>,
+}
+
+impl QueryRecord {
+    pub fn create(
+        index_id: &str,
+        client: &str,
+        request: &QueryRequest,
+        response: &QueryResponse,
+        took: Duration,
+    ) -> Self {
+        let results = response
+            .matches
+            .iter()
+            .take(TOP_RESULTS)
+            .map(|hit| QueryResult {
+                id: hit.doc["__id"][0].as_str().unwrap_or_default().into(),
+                score: hit.score,
+            })
+            .collect();
+
+        QueryRecord {
+            at: util::timestamp(),
+            index_id: index_id.into(),
+            request_id: current_request_id(),
+            client: client.into(),
+            query: json::to_value(request).expect("query should serialize"),
+            took_ms: took.as_millis() as u64,
+            total_hits: response.total.as_ref().map(|total| total.value),
+            timed_out: response.timed_out,
+            results,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Batch {
+    records: Vec<QueryRecord>,
+    opened_at: Option<Instant>,
+}
+
+/// Buffers query records and writes them to the analytics store in batches.
+pub struct Analytics {
+    store: Box<dyn AnalyticsStore>,
+    batch: Mutex<Batch>,
+}
+
+/// Key of a batch written at `at`, partitioned by day for query engines such as Athena.
+fn batch_key(at: DateTime<Utc>) -> String {
+    format!(
+        "analytics/dt={}/{}-{}.ndjson",
+        at.format("%Y-%m-%d"),
+        at.format("%H%M%S%3f"),
+        util::generate_id()
+    )
+}
+
+impl Analytics {
+    pub async fn create() -> Self {
+        Analytics::new(Box::new(S3AnalyticsStore::create(None).await))
+    }
+
+    pub fn new(store: Box<dyn AnalyticsStore>) -> Self {
+        Analytics {
+            store,
+            batch: Mutex::new(Batch::default()),
+        }
+    }
+
+    /// Adds `record` to the batch, writing the batch once it's full or old.
+    pub async fn record(&self, record: QueryRecord) {
+        let ready = {
+            let mut batch = self.batch.lock().unwrap();
+            let opened_at = *batch.opened_at.get_or_insert_with(Instant::now);
+            batch.records.push(record);
+
+            let ready =
+                batch.records.len() >= MAX_BATCH_RECORDS || opened_at.elapsed() >= MAX_BATCH_AGE;
+            ready.then(|| std::mem::take(&mut *batch))
+        };
+
+        if let Some(batch) = ready {
+            self.write(batch.records).await;
+        }
+    }
+
+    /// Writes the records held so far.
+    pub async fn flush(&self) {
+        let batch = std::mem::take(&mut *self.batch.lock().unwrap());
+        if !batch.records.is_empty() {
+            self.write(batch.records).await;
+        }
+    }
+
+    /// Writes `records` as a batch, logging rather than returning failures so that queries
+    /// never fail for want of analytics.
+    async fn write(&self, records: Vec<QueryRecord>) {
+        let mut batch = vec![];
+        for record in &records {
+            json::to_writer(&mut batch, record).expect("record should serialize");
+            batch.push(b'\n');
+        }
+
+        let key = batch_key(Utc::now());
+        if let Err(err) = self.store.put_batch(&key, batch).await {
+            warn!(message = "analytics_write_failed", key, records = records.len(), error = %err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::analytics::test_util::TestAnalyticsStore;
+
+    fn record(index_id: &str) -> QueryRecord {
+        QueryRecord {
+            at: util::timestamp(),
+            index_id: index_id.into(),
+            request_id: None,
+            client: String::from("search-ui"),
+            query: json::json!({ "query": "zen" }),
+            took_ms: 3,
+            total_hits: Some(1),
+            timed_out: false,
+            results: vec![QueryResult {
+                id: String::from("zen"),
+                score: 1.5,
+            }],
+        }
+    }
+
+    #[tokio::test]
+    async fn records_are_written_in_batches_of_ndjson() {
+        let store = TestAnalyticsStore::create();
+        let analytics = Analytics::new(Box::new(store.clone()));
+
+        analytics.record(record("books")).await;
+        analytics.record(record("films")).await;
+        assert!(store.batches().is_empty());
+
+        analytics.flush().await;
+        let batches = store.batches();
+        assert_eq!(1, batches.len());
+
+        let (key, batch) = batches.into_iter().next().unwrap();
+        assert!(key.starts_with("analytics/dt="));
+        assert!(key.ends_with(".ndjson"));
+
+        let records: Vec<QueryRecord> = String::from_utf8(batch)
+            .unwrap()
+            .lines()
+            .map(|line| json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(2, records.len());
+        assert_eq!("films", records[1].index_id);
+
+        // Nothing is left to write.
+        analytics.flush().await;
+        assert_eq!(1, store.batches().len());
+    }
+}
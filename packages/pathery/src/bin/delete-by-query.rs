@@ -0,0 +1,12 @@
+use pathery::lambda::http::ApiKeyAuth;
+use pathery::service::index::DeleteByQueryService;
+use pathery::service::start_service;
+use pathery::store::api_key::ApiKeyScope;
+
+#[tokio::main]
+async fn main() -> Result<(), lambda_http::Error> {
+    let service =
+        ApiKeyAuth::create(DeleteByQueryService::create().await, ApiKeyScope::Write).await;
+
+    start_service(&service).await
+}
@@ -1,9 +1,11 @@
+use pathery::lambda::http::ApiKeyAuth;
 use pathery::service::doc::DeleteDocService;
 use pathery::service::start_service;
+use pathery::store::api_key::ApiKeyScope;
 
 #[tokio::main]
 async fn main() -> Result<(), lambda_http::Error> {
-    let service = DeleteDocService::create().await;
+    let service = ApiKeyAuth::create(DeleteDocService::create().await, ApiKeyScope::Write).await;
 
     start_service(&service).await
 }
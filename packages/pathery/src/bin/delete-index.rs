@@ -0,0 +1,11 @@
+use pathery::lambda::http::ApiKeyAuth;
+use pathery::service::index::DeleteIndexService;
+use pathery::service::start_service;
+use pathery::store::api_key::ApiKeyScope;
+
+#[tokio::main]
+async fn main() -> Result<(), lambda_http::Error> {
+    let service = ApiKeyAuth::create(DeleteIndexService::create().await, ApiKeyScope::Admin).await;
+
+    start_service(&service).await
+}
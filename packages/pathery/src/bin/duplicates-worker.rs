@@ -0,0 +1,29 @@
+use pathery::index::LambdaIndexLoader;
+use pathery::lambda;
+use pathery::lambda::lambda_runtime::{run, service_fn};
+use pathery::lambda::sqs;
+use pathery::schema::SchemaProvider;
+use pathery::store::document::DDBDocumentStore;
+use pathery::store::report::S3ReportStore;
+use pathery::worker::duplicates::handle_event;
+
+#[tokio::main]
+async fn main() -> Result<(), sqs::Error> {
+    lambda::init_tracing();
+
+    let document_store = DDBDocumentStore::create(None).await;
+    let index_loader = LambdaIndexLoader::create().await;
+    let schema_loader = SchemaProvider::lambda().await;
+    let report_store = S3ReportStore::create(None).await;
+
+    run(service_fn(|event| {
+        handle_event(
+            &document_store,
+            &index_loader,
+            &schema_loader,
+            &report_store,
+            event,
+        )
+    }))
+    .await
+}
@@ -0,0 +1,30 @@
+use pathery::index::LambdaIndexLoader;
+use pathery::lambda::lambda_runtime::{run, service_fn, Error};
+use pathery::schema::SchemaProvider;
+use pathery::store::document::DDBDocumentStore;
+use pathery::worker::dynamodb_stream::handle_event;
+use pathery::worker::index_writer::client::LambdaIndexWriterClient;
+use pathery::{lambda, util};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    lambda::init_tracing();
+
+    let index_id = util::require_env("STREAM_INDEX_ID");
+    let schema_loader = SchemaProvider::lambda().await;
+    let index_loader = LambdaIndexLoader::create().await;
+    let document_store = DDBDocumentStore::create(None).await;
+    let writer_client = LambdaIndexWriterClient::create(None).await;
+
+    run(service_fn(|event| {
+        handle_event(
+            &schema_loader,
+            &index_loader,
+            &document_store,
+            &writer_client,
+            &index_id,
+            event,
+        )
+    }))
+    .await
+}
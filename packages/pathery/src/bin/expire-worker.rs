@@ -0,0 +1,20 @@
+use pathery::index::LambdaIndexLoader;
+use pathery::lambda;
+use pathery::lambda::lambda_runtime::{run, service_fn, Error};
+use pathery::schema::SchemaProvider;
+use pathery::worker::expire::handle_event;
+use pathery::worker::index_writer::client::LambdaIndexWriterClient;
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    lambda::init_tracing();
+
+    let index_loader = LambdaIndexLoader::create().await;
+    let schema_loader = SchemaProvider::lambda().await;
+    let writer_client = LambdaIndexWriterClient::create(None).await;
+
+    run(service_fn(|event| {
+        handle_event(&index_loader, &schema_loader, &writer_client, event)
+    }))
+    .await
+}
@@ -1,9 +1,18 @@
+use pathery::enrich::Enricher;
 use pathery::index::LambdaIndexLoader;
 use pathery::lambda;
 use pathery::lambda::lambda_runtime::{run, service_fn};
 use pathery::lambda::sqs;
+use pathery::schema::SchemaProvider;
+use pathery::store::change::DDBChangeStore;
 use pathery::store::document::DDBDocumentStore;
+use pathery::store::event::DDBEventStore;
+use pathery::store::job::DDBJobStore;
+use pathery::store::lease::DDBLeaseStore;
+use pathery::store::lookup::DDBLookupTable;
+use pathery::store::token::DDBTokenStore;
 use pathery::worker::index_writer::handle_event;
+use pathery::worker::index_writer::pool::WriterPool;
 
 #[tokio::main]
 async fn main() -> Result<(), sqs::Error> {
@@ -11,9 +20,29 @@ async fn main() -> Result<(), sqs::Error> {
 
     let document_store = DDBDocumentStore::create(None).await;
     let index_loader = LambdaIndexLoader::create().await;
+    let schema_loader = SchemaProvider::lambda().await;
+    let job_store = DDBJobStore::create(None).await;
+    let change_store = DDBChangeStore::create(None).await;
+    let event_store = DDBEventStore::create(None).await;
+    let token_store = DDBTokenStore::create(None).await;
+    let lease_store = DDBLeaseStore::create(None).await;
+    let writer_pool = WriterPool::default();
+    let enricher = Enricher::new(DDBLookupTable::create().await);
 
     run(service_fn(|event| {
-        handle_event(&document_store, &index_loader, event)
+        handle_event(
+            &document_store,
+            &index_loader,
+            &schema_loader,
+            &job_store,
+            &change_store,
+            &event_store,
+            &token_store,
+            &lease_store,
+            &writer_pool,
+            &enricher,
+            event,
+        )
     }))
     .await
 }
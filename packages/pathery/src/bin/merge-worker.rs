@@ -0,0 +1,28 @@
+use pathery::index::LambdaIndexLoader;
+use pathery::lambda;
+use pathery::lambda::lambda_runtime::{run, service_fn, Error};
+use pathery::schema::SchemaProvider;
+use pathery::store::event::DDBEventStore;
+use pathery::store::lease::DDBLeaseStore;
+use pathery::worker::merge::handle_event;
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    lambda::init_tracing();
+
+    let index_loader = LambdaIndexLoader::create().await;
+    let schema_loader = SchemaProvider::lambda().await;
+    let lease_store = DDBLeaseStore::create(None).await;
+    let event_store = DDBEventStore::create(None).await;
+
+    run(service_fn(|event| {
+        handle_event(
+            &index_loader,
+            &schema_loader,
+            &lease_store,
+            &event_store,
+            event,
+        )
+    }))
+    .await
+}
@@ -0,0 +1,13 @@
+use pathery::lambda::http::{ApiKeyAuth, FieldDeprecations};
+use pathery::service::doc::PatchDocService;
+use pathery::service::start_service;
+use pathery::store::api_key::ApiKeyScope;
+use pathery::store::field_usage::FieldUse;
+
+#[tokio::main]
+async fn main() -> Result<(), lambda_http::Error> {
+    let service = FieldDeprecations::create(PatchDocService::create().await, FieldUse::Write).await;
+    let service = ApiKeyAuth::create(service, ApiKeyScope::Write).await;
+
+    start_service(&service).await
+}
@@ -0,0 +1,22 @@
+use std::env;
+use std::net::SocketAddr;
+
+use pathery::lambda;
+use pathery::schema::SchemaProvider;
+use pathery::server::LocalServer;
+
+#[tokio::main]
+async fn main() -> Result<(), lambda::Error> {
+    lambda::init_tracing();
+
+    let config_path = env::var("PATHERY_CONFIG").unwrap_or_else(|_| String::from("pathery.json"));
+    let addr: SocketAddr = env::var("PATHERY_ADDR")
+        .unwrap_or_else(|_| String::from("127.0.0.1:8080"))
+        .parse()?;
+
+    let schema_loader = SchemaProvider::from_path(&config_path);
+
+    LocalServer::create(schema_loader).serve(addr).await?;
+
+    Ok(())
+}
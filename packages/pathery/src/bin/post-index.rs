@@ -1,9 +1,15 @@
+use pathery::lambda::http::{ApiKeyAuth, FieldDeprecations, RateLimited};
 use pathery::service::index::PostIndexService;
 use pathery::service::start_service;
+use pathery::store::api_key::ApiKeyScope;
+use pathery::store::field_usage::FieldUse;
 
 #[tokio::main]
 async fn main() -> Result<(), lambda_http::Error> {
-    let service = PostIndexService::create().await;
+    let service =
+        FieldDeprecations::create(PostIndexService::create().await, FieldUse::Write).await;
+    let service = RateLimited::create(service).await;
+    let service = ApiKeyAuth::create(service, ApiKeyScope::Write).await;
 
     start_service(&service).await
 }
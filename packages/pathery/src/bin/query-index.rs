@@ -1,9 +1,15 @@
+use pathery::lambda::http::{ApiKeyAuth, FieldDeprecations, RateLimited};
 use pathery::service::index::QueryIndexService;
 use pathery::service::start_service;
+use pathery::store::api_key::ApiKeyScope;
+use pathery::store::field_usage::FieldUse;
 
 #[tokio::main]
 async fn main() -> Result<(), lambda_http::Error> {
-    let service = QueryIndexService::create().await;
+    let service =
+        FieldDeprecations::create(QueryIndexService::create().await, FieldUse::Query).await;
+    let service = RateLimited::create(service).await;
+    let service = ApiKeyAuth::create(service, ApiKeyScope::Read).await;
 
     start_service(&service).await
 }
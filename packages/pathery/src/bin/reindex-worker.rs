@@ -0,0 +1,33 @@
+use pathery::enrich::Enricher;
+use pathery::index::LambdaIndexLoader;
+use pathery::lambda;
+use pathery::lambda::lambda_runtime::{run, service_fn};
+use pathery::lambda::sqs;
+use pathery::schema::SchemaProvider;
+use pathery::store::document::DDBDocumentStore;
+use pathery::store::lease::DDBLeaseStore;
+use pathery::store::lookup::DDBLookupTable;
+use pathery::worker::reindex::handle_event;
+
+#[tokio::main]
+async fn main() -> Result<(), sqs::Error> {
+    lambda::init_tracing();
+
+    let document_store = DDBDocumentStore::create(None).await;
+    let index_loader = LambdaIndexLoader::create().await;
+    let schema_loader = SchemaProvider::lambda().await;
+    let lease_store = DDBLeaseStore::create(None).await;
+    let enricher = Enricher::new(DDBLookupTable::create().await);
+
+    run(service_fn(|event| {
+        handle_event(
+            &document_store,
+            &index_loader,
+            &schema_loader,
+            &lease_store,
+            &enricher,
+            event,
+        )
+    }))
+    .await
+}
@@ -1,9 +1,11 @@
+use pathery::lambda::http::ApiKeyAuth;
 use pathery::service::index::StatsIndexService;
 use pathery::service::start_service;
+use pathery::store::api_key::ApiKeyScope;
 
 #[tokio::main]
 async fn main() -> Result<(), lambda_http::Error> {
-    let service = StatsIndexService::create().await;
+    let service = ApiKeyAuth::create(StatsIndexService::create().await, ApiKeyScope::Read).await;
 
     start_service(&service).await
 }
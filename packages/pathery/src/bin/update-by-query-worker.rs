@@ -0,0 +1,32 @@
+use pathery::index::LambdaIndexLoader;
+use pathery::lambda;
+use pathery::lambda::lambda_runtime::{run, service_fn};
+use pathery::lambda::sqs;
+use pathery::schema::SchemaProvider;
+use pathery::store::document::DDBDocumentStore;
+use pathery::store::job::DDBJobStore;
+use pathery::worker::index_writer::client::LambdaIndexWriterClient;
+use pathery::worker::update_by_query::handle_event;
+
+#[tokio::main]
+async fn main() -> Result<(), sqs::Error> {
+    lambda::init_tracing();
+
+    let document_store = DDBDocumentStore::create(None).await;
+    let index_loader = LambdaIndexLoader::create().await;
+    let schema_loader = SchemaProvider::lambda().await;
+    let job_store = DDBJobStore::create(None).await;
+    let writer_client = LambdaIndexWriterClient::create(None).await;
+
+    run(service_fn(|event| {
+        handle_event(
+            &document_store,
+            &index_loader,
+            &schema_loader,
+            &job_store,
+            &writer_client,
+            event,
+        )
+    }))
+    .await
+}
@@ -0,0 +1,139 @@
+//! The catalog, a built-in index with an entry for every other index, so that operators can find
+//! indexes with the query API rather than listing them all, e.g. those with a field named `email`:
+//!
+//! ```json
+//! { "query": { "term": { "field": "fields", "value": "email" } } }
+//! ```
+//!
+//! Entries hold the index's id, tenant, prefix, `owner` and `tags` settings, fields and document
+//! count, as of the catalog worker's last rebuild. The catalog is search only, so matches are
+//! returned from its stored fields rather than the document store, and it isn't tenant scoped:
+//! only requests without a tenant can query it.
+
+use tracing::{info, warn};
+
+use crate::index::{IndexExt, IndexLoader, IndexWriterExt};
+use crate::schema::{parse_index_config, IndexConfig, SchemaLoader};
+use crate::search_doc::SearchDoc;
+use crate::service::ServiceError;
+use crate::{json, tenant};
+
+/// Id of the catalog index, reserved so that no index can be created with it.
+pub const CATALOG_INDEX_ID: &str = "_catalog";
+
+/// The built-in config of the catalog index.
+pub(crate) fn config() -> IndexConfig {
+    let definition = json::json!({
+        "fields": [
+            { "name": "index_id", "kind": "text", "flags": ["STRING", "STORED"] },
+            { "name": "tenant", "kind": "text", "flags": ["STRING", "STORED"] },
+            { "name": "prefix", "kind": "text", "flags": ["STRING", "STORED"] },
+            { "name": "owner", "kind": "text", "flags": ["STRING", "STORED"] },
+            { "name": "tags", "kind": "text", "flags": ["STRING", "STORED"] },
+            { "name": "fields", "kind": "text", "flags": ["STRING", "STORED"] },
+            { "name": "num_docs", "kind": "u64", "flags": ["INDEXED", "STORED", "FAST"] },
+            { "name": "last_commit", "kind": "date", "flags": ["INDEXED", "STORED", "FAST"] }
+        ],
+        "settings": {
+            "search_only": true
+        }
+    });
+
+    parse_index_config(CATALOG_INDEX_ID, definition).expect("catalog config should be valid")
+}
+
+/// The catalog entry of `index_id`, or None when it has no schema to open it with.
+pub fn entry(
+    index_loader: &dyn IndexLoader,
+    schema_loader: &dyn SchemaLoader,
+    index_id: &str,
+) -> Result<Option<json::Value>, ServiceError> {
+    let Some(prefix) = schema_loader.index_prefix(index_id) else {
+        return Ok(None);
+    };
+    let settings = schema_loader.load_settings(index_id)?;
+    let index = index_loader.load_index(index_id, None)?;
+
+    // The index's own schema, which for dynamic indexes has the fields derived since.
+    let fields: Vec<String> = index
+        .schema()
+        .fields()
+        .map(|(_, entry)| entry.name())
+        .filter(|name| !name.starts_with("__"))
+        .map(String::from)
+        .collect();
+    let num_docs = index
+        .reader()
+        .map_err(ServiceError::internal_error)?
+        .searcher()
+        .num_docs();
+
+    let mut entry = json::json!({
+        "__id": index_id,
+        "index_id": index_id,
+        "prefix": tenant::unscope(&prefix),
+        "tags": settings.tags,
+        "fields": fields,
+        "num_docs": num_docs,
+    });
+    if let Some(tenant_id) = tenant::tenant_of(index_id) {
+        entry["tenant"] = tenant_id.into();
+    }
+    if let Some(owner) = settings.owner {
+        entry["owner"] = owner.into();
+    }
+    if let Some(meta) = index.last_commit() {
+        entry["last_commit"] = meta.committed_at.into();
+    }
+
+    Ok(Some(entry))
+}
+
+/// Rebuilds the catalog from every index into a staging index, then swaps it in, so queries see
+/// either the old catalog or the new one. Returns the number of entries. The caller holds the
+/// catalog's lease.
+pub fn rebuild(
+    index_loader: &dyn IndexLoader,
+    schema_loader: &dyn SchemaLoader,
+) -> Result<usize, ServiceError> {
+    let schema = schema_loader.load_schema(CATALOG_INDEX_ID)?;
+    let settings = schema_loader.load_settings(CATALOG_INDEX_ID)?;
+
+    let staging = index_loader.create_staging_index(CATALOG_INDEX_ID, schema.clone())?;
+    let mut writer = staging.default_writer(&settings.merge_policy);
+    let mut entries = 0;
+
+    for index_id in index_loader.list_indexes()? {
+        if index_id == CATALOG_INDEX_ID {
+            continue;
+        }
+        // One index that can't be opened shouldn't keep every other out of the catalog.
+        let entry = match entry(index_loader, schema_loader, &index_id) {
+            Ok(Some(entry)) => entry,
+            Ok(None) => continue,
+            Err(err) => {
+                warn!(message = "catalog_entry_failed", index = index_id, error = %err);
+                continue;
+            }
+        };
+
+        let doc = SearchDoc::from_json_with_settings(&schema, entry, &settings)
+            .map_err(ServiceError::internal_error)?;
+        writer
+            .add_document(doc.document_with_settings(&schema, &settings))
+            .map_err(ServiceError::internal_error)?;
+        entries += 1;
+    }
+
+    writer
+        .commit_with_meta()
+        .map_err(ServiceError::internal_error)?;
+    writer
+        .wait_merging_threads()
+        .map_err(ServiceError::internal_error)?;
+
+    index_loader.swap_staging_index(CATALOG_INDEX_ID)?;
+    info!(message = "catalog_rebuilt", entries);
+
+    Ok(entries)
+}
@@ -0,0 +1,169 @@
+//! Checks that the segments of an index are in a format this build of tantivy reads.
+//!
+//! tantivy appends a footer recording its version to every file it writes, and reads the format
+//! it writes along with some older ones. Segments in an older format are rewritten in the current
+//! one whenever they're merged, which the merge worker does for every index with outdated
+//! segments, so that an upgrade which drops the older format doesn't strand the index.
+
+use std::io;
+use std::path::Path;
+
+use serde::Deserialize;
+use tantivy::directory::FileSlice;
+use tantivy::{Directory, SegmentComponent, SegmentId, SegmentMeta};
+use tantivy_common::HasLen;
+use tracing::{error, warn};
+
+use crate::json;
+use crate::service::ServiceError;
+
+/// Oldest segment format the linked tantivy reads.
+const MIN_FORMAT_VERSION: u32 = 4;
+
+/// The `(u32, u32)` of footer length and magic number ending each file.
+const FOOTER_TRAILER_LEN: usize = 8;
+
+#[derive(Deserialize)]
+struct FooterVersion {
+    index_format_version: u32,
+}
+
+#[derive(Deserialize)]
+struct Footer {
+    version: FooterVersion,
+
+    /// CRC32 of the file's contents before the footer.
+    crc: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SegmentFormat {
+    pub segment_id: SegmentId,
+    pub format_version: u32,
+}
+
+/// The segment format the linked tantivy writes.
+pub fn format_version() -> u32 {
+    json::to_value(tantivy::version())
+        .ok()
+        .and_then(|version| version["index_format_version"].as_u64())
+        .expect("tantivy version should have a format version") as u32
+}
+
+fn read_footer(file: FileSlice) -> io::Result<Footer> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+
+    if file.len() < FOOTER_TRAILER_LEN {
+        return Err(invalid("file is smaller than its footer"));
+    }
+    let trailer = file.slice_from_end(FOOTER_TRAILER_LEN).read_bytes()?;
+    let footer_len = u32::from_le_bytes(trailer.as_slice()[..4].try_into().unwrap()) as usize;
+
+    let end = file.len() - FOOTER_TRAILER_LEN;
+    if footer_len > end {
+        return Err(invalid("footer is longer than the file"));
+    }
+    let footer = file.slice(end - footer_len..end).read_bytes()?;
+
+    json::from_slice(footer.as_slice()).map_err(|err| invalid(&err.to_string()))
+}
+
+/// The checksum tantivy recorded in the footer of `file`, read from a directory beneath the
+/// index's own.
+pub(crate) fn file_checksum(file: FileSlice) -> io::Result<u32> {
+    Ok(read_footer(file)?.crc)
+}
+
+/// The format of each of `segments`, read from the footer of its term dictionary. `directory` is
+/// the one beneath the index's own, whose reads keep the footer.
+pub fn segment_formats(
+    directory: &dyn Directory,
+    segments: &[SegmentMeta],
+) -> Result<Vec<SegmentFormat>, ServiceError> {
+    segments
+        .iter()
+        .map(|segment| {
+            let path = segment.relative_path(SegmentComponent::Terms);
+            let file = directory
+                .open_read(Path::new(&path))
+                .map_err(ServiceError::internal_error)?;
+            let footer = read_footer(file).map_err(ServiceError::internal_error)?;
+
+            Ok(SegmentFormat {
+                segment_id: segment.id(),
+                format_version: footer.version.index_format_version,
+            })
+        })
+        .collect()
+}
+
+/// Fails with a 503 naming the formats when any segment of `index_id` is in a format that can't be
+/// read, rather than the index failing on its first search. Segments that are readable but
+/// outdated are logged, to be rewritten by the merge worker.
+pub fn check_compatibility(index_id: &str, formats: &[SegmentFormat]) -> Result<(), ServiceError> {
+    let current = format_version();
+
+    let mut unreadable = formats
+        .iter()
+        .map(|format| format.format_version)
+        .filter(|version| *version < MIN_FORMAT_VERSION || *version > current)
+        .collect::<Vec<_>>();
+    unreadable.sort_unstable();
+    unreadable.dedup();
+
+    if !unreadable.is_empty() {
+        error!(
+            message = "index_incompatible",
+            index = index_id,
+            formats = ?unreadable,
+            supported = ?(MIN_FORMAT_VERSION..=current)
+        );
+        return Err(ServiceError::unavailable(&format!(
+            "Index [{}] has segments in format {:?}, which this release can't read (it reads \
+             formats {} to {}). Deploy a release that reads them and let the merge worker rewrite \
+             the index before upgrading.",
+            index_id, unreadable, MIN_FORMAT_VERSION, current
+        )));
+    }
+
+    let outdated = formats
+        .iter()
+        .filter(|format| format.format_version < current)
+        .count();
+    if outdated > 0 {
+        warn!(
+            message = "index_format_outdated",
+            index = index_id,
+            segments = outdated,
+            current
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::IndexLoader;
+    use crate::test_utils::*;
+
+    #[tokio::test]
+    async fn segment_formats_are_read_and_checked() {
+        let ctx = setup()
+            .with_documents("test", vec![json!({ "title": "hello" })])
+            .await;
+
+        let formats = ctx.index_loader().segment_formats("test").unwrap();
+        assert_eq!(1, formats.len());
+        assert_eq!(format_version(), formats[0].format_version);
+        check_compatibility("test", &formats).unwrap();
+
+        let future = SegmentFormat {
+            segment_id: SegmentId::generate_random(),
+            format_version: format_version() + 1,
+        };
+        let err = check_compatibility("test", &[formats[0].clone(), future]).unwrap_err();
+        assert_eq!(503, err.status());
+    }
+}
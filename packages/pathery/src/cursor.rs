@@ -0,0 +1,127 @@
+//! Opaque tokens for paging through query results.
+//!
+//! A cursor is the position of a page in the matches of one query, signed so that clients can't
+//! forge positions or reuse a cursor with a different query. Clients only pass cursors back, so
+//! the format can change without breaking them.
+
+use ring::{digest, hmac};
+use serde::{Deserialize, Serialize};
+
+use crate::service::ServiceError;
+use crate::{json, util};
+
+/// Deepest match a cursor can page to, bounding the matches ranked for a page.
+pub const MAX_CURSOR_OFFSET: usize = 10_000;
+
+const ENCODING: base64::Config = base64::URL_SAFE_NO_PAD;
+
+/// Key signing and verifying cursors. Every service handing out cursors for the same indexes
+/// needs the same key.
+#[derive(Clone)]
+pub struct CursorKey(hmac::Key);
+
+impl CursorKey {
+    pub fn new(secret: &[u8]) -> Self {
+        CursorKey(hmac::Key::new(hmac::HMAC_SHA256, secret))
+    }
+
+    /// The key in the secret `CURSOR_SIGNING_KEY_SECRET_ARN` names, read once as the function
+    /// starts so that the key itself isn't in the function's configuration.
+    pub async fn from_env() -> Self {
+        let secret_arn = util::require_env("CURSOR_SIGNING_KEY_SECRET_ARN");
+        let sdk_config = aws_config::load_from_env().await;
+
+        let secret = aws_sdk_secretsmanager::Client::new(&sdk_config)
+            .get_secret_value()
+            .secret_id(secret_arn)
+            .send()
+            .await
+            .expect("cursor signing key should be readable");
+
+        CursorKey::new(
+            secret
+                .secret_string()
+                .expect("cursor signing key should be a string")
+                .as_bytes(),
+        )
+    }
+
+    /// A random key, for cursors only this process needs to read back.
+    pub fn generate() -> Self {
+        CursorKey::new(&rand::random::<[u8; 32]>())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct Cursor {
+    /// Number of matches before the page.
+    #[serde(rename = "o")]
+    pub offset: usize,
+
+    /// Fingerprint of the query the cursor pages through.
+    #[serde(rename = "q")]
+    pub query: String,
+}
+
+impl Cursor {
+    pub fn encode(&self, key: &CursorKey) -> String {
+        let payload = base64::encode_config(
+            json::to_vec(self).expect("cursor should serialize"),
+            ENCODING,
+        );
+        let tag = hmac::sign(&key.0, payload.as_bytes());
+
+        format!("{}.{}", payload, base64::encode_config(tag, ENCODING))
+    }
+
+    /// The cursor `token` encodes, if it was signed with `key`.
+    pub fn decode(token: &str, key: &CursorKey) -> Result<Cursor, ServiceError> {
+        let invalid = || ServiceError::invalid_request(&format!("Invalid cursor [{}]", token));
+
+        let (payload, tag) = token.split_once('.').ok_or_else(invalid)?;
+        let tag = base64::decode_config(tag, ENCODING).map_err(|_| invalid())?;
+        hmac::verify(&key.0, payload.as_bytes(), &tag).map_err(|_| invalid())?;
+
+        let payload = base64::decode_config(payload, ENCODING).map_err(|_| invalid())?;
+        json::from_slice(&payload).map_err(|_| invalid())
+    }
+}
+
+/// A short digest of `value`, identifying a query by the parts of it that decide which matches
+/// it has and their order.
+pub fn fingerprint(value: &impl Serialize) -> String {
+    let bytes = json::to_vec(value).expect("fingerprinted value should serialize");
+    let digest = digest::digest(&digest::SHA256, &bytes);
+
+    base64::encode_config(&digest.as_ref()[..12], ENCODING)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursor_round_trips_only_with_its_key() {
+        let key = CursorKey::new(b"secret");
+        let cursor = Cursor {
+            offset: 20,
+            query: fingerprint(&"title:hello"),
+        };
+
+        let token = cursor.encode(&key);
+        assert_eq!(cursor, Cursor::decode(&token, &key).unwrap());
+
+        assert!(Cursor::decode(&token, &CursorKey::new(b"other")).is_err());
+
+        // A forged position fails to verify.
+        let forged = Cursor {
+            offset: 5000,
+            query: cursor.query.clone(),
+        }
+        .encode(&CursorKey::new(b"other"));
+        let (forged_payload, _) = forged.split_once('.').unwrap();
+        let (_, tag) = token.split_once('.').unwrap();
+        assert!(Cursor::decode(&format!("{}.{}", forged_payload, tag), &key).is_err());
+        assert!(Cursor::decode("garbage", &key).is_err());
+    }
+}
@@ -1,12 +1,26 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::future::Future;
+use std::io::{self, BufWriter, Write};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Instant;
 
-use tantivy::directory::error::OpenDirectoryError;
-use tantivy::directory::{DirectoryLock, MmapDirectory};
-use tantivy::Directory;
+use tantivy::directory::error::{
+    DeleteError, LockError, OpenDirectoryError, OpenReadError, OpenWriteError,
+};
+use tantivy::directory::{
+    AntiCallToken, DirectoryLock, FileHandle, Lock, MmapDirectory, OwnedBytes, TerminatingWrite,
+    WatchCallback, WatchHandle, WritePtr,
+};
+use tantivy::{Directory, Index};
 use tokio::runtime::Handle;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
 
+use crate::metrics::MetricSet;
+use crate::service::ServiceError;
+use crate::store::file::FileStore;
 use crate::worker::async_delete::client::AsyncDeleteClient;
 use crate::worker::async_delete::job::AsyncDeleteJob;
 
@@ -60,7 +74,7 @@ impl Directory for PatheryDirectory {
     }
 
     fn delete(&self, path: &std::path::Path) -> Result<(), tantivy::directory::error::DeleteError> {
-        let path = self.directory_path.join(path.to_path_buf());
+        let path = self.directory_path.join(path);
         let job = AsyncDeleteJob::fs_delete(path);
         self.handle
             .block_on(self.async_delete_client.submit_job(job))
@@ -101,7 +115,7 @@ impl Directory for PatheryDirectory {
             let filtered_segments: Vec<_> = segments
                 .iter()
                 .enumerate()
-                .filter(|(idx, _)| (idx + self.partition_n) % self.total_partitions == 0)
+                .filter(|(idx, _)| (idx + self.partition_n).is_multiple_of(self.total_partitions))
                 .map(|(_, v)| v.to_owned())
                 .collect();
 
@@ -138,3 +152,478 @@ impl Directory for PatheryDirectory {
         Ok(DirectoryLock::from(Box::new(NoopLockGuard)))
     }
 }
+
+/// Directory that keeps an index's files in a [`FileStore`], such as S3 for indexes too large to
+/// keep on EFS. Files are read through a local cache, which is safe because tantivy never changes
+/// a file once written. The exceptions are `meta.json` and `.managed.json`, which tantivy writes
+/// atomically and which are always read from the store.
+///
+/// Like [`PatheryDirectory`] there's no lockfile, and changes to `meta.json` aren't watched, so
+/// readers only see new commits when reopened.
+#[derive(Clone, Debug)]
+pub struct FileStoreDirectory {
+    store: Arc<dyn FileStore>,
+
+    cache_path: PathBuf,
+
+    cache: MmapDirectory,
+
+    handle: Handle,
+}
+
+impl FileStoreDirectory {
+    pub fn open<P>(
+        store: Arc<dyn FileStore>,
+        cache_path: P,
+    ) -> Result<FileStoreDirectory, OpenDirectoryError>
+    where
+        P: AsRef<Path>,
+    {
+        let cache_path = cache_path.as_ref().to_owned();
+        fs::create_dir_all(&cache_path)
+            .map_err(|err| OpenDirectoryError::wrap_io_error(err, cache_path.clone()))?;
+
+        Ok(FileStoreDirectory {
+            store,
+            cache: MmapDirectory::open(&cache_path)?,
+            cache_path,
+            handle: Handle::try_current().unwrap(),
+        })
+    }
+
+    /// Waits on a call to the store. Tantivy calls the directory from its own threads as well as
+    /// from tasks on the runtime.
+    fn block_on<F: Future>(&self, future: F) -> F::Output {
+        tokio::task::block_in_place(|| self.handle.block_on(future))
+    }
+
+    /// Downloads `path` into the cache unless it's already there.
+    fn fetch(&self, path: &Path) -> Result<(), OpenReadError> {
+        if self.cache_path.join(path).exists() {
+            return Ok(());
+        }
+
+        self.block_on(self.download(path)).map(|_| ())
+    }
+
+    /// Downloads `path` into the cache, returning its size. Downloads land in a temporary file
+    /// first, so that a failed download never leaves a partial file in the cache.
+    async fn download(&self, path: &Path) -> Result<u64, OpenReadError> {
+        let wrap = |err| OpenReadError::wrap_io_error(err, path.to_owned());
+        let download = self
+            .cache_path
+            .join(format!(".{}.download", path.to_string_lossy()));
+        let found = self
+            .store
+            .download_file(&path.to_string_lossy(), &download)
+            .await
+            .map_err(|err| wrap(io::Error::other(err)))?;
+        if !found {
+            return Err(OpenReadError::FileDoesNotExist(path.to_owned()));
+        }
+
+        let size = tokio::fs::metadata(&download).await.map_err(wrap)?.len();
+        tokio::fs::rename(&download, self.cache_path.join(path))
+            .await
+            .map_err(wrap)?;
+
+        Ok(size)
+    }
+
+    /// Opens the index, first downloading the files of its searchable segments that aren't
+    /// cached yet, up to `concurrency` at a time, rather than one at a time as its first query
+    /// reads them. Stores are read through a [`CompressedDirectory`], compressing new ones when
+    /// `compress` is set. How long opening took and what was downloaded are emitted as metrics of
+    /// `index_id`.
+    pub fn open_index(
+        &self,
+        index_id: &str,
+        concurrency: usize,
+        compress: bool,
+    ) -> Result<Index, ServiceError> {
+        let started = Instant::now();
+        let index = Index::open(CompressedDirectory::new(self.clone(), compress))
+            .map_err(ServiceError::internal_error)?;
+        let paths: HashSet<PathBuf> = index
+            .searchable_segment_metas()
+            .map_err(ServiceError::internal_error)?
+            .iter()
+            .flat_map(|segment| segment.list_files())
+            .filter(|path| !self.cache_path.join(path).exists())
+            .collect();
+
+        let permits = Arc::new(Semaphore::new(concurrency.max(1)));
+        let mut downloads = JoinSet::new();
+        for path in paths {
+            let directory = self.clone();
+            let permits = Arc::clone(&permits);
+            downloads.spawn_on(
+                async move {
+                    let _permit = permits.acquire_owned().await;
+                    directory.download(&path).await
+                },
+                &self.handle,
+            );
+        }
+
+        let sizes = self.block_on(async {
+            let mut sizes = vec![];
+            while let Some(downloaded) = downloads.join_next().await {
+                match downloaded.map_err(ServiceError::internal_error)? {
+                    Ok(size) => sizes.push(size),
+                    // Not every segment component is written, e.g. deletes.
+                    Err(OpenReadError::FileDoesNotExist(_)) => {}
+                    Err(err) => return Err(ServiceError::internal_error(err)),
+                }
+            }
+            Ok(sizes)
+        })?;
+
+        MetricSet::index(index_id)
+            .duration("IndexOpenDuration", started.elapsed())
+            .count("PrefetchedFiles", sizes.len())
+            .count("PrefetchedBytes", sizes.iter().sum::<u64>() as usize)
+            .emit();
+
+        Ok(index)
+    }
+
+    fn upload(&self, path: &Path) -> io::Result<()> {
+        self.block_on(
+            self.store
+                .upload_file(&path.to_string_lossy(), &self.cache_path.join(path)),
+        )
+        .map_err(io::Error::other)
+    }
+}
+
+/// Writes a new file to the cache, uploading it to the store once tantivy is done with it.
+struct UploadOnTerminate {
+    inner: WritePtr,
+
+    path: PathBuf,
+
+    directory: FileStoreDirectory,
+}
+
+impl Write for UploadOnTerminate {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl TerminatingWrite for UploadOnTerminate {
+    fn terminate_ref(&mut self, token: AntiCallToken) -> io::Result<()> {
+        self.inner.terminate_ref(token)?;
+        self.directory.upload(&self.path)
+    }
+}
+
+impl Directory for FileStoreDirectory {
+    fn get_file_handle(&self, path: &Path) -> Result<Box<dyn FileHandle>, OpenReadError> {
+        self.fetch(path)?;
+        self.cache.get_file_handle(path)
+    }
+
+    fn delete(&self, path: &Path) -> Result<(), DeleteError> {
+        let wrap = |io_error| DeleteError::IoError {
+            io_error,
+            filepath: path.to_owned(),
+        };
+
+        self.block_on(self.store.delete_file(&path.to_string_lossy()))
+            .map_err(|err| wrap(io::Error::other(err)))?;
+
+        match fs::remove_file(self.cache_path.join(path)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(wrap(err)),
+            _ => Ok(()),
+        }
+    }
+
+    fn exists(&self, path: &Path) -> Result<bool, OpenReadError> {
+        if self.cache_path.join(path).exists() {
+            return Ok(true);
+        }
+
+        self.block_on(self.store.file_exists(&path.to_string_lossy()))
+            .map_err(|err| OpenReadError::wrap_io_error(io::Error::other(err), path.to_owned()))
+    }
+
+    fn open_write(&self, path: &Path) -> Result<WritePtr, OpenWriteError> {
+        let inner = self.cache.open_write(path)?;
+
+        Ok(BufWriter::new(Box::new(UploadOnTerminate {
+            inner,
+            path: path.to_owned(),
+            directory: self.clone(),
+        })))
+    }
+
+    fn atomic_read(&self, path: &Path) -> Result<Vec<u8>, OpenReadError> {
+        self.block_on(self.store.read_file(&path.to_string_lossy()))
+            .map_err(|err| OpenReadError::wrap_io_error(io::Error::other(err), path.to_owned()))?
+            .ok_or_else(|| OpenReadError::FileDoesNotExist(path.to_owned()))
+    }
+
+    fn atomic_write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        self.block_on(
+            self.store
+                .write_file(&path.to_string_lossy(), data.to_vec()),
+        )
+        .map_err(io::Error::other)
+    }
+
+    fn sync_directory(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn watch(&self, _watch_callback: WatchCallback) -> tantivy::Result<WatchHandle> {
+        Ok(WatchHandle::empty())
+    }
+
+    fn acquire_lock(&self, _lock: &Lock) -> Result<DirectoryLock, LockError> {
+        Ok(DirectoryLock::from(Box::new(NoopLockGuard)))
+    }
+}
+
+/// Header of each file [`CompressedDirectory`] compressed, followed by a zstd frame of its
+/// contents.
+const COMPRESSED_HEADER: &[u8; 4] = b"PZS1";
+
+/// zstd level stores are compressed at, favoring write speed since every commit writes stores.
+const COMPRESSION_LEVEL: i32 = 3;
+
+/// Directory that zstd-compresses segment stores as they're written, and decompresses them when
+/// opened. Compressed files start with a header, so stores written without compression, before
+/// it was turned on or after it was turned off, are read as they are.
+///
+/// Only stores, the `.store` file of each segment holding its stored documents, are compressed.
+/// Other segment files are read at random through mmap, and would have to be held decompressed in
+/// memory instead.
+#[derive(Clone, Debug)]
+pub struct CompressedDirectory {
+    inner: Box<dyn Directory>,
+
+    compress: bool,
+}
+
+impl CompressedDirectory {
+    /// Wraps `inner`, compressing newly written stores when `compress` is set.
+    pub fn new<D: Into<Box<dyn Directory>>>(inner: D, compress: bool) -> CompressedDirectory {
+        CompressedDirectory {
+            inner: inner.into(),
+            compress,
+        }
+    }
+}
+
+fn is_store(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "store")
+}
+
+/// Compresses a file into the directory's writer, finishing the frame once tantivy is done.
+struct CompressOnWrite {
+    encoder: Option<zstd::Encoder<'static, WritePtr>>,
+}
+
+impl Write for CompressOnWrite {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.encoder
+            .as_mut()
+            .expect("file should not be written after it's terminated")
+            .write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.encoder {
+            Some(encoder) => encoder.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl TerminatingWrite for CompressOnWrite {
+    fn terminate_ref(&mut self, token: AntiCallToken) -> io::Result<()> {
+        match self.encoder.take() {
+            Some(encoder) => encoder.finish()?.terminate_ref(token),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Directory for CompressedDirectory {
+    fn get_file_handle(&self, path: &Path) -> Result<Box<dyn FileHandle>, OpenReadError> {
+        let handle = self.inner.get_file_handle(path)?;
+        if !is_store(path) || handle.len() < COMPRESSED_HEADER.len() {
+            return Ok(handle);
+        }
+
+        let wrap = |err| OpenReadError::wrap_io_error(err, path.to_owned());
+        let header = handle
+            .read_bytes(0..COMPRESSED_HEADER.len())
+            .map_err(wrap)?;
+        if header.as_slice() != COMPRESSED_HEADER {
+            return Ok(handle);
+        }
+
+        let compressed = handle
+            .read_bytes(COMPRESSED_HEADER.len()..handle.len())
+            .map_err(wrap)?;
+        let contents = zstd::decode_all(compressed.as_slice()).map_err(wrap)?;
+
+        Ok(Box::new(OwnedBytes::new(contents)))
+    }
+
+    fn delete(&self, path: &Path) -> Result<(), DeleteError> {
+        self.inner.delete(path)
+    }
+
+    fn exists(&self, path: &Path) -> Result<bool, OpenReadError> {
+        self.inner.exists(path)
+    }
+
+    fn open_write(&self, path: &Path) -> Result<WritePtr, OpenWriteError> {
+        let mut writer = self.inner.open_write(path)?;
+        if !self.compress || !is_store(path) {
+            return Ok(writer);
+        }
+
+        let wrap = |io_error| OpenWriteError::IoError {
+            io_error,
+            filepath: path.to_owned(),
+        };
+        writer.write_all(COMPRESSED_HEADER).map_err(wrap)?;
+        let encoder = zstd::Encoder::new(writer, COMPRESSION_LEVEL).map_err(wrap)?;
+
+        Ok(BufWriter::new(Box::new(CompressOnWrite {
+            encoder: Some(encoder),
+        })))
+    }
+
+    fn atomic_read(&self, path: &Path) -> Result<Vec<u8>, OpenReadError> {
+        self.inner.atomic_read(path)
+    }
+
+    fn atomic_write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        self.inner.atomic_write(path, data)
+    }
+
+    fn sync_directory(&self) -> io::Result<()> {
+        self.inner.sync_directory()
+    }
+
+    fn watch(&self, watch_callback: WatchCallback) -> tantivy::Result<WatchHandle> {
+        self.inner.watch(watch_callback)
+    }
+
+    fn acquire_lock(&self, lock: &Lock) -> Result<DirectoryLock, LockError> {
+        self.inner.acquire_lock(lock)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use tantivy::collector::Count;
+    use tantivy::directory::RamDirectory;
+    use tantivy::query::AllQuery;
+    use tantivy::schema::{Schema, STORED, TEXT};
+    use tantivy::{doc, Index, IndexSettings};
+    use tantivy_common::HasLen;
+
+    use super::*;
+    use crate::store::file::test_util::TestFileStore;
+    use crate::util;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn file_store_directory_round_trips_an_index() {
+        let store = TestFileStore::create();
+        let open = || {
+            let cache_path = env::temp_dir().join(format!("pathery-{}", util::generate_id()));
+            FileStoreDirectory::open(Arc::new(store.clone()), cache_path).unwrap()
+        };
+
+        let mut schema = Schema::builder();
+        let title = schema.add_text_field("title", TEXT | STORED);
+        let index = Index::create(open(), schema.build(), IndexSettings::default()).unwrap();
+        let mut writer = index.writer(15_000_000).unwrap();
+        writer.add_document(doc!(title => "hello")).unwrap();
+        writer.commit().unwrap();
+        writer.wait_merging_threads().unwrap();
+
+        assert!(store.file_exists("meta.json").await.unwrap());
+
+        // A fresh cache downloads the committed segments from the store.
+        let index = Index::open(open()).unwrap();
+        let searcher = index.reader().unwrap().searcher();
+        assert_eq!(1, searcher.search(&AllQuery, &Count).unwrap());
+
+        let missing = open().atomic_read(Path::new("missing.json")).unwrap_err();
+        assert!(matches!(missing, OpenReadError::FileDoesNotExist(_)));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn open_index_prefetches_segment_files() {
+        let store = TestFileStore::create();
+        let cache_path = || env::temp_dir().join(format!("pathery-{}", util::generate_id()));
+
+        let mut schema = Schema::builder();
+        let title = schema.add_text_field("title", TEXT | STORED);
+        let directory = FileStoreDirectory::open(Arc::new(store.clone()), cache_path()).unwrap();
+        let index = Index::create(directory, schema.build(), IndexSettings::default()).unwrap();
+        let mut writer = index.writer(15_000_000).unwrap();
+        for _ in 0..3 {
+            writer.add_document(doc!(title => "hello")).unwrap();
+            writer.commit().unwrap();
+        }
+        writer.wait_merging_threads().unwrap();
+
+        let cache_path = cache_path();
+        let directory = FileStoreDirectory::open(Arc::new(store.clone()), &cache_path).unwrap();
+        let index = directory.open_index("test", 2, false).unwrap();
+
+        let segments = index.searchable_segment_metas().unwrap();
+        assert!(!segments.is_empty());
+        for path in segments.iter().flat_map(|segment| segment.list_files()) {
+            let stored = store.file_exists(&path.to_string_lossy()).await.unwrap();
+            assert_eq!(stored, cache_path.join(&path).exists(), "{:?}", path);
+        }
+    }
+
+    #[test]
+    fn compressed_directory_compresses_stores() {
+        let contents = "stored document ".repeat(1000);
+        let ram = RamDirectory::create();
+        let write = |directory: &CompressedDirectory, path: &str| {
+            let mut writer = directory.open_write(Path::new(path)).unwrap();
+            writer.write_all(contents.as_bytes()).unwrap();
+            writer.terminate().unwrap();
+        };
+
+        let directory = CompressedDirectory::new(ram.clone(), true);
+        write(&directory, "a.store");
+        write(&directory, "a.idx");
+
+        // Stores are compressed at rest, other files aren't.
+        let stored = ram.open_read(Path::new("a.store")).unwrap();
+        assert!(stored.len() < contents.len() / 10);
+        assert_eq!(
+            contents.len(),
+            ram.open_read(Path::new("a.idx")).unwrap().len()
+        );
+
+        // Compressed and uncompressed stores both read back, whether or not writes are compressed.
+        let directory = CompressedDirectory::new(ram, false);
+        write(&directory, "b.store");
+        for path in ["a.store", "b.store"] {
+            let read = directory.open_read(Path::new(path)).unwrap();
+            assert_eq!(contents.as_bytes(), read.read_bytes().unwrap().as_slice());
+        }
+    }
+}
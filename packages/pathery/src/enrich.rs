@@ -0,0 +1,189 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::json;
+use crate::service::ServiceError;
+use crate::store::lookup::{LookupItem, LookupTable};
+
+/// How long a looked up item is reused before it's read again, so that changes to a lookup table
+/// reach newly indexed documents within this long.
+const CACHE_TTL: Duration = Duration::from_secs(300);
+
+/// Most looked up items kept at once. The cache is cleared when it fills up.
+const MAX_CACHED_ITEMS: usize = 10_000;
+
+/// Joins documents against a DynamoDB table at index time, copying attributes of the item whose
+/// key matches a field of the document into other fields. Only the index sees the enriched
+/// fields; the stored documents queries return are left as they were written.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct EnrichConfig {
+    /// The document field holding the key to look up, e.g. `category_id`. Documents without it
+    /// aren't enriched. Arrays of keys look up each one.
+    pub field: String,
+
+    /// Name of the DynamoDB table to look the key up in.
+    pub table: String,
+
+    /// The table's partition key attribute.
+    #[serde(default = "default_key")]
+    pub key: String,
+
+    /// Attributes of the matching item to copy, to the document field each is mapped to, e.g.
+    /// `{ "name": "category_name" }`.
+    pub attributes: BTreeMap<String, String>,
+}
+
+fn default_key() -> String {
+    String::from("id")
+}
+
+type CacheKey = (String, String, String);
+
+struct CachedItem {
+    item: Option<LookupItem>,
+    read_at: Instant,
+}
+
+/// Applies an index's [`EnrichConfig`]s to documents, caching looked up items so that documents
+/// sharing a key cost one read.
+pub struct Enricher {
+    lookup_table: Box<dyn LookupTable>,
+
+    cache: Mutex<HashMap<CacheKey, CachedItem>>,
+}
+
+impl Enricher {
+    pub fn new(lookup_table: impl LookupTable + 'static) -> Enricher {
+        Enricher {
+            lookup_table: Box::new(lookup_table),
+            cache: Default::default(),
+        }
+    }
+
+    /// Adds the attributes `enrichments` copy to `content`, replacing any values already there.
+    pub async fn enrich(
+        &self,
+        enrichments: &[EnrichConfig],
+        content: &mut json::Map<String, json::Value>,
+    ) -> Result<(), ServiceError> {
+        for enrichment in enrichments {
+            let keys = match content.get(&enrichment.field) {
+                Some(json::Value::Array(keys)) => keys.clone(),
+                Some(json::Value::Null) | None => continue,
+                Some(key) => vec![key.clone()],
+            };
+
+            let mut values: BTreeMap<&str, Vec<json::Value>> = BTreeMap::new();
+            for key in &keys {
+                let item = match self.get_item(enrichment, key).await? {
+                    Some(item) => item,
+                    None => continue,
+                };
+                for (attribute, target) in &enrichment.attributes {
+                    if let Some(value) = item.get(attribute) {
+                        values.entry(target).or_default().push(value.clone());
+                    }
+                }
+            }
+
+            for (target, mut found) in values {
+                let value = if found.len() == 1 {
+                    found.remove(0)
+                } else {
+                    json::Value::Array(found)
+                };
+                content.insert(target.to_string(), value);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn get_item(
+        &self,
+        enrichment: &EnrichConfig,
+        key: &json::Value,
+    ) -> Result<Option<LookupItem>, ServiceError> {
+        let cache_key = (
+            enrichment.table.clone(),
+            enrichment.key.clone(),
+            key.to_string(),
+        );
+
+        if let Some(cached) = self.cache.lock().unwrap().get(&cache_key) {
+            if cached.read_at.elapsed() < CACHE_TTL {
+                return Ok(cached.item.clone());
+            }
+        }
+
+        let item = self
+            .lookup_table
+            .get_item(&enrichment.table, &enrichment.key, key)
+            .await?;
+
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= MAX_CACHED_ITEMS {
+            cache.clear();
+        }
+        cache.insert(
+            cache_key,
+            CachedItem {
+                item: item.clone(),
+                read_at: Instant::now(),
+            },
+        );
+
+        Ok(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::lookup::test_util::TestLookupTable;
+    use crate::test_utils::*;
+
+    #[tokio::test]
+    async fn enrich_copies_attributes_of_the_matching_item() {
+        let lookup_table = TestLookupTable::create();
+        lookup_table.put_item("categories", "id", json!({ "id": "c1", "name": "Fiction" }));
+        lookup_table.put_item("categories", "id", json!({ "id": "c2", "name": "Poetry" }));
+        let enricher = Enricher::new(lookup_table.clone());
+
+        let enrichments: Vec<EnrichConfig> = json::from_value(json!([{
+            "field": "category_id",
+            "table": "categories",
+            "attributes": { "name": "category_name" }
+        }]))
+        .unwrap();
+
+        let enrich = |doc: json::Value| {
+            let enricher = &enricher;
+            let enrichments = &enrichments;
+            async move {
+                let mut content = doc.as_object().unwrap().clone();
+                enricher.enrich(enrichments, &mut content).await.unwrap();
+                json::Value::Object(content)
+            }
+        };
+
+        assert_eq!(
+            json!({ "category_id": "c1", "category_name": "Fiction" }),
+            enrich(json!({ "category_id": "c1" })).await
+        );
+        assert_eq!(
+            json!({ "category_id": ["c1", "c2", "c3"], "category_name": ["Fiction", "Poetry"] }),
+            enrich(json!({ "category_id": ["c1", "c2", "c3"] })).await
+        );
+        assert_eq!(
+            json!({ "title": "Untitled" }),
+            enrich(json!({ "title": "Untitled" })).await
+        );
+
+        // Each key was read once, including the one without an item.
+        assert_eq!(3, lookup_table.reads());
+    }
+}
@@ -1,15 +1,32 @@
-use std::fs;
-use std::path::Path;
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::{env, fs};
 
-use tantivy::merge_policy::DefaultMergePolicy;
-use tantivy::schema::Field;
-use tantivy::{Index, IndexWriter};
+use serde::{Deserialize, Serialize};
+use tantivy::directory::{Directory, MmapDirectory, RamDirectory};
+use tantivy::merge_policy::{DefaultMergePolicy, MergePolicy, NoMergePolicy};
+use tantivy::query::QueryParser;
+use tantivy::schema::{Field, FieldEntry, FieldType, Schema};
+use tantivy::{Index, IndexWriter, SegmentMeta};
+use tokio::runtime::Handle;
+use tracing::warn;
 
-use crate::directory::PatheryDirectory;
-use crate::schema::{SchemaLoader, SchemaProvider};
+use crate::compat::{self, SegmentFormat};
+use crate::directory::{CompressedDirectory, FileStoreDirectory, PatheryDirectory};
+use crate::manifest::{self, IndexManifest};
+use crate::schema::{
+    diff_schema, IndexSettings, MergePolicyConfig, SchemaChange, SchemaLoader, SchemaProvider,
+};
 use crate::service::ServiceError;
-use crate::worker::async_delete::client::{AsyncDeleteClient, LambdaAsyncDeleteClient};
+use crate::store::document::SearchDocRef;
+use crate::store::file::{FileStore, PrefixedFileStore, S3FileStore};
+use crate::worker::async_delete::client::{
+    AsyncDeleteClient, LambdaAsyncDeleteClient, LocalDeleteClient,
+};
+use crate::{json, tokenizer, util};
 
 pub trait IndexLoader: Send + Sync {
     fn load_index(
@@ -17,127 +34,1268 @@ pub trait IndexLoader: Send + Sync {
         index_id: &str,
         with_partition: Option<(usize, usize)>,
     ) -> Result<Index, ServiceError>;
+
+    /// Removes the index and all of its files. Deleting an index that does not exist is a no-op.
+    fn delete_index(&self, index_id: &str) -> Result<(), ServiceError>;
+
+    /// Ids of every index that has been created, sorted.
+    fn list_indexes(&self) -> Result<Vec<String>, ServiceError>;
+
+    /// Adds `fields` to the schema of an existing index and returns the reopened index. Existing
+    /// segments are untouched and simply have no values for the new fields.
+    fn extend_schema(&self, index_id: &str, fields: Vec<FieldEntry>)
+        -> Result<Index, ServiceError>;
+
+    /// Creates an empty index with `schema` alongside `index_id`, to rebuild the index into
+    /// without disturbing it. Replaces any staging index left by an earlier rebuild.
+    fn create_staging_index(&self, index_id: &str, schema: Schema) -> Result<Index, ServiceError>;
+
+    /// Replaces `index_id` with its staging index.
+    fn swap_staging_index(&self, index_id: &str) -> Result<(), ServiceError>;
+
+    /// The format each searchable segment of `index_id` was written in.
+    fn segment_formats(&self, index_id: &str) -> Result<Vec<SegmentFormat>, ServiceError>;
+
+    /// The files of the last commit of `index_id`, with their sizes and checksums.
+    fn manifest(&self, index_id: &str) -> Result<IndexManifest, ServiceError>;
+
+    /// Writes a file of pathery's own, such as [`SCHEMA_VERSION_FILE`], beside the files of
+    /// `index_id`, where commits won't garbage collect it. It can be read back through
+    /// `Index::directory`.
+    fn write_index_file(
+        &self,
+        index_id: &str,
+        name: &str,
+        bytes: &[u8],
+    ) -> Result<(), ServiceError>;
 }
 
+/// File in each index directory recording the [`SchemaLoader::load_schema_version`] the index
+/// was built with.
+pub(crate) const SCHEMA_VERSION_FILE: &str = "pathery-schema-version";
+
+/// Writes the schema version to the index's underlying `directory`. Writing it through
+/// `Index::directory` would register it as a tantivy file, to be garbage collected on commit.
+fn write_schema_version(directory: &dyn Directory, version: &str) -> Result<(), ServiceError> {
+    directory
+        .atomic_write(Path::new(SCHEMA_VERSION_FILE), version.as_bytes())
+        .map_err(ServiceError::internal_error)
+}
+
+/// Rewrites `meta.json` with `fields` appended to the schema. Field ids are positional, so
+/// existing fields keep their ids.
+fn write_extended_schema(index: &Index, fields: Vec<FieldEntry>) -> Result<(), ServiceError> {
+    let mut metas = index.load_metas().map_err(ServiceError::internal_error)?;
+
+    let mut schema = Schema::builder();
+    for (_, entry) in metas.schema.fields() {
+        schema.add_field(entry.clone());
+    }
+    for entry in fields {
+        schema.add_field(entry);
+    }
+    metas.schema = schema.build();
+
+    let mut buffer = json::to_vec_pretty(&metas).map_err(ServiceError::internal_error)?;
+    buffer.push(b'\n');
+
+    index
+        .directory()
+        .atomic_write(Path::new("meta.json"), &buffer)
+        .map_err(ServiceError::internal_error)
+}
+
+fn compress_stored_fields(schema_loader: &SchemaProvider, index_id: &str) -> bool {
+    schema_loader
+        .load_settings(index_id)
+        .is_ok_and(|settings| settings.compress_stored_fields)
+}
+
+/// Warns when the index was built from a different field config than is configured now.
+/// Indexes built before schema versions were recorded are compared by schema, which dynamic
+/// indexes are expected to have grown past.
+fn warn_schema_drift(schema_loader: &SchemaProvider, index_id: &str, index: &Index) {
+    let version = index.schema_version();
+    let configured_version = schema_loader.load_schema_version(index_id).ok();
+
+    let dynamic = schema_loader
+        .load_settings(index_id)
+        .is_ok_and(|settings| settings.dynamic);
+
+    let reasons = match schema_loader.load_schema(index_id) {
+        Ok(configured) if !dynamic => match diff_schema(&index.schema(), &configured) {
+            SchemaChange::ReindexRequired(reasons) => reasons,
+            SchemaChange::Unchanged => vec![],
+        },
+        _ => vec![],
+    };
+
+    let version_changed = matches!(
+        (&version, &configured_version),
+        (Some(version), Some(configured)) if version != configured
+    );
+
+    if version_changed || !reasons.is_empty() {
+        warn!(
+            message = "schema_drift",
+            index_id,
+            version = ?version,
+            configured_version = ?configured_version,
+            reasons = ?reasons
+        );
+    }
+}
+
+/// Loads indexes from the storage chosen by the `PATHERY_DATA_DIRECTORY` env var: the EFS mount
+/// by default, an S3 bucket when it's `s3://bucket/prefix`, a local directory when it names one,
+/// or memory when it's `:memory:`. Local and memory storage are for development and integration
+/// tests, where there's no EFS or async delete queue.
 pub struct LambdaIndexLoader {
+    inner: Box<dyn IndexLoader>,
+}
+
+impl LambdaIndexLoader {
+    pub async fn create() -> Self {
+        let schema_loader = SchemaProvider::lambda().await;
+
+        let inner: Box<dyn IndexLoader> = match env::var(DATA_DIRECTORY_ENV).ok().as_deref() {
+            None => {
+                let async_delete_client = LambdaAsyncDeleteClient::create(None).await;
+                Box::new(DirectoryIndexLoader::create(
+                    schema_loader,
+                    DATA_DIRECTORY,
+                    Arc::new(async_delete_client),
+                ))
+            }
+            Some(MEMORY_DATA_DIRECTORY) => Box::new(RamIndexLoader::create(schema_loader)),
+            Some(data_directory) if data_directory.starts_with(S3_DATA_DIRECTORY_SCHEME) => {
+                let location = &data_directory[S3_DATA_DIRECTORY_SCHEME.len()..];
+                let (bucket_name, prefix) = location.split_once('/').unwrap_or((location, ""));
+                let store = S3FileStore::create(Some(bucket_name), prefix).await;
+                Box::new(FileStoreIndexLoader::create(
+                    schema_loader,
+                    Arc::new(store),
+                    &env::temp_dir().join(CACHE_DIRECTORY),
+                ))
+            }
+            Some(data_directory) => {
+                fs::create_dir_all(data_directory).expect("data directory should be creatable");
+                Box::new(DirectoryIndexLoader::create(
+                    schema_loader,
+                    data_directory,
+                    Arc::new(LocalDeleteClient),
+                ))
+            }
+        };
+
+        Self { inner }
+    }
+}
+
+impl IndexLoader for LambdaIndexLoader {
+    fn load_index(
+        &self,
+        index_id: &str,
+        with_partition: Option<(usize, usize)>,
+    ) -> Result<Index, ServiceError> {
+        self.inner.load_index(index_id, with_partition)
+    }
+
+    fn delete_index(&self, index_id: &str) -> Result<(), ServiceError> {
+        self.inner.delete_index(index_id)
+    }
+
+    fn list_indexes(&self) -> Result<Vec<String>, ServiceError> {
+        self.inner.list_indexes()
+    }
+
+    fn extend_schema(
+        &self,
+        index_id: &str,
+        fields: Vec<FieldEntry>,
+    ) -> Result<Index, ServiceError> {
+        self.inner.extend_schema(index_id, fields)
+    }
+
+    fn create_staging_index(&self, index_id: &str, schema: Schema) -> Result<Index, ServiceError> {
+        self.inner.create_staging_index(index_id, schema)
+    }
+
+    fn swap_staging_index(&self, index_id: &str) -> Result<(), ServiceError> {
+        self.inner.swap_staging_index(index_id)
+    }
+
+    fn segment_formats(&self, index_id: &str) -> Result<Vec<SegmentFormat>, ServiceError> {
+        self.inner.segment_formats(index_id)
+    }
+
+    fn manifest(&self, index_id: &str) -> Result<IndexManifest, ServiceError> {
+        self.inner.manifest(index_id)
+    }
+
+    fn write_index_file(
+        &self,
+        index_id: &str,
+        name: &str,
+        bytes: &[u8],
+    ) -> Result<(), ServiceError> {
+        self.inner.write_index_file(index_id, name, bytes)
+    }
+}
+
+const DATA_DIRECTORY_ENV: &str = "PATHERY_DATA_DIRECTORY";
+
+const DATA_DIRECTORY: &str = "/mnt/pathery-data";
+
+const MEMORY_DATA_DIRECTORY: &str = ":memory:";
+
+/// Prefix of data directories naming an S3 location, e.g. `s3://bucket/prefix`.
+const S3_DATA_DIRECTORY_SCHEME: &str = "s3://";
+
+/// Directory under the temp directory that files of indexes in S3 are cached in.
+const CACHE_DIRECTORY: &str = "pathery-cache";
+
+/// Segment files downloaded at once when opening an index kept in a file store, unless its
+/// settings choose otherwise.
+const DEFAULT_PREFETCH_CONCURRENCY: usize = 8;
+
+/// Loads each index from a directory named by its id under `data_directory`. Each index's path is
+/// a link to its current version under `.versions`, so that swapping in a rebuilt version replaces
+/// the link in a single rename and loaders see either the old version or the new one.
+pub struct DirectoryIndexLoader {
     schema_loader: SchemaProvider,
 
+    data_directory: String,
+
     async_delete_client: Arc<dyn AsyncDeleteClient>,
 }
 
-impl LambdaIndexLoader {
-    pub async fn create() -> Self {
-        let async_delete_client = LambdaAsyncDeleteClient::create(None).await;
-        let async_delete_client = Arc::new(async_delete_client);
+fn remove_dir_if_exists(path: &str) -> Result<(), ServiceError> {
+    match fs::remove_dir_all(path) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+            Err(ServiceError::internal_error(err))
+        }
+        _ => Ok(()),
+    }
+}
 
-        Self {
-            schema_loader: SchemaProvider::lambda(),
+impl DirectoryIndexLoader {
+    pub fn create(
+        schema_loader: SchemaProvider,
+        data_directory: &str,
+        async_delete_client: Arc<dyn AsyncDeleteClient>,
+    ) -> Self {
+        DirectoryIndexLoader {
+            schema_loader,
+            data_directory: data_directory.trim_end_matches('/').into(),
             async_delete_client,
         }
     }
+
+    fn index_directory(&self, index_id: &str) -> String {
+        format!("{}/{index_id}", self.data_directory)
+    }
+
+    /// Rebuilt indexes are staged and versioned under hidden directories, which aren't listed as
+    /// indexes.
+    fn staging_directory(&self, index_id: &str) -> String {
+        format!("{}/.staging/{index_id}", self.data_directory)
+    }
+
+    /// A new version directory for `index_id`, and the path it's linked to by, which is relative
+    /// to the data directory so that links survive the mount moving.
+    fn version_directory(&self, index_id: &str) -> Result<(String, String), ServiceError> {
+        fs::create_dir_all(format!("{}/.versions", self.data_directory))
+            .map_err(ServiceError::internal_error)?;
+
+        let target = format!(".versions/{index_id}-{}", util::generate_id());
+        Ok((format!("{}/{target}", self.data_directory), target))
+    }
+
+    /// The version directory `index_id` links to, `None` when it doesn't exist or is a directory
+    /// created before indexes were versioned.
+    fn linked_version(&self, index_id: &str) -> Option<String> {
+        let target = fs::read_link(self.index_directory(index_id)).ok()?;
+        Some(format!("{}/{}", self.data_directory, target.to_str()?))
+    }
+
+    /// Creates `index_id` in a new version directory and links it in, unless another loader
+    /// linked one in first, which is then loaded instead.
+    fn create_index(
+        &self,
+        index_id: &str,
+        with_partition: Option<(usize, usize)>,
+    ) -> Result<Index, ServiceError> {
+        let (version_path, target) = self.version_directory(index_id)?;
+        fs::create_dir(&version_path).map_err(ServiceError::internal_error)?;
+
+        let schema = self.schema_loader.load_schema(index_id)?;
+        let directory = MmapDirectory::open(&version_path).map_err(ServiceError::internal_error)?;
+        write_schema_version(
+            &directory,
+            &self.schema_loader.load_schema_version(index_id)?,
+        )?;
+        let index = Index::create(
+            CompressedDirectory::new(
+                directory,
+                compress_stored_fields(&self.schema_loader, index_id),
+            ),
+            schema,
+            tantivy::IndexSettings::default(),
+        )
+        .expect("Index should be creatable");
+
+        match symlink(&target, self.index_directory(index_id)) {
+            Ok(()) => Ok(index),
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {
+                remove_dir_if_exists(&version_path)?;
+                self.load_index(index_id, with_partition)
+            }
+            Err(err) => Err(ServiceError::internal_error(err)),
+        }
+    }
 }
 
-impl IndexLoader for LambdaIndexLoader {
+impl IndexLoader for DirectoryIndexLoader {
     fn load_index(
         &self,
         index_id: &str,
         with_partition: Option<(usize, usize)>,
     ) -> Result<Index, ServiceError> {
-        let directory_path = format!("/mnt/pathery-data/{index_id}");
+        let directory_path = self.index_directory(index_id);
 
         let mut index = if let Ok(existing_dir) =
             PatheryDirectory::open(&directory_path, with_partition, &self.async_delete_client)
         {
-            Index::open(existing_dir).expect("Index should be openable")
+            let index = Index::open(CompressedDirectory::new(
+                existing_dir.clone(),
+                compress_stored_fields(&self.schema_loader, index_id),
+            ))
+            .expect("Index should be openable");
+            let segments = index
+                .searchable_segment_metas()
+                .map_err(ServiceError::internal_error)?;
+            compat::check_compatibility(
+                index_id,
+                &compat::segment_formats(&existing_dir, &segments)?,
+            )?;
+            warn_schema_drift(&self.schema_loader, index_id, &index);
+            index
+        } else {
+            self.create_index(index_id, with_partition)?
+        };
+
+        let analyzers = self
+            .schema_loader
+            .load_analyzers(index_id)
+            .unwrap_or_default();
+        tokenizer::register_tokenizers(&index, analyzers);
+
+        index
+            .set_default_multithread_executor()
+            .expect("default multithread executor should succeed");
+
+        Ok(index)
+    }
+
+    fn delete_index(&self, index_id: &str) -> Result<(), ServiceError> {
+        let Some(version_path) = self.linked_version(index_id) else {
+            return remove_dir_if_exists(&self.index_directory(index_id));
+        };
+
+        // Unlinked first, so loaders don't open a version while it's being removed.
+        fs::remove_file(self.index_directory(index_id)).map_err(ServiceError::internal_error)?;
+        remove_dir_if_exists(&version_path)
+    }
+
+    fn list_indexes(&self) -> Result<Vec<String>, ServiceError> {
+        let mut index_ids = fs::read_dir(&self.data_directory)
+            .map_err(ServiceError::internal_error)?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_dir())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .filter(|name| !name.starts_with('.'))
+            .collect::<Vec<_>>();
+
+        index_ids.sort();
+
+        Ok(index_ids)
+    }
+
+    fn extend_schema(
+        &self,
+        index_id: &str,
+        fields: Vec<FieldEntry>,
+    ) -> Result<Index, ServiceError> {
+        let index = self.load_index(index_id, None)?;
+        write_extended_schema(&index, fields)?;
+        self.load_index(index_id, None)
+    }
+
+    fn create_staging_index(&self, index_id: &str, schema: Schema) -> Result<Index, ServiceError> {
+        let directory_path = self.staging_directory(index_id);
+        remove_dir_if_exists(&directory_path)?;
+        fs::create_dir_all(&directory_path).map_err(ServiceError::internal_error)?;
+
+        let directory =
+            MmapDirectory::open(&directory_path).map_err(ServiceError::internal_error)?;
+        write_schema_version(
+            &directory,
+            &self.schema_loader.load_schema_version(index_id)?,
+        )?;
+        let index = Index::create(
+            CompressedDirectory::new(
+                directory,
+                compress_stored_fields(&self.schema_loader, index_id),
+            ),
+            schema,
+            tantivy::IndexSettings::default(),
+        )
+        .map_err(ServiceError::internal_error)?;
+
+        let analyzers = self.schema_loader.load_analyzers(index_id)?;
+        tokenizer::register_tokenizers(&index, analyzers);
+
+        Ok(index)
+    }
+
+    fn swap_staging_index(&self, index_id: &str) -> Result<(), ServiceError> {
+        let index_path = self.index_directory(index_id);
+        let staging_path = self.staging_directory(index_id);
+        let link_path = format!("{staging_path}.link");
+
+        let (version_path, target) = self.version_directory(index_id)?;
+        fs::rename(&staging_path, &version_path).map_err(ServiceError::internal_error)?;
+        // A link left by an interrupted swap is replaced.
+        let _ = fs::remove_file(&link_path);
+        symlink(&target, &link_path).map_err(ServiceError::internal_error)?;
+
+        let retired_path = match self.linked_version(index_id) {
+            Some(retired_path) => Some(retired_path),
+            // An index created before indexes were versioned is moved aside first, the one swap
+            // a concurrent loader can see the index missing during.
+            None if Path::new(&index_path).is_dir() => {
+                let (retired_path, _) = self.version_directory(index_id)?;
+                fs::rename(&index_path, &retired_path).map_err(ServiceError::internal_error)?;
+                Some(retired_path)
+            }
+            None => None,
+        };
+
+        // Replaces the link atomically. Readers that opened the old version keep it until they
+        // finish, since renames and removals don't affect open files.
+        fs::rename(&link_path, &index_path).map_err(ServiceError::internal_error)?;
+
+        match retired_path {
+            Some(retired_path) => remove_dir_if_exists(&retired_path),
+            None => Ok(()),
+        }
+    }
+
+    fn segment_formats(&self, index_id: &str) -> Result<Vec<SegmentFormat>, ServiceError> {
+        let index = self.load_index(index_id, None)?;
+        let directory = PatheryDirectory::open(
+            self.index_directory(index_id),
+            None,
+            &self.async_delete_client,
+        )
+        .map_err(ServiceError::internal_error)?;
+        let segments = index
+            .searchable_segment_metas()
+            .map_err(ServiceError::internal_error)?;
+
+        compat::segment_formats(&directory, &segments)
+    }
+
+    fn manifest(&self, index_id: &str) -> Result<IndexManifest, ServiceError> {
+        let index = self.load_index(index_id, None)?;
+        let directory = PatheryDirectory::open(
+            self.index_directory(index_id),
+            None,
+            &self.async_delete_client,
+        )
+        .map_err(ServiceError::internal_error)?;
+
+        manifest::build(index_id, &index, &directory)
+    }
+
+    fn write_index_file(
+        &self,
+        index_id: &str,
+        name: &str,
+        bytes: &[u8],
+    ) -> Result<(), ServiceError> {
+        MmapDirectory::open(self.index_directory(index_id))
+            .map_err(ServiceError::internal_error)?
+            .atomic_write(Path::new(name), bytes)
+            .map_err(ServiceError::internal_error)
+    }
+}
+
+/// Loads each index from the files under its id in a [`FileStore`], such as a prefix of an S3
+/// bucket, for indexes too large to keep on EFS. Files are read through a
+/// [`FileStoreDirectory`], which caches them under `cache_directory`.
+///
+/// A store can't rename files, so a rebuilt index is swapped in by copying its files over the
+/// index's, `meta.json` last. Loaders see the old commit until `meta.json` is replaced, since the
+/// rebuilt segments' files have names of their own.
+#[derive(Clone)]
+pub struct FileStoreIndexLoader {
+    schema_loader: SchemaProvider,
+
+    store: Arc<dyn FileStore>,
+
+    cache_directory: PathBuf,
+}
+
+/// Waits on a call to the store from the loader's synchronous methods.
+fn block_on<F: Future>(future: F) -> F::Output {
+    tokio::task::block_in_place(|| Handle::current().block_on(future))
+}
+
+impl FileStoreIndexLoader {
+    pub fn create(
+        schema_loader: SchemaProvider,
+        store: Arc<dyn FileStore>,
+        cache_directory: &Path,
+    ) -> Self {
+        FileStoreIndexLoader {
+            schema_loader,
+            store,
+            cache_directory: cache_directory.to_owned(),
+        }
+    }
+
+    /// Rebuilt indexes are staged under a hidden directory, which isn't listed as an index.
+    fn staging_path(index_id: &str) -> String {
+        format!(".staging/{index_id}")
+    }
+
+    /// The directory of the files under `path` in the store.
+    fn directory(&self, path: &str) -> Result<FileStoreDirectory, ServiceError> {
+        let store = PrefixedFileStore::new(Arc::clone(&self.store), path);
+        FileStoreDirectory::open(Arc::new(store), self.cache_directory.join(path))
+            .map_err(ServiceError::internal_error)
+    }
+
+    /// Creates an empty index with `schema` under `path`.
+    fn create_index(
+        &self,
+        index_id: &str,
+        path: &str,
+        schema: Schema,
+    ) -> Result<Index, ServiceError> {
+        let directory = self.directory(path)?;
+        write_schema_version(
+            &directory,
+            &self.schema_loader.load_schema_version(index_id)?,
+        )?;
+        Index::create(
+            CompressedDirectory::new(
+                directory,
+                compress_stored_fields(&self.schema_loader, index_id),
+            ),
+            schema,
+            tantivy::IndexSettings::default(),
+        )
+        .map_err(ServiceError::internal_error)
+    }
+
+    /// Deletes the files under `path`, `meta.json` first so that loaders don't open an index
+    /// with files missing, and its cached copies.
+    fn delete_files(&self, path: &str) -> Result<(), ServiceError> {
+        let meta_path = format!("{path}/meta.json");
+        block_on(self.store.delete_file(&meta_path))?;
+        for file_path in block_on(self.store.list_files(&format!("{path}/")))? {
+            block_on(self.store.delete_file(&file_path))?;
+        }
+
+        remove_dir_if_exists(&self.cache_directory.join(path).to_string_lossy())
+    }
+
+    /// Copies the file at `from` in the store to `to`, through a local file since segment files
+    /// can be larger than memory.
+    fn copy_file(&self, from: &str, to: &str) -> Result<(), ServiceError> {
+        fs::create_dir_all(&self.cache_directory).map_err(ServiceError::internal_error)?;
+        let local_path = self
+            .cache_directory
+            .join(format!(".{}.copy", util::generate_id()));
+
+        let copied = block_on(async {
+            if self.store.download_file(from, &local_path).await? {
+                self.store.upload_file(to, &local_path).await?;
+            }
+            Ok(())
+        });
+        let _ = fs::remove_file(&local_path);
+
+        copied
+    }
+}
+
+impl IndexLoader for FileStoreIndexLoader {
+    fn load_index(
+        &self,
+        index_id: &str,
+        _with_partition: Option<(usize, usize)>,
+    ) -> Result<Index, ServiceError> {
+        let directory = self.directory(index_id)?;
+        let compress = compress_stored_fields(&self.schema_loader, index_id);
+
+        let exists = directory
+            .exists(Path::new("meta.json"))
+            .map_err(ServiceError::internal_error)?;
+        let mut index = if exists {
+            let concurrency = self
+                .schema_loader
+                .load_settings(index_id)
+                .ok()
+                .and_then(|settings| settings.prefetch_concurrency)
+                .unwrap_or(DEFAULT_PREFETCH_CONCURRENCY);
+            let index = directory.open_index(index_id, concurrency, compress)?;
+            let segments = index
+                .searchable_segment_metas()
+                .map_err(ServiceError::internal_error)?;
+            compat::check_compatibility(
+                index_id,
+                &compat::segment_formats(&directory, &segments)?,
+            )?;
+            warn_schema_drift(&self.schema_loader, index_id, &index);
+            index
         } else {
-            fs::create_dir(&directory_path).expect("Directory should be creatable");
             let schema = self.schema_loader.load_schema(index_id)?;
-            Index::create_in_dir(Path::new(&directory_path), schema)
-                .expect("Index should be creatable")
+            self.create_index(index_id, index_id, schema)?
         };
 
+        let analyzers = self
+            .schema_loader
+            .load_analyzers(index_id)
+            .unwrap_or_default();
+        tokenizer::register_tokenizers(&index, analyzers);
+
         index
             .set_default_multithread_executor()
             .expect("default multithread executor should succeed");
 
         Ok(index)
     }
+
+    fn delete_index(&self, index_id: &str) -> Result<(), ServiceError> {
+        self.delete_files(index_id)
+    }
+
+    fn list_indexes(&self) -> Result<Vec<String>, ServiceError> {
+        let mut index_ids = block_on(self.store.list_files(""))?
+            .iter()
+            .filter_map(|path| path.strip_suffix("/meta.json"))
+            .filter(|index_id| !index_id.starts_with('.') && !index_id.contains('/'))
+            .map(String::from)
+            .collect::<Vec<_>>();
+
+        index_ids.sort();
+
+        Ok(index_ids)
+    }
+
+    fn extend_schema(
+        &self,
+        index_id: &str,
+        fields: Vec<FieldEntry>,
+    ) -> Result<Index, ServiceError> {
+        let index = self.load_index(index_id, None)?;
+        write_extended_schema(&index, fields)?;
+        self.load_index(index_id, None)
+    }
+
+    fn create_staging_index(&self, index_id: &str, schema: Schema) -> Result<Index, ServiceError> {
+        let staging_path = Self::staging_path(index_id);
+        self.delete_files(&staging_path)?;
+
+        let index = self.create_index(index_id, &staging_path, schema)?;
+        let analyzers = self.schema_loader.load_analyzers(index_id)?;
+        tokenizer::register_tokenizers(&index, analyzers);
+
+        Ok(index)
+    }
+
+    fn swap_staging_index(&self, index_id: &str) -> Result<(), ServiceError> {
+        let staging_path = Self::staging_path(index_id);
+        let staged = block_on(self.store.list_files(&format!("{staging_path}/")))?;
+        let names: HashSet<&str> = staged
+            .iter()
+            .filter_map(|path| path.strip_prefix(&format!("{staging_path}/")))
+            .collect();
+        if !names.contains("meta.json") {
+            return Err(ServiceError::not_found(&format!(
+                "No staging index for [{}]",
+                index_id
+            )));
+        }
+
+        let retired = block_on(self.store.list_files(&format!("{index_id}/")))?;
+
+        let copy = |name: &str| {
+            self.copy_file(
+                &format!("{staging_path}/{name}"),
+                &format!("{index_id}/{name}"),
+            )
+        };
+        for name in names.iter().filter(|name| **name != "meta.json") {
+            copy(name)?;
+        }
+        copy("meta.json")?;
+
+        // Files of the retired commit are removed once nothing new refers to them.
+        for path in retired {
+            let name = path.strip_prefix(&format!("{index_id}/")).unwrap_or(&path);
+            if !names.contains(name) {
+                block_on(self.store.delete_file(&path))?;
+            }
+        }
+
+        self.delete_files(&staging_path)
+    }
+
+    fn segment_formats(&self, index_id: &str) -> Result<Vec<SegmentFormat>, ServiceError> {
+        let index = self.load_index(index_id, None)?;
+        let segments = index
+            .searchable_segment_metas()
+            .map_err(ServiceError::internal_error)?;
+
+        compat::segment_formats(&self.directory(index_id)?, &segments)
+    }
+
+    fn manifest(&self, index_id: &str) -> Result<IndexManifest, ServiceError> {
+        let index = self.load_index(index_id, None)?;
+
+        manifest::build(index_id, &index, &self.directory(index_id)?)
+    }
+
+    fn write_index_file(
+        &self,
+        index_id: &str,
+        name: &str,
+        bytes: &[u8],
+    ) -> Result<(), ServiceError> {
+        self.directory(index_id)?
+            .atomic_write(Path::new(name), bytes)
+            .map_err(ServiceError::internal_error)
+    }
+}
+
+/// Holds indexes in memory, for the life of the loader and its clones.
+#[derive(Debug)]
+pub struct RamIndexLoader {
+    schema_loader: SchemaProvider,
+
+    table: Arc<Mutex<HashMap<String, (Index, RamDirectory)>>>,
+
+    staging: Arc<Mutex<HashMap<String, (Index, RamDirectory)>>>,
+}
+
+impl Clone for RamIndexLoader {
+    fn clone(&self) -> Self {
+        Self {
+            schema_loader: self.schema_loader.clone(),
+            table: self.table.clone(),
+            staging: self.staging.clone(),
+        }
+    }
+}
+
+fn create_ram_index(schema: Schema, version: &str) -> (Index, RamDirectory) {
+    let directory = RamDirectory::create();
+    let index = Index::create(directory.clone(), schema, tantivy::IndexSettings::default())
+        .expect("Index should be creatable");
+    write_schema_version(&directory, version).expect("schema version should write");
+    (index, directory)
+}
+
+impl IndexLoader for RamIndexLoader {
+    fn load_index(
+        &self,
+        index_id: &str,
+        _with_partition: Option<(usize, usize)>,
+    ) -> Result<Index, ServiceError> {
+        let mut table = self.table.lock().unwrap();
+
+        let entry = (*table).entry(index_id.into());
+
+        let schema = self.schema_loader.load_schema(index_id)?;
+        let version = self.schema_loader.load_schema_version(index_id)?;
+        let analyzers = self.schema_loader.load_analyzers(index_id)?;
+
+        let (index, _) = entry.or_insert_with(|| {
+            let (index, directory) = create_ram_index(schema, &version);
+            tokenizer::register_tokenizers(&index, analyzers);
+            (index, directory)
+        });
+
+        Ok(index.clone())
+    }
+
+    fn delete_index(&self, index_id: &str) -> Result<(), ServiceError> {
+        let mut table = self.table.lock().unwrap();
+        table.remove(index_id);
+        Ok(())
+    }
+
+    fn list_indexes(&self) -> Result<Vec<String>, ServiceError> {
+        let table = self.table.lock().unwrap();
+        let mut index_ids = table.keys().cloned().collect::<Vec<_>>();
+        index_ids.sort();
+        Ok(index_ids)
+    }
+
+    fn extend_schema(
+        &self,
+        index_id: &str,
+        fields: Vec<FieldEntry>,
+    ) -> Result<Index, ServiceError> {
+        let index = self.load_index(index_id, None)?;
+        write_extended_schema(&index, fields)?;
+        let analyzers = self.schema_loader.load_analyzers(index_id)?;
+
+        let mut table = self.table.lock().unwrap();
+        let (index, directory) = table.get_mut(index_id).expect("index was just loaded");
+
+        // Reopen from the raw directory, since `Index::directory` already checks file
+        // footers and wrapping it again would check them twice.
+        *index = Index::open(directory.clone()).map_err(ServiceError::internal_error)?;
+        tokenizer::register_tokenizers(index, analyzers);
+
+        Ok(index.clone())
+    }
+
+    fn create_staging_index(&self, index_id: &str, schema: Schema) -> Result<Index, ServiceError> {
+        let version = self.schema_loader.load_schema_version(index_id)?;
+        let (index, directory) = create_ram_index(schema, &version);
+        tokenizer::register_tokenizers(&index, self.schema_loader.load_analyzers(index_id)?);
+
+        let mut staging = self.staging.lock().unwrap();
+        staging.insert(index_id.into(), (index.clone(), directory));
+        Ok(index)
+    }
+
+    fn swap_staging_index(&self, index_id: &str) -> Result<(), ServiceError> {
+        let staged = self
+            .staging
+            .lock()
+            .unwrap()
+            .remove(index_id)
+            .ok_or_else(|| {
+                ServiceError::not_found(&format!("No staging index for [{}]", index_id))
+            })?;
+        self.table.lock().unwrap().insert(index_id.into(), staged);
+        Ok(())
+    }
+
+    fn segment_formats(&self, index_id: &str) -> Result<Vec<SegmentFormat>, ServiceError> {
+        let index = self.load_index(index_id, None)?;
+        let table = self.table.lock().unwrap();
+        let (_, directory) = table.get(index_id).expect("index was just loaded");
+        let segments = index
+            .searchable_segment_metas()
+            .map_err(ServiceError::internal_error)?;
+
+        compat::segment_formats(directory, &segments)
+    }
+
+    fn manifest(&self, index_id: &str) -> Result<IndexManifest, ServiceError> {
+        let index = self.load_index(index_id, None)?;
+        let table = self.table.lock().unwrap();
+        let (_, directory) = table.get(index_id).expect("index was just loaded");
+
+        manifest::build(index_id, &index, directory)
+    }
+
+    fn write_index_file(
+        &self,
+        index_id: &str,
+        name: &str,
+        bytes: &[u8],
+    ) -> Result<(), ServiceError> {
+        self.load_index(index_id, None)?;
+        let table = self.table.lock().unwrap();
+        let (_, directory) = table.get(index_id).expect("index was just loaded");
+        directory
+            .atomic_write(Path::new(name), bytes)
+            .map_err(ServiceError::internal_error)
+    }
+}
+
+impl RamIndexLoader {
+    pub fn create(schema_loader: SchemaProvider) -> Self {
+        RamIndexLoader {
+            schema_loader,
+            table: Arc::new(Mutex::new(HashMap::new())),
+            staging: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// A loader over the same indexes that loads schemas from `schema_loader`, as if the
+    /// config had changed since the indexes were built.
+    pub fn with_schema_loader(&self, schema_loader: SchemaProvider) -> Self {
+        RamIndexLoader {
+            schema_loader,
+            ..self.clone()
+        }
+    }
+}
+
+/// Metadata stored as the payload of every index writer commit.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CommitMeta {
+    pub committed_at: String,
+}
+
+fn merge_policy(config: &MergePolicyConfig) -> Box<dyn MergePolicy> {
+    let settings = match config {
+        MergePolicyConfig::Log(settings) => settings,
+        MergePolicyConfig::None => return Box::new(NoMergePolicy),
+    };
+
+    let mut merge_policy = DefaultMergePolicy::default();
+    merge_policy.set_max_docs_before_merge(settings.max_docs_before_merge.unwrap_or(10_000));
+    if let Some(min_num_segments) = settings.min_num_segments {
+        merge_policy.set_min_num_segments(min_num_segments);
+    }
+    if let Some(min_layer_size) = settings.min_layer_size {
+        merge_policy.set_min_layer_size(min_layer_size);
+    }
+    if let Some(level_log_size) = settings.level_log_size {
+        merge_policy.set_level_log_size(level_log_size);
+    }
+    if let Some(ratio) = settings.del_docs_ratio_before_merge {
+        merge_policy.set_del_docs_ratio_before_merge(ratio);
+    }
+    Box::new(merge_policy)
 }
 
 pub trait IndexExt {
-    fn default_writer(&self) -> IndexWriter;
+    /// Writer that merges in the background with the merge policy `config` describes.
+    fn default_writer(&self, config: &MergePolicyConfig) -> IndexWriter;
+
+    /// Writer that only merges when asked to with [`IndexWriterExt::merge_now`], for writers
+    /// that outlive the invocation that opened them. Background merges would otherwise finish
+    /// whenever the instance next runs, possibly while another worker holds the index's lease.
+    fn foreground_merge_writer(&self) -> IndexWriter;
+
+    /// Metadata from the most recent commit made with [`IndexWriterExt::commit_with_meta`].
+    fn last_commit(&self) -> Option<CommitMeta>;
 
     fn id_field(&self) -> Field;
+
+    /// The [`SchemaLoader::load_schema_version`] the index was built with, if it was recorded.
+    fn schema_version(&self) -> Option<String>;
+
+    /// Query parser that searches all indexed text fields by default, boosted by the field
+    /// boosts in `settings`.
+    fn query_parser(&self, settings: &IndexSettings) -> QueryParser;
+
+    /// References to every live document, for reading the whole index from the document store.
+    fn doc_refs(&self) -> Result<Vec<SearchDocRef>, ServiceError>;
 }
 
 impl IndexExt for Index {
-    fn default_writer(&self) -> IndexWriter {
+    fn doc_refs(&self) -> Result<Vec<SearchDocRef>, ServiceError> {
+        let schema = self.schema();
+        let searcher = self
+            .reader()
+            .map_err(ServiceError::internal_error)?
+            .searcher();
+
+        let mut doc_refs = vec![];
+        for segment_reader in searcher.segment_readers() {
+            let store_reader = segment_reader
+                .get_store_reader()
+                .map_err(ServiceError::internal_error)?;
+            for doc_id in segment_reader.doc_ids_alive() {
+                let document = store_reader
+                    .get(doc_id)
+                    .map_err(ServiceError::internal_error)?;
+                doc_refs.push(SearchDocRef::from(schema.to_named_doc(&document)));
+            }
+        }
+
+        Ok(doc_refs)
+    }
+
+    fn default_writer(&self, config: &MergePolicyConfig) -> IndexWriter {
         let writer = self
             .writer(100_000_000)
             .expect("Writer should be available");
 
-        let mut merge_policy = DefaultMergePolicy::default();
-        merge_policy.set_max_docs_before_merge(10_000);
-
-        writer.set_merge_policy(Box::new(merge_policy));
+        writer.set_merge_policy(merge_policy(config));
 
         writer
     }
 
+    fn foreground_merge_writer(&self) -> IndexWriter {
+        self.default_writer(&MergePolicyConfig::None)
+    }
+
     fn id_field(&self) -> Field {
         self.schema()
             .get_field("__id")
             .expect("__id field should exist")
     }
+
+    fn last_commit(&self) -> Option<CommitMeta> {
+        let payload = self.load_metas().ok()?.payload?;
+        json::from_str(&payload).ok()
+    }
+
+    fn schema_version(&self) -> Option<String> {
+        let bytes = self
+            .directory()
+            .atomic_read(Path::new(SCHEMA_VERSION_FILE))
+            .ok()?;
+        String::from_utf8(bytes).ok()
+    }
+
+    fn query_parser(&self, settings: &IndexSettings) -> QueryParser {
+        let schema = self.schema();
+
+        let default_fields = schema
+            .fields()
+            .filter_map(|(field, entry)| {
+                if !entry.is_indexed() {
+                    return None;
+                }
+                match entry.field_type() {
+                    FieldType::Str(_) => Some(field),
+                    _ => None,
+                }
+            })
+            .collect::<Vec<Field>>();
+
+        let mut query_parser = QueryParser::for_index(self, default_fields);
+
+        for (field_name, boost) in &settings.field_boosts {
+            if let Some(field) = schema.get_field(field_name) {
+                query_parser.set_field_boost(field, *boost);
+            }
+        }
+
+        query_parser
+    }
 }
 
-#[cfg(test)]
-pub mod test_util {
-    use std::collections::HashMap;
-    use std::sync::{Arc, Mutex};
+pub trait IndexWriterExt {
+    /// Commits pending changes, recording [`CommitMeta`] in the commit payload.
+    fn commit_with_meta(&mut self) -> tantivy::Result<u64>;
 
-    use super::*;
+    /// Runs the merges the merge policy `config` describes would, waiting for each to finish.
+    /// Returns the segments merged away.
+    fn merge_now(&mut self, config: &MergePolicyConfig) -> tantivy::Result<Vec<SegmentMeta>>;
+}
 
-    #[derive(Debug)]
-    pub struct TestIndexLoader {
-        schema_loader: SchemaProvider,
+impl IndexWriterExt for IndexWriter {
+    fn commit_with_meta(&mut self) -> tantivy::Result<u64> {
+        let meta = CommitMeta {
+            committed_at: util::timestamp(),
+        };
 
-        table: Arc<Mutex<HashMap<String, Index>>>,
+        let mut commit = self.prepare_commit()?;
+        commit.set_payload(&json::to_string(&meta).expect("commit meta should serialize"));
+        commit.commit()
     }
 
-    impl Clone for TestIndexLoader {
-        fn clone(&self) -> Self {
-            Self {
-                schema_loader: self.schema_loader.clone(),
-                table: self.table.clone(),
+    fn merge_now(&mut self, config: &MergePolicyConfig) -> tantivy::Result<Vec<SegmentMeta>> {
+        let merge_policy = merge_policy(config);
+        let mut merged = vec![];
+
+        loop {
+            let segments = self.index().searchable_segment_metas()?;
+            let candidates = merge_policy.compute_merge_candidates(&segments);
+            if candidates.is_empty() {
+                return Ok(merged);
+            }
+            for candidate in candidates {
+                self.merge(&candidate.0).wait()?;
+                merged.extend(
+                    segments
+                        .iter()
+                        .filter(|segment| candidate.0.contains(&segment.id()))
+                        .cloned(),
+                );
             }
         }
     }
+}
 
-    impl IndexLoader for TestIndexLoader {
-        fn load_index(
-            &self,
-            index_id: &str,
-            _with_partition: Option<(usize, usize)>,
-        ) -> Result<Index, ServiceError> {
-            let mut table = self.table.lock().unwrap();
+#[cfg(test)]
+pub mod test_util {
+    pub use super::RamIndexLoader as TestIndexLoader;
+}
 
-            let entry = (*table).entry(index_id.into());
+#[cfg(test)]
+mod tests {
+    use tantivy::doc;
 
-            let schema = self.schema_loader.load_schema(index_id)?;
+    use super::*;
+    use crate::cursor::CursorKey;
+    use crate::service::index::{BatchIndexService, QueryIndexService};
+    use crate::service::{ServiceHandler, ServiceRequest};
+    use crate::store::file::test_util::TestFileStore;
+    use crate::test_utils::*;
+    use crate::worker::index_writer::client::LocalIndexWriterClient;
 
-            let index = entry.or_insert_with(|| Index::create_in_ram(schema));
+    #[tokio::test(flavor = "multi_thread")]
+    async fn directory_loader_keeps_indexes_in_a_local_directory() {
+        let ctx = setup();
+        let data_directory = env::temp_dir().join(format!("pathery-{}", util::generate_id()));
+        fs::create_dir_all(&data_directory).unwrap();
 
-            Ok(index.clone())
-        }
+        let index_loader = DirectoryIndexLoader::create(
+            ctx.schema_loader().clone(),
+            data_directory.to_str().unwrap(),
+            Arc::new(LocalDeleteClient),
+        );
+
+        let index = index_loader.load_index("test", None).unwrap();
+        let mut writer = index.default_writer(&MergePolicyConfig::default());
+        writer.add_document(doc!(index.id_field() => "a")).unwrap();
+        writer.commit_with_meta().unwrap();
+        writer.wait_merging_threads().unwrap();
+
+        let index = index_loader.load_index("test", None).unwrap();
+        assert_eq!(1, index.reader().unwrap().searcher().num_docs());
+        assert_eq!(vec!["test"], index_loader.list_indexes().unwrap());
+
+        // Rebuilding swaps in an empty index, without listing the staging index.
+        index_loader
+            .create_staging_index("test", index.schema())
+            .unwrap();
+        assert_eq!(vec!["test"], index_loader.list_indexes().unwrap());
+        index_loader.swap_staging_index("test").unwrap();
+        let index = index_loader.load_index("test", None).unwrap();
+        assert_eq!(0, index.reader().unwrap().searcher().num_docs());
+
+        // The index's path links to its only version, the swapped in one.
+        let versions = || {
+            fs::read_dir(data_directory.join(".versions"))
+                .unwrap()
+                .count()
+        };
+        assert!(fs::symlink_metadata(data_directory.join("test"))
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(1, versions());
+
+        // Indexes created before versioning are swapped for a linked version too.
+        let legacy_path = data_directory.join("test-legacy");
+        fs::create_dir(&legacy_path).unwrap();
+        Index::create_in_dir(&legacy_path, index.schema()).unwrap();
+        index_loader
+            .create_staging_index("test-legacy", index.schema())
+            .unwrap();
+        index_loader.swap_staging_index("test-legacy").unwrap();
+        assert!(fs::read_link(&legacy_path).is_ok());
+        assert_eq!(2, versions());
+
+        index_loader.delete_index("test").unwrap();
+        index_loader.delete_index("test-legacy").unwrap();
+        assert!(index_loader.list_indexes().unwrap().is_empty());
+        assert_eq!(0, versions());
+
+        fs::remove_dir_all(data_directory).unwrap();
     }
 
-    impl TestIndexLoader {
-        pub fn create(schema_loader: SchemaProvider) -> Self {
-            TestIndexLoader {
-                schema_loader,
-                table: Arc::new(Mutex::new(HashMap::new())),
-            }
+    #[tokio::test(flavor = "multi_thread")]
+    async fn file_store_loader_keeps_indexes_in_a_file_store() {
+        let ctx = setup();
+        let store = TestFileStore::create();
+        let cache_directory = env::temp_dir().join(format!("pathery-{}", util::generate_id()));
+        let index_loader = FileStoreIndexLoader::create(
+            ctx.schema_loader().clone(),
+            Arc::new(store.clone()),
+            &cache_directory,
+        );
+
+        let writer_client = LocalIndexWriterClient::create(
+            index_loader.clone(),
+            ctx.schema_loader().clone(),
+            ctx.document_store().clone(),
+            ctx.job_store().clone(),
+            ctx.change_store().clone(),
+            ctx.event_store().clone(),
+        );
+        let batch_index = BatchIndexService::new(
+            Box::new(ctx.schema_loader().clone()),
+            Box::new(ctx.document_store().clone()),
+            Box::new(writer_client),
+        );
+        let docs = vec![json!({ "title": "hello" }), json!({ "title": "world" })];
+        let request = ServiceRequest::create(docs).with_path_param("index_id", "test");
+        batch_index.handle_request(request).await.unwrap();
+
+        assert!(store.file_exists("test/meta.json").await.unwrap());
+        assert_eq!(vec!["test"], index_loader.list_indexes().unwrap());
+
+        // A loader with a fresh cache queries the committed segments from the store.
+        let query_index = QueryIndexService::new(
+            Box::new(ctx.schema_loader().clone()),
+            Box::new(FileStoreIndexLoader::create(
+                ctx.schema_loader().clone(),
+                Arc::new(store.clone()),
+                &cache_directory.join("fresh"),
+            )),
+            Box::new(ctx.document_store().clone()),
+            CursorKey::generate(),
+        );
+        let query = |query: &str| {
+            ServiceRequest::create(json::from_value(json!({ "query": query })).unwrap())
+                .with_path_param("index_id", "test")
+        };
+        let response = query_index.handle_request(query("hello")).await.unwrap();
+        assert_eq!(1, response.matches.len());
+
+        // Opening it prefetched every segment store into the cache.
+        let cached = fs::read_dir(cache_directory.join("fresh/test")).unwrap();
+        let stores = cached
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "store"))
+            .count();
+        assert_eq!(1, stores);
+
+        // Rebuilding swaps in an empty index, without listing the staging index.
+        let index = index_loader.load_index("test", None).unwrap();
+        index_loader
+            .create_staging_index("test", index.schema())
+            .unwrap();
+        assert_eq!(vec!["test"], index_loader.list_indexes().unwrap());
+        index_loader.swap_staging_index("test").unwrap();
+        let response = query_index.handle_request(query("hello")).await.unwrap();
+        assert!(response.matches.is_empty());
+        assert!(store.list_files(".staging/").await.unwrap().is_empty());
+
+        index_loader.delete_index("test").unwrap();
+        assert!(index_loader.list_indexes().unwrap().is_empty());
+        assert!(store.list_files("").await.unwrap().is_empty());
+
+        fs::remove_dir_all(cache_directory).unwrap();
+    }
+
+    #[test]
+    fn merge_now_follows_the_configured_merge_policy() {
+        let ctx = setup();
+        let index = ctx.index_loader().load_index("test", None).unwrap();
+        let mut writer = index.foreground_merge_writer();
+        for id in ["a", "b", "c"] {
+            writer.add_document(doc!(index.id_field() => id)).unwrap();
+            writer.commit_with_meta().unwrap();
         }
+        let segments = || index.searchable_segment_metas().unwrap().len();
+
+        writer.merge_now(&MergePolicyConfig::None).unwrap();
+        assert_eq!(3, segments());
+
+        let config: MergePolicyConfig =
+            json::from_value(json!({ "kind": "log", "min_num_segments": 2 })).unwrap();
+        writer.merge_now(&config).unwrap();
+        assert_eq!(1, segments());
     }
 }
@@ -0,0 +1,4 @@
+use aws_lambda_events::event::dynamodb;
+use lambda_runtime::LambdaEvent;
+
+pub type DynamoDbEvent = LambdaEvent<dynamodb::Event>;
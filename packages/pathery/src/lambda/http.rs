@@ -0,0 +1,707 @@
+//! Request correlation for HTTP handlers.
+//!
+//! Every API request is assigned a request id, taken from the `x-request-id` header when
+//! the caller supplies one. The id is attached to the tracing span for the request, echoed
+//! back in the response headers and carried on any index writer jobs the request submits.
+//! Requests traced by X-Ray carry its trace id the same way, from the invocation or a well formed
+//! `x-amzn-trace-id` header, and the writer queue is sent it for X-Ray to link the queued jobs.
+//!
+//! Handlers wrapped in [`ApiKeyAuth`] also check the request's `x-api-key` header against the
+//! keys in the data table before the request is dispatched, when `PATHERY_API_KEY_AUTH` is
+//! `true`. Requests without a known key are refused with a 401, and those whose key lacks the
+//! handler's scope with a 403. Keys granted the scope on only some indexes are checked against
+//! the requested index by [`ServiceRequest::index_id`], so every handler enforces them.
+//!
+//! Handlers wrapped in [`RateLimited`] take a token from the requested index's bucket in the data
+//! table before dispatching, for indexes with `settings.rate_limit`, and refuse requests with a
+//! 429 while the bucket is empty.
+//!
+//! Handlers wrapped in [`FieldDeprecations`] look for the requested index's
+//! `settings.deprecated_fields` among the fields a request uses. Each one found is logged,
+//! counted as a metric and against the request's client in the data table, and returned as a
+//! `Warning` header on the response, while the request is served as usual.
+
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::io::BufRead;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tantivy::schema::Schema;
+use tracing::warn;
+
+use crate::metrics::MetricSet;
+use crate::schema::{IndexSettings, SchemaLoader, SchemaProvider};
+use crate::service::{ServiceError, ServiceHandler, ServiceRequest};
+use crate::store::api_key::{ApiKey, ApiKeyScope, ApiKeyStore, DDBApiKeyStore};
+use crate::store::field_usage::{DDBFieldUsageStore, FieldUsageStore, FieldUse};
+use crate::store::rate_limit::{DDBRateLimitStore, RateLimitStore};
+use crate::{json, util};
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+pub const TRACE_ID_HEADER: &str = "x-amzn-trace-id";
+
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+pub const API_KEY_AUTH_ENV: &str = "PATHERY_API_KEY_AUTH";
+
+pub const WARNING_HEADER: &str = "warning";
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+
+    static TRACE_ID: Option<String>;
+
+    static WARNINGS: RefCell<Vec<String>>;
+}
+
+/// Returns the request id from the incoming headers or generates a new one.
+pub fn extract_request_id(request: &lambda_http::Request) -> String {
+    request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty())
+        .map(String::from)
+        .unwrap_or_else(util::generate_id)
+}
+
+/// Runs `f` with `request_id` as the current request id.
+pub async fn with_request_id<F>(request_id: String, f: F) -> F::Output
+where F: Future {
+    REQUEST_ID.scope(request_id, f).await
+}
+
+/// The id of the request currently being handled, if any.
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(|request_id| request_id.clone()).ok()
+}
+
+/// Longest trace header accepted from a client, well past any X-Ray would send.
+const MAX_TRACE_HEADER_LEN: usize = 256;
+
+/// Whether `header` is an X-Ray trace header, such as
+/// `Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=1`: `key=value` pairs
+/// including a well formed `Root`.
+fn is_trace_header(header: &str) -> bool {
+    let is_hex = |value: &str, len: usize| {
+        value.len() == len && value.bytes().all(|byte| byte.is_ascii_hexdigit())
+    };
+    let is_root = |value: &str| match value.split('-').collect::<Vec<_>>().as_slice() {
+        ["1", time, id] => is_hex(time, 8) && is_hex(id, 24),
+        _ => false,
+    };
+
+    let mut has_root = false;
+    for field in header.split(';') {
+        let (key, value) = match field.split_once('=') {
+            Some(pair) => pair,
+            None => return false,
+        };
+        let valid = match key {
+            "Root" => {
+                has_root = true;
+                is_root(value)
+            }
+            "Parent" => is_hex(value, 16),
+            "Sampled" => matches!(value, "0" | "1" | "?"),
+            _ => {
+                !key.is_empty()
+                    && key.bytes().all(|byte| byte.is_ascii_alphanumeric())
+                    && value
+                        .bytes()
+                        .all(|byte| byte.is_ascii_graphic() && byte != b';')
+            }
+        };
+        if !valid {
+            return false;
+        }
+    }
+
+    has_root && header.len() <= MAX_TRACE_HEADER_LEN
+}
+
+/// Returns the X-Ray trace id of the invocation, or of the incoming headers when the invocation has
+/// none, if the request is traced. Malformed headers are dropped rather than sent on to the writer
+/// queue.
+pub fn extract_trace_id(request: &lambda_http::Request) -> Option<String> {
+    let invocation = request
+        .extensions()
+        .get::<lambda_runtime::Context>()
+        .map(|context| context.xray_trace_id.clone());
+    let header = || {
+        request
+            .headers()
+            .get(TRACE_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .filter(|value| is_trace_header(value))
+            .map(String::from)
+    };
+
+    invocation
+        .filter(|trace_id| !trace_id.is_empty())
+        .or_else(header)
+}
+
+/// Runs `f` with `trace_id` as the current trace id.
+pub async fn with_trace_id<F>(trace_id: Option<String>, f: F) -> F::Output
+where F: Future {
+    TRACE_ID.scope(trace_id, f).await
+}
+
+/// The X-Ray trace id of the request currently being handled, if it's traced.
+pub fn current_trace_id() -> Option<String> {
+    TRACE_ID.try_with(Clone::clone).ok().flatten()
+}
+
+/// Runs `f`, returning its output along with the warnings added while it ran.
+pub async fn with_warnings<F>(f: F) -> (F::Output, Vec<String>)
+where F: Future {
+    WARNINGS
+        .scope(RefCell::new(vec![]), async {
+            let output = f.await;
+            (output, WARNINGS.with(RefCell::take))
+        })
+        .await
+}
+
+/// Adds `message` to the warnings returned with the response to the request being handled,
+/// unless it's already there. Does nothing outside of a request.
+pub fn add_warning(message: String) {
+    let _ = WARNINGS.try_with(|warnings| {
+        let mut warnings = warnings.borrow_mut();
+        if !warnings.contains(&message) {
+            warnings.push(message);
+        }
+    });
+}
+
+/// The value of a `Warning` header carrying `message`, in the format of RFC 7234.
+pub fn warning_header(message: &str) -> String {
+    format!("299 pathery \"{}\"", message.replace('"', "'"))
+}
+
+/// The key a request was authorized with and the scope its handler needs, for the request's
+/// index to be checked against the key's index grants.
+#[derive(Clone, Debug)]
+pub struct Authorization {
+    pub api_key: ApiKey,
+
+    pub scope: ApiKeyScope,
+}
+
+impl Authorization {
+    /// Refuses `index_id`, as named in the request, unless the key has the handler's scope on it.
+    pub fn check_index(&self, index_id: &str) -> Result<(), ServiceError> {
+        if !self.api_key.allows_index(self.scope, index_id) {
+            return Err(ServiceError::forbidden(&format!(
+                "API key [{}] lacks the {:?} scope on index [{}]",
+                self.api_key.name, self.scope, index_id
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// Dispatches requests to `service` only when their API key has `scope`.
+pub struct ApiKeyAuth<S> {
+    service: S,
+
+    /// Where keys are checked, `None` when API key auth is turned off.
+    key_store: Option<Box<dyn ApiKeyStore>>,
+
+    scope: ApiKeyScope,
+}
+
+impl<S> ApiKeyAuth<S> {
+    /// Checks keys against the data table when [`API_KEY_AUTH_ENV`] is `true`.
+    pub async fn create(service: S, scope: ApiKeyScope) -> Self {
+        let key_store: Option<Box<dyn ApiKeyStore>> =
+            match std::env::var(API_KEY_AUTH_ENV).as_deref() {
+                Ok("true") => Some(Box::new(DDBApiKeyStore::create(None).await)),
+                _ => None,
+            };
+
+        ApiKeyAuth {
+            service,
+            key_store,
+            scope,
+        }
+    }
+
+    pub fn new(service: S, key_store: Box<dyn ApiKeyStore>, scope: ApiKeyScope) -> Self {
+        ApiKeyAuth {
+            service,
+            key_store: Some(key_store),
+            scope,
+        }
+    }
+
+    /// Checks `key`, the request's [`API_KEY_HEADER`], has the handler's scope on at least one
+    /// index. `None` when API key auth is turned off.
+    async fn authorize(&self, key: Option<String>) -> Result<Option<Authorization>, ServiceError> {
+        let Some(key_store) = &self.key_store else {
+            return Ok(None);
+        };
+
+        let key = key.filter(|key| !key.is_empty()).ok_or_else(|| {
+            ServiceError::unauthorized(&format!("Missing {} header", API_KEY_HEADER))
+        })?;
+
+        let api_key = key_store
+            .get_key(&key)
+            .await?
+            .ok_or_else(|| ServiceError::unauthorized("Invalid API key"))?;
+
+        if !api_key.allows_any(self.scope) {
+            return Err(ServiceError::forbidden(&format!(
+                "API key [{}] lacks the {:?} scope",
+                api_key.name, self.scope
+            )));
+        }
+
+        Ok(Some(Authorization {
+            api_key,
+            scope: self.scope,
+        }))
+    }
+}
+
+#[async_trait]
+impl<S, B, R> ServiceHandler<B, R> for ApiKeyAuth<S>
+where
+    S: ServiceHandler<B, R> + Send,
+    B: for<'de> Deserialize<'de> + Send + 'static,
+    R: Serialize + 'static,
+{
+    async fn handle_request(&self, request: ServiceRequest<B>) -> Result<R, ServiceError> {
+        let key = request.header(API_KEY_HEADER).map(String::from);
+        let request = match self.authorize(key).await? {
+            Some(authorization) => request.with_authorization(authorization),
+            None => request,
+        };
+
+        self.service.handle_request(request).await
+    }
+}
+
+/// Dispatches requests to `service` only while their index's rate limit allows.
+pub struct RateLimited<S> {
+    service: S,
+
+    schema_loader: Box<dyn SchemaLoader>,
+
+    rate_limit_store: Box<dyn RateLimitStore>,
+}
+
+impl<S> RateLimited<S> {
+    pub async fn create(service: S) -> Self {
+        RateLimited {
+            service,
+            schema_loader: Box::new(SchemaProvider::lambda().await),
+            rate_limit_store: Box::new(DDBRateLimitStore::create(None).await),
+        }
+    }
+
+    pub fn new(
+        service: S,
+        schema_loader: Box<dyn SchemaLoader>,
+        rate_limit_store: Box<dyn RateLimitStore>,
+    ) -> Self {
+        RateLimited {
+            service,
+            schema_loader,
+            rate_limit_store,
+        }
+    }
+}
+
+#[async_trait]
+impl<S, B, R> ServiceHandler<B, R> for RateLimited<S>
+where
+    S: ServiceHandler<B, R> + Send,
+    B: for<'de> Deserialize<'de> + Send + 'static,
+    R: Serialize + 'static,
+{
+    async fn handle_request(&self, request: ServiceRequest<B>) -> Result<R, ServiceError> {
+        // Requests for an index that can't be resolved are left for the service to refuse.
+        let config = request.index_id().ok().and_then(|index_id| {
+            let settings = self.schema_loader.load_settings(&index_id).ok()?;
+            Some((index_id, settings.rate_limit?))
+        });
+
+        if let Some((index_id, config)) = config {
+            if !self.rate_limit_store.take_token(&index_id, &config).await? {
+                warn!(message = "rate_limited", index = index_id);
+                return Err(ServiceError::rate_limit());
+            }
+        }
+
+        self.service.handle_request(request).await
+    }
+}
+
+/// Request bodies naming fields of the requested index.
+pub trait FieldReferences: Sized {
+    /// Names of the fields `request` uses. Names outside the schema are allowed, and fields of
+    /// bodies that can't be parsed are left for the handler to refuse.
+    fn field_references(
+        request: &ServiceRequest<Self>,
+        schema: &Schema,
+        settings: &IndexSettings,
+    ) -> Vec<String>;
+}
+
+fn document_fields(document: &json::Value) -> impl Iterator<Item = String> + '_ {
+    document
+        .as_object()
+        .into_iter()
+        .flat_map(|doc| doc.keys().cloned())
+}
+
+/// A document or patch, or else the documents of a bulk body, one per line.
+impl FieldReferences for json::Value {
+    fn field_references(
+        request: &ServiceRequest<Self>,
+        _schema: &Schema,
+        _settings: &IndexSettings,
+    ) -> Vec<String> {
+        if let Ok(document) = request.body() {
+            return document_fields(&document).collect();
+        }
+
+        let Ok(reader) = request.body_reader() else {
+            return vec![];
+        };
+        let fields: BTreeSet<String> = reader
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|line| json::from_str::<json::Value>(&line).ok())
+            .flat_map(|document| document_fields(&document).collect::<Vec<_>>())
+            .collect();
+        fields.into_iter().collect()
+    }
+}
+
+impl FieldReferences for Vec<json::Value> {
+    fn field_references(
+        request: &ServiceRequest<Self>,
+        _schema: &Schema,
+        _settings: &IndexSettings,
+    ) -> Vec<String> {
+        let documents = request.body().unwrap_or_default();
+        let fields: BTreeSet<String> = documents.iter().flat_map(document_fields).collect();
+        fields.into_iter().collect()
+    }
+}
+
+/// Whether `name`, as a request gives it, refers to `field` or a path into it.
+fn refers_to(name: &str, field: &str) -> bool {
+    name.strip_prefix(field)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+}
+
+/// Tracks requests using their index's deprecated fields, before dispatching them to `service`.
+pub struct FieldDeprecations<S> {
+    service: S,
+
+    schema_loader: Box<dyn SchemaLoader>,
+
+    usage_store: Box<dyn FieldUsageStore>,
+
+    operation: FieldUse,
+}
+
+impl<S> FieldDeprecations<S> {
+    pub async fn create(service: S, operation: FieldUse) -> Self {
+        FieldDeprecations {
+            service,
+            schema_loader: Box::new(SchemaProvider::lambda().await),
+            usage_store: Box::new(DDBFieldUsageStore::create(None).await),
+            operation,
+        }
+    }
+
+    pub fn new(
+        service: S,
+        schema_loader: Box<dyn SchemaLoader>,
+        usage_store: Box<dyn FieldUsageStore>,
+        operation: FieldUse,
+    ) -> Self {
+        FieldDeprecations {
+            service,
+            schema_loader,
+            usage_store,
+            operation,
+        }
+    }
+
+    /// The requested index and the deprecated fields of it `request` uses, if any.
+    fn deprecated_fields<B>(&self, request: &ServiceRequest<B>) -> Option<(String, Vec<String>)>
+    where B: FieldReferences + for<'de> Deserialize<'de> {
+        let index_id = request.index_id().ok()?;
+        let settings = self.schema_loader.load_settings(&index_id).ok()?;
+        if settings.deprecated_fields.is_empty() {
+            return None;
+        }
+        let schema = self.schema_loader.load_schema(&index_id).ok()?;
+
+        let names = B::field_references(request, &schema, &settings);
+        let fields: Vec<String> = settings
+            .deprecated_fields
+            .iter()
+            .filter(|field| names.iter().any(|name| refers_to(name, field)))
+            .cloned()
+            .collect();
+
+        (!fields.is_empty()).then_some((index_id, fields))
+    }
+}
+
+#[async_trait]
+impl<S, B, R> ServiceHandler<B, R> for FieldDeprecations<S>
+where
+    S: ServiceHandler<B, R> + Send,
+    B: FieldReferences + for<'de> Deserialize<'de> + Send + 'static,
+    R: Serialize + 'static,
+{
+    async fn handle_request(&self, request: ServiceRequest<B>) -> Result<R, ServiceError> {
+        if let Some((index_id, fields)) = self.deprecated_fields(&request) {
+            let client = request.client();
+            let metric = match self.operation {
+                FieldUse::Query => "DeprecatedFieldQueries",
+                FieldUse::Write => "DeprecatedFieldWrites",
+            };
+
+            for field in fields {
+                warn!(
+                    message = "deprecated_field_used",
+                    index = index_id,
+                    field,
+                    client,
+                    operation = ?self.operation
+                );
+                add_warning(format!("Field [{}] is deprecated", field));
+                MetricSet::index(&index_id)
+                    .dimension("Field", &field)
+                    .count(metric, 1)
+                    .emit();
+
+                if let Err(err) = self
+                    .usage_store
+                    .record_use(&index_id, &field, &client, self.operation)
+                    .await
+                {
+                    warn!(message = "field_usage_record_failed", index = index_id, error = %err);
+                }
+            }
+        }
+
+        self.service.handle_request(request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::api_key::{IndexGrant, MemoryApiKeyStore};
+    use crate::store::field_usage::MemoryFieldUsageStore;
+    use crate::store::rate_limit::MemoryRateLimitStore;
+    use crate::test_utils::*;
+
+    struct EchoService;
+
+    #[async_trait]
+    impl ServiceHandler<json::Value, json::Value> for EchoService {
+        async fn handle_request(
+            &self,
+            request: ServiceRequest<json::Value>,
+        ) -> Result<json::Value, ServiceError> {
+            Ok(json::Value::String(request.index_id()?))
+        }
+    }
+
+    #[tokio::test]
+    async fn api_key_auth_checks_key_scopes() {
+        let key_store = MemoryApiKeyStore::create();
+        key_store.put_key(
+            "reader-key",
+            ApiKey {
+                name: String::from("reader"),
+                scopes: vec![ApiKeyScope::Read],
+                indexes: vec![],
+                tenant_id: None,
+            },
+        );
+        key_store.put_key(
+            "admin-key",
+            ApiKey {
+                name: String::from("admin"),
+                scopes: vec![ApiKeyScope::Admin],
+                indexes: vec![],
+                tenant_id: None,
+            },
+        );
+        key_store.put_key(
+            "orders-key",
+            ApiKey {
+                name: String::from("orders"),
+                scopes: vec![ApiKeyScope::Read],
+                indexes: vec![IndexGrant {
+                    pattern: String::from("orders-*"),
+                    scopes: vec![ApiKeyScope::Write],
+                }],
+                tenant_id: None,
+            },
+        );
+        let service = ApiKeyAuth::new(EchoService, Box::new(key_store), ApiKeyScope::Write);
+        let request = |key: Option<&str>, index_id: &str| {
+            let request = ServiceRequest::create(json!({ "title": "hello" }))
+                .with_path_param("index_id", index_id);
+            match key {
+                Some(key) => request.with_header(API_KEY_HEADER, key),
+                None => request,
+            }
+        };
+
+        for (key, status) in [
+            (None, 401),
+            (Some("unknown"), 401),
+            (Some("reader-key"), 403),
+            (Some("orders-key"), 403),
+        ] {
+            let err = service
+                .handle_request(request(key, "products-1"))
+                .await
+                .unwrap_err();
+            assert_eq!(status, err.status());
+        }
+
+        for key in ["admin-key", "orders-key"] {
+            let response = service
+                .handle_request(request(Some(key), "orders-1"))
+                .await
+                .unwrap();
+            assert_eq!(json!("orders-1"), response);
+        }
+    }
+
+    #[tokio::test]
+    async fn api_key_auth_scopes_index_ids_to_the_key_tenant() {
+        let key_store = MemoryApiKeyStore::create();
+        for tenant_id in ["acme", "globex"] {
+            key_store.put_key(
+                &format!("{tenant_id}-key"),
+                ApiKey {
+                    name: String::from(tenant_id),
+                    scopes: vec![ApiKeyScope::Write],
+                    indexes: vec![],
+                    tenant_id: Some(String::from(tenant_id)),
+                },
+            );
+        }
+        let service = ApiKeyAuth::new(EchoService, Box::new(key_store), ApiKeyScope::Write);
+        let request = |key: &str, index_id: &str| {
+            ServiceRequest::create(json!({ "title": "hello" }))
+                .with_path_param("index_id", index_id)
+                .with_header(API_KEY_HEADER, key)
+        };
+
+        let response = service
+            .handle_request(request("acme-key", "orders"))
+            .await
+            .unwrap();
+        assert_eq!(json!("acme~orders"), response);
+
+        // Naming another tenant's index by its full id stays within the key's tenant.
+        let response = service
+            .handle_request(request("acme-key", "globex~orders"))
+            .await
+            .unwrap();
+        assert_eq!(json!("acme~globex~orders"), response);
+    }
+
+    #[tokio::test]
+    async fn rate_limited_refuses_requests_over_the_index_limit() {
+        let schema_loader = SchemaProvider::from_json(json!({
+            "indexes": [
+                {
+                    "prefix": "limited",
+                    "fields": [],
+                    "settings": {
+                        "rate_limit": { "requests_per_second": 0.01, "burst": 2 }
+                    }
+                },
+                { "prefix": "unlimited", "fields": [] }
+            ]
+        }));
+        let service = RateLimited::new(
+            EchoService,
+            Box::new(schema_loader),
+            Box::new(MemoryRateLimitStore::create()),
+        );
+        let request = |index_id: &str| {
+            ServiceRequest::create(json!({})).with_path_param("index_id", index_id)
+        };
+
+        for _ in 0..2 {
+            service.handle_request(request("limited")).await.unwrap();
+        }
+        let err = service
+            .handle_request(request("limited"))
+            .await
+            .unwrap_err();
+        assert_eq!(429, err.status());
+
+        for _ in 0..3 {
+            service.handle_request(request("unlimited")).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn field_deprecations_warn_and_count_clients() {
+        let schema_loader = SchemaProvider::from_json(json!({
+            "indexes": [{
+                "prefix": "test",
+                "fields": [
+                    { "name": "title", "kind": "text", "flags": ["TEXT"] },
+                    { "name": "meta", "kind": "json", "flags": ["TEXT"] }
+                ],
+                "settings": { "deprecated_fields": ["meta"] }
+            }]
+        }));
+        let usage_store = MemoryFieldUsageStore::create();
+        let service = FieldDeprecations::new(
+            EchoService,
+            Box::new(schema_loader),
+            Box::new(usage_store.clone()),
+            FieldUse::Write,
+        );
+        let request = |body: json::Value| {
+            ServiceRequest::create(body)
+                .with_path_param("index_id", "test")
+                .with_header("user-agent", "logstash")
+        };
+
+        let (response, warnings) =
+            with_warnings(service.handle_request(request(json!({ "meta": { "a": 1 } })))).await;
+        assert_eq!(json!("test"), response.unwrap());
+        assert_eq!(vec!["Field [meta] is deprecated"], warnings);
+
+        let (_, warnings) =
+            with_warnings(service.handle_request(request(json!({ "title": "hello" })))).await;
+        assert!(warnings.is_empty());
+
+        let uses = usage_store.list_uses("test").await.unwrap();
+        assert_eq!(1, uses.len());
+        assert_eq!("meta", uses[0].field);
+        assert_eq!("logstash", uses[0].client);
+        assert_eq!(1, uses[0].count);
+
+        assert!(refers_to("meta.author", "meta"));
+        assert!(!refers_to("metadata", "meta"));
+    }
+}
@@ -1,3 +1,5 @@
+pub mod dynamodb;
+pub mod http;
 pub mod sqs;
 
 pub use lambda_runtime::Error;
@@ -8,6 +10,8 @@ pub fn init_tracing() {
         .json()
         .with_max_level(tracing::Level::INFO)
         .with_target(false)
+        .with_current_span(true)
+        .with_span_list(false)
         .without_time()
         .init();
 }
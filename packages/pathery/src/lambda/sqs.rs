@@ -1,5 +1,20 @@
 use aws_lambda_events::event::sqs;
 pub use lambda_runtime::Error;
 use lambda_runtime::LambdaEvent;
+use serde::Serialize;
 
 pub type SqsEvent = LambdaEvent<sqs::SqsEvent>;
+
+/// Response for an SQS event source with `ReportBatchItemFailures` enabled. Only the listed
+/// messages are returned to the queue; the rest of the batch is deleted.
+#[derive(Serialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SqsBatchResponse {
+    pub batch_item_failures: Vec<BatchItemFailure>,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchItemFailure {
+    pub item_identifier: String,
+}
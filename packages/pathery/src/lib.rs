@@ -1,11 +1,27 @@
+pub mod aggregation;
+pub mod analytics;
+pub mod catalog;
+pub mod compat;
+pub mod cursor;
 pub mod directory;
+pub mod enrich;
 pub mod index;
 pub mod lambda;
+pub mod manifest;
+pub mod metrics;
+pub mod query;
+pub mod reader_cache;
+pub mod retry;
 pub mod schema;
 pub mod search_doc;
 pub mod serialize;
+pub mod server;
 pub mod service;
 pub mod store;
+pub mod suggest;
+pub mod tenant;
+pub mod time_partition;
+pub mod tokenizer;
 pub mod util;
 pub mod worker;
 
@@ -19,8 +35,11 @@ pub mod test_utils {
     use crate::index::test_util::TestIndexLoader;
     use crate::schema::{SchemaLoader, SchemaProvider};
     use crate::search_doc::SearchDoc;
+    use crate::store::change::test_util::TestChangeStore;
     use crate::store::document::test_util::TestDocumentStore;
     use crate::store::document::DocumentStore;
+    use crate::store::event::test_util::TestEventStore;
+    use crate::store::job::test_util::TestJobStore;
     use crate::worker::index_writer::client::test_utils::TestIndexWriterClient;
     use crate::worker::index_writer::client::IndexWriterClient;
     use crate::worker::index_writer::job::Job;
@@ -33,14 +52,21 @@ pub mod test_utils {
         writer_client: TestIndexWriterClient,
 
         index_loader: TestIndexLoader,
+
+        job_store: TestJobStore,
+
+        change_store: TestChangeStore,
+
+        event_store: TestEventStore,
     }
 
     impl TestContext {
         pub async fn with_documents(self, index_id: &str, docs: Vec<json::Value>) -> TestContext {
             let schema = self.schema_loader.load_schema(index_id).unwrap();
+            let settings = self.schema_loader.load_settings(index_id).unwrap();
             let documents: Vec<_> = docs
                 .into_iter()
-                .map(|value| SearchDoc::from_json(&schema, value).unwrap())
+                .map(|value| SearchDoc::from_json_with_settings(&schema, value, &settings).unwrap())
                 .collect();
             let doc_refs = self.document_store.save_documents(documents).await.unwrap();
             let mut job = Job::create(index_id);
@@ -66,6 +92,18 @@ pub mod test_utils {
         pub fn index_loader(&self) -> &TestIndexLoader {
             &self.index_loader
         }
+
+        pub fn job_store(&self) -> &TestJobStore {
+            &self.job_store
+        }
+
+        pub fn change_store(&self) -> &TestChangeStore {
+            &self.change_store
+        }
+
+        pub fn event_store(&self) -> &TestEventStore {
+            &self.event_store
+        }
     }
 
     pub fn setup() -> TestContext {
@@ -108,8 +146,152 @@ pub mod test_utils {
                             "name": "props",
                             "kind": "json",
                             "flags": ["TEXT"]
+                        },
+                        {
+                            "name": "client_ip",
+                            "kind": "ip",
+                            "flags": ["INDEXED"]
+                        },
+                        {
+                            "name": "category",
+                            "kind": "facet",
+                            "flags": []
+                        },
+                        {
+                            "name": "handle",
+                            "kind": "text",
+                            "flags": [],
+                            "tokenizer": { "type": "ngram", "min_gram": 2, "max_gram": 3, "prefix_only": true }
+                        },
+                        {
+                            "name": "summary",
+                            "kind": "text",
+                            "flags": ["TEXT"],
+                            "language": "english",
+                            "stopwords": { "language": "english", "words": ["gone"] },
+                            "ascii_folding": true
                         }
                     ]
+                },
+                {
+                    "prefix": "searchonly",
+                    "fields": [
+                        {
+                            "name": "title",
+                            "kind": "text",
+                            "flags": ["TEXT"]
+                        },
+                        {
+                            "name": "price",
+                            "kind": "f64",
+                            "flags": ["INDEXED", "STORED"]
+                        }
+                    ],
+                    "settings": {
+                        "search_only": true,
+                        "owner": "search-team",
+                        "tags": ["products", "pricing"]
+                    }
+                },
+                {
+                    "prefix": "dynamic",
+                    "fields": [
+                        {
+                            "name": "title",
+                            "kind": "text",
+                            "flags": ["TEXT"]
+                        }
+                    ],
+                    "settings": {
+                        "dynamic": true
+                    }
+                },
+                {
+                    "prefix": "expiring",
+                    "fields": [
+                        {
+                            "name": "title",
+                            "kind": "text",
+                            "flags": ["TEXT"]
+                        },
+                        {
+                            "name": "expires_at",
+                            "kind": "date",
+                            "flags": ["INDEXED"]
+                        }
+                    ],
+                    "settings": {
+                        "ttl_field": "expires_at"
+                    }
+                },
+                {
+                    "prefix": "rolling",
+                    "fields": [
+                        {
+                            "name": "title",
+                            "kind": "text",
+                            "flags": ["TEXT"]
+                        },
+                        {
+                            "name": "timestamp",
+                            "kind": "date",
+                            "flags": ["INDEXED", "FAST"]
+                        }
+                    ],
+                    "settings": {
+                        "time_partition": { "field": "timestamp", "retention_days": 7 },
+                        "dynamodb_stream": {
+                            "id_attributes": ["pk"],
+                            "attributes": { "Title": "title", "Timestamp": "timestamp" }
+                        }
+                    }
+                },
+                {
+                    "prefix": "suggesting",
+                    "fields": [
+                        {
+                            "name": "title",
+                            "kind": "text",
+                            "flags": ["TEXT"]
+                        }
+                    ],
+                    "settings": {
+                        "suggest": { "fields": ["title"] }
+                    }
+                },
+                {
+                    "prefix": "streamed",
+                    "fields": [
+                        {
+                            "name": "title",
+                            "kind": "text",
+                            "flags": ["TEXT"]
+                        },
+                        {
+                            "name": "year",
+                            "kind": "i64",
+                            "flags": ["INDEXED"]
+                        }
+                    ],
+                    "settings": {
+                        "dynamodb_stream": {
+                            "id_attributes": ["pk", "sk"],
+                            "attributes": { "Title": "title", "Details.Year": "year" }
+                        }
+                    }
+                },
+                {
+                    "prefix": "audited",
+                    "fields": [
+                        {
+                            "name": "title",
+                            "kind": "text",
+                            "flags": ["TEXT"]
+                        }
+                    ],
+                    "settings": {
+                        "analytics": true
+                    }
                 }
             ]
         });
@@ -120,14 +302,27 @@ pub mod test_utils {
 
         let document_store = TestDocumentStore::create();
 
+        let job_store = TestJobStore::create();
+
+        let change_store = TestChangeStore::create();
+
+        let event_store = TestEventStore::create();
+
         TestContext {
-            schema_loader,
             writer_client: TestIndexWriterClient::create(
                 index_loader.clone(),
+                schema_loader.clone(),
                 document_store.clone(),
+                job_store.clone(),
+                change_store.clone(),
+                event_store.clone(),
             ),
+            schema_loader,
             document_store,
             index_loader,
+            job_store,
+            change_store,
+            event_store,
         }
     }
 }
@@ -0,0 +1,120 @@
+//! Manifests of the files making up an index's last commit.
+//!
+//! A manifest lists each searchable segment with its files, their sizes and the checksums
+//! tantivy recorded in their footers, so tooling outside pathery can back up, replicate or audit
+//! an index without reading its directory. The format is versioned by [`MANIFEST_VERSION`]:
+//! fields may be added within a version, but not renamed or removed.
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use tantivy::directory::error::OpenReadError;
+use tantivy::{Directory, Index, SegmentMeta};
+use tantivy_common::HasLen;
+
+use crate::compat;
+use crate::index::IndexExt;
+use crate::service::ServiceError;
+
+pub const MANIFEST_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ManifestFile {
+    pub name: String,
+
+    pub size_bytes: u64,
+
+    /// CRC32 of the file's contents, as recorded in its footer.
+    pub crc32: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ManifestSegment {
+    pub id: String,
+
+    pub max_doc: u32,
+
+    pub num_deleted: u32,
+
+    /// Opstamp of the segment's delete file, which changes whenever documents of the segment are
+    /// deleted. None while the segment has no deletes.
+    pub generation: Option<u64>,
+
+    /// The segment's files, sorted by name.
+    pub files: Vec<ManifestFile>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct IndexManifest {
+    pub manifest_version: u32,
+
+    pub index_id: String,
+
+    /// Opstamp of the commit the manifest describes.
+    pub opstamp: u64,
+
+    pub committed_at: Option<String>,
+
+    /// Format tantivy writes new segments in.
+    pub format_version: u32,
+
+    /// Searchable segments, sorted by id.
+    pub segments: Vec<ManifestSegment>,
+}
+
+fn manifest_segment(
+    directory: &dyn Directory,
+    segment: &SegmentMeta,
+) -> Result<ManifestSegment, ServiceError> {
+    let mut paths: Vec<PathBuf> = segment.list_files().into_iter().collect();
+    paths.sort();
+
+    let mut files = vec![];
+    for path in paths {
+        let file = match directory.open_read(&path) {
+            Ok(file) => file,
+            // Not every segment component is written, e.g. deletes.
+            Err(OpenReadError::FileDoesNotExist(_)) => continue,
+            Err(err) => return Err(ServiceError::internal_error(err)),
+        };
+        files.push(ManifestFile {
+            name: path.to_string_lossy().into_owned(),
+            size_bytes: file.len() as u64,
+            crc32: compat::file_checksum(file).map_err(ServiceError::internal_error)?,
+        });
+    }
+
+    Ok(ManifestSegment {
+        id: segment.id().uuid_string(),
+        max_doc: segment.max_doc(),
+        num_deleted: segment.num_deleted_docs(),
+        generation: segment.delete_opstamp(),
+        files,
+    })
+}
+
+/// The manifest of the last commit of `index`. `directory` is the one beneath the index's own,
+/// whose reads keep the footers holding checksums.
+pub fn build(
+    index_id: &str,
+    index: &Index,
+    directory: &dyn Directory,
+) -> Result<IndexManifest, ServiceError> {
+    let metas = index.load_metas().map_err(ServiceError::internal_error)?;
+
+    let mut segments = metas
+        .segments
+        .iter()
+        .map(|segment| manifest_segment(directory, segment))
+        .collect::<Result<Vec<_>, _>>()?;
+    segments.sort_by(|a, b| a.id.cmp(&b.id));
+
+    Ok(IndexManifest {
+        manifest_version: MANIFEST_VERSION,
+        index_id: index_id.into(),
+        opstamp: metas.opstamp,
+        committed_at: index.last_commit().map(|meta| meta.committed_at),
+        format_version: compat::format_version(),
+        segments,
+    })
+}
@@ -0,0 +1,189 @@
+//! Metrics about queries and indexing.
+//!
+//! Metrics are emitted as a [`MetricSet`] of values sharing dimensions, such as the index they
+//! describe, to the process wide [`MetricsSink`]. The default sink writes CloudWatch Embedded
+//! Metric Format (EMF) lines to stdout, which CloudWatch extracts metrics from when Lambda ships
+//! them to its logs. Other backends implement [`MetricsSink`] and are installed with [`set_sink`].
+
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use chrono::Utc;
+
+use crate::json;
+
+/// Namespace of the metrics when `METRICS_NAMESPACE` isn't set.
+const DEFAULT_NAMESPACE: &str = "Pathery";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    Count,
+    Milliseconds,
+}
+
+impl Unit {
+    fn name(self) -> &'static str {
+        match self {
+            Unit::Count => "Count",
+            Unit::Milliseconds => "Milliseconds",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metric {
+    pub name: &'static str,
+    pub value: f64,
+    pub unit: Unit,
+}
+
+/// Values measured together, such as those of one query, and the dimensions they're grouped by.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MetricSet {
+    pub dimensions: Vec<(&'static str, String)>,
+    pub metrics: Vec<Metric>,
+}
+
+impl MetricSet {
+    /// Metrics of `index_id`, grouped by the index.
+    pub fn index(index_id: &str) -> Self {
+        MetricSet::default().dimension("IndexId", index_id)
+    }
+
+    pub fn dimension(mut self, name: &'static str, value: &str) -> Self {
+        self.dimensions.push((name, value.into()));
+        self
+    }
+
+    pub fn count(mut self, name: &'static str, value: usize) -> Self {
+        self.metrics.push(Metric {
+            name,
+            value: value as f64,
+            unit: Unit::Count,
+        });
+        self
+    }
+
+    pub fn duration(mut self, name: &'static str, value: Duration) -> Self {
+        self.metrics.push(Metric {
+            name,
+            value: value.as_secs_f64() * 1000.0,
+            unit: Unit::Milliseconds,
+        });
+        self
+    }
+
+    /// Sends the metrics to the installed sink.
+    pub fn emit(self) {
+        sink().emit(&self);
+    }
+}
+
+pub trait MetricsSink: Send + Sync {
+    fn emit(&self, metrics: &MetricSet);
+}
+
+/// Writes metrics to stdout in CloudWatch Embedded Metric Format.
+pub struct EmfSink {
+    namespace: String,
+}
+
+impl EmfSink {
+    pub fn new(namespace: &str) -> Self {
+        EmfSink {
+            namespace: namespace.into(),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let namespace = std::env::var("METRICS_NAMESPACE");
+        EmfSink::new(namespace.as_deref().unwrap_or(DEFAULT_NAMESPACE))
+    }
+
+    /// The EMF document of `metrics`, timestamped `timestamp` in milliseconds.
+    fn document(&self, metrics: &MetricSet, timestamp: i64) -> json::Value {
+        let mut document = json::Map::new();
+        for (name, value) in &metrics.dimensions {
+            document.insert(String::from(*name), json::Value::from(value.as_str()));
+        }
+        for metric in &metrics.metrics {
+            document.insert(String::from(metric.name), json::Value::from(metric.value));
+        }
+
+        let dimensions: Vec<&str> = metrics.dimensions.iter().map(|(name, _)| *name).collect();
+        let definitions: Vec<json::Value> = metrics
+            .metrics
+            .iter()
+            .map(|metric| json::json!({ "Name": metric.name, "Unit": metric.unit.name() }))
+            .collect();
+        document.insert(
+            String::from("_aws"),
+            json::json!({
+                "Timestamp": timestamp,
+                "CloudWatchMetrics": [{
+                    "Namespace": self.namespace,
+                    "Dimensions": [dimensions],
+                    "Metrics": definitions,
+                }],
+            }),
+        );
+
+        json::Value::Object(document)
+    }
+}
+
+impl MetricsSink for EmfSink {
+    fn emit(&self, metrics: &MetricSet) {
+        if metrics.metrics.is_empty() {
+            return;
+        }
+        println!("{}", self.document(metrics, Utc::now().timestamp_millis()));
+    }
+}
+
+static SINK: OnceLock<Box<dyn MetricsSink>> = OnceLock::new();
+
+/// Installs the sink metrics are emitted to, returning false when one is already installed.
+/// Without one, metrics go to an [`EmfSink`] configured from the environment.
+pub fn set_sink(sink: Box<dyn MetricsSink>) -> bool {
+    SINK.set(sink).is_ok()
+}
+
+fn sink() -> &'static dyn MetricsSink {
+    SINK.get_or_init(|| Box::new(EmfSink::from_env())).as_ref()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn emf_document_declares_dimensions_and_metrics() {
+        let metrics = MetricSet::index("test")
+            .count("QueryHits", 3)
+            .duration("QueryLatency", Duration::from_millis(12));
+
+        let document = EmfSink::new("Pathery").document(&metrics, 1_668_000_000_000);
+
+        assert_eq!(
+            json!({
+                "IndexId": "test",
+                "QueryHits": 3.0,
+                "QueryLatency": 12.0,
+                "_aws": {
+                    "Timestamp": 1_668_000_000_000_i64,
+                    "CloudWatchMetrics": [{
+                        "Namespace": "Pathery",
+                        "Dimensions": [["IndexId"]],
+                        "Metrics": [
+                            { "Name": "QueryHits", "Unit": "Count" },
+                            { "Name": "QueryLatency", "Unit": "Milliseconds" }
+                        ]
+                    }]
+                }
+            }),
+            document
+        );
+    }
+}
@@ -0,0 +1,149 @@
+//! Helpers for composing [`Query`] values without hand-writing JSON.
+//!
+//! ```
+//! use pathery::query::{match_, term, Query};
+//!
+//! let query = Query::bool()
+//!     .must(term("status", "active"))
+//!     .should(match_("title", "zen art"))
+//!     .build();
+//! ```
+
+use super::{BoolQuery, Query};
+use crate::json;
+
+pub fn query_string(query: &str) -> Query {
+    Query::QueryString(query.into())
+}
+
+pub fn term<V>(field: &str, value: V) -> Query
+where V: Into<json::Value> {
+    Query::Term {
+        field: field.into(),
+        value: value.into(),
+    }
+}
+
+/// Matches documents where `field` has any of `values`.
+pub fn any_of<V>(field: &str, values: impl IntoIterator<Item = V>) -> Query
+where V: Into<json::Value> {
+    Query::Bool(BoolQuery {
+        should: values.into_iter().map(|value| term(field, value)).collect(),
+        ..Default::default()
+    })
+}
+
+pub fn match_(field: &str, query: &str) -> Query {
+    Query::Match {
+        field: field.into(),
+        query: query.into(),
+    }
+}
+
+pub fn range(field: &str) -> RangeQueryBuilder {
+    RangeQueryBuilder {
+        field: field.into(),
+        ..Default::default()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct BoolQueryBuilder {
+    inner: BoolQuery,
+}
+
+impl BoolQueryBuilder {
+    pub fn must(mut self, query: Query) -> Self {
+        self.inner.must.push(query);
+        self
+    }
+
+    pub fn should(mut self, query: Query) -> Self {
+        self.inner.should.push(query);
+        self
+    }
+
+    pub fn must_not(mut self, query: Query) -> Self {
+        self.inner.must_not.push(query);
+        self
+    }
+
+    pub fn filter(mut self, query: Query) -> Self {
+        self.inner.filter.push(query);
+        self
+    }
+
+    pub fn build(self) -> Query {
+        Query::Bool(self.inner)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RangeQueryBuilder {
+    field: String,
+    gt: Option<json::Value>,
+    gte: Option<json::Value>,
+    lt: Option<json::Value>,
+    lte: Option<json::Value>,
+}
+
+impl RangeQueryBuilder {
+    pub fn gt<V>(mut self, value: V) -> Self
+    where V: Into<json::Value> {
+        self.gt = Some(value.into());
+        self
+    }
+
+    pub fn gte<V>(mut self, value: V) -> Self
+    where V: Into<json::Value> {
+        self.gte = Some(value.into());
+        self
+    }
+
+    pub fn lt<V>(mut self, value: V) -> Self
+    where V: Into<json::Value> {
+        self.lt = Some(value.into());
+        self
+    }
+
+    pub fn lte<V>(mut self, value: V) -> Self
+    where V: Into<json::Value> {
+        self.lte = Some(value.into());
+        self
+    }
+
+    pub fn build(self) -> Query {
+        Query::Range {
+            field: self.field,
+            gt: self.gt,
+            gte: self.gte,
+            lt: self.lt,
+            lte: self.lte,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_serializes_to_dsl() {
+        let query = Query::bool()
+            .must(term("status", "active"))
+            .should(match_("title", "zen"))
+            .filter(range("year").gte(1900).lt(2000).build())
+            .build();
+
+        assert_eq!(
+            json::json!({
+                "bool": {
+                    "must": [{ "term": { "field": "status", "value": "active" } }],
+                    "should": [{ "match": { "field": "title", "query": "zen" } }],
+                    "filter": [{ "range": { "field": "year", "gte": 1900, "lt": 2000 } }]
+                }
+            }),
+            json::to_value(query).unwrap()
+        );
+    }
+}
@@ -0,0 +1,203 @@
+//! Translation of OpenSearch and Elasticsearch query clauses into [`Query`], so that queries
+//! written for either can be sent to an index unchanged:
+//!
+//! ```json
+//! {
+//!   "bool": {
+//!     "must": { "match": { "title": "zen art" } },
+//!     "filter": [{ "term": { "status": "active" } }, { "range": { "year": { "gte": 1970 } } }]
+//!   }
+//! }
+//! ```
+//!
+//! Only `match_all`, `match`, `term`, `terms`, `range` and `bool` are supported, without their
+//! tuning parameters such as `boost`, `operator` or `minimum_should_match`. Anything else is an
+//! invalid request rather than silently matching differently than it would have in OpenSearch.
+
+use super::{builder, invalid, BoolQuery, Query};
+use crate::json;
+use crate::service::ServiceError;
+
+type Object = json::Map<String, json::Value>;
+
+/// The [`Query`] equivalent to the clause `value`.
+pub fn translate(value: &json::Value) -> Result<Query, ServiceError> {
+    let (kind, body) = single_entry(value, "query clause")?;
+
+    match kind.as_str() {
+        "match_all" => {
+            expect_params(kind, object(body, kind)?, &[])?;
+            Ok(Query::MatchAll {})
+        }
+        "match" => {
+            let (field, params) = single_entry(body, kind)?;
+            let query = match params {
+                json::Value::Object(params) => {
+                    expect_params(kind, params, &["query"])?;
+                    params.get("query").ok_or_else(|| missing(kind, "query"))?
+                }
+                value => value,
+            };
+            Ok(builder::match_(field, &match_text(kind, query)?))
+        }
+        "term" => {
+            let (field, params) = single_entry(body, kind)?;
+            let value = match params {
+                json::Value::Object(params) => {
+                    expect_params(kind, params, &["value"])?;
+                    params.get("value").ok_or_else(|| missing(kind, "value"))?
+                }
+                value => value,
+            };
+            Ok(builder::term(field, value.clone()))
+        }
+        "terms" => {
+            let (field, values) = single_entry(body, kind)?;
+            let values = values.as_array().ok_or_else(|| {
+                invalid(format!(
+                    "Expected an array of values for [terms] on [{}]",
+                    field
+                ))
+            })?;
+            Ok(builder::any_of(field, values.iter().cloned()))
+        }
+        "range" => {
+            let (field, params) = single_entry(body, kind)?;
+            let params = object(params, kind)?;
+            expect_params(kind, params, &["gt", "gte", "lt", "lte"])?;
+            Ok(Query::Range {
+                field: field.clone(),
+                gt: params.get("gt").cloned(),
+                gte: params.get("gte").cloned(),
+                lt: params.get("lt").cloned(),
+                lte: params.get("lte").cloned(),
+            })
+        }
+        "bool" => {
+            let params = object(body, kind)?;
+            expect_params(kind, params, &["must", "should", "must_not", "filter"])?;
+            Ok(Query::Bool(BoolQuery {
+                must: clauses(params.get("must"))?,
+                should: clauses(params.get("should"))?,
+                must_not: clauses(params.get("must_not"))?,
+                filter: clauses(params.get("filter"))?,
+            }))
+        }
+        _ => Err(invalid(format!("Unsupported query clause [{}]", kind))),
+    }
+}
+
+/// The clauses of a `bool` occurrence, which may be a single clause or an array of them.
+fn clauses(value: Option<&json::Value>) -> Result<Vec<Query>, ServiceError> {
+    match value {
+        None => Ok(vec![]),
+        Some(json::Value::Array(values)) => values.iter().map(translate).collect(),
+        Some(value) => Ok(vec![translate(value)?]),
+    }
+}
+
+/// The only key of `value` and its value, as clauses and their fields are written.
+fn single_entry<'a>(
+    value: &'a json::Value,
+    context: &str,
+) -> Result<(&'a String, &'a json::Value), ServiceError> {
+    let object = object(value, context)?;
+    match (object.len(), object.iter().next()) {
+        (1, Some(entry)) => Ok(entry),
+        _ => Err(invalid(format!(
+            "Expected exactly one key in [{}], got {}",
+            context,
+            object.len()
+        ))),
+    }
+}
+
+fn object<'a>(value: &'a json::Value, context: &str) -> Result<&'a Object, ServiceError> {
+    value
+        .as_object()
+        .ok_or_else(|| invalid(format!("Expected an object for [{}]", context)))
+}
+
+/// Rejects parameters of `kind` other than `supported`.
+fn expect_params(kind: &str, params: &Object, supported: &[&str]) -> Result<(), ServiceError> {
+    match params.keys().find(|key| !supported.contains(&key.as_str())) {
+        Some(key) => Err(invalid(format!(
+            "Unsupported parameter [{}] of [{}]",
+            key, kind
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// The text of a `match` query, which OpenSearch also accepts as a number or boolean.
+fn match_text(kind: &str, value: &json::Value) -> Result<String, ServiceError> {
+    match value {
+        json::Value::String(text) => Ok(text.clone()),
+        json::Value::Number(_) | json::Value::Bool(_) => Ok(value.to_string()),
+        _ => Err(invalid(format!(
+            "Expected text for [{}], got {}",
+            kind, value
+        ))),
+    }
+}
+
+fn missing(kind: &str, param: &str) -> ServiceError {
+    invalid(format!("Missing parameter [{}] of [{}]", param, kind))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::{any_of, match_, range, term};
+
+    #[test]
+    fn translate_clauses() {
+        let query = translate(&json::json!({
+            "bool": {
+                "must": { "match": { "title": "zen art" } },
+                "should": [
+                    { "match": { "author": { "query": "pirsig" } } },
+                    { "terms": { "isbn": ["a", "b"] } }
+                ],
+                "must_not": { "term": { "isbn": { "value": "c" } } },
+                "filter": [
+                    { "term": { "year": 1974 } },
+                    { "range": { "year": { "gte": 1970, "lt": 1980 } } },
+                    { "match_all": {} }
+                ]
+            }
+        }))
+        .unwrap();
+
+        assert_eq!(
+            Query::bool()
+                .must(match_("title", "zen art"))
+                .should(match_("author", "pirsig"))
+                .should(any_of("isbn", ["a", "b"]))
+                .must_not(term("isbn", "c"))
+                .filter(term("year", 1974))
+                .filter(range("year").gte(1970).lt(1980).build())
+                .filter(Query::MatchAll {})
+                .build(),
+            query
+        );
+    }
+
+    #[test]
+    fn translate_rejects_unsupported_clauses() {
+        let rejected = [
+            json::json!({ "fuzzy": { "title": "zen" } }),
+            json::json!({ "match": { "title": { "query": "zen", "operator": "and" } } }),
+            json::json!({ "term": { "isbn": "a", "year": 1974 } }),
+            json::json!({ "range": { "date_added": { "gte": "now-1d", "time_zone": "UTC" } } }),
+            json::json!({ "bool": { "must": [], "minimum_should_match": 1 } }),
+            json::json!({ "match": {}, "term": {} }),
+            json::json!("zen"),
+        ];
+
+        for query in rejected {
+            let err = translate(&query).unwrap_err();
+            assert_eq!(400, err.status(), "{} should be rejected", query);
+        }
+    }
+}
@@ -0,0 +1,1374 @@
+//! JSON query DSL compiled into tantivy queries.
+//!
+//! Queries are either a plain query string in tantivy syntax or a structured query:
+//!
+//! ```json
+//! {
+//!   "bool": {
+//!     "must": [{ "term": { "field": "status", "value": "active" } }],
+//!     "should": [{ "match": { "field": "title", "query": "zen art" } }]
+//!   }
+//! }
+//! ```
+
+pub mod builder;
+pub mod es;
+pub mod signals;
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Bound;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono_tz::Tz;
+use serde::{Deserialize, Deserializer, Serialize};
+use tantivy::query::{
+    AllQuery, BooleanQuery, BoostQuery, Explanation, Occur, Query as TantivyQuery, RangeQuery,
+    Scorer, TermQuery, Weight,
+};
+use tantivy::schema::{Facet, Field, FieldType, IndexRecordOption, Schema, Type};
+use tantivy::{
+    DateTime, DocId, DocSet, Index, LeasedItem, Score, Searcher, SegmentReader, Term, TERMINATED,
+};
+
+pub use self::builder::{any_of, match_, query_string, range, term};
+use crate::index::IndexExt;
+use crate::schema::{is_ip_field, IndexSettings, QueryLimits};
+use crate::service::ServiceError;
+use crate::{json, tokenizer, util};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Query {
+    /// Query string in tantivy query syntax, searching all indexed text fields by default.
+    QueryString(String),
+
+    /// Matches documents containing the exact, un-analyzed term.
+    Term {
+        field: String,
+        value: json::Value,
+    },
+
+    /// Analyzes `query` with the field's tokenizer and matches any of the resulting terms.
+    Match {
+        field: String,
+        query: String,
+    },
+
+    Range {
+        field: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        gt: Option<json::Value>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        gte: Option<json::Value>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        lt: Option<json::Value>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        lte: Option<json::Value>,
+    },
+
+    Bool(BoolQuery),
+
+    MatchAll {},
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct BoolQuery {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub must: Vec<Query>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub should: Vec<Query>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub must_not: Vec<Query>,
+
+    /// Like `must` but does not contribute to the score.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub filter: Vec<Query>,
+}
+
+impl Default for Query {
+    fn default() -> Self {
+        Query::MatchAll {}
+    }
+}
+
+impl From<&str> for Query {
+    fn from(query: &str) -> Self {
+        Query::QueryString(query.into())
+    }
+}
+
+impl From<String> for Query {
+    fn from(query: String) -> Self {
+        Query::QueryString(query)
+    }
+}
+
+/// Deserializes either a bare query string or a structured [`Query`].
+pub fn string_or_dsl<'de, D>(deserializer: D) -> Result<Query, D::Error>
+where D: Deserializer<'de> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrDsl {
+        String(String),
+        Dsl(Query),
+    }
+
+    Ok(match StringOrDsl::deserialize(deserializer)? {
+        StringOrDsl::String(query) => Query::QueryString(query),
+        StringOrDsl::Dsl(query) => query,
+    })
+}
+
+fn invalid(message: String) -> ServiceError {
+    ServiceError::InvalidRequest(message)
+}
+
+fn lookup_field(schema: &Schema, name: &str) -> Result<Field, ServiceError> {
+    schema
+        .get_field(name)
+        .ok_or_else(|| invalid(format!("Field [{}] does not exist", name)))
+}
+
+/// Resolves a dotted name such as `metadata.author.name` to the JSON field it points into.
+fn lookup_json_path(schema: &Schema, name: &str) -> Option<Field> {
+    name.match_indices('.')
+        .map(|(idx, _)| &name[..idx])
+        .filter_map(|prefix| schema.get_field(prefix))
+        .find(|field| {
+            matches!(
+                schema.get_field_entry(*field).field_type(),
+                FieldType::JsonObject(_)
+            )
+        })
+}
+
+/// Compiles a term or match query against a path inside a JSON field. Terms in JSON fields
+/// carry their path and value type, so the query parser builds them.
+fn compile_json_path(
+    index: &Index,
+    settings: &IndexSettings,
+    field: Field,
+    path: &str,
+    values: Vec<String>,
+) -> Result<Box<dyn TantivyQuery>, ServiceError> {
+    let parser = index.query_parser(settings);
+
+    let clauses = values
+        .into_iter()
+        .map(|value| {
+            let query = parser
+                .parse_query(&format!("{}:\"{}\"", path, value.replace('"', "")))
+                .map_err(|err| invalid(err.to_string()))?;
+            Ok((Occur::Should, query))
+        })
+        .collect::<Result<Vec<_>, ServiceError>>()?;
+
+    let query: Box<dyn TantivyQuery> = Box::new(BooleanQuery::new(clauses));
+
+    Ok(
+        match settings.field_boost(index.schema().get_field_name(field)) {
+            Some(boost) => Box::new(BoostQuery::new(query, boost)),
+            None => query,
+        },
+    )
+}
+
+/// Replaces the field names of `field:value` clauses in a query string with `resolve(name)`,
+/// leaving quoted phrases and values untouched.
+fn resolve_query_string(query: &str, resolve: &dyn Fn(&str) -> String) -> String {
+    let is_name_char = |c: char| c.is_alphanumeric() || matches!(c, '_' | '-' | '.');
+
+    let mut resolved = String::with_capacity(query.len());
+    let mut chars = query.char_indices().peekable();
+    let mut in_phrase = false;
+    let mut at_clause_start = true;
+
+    while let Some((start, c)) = chars.next() {
+        if in_phrase {
+            resolved.push(c);
+            match c {
+                '\\' => resolved.extend(chars.next().map(|(_, escaped)| escaped)),
+                '"' => in_phrase = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        if at_clause_start && is_name_char(c) && c != '-' {
+            let mut end = start + c.len_utf8();
+            while let Some(&(idx, next)) = chars.peek() {
+                if !is_name_char(next) {
+                    break;
+                }
+                end = idx + next.len_utf8();
+                chars.next();
+            }
+
+            let name = &query[start..end];
+            match chars.peek() {
+                Some((_, ':')) => resolved.push_str(&resolve(name)),
+                _ => resolved.push_str(name),
+            }
+            at_clause_start = false;
+            continue;
+        }
+
+        resolved.push(c);
+        in_phrase = c == '"';
+        at_clause_start = c.is_whitespace() || matches!(c, '(' | '+' | '-');
+    }
+
+    resolved
+}
+
+fn parse_date(
+    field_name: &str,
+    value: &json::Value,
+    time_zone: Tz,
+) -> Result<DateTime, ServiceError> {
+    value
+        .as_str()
+        .and_then(|value| util::parse_date(value, time_zone))
+        .map(|date| DateTime::from_unix_timestamp(date.timestamp()))
+        .ok_or_else(|| invalid(format!("Expected RFC3339 date for field [{}]", field_name)))
+}
+
+/// Converts a JSON value into a term for `field` according to the field's type. Dates without
+/// an offset are read in `time_zone`.
+fn value_to_term(
+    schema: &Schema,
+    field: Field,
+    value: &json::Value,
+    time_zone: Tz,
+) -> Result<Term, ServiceError> {
+    let field_name = schema.get_field_name(field);
+    let mismatch = |expected: &str| {
+        invalid(format!(
+            "Expected {} value for field [{}], found {}",
+            expected, field_name, value
+        ))
+    };
+
+    let entry = schema.get_field_entry(field);
+
+    if is_ip_field(entry) {
+        return value
+            .as_str()
+            .and_then(tokenizer::parse_ip)
+            .map(|addr| Term::from_field_text(field, &tokenizer::ip_term(addr)))
+            .ok_or_else(|| mismatch("IP address"));
+    }
+
+    match entry.field_type() {
+        FieldType::Str(_) => value
+            .as_str()
+            .map(|value| Term::from_field_text(field, value))
+            .ok_or_else(|| mismatch("string")),
+        FieldType::U64(_) => value
+            .as_u64()
+            .map(|value| Term::from_field_u64(field, value))
+            .ok_or_else(|| mismatch("u64")),
+        FieldType::I64(_) => value
+            .as_i64()
+            .map(|value| Term::from_field_i64(field, value))
+            .ok_or_else(|| mismatch("i64")),
+        FieldType::F64(_) => value
+            .as_f64()
+            .map(|value| Term::from_field_f64(field, value))
+            .ok_or_else(|| mismatch("f64")),
+        FieldType::Date(_) => Ok(Term::from_field_date(
+            field,
+            parse_date(field_name, value, time_zone)?,
+        )),
+        // A facet term also matches every facet below it.
+        FieldType::Facet(_) => value
+            .as_str()
+            .and_then(|path| Facet::from_text(path).ok())
+            .map(|facet| Term::from_facet(field, &facet))
+            .ok_or_else(|| mismatch("facet path")),
+        _ => Err(invalid(format!(
+            "Field [{}] does not support term queries",
+            field_name
+        ))),
+    }
+}
+
+fn bound(
+    schema: &Schema,
+    field: Field,
+    exclusive: &Option<json::Value>,
+    inclusive: &Option<json::Value>,
+    time_zone: Tz,
+) -> Result<Bound<Term>, ServiceError> {
+    Ok(match (exclusive, inclusive) {
+        (Some(_), Some(_)) => {
+            return Err(invalid(String::from(
+                "Range bounds cannot be both inclusive and exclusive",
+            )))
+        }
+        (Some(value), None) => Bound::Excluded(value_to_term(schema, field, value, time_zone)?),
+        (None, Some(value)) => Bound::Included(value_to_term(schema, field, value, time_zone)?),
+        (None, None) => Bound::Unbounded,
+    })
+}
+
+/// Leaf clauses of a query counted so far, checked against `limits` along with the nesting of its
+/// boolean queries as they're walked, so that an oversized query is rejected without walking all
+/// of it.
+struct QueryShape {
+    limits: QueryLimits,
+    clauses: usize,
+}
+
+impl QueryShape {
+    fn add_clause(&mut self) -> Result<(), ServiceError> {
+        self.clauses += 1;
+        if self.clauses > self.limits.max_clauses() {
+            return Err(invalid(format!(
+                "Query has more than {} clauses",
+                self.limits.max_clauses()
+            )));
+        }
+        Ok(())
+    }
+
+    fn enter_bool(&self, depth: usize) -> Result<(), ServiceError> {
+        if depth > self.limits.max_depth() {
+            return Err(invalid(format!(
+                "Query nests boolean queries more than {} deep",
+                self.limits.max_depth()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Counts the clauses of a parsed query string at `depth`.
+    fn add_parsed(&mut self, query: &dyn TantivyQuery, depth: usize) -> Result<(), ServiceError> {
+        match query.downcast_ref::<BooleanQuery>() {
+            Some(bool_query) => {
+                self.enter_bool(depth + 1)?;
+                for (_, clause) in bool_query.clauses() {
+                    self.add_parsed(clause.as_ref(), depth + 1)?;
+                }
+                Ok(())
+            }
+            None => self.add_clause(),
+        }
+    }
+}
+
+/// The first word of a query string with a `*` in it, outside of quotes and ranges. The query
+/// parser reads `*` on its own as matching every document, so `zen*` would match everything rather
+/// than the prefix its author meant.
+fn wildcard_term(query: &str) -> Option<&str> {
+    let mut quoted = false;
+    let mut ranged = false;
+
+    for word in query.split_whitespace() {
+        let starts_quoted = quoted;
+        quoted ^= word.matches('"').count() % 2 == 1;
+        let starts_ranged = ranged || word.contains(['[', '{']);
+        ranged = starts_ranged && !word.contains([']', '}']);
+
+        if starts_quoted || starts_ranged {
+            continue;
+        }
+        let term = word.rsplit(':').next().unwrap_or(word);
+        let term = term.trim_start_matches(['+', '-']);
+        if !term.starts_with('"') && term != "*" && term.contains('*') && !term.contains("\\*") {
+            return Some(word);
+        }
+    }
+
+    None
+}
+
+impl Query {
+    pub fn bool() -> builder::BoolQueryBuilder {
+        builder::BoolQueryBuilder::default()
+    }
+
+    /// Compiles the query into a tantivy query for `index`, applying the field aliases, boosts
+    /// and query limits in `settings`.
+    pub fn compile(
+        &self,
+        index: &Index,
+        settings: &IndexSettings,
+    ) -> Result<Box<dyn TantivyQuery>, ServiceError> {
+        let mut shape = QueryShape {
+            limits: settings.query_limits,
+            clauses: 0,
+        };
+
+        if settings.field_aliases.is_empty() {
+            self.measure(index, settings, &mut shape, 0)?;
+            return self.compile_resolved(index, settings);
+        }
+        let query = self.resolve_aliases(&index.schema(), settings);
+        query.measure(index, settings, &mut shape, 0)?;
+        query.compile_resolved(index, settings)
+    }
+
+    /// Adds the clauses of the query, nested `depth` boolean queries deep, to `shape`.
+    fn measure(
+        &self,
+        index: &Index,
+        settings: &IndexSettings,
+        shape: &mut QueryShape,
+        depth: usize,
+    ) -> Result<(), ServiceError> {
+        match self {
+            Query::QueryString(query) => {
+                if let Some(term) = wildcard_term(query) {
+                    return Err(invalid(format!(
+                        "Wildcard term [{}] isn't supported, since it would match every document",
+                        term
+                    )));
+                }
+                let parsed = index
+                    .query_parser(settings)
+                    .parse_query(query)
+                    .map_err(|err| invalid(err.to_string()))?;
+                shape.add_parsed(parsed.as_ref(), depth)
+            }
+            Query::Bool(bool_query) => {
+                shape.enter_bool(depth + 1)?;
+                for query in bool_query
+                    .must
+                    .iter()
+                    .chain(&bool_query.should)
+                    .chain(&bool_query.must_not)
+                    .chain(&bool_query.filter)
+                {
+                    query.measure(index, settings, shape, depth + 1)?;
+                }
+                Ok(())
+            }
+            Query::Term { .. } | Query::Match { .. } | Query::Range { .. } | Query::MatchAll {} => {
+                shape.add_clause()
+            }
+        }
+    }
+
+    /// The query with every field alias replaced by the field it stands for.
+    pub fn resolve_aliases(&self, schema: &Schema, settings: &IndexSettings) -> Query {
+        let resolve = |field: &str| settings.resolve_field(schema, field).into_owned();
+
+        match self {
+            Query::QueryString(query) => {
+                Query::QueryString(resolve_query_string(query, &|field| resolve(field)))
+            }
+            Query::Term { field, value } => Query::Term {
+                field: resolve(field),
+                value: value.clone(),
+            },
+            Query::Match { field, query } => Query::Match {
+                field: resolve(field),
+                query: query.clone(),
+            },
+            Query::Range {
+                field,
+                gt,
+                gte,
+                lt,
+                lte,
+            } => Query::Range {
+                field: resolve(field),
+                gt: gt.clone(),
+                gte: gte.clone(),
+                lt: lt.clone(),
+                lte: lte.clone(),
+            },
+            Query::Bool(bool_query) => {
+                let resolve_all = |queries: &Vec<Query>| {
+                    queries
+                        .iter()
+                        .map(|query| query.resolve_aliases(schema, settings))
+                        .collect()
+                };
+                Query::Bool(BoolQuery {
+                    must: resolve_all(&bool_query.must),
+                    should: resolve_all(&bool_query.should),
+                    must_not: resolve_all(&bool_query.must_not),
+                    filter: resolve_all(&bool_query.filter),
+                })
+            }
+            Query::MatchAll {} => Query::MatchAll {},
+        }
+    }
+
+    /// Names of the fields the query searches by name, including the `field:` clauses of query
+    /// strings but not the default fields they search otherwise.
+    pub fn fields(&self) -> Vec<String> {
+        match self {
+            Query::QueryString(query) => {
+                let fields = RefCell::new(vec![]);
+                resolve_query_string(query, &|field| {
+                    fields.borrow_mut().push(String::from(field));
+                    String::from(field)
+                });
+                fields.into_inner()
+            }
+            Query::Term { field, .. } | Query::Match { field, .. } | Query::Range { field, .. } => {
+                vec![field.clone()]
+            }
+            Query::Bool(bool_query) => bool_query
+                .must
+                .iter()
+                .chain(&bool_query.should)
+                .chain(&bool_query.must_not)
+                .chain(&bool_query.filter)
+                .flat_map(Query::fields)
+                .collect(),
+            Query::MatchAll {} => vec![],
+        }
+    }
+
+    fn compile_resolved(
+        &self,
+        index: &Index,
+        settings: &IndexSettings,
+    ) -> Result<Box<dyn TantivyQuery>, ServiceError> {
+        let schema = index.schema();
+
+        match self {
+            Query::QueryString(query) => index
+                .query_parser(settings)
+                .parse_query(query)
+                .map_err(|err| invalid(err.to_string())),
+            Query::Term { field: path, value } if schema.get_field(path).is_none() => {
+                let field = lookup_json_path(&schema, path)
+                    .ok_or_else(|| invalid(format!("Field [{}] does not exist", path)))?;
+                let value = match value {
+                    json::Value::String(value) => value.clone(),
+                    value => value.to_string(),
+                };
+                compile_json_path(index, settings, field, path, vec![value])
+            }
+            Query::Term { field, value } => {
+                let field = lookup_field(&schema, field)?;
+
+                // A CIDR block on an ip field matches every address in the block.
+                if let Some((first, last)) = value
+                    .as_str()
+                    .filter(|_| is_ip_field(schema.get_field_entry(field)))
+                    .and_then(tokenizer::parse_cidr)
+                {
+                    let bound = |addr| Term::from_field_text(field, &tokenizer::ip_term(addr));
+                    return Ok(Box::new(RangeQuery::new_term_bounds(
+                        field,
+                        Type::Str,
+                        &Bound::Included(bound(first)),
+                        &Bound::Included(bound(last)),
+                    )));
+                }
+
+                let term = value_to_term(&schema, field, value, settings.time_zone())?;
+                Ok(Box::new(TermQuery::new(term, IndexRecordOption::WithFreqs)))
+            }
+            Query::Match { field: path, query } if schema.get_field(path).is_none() => {
+                let field = lookup_json_path(&schema, path)
+                    .ok_or_else(|| invalid(format!("Field [{}] does not exist", path)))?;
+                let tokenizer = index
+                    .tokenizer_for_field(field)
+                    .map_err(|err| invalid(err.to_string()))?;
+
+                let mut tokens = vec![];
+                tokenizer
+                    .token_stream(query)
+                    .process(&mut |token| tokens.push(token.text.clone()));
+
+                compile_json_path(index, settings, field, path, tokens)
+            }
+            Query::Match { field, query } => {
+                let field = lookup_field(&schema, field)?;
+                let tokenizer = index
+                    .tokenizer_for_field(field)
+                    .map_err(|err| invalid(err.to_string()))?;
+
+                let mut terms = vec![];
+                tokenizer
+                    .token_stream(query)
+                    .process(&mut |token| terms.push(Term::from_field_text(field, &token.text)));
+
+                let clauses = terms
+                    .into_iter()
+                    .map(|term| {
+                        let query: Box<dyn TantivyQuery> =
+                            Box::new(TermQuery::new(term, IndexRecordOption::WithFreqs));
+                        (Occur::Should, query)
+                    })
+                    .collect();
+
+                let query: Box<dyn TantivyQuery> = Box::new(BooleanQuery::new(clauses));
+
+                Ok(match settings.field_boost(schema.get_field_name(field)) {
+                    Some(boost) => Box::new(BoostQuery::new(query, boost)),
+                    None => query,
+                })
+            }
+            Query::Range {
+                field,
+                gt,
+                gte,
+                lt,
+                lte,
+            } => {
+                let field = lookup_field(&schema, field)?;
+                let value_type = schema.get_field_entry(field).field_type().value_type();
+
+                if !matches!(
+                    value_type,
+                    Type::Str | Type::U64 | Type::I64 | Type::F64 | Type::Date
+                ) {
+                    return Err(invalid(format!(
+                        "Field [{}] does not support range queries",
+                        schema.get_field_name(field)
+                    )));
+                }
+
+                let lower = bound(&schema, field, gt, gte, settings.time_zone())?;
+                let upper = bound(&schema, field, lt, lte, settings.time_zone())?;
+
+                Ok(Box::new(RangeQuery::new_term_bounds(
+                    field, value_type, &lower, &upper,
+                )))
+            }
+            Query::Bool(bool_query) => {
+                let mut clauses: Vec<(Occur, Box<dyn TantivyQuery>)> = vec![];
+
+                for query in &bool_query.must {
+                    clauses.push((Occur::Must, query.compile_resolved(index, settings)?));
+                }
+                for query in &bool_query.should {
+                    clauses.push((Occur::Should, query.compile_resolved(index, settings)?));
+                }
+                for query in &bool_query.must_not {
+                    clauses.push((Occur::MustNot, query.compile_resolved(index, settings)?));
+                }
+                for query in &bool_query.filter {
+                    clauses.push((
+                        Occur::Must,
+                        Box::new(BoostQuery::new(
+                            query.compile_resolved(index, settings)?,
+                            0.0,
+                        )),
+                    ));
+                }
+
+                // A query made up of only exclusions matches everything else.
+                if clauses.iter().all(|(occur, _)| *occur == Occur::MustNot) {
+                    clauses.push((Occur::Must, Box::new(AllQuery)));
+                }
+
+                Ok(Box::new(BooleanQuery::new(clauses)))
+            }
+            Query::MatchAll {} => Ok(Box::new(AllQuery)),
+        }
+    }
+}
+
+/// Scores `inner` with BM25 statistics from `stats_searcher` instead of the searcher running the
+/// query. Running a query over part of an index, such as a partition, with statistics from the
+/// whole index scores every document the same as running it over the whole index.
+pub struct GlobalStatsQuery {
+    inner: Box<dyn TantivyQuery>,
+    stats_searcher: Arc<LeasedItem<Searcher>>,
+}
+
+impl GlobalStatsQuery {
+    pub fn new(inner: Box<dyn TantivyQuery>, stats_searcher: Arc<LeasedItem<Searcher>>) -> Self {
+        GlobalStatsQuery {
+            inner,
+            stats_searcher,
+        }
+    }
+}
+
+impl Clone for GlobalStatsQuery {
+    fn clone(&self) -> Self {
+        GlobalStatsQuery {
+            inner: self.inner.box_clone(),
+            stats_searcher: self.stats_searcher.clone(),
+        }
+    }
+}
+
+impl fmt::Debug for GlobalStatsQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GlobalStatsQuery")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl TantivyQuery for GlobalStatsQuery {
+    fn weight(
+        &self,
+        _searcher: &Searcher,
+        scoring_enabled: bool,
+    ) -> tantivy::Result<Box<dyn Weight>> {
+        self.inner.weight(&self.stats_searcher, scoring_enabled)
+    }
+
+    fn query_terms(&self, terms: &mut BTreeMap<Term, bool>) {
+        self.inner.query_terms(terms)
+    }
+}
+
+/// Matches only the first `limit` candidates of `inner` in each segment, in doc id order, so a
+/// query stops examining a segment early. Trades recall for latency: the matches it keeps are
+/// the oldest rather than the best.
+pub struct TerminateAfterQuery {
+    inner: Box<dyn TantivyQuery>,
+    limit: u32,
+}
+
+impl TerminateAfterQuery {
+    pub fn new(inner: Box<dyn TantivyQuery>, limit: u32) -> Self {
+        TerminateAfterQuery { inner, limit }
+    }
+}
+
+impl Clone for TerminateAfterQuery {
+    fn clone(&self) -> Self {
+        TerminateAfterQuery {
+            inner: self.inner.box_clone(),
+            limit: self.limit,
+        }
+    }
+}
+
+impl fmt::Debug for TerminateAfterQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TerminateAfterQuery")
+            .field("inner", &self.inner)
+            .field("limit", &self.limit)
+            .finish()
+    }
+}
+
+impl TantivyQuery for TerminateAfterQuery {
+    fn weight(
+        &self,
+        searcher: &Searcher,
+        scoring_enabled: bool,
+    ) -> tantivy::Result<Box<dyn Weight>> {
+        Ok(Box::new(TerminateAfterWeight {
+            inner: self.inner.weight(searcher, scoring_enabled)?,
+            limit: self.limit,
+        }))
+    }
+
+    fn query_terms(&self, terms: &mut BTreeMap<Term, bool>) {
+        self.inner.query_terms(terms)
+    }
+}
+
+struct TerminateAfterWeight {
+    inner: Box<dyn Weight>,
+    limit: u32,
+}
+
+impl Weight for TerminateAfterWeight {
+    fn scorer(&self, reader: &SegmentReader, boost: Score) -> tantivy::Result<Box<dyn Scorer>> {
+        Ok(Box::new(TerminateAfterScorer {
+            inner: self.inner.scorer(reader, boost)?,
+            remaining: self.limit,
+        }))
+    }
+
+    fn explain(&self, reader: &SegmentReader, doc: DocId) -> tantivy::Result<Explanation> {
+        self.inner.explain(reader, doc)
+    }
+}
+
+struct TerminateAfterScorer {
+    inner: Box<dyn Scorer>,
+    /// Candidates left to examine, including the current one.
+    remaining: u32,
+}
+
+impl DocSet for TerminateAfterScorer {
+    fn advance(&mut self) -> DocId {
+        self.remaining = self.remaining.saturating_sub(1);
+        if self.remaining == 0 {
+            return TERMINATED;
+        }
+        self.inner.advance()
+    }
+
+    fn doc(&self) -> DocId {
+        if self.remaining == 0 {
+            return TERMINATED;
+        }
+        self.inner.doc()
+    }
+
+    fn size_hint(&self) -> u32 {
+        self.inner.size_hint().min(self.remaining)
+    }
+}
+
+impl Scorer for TerminateAfterScorer {
+    fn score(&mut self) -> Score {
+        self.inner.score()
+    }
+}
+
+/// The time a query's collection stops, shared by every part of the query.
+#[derive(Debug)]
+pub struct Deadline {
+    at: Instant,
+    expired: AtomicBool,
+}
+
+impl Deadline {
+    pub fn after(budget: Duration) -> Arc<Self> {
+        Arc::new(Deadline {
+            at: Instant::now() + budget,
+            expired: AtomicBool::new(false),
+        })
+    }
+
+    /// Whether the deadline has passed, remembering once it has.
+    fn check(&self) -> bool {
+        if !self.expired() && Instant::now() >= self.at {
+            self.expired.store(true, Ordering::Relaxed);
+        }
+        self.expired()
+    }
+
+    /// Whether a query stopped collecting at the deadline.
+    pub fn expired(&self) -> bool {
+        self.expired.load(Ordering::Relaxed)
+    }
+}
+
+/// Candidates a [`TimeBudgetQuery`] examines between checks of its deadline, since reading the
+/// clock for every candidate would slow down the queries it's meant to bound.
+const DEADLINE_CHECK_INTERVAL: u32 = 1024;
+
+/// Matches the candidates of `inner` until `deadline`, then stops, so that collectors finish with
+/// the matches found so far.
+pub struct TimeBudgetQuery {
+    inner: Box<dyn TantivyQuery>,
+    deadline: Arc<Deadline>,
+}
+
+impl TimeBudgetQuery {
+    pub fn new(inner: Box<dyn TantivyQuery>, deadline: Arc<Deadline>) -> Self {
+        TimeBudgetQuery { inner, deadline }
+    }
+}
+
+impl Clone for TimeBudgetQuery {
+    fn clone(&self) -> Self {
+        TimeBudgetQuery {
+            inner: self.inner.box_clone(),
+            deadline: self.deadline.clone(),
+        }
+    }
+}
+
+impl fmt::Debug for TimeBudgetQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TimeBudgetQuery")
+            .field("inner", &self.inner)
+            .field("deadline", &self.deadline)
+            .finish()
+    }
+}
+
+impl TantivyQuery for TimeBudgetQuery {
+    fn weight(
+        &self,
+        searcher: &Searcher,
+        scoring_enabled: bool,
+    ) -> tantivy::Result<Box<dyn Weight>> {
+        Ok(Box::new(TimeBudgetWeight {
+            inner: self.inner.weight(searcher, scoring_enabled)?,
+            deadline: self.deadline.clone(),
+        }))
+    }
+
+    fn query_terms(&self, terms: &mut BTreeMap<Term, bool>) {
+        self.inner.query_terms(terms)
+    }
+}
+
+struct TimeBudgetWeight {
+    inner: Box<dyn Weight>,
+    deadline: Arc<Deadline>,
+}
+
+impl Weight for TimeBudgetWeight {
+    fn scorer(&self, reader: &SegmentReader, boost: Score) -> tantivy::Result<Box<dyn Scorer>> {
+        Ok(Box::new(TimeBudgetScorer {
+            inner: self.inner.scorer(reader, boost)?,
+            stopped: self.deadline.check(),
+            deadline: self.deadline.clone(),
+            unchecked: 0,
+        }))
+    }
+
+    fn explain(&self, reader: &SegmentReader, doc: DocId) -> tantivy::Result<Explanation> {
+        self.inner.explain(reader, doc)
+    }
+}
+
+struct TimeBudgetScorer {
+    inner: Box<dyn Scorer>,
+    deadline: Arc<Deadline>,
+    /// Whether the deadline passed, ending the scorer's candidates.
+    stopped: bool,
+    /// Candidates examined since the deadline was last checked.
+    unchecked: u32,
+}
+
+impl DocSet for TimeBudgetScorer {
+    fn advance(&mut self) -> DocId {
+        if !self.stopped {
+            self.unchecked += 1;
+            if self.unchecked >= DEADLINE_CHECK_INTERVAL {
+                self.unchecked = 0;
+                self.stopped = self.deadline.check();
+            }
+        }
+        if self.stopped {
+            return TERMINATED;
+        }
+        self.inner.advance()
+    }
+
+    fn doc(&self) -> DocId {
+        if self.stopped {
+            return TERMINATED;
+        }
+        self.inner.doc()
+    }
+
+    fn size_hint(&self) -> u32 {
+        self.inner.size_hint()
+    }
+}
+
+impl Scorer for TimeBudgetScorer {
+    fn score(&mut self) -> Score {
+        self.inner.score()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tantivy::collector::{Count, TopDocs};
+
+    use super::*;
+    use crate::index::IndexLoader;
+    use crate::test_utils::*;
+
+    async fn count(query: Query) -> Result<usize, ServiceError> {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![
+                    json!({ "title": "zen and the art", "isbn": "a", "year": 1974 }),
+                    json!({ "title": "the art of war", "isbn": "b", "year": 1910 }),
+                    json!({ "title": "moby dick", "isbn": "c", "year": 1851 }),
+                ],
+            )
+            .await;
+
+        let index = ctx.index_loader().load_index("test", None).unwrap();
+        let query = query.compile(&index, &IndexSettings::default())?;
+
+        Ok(index
+            .reader()
+            .unwrap()
+            .searcher()
+            .search(&query, &Count)
+            .unwrap())
+    }
+
+    #[test]
+    fn deserialize_string_or_dsl() {
+        #[derive(Deserialize)]
+        struct Request {
+            #[serde(deserialize_with = "string_or_dsl")]
+            query: Query,
+        }
+
+        let request: Request = json::from_value(json!({ "query": "hello" })).unwrap();
+        assert_eq!(Query::QueryString("hello".into()), request.query);
+
+        let request: Request = json::from_value(json!({
+            "query": { "term": { "field": "isbn", "value": "a" } }
+        }))
+        .unwrap();
+        assert_eq!(term("isbn", "a"), request.query);
+    }
+
+    #[tokio::test]
+    async fn compile_bool_query() {
+        let query = Query::bool()
+            .must(match_("title", "the ART"))
+            .must_not(term("isbn", "b"))
+            .build();
+
+        assert_eq!(1, count(query).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn compile_range_query() {
+        assert_eq!(2, count(range("year").gte(1900).build()).await.unwrap());
+        assert_eq!(
+            1,
+            count(range("year").gt(1851).lt(1974).build())
+                .await
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn compile_must_not_only() {
+        let query = Query::bool().must_not(term("isbn", "a")).build();
+
+        assert_eq!(2, count(query).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn compile_rejects_queries_over_limits() {
+        let ctx = setup();
+        let index = ctx.index_loader().load_index("test", None).unwrap();
+        let settings = IndexSettings {
+            query_limits: QueryLimits {
+                max_clauses: Some(3),
+                max_depth: Some(2),
+                timeout_ms: None,
+            },
+            ..Default::default()
+        };
+        let compile = |query: Query| query.compile(&index, &settings).map(|_| ());
+
+        let flat = Query::bool()
+            .should(term("isbn", "a"))
+            .should(term("isbn", "b"))
+            .filter(range("year").gte(1900).build())
+            .build();
+        assert!(compile(flat.clone()).is_ok());
+
+        let wide = Query::bool()
+            .must(flat.clone())
+            .must(term("isbn", "c"))
+            .build();
+        assert_eq!(400, compile(wide).unwrap_err().status());
+
+        let nested = Query::bool().must(term("isbn", "a")).build();
+        let deep = Query::bool()
+            .must(Query::bool().must(nested).build())
+            .build();
+        assert_eq!(400, compile(deep).unwrap_err().status());
+
+        assert!(compile(Query::from("title:zen title:art")).is_ok());
+        let long = Query::from("title:zen title:and title:the title:art");
+        assert_eq!(400, compile(long).unwrap_err().status());
+
+        assert!(compile(Query::from("year:[* TO 2000] title:\"zen*\"")).is_ok());
+        let wildcard = Query::from("title:zen*");
+        assert_eq!(400, compile(wildcard).unwrap_err().status());
+    }
+
+    #[tokio::test]
+    async fn time_budget_query_stops_at_the_deadline() {
+        let ctx = setup()
+            .with_documents("test", vec![json!({ "title": "zen" })])
+            .await;
+        let index = ctx.index_loader().load_index("test", None).unwrap();
+        let searcher = index.reader().unwrap().searcher();
+        let count = |deadline: &Arc<Deadline>| {
+            let query = TimeBudgetQuery::new(Box::new(AllQuery), deadline.clone());
+            searcher.search(&query, &Count).unwrap()
+        };
+
+        let deadline = Deadline::after(Duration::from_secs(60));
+        assert_eq!(1, count(&deadline));
+        assert!(!deadline.expired());
+
+        let deadline = Deadline::after(Duration::ZERO);
+        assert_eq!(0, count(&deadline));
+        assert!(deadline.expired());
+    }
+
+    #[tokio::test]
+    async fn compile_term_type_mismatch() {
+        let err = count(term("year", "nineteen")).await.unwrap_err();
+
+        assert_eq!(400, err.status());
+    }
+
+    #[tokio::test]
+    async fn compile_with_field_boosts() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![
+                    json!({ "__id": "a", "title": "zen" }),
+                    json!({ "__id": "b", "author": "zen" }),
+                ],
+            )
+            .await;
+
+        let index = ctx.index_loader().load_index("test", None).unwrap();
+        let searcher = index.reader().unwrap().searcher();
+
+        let top_id = |settings: IndexSettings| {
+            let query = query_string("zen").compile(&index, &settings).unwrap();
+            let (_, address) = searcher.search(&query, &TopDocs::with_limit(1)).unwrap()[0];
+            let doc = searcher.doc(address).unwrap();
+            doc.get_first(index.id_field())
+                .and_then(|id| id.as_text())
+                .map(String::from)
+                .unwrap()
+        };
+
+        let boosted = |field: &str| IndexSettings {
+            field_boosts: [(field.into(), 10.0)].into(),
+            ..Default::default()
+        };
+
+        assert_eq!("a", top_id(boosted("title")));
+        assert_eq!("b", top_id(boosted("author")));
+    }
+
+    #[tokio::test]
+    async fn compile_resolves_field_aliases() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![
+                    json!({ "__id": "a", "title": "zen art", "author": "pirsig" }),
+                    json!({ "__id": "b", "title": "motorcycle", "author": "zen" }),
+                ],
+            )
+            .await;
+
+        let index = ctx.index_loader().load_index("test", None).unwrap();
+        let searcher = index.reader().unwrap().searcher();
+        let settings = IndexSettings {
+            field_aliases: [
+                ("name".into(), "title".into()),
+                ("title".into(), "author".into()),
+            ]
+            .into(),
+            ..Default::default()
+        };
+
+        let count = |query: Query| {
+            let query = query.compile(&index, &settings).unwrap();
+            searcher.search(&query, &Count).unwrap()
+        };
+
+        assert_eq!(1, count(query_string("name:zen")));
+        assert_eq!(1, count(query_string("name:\"zen art\" -name:motorcycle")));
+        assert_eq!(
+            1,
+            count(Query::Match {
+                field: "name".into(),
+                query: "zen".into()
+            })
+        );
+        // Fields in the schema are never aliases, whatever the settings say.
+        assert_eq!(1, count(query_string("title:motorcycle")));
+
+        assert_eq!(
+            "title:zen AND (+title:\"name:art\" -title:x) author:a:b",
+            resolve_query_string(
+                "name:zen AND (+name:\"name:art\" -name:x) writer:a:b",
+                &|name| {
+                    match name {
+                        "name" => "title".into(),
+                        "writer" => "author".into(),
+                        _ => name.into(),
+                    }
+                }
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn compile_range_reads_dates_in_time_zone() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![json!({ "title": "late", "date_added": "2022-11-14T03:00:00Z" })],
+            )
+            .await;
+
+        let index = ctx.index_loader().load_index("test", None).unwrap();
+        let searcher = index.reader().unwrap().searcher();
+
+        let count = |time_zone: Option<&str>| {
+            let settings = IndexSettings {
+                time_zone: time_zone.map(|tz| tz.parse().unwrap()),
+                ..Default::default()
+            };
+            let query = range("date_added")
+                .gte("2022-11-14")
+                .build()
+                .compile(&index, &settings)
+                .unwrap();
+            searcher.search(&query, &Count).unwrap()
+        };
+
+        assert_eq!(1, count(None));
+        assert_eq!(0, count(Some("America/New_York")));
+    }
+
+    #[tokio::test]
+    async fn compile_ip_queries() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![
+                    json!({ "client_ip": "10.0.1.5" }),
+                    json!({ "client_ip": "10.1.0.1" }),
+                    json!({ "client_ip": "2001:db8::1" }),
+                ],
+            )
+            .await;
+
+        let index = ctx.index_loader().load_index("test", None).unwrap();
+        let searcher = index.reader().unwrap().searcher();
+
+        let count = |query: Query| {
+            let query = query.compile(&index, &IndexSettings::default()).unwrap();
+            searcher.search(&query, &Count).unwrap()
+        };
+
+        assert_eq!(1, count(term("client_ip", "10.0.1.5")));
+        assert_eq!(1, count(term("client_ip", "10.0.0.0/16")));
+        assert_eq!(2, count(term("client_ip", "10.0.0.0/8")));
+        assert_eq!(1, count(term("client_ip", "2001:db8::/32")));
+        assert_eq!(
+            2,
+            count(
+                range("client_ip")
+                    .gte("10.0.0.0")
+                    .lte("10.255.255.255")
+                    .build()
+            )
+        );
+        assert_eq!(
+            1,
+            count(query_string("client_ip:[10.0.0.0 TO 10.0.255.255]"))
+        );
+
+        assert!(term("client_ip", "not-an-ip")
+            .compile(&index, &IndexSettings::default())
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn compile_json_path_queries() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![
+                    json!({ "props": { "author": { "name": "John Smith" }, "pages": 320 } }),
+                    json!({ "props": { "author": { "name": "Jane Doe" }, "pages": 80 } }),
+                ],
+            )
+            .await;
+
+        let index = ctx.index_loader().load_index("test", None).unwrap();
+        let searcher = index.reader().unwrap().searcher();
+
+        let count = |query: Query| {
+            let query = query.compile(&index, &IndexSettings::default()).unwrap();
+            searcher.search(&query, &Count).unwrap()
+        };
+
+        assert_eq!(1, count(query_string("props.author.name:smith")));
+        assert_eq!(1, count(term("props.author.name", "smith")));
+        assert_eq!(2, count(match_("props.author.name", "smith doe")));
+        assert_eq!(1, count(term("props.pages", 320)));
+
+        assert!(term("missing.path", "smith")
+            .compile(&index, &IndexSettings::default())
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn global_stats_query_scores_like_the_whole_index() {
+        let ctx = setup()
+            .with_documents(
+                "test-all",
+                vec![
+                    json!({ "__id": "a", "title": "zen" }),
+                    json!({ "__id": "b", "title": "art" }),
+                    json!({ "__id": "c", "title": "war" }),
+                ],
+            )
+            .await
+            .with_documents("test-part", vec![json!({ "__id": "a", "title": "zen" })])
+            .await;
+
+        let settings = IndexSettings::default();
+        let top_score = |index: &Index, query: Box<dyn TantivyQuery>| {
+            let searcher = index.reader().unwrap().searcher();
+            searcher.search(&query, &TopDocs::with_limit(1)).unwrap()[0].0
+        };
+
+        let all = ctx.index_loader().load_index("test-all", None).unwrap();
+        let part = ctx.index_loader().load_index("test-part", None).unwrap();
+        let query = query_string("zen");
+
+        let global = GlobalStatsQuery::new(
+            query.compile(&part, &settings).unwrap(),
+            Arc::new(all.reader().unwrap().searcher()),
+        );
+
+        let expected = top_score(&all, query.compile(&all, &settings).unwrap());
+        assert_ne!(
+            expected,
+            top_score(&part, query.compile(&part, &settings).unwrap())
+        );
+        assert_eq!(expected, top_score(&part, Box::new(global)));
+    }
+
+    #[tokio::test]
+    async fn terminate_after_query_limits_candidates_per_segment() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![
+                    json!({ "__id": "a", "title": "zen" }),
+                    json!({ "__id": "b", "title": "zen" }),
+                    json!({ "__id": "c", "title": "zen" }),
+                    json!({ "__id": "d", "title": "art" }),
+                ],
+            )
+            .await;
+
+        let index = ctx.index_loader().load_index("test", None).unwrap();
+        let searcher = index.reader().unwrap().searcher();
+        let query = query_string("zen")
+            .compile(&index, &IndexSettings::default())
+            .unwrap();
+
+        let count = |limit: u32| {
+            let query = TerminateAfterQuery::new(query.box_clone(), limit);
+            searcher.search(&query, &Count).unwrap()
+        };
+
+        assert_eq!(0, count(0));
+        assert_eq!(2, count(2));
+        assert_eq!(3, count(10));
+    }
+}
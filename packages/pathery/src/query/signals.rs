@@ -0,0 +1,338 @@
+//! Ranking presets that multiply match scores by document-level signals, read from the `FAST`
+//! fields named in the index's `boost_signals`.
+//!
+//! ```json
+//! { "query": "zen", "boost": { "preset": "recency_7d" } }
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tantivy::fastfield::{DynamicFastFieldReader, FastFieldReader, FastValue};
+use tantivy::query::{Explanation, Query as TantivyQuery, Scorer, Weight};
+use tantivy::schema::{Field, Schema, Type};
+use tantivy::{DateTime, DocId, DocSet, Score, Searcher, SegmentReader, Term};
+
+use crate::schema::IndexSettings;
+use crate::service::ServiceError;
+
+const DAY_SECS: f64 = 86_400.0;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BoostOptions {
+    pub preset: BoostPreset,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BoostPreset {
+    /// Multiplies scores by `log10(10 + popularity)`, so popular documents rank higher without
+    /// a handful of viral ones drowning out relevance.
+    #[serde(rename = "popularity")]
+    Popularity,
+
+    /// Multiplies scores by `sqrt(1 + rating)`.
+    #[serde(rename = "rating")]
+    Rating,
+
+    /// Halves scores for every day of age.
+    #[serde(rename = "recency_1d")]
+    Recency1d,
+
+    /// Halves scores for every week of age.
+    #[serde(rename = "recency_7d")]
+    Recency7d,
+
+    /// Halves scores for every 30 days of age.
+    #[serde(rename = "recency_30d")]
+    Recency30d,
+
+    /// Halves scores for every year of age.
+    #[serde(rename = "recency_365d")]
+    Recency365d,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum BoostFunction {
+    Log,
+    Sqrt,
+    /// Halves per `half_life` seconds before `now`.
+    Decay {
+        half_life: f64,
+        now: f64,
+    },
+}
+
+impl BoostFunction {
+    fn factor(&self, value: f64) -> Score {
+        let factor = match *self {
+            BoostFunction::Log => (10.0 + value.max(0.0)).log10(),
+            BoostFunction::Sqrt => (1.0 + value.max(0.0)).sqrt(),
+            BoostFunction::Decay { half_life, now } => {
+                0.5f64.powf((now - value).max(0.0) / half_life)
+            }
+        };
+        factor as Score
+    }
+}
+
+/// A fast field value as a number, dates as unix timestamps in seconds.
+fn decode(value_type: Type, value: u64) -> f64 {
+    match value_type {
+        Type::U64 => value as f64,
+        Type::I64 => i64::from_u64(value) as f64,
+        Type::F64 => f64::from_u64(value),
+        Type::Date => DateTime::from_u64(value).into_unix_timestamp() as f64,
+        _ => 0.0,
+    }
+}
+
+impl BoostOptions {
+    /// Wraps `query` to boost its scores by the preset's signal in `settings.boost_signals`.
+    pub fn apply(
+        &self,
+        query: Box<dyn TantivyQuery>,
+        schema: &Schema,
+        settings: &IndexSettings,
+    ) -> Result<Box<dyn TantivyQuery>, ServiceError> {
+        let signals = &settings.boost_signals;
+        let (signal, field_name, half_life_days) = match self.preset {
+            BoostPreset::Popularity => ("popularity", &signals.popularity, None),
+            BoostPreset::Rating => ("rating", &signals.rating, None),
+            BoostPreset::Recency1d => ("recency", &signals.recency, Some(1.0)),
+            BoostPreset::Recency7d => ("recency", &signals.recency, Some(7.0)),
+            BoostPreset::Recency30d => ("recency", &signals.recency, Some(30.0)),
+            BoostPreset::Recency365d => ("recency", &signals.recency, Some(365.0)),
+        };
+
+        let field_name = field_name.as_ref().ok_or_else(|| {
+            ServiceError::invalid_request(&format!(
+                "Index has no {} signal configured in boost_signals",
+                signal
+            ))
+        })?;
+        let field = schema.get_field(field_name).ok_or_else(|| {
+            ServiceError::invalid_request(&format!("Field [{}] does not exist", field_name))
+        })?;
+
+        let entry = schema.get_field_entry(field);
+        let value_type = entry.field_type().value_type();
+        let valid_type = match half_life_days {
+            Some(_) => value_type == Type::Date,
+            None => matches!(value_type, Type::U64 | Type::I64 | Type::F64),
+        };
+        if !entry.is_fast() || !valid_type {
+            return Err(ServiceError::invalid_request(&format!(
+                "Field [{}] must be a FAST {} field for the {} signal",
+                field_name,
+                if half_life_days.is_some() {
+                    "date"
+                } else {
+                    "numeric"
+                },
+                signal
+            )));
+        }
+
+        let function = match (self.preset, half_life_days) {
+            (_, Some(days)) => {
+                // Ages are measured from the start of the hour, so scores and the cursors
+                // paging through them stay stable for an hour at a time.
+                let now = Utc::now().timestamp();
+                BoostFunction::Decay {
+                    half_life: days * DAY_SECS,
+                    now: (now - now.rem_euclid(3600)) as f64,
+                }
+            }
+            (BoostPreset::Popularity, None) => BoostFunction::Log,
+            (_, None) => BoostFunction::Sqrt,
+        };
+
+        Ok(Box::new(SignalBoostQuery {
+            inner: query,
+            field,
+            value_type,
+            function,
+        }))
+    }
+}
+
+struct SignalBoostQuery {
+    inner: Box<dyn TantivyQuery>,
+    field: Field,
+    value_type: Type,
+    function: BoostFunction,
+}
+
+impl Clone for SignalBoostQuery {
+    fn clone(&self) -> Self {
+        SignalBoostQuery {
+            inner: self.inner.box_clone(),
+            field: self.field,
+            value_type: self.value_type,
+            function: self.function,
+        }
+    }
+}
+
+impl fmt::Debug for SignalBoostQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SignalBoostQuery")
+            .field("inner", &self.inner)
+            .field("function", &self.function)
+            .finish()
+    }
+}
+
+impl TantivyQuery for SignalBoostQuery {
+    fn weight(
+        &self,
+        searcher: &Searcher,
+        scoring_enabled: bool,
+    ) -> tantivy::Result<Box<dyn Weight>> {
+        Ok(Box::new(SignalBoostWeight {
+            inner: self.inner.weight(searcher, scoring_enabled)?,
+            field: self.field,
+            value_type: self.value_type,
+            function: self.function,
+        }))
+    }
+
+    fn query_terms(&self, terms: &mut BTreeMap<Term, bool>) {
+        self.inner.query_terms(terms)
+    }
+}
+
+struct SignalBoostWeight {
+    inner: Box<dyn Weight>,
+    field: Field,
+    value_type: Type,
+    function: BoostFunction,
+}
+
+impl SignalBoostWeight {
+    fn factor(&self, reader: &DynamicFastFieldReader<u64>, doc: DocId) -> Score {
+        self.function
+            .factor(decode(self.value_type, reader.get(doc)))
+    }
+}
+
+impl Weight for SignalBoostWeight {
+    fn scorer(&self, reader: &SegmentReader, boost: Score) -> tantivy::Result<Box<dyn Scorer>> {
+        Ok(Box::new(SignalBoostScorer {
+            inner: self.inner.scorer(reader, boost)?,
+            values: reader.fast_fields().u64_lenient(self.field)?,
+            value_type: self.value_type,
+            function: self.function,
+        }))
+    }
+
+    fn explain(&self, reader: &SegmentReader, doc: DocId) -> tantivy::Result<Explanation> {
+        let inner = self.inner.explain(reader, doc)?;
+        let values = reader.fast_fields().u64_lenient(self.field)?;
+        let factor = self.factor(&values, doc);
+
+        let mut explanation = Explanation::new("signal boost", inner.value() * factor);
+        explanation.add_detail(inner);
+        explanation.add_const("signal factor", factor);
+        Ok(explanation)
+    }
+}
+
+struct SignalBoostScorer {
+    inner: Box<dyn Scorer>,
+    values: DynamicFastFieldReader<u64>,
+    value_type: Type,
+    function: BoostFunction,
+}
+
+impl DocSet for SignalBoostScorer {
+    fn advance(&mut self) -> DocId {
+        self.inner.advance()
+    }
+
+    fn seek(&mut self, target: DocId) -> DocId {
+        self.inner.seek(target)
+    }
+
+    fn doc(&self) -> DocId {
+        self.inner.doc()
+    }
+
+    fn size_hint(&self) -> u32 {
+        self.inner.size_hint()
+    }
+}
+
+impl Scorer for SignalBoostScorer {
+    fn score(&mut self) -> Score {
+        let value = decode(self.value_type, self.values.get(self.inner.doc()));
+        self.inner.score() * self.function.factor(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tantivy::collector::TopDocs;
+    use tantivy::query::AllQuery;
+    use tantivy::schema::{FAST, STORED, STRING};
+    use tantivy::{doc, Index};
+
+    use super::*;
+    use crate::schema::BoostSignals;
+
+    #[test]
+    fn recency_preset_ranks_newer_documents_first() {
+        let mut schema = Schema::builder();
+        let id = schema.add_text_field("id", STRING | STORED);
+        let published = schema.add_date_field("published", FAST);
+        let index = Index::create_in_ram(schema.build());
+
+        let days_ago =
+            |days: i64| DateTime::from_unix_timestamp(Utc::now().timestamp() - days * 86_400);
+        let mut writer = index.writer_with_num_threads(1, 15_000_000).unwrap();
+        writer
+            .add_document(doc!(id => "old", published => days_ago(30)))
+            .unwrap();
+        writer
+            .add_document(doc!(id => "new", published => days_ago(1)))
+            .unwrap();
+        writer.commit().unwrap();
+
+        let settings = IndexSettings {
+            boost_signals: BoostSignals {
+                recency: Some("published".into()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let boost = |preset| {
+            BoostOptions { preset }
+                .apply(Box::new(AllQuery), &index.schema(), &settings)
+                .map(|query| {
+                    let searcher = index.reader().unwrap().searcher();
+                    searcher
+                        .search(&query, &TopDocs::with_limit(2))
+                        .unwrap()
+                        .into_iter()
+                        .map(|(score, address)| {
+                            let doc = searcher.doc(address).unwrap();
+                            (
+                                doc.get_first(id).unwrap().as_text().unwrap().to_string(),
+                                score,
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                })
+        };
+
+        let ranked = boost(BoostPreset::Recency7d).unwrap();
+        assert_eq!("new", ranked[0].0);
+        assert!(ranked[1].1 < 0.1);
+
+        // Signals that aren't configured are rejected.
+        let err = boost(BoostPreset::Popularity).unwrap_err();
+        assert_eq!(400, err.status());
+    }
+}
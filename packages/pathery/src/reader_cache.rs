@@ -0,0 +1,143 @@
+//! Index readers kept across the requests a warm Lambda serves.
+//!
+//! Each segment's doc store reader caches the blocks it decompresses, so the hits of a query that
+//! share blocks only decompress them once. Those caches live as long as the reader, so opening a
+//! reader per request threw them away between requests. Readers are reused for as long as the
+//! index's `meta.json` is unchanged, a new commit or schema change opening a new reader.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use tantivy::{Directory, Index, IndexReader, ReloadPolicy};
+use tracing::info;
+
+use crate::service::ServiceError;
+
+/// Most readers kept at once, each holding its segments' block caches.
+const MAX_READERS: usize = 32;
+
+/// Index id and partition of a reader.
+type ReaderKey = (String, Option<(usize, usize)>);
+
+struct CachedReader {
+    meta: Vec<u8>,
+
+    reader: IndexReader,
+
+    last_used: Instant,
+}
+
+#[derive(Default)]
+pub struct ReaderCache {
+    readers: Mutex<HashMap<ReaderKey, CachedReader>>,
+
+    opened: AtomicUsize,
+}
+
+impl ReaderCache {
+    /// A reader of `index`, the cached one when it was opened on the same commit.
+    pub fn reader(
+        &self,
+        index_id: &str,
+        with_partition: Option<(usize, usize)>,
+        index: &Index,
+    ) -> Result<IndexReader, ServiceError> {
+        Ok(self.lookup(index_id, with_partition, index)?.0)
+    }
+
+    /// Like [`reader`](Self::reader), along with whether the reader came from the cache.
+    pub fn lookup(
+        &self,
+        index_id: &str,
+        with_partition: Option<(usize, usize)>,
+        index: &Index,
+    ) -> Result<(IndexReader, bool), ServiceError> {
+        let meta = index
+            .directory()
+            .atomic_read(Path::new("meta.json"))
+            .map_err(ServiceError::internal_error)?;
+        let key = (index_id.to_string(), with_partition);
+
+        let mut readers = self.readers.lock().unwrap();
+        if let Some(cached) = readers.get_mut(&key) {
+            if cached.meta == meta {
+                cached.last_used = Instant::now();
+                return Ok((cached.reader.clone(), true));
+            }
+        }
+
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()
+            .map_err(ServiceError::internal_error)?;
+
+        if readers.len() >= MAX_READERS && !readers.contains_key(&key) {
+            let oldest = readers
+                .iter()
+                .min_by_key(|(_, cached)| cached.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                readers.remove(&oldest);
+            }
+        }
+        readers.insert(
+            key,
+            CachedReader {
+                meta,
+                reader: reader.clone(),
+                last_used: Instant::now(),
+            },
+        );
+        let opened = self.opened.fetch_add(1, Ordering::Relaxed) + 1;
+        info!(
+            message = "reader_opened",
+            index = index_id,
+            cached = readers.len(),
+            opened
+        );
+
+        Ok((reader, false))
+    }
+
+    /// Number of readers opened rather than reused.
+    pub fn opened(&self) -> usize {
+        self.opened.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::IndexLoader;
+    use crate::test_utils::*;
+
+    #[tokio::test]
+    async fn readers_are_reused_until_the_index_changes() {
+        let ctx = setup()
+            .with_documents("test", vec![json!({ "title": "hello" })])
+            .await;
+        let cache = ReaderCache::default();
+        let num_docs = |ctx: &TestContext| {
+            let index = ctx.index_loader().load_index("test", None).unwrap();
+            cache
+                .reader("test", None, &index)
+                .unwrap()
+                .searcher()
+                .num_docs()
+        };
+
+        assert_eq!(1, num_docs(&ctx));
+        assert_eq!(1, num_docs(&ctx));
+        assert_eq!(1, cache.opened());
+
+        let ctx = ctx
+            .with_documents("test", vec![json!({ "title": "world" })])
+            .await;
+        assert_eq!(2, num_docs(&ctx));
+        assert_eq!(2, cache.opened());
+    }
+}
@@ -0,0 +1,194 @@
+//! Retry and circuit breaking for calls to AWS services.
+
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+
+/// Exponential backoff with full jitter.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff {
+            max_attempts: 4,
+            base_delay: Duration::from_millis(25),
+            max_delay: Duration::from_secs(1),
+        }
+    }
+}
+
+impl Backoff {
+    /// A random delay of up to `base_delay * 2^attempt`, capped at `max_delay`.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let ceiling = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+
+        ceiling.mul_f64(rand::thread_rng().gen::<f64>())
+    }
+
+    /// Calls `f` until it succeeds, `should_retry` rejects the error or attempts run out.
+    pub async fn retry<T, E, F, Fut>(
+        &self,
+        mut f: F,
+        should_retry: impl Fn(&E) -> bool,
+    ) -> Result<T, E>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut attempt = 0;
+
+        loop {
+            match f().await {
+                Ok(value) => return Ok(value),
+                Err(err) if attempt + 1 < self.max_attempts && should_retry(&err) => {
+                    tokio::time::sleep(self.delay(attempt)).await;
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct CircuitState {
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+}
+
+/// Fails fast after repeated failures so callers shed load instead of piling up retries.
+///
+/// Once `failure_threshold` consecutive calls fail the circuit opens and rejects calls for
+/// `cooldown`. After the cooldown calls are let through again, a single failure re-opens it and a
+/// success closes it.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    cooldown: Duration,
+    state: Mutex<CircuitState>,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        CircuitBreaker::new(5, Duration::from_secs(30))
+    }
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, cooldown: Duration) -> Self {
+        CircuitBreaker {
+            failure_threshold,
+            cooldown,
+            state: Mutex::new(CircuitState::default()),
+        }
+    }
+
+    /// Whether a call should be attempted.
+    pub fn allow(&self) -> bool {
+        let state = self.state.lock().unwrap();
+
+        match state.opened_at {
+            Some(opened_at) => opened_at.elapsed() >= self.cooldown,
+            None => true,
+        }
+    }
+
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        *state = CircuitState::default();
+    }
+
+    pub fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures += 1;
+
+        if state.consecutive_failures >= self.failure_threshold {
+            state.opened_at = Some(Instant::now());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+
+    fn no_delay(max_attempts: u32) -> Backoff {
+        Backoff {
+            max_attempts,
+            base_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+        }
+    }
+
+    #[tokio::test]
+    async fn retry_until_success() {
+        let calls = AtomicU32::new(0);
+
+        let result: Result<u32, &str> = no_delay(3)
+            .retry(
+                || async {
+                    match calls.fetch_add(1, Ordering::SeqCst) {
+                        0 | 1 => Err("transient"),
+                        n => Ok(n),
+                    }
+                },
+                |_| true,
+            )
+            .await;
+
+        assert_eq!(Ok(2), result);
+    }
+
+    #[tokio::test]
+    async fn retry_stops_on_fatal_error() {
+        let calls = AtomicU32::new(0);
+
+        let result: Result<(), &str> = no_delay(3)
+            .retry(
+                || async {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    Err("fatal")
+                },
+                |err| *err != "fatal",
+            )
+            .await;
+
+        assert_eq!(Err("fatal"), result);
+        assert_eq!(1, calls.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn circuit_opens_after_threshold() {
+        let breaker = CircuitBreaker::new(2, Duration::from_secs(60));
+
+        breaker.record_failure();
+        assert!(breaker.allow());
+
+        breaker.record_failure();
+        assert!(!breaker.allow());
+
+        breaker.record_success();
+        assert!(breaker.allow());
+    }
+
+    #[test]
+    fn circuit_half_opens_after_cooldown() {
+        let breaker = CircuitBreaker::new(1, Duration::ZERO);
+
+        breaker.record_failure();
+
+        assert!(breaker.allow());
+    }
+}
@@ -1,28 +1,65 @@
-use std::fs;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::{fmt, fs};
 
+use async_trait::async_trait;
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
 use serde_json as json;
-use tantivy::schema::{self, DocParsingError, Field, NumericOptions, Schema, TextOptions};
+use tantivy::schema::{
+    self, BytesOptions, DocParsingError, FacetOptions, Field, FieldEntry, FieldType,
+    IndexRecordOption, NumericOptions, Schema, TextFieldIndexing, TextOptions,
+};
+use tantivy::tokenizer::TextAnalyzer;
 use thiserror::Error;
+use tracing::error;
 
+use crate::catalog;
+use crate::enrich::EnrichConfig;
 use crate::service::ServiceError;
+use crate::store::schema::{DDBSchemaStore, SchemaStore};
+use crate::tokenizer::{FieldAnalyzer, Language, StopwordsConfig, TokenizerConfig, IP_TOKENIZER};
+use crate::worker::dynamodb_stream::DynamoDbStreamConfig;
+use crate::{tenant, util};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum TextFieldOption {
     TEXT,
     STRING,
     FAST,
+    STORED,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum NumericFieldOption {
     INDEXED,
+    STORED,
     FAST,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum BytesFieldOption {
+    INDEXED,
+    STORED,
+    FAST,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum IpFieldOption {
+    INDEXED,
+    STORED,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum FacetFieldOption {
+    STORED,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum JsonFieldOption {
     TEXT,
+    STORED,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -32,6 +69,21 @@ pub enum FieldConfig {
     TextFieldConfig {
         name: String,
         flags: Vec<TextFieldOption>,
+        /// Overrides the tokenizer chosen by `TEXT` or `STRING`. Implies `TEXT` indexing when
+        /// neither flag is set.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tokenizer: Option<TokenizerConfig>,
+        /// Stems terms in this language on top of the `default` tokenizer, so that e.g. `fishing`
+        /// matches `fished`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        language: Option<Language>,
+        /// Words dropped during analysis of values and query strings.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        stopwords: Option<StopwordsConfig>,
+        /// Folds accented and other non-ASCII characters to their ASCII equivalents, so that
+        /// `café` matches `cafe`.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        ascii_folding: bool,
     },
     #[serde(rename = "date")]
     DateFieldConfig {
@@ -43,6 +95,34 @@ pub enum FieldConfig {
         name: String,
         flags: Vec<NumericFieldOption>,
     },
+    #[serde(rename = "u64")]
+    UnsignedIntegerFieldConfig {
+        name: String,
+        flags: Vec<NumericFieldOption>,
+    },
+    #[serde(rename = "f64")]
+    FloatFieldConfig {
+        name: String,
+        flags: Vec<NumericFieldOption>,
+    },
+    /// Opaque binary values, given as base64 strings in documents.
+    #[serde(rename = "bytes")]
+    BytesFieldConfig {
+        name: String,
+        flags: Vec<BytesFieldOption>,
+    },
+    /// IPv4 or IPv6 addresses, supporting range and CIDR queries.
+    #[serde(rename = "ip")]
+    IpFieldConfig {
+        name: String,
+        flags: Vec<IpFieldOption>,
+    },
+    /// Hierarchical paths such as `/books/fiction`, counted with facet aggregations.
+    #[serde(rename = "facet")]
+    FacetFieldConfig {
+        name: String,
+        flags: Vec<FacetFieldOption>,
+    },
     #[serde(rename = "json")]
     JsonFieldConfig {
         name: String,
@@ -50,10 +130,379 @@ pub enum FieldConfig {
     },
 }
 
+impl FieldConfig {
+    /// The per-field analyzer of a text field, if its config needs one.
+    fn custom_analyzer(&self) -> Option<(&str, FieldAnalyzer)> {
+        match self {
+            FieldConfig::TextFieldConfig {
+                name,
+                language,
+                stopwords,
+                ascii_folding,
+                ..
+            } => {
+                let analyzer = FieldAnalyzer {
+                    language: *language,
+                    stopwords: stopwords.clone(),
+                    ascii_folding: *ascii_folding,
+                };
+                analyzer.is_custom().then_some((name.as_str(), analyzer))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct IndexSettings {
+    /// Queries return only ids and scores, without hydrating documents or generating snippets.
+    /// For indexes whose canonical documents live in another database.
+    #[serde(default)]
+    pub search_only: bool,
+
+    /// Score multipliers for analyzed queries against each field. Boosts are applied at query
+    /// time so changing them takes effect without reindexing.
+    #[serde(default)]
+    pub field_boosts: HashMap<String, f32>,
+
+    /// Fields queries generate snippets for unless they pick their own `highlight.fields`, to
+    /// skip id-like and metadata fields nobody reads snippets of. Defaults to every indexed text
+    /// field.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snippet_fields: Option<Vec<String>>,
+
+    /// IANA time zone for dates given without an offset, e.g. `2022-11-14` or
+    /// `2022-11-14T09:30:00`. Defaults to UTC.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_zone: Option<Tz>,
+
+    /// Derive fields for keys outside the configured schema from the documents being indexed,
+    /// instead of ignoring them.
+    #[serde(default)]
+    pub dynamic: bool,
+
+    /// Stop examining each segment after this many candidate matches, for latency-critical
+    /// queries such as autocomplete that can do without exhaustive recall. Queries can override
+    /// it with their own `terminate_after`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub terminate_after: Option<u32>,
+
+    /// Documents larger than this many bytes of JSON are rejected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_doc_bytes: Option<usize>,
+
+    /// Limits on every text value, unless overridden for the field in `field_limits`.
+    #[serde(flatten)]
+    pub default_field_limits: FieldLimits,
+
+    /// Per-field overrides of the text value limits.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub field_limits: HashMap<String, FieldLimits>,
+
+    /// Whether text values over their limits reject the document or are truncated to fit.
+    #[serde(default)]
+    pub oversized_fields: OversizePolicy,
+
+    /// How each field's value is indexed when documents leave it out or set it to `null`.
+    /// Fields not listed are left out of the index, as are their `null` values outside the
+    /// schema.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub null_values: HashMap<String, NullValue>,
+
+    /// Compress the stored documents of newly written segments with zstd, for indexes whose
+    /// storage is mostly stored fields. Existing segments are read either way.
+    #[serde(default)]
+    pub compress_stored_fields: bool,
+
+    /// Segment files downloaded at once when an index kept in S3 is opened. Defaults to 8.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefetch_concurrency: Option<usize>,
+
+    /// Other names queries can use for fields, mapped to the field each stands for, so that
+    /// queries written against a field's old name keep working after it's renamed. Names of
+    /// fields in the schema are never treated as aliases.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub field_aliases: HashMap<String, String>,
+
+    /// Fields on their way out of the schema. Requests using them still succeed, with a warning,
+    /// and are counted per client so the field can be removed once nobody uses it.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deprecated_fields: Vec<String>,
+
+    /// Lookup tables to join documents against as they're indexed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub enrich: Vec<EnrichConfig>,
+
+    /// How the index writer merges the segments each commit writes.
+    #[serde(default)]
+    pub merge_policy: MergePolicyConfig,
+
+    /// Fields holding document-level ranking signals, for queries to boost by with a `preset`.
+    #[serde(default)]
+    pub boost_signals: BoostSignals,
+
+    /// `INDEXED` date field holding when each document expires. The expire worker deletes
+    /// documents once it's passed; those without a value never expire.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl_field: Option<String>,
+
+    /// Route writes to the index id equal to the `prefix` into a partition per day, which
+    /// queries to the prefix fan out across.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_partition: Option<TimePartitionConfig>,
+
+    /// Text fields whose most frequent terms the index writer keeps in a suggestion dictionary,
+    /// rebuilt each commit.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suggest: Option<SuggestConfig>,
+
+    /// Record every query with its top results in the data bucket, for relevance tuning and
+    /// auditing.
+    #[serde(default)]
+    pub analytics: bool,
+
+    /// Limits on the size of the index's queries.
+    #[serde(default)]
+    pub query_limits: QueryLimits,
+
+    /// How often the index can be queried and written to, so one busy index can't use up the
+    /// Lambda concurrency every index shares. Unlimited by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<RateLimitConfig>,
+
+    /// How the items of a DynamoDB table map to documents, for indexes kept in step with the
+    /// table by the DynamoDB stream worker.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dynamodb_stream: Option<DynamoDbStreamConfig>,
+
+    /// Team or person responsible for the index, listed in its catalog entry.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+
+    /// Labels listed in the index's catalog entry, for finding related indexes.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+/// Admits `requests_per_second` on average, and up to `burst` at once after the index has been
+/// idle.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct RateLimitConfig {
+    pub requests_per_second: f64,
+
+    /// Requests that can be made at once after the index has been idle. Defaults to one
+    /// second's worth of requests.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub burst: Option<u32>,
+}
+
+impl RateLimitConfig {
+    pub fn burst(&self) -> u32 {
+        self.burst
+            .unwrap_or_else(|| self.requests_per_second.ceil() as u32)
+            .max(1)
+    }
+}
+
+/// Limits on the size and running time of queries, so that a pathological query is rejected or cut
+/// short rather than using up the Lambda's time. Query strings count the clauses and nesting they
+/// parse into.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueryLimits {
+    /// Most leaf clauses, such as terms and ranges, in a query and its filters. Defaults to
+    /// 1,024.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_clauses: Option<usize>,
+
+    /// Most levels of boolean queries nested in one another. Defaults to 20.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_depth: Option<usize>,
+
+    /// Milliseconds a query can spend collecting matches before it stops and returns those it
+    /// found so far, flagged `timed_out`. Queries can set their own `timeout_ms`. Unlimited by
+    /// default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+}
+
+impl QueryLimits {
+    pub fn max_clauses(&self) -> usize {
+        self.max_clauses.unwrap_or(1024)
+    }
+
+    pub fn max_depth(&self) -> usize {
+        self.max_depth.unwrap_or(20)
+    }
+}
+
+/// Fields of the suggestion dictionary and how many terms it keeps.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SuggestConfig {
+    pub fields: Vec<String>,
+
+    /// Most terms kept across every field, those in the most documents first. Defaults to
+    /// 10,000.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_terms: Option<usize>,
+}
+
+/// Daily partitions of a rolling index, named `{prefix}-YYYY-MM-DD` by UTC day.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct TimePartitionConfig {
+    /// Date field whose day picks a document's partition. Documents without one, or when no
+    /// field is set, go to the partition of the day they're written.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+
+    /// Days of partitions, including today's, that queries search. Older partitions are
+    /// dropped. Defaults to keeping every partition.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retention_days: Option<u32>,
+}
+
+/// `FAST` fields with document-level ranking signals, each optional.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct BoostSignals {
+    /// Numeric field such as a view or sales count.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub popularity: Option<String>,
+
+    /// Numeric field such as an average review score.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rating: Option<String>,
+
+    /// Date field such as when the document was published.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recency: Option<String>,
+}
+
+/// Limits on the size of each text value of a field.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FieldLimits {
+    /// Most characters in a value.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_field_chars: Option<usize>,
+
+    /// Most tokens in a value, split on whitespace and punctuation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_field_tokens: Option<usize>,
+}
+
+/// What to do with a text value over its field's limits.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OversizePolicy {
+    #[default]
+    Reject,
+    Truncate,
+}
+
+/// What to index for a field whose value is missing or `null`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum NullValue {
+    /// Leave the field out, dropping explicit `null`s rather than rejecting them.
+    #[default]
+    Skip,
+
+    /// Index this value instead, so queries for it match documents without the field. It isn't
+    /// stored, so the documents are still returned without the field.
+    Sentinel(json::Value),
+
+    /// Fill in this value, which is stored and returned as if the document had given it.
+    Default(json::Value),
+}
+
+/// How the index writer merges segments after committing.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum MergePolicyConfig {
+    /// Merges segments of similar sizes into levels that grow by a constant factor.
+    Log(LogMergeSettings),
+
+    /// Never merges, for indexes whose writers can't afford the time merges take. Segments
+    /// accumulate until the index is rebuilt.
+    None,
+}
+
+impl Default for MergePolicyConfig {
+    fn default() -> Self {
+        MergePolicyConfig::Log(LogMergeSettings::default())
+    }
+}
+
+/// Settings of the log merge policy, each defaulting to tantivy's except where noted.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct LogMergeSettings {
+    /// Fewest segments of a level merged together. Defaults to 8.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_num_segments: Option<usize>,
+
+    /// Segments with more documents than this are left out of merges, bounding the size of the
+    /// merges a commit can trigger. Defaults to 10,000.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_docs_before_merge: Option<usize>,
+
+    /// Segments smaller than this many documents all belong to the lowest level. Defaults to
+    /// 10,000.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_layer_size: Option<u32>,
+
+    /// Log of the ratio between the sizes of consecutive levels. Defaults to 0.75.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub level_log_size: Option<f64>,
+
+    /// Share of deleted documents past which a segment is merged away even when its level isn't
+    /// full. Defaults to 1.0, never.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub del_docs_ratio_before_merge: Option<f32>,
+}
+
+impl IndexSettings {
+    pub fn field_limits(&self, field_name: &str) -> FieldLimits {
+        self.field_limits
+            .get(field_name)
+            .copied()
+            .unwrap_or(self.default_field_limits)
+    }
+
+    pub fn time_zone(&self) -> Tz {
+        self.time_zone.unwrap_or(Tz::UTC)
+    }
+
+    pub fn field_boost(&self, field_name: &str) -> Option<f32> {
+        self.field_boosts.get(field_name).copied()
+    }
+
+    /// The name of the field in `schema` that `name` refers to, resolving aliases. A dotted path
+    /// into a JSON field resolves the alias it starts with.
+    pub fn resolve_field<'a>(&'a self, schema: &Schema, name: &'a str) -> Cow<'a, str> {
+        if self.field_aliases.is_empty() || schema.get_field(name).is_some() {
+            return Cow::Borrowed(name);
+        }
+
+        if let Some(field_name) = self.field_aliases.get(name) {
+            return Cow::Borrowed(field_name);
+        }
+
+        match name.split_once('.') {
+            Some((prefix, path)) if schema.get_field(prefix).is_none() => {
+                match self.field_aliases.get(prefix) {
+                    Some(field_name) => Cow::Owned(format!("{}.{}", field_name, path)),
+                    None => Cow::Borrowed(name),
+                }
+            }
+            _ => Cow::Borrowed(name),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct IndexConfig {
     prefix: String,
     fields: Vec<FieldConfig>,
+    #[serde(default)]
+    settings: IndexSettings,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -61,8 +510,115 @@ pub struct PatheryConfig {
     indexes: Vec<IndexConfig>,
 }
 
+impl PatheryConfig {
+    /// Checks settings that deserialize but can't be built into a schema.
+    fn validate(&self) -> Result<(), String> {
+        for index in &self.indexes {
+            for field in &index.fields {
+                if let FieldConfig::TextFieldConfig {
+                    name,
+                    tokenizer,
+                    language,
+                    stopwords,
+                    ascii_folding,
+                    ..
+                } = field
+                {
+                    if let Some(tokenizer) = tokenizer {
+                        tokenizer
+                            .validate()
+                            .map_err(|message| format!("Field [{}]: {}", name, message))?;
+                    }
+
+                    if let Some(stopwords) = stopwords {
+                        stopwords
+                            .validate()
+                            .map_err(|message| format!("Field [{}]: {}", name, message))?;
+                    }
+
+                    if (language.is_some() || stopwords.is_some() || *ascii_folding)
+                        && !matches!(tokenizer, None | Some(TokenizerConfig::Default))
+                    {
+                        return Err(format!(
+                            "Field [{}]: language, stopwords and ascii_folding require the \
+                             default tokenizer",
+                            name
+                        ));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum SchemaError {
+    #[error("Schema for index [{0}] not found")]
+    IndexNotFound(String),
+    #[error("Invalid pathery config: {0}")]
+    InvalidConfig(String),
+    #[error("Field [{field}] has unsupported kind [{kind}]")]
+    UnsupportedFieldKind { field: String, kind: String },
+    #[error("Schema store unavailable: {0}")]
+    StoreUnavailable(String),
+}
+
+/// Unknown index ids are the caller's mistake, but a config that can't be loaded is the
+/// deployment's.
+impl From<SchemaError> for ServiceError {
+    fn from(err: SchemaError) -> Self {
+        match err {
+            SchemaError::IndexNotFound(_) => ServiceError::not_found(&err.to_string()),
+            SchemaError::StoreUnavailable(_) => ServiceError::unavailable(&err.to_string()),
+            _ => ServiceError::internal_error(err),
+        }
+    }
+}
+
+#[async_trait]
 pub trait SchemaLoader: Send + Sync {
-    fn load_schema(&self, index_id: &str) -> Result<Schema, ServiceError>;
+    fn load_schema(&self, index_id: &str) -> Result<Schema, SchemaError>;
+
+    /// The configured prefix that `index_id` was created from, if any.
+    fn index_prefix(&self, index_id: &str) -> Option<String>;
+
+    fn load_settings(&self, index_id: &str) -> Result<IndexSettings, SchemaError>;
+
+    /// Named analyzers that fields of the index are configured with, to register on the index
+    /// when it's loaded.
+    fn load_analyzers(&self, index_id: &str) -> Result<Vec<(String, TextAnalyzer)>, SchemaError>;
+
+    /// Fingerprint of the index's configured fields, including analysis options that don't
+    /// show in the tantivy schema. Stored with an index when it's built, so that a config change
+    /// is detected even when the index has a schema of its own such as a dynamic index.
+    fn load_schema_version(&self, index_id: &str) -> Result<String, SchemaError>;
+
+    /// Deletes the config of `index_id` if it was created through the API, once the index is
+    /// deleted. Bundled configs are left as they are.
+    async fn delete_config(&self, index_id: &str) -> Result<(), SchemaError>;
+}
+
+/// FNV-1a hash of `bytes`. Unlike `DefaultHasher` it's stable across Rust releases.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// [`fnv1a`] hash of `bytes` as 16 hex characters.
+pub(crate) fn fingerprint(bytes: &[u8]) -> String {
+    format!("{:016x}", fnv1a(bytes))
+}
+
+/// Name of the system field holding [`id_key`] of each document's `__id`.
+pub const ID_KEY_FIELD: &str = "__id_key";
+
+/// A `FAST` stand-in for the document id `id`, which equally ranked matches are ordered by,
+/// since text fields can't be `FAST`.
+pub fn id_key(id: &str) -> u64 {
+    fnv1a(id.as_bytes())
 }
 
 #[derive(Error, Debug)]
@@ -75,15 +631,26 @@ pub enum IndexDocError {
     DocParsingError(DocParsingError),
 }
 
-fn numeric_field_options(flags: &Vec<NumericFieldOption>) -> NumericOptions {
+fn numeric_field_options(flags: &[NumericFieldOption]) -> NumericOptions {
     flags
         .iter()
         .fold(NumericOptions::default(), |acc, opt| match opt {
             NumericFieldOption::INDEXED => acc | schema::INDEXED,
+            NumericFieldOption::STORED => acc | schema::STORED,
             NumericFieldOption::FAST => acc | schema::FAST,
         })
 }
 
+/// Whether the field was configured with the `ip` kind.
+pub fn is_ip_field(entry: &FieldEntry) -> bool {
+    match entry.field_type() {
+        FieldType::Str(options) => options
+            .get_indexing_options()
+            .is_some_and(|indexing| indexing.tokenizer() == IP_TOKENIZER),
+        _ => false,
+    }
+}
+
 pub trait SchemaExt {
     fn id_field(&self) -> Field;
 }
@@ -95,50 +662,253 @@ impl SchemaExt for Schema {
     }
 }
 
-#[derive(Clone, Debug)]
+/// Values of `kind` that [`FieldConfig`] accepts.
+const FIELD_KINDS: &[&str] = &[
+    "text", "date", "i64", "u64", "f64", "bytes", "ip", "facet", "json",
+];
+
+/// Parses and validates a pathery config. Unknown field kinds are reported on their own, since
+/// serde would only describe them as an unknown variant of the whole field.
+fn parse_config(config: json::Value) -> Result<PatheryConfig, SchemaError> {
+    let fields = config["indexes"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .flat_map(|index| index["fields"].as_array().into_iter().flatten());
+
+    for field in fields {
+        if let Some(kind) = field["kind"].as_str() {
+            if !FIELD_KINDS.contains(&kind) {
+                return Err(SchemaError::UnsupportedFieldKind {
+                    field: field["name"].as_str().unwrap_or_default().into(),
+                    kind: kind.into(),
+                });
+            }
+        }
+    }
+
+    let config: PatheryConfig =
+        json::from_value(config).map_err(|err| SchemaError::InvalidConfig(err.to_string()))?;
+    config.validate().map_err(SchemaError::InvalidConfig)?;
+    Ok(config)
+}
+
+/// Parses and validates the config of an index created through the API, which has the fields and
+/// settings of an index in the bundled config but applies only to `index_id`.
+pub fn parse_index_config(
+    index_id: &str,
+    definition: json::Value,
+) -> Result<IndexConfig, SchemaError> {
+    let mut definition = match definition {
+        json::Value::Object(definition) => definition,
+        _ => {
+            return Err(SchemaError::InvalidConfig(
+                "expected an object with fields and settings".into(),
+            ))
+        }
+    };
+    definition.insert("prefix".into(), index_id.into());
+
+    let config = parse_config(json::json!({ "indexes": [definition] }))?;
+    Ok(config
+        .indexes
+        .into_iter()
+        .next()
+        .expect("config should have the index"))
+}
+
+#[derive(Clone)]
 pub struct SchemaProvider {
-    config: PatheryConfig,
+    /// A config that failed to load fails every lookup, so that requests report the error
+    /// instead of the function crashing on start.
+    config: Result<PatheryConfig, SchemaError>,
+
+    /// Where configs of indexes created through the API are kept, for ids that don't match a
+    /// prefix of the bundled config.
+    store: Option<Arc<dyn SchemaStore>>,
+
+    /// Configs read from `store`. A created index's config never changes, so they're kept for
+    /// the life of the instance or until the index is deleted.
+    created: Arc<RwLock<HashMap<String, IndexConfig>>>,
+}
+
+impl fmt::Debug for SchemaProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SchemaProvider")
+            .field("config", &self.config)
+            .field("created", &self.created)
+            .finish_non_exhaustive()
+    }
 }
 
 impl SchemaProvider {
-    pub fn lambda() -> Self {
-        let config_path = "/opt/pathery/config.json";
-        let content = fs::read_to_string(config_path).expect("config should exist");
-        let config: PatheryConfig = json::from_str(&content).expect("config should parse");
+    pub async fn lambda() -> Self {
+        SchemaProvider::from_path("/opt/pathery/config.json")
+            .with_store(DDBSchemaStore::create(None).await)
+    }
+
+    /// Provider for the config file at `config_path`. An invalid config is logged, and fails each
+    /// schema it's asked for.
+    pub fn from_path(config_path: &str) -> Self {
+        let config = fs::read_to_string(config_path)
+            .map_err(|err| SchemaError::InvalidConfig(format!("{}: {}", config_path, err)))
+            .and_then(|content| {
+                json::from_str(&content).map_err(|err| SchemaError::InvalidConfig(err.to_string()))
+            })
+            .and_then(parse_config);
+
+        if let Err(err) = &config {
+            error!(message = "config_invalid", error = %err);
+        }
 
-        SchemaProvider { config }
+        SchemaProvider {
+            config,
+            store: None,
+            created: Default::default(),
+        }
     }
 
+    /// Provider for a config known to be valid, panicking otherwise.
     pub fn from_json(config: json::Value) -> Self {
-        let config = json::from_value(config).expect("config should parse");
-        Self { config }
+        let config = parse_config(config).expect("config should be valid");
+        Self {
+            config: Ok(config),
+            store: None,
+            created: Default::default(),
+        }
     }
-}
 
-impl SchemaLoader for SchemaProvider {
-    fn load_schema(&self, index_id: &str) -> Result<Schema, ServiceError> {
-        let config = self
+    /// Also loads the configs of indexes created through the API from `store`.
+    pub fn with_store(mut self, store: impl SchemaStore + 'static) -> Self {
+        self.store = Some(Arc::new(store));
+        self
+    }
+
+    /// The config of `index_id`. Bundled prefixes match the ids of every tenant's indexes, and
+    /// are scoped to the index's tenant. The catalog has a config of its own.
+    fn index_config(&self, index_id: &str) -> Result<IndexConfig, SchemaError> {
+        if index_id == catalog::CATALOG_INDEX_ID {
+            return Ok(catalog::config());
+        }
+
+        let bundled = self
             .config
+            .as_ref()
+            .map_err(Clone::clone)?
             .indexes
             .iter()
-            .find(|config| index_id.starts_with(&config.prefix))
-            .ok_or_else(|| {
-                ServiceError::not_found(&format!("Schema for index [{}] not found", index_id))
-            })?;
+            .find(|config| tenant::unscope(index_id).starts_with(&config.prefix));
+
+        match bundled {
+            Some(config) => Ok(IndexConfig {
+                prefix: tenant::scope(tenant::tenant_of(index_id), &config.prefix),
+                ..config.clone()
+            }),
+            None => self
+                .created_config(index_id)?
+                .ok_or_else(|| SchemaError::IndexNotFound(index_id.into())),
+        }
+    }
+
+    fn created_config(&self, index_id: &str) -> Result<Option<IndexConfig>, SchemaError> {
+        let store = match &self.store {
+            Some(store) => store,
+            None => return Ok(None),
+        };
+
+        if let Some(config) = self.created.read().unwrap().get(index_id) {
+            return Ok(Some(config.clone()));
+        }
+
+        let definition = util::block_on(store.get_config(index_id))
+            .map_err(|err| SchemaError::StoreUnavailable(err.to_string()))?;
+        let config = match definition {
+            Some(definition) => parse_index_config(index_id, definition)?,
+            None => return Ok(None),
+        };
+
+        self.created
+            .write()
+            .unwrap()
+            .insert(index_id.into(), config.clone());
+        Ok(Some(config))
+    }
+}
+
+#[async_trait]
+impl SchemaLoader for SchemaProvider {
+    fn index_prefix(&self, index_id: &str) -> Option<String> {
+        self.index_config(index_id)
+            .ok()
+            .map(|config| config.prefix.clone())
+    }
+
+    fn load_settings(&self, index_id: &str) -> Result<IndexSettings, SchemaError> {
+        Ok(self.index_config(index_id)?.settings.clone())
+    }
+
+    fn load_schema_version(&self, index_id: &str) -> Result<String, SchemaError> {
+        let fields = json::to_vec(&self.index_config(index_id)?.fields)
+            .expect("field config should serialize");
+        Ok(fingerprint(&fields))
+    }
+
+    fn load_analyzers(&self, index_id: &str) -> Result<Vec<(String, TextAnalyzer)>, SchemaError> {
+        let config = self.index_config(index_id)?;
+
+        Ok(config
+            .fields
+            .iter()
+            .filter_map(FieldConfig::custom_analyzer)
+            .map(|(name, analyzer)| (FieldAnalyzer::tokenizer_name(name), analyzer.build()))
+            .collect())
+    }
+
+    fn load_schema(&self, index_id: &str) -> Result<Schema, SchemaError> {
+        let config = self.index_config(index_id)?;
 
         let mut schema = Schema::builder();
 
         for field in &config.fields {
             match &field {
-                FieldConfig::TextFieldConfig { name, flags } => {
-                    let field_opts =
+                FieldConfig::TextFieldConfig {
+                    name,
+                    flags,
+                    tokenizer,
+                    language,
+                    ..
+                } => {
+                    let mut field_opts =
                         flags
                             .iter()
                             .fold(TextOptions::default(), |acc, opt| match opt {
                                 TextFieldOption::TEXT => acc | schema::TEXT,
                                 TextFieldOption::STRING => acc | schema::STRING,
                                 TextFieldOption::FAST => acc | schema::FAST,
+                                TextFieldOption::STORED => acc | schema::STORED,
                             });
+
+                    let tokenizer = match language {
+                        _ if field.custom_analyzer().is_some() => {
+                            Some(FieldAnalyzer::tokenizer_name(name))
+                        }
+                        Some(language) => Some(language.tokenizer_name()),
+                        None => tokenizer.as_ref().map(TokenizerConfig::name),
+                    };
+
+                    if let Some(tokenizer) = tokenizer {
+                        let indexing =
+                            field_opts
+                                .get_indexing_options()
+                                .cloned()
+                                .unwrap_or_else(|| {
+                                    TextFieldIndexing::default()
+                                        .set_index_option(IndexRecordOption::WithFreqsAndPositions)
+                                });
+                        field_opts =
+                            field_opts.set_indexing_options(indexing.set_tokenizer(&tokenizer));
+                    }
+
                     schema.add_text_field(name, field_opts);
                 }
                 FieldConfig::DateFieldConfig { name, flags } => {
@@ -147,12 +917,53 @@ impl SchemaLoader for SchemaProvider {
                 FieldConfig::IntegerFieldConfig { name, flags } => {
                     schema.add_i64_field(name, numeric_field_options(flags));
                 }
+                FieldConfig::UnsignedIntegerFieldConfig { name, flags } => {
+                    schema.add_u64_field(name, numeric_field_options(flags));
+                }
+                FieldConfig::FloatFieldConfig { name, flags } => {
+                    schema.add_f64_field(name, numeric_field_options(flags));
+                }
+                FieldConfig::BytesFieldConfig { name, flags } => {
+                    let field_opts =
+                        flags
+                            .iter()
+                            .fold(BytesOptions::default(), |acc, opt| match opt {
+                                BytesFieldOption::INDEXED => acc | schema::INDEXED,
+                                BytesFieldOption::STORED => acc | schema::STORED,
+                                BytesFieldOption::FAST => acc | schema::FAST,
+                            });
+                    schema.add_bytes_field(name, field_opts);
+                }
+                FieldConfig::IpFieldConfig { name, flags } => {
+                    let field_opts =
+                        flags
+                            .iter()
+                            .fold(TextOptions::default(), |acc, opt| match opt {
+                                IpFieldOption::INDEXED => acc.set_indexing_options(
+                                    TextFieldIndexing::default()
+                                        .set_tokenizer(IP_TOKENIZER)
+                                        .set_index_option(IndexRecordOption::Basic),
+                                ),
+                                IpFieldOption::STORED => acc.set_stored(),
+                            });
+                    schema.add_text_field(name, field_opts);
+                }
+                FieldConfig::FacetFieldConfig { name, flags } => {
+                    let field_opts =
+                        flags
+                            .iter()
+                            .fold(FacetOptions::default(), |acc, opt| match opt {
+                                FacetFieldOption::STORED => acc.set_stored(),
+                            });
+                    schema.add_facet_field(name, field_opts);
+                }
                 FieldConfig::JsonFieldConfig { name, flags } => {
                     let field_opts =
                         flags
                             .iter()
                             .fold(TextOptions::default(), |acc, opt| match opt {
                                 JsonFieldOption::TEXT => acc | schema::TEXT,
+                                JsonFieldOption::STORED => acc | schema::STORED,
                             });
                     schema.add_json_field(name, field_opts);
                 }
@@ -164,8 +975,139 @@ impl SchemaLoader for SchemaProvider {
         // __id is the document id used for uniqueness
         schema.add_text_field("__id", schema::STRING | schema::STORED);
 
+        // __id_key orders equally ranked matches
+        schema.add_u64_field(ID_KEY_FIELD, schema::FAST);
+
         Ok(schema.build())
     }
+
+    async fn delete_config(&self, index_id: &str) -> Result<(), SchemaError> {
+        self.created.write().unwrap().remove(index_id);
+
+        match &self.store {
+            Some(store) => store
+                .delete_config(index_id)
+                .await
+                .map_err(|err| SchemaError::StoreUnavailable(err.to_string())),
+            None => Ok(()),
+        }
+    }
+}
+
+fn is_dynamic_field_name(name: &str) -> bool {
+    schema::is_valid_field_name(name) && !name.starts_with("__") && !name.contains('.')
+}
+
+fn derive_field(name: &str, value: &json::Value) -> Option<FieldEntry> {
+    // Segments written before a field existed have no fieldnorms for it, so derived fields are
+    // indexed without them.
+    let text_indexing = || TextFieldIndexing::default().set_fieldnorms(false);
+
+    match value {
+        json::Value::Array(values) => values
+            .iter()
+            .find(|value| !value.is_null())
+            .and_then(|value| derive_field(name, value)),
+        json::Value::String(text) if chrono::DateTime::parse_from_rfc3339(text).is_ok() => Some(
+            FieldEntry::new_date(name.into(), NumericOptions::default().set_indexed()),
+        ),
+        json::Value::String(_) => Some(FieldEntry::new_text(
+            name.into(),
+            TextOptions::default().set_indexing_options(
+                text_indexing().set_index_option(IndexRecordOption::WithFreqsAndPositions),
+            ),
+        )),
+        json::Value::Number(_) => Some(FieldEntry::new_f64(
+            name.into(),
+            NumericOptions::default().set_indexed(),
+        )),
+        json::Value::Object(_) => Some(FieldEntry::new_json(
+            name.into(),
+            TextOptions::default()
+                .set_indexing_options(
+                    text_indexing().set_index_option(IndexRecordOption::WithFreqsAndPositions),
+                )
+                .into(),
+        )),
+        _ => None,
+    }
+}
+
+/// Fields for the keys of `documents` that `schema` doesn't define, typed by the first value
+/// seen: RFC 3339 strings as dates, other strings as full-text, numbers as `f64` and objects as
+/// `json`. Keys with only booleans or nulls, reserved `__` keys and keys containing `.` are
+/// skipped.
+pub fn derive_fields<'a>(
+    schema: &Schema,
+    documents: impl IntoIterator<Item = &'a json::Map<String, json::Value>>,
+) -> Vec<FieldEntry> {
+    let mut fields: Vec<FieldEntry> = vec![];
+
+    for document in documents {
+        for (name, value) in document {
+            if !is_dynamic_field_name(name)
+                || schema.get_field(name).is_some()
+                || fields.iter().any(|field| field.name() == name)
+            {
+                continue;
+            }
+
+            if let Some(field) = derive_field(name, value) {
+                fields.push(field);
+            }
+        }
+    }
+
+    fields
+}
+
+/// Stable identifier for a schema's field definitions, formatted as 16 hex characters.
+pub fn schema_fingerprint(schema: &Schema) -> String {
+    fingerprint(&json::to_vec(schema).expect("schema should serialize"))
+}
+
+/// How a configured schema relates to the schema an existing index was created with.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SchemaChange {
+    /// Field definitions match. Query time settings such as field boosts apply without reindexing.
+    Unchanged,
+
+    /// Field definitions differ from the indexed data, with the reasons why. The index keeps
+    /// its original schema until it is rebuilt.
+    ReindexRequired(Vec<String>),
+}
+
+pub fn diff_schema(current: &Schema, configured: &Schema) -> SchemaChange {
+    let mut reasons = vec![];
+
+    for (_, entry) in configured.fields() {
+        match current.get_field(entry.name()) {
+            // Indexes built before ties were ordered by it order them without it until they're
+            // rebuilt.
+            None if entry.name() == ID_KEY_FIELD => {}
+            None => reasons.push(format!("field [{}] added", entry.name())),
+            Some(field) if current.get_field_entry(field) != entry => {
+                reasons.push(format!("field [{}] changed", entry.name()))
+            }
+            Some(_) => {}
+        }
+    }
+
+    for (_, entry) in current.fields() {
+        if configured.get_field(entry.name()).is_none() {
+            reasons.push(format!("field [{}] removed", entry.name()));
+        }
+    }
+
+    if reasons.is_empty() && current != configured {
+        reasons.push(String::from("fields reordered"));
+    }
+
+    if reasons.is_empty() {
+        SchemaChange::Unchanged
+    } else {
+        SchemaChange::ReindexRequired(reasons)
+    }
 }
 
 #[cfg(test)]
@@ -200,6 +1142,26 @@ mod tests {
                             "flags": ["INDEXED", "FAST"],
                             "kind": "i64",
                         },
+                        {
+                            "name": "stock",
+                            "flags": ["INDEXED", "STORED"],
+                            "kind": "u64",
+                        },
+                        {
+                            "name": "price",
+                            "flags": ["INDEXED", "FAST"],
+                            "kind": "f64",
+                        },
+                        {
+                            "name": "client_ip",
+                            "flags": ["INDEXED", "STORED"],
+                            "kind": "ip",
+                        },
+                        {
+                            "name": "thumbnail",
+                            "flags": ["STORED"],
+                            "kind": "bytes",
+                        },
                         {
                             "name": "meta",
                             "flags": ["TEXT"],
@@ -227,4 +1189,138 @@ mod tests {
 
         println!("{}", json::to_string_pretty(&schema).expect("ok"));
     }
+
+    #[test]
+    fn derive_fields_from_documents() {
+        let mut schema = Schema::builder();
+        schema.add_text_field("title", schema::TEXT);
+        let schema = schema.build();
+
+        let documents = [
+            json!({
+                "__id": "a",
+                "title": "hello",
+                "tags": ["x"],
+                "published": "2022-11-14T21:30:04Z",
+                "rating": 4.5,
+                "meta": { "author": "smith" },
+                "in_stock": true,
+            }),
+            json!({ "rating": "high", "pages": 100 }),
+        ];
+
+        let fields = derive_fields(
+            &schema,
+            documents
+                .iter()
+                .map(|document| document.as_object().unwrap()),
+        );
+
+        let kinds = fields
+            .iter()
+            .map(|field| (field.name(), field.field_type().value_type()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            vec![
+                ("meta", schema::Type::Json),
+                ("published", schema::Type::Date),
+                ("rating", schema::Type::F64),
+                ("tags", schema::Type::Str),
+                ("pages", schema::Type::F64),
+            ],
+            kinds
+        );
+        assert!(fields.iter().all(|field| !field.has_fieldnorms()));
+    }
+
+    #[test]
+    fn diff_schema_ignores_settings() {
+        let config = |flags: &str, boost: f32| {
+            SchemaProvider::from_json(json!({
+                "indexes": [{
+                    "prefix": "test",
+                    "fields": [{ "name": "title", "kind": "text", "flags": [flags] }],
+                    "settings": { "field_boosts": { "title": boost } }
+                }]
+            }))
+            .load_schema("test")
+            .unwrap()
+        };
+
+        assert_eq!(
+            SchemaChange::Unchanged,
+            diff_schema(&config("TEXT", 1.0), &config("TEXT", 2.0))
+        );
+        assert_eq!(
+            SchemaChange::ReindexRequired(vec![String::from("field [title] changed")]),
+            diff_schema(&config("TEXT", 1.0), &config("STRING", 1.0))
+        );
+    }
+
+    #[test]
+    fn schema_version_tracks_field_config() {
+        let version = |field: json::Value, boost: f32| {
+            SchemaProvider::from_json(json!({
+                "indexes": [{
+                    "prefix": "test",
+                    "fields": [field],
+                    "settings": { "field_boosts": { "title": boost } }
+                }]
+            }))
+            .load_schema_version("test")
+            .unwrap()
+        };
+
+        let title = json!({ "name": "title", "kind": "text", "flags": ["TEXT"] });
+        let with_stopwords = json!({
+            "name": "title",
+            "kind": "text",
+            "flags": ["TEXT"],
+            "stopwords": { "words": ["the"] }
+        });
+
+        assert_eq!(version(title.clone(), 1.0), version(title.clone(), 2.0));
+        assert_ne!(version(title, 1.0), version(with_stopwords, 1.0));
+    }
+
+    #[test]
+    fn schema_errors_are_typed() {
+        let config = |kind: &str| {
+            json!({
+                "indexes": [{
+                    "prefix": "test",
+                    "fields": [{ "name": "location", "kind": kind, "flags": [] }]
+                }]
+            })
+        };
+
+        assert_eq!(
+            Err(SchemaError::UnsupportedFieldKind {
+                field: "location".into(),
+                kind: "geo".into()
+            }),
+            parse_config(config("geo")).map(|_| ())
+        );
+        assert!(matches!(
+            parse_config(json!({ "indexes": "test" })),
+            Err(SchemaError::InvalidConfig(_))
+        ));
+
+        let not_found = SchemaProvider::from_json(config("ip"))
+            .load_schema("other")
+            .unwrap_err();
+        assert_eq!(SchemaError::IndexNotFound("other".into()), not_found);
+        assert_eq!(404, ServiceError::from(not_found).status());
+
+        let invalid = SchemaProvider {
+            config: Err(SchemaError::InvalidConfig("bad".into())),
+            store: None,
+            created: Default::default(),
+        };
+        assert_eq!(
+            500,
+            ServiceError::from(invalid.load_settings("test").unwrap_err()).status()
+        );
+    }
 }
@@ -1,11 +1,15 @@
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Map, Value};
-use tantivy::schema::{DocParsingError, Schema};
+use tantivy::schema::{DocParsingError, FieldType, Schema};
+use tantivy::tokenizer::{SimpleTokenizer, Tokenizer};
 use tantivy::Document;
 use thiserror::Error;
+use tracing::warn;
 
+use crate::schema::{id_key, is_ip_field, IndexSettings, NullValue, OversizePolicy, ID_KEY_FIELD};
 use crate::serialize::compressed_json;
-use crate::util;
+use crate::{tokenizer, util};
 
 #[derive(Debug, Error, PartialEq, Eq)]
 pub enum SearchDocError {
@@ -20,6 +24,19 @@ pub enum SearchDocError {
 
     #[error("cannot index empty document")]
     EmptyDocument,
+
+    #[error("__id cannot be changed by an update")]
+    IdMismatch,
+
+    #[error("document is {size} bytes, over the limit of {limit}")]
+    DocumentTooLarge { size: usize, limit: usize },
+
+    #[error("The field '{field}' is over the limit of {limit} {unit}")]
+    FieldTooLong {
+        field: String,
+        limit: usize,
+        unit: &'static str,
+    },
 }
 
 impl From<DocParsingError> for SearchDocError {
@@ -28,6 +45,14 @@ impl From<DocParsingError> for SearchDocError {
     }
 }
 
+/// A problem with one field of a document, or with the whole document when `field` is unset.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FieldError {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    pub message: String,
+}
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
 pub struct DDBKey {
     pub pk: String,
@@ -76,10 +101,210 @@ pub struct SearchDoc {
     content: Map<String, Value>,
 }
 
+/// Converts a JSON value into the representation tantivy expects for a numeric field: numeric
+/// strings are parsed and integral floats such as `3.0` become integers. Other values are left
+/// for schema validation to reject.
+fn coerce_number(field_type: &FieldType, value: &Value) -> Option<Value> {
+    let number = match value {
+        Value::String(text) => text.trim().parse::<serde_json::Number>().ok()?,
+        Value::Number(number) => number.clone(),
+        _ => return None,
+    };
+
+    match field_type {
+        FieldType::I64(_) => number
+            .as_i64()
+            .or_else(|| integral(number.as_f64()?).map(|value| value as i64))
+            .map(Value::from),
+        FieldType::U64(_) => number
+            .as_u64()
+            .or_else(|| {
+                integral(number.as_f64()?)
+                    .filter(|value| *value >= 0.0)
+                    .map(|value| value as u64)
+            })
+            .map(Value::from),
+        FieldType::F64(_) => number.as_f64().map(Value::from),
+        _ => None,
+    }
+}
+
+/// Rewrites date strings without an offset as RFC 3339 in `time_zone`, which is what tantivy
+/// expects for date fields.
+fn coerce_date(value: &Value, time_zone: Tz) -> Option<Value> {
+    let date = util::parse_date(value.as_str()?, time_zone)?;
+    Some(Value::String(date.to_rfc3339()))
+}
+
+fn integral(value: f64) -> Option<f64> {
+    (value.fract() == 0.0 && value.is_finite()).then_some(value)
+}
+
+fn coerce_values(
+    schema: &Schema,
+    json_object: &mut Map<String, Value>,
+    time_zone: Tz,
+) -> Result<(), SearchDocError> {
+    for (name, value) in json_object.iter_mut() {
+        let entry = match schema.get_field(name) {
+            Some(field) => schema.get_field_entry(field),
+            None => continue,
+        };
+        let field_type = entry.field_type();
+
+        let values = match value {
+            Value::Array(values) => values.iter_mut().collect::<Vec<_>>(),
+            value => vec![value],
+        };
+
+        for value in values {
+            if is_ip_field(entry) && value.as_str().and_then(tokenizer::parse_ip).is_none() {
+                return Err(SearchDocError::SchemaValidationError(format!(
+                    "The field '{}' could not be parsed: expected an IP address, found {}",
+                    name, value
+                )));
+            }
+
+            let coerced = match field_type {
+                FieldType::Date(_) => coerce_date(value, time_zone),
+                field_type => coerce_number(field_type, value),
+            };
+
+            if let Some(coerced) = coerced {
+                *value = coerced;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Byte offset `text` would be cut at to fit in `limit` characters, if it's over.
+fn char_limit(text: &str, limit: usize) -> Option<usize> {
+    text.char_indices().nth(limit).map(|(offset, _)| offset)
+}
+
+/// Byte offset `text` would be cut at to fit in `limit` tokens, if it's over.
+fn token_limit(text: &str, limit: usize) -> Option<usize> {
+    let mut tokens = SimpleTokenizer.token_stream(text);
+    let mut count = 0;
+    let mut end = 0;
+    while tokens.advance() {
+        if count == limit {
+            return Some(end);
+        }
+        count += 1;
+        end = tokens.token().offset_to;
+    }
+    None
+}
+
+/// Enforces the size limits of text fields, which in dynamic indexes includes fields that aren't
+/// in the schema yet. Values over a limit are truncated or rejected, per the index's
+/// `oversized_fields` policy.
+fn limit_values(
+    schema: &Schema,
+    json_object: &mut Map<String, Value>,
+    settings: &IndexSettings,
+) -> Result<(), SearchDocError> {
+    for (name, value) in json_object.iter_mut() {
+        let is_text = match schema.get_field(name) {
+            Some(field) => matches!(
+                schema.get_field_entry(field).field_type(),
+                FieldType::Str(_)
+            ),
+            None => settings.dynamic,
+        };
+        if !is_text || name == "__id" {
+            continue;
+        }
+
+        let limits = settings.field_limits(name);
+
+        let values = match value {
+            Value::Array(values) => values.iter_mut().collect::<Vec<_>>(),
+            value => vec![value],
+        };
+
+        for value in values {
+            let text = match value {
+                Value::String(text) => text,
+                _ => continue,
+            };
+
+            let over = [
+                limits
+                    .max_field_chars
+                    .and_then(|limit| Some((limit, "characters", char_limit(text, limit)?))),
+                limits
+                    .max_field_tokens
+                    .and_then(|limit| Some((limit, "tokens", token_limit(text, limit)?))),
+            ];
+
+            for (limit, unit, offset) in over.into_iter().flatten() {
+                match settings.oversized_fields {
+                    OversizePolicy::Reject => {
+                        return Err(SearchDocError::FieldTooLong {
+                            field: name.clone(),
+                            limit,
+                            unit,
+                        })
+                    }
+                    OversizePolicy::Truncate => {
+                        warn!(message = "field_truncated", field = name, limit, unit);
+                        // Both offsets fall within the original text, so this keeps the shorter.
+                        text.truncate(offset.min(text.len()));
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Applies the index's `null_values` to the fields the document leaves out or sets to `null`.
+/// Sentinels are only indexed, by [`SearchDoc::document_with_settings`], so their fields are
+/// left out like skipped ones.
+fn fill_null_values(json_object: &mut Map<String, Value>, settings: &IndexSettings) {
+    for (name, null_value) in &settings.null_values {
+        if json_object.get(name).is_some_and(|value| !value.is_null()) {
+            continue;
+        }
+        match null_value {
+            NullValue::Default(value) => {
+                json_object.insert(name.clone(), value.clone());
+            }
+            NullValue::Skip | NullValue::Sentinel(_) => {
+                json_object.remove(name);
+            }
+        }
+    }
+}
+
 impl SearchDoc {
     /// Converts a JSON value into a SearchDoc if the document is valid according to the schema.
     /// Also generate an `__id` if no `__id` is present.
     pub fn from_json(schema: &Schema, json_value: Value) -> Result<SearchDoc, SearchDocError> {
+        SearchDoc::from_json_with_settings(schema, json_value, &IndexSettings::default())
+    }
+
+    /// Like [`SearchDoc::from_json`], reading dates without an offset in the index's time zone.
+    /// Dynamic indexes accept documents made up entirely of fields outside the schema.
+    pub fn from_json_with_settings(
+        schema: &Schema,
+        json_value: Value,
+        settings: &IndexSettings,
+    ) -> Result<SearchDoc, SearchDocError> {
+        if let Some(limit) = settings.max_doc_bytes {
+            let size = serde_json::to_vec(&json_value)
+                .expect("json value should serialize")
+                .len();
+            if size > limit {
+                return Err(SearchDocError::DocumentTooLarge { size, limit });
+            }
+        }
+
         let mut json_object = match json_value {
             Value::Object(obj) => obj,
             _ => return Err(SearchDocError::NotAnObject),
@@ -89,13 +314,23 @@ impl SearchDoc {
             .entry("__id")
             .or_insert_with(|| json!(util::generate_id()))
             .as_str()
-            .ok_or_else(|| SearchDocError::InvalidIdType)?
+            .ok_or(SearchDocError::InvalidIdType)?
             .to_string();
 
+        fill_null_values(&mut json_object, settings);
+        coerce_values(schema, &mut json_object, settings.time_zone())?;
+        limit_values(schema, &mut json_object, settings)?;
+
         // Validate the document against the provided schema.
         let document = schema.json_object_to_doc(json_object.clone())?;
 
-        if document.field_values().len() <= 1 {
+        let is_empty = if settings.dynamic {
+            json_object.len() <= 1
+        } else {
+            document.field_values().len() <= 1
+        };
+
+        if is_empty {
             return Err(SearchDocError::EmptyDocument);
         }
 
@@ -105,14 +340,153 @@ impl SearchDoc {
         })
     }
 
+    /// Checks every field of `json_value` against the schema, rather than stopping at the first
+    /// invalid one like [`SearchDoc::from_json_with_settings`]. Problems with the document as a
+    /// whole are only reported once its fields are valid.
+    pub fn validate_fields(
+        schema: &Schema,
+        json_value: &Value,
+        settings: &IndexSettings,
+    ) -> Vec<FieldError> {
+        let mut errors = vec![];
+
+        for (name, value) in json_value.as_object().into_iter().flatten() {
+            if name == "__id" || value.is_null() && settings.null_values.contains_key(name) {
+                continue;
+            }
+
+            let mut field_object = Map::new();
+            field_object.insert(name.clone(), value.clone());
+
+            let result = coerce_values(schema, &mut field_object, settings.time_zone())
+                .and_then(|_| limit_values(schema, &mut field_object, settings))
+                .and_then(|_| Ok(schema.json_object_to_doc(field_object)?));
+
+            if let Err(err) = result {
+                errors.push(FieldError {
+                    field: Some(name.clone()),
+                    message: err.to_string(),
+                });
+            }
+        }
+
+        if errors.is_empty() {
+            if let Err(err) =
+                SearchDoc::from_json_with_settings(schema, json_value.clone(), settings)
+            {
+                errors.push(FieldError {
+                    field: None,
+                    message: err.to_string(),
+                });
+            }
+        }
+
+        errors
+    }
+
+    /// Merges the top-level fields of `patch` into this document, removing fields set to `null`,
+    /// and validates the result against the schema.
+    pub fn merge(
+        &self,
+        schema: &Schema,
+        patch: Value,
+        settings: &IndexSettings,
+    ) -> Result<SearchDoc, SearchDocError> {
+        let patch = match patch {
+            Value::Object(obj) => obj,
+            _ => return Err(SearchDocError::NotAnObject),
+        };
+
+        let mut content = self.content.clone();
+
+        for (key, value) in patch {
+            if key == "__id" {
+                if value.as_str() != Some(self.id.id()) {
+                    return Err(SearchDocError::IdMismatch);
+                }
+                continue;
+            }
+
+            if value.is_null() {
+                content.remove(&key);
+            } else {
+                content.insert(key, value);
+            }
+        }
+
+        SearchDoc::from_json_with_settings(schema, Value::Object(content), settings)
+    }
+
     pub fn id(&self) -> &SearchDocId {
         &self.id
     }
 
+    pub fn content(&self) -> &Map<String, Value> {
+        &self.content
+    }
+
+    pub fn content_mut(&mut self) -> &mut Map<String, Value> {
+        &mut self.content
+    }
+
+    /// Converts the document for indexing with `schema`. Values that don't fit their field are
+    /// dropped, which only happens for fields a dynamic index derived from earlier documents.
     pub fn document(&self, schema: &Schema) -> Document {
-        schema
-            .json_object_to_doc(self.content.clone())
-            .expect("should succeed since from_json validates")
+        let mut document = Document::default();
+
+        for (name, value) in &self.content {
+            let field = match schema.get_field(name) {
+                Some(field) if name != ID_KEY_FIELD => field,
+                _ => continue,
+            };
+            let field_type = schema.get_field_entry(field).field_type();
+
+            let values = match value {
+                Value::Array(values) => values.iter().collect::<Vec<_>>(),
+                value => vec![value],
+            };
+
+            for value in values {
+                match field_type.value_from_json(value.clone()) {
+                    Ok(value) => document.add_field_value(field, value),
+                    Err(_) => warn!(
+                        message = "dynamic_value_dropped",
+                        doc_id = self.id.id(),
+                        field = name
+                    ),
+                }
+            }
+        }
+
+        document
+    }
+
+    /// Like [`SearchDoc::document`], also indexing the document's `__id_key` and the sentinels of
+    /// the index's `null_values` for fields the document has no values for.
+    pub fn document_with_settings(&self, schema: &Schema, settings: &IndexSettings) -> Document {
+        let mut document = self.document(schema);
+
+        if let Some(field) = schema.get_field(ID_KEY_FIELD) {
+            document.add_u64(field, id_key(self.id.id()));
+        }
+
+        for (name, null_value) in &settings.null_values {
+            let (NullValue::Sentinel(sentinel), Some(field)) = (null_value, schema.get_field(name))
+            else {
+                continue;
+            };
+            if document.get_first(field).is_some() {
+                continue;
+            }
+
+            let field_type = schema.get_field_entry(field).field_type();
+            match field_type.value_from_json(sentinel.clone()) {
+                Ok(value) => document.add_field_value(field, value),
+                Err(_) => warn!(message = "sentinel_dropped", field = name),
+            }
+        }
+
+        document
     }
 }
 
@@ -121,6 +495,7 @@ mod tests {
     use tantivy::schema;
 
     use super::*;
+    use crate::schema::FieldLimits;
 
     fn setup() -> Schema {
         let mut schema = Schema::builder();
@@ -138,7 +513,7 @@ mod tests {
 
         let search_doc = SearchDoc::from_json(&schema, value).unwrap();
 
-        assert!(search_doc.id.0.len() > 0);
+        assert!(!search_doc.id.0.is_empty());
     }
 
     #[test]
@@ -168,4 +543,222 @@ mod tests {
             search_doc,
         );
     }
+
+    #[test]
+    fn from_json_with_settings_enforces_size_limits() {
+        let schema = setup();
+        let doc = json!({ "__id": "foo", "name": "the quick brown fox" });
+
+        let mut settings = IndexSettings {
+            max_doc_bytes: Some(16),
+            ..Default::default()
+        };
+        assert_eq!(
+            SearchDocError::DocumentTooLarge {
+                size: 43,
+                limit: 16
+            },
+            SearchDoc::from_json_with_settings(&schema, doc.clone(), &settings).unwrap_err()
+        );
+
+        settings.max_doc_bytes = None;
+        settings.default_field_limits.max_field_tokens = Some(3);
+        assert_eq!(
+            SearchDocError::FieldTooLong {
+                field: "name".into(),
+                limit: 3,
+                unit: "tokens"
+            },
+            SearchDoc::from_json_with_settings(&schema, doc.clone(), &settings).unwrap_err()
+        );
+
+        settings.oversized_fields = OversizePolicy::Truncate;
+        let search_doc =
+            SearchDoc::from_json_with_settings(&schema, doc.clone(), &settings).unwrap();
+        assert_eq!(json!("the quick brown"), search_doc.content["name"]);
+
+        settings.field_limits.insert(
+            "name".into(),
+            FieldLimits {
+                max_field_chars: Some(6),
+                max_field_tokens: None,
+            },
+        );
+        let search_doc = SearchDoc::from_json_with_settings(&schema, doc, &settings).unwrap();
+        assert_eq!(json!("the qu"), search_doc.content["name"]);
+    }
+
+    #[test]
+    fn merge_updates_and_removes_fields() {
+        let mut schema = Schema::builder();
+        schema.add_text_field("__id", schema::STRING);
+        schema.add_text_field("name", schema::STRING);
+        schema.add_text_field("nickname", schema::STRING);
+        let schema = schema.build();
+
+        let search_doc = SearchDoc::from_json(
+            &schema,
+            json!({ "__id": "foo", "name": "hello", "nickname": "world" }),
+        )
+        .unwrap();
+
+        let merged = search_doc
+            .merge(
+                &schema,
+                json!({ "name": "goodbye", "nickname": null }),
+                &IndexSettings::default(),
+            )
+            .unwrap();
+
+        assert_eq!(
+            json!({ "__id": "foo", "name": "goodbye" }),
+            Value::Object(merged.content)
+        );
+    }
+
+    #[test]
+    fn merge_rejects_id_change() {
+        let schema = setup();
+
+        let search_doc =
+            SearchDoc::from_json(&schema, json!({ "__id": "foo", "name": "hello" })).unwrap();
+
+        let err = search_doc
+            .merge(&schema, json!({ "__id": "bar" }), &IndexSettings::default())
+            .unwrap_err();
+
+        assert_eq!(SearchDocError::IdMismatch, err);
+    }
+
+    #[test]
+    fn from_json_coerces_numbers() {
+        let mut schema = Schema::builder();
+        schema.add_text_field("__id", schema::STRING);
+        schema.add_i64_field("count", schema::INDEXED);
+        schema.add_u64_field("stock", schema::INDEXED);
+        schema.add_f64_field("price", schema::INDEXED);
+        let schema = schema.build();
+
+        let value = json!({ "count": 3.0, "stock": ["7", 8], "price": "12.5" });
+
+        let search_doc = SearchDoc::from_json(&schema, value).unwrap();
+
+        assert_eq!(json!(3), search_doc.content["count"]);
+        assert_eq!(json!([7, 8]), search_doc.content["stock"]);
+        assert_eq!(json!(12.5), search_doc.content["price"]);
+
+        let value = json!({ "count": 3.5 });
+
+        assert!(SearchDoc::from_json(&schema, value).is_err());
+    }
+
+    #[test]
+    fn from_json_validates_base64_bytes() {
+        let mut schema = Schema::builder();
+        schema.add_text_field("__id", schema::STRING);
+        schema.add_bytes_field("thumbnail", schema::STORED);
+        let schema = schema.build();
+
+        let search_doc = SearchDoc::from_json(&schema, json!({ "thumbnail": "aGVsbG8=" })).unwrap();
+
+        assert_eq!(json!("aGVsbG8="), search_doc.content["thumbnail"]);
+
+        let err = SearchDoc::from_json(&schema, json!({ "thumbnail": "not base64!" })).unwrap_err();
+
+        assert!(matches!(err, SearchDocError::SchemaValidationError(_)));
+    }
+
+    #[test]
+    fn from_json_with_settings_localizes_dates_without_offset() {
+        let mut schema = Schema::builder();
+        schema.add_text_field("__id", schema::STRING);
+        schema.add_date_field("published", schema::INDEXED);
+        let schema = schema.build();
+
+        let tz: Tz = "Europe/Paris".parse().unwrap();
+        let value = json!({ "published": ["2022-11-14", "2022-11-14T08:00:00Z"] });
+
+        let settings = IndexSettings {
+            time_zone: Some(tz),
+            ..Default::default()
+        };
+
+        let search_doc = SearchDoc::from_json_with_settings(&schema, value, &settings).unwrap();
+
+        assert_eq!(
+            json!(["2022-11-14T00:00:00+01:00", "2022-11-14T08:00:00+00:00"]),
+            search_doc.content["published"]
+        );
+    }
+
+    #[test]
+    fn from_json_validates_ip_addresses() {
+        let mut schema = Schema::builder();
+        schema.add_text_field("__id", schema::STRING);
+        schema.add_text_field(
+            "client_ip",
+            schema::TextOptions::default().set_indexing_options(
+                schema::TextFieldIndexing::default().set_tokenizer(tokenizer::IP_TOKENIZER),
+            ),
+        );
+        let schema = schema.build();
+
+        assert!(SearchDoc::from_json(&schema, json!({ "client_ip": "10.0.0.1" })).is_ok());
+        assert!(SearchDoc::from_json(&schema, json!({ "client_ip": "10.0.0.300" })).is_err());
+    }
+
+    #[test]
+    fn null_values_fill_defaults_and_index_sentinels() {
+        let mut schema = Schema::builder();
+        schema.add_text_field("__id", schema::STRING);
+        schema.add_text_field("name", schema::STRING);
+        let category = schema.add_text_field("category", schema::STRING);
+        let rank = schema.add_i64_field("rank", schema::INDEXED);
+        let schema = schema.build();
+
+        let settings = IndexSettings {
+            null_values: [
+                ("name".into(), NullValue::Skip),
+                ("category".into(), NullValue::Sentinel(json!("__missing__"))),
+                ("rank".into(), NullValue::Default(json!(0))),
+            ]
+            .into(),
+            ..Default::default()
+        };
+
+        let value = json!({ "__id": "a", "name": null, "category": null, "tags": "x" });
+        let search_doc = SearchDoc::from_json_with_settings(&schema, value, &settings).unwrap();
+
+        assert_eq!(
+            json!({ "__id": "a", "rank": 0, "tags": "x" }),
+            Value::Object(search_doc.content.clone())
+        );
+
+        let document = search_doc.document_with_settings(&schema, &settings);
+        assert_eq!(
+            Some("__missing__"),
+            document
+                .get_first(category)
+                .and_then(|value| value.as_text())
+        );
+        assert_eq!(
+            Some(0),
+            document.get_first(rank).and_then(|value| value.as_i64())
+        );
+
+        let value = json!({ "__id": "b", "category": "books", "rank": 3 });
+        let search_doc = SearchDoc::from_json_with_settings(&schema, value, &settings).unwrap();
+        let document = search_doc.document_with_settings(&schema, &settings);
+        assert_eq!(
+            vec!["books"],
+            document
+                .get_all(category)
+                .filter_map(|value| value.as_text())
+                .collect::<Vec<_>>()
+        );
+
+        // Without null values configured, null stays invalid for schema fields.
+        let value = json!({ "name": "world", "category": null });
+        assert!(SearchDoc::from_json(&schema, value).is_err());
+    }
 }
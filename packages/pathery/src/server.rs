@@ -0,0 +1,229 @@
+//! A standalone HTTP server running the API handlers in-process, for developing against Pathery
+//! locally before deploying the Lambda stack.
+//!
+//! Indexes, documents and job statuses are held in memory and lost when the server stops. Writes
+//! are indexed as they're submitted instead of being queued for the index writer, so they can be
+//! queried as soon as the request returns.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use http::Method;
+use hyper::service::{make_service_fn, service_fn};
+use lambda_http::{Body, RequestExt};
+use tracing::info;
+
+use crate::cursor::CursorKey;
+use crate::index::RamIndexLoader;
+use crate::schema::SchemaProvider;
+use crate::service::index::{
+    BatchIndexService, PostIndexService, QueryIndexService, StatsIndexService,
+};
+use crate::service::{map_error_response, ServiceError, ServiceHandler};
+use crate::store::change::MemoryChangeStore;
+use crate::store::document::MemoryDocumentStore;
+use crate::store::event::MemoryEventStore;
+use crate::store::job::MemoryJobStore;
+use crate::worker::index_writer::client::LocalIndexWriterClient;
+
+type Response = lambda_http::Response<Body>;
+
+pub struct LocalServer {
+    post_index: PostIndexService,
+
+    batch_index: BatchIndexService,
+
+    query_index: QueryIndexService,
+
+    stats_index: StatsIndexService,
+}
+
+fn query_params(query: Option<&str>) -> HashMap<String, Vec<String>> {
+    let mut params: HashMap<String, Vec<String>> = HashMap::new();
+    for (name, value) in form_urlencoded::parse(query.unwrap_or_default().as_bytes()) {
+        params
+            .entry(name.into_owned())
+            .or_default()
+            .push(value.into_owned());
+    }
+    params
+}
+
+impl LocalServer {
+    pub fn create(schema_loader: SchemaProvider) -> Self {
+        let index_loader = RamIndexLoader::create(schema_loader.clone());
+        let document_store = MemoryDocumentStore::create();
+        let job_store = MemoryJobStore::create();
+        let writer_client = LocalIndexWriterClient::create(
+            index_loader.clone(),
+            schema_loader.clone(),
+            document_store.clone(),
+            job_store.clone(),
+            MemoryChangeStore::create(),
+            MemoryEventStore::create(),
+        );
+
+        LocalServer {
+            post_index: PostIndexService::new(
+                Box::new(schema_loader.clone()),
+                Box::new(document_store.clone()),
+                Box::new(writer_client.clone()),
+                Box::new(job_store),
+            ),
+            batch_index: BatchIndexService::new(
+                Box::new(schema_loader.clone()),
+                Box::new(document_store.clone()),
+                Box::new(writer_client),
+            ),
+            query_index: QueryIndexService::new(
+                Box::new(schema_loader),
+                Box::new(index_loader.clone()),
+                Box::new(document_store),
+                CursorKey::generate(),
+            ),
+            stats_index: StatsIndexService::new(Box::new(index_loader)),
+        }
+    }
+
+    /// Routes `request` to the handler for its method and path, with the path and query string
+    /// parameters API Gateway would extract.
+    pub async fn handle_request(
+        &self,
+        request: lambda_http::Request,
+    ) -> Result<Response, lambda_http::Error> {
+        let method = request.method().clone();
+        let path = request.uri().path().to_string();
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+        let params = query_params(request.uri().query());
+        let request = request.with_query_string_parameters(params);
+        let with_index_id = |request: lambda_http::Request, index_id: &str| {
+            request.with_path_parameters(HashMap::from([(
+                String::from("index_id"),
+                String::from(index_id),
+            )]))
+        };
+
+        match (&method, segments.as_slice()) {
+            (&Method::POST, ["index", index_id]) => {
+                self.post_index
+                    .handle_event(with_index_id(request, index_id))
+                    .await
+            }
+            (&Method::POST, ["index", index_id, "batch"]) => {
+                self.batch_index
+                    .handle_event(with_index_id(request, index_id))
+                    .await
+            }
+            (&Method::GET | &Method::POST, ["index", index_id, "query"]) => {
+                self.query_index
+                    .handle_event(with_index_id(request, index_id))
+                    .await
+            }
+            (&Method::GET, ["index", index_id, "stats"]) => {
+                self.stats_index
+                    .handle_event(with_index_id(request, index_id))
+                    .await
+            }
+            _ => map_error_response(ServiceError::not_found(&format!(
+                "No route for {} {}",
+                method, path
+            ))),
+        }
+    }
+
+    async fn handle_hyper_request(
+        &self,
+        request: hyper::Request<hyper::Body>,
+    ) -> Result<hyper::Response<hyper::Body>, lambda_http::Error> {
+        let (parts, body) = request.into_parts();
+        let body = hyper::body::to_bytes(body).await?;
+        let body = if body.is_empty() {
+            Body::Empty
+        } else {
+            match String::from_utf8(body.to_vec()) {
+                Ok(text) => Body::Text(text),
+                Err(err) => Body::Binary(err.into_bytes()),
+            }
+        };
+
+        let response = self
+            .handle_request(http::Request::from_parts(parts, body))
+            .await?;
+
+        let (parts, body) = response.into_parts();
+        let body = match body {
+            Body::Empty => hyper::Body::empty(),
+            Body::Text(text) => hyper::Body::from(text),
+            Body::Binary(bytes) => hyper::Body::from(bytes),
+        };
+
+        Ok(hyper::Response::from_parts(parts, body))
+    }
+
+    /// Serves requests on `addr` until the process is stopped.
+    pub async fn serve(self, addr: SocketAddr) -> Result<(), hyper::Error> {
+        let server = Arc::new(self);
+
+        let make_service = make_service_fn(move |_| {
+            let server = server.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let server = server.clone();
+                    async move { server.handle_hyper_request(request).await }
+                }))
+            }
+        });
+
+        info!(message = "server_started", %addr);
+
+        hyper::Server::bind(&addr).serve(make_service).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[tokio::test]
+    async fn server_routes_requests_to_handlers() {
+        let server = LocalServer::create(setup().schema_loader().clone());
+
+        let request = |method: &str, uri: &str, body: Body| {
+            let request = http::Request::builder()
+                .method(method)
+                .uri(uri)
+                .body(body)
+                .unwrap();
+            server.handle_request(request)
+        };
+
+        let response = request(
+            "POST",
+            "/index/test",
+            Body::from(json!({ "__id": "a", "title": "Hello world" }).to_string()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(200, response.status());
+
+        // The write is indexed by the time the request returns.
+        let response = request("GET", "/index/test/query?q=hello", Body::Empty)
+            .await
+            .unwrap();
+        assert_eq!(200, response.status());
+        let body: json::Value = match response.body() {
+            Body::Text(body) => json::from_str(body).unwrap(),
+            body => panic!("expected text body, got {:?}", body),
+        };
+        assert_eq!(json!(["a"]), body["matches"][0]["doc"]["__id"]);
+
+        let response = request("DELETE", "/index/test/query", Body::Empty)
+            .await
+            .unwrap();
+        assert_eq!(404, response.status());
+    }
+}
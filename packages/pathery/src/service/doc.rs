@@ -2,8 +2,11 @@ use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use serde_json as json;
 
-use super::{ServiceHandler, ServiceRequest, ServiceResponse};
+use super::{ServiceError, ServiceHandler, ServiceRequest, ServiceResponse};
+use crate::schema::{SchemaLoader, SchemaProvider};
 use crate::search_doc::SearchDocId;
+use crate::store::document::{DDBDocumentStore, DocumentStore, SearchDocRef};
+use crate::util;
 use crate::worker::index_writer::client::{IndexWriterClient, LambdaIndexWriterClient};
 use crate::worker::index_writer::job::Job;
 
@@ -28,10 +31,11 @@ impl ServiceHandler<json::Value, DeleteDocResponse> for DeleteDocService {
         &self,
         request: ServiceRequest<json::Value>,
     ) -> ServiceResponse<DeleteDocResponse> {
-        let index_id = request.path_param("index_id")?;
+        let index_id = request.index_id()?;
         let doc_id = request.path_param("doc_id")?;
+        let token = request.write_token()?;
 
-        let mut job = Job::create(&index_id);
+        let mut job = Job::create(&index_id).with_token(token);
 
         job.delete_doc(SearchDocId::parse(&doc_id));
 
@@ -50,3 +54,154 @@ impl DeleteDocService {
         }
     }
 }
+
+#[derive(Serialize, Debug)]
+pub struct PatchDocResponse {
+    pub job_id: String,
+    pub updated_at: String,
+}
+
+pub struct PatchDocService {
+    schema_loader: Box<dyn SchemaLoader>,
+
+    document_store: Box<dyn DocumentStore>,
+
+    writer_client: Box<dyn IndexWriterClient>,
+}
+
+#[async_trait]
+impl ServiceHandler<json::Value, PatchDocResponse> for PatchDocService {
+    async fn handle_request(
+        &self,
+        request: ServiceRequest<json::Value>,
+    ) -> ServiceResponse<PatchDocResponse> {
+        let body = request.body()?;
+
+        let index_id = request.index_id()?;
+        let doc_id = request.path_param("doc_id")?;
+        let token = request.write_token()?;
+
+        let schema = self.schema_loader.load_schema(&index_id)?;
+        let settings = self.schema_loader.load_settings(&index_id)?;
+
+        let existing = self
+            .document_store
+            .get_documents(vec![SearchDocRef::from(SearchDocId::parse(&doc_id))])
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| ServiceError::not_found(&format!("Document [{}] not found", doc_id)))?;
+
+        let document = existing
+            .merge(&schema, body, &settings)
+            .map_err(|err| ServiceError::invalid_request(&err.to_string()))?;
+
+        let doc_refs = self.document_store.save_documents(vec![document]).await?;
+
+        let mut job = Job::create(&index_id).with_token(token);
+
+        for doc_ref in doc_refs {
+            job.index_doc(doc_ref);
+        }
+
+        let job_id = self.writer_client.submit_job(job).await?;
+
+        Ok(PatchDocResponse {
+            job_id,
+            updated_at: util::timestamp(),
+        })
+    }
+}
+
+impl PatchDocService {
+    pub async fn create() -> Self {
+        let document_store = DDBDocumentStore::create(None).await;
+        let writer_client = LambdaIndexWriterClient::create(None).await;
+        let schema_loader = SchemaProvider::lambda().await;
+
+        PatchDocService {
+            document_store: Box::new(document_store),
+            writer_client: Box::new(writer_client),
+            schema_loader: Box::new(schema_loader),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::IndexLoader;
+    use crate::test_utils::*;
+
+    fn test_service(ctx: &TestContext) -> PatchDocService {
+        PatchDocService {
+            schema_loader: Box::new(ctx.schema_loader().clone()),
+            document_store: Box::new(ctx.document_store().clone()),
+            writer_client: Box::new(ctx.writer_client().clone()),
+        }
+    }
+
+    #[tokio::test]
+    async fn patch_doc_merges_fields() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![json!({
+                    "__id": "foobar",
+                    "title": "hello",
+                    "author": "world"
+                })],
+            )
+            .await;
+
+        let service = test_service(&ctx);
+
+        let request = ServiceRequest::create(json!({ "title": "goodbye" }))
+            .with_path_param("index_id", "test")
+            .with_path_param("doc_id", "foobar");
+
+        service.handle_request(request).await.unwrap();
+
+        let searcher = ctx
+            .index_loader()
+            .load_index("test", None)
+            .unwrap()
+            .reader()
+            .unwrap()
+            .searcher();
+
+        assert_eq!(1, searcher.num_docs());
+
+        let stored = ctx
+            .document_store()
+            .get_documents(vec![SearchDocRef::from(SearchDocId::parse("foobar"))])
+            .await
+            .unwrap();
+        let schema = ctx.schema_loader().load_schema("test").unwrap();
+        let named_doc = schema.to_named_doc(&stored[0].document(&schema));
+
+        assert_eq!(
+            json!({
+                "__id": ["foobar"],
+                "title": ["goodbye"],
+                "author": ["world"],
+            }),
+            json::to_value(named_doc).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn patch_doc_not_found() {
+        let ctx = setup();
+
+        let service = test_service(&ctx);
+
+        let request = ServiceRequest::create(json!({ "title": "goodbye" }))
+            .with_path_param("index_id", "test")
+            .with_path_param("doc_id", "missing");
+
+        let err = service.handle_request(request).await.unwrap_err();
+
+        assert_eq!(404, err.status());
+    }
+}
@@ -0,0 +1,242 @@
+use async_graphql::{
+    Context, EmptyMutation, EmptySubscription, ErrorExtensions, Json, Object, Request, Response,
+    Schema,
+};
+use async_trait::async_trait;
+
+use super::index::{QueryIndexService, QueryRequest, SearchHit};
+use super::{ServiceError, ServiceHandler, ServiceRequest, ServiceResponse};
+use crate::lambda::http::Authorization;
+use crate::{json, tenant};
+
+pub type PatherySchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+impl ErrorExtensions for ServiceError {
+    fn extend(&self) -> async_graphql::Error {
+        let status = self.status();
+        async_graphql::Error::new(self.to_string()).extend_with(|_, e| e.set("status", status))
+    }
+}
+
+pub struct QueryRoot;
+
+/// Who made the request being executed.
+struct Caller {
+    tenant_id: Option<String>,
+
+    /// The API key the request was authorized with, if any.
+    authorization: Option<Authorization>,
+}
+
+impl Caller {
+    /// `index`, as named in the query, scoped to the caller's tenant once the caller's key is
+    /// checked for a grant on it, as [`ServiceRequest::authorize_index`] does for path params.
+    fn index_id(&self, index: &str) -> Result<String, ServiceError> {
+        if let Some(authorization) = &self.authorization {
+            authorization.check_index(index)?;
+        }
+
+        Ok(tenant::scope(self.tenant_id.as_deref(), index))
+    }
+}
+
+#[Object]
+impl QueryRoot {
+    /// Search an index with a query string.
+    async fn search(
+        &self,
+        ctx: &Context<'_>,
+        index: String,
+        query: String,
+    ) -> async_graphql::Result<Vec<Hit>> {
+        let service = ctx.data::<QueryIndexService>()?;
+        let index = ctx
+            .data::<Caller>()?
+            .index_id(&index)
+            .map_err(|err| err.extend())?;
+
+        let request = QueryRequest {
+            query: query.into(),
+            ..Default::default()
+        };
+
+        let response = service
+            .query(&index, request)
+            .await
+            .map_err(|err| err.extend())?;
+
+        Ok(response.matches.into_iter().map(Hit).collect())
+    }
+}
+
+pub struct Hit(SearchHit);
+
+#[Object]
+impl Hit {
+    async fn id(&self) -> Option<&str> {
+        self.0.doc.get("__id")?.get(0)?.as_str()
+    }
+
+    async fn score(&self) -> f32 {
+        self.0.score
+    }
+
+    /// The matched document, optionally restricted to the selected `fields`.
+    async fn doc(&self, fields: Option<Vec<String>>) -> Json<json::Value> {
+        let doc = match (fields, self.0.doc.as_object()) {
+            (Some(fields), Some(doc)) => json::Value::Object(
+                doc.iter()
+                    .filter(|(name, _)| fields.contains(name))
+                    .map(|(name, value)| (name.clone(), value.clone()))
+                    .collect(),
+            ),
+            _ => self.0.doc.clone(),
+        };
+
+        Json(doc)
+    }
+
+    async fn snippets(&self) -> Json<json::Value> {
+        Json(self.0.snippets.clone())
+    }
+}
+
+pub struct GraphQLService {
+    schema: PatherySchema,
+}
+
+#[async_trait]
+impl ServiceHandler<Request, Response> for GraphQLService {
+    async fn handle_request(&self, request: ServiceRequest<Request>) -> ServiceResponse<Response> {
+        let caller = Caller {
+            tenant_id: request.tenant()?,
+            authorization: request.authorization().cloned(),
+        };
+        let body = request.body()?.data(caller);
+
+        Ok(self.schema.execute(body).await)
+    }
+}
+
+impl GraphQLService {
+    pub async fn create() -> Self {
+        let query_service = QueryIndexService::create().await;
+
+        GraphQLService::new(query_service)
+    }
+
+    pub fn new(query_service: QueryIndexService) -> Self {
+        let schema = Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+            .data(query_service)
+            .finish();
+
+        GraphQLService { schema }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cursor::CursorKey;
+    use crate::store::api_key::{ApiKey, ApiKeyScope, IndexGrant};
+    use crate::test_utils::*;
+
+    fn test_service(ctx: &TestContext) -> GraphQLService {
+        GraphQLService::new(QueryIndexService::new(
+            Box::new(ctx.schema_loader().clone()),
+            Box::new(ctx.index_loader().clone()),
+            Box::new(ctx.document_store().clone()),
+            CursorKey::new(b"test"),
+        ))
+    }
+
+    #[tokio::test]
+    async fn graphql_search_with_field_selection() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![json!({
+                    "__id": "foobar",
+                    "title": "hello",
+                    "author": "world"
+                })],
+            )
+            .await;
+
+        let service = test_service(&ctx);
+
+        let request = ServiceRequest::create(Request::new(
+            r#"{ search(index: "test", query: "hello") { id doc(fields: ["title"]) } }"#,
+        ));
+
+        let response = service.handle_request(request).await.unwrap();
+
+        assert_eq!(
+            json!({
+                "search": [{
+                    "id": "foobar",
+                    "doc": { "title": ["hello"] }
+                }]
+            }),
+            response.data.into_json().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn graphql_search_invalid_query() {
+        let ctx = setup();
+
+        let service = test_service(&ctx);
+
+        let request = ServiceRequest::create(Request::new(
+            r#"{ search(index: "test", query: "title:") { id } }"#,
+        ));
+
+        let response = service.handle_request(request).await.unwrap();
+
+        assert_eq!(1, response.errors.len());
+        assert_eq!(
+            Some(&async_graphql::Value::from(400)),
+            response.errors[0]
+                .extensions
+                .as_ref()
+                .and_then(|extensions| extensions.get("status"))
+        );
+    }
+
+    #[tokio::test]
+    async fn graphql_search_checks_the_key_index_grants() {
+        let ctx = setup()
+            .with_documents("test", vec![json!({ "title": "hello" })])
+            .await;
+
+        let service = test_service(&ctx);
+
+        let request = ServiceRequest::create(Request::new(
+            r#"{ search(index: "test", query: "hello") { id } }"#,
+        ))
+        .with_authorization(Authorization {
+            api_key: ApiKey {
+                name: String::from("orders"),
+                scopes: vec![],
+                indexes: vec![IndexGrant {
+                    pattern: String::from("orders-*"),
+                    scopes: vec![ApiKeyScope::Read],
+                }],
+                tenant_id: None,
+            },
+            scope: ApiKeyScope::Read,
+        });
+
+        let response = service.handle_request(request).await.unwrap();
+
+        assert_eq!(1, response.errors.len());
+        assert_eq!(
+            Some(&async_graphql::Value::from(403)),
+            response.errors[0]
+                .extensions
+                .as_ref()
+                .and_then(|extensions| extensions.get("status"))
+        );
+    }
+}
@@ -1,17 +1,24 @@
+use std::collections::BTreeMap;
+
 use async_trait::async_trait;
 use serde::Serialize;
 
-use crate::json;
 use crate::schema::{SchemaLoader, SchemaProvider};
 use crate::search_doc::SearchDoc;
 use crate::service::{ServiceError, ServiceHandler, ServiceRequest, ServiceResponse};
 use crate::store::document::{DDBDocumentStore, DocumentStore};
 use crate::worker::index_writer::client::{IndexWriterClient, LambdaIndexWriterClient};
 use crate::worker::index_writer::job::Job;
+use crate::{json, time_partition};
 
 #[derive(Serialize)]
 pub struct BatchIndexResponse {
     pub job_id: String,
+
+    /// Every job, when the batch was split across the partitions of a rolling index. `job_id`
+    /// is the first.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub job_ids: Vec<String>,
 }
 
 pub struct BatchIndexService {
@@ -30,15 +37,15 @@ impl ServiceHandler<Vec<json::Value>, BatchIndexResponse> for BatchIndexService
     ) -> ServiceResponse<BatchIndexResponse> {
         let body = request.body()?;
 
-        let index_id = request.path_param("index_id")?;
+        let index_id = request.index_id()?;
+        let token = request.write_token()?;
 
         let schema = self.schema_loader.load_schema(&index_id)?;
-
-        let mut job = Job::create(&index_id);
+        let settings = self.schema_loader.load_settings(&index_id)?;
 
         let documents = body
             .into_iter()
-            .map(|value| SearchDoc::from_json(&schema, value))
+            .map(|value| SearchDoc::from_json_with_settings(&schema, value, &settings))
             .collect::<Vec<_>>();
 
         let error = documents
@@ -50,8 +57,7 @@ impl ServiceHandler<Vec<json::Value>, BatchIndexResponse> for BatchIndexService
         if let Some((idx, error)) = error.first() {
             return Err(ServiceError::invalid_request(&format!(
                 "Error parsing document (path: [{}]): {}",
-                idx,
-                error.to_string()
+                idx, error
             )));
         }
 
@@ -60,15 +66,40 @@ impl ServiceHandler<Vec<json::Value>, BatchIndexResponse> for BatchIndexService
             .filter_map(Result::ok)
             .collect::<Vec<_>>();
 
-        let doc_refs = self.document_store.save_documents(documents).await?;
+        let mut partitions: BTreeMap<String, Vec<SearchDoc>> = BTreeMap::new();
+        for document in documents {
+            let target = time_partition::target_index(
+                self.schema_loader.as_ref(),
+                &index_id,
+                &settings,
+                &schema,
+                &document,
+            );
+            partitions.entry(target).or_default().push(document);
+        }
+        if partitions.is_empty() {
+            partitions.insert(index_id.clone(), vec![]);
+        }
+
+        let mut job_ids = vec![];
+        for (target, documents) in partitions {
+            let mut job = Job::create(&target).with_token(token);
+
+            let doc_refs = self.document_store.save_documents(documents).await?;
 
-        for doc_ref in doc_refs {
-            job.index_doc(doc_ref)
+            for doc_ref in doc_refs {
+                job.index_doc(doc_ref)
+            }
+
+            job_ids.push(self.index_writer.submit_job(job).await?);
         }
 
-        let job_id = self.index_writer.submit_job(job).await?;
+        let job_id = job_ids.first().cloned().unwrap_or_default();
+        if job_ids.len() == 1 {
+            job_ids.clear();
+        }
 
-        Ok(BatchIndexResponse { job_id })
+        Ok(BatchIndexResponse { job_id, job_ids })
     }
 }
 
@@ -76,7 +107,7 @@ impl BatchIndexService {
     pub async fn create() -> Self {
         let document_store = DDBDocumentStore::create(None).await;
         let writer_client = LambdaIndexWriterClient::create(None).await;
-        let schema_loader = SchemaProvider::lambda();
+        let schema_loader = SchemaProvider::lambda().await;
 
         BatchIndexService {
             document_store: Box::new(document_store),
@@ -84,4 +115,16 @@ impl BatchIndexService {
             schema_loader: Box::new(schema_loader),
         }
     }
+
+    pub fn new(
+        schema_loader: Box<dyn SchemaLoader>,
+        document_store: Box<dyn DocumentStore>,
+        index_writer: Box<dyn IndexWriterClient>,
+    ) -> Self {
+        BatchIndexService {
+            schema_loader,
+            document_store,
+            index_writer,
+        }
+    }
 }
@@ -0,0 +1,234 @@
+use std::io::BufRead;
+
+use async_trait::async_trait;
+use serde::Serialize;
+
+use crate::schema::{SchemaLoader, SchemaProvider};
+use crate::search_doc::SearchDoc;
+use crate::service::{ServiceError, ServiceHandler, ServiceRequest, ServiceResponse};
+use crate::store::document::{DDBDocumentStore, DocumentStore};
+use crate::time_partition::WriteOp;
+use crate::worker::index_writer::client::{IndexWriterClient, LambdaIndexWriterClient};
+use crate::worker::index_writer::job::Job;
+use crate::{json, time_partition};
+
+/// Maximum number of documents the document store accepts in a single save.
+pub(crate) const MAX_DOCS_PER_SAVE: usize = 25;
+
+/// Maximum number of documents indexed by a single writer job.
+pub(crate) const MAX_DOCS_PER_JOB: usize = 250;
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct BulkIndexError {
+    /// 1-based line number in the request body.
+    pub line: usize,
+    pub message: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct BulkIndexResponse {
+    pub job_ids: Vec<String>,
+    pub indexed: usize,
+    pub errors: Vec<BulkIndexError>,
+}
+
+pub struct BulkIndexService {
+    schema_loader: Box<dyn SchemaLoader>,
+
+    document_store: Box<dyn DocumentStore>,
+
+    writer_client: Box<dyn IndexWriterClient>,
+}
+
+#[async_trait]
+impl ServiceHandler<json::Value, BulkIndexResponse> for BulkIndexService {
+    async fn handle_request(
+        &self,
+        request: ServiceRequest<json::Value>,
+    ) -> ServiceResponse<BulkIndexResponse> {
+        let index_id = request.index_id()?;
+        let token = request.write_token()?;
+
+        let schema = self.schema_loader.load_schema(&index_id)?;
+        let settings = self.schema_loader.load_settings(&index_id)?;
+
+        let mut ops = vec![];
+        let mut indexed = 0;
+        let mut errors = vec![];
+
+        for (idx, line) in request.body_reader()?.lines().enumerate() {
+            let line = line.map_err(|err| {
+                ServiceError::invalid_request(&format!("Unable to read body: {}", err))
+            })?;
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let document = json::from_str(&line)
+                .map_err(|err| err.to_string())
+                .and_then(|value| {
+                    SearchDoc::from_json_with_settings(&schema, value, &settings)
+                        .map_err(|err| err.to_string())
+                });
+
+            match document {
+                Ok(document) => {
+                    ops.push(WriteOp::Index(document));
+                    indexed += 1;
+                }
+                Err(message) => errors.push(BulkIndexError {
+                    line: idx + 1,
+                    message,
+                }),
+            }
+        }
+
+        let partitions = time_partition::group_by_target(
+            self.schema_loader.as_ref(),
+            &index_id,
+            &settings,
+            &schema,
+            &[],
+            ops,
+        );
+
+        let mut job_ids = vec![];
+        for (target, ops) in &partitions {
+            let documents: Vec<SearchDoc> =
+                ops.iter().filter_map(WriteOp::document).cloned().collect();
+            for batch in documents.chunks(MAX_DOCS_PER_JOB) {
+                let mut job = Job::create(target).with_token(token);
+
+                for chunk in batch.chunks(MAX_DOCS_PER_SAVE) {
+                    let doc_refs = self.document_store.save_documents(chunk.to_vec()).await?;
+
+                    for doc_ref in doc_refs {
+                        job.index_doc(doc_ref);
+                    }
+                }
+
+                job_ids.push(self.writer_client.submit_job(job).await?);
+            }
+        }
+
+        Ok(BulkIndexResponse {
+            job_ids,
+            indexed,
+            errors,
+        })
+    }
+}
+
+impl BulkIndexService {
+    pub async fn create() -> Self {
+        let document_store = DDBDocumentStore::create(None).await;
+        let writer_client = LambdaIndexWriterClient::create(None).await;
+        let schema_loader = SchemaProvider::lambda().await;
+
+        BulkIndexService {
+            document_store: Box::new(document_store),
+            writer_client: Box::new(writer_client),
+            schema_loader: Box::new(schema_loader),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::IndexLoader;
+    use crate::test_utils::*;
+
+    fn test_service(ctx: &TestContext) -> BulkIndexService {
+        BulkIndexService {
+            schema_loader: Box::new(ctx.schema_loader().clone()),
+            document_store: Box::new(ctx.document_store().clone()),
+            writer_client: Box::new(ctx.writer_client().clone()),
+        }
+    }
+
+    #[tokio::test]
+    async fn bulk_index_reports_line_errors() {
+        let ctx = setup();
+
+        let service = test_service(&ctx);
+
+        let body = [
+            r#"{"title": "hello"}"#,
+            "",
+            r#"{"title": 1}"#,
+            "not json",
+            r#"{"title": "world"}"#,
+        ]
+        .join("\n");
+
+        let request = ServiceRequest::create_raw(&body).with_path_param("index_id", "test");
+
+        let response = service.handle_request(request).await.unwrap();
+
+        assert_eq!(2, response.indexed);
+        assert_eq!(1, response.job_ids.len());
+        assert_eq!(
+            vec![3, 4],
+            response
+                .errors
+                .iter()
+                .map(|err| err.line)
+                .collect::<Vec<_>>()
+        );
+
+        let num_docs = ctx
+            .index_loader()
+            .load_index("test", None)
+            .unwrap()
+            .reader()
+            .unwrap()
+            .searcher()
+            .num_docs();
+
+        assert_eq!(2, num_docs);
+    }
+
+    #[tokio::test]
+    async fn bulk_index_accepts_gzip_body() {
+        use std::io::Write;
+
+        let ctx = setup();
+
+        let service = test_service(&ctx);
+
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder
+            .write_all(b"{\"title\": \"hello\"}\n{\"title\": \"world\"}\n")
+            .unwrap();
+
+        let request = ServiceRequest::create_binary(encoder.finish().unwrap())
+            .with_header("Content-Encoding", "gzip")
+            .with_path_param("index_id", "test");
+
+        let response = service.handle_request(request).await.unwrap();
+
+        assert_eq!(2, response.indexed);
+        assert!(response.errors.is_empty());
+    }
+
+    #[tokio::test]
+    async fn bulk_index_chunks_jobs() {
+        let ctx = setup();
+
+        let service = test_service(&ctx);
+
+        let body = (0..MAX_DOCS_PER_JOB + 1)
+            .map(|n| json!({ "title": format!("doc {n}") }).to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let request = ServiceRequest::create_raw(&body).with_path_param("index_id", "test");
+
+        let response = service.handle_request(request).await.unwrap();
+
+        assert_eq!(MAX_DOCS_PER_JOB + 1, response.indexed);
+        assert_eq!(2, response.job_ids.len());
+    }
+}
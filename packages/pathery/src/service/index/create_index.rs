@@ -0,0 +1,127 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::json;
+use crate::schema::{parse_index_config, SchemaLoader, SchemaProvider};
+use crate::service::{ServiceError, ServiceHandler, ServiceRequest, ServiceResponse};
+use crate::store::schema::{DDBSchemaStore, SchemaStore};
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct CreateIndexResponse {
+    pub index_id: String,
+
+    pub schema_version: String,
+}
+
+/// Creates an index from the fields and settings in the request, without redeploying the bundled
+/// config. The index's files are created by its first write or query.
+pub struct CreateIndexService {
+    schema_loader: Box<dyn SchemaLoader>,
+
+    schema_store: Box<dyn SchemaStore>,
+}
+
+#[async_trait]
+impl ServiceHandler<json::Value, CreateIndexResponse> for CreateIndexService {
+    async fn handle_request(
+        &self,
+        request: ServiceRequest<json::Value>,
+    ) -> ServiceResponse<CreateIndexResponse> {
+        let body = request.body()?;
+
+        let index_id = request.index_id()?;
+
+        // Index ids name the index's directory, where dot names are reserved for staging.
+        if index_id.starts_with('.') || index_id.contains('/') {
+            return Err(ServiceError::invalid_request(&format!(
+                "Invalid index id [{}]",
+                index_id
+            )));
+        }
+
+        if self.schema_loader.index_prefix(&index_id).is_some() {
+            return Err(ServiceError::invalid_request(&format!(
+                "Index [{}] already exists",
+                index_id
+            )));
+        }
+
+        parse_index_config(&index_id, body.clone())
+            .map_err(|err| ServiceError::invalid_request(&err.to_string()))?;
+
+        if !self.schema_store.create_config(&index_id, &body).await? {
+            return Err(ServiceError::invalid_request(&format!(
+                "Index [{}] already exists",
+                index_id
+            )));
+        }
+
+        Ok(CreateIndexResponse {
+            schema_version: self.schema_loader.load_schema_version(&index_id)?,
+            index_id: request.path_param("index_id")?,
+        })
+    }
+}
+
+impl CreateIndexService {
+    pub async fn create() -> Self {
+        CreateIndexService {
+            schema_loader: Box::new(SchemaProvider::lambda().await),
+            schema_store: Box::new(DDBSchemaStore::create(None).await),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::schema::test_util::TestSchemaStore;
+    use crate::test_utils::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn created_index_is_immediately_loadable() {
+        let ctx = setup();
+        let schema_store = TestSchemaStore::create();
+        let schema_loader = ctx.schema_loader().clone().with_store(schema_store.clone());
+
+        let service = CreateIndexService {
+            schema_loader: Box::new(schema_loader.clone()),
+            schema_store: Box::new(schema_store),
+        };
+
+        let create = |index_id: &str, body: json::Value| {
+            let request = ServiceRequest::create(body).with_path_param("index_id", index_id);
+            service.handle_request(request)
+        };
+
+        let definition = json!({
+            "fields": [{ "name": "title", "kind": "text", "flags": ["TEXT"] }],
+            "settings": { "search_only": true }
+        });
+
+        let response = create("runtime", definition.clone()).await.unwrap();
+        assert_eq!(
+            schema_loader.load_schema_version("runtime").unwrap(),
+            response.schema_version
+        );
+
+        let schema = schema_loader.load_schema("runtime").unwrap();
+        assert!(schema.get_field("title").is_some());
+        assert!(schema_loader.load_settings("runtime").unwrap().search_only);
+
+        // Ids that already have a schema, whether created or configured, are rejected.
+        let err = create("runtime", definition.clone()).await.unwrap_err();
+        assert_eq!(400, err.status());
+        let err = create("test-books", definition).await.unwrap_err();
+        assert_eq!(400, err.status());
+
+        let err = create(
+            "invalid",
+            json!({ "fields": [{ "name": "title", "kind": "geo" }] }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!("Field [title] has unsupported kind [geo]", err.message());
+        assert!(schema_loader.load_schema("invalid").is_err());
+    }
+}
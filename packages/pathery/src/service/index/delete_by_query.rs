@@ -0,0 +1,287 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tantivy::collector::{Count, DocSetCollector};
+use tantivy::query::Query as TantivyQuery;
+use tantivy::Index;
+
+use crate::index::{IndexExt, IndexLoader, LambdaIndexLoader};
+use crate::query::{self, Query};
+use crate::schema::{SchemaLoader, SchemaProvider};
+use crate::search_doc::SearchDocId;
+use crate::service::{ServiceHandler, ServiceRequest, ServiceResponse};
+use crate::time_partition;
+use crate::worker::index_writer::client::{IndexWriterClient, LambdaIndexWriterClient};
+use crate::worker::index_writer::job::Job;
+
+/// Keeps each job comfortably below the SQS message size limit.
+const MAX_DELETES_PER_JOB: usize = 1_000;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DeleteByQueryRequest {
+    #[serde(deserialize_with = "query::string_or_dsl")]
+    pub query: Query,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct DeleteByQueryResponse {
+    pub job_ids: Vec<String>,
+    pub matched: usize,
+    /// Nothing was deleted, `matched` is what would have been.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub dry_run: bool,
+}
+
+/// Submits jobs deleting every document of `index` that matches `query`, or just counts them
+/// when it's a `dry_run`.
+pub async fn delete_matching(
+    writer_client: &dyn IndexWriterClient,
+    index_id: &str,
+    index: &Index,
+    query: &dyn TantivyQuery,
+    dry_run: bool,
+) -> ServiceResponse<DeleteByQueryResponse> {
+    let searcher = index.reader().expect("Reader should load").searcher();
+
+    if dry_run {
+        return Ok(DeleteByQueryResponse {
+            job_ids: vec![],
+            matched: searcher
+                .search(query, &Count)
+                .expect("search should succeed"),
+            dry_run,
+        });
+    }
+
+    let addresses = searcher
+        .search(query, &DocSetCollector)
+        .expect("search should succeed");
+
+    let id_field = index.id_field();
+
+    let doc_ids = addresses
+        .into_iter()
+        .map(|address| {
+            let document = searcher.doc(address).expect("doc should exist");
+            let id = document
+                .get_first(id_field)
+                .and_then(|id| id.as_text())
+                .expect("__id should be stored");
+            SearchDocId::parse(id)
+        })
+        .collect::<Vec<_>>();
+
+    let mut job_ids = vec![];
+
+    for chunk in doc_ids.chunks(MAX_DELETES_PER_JOB) {
+        let mut job = Job::create(index_id);
+
+        for doc_id in chunk {
+            job.delete_doc(doc_id.clone());
+        }
+
+        job_ids.push(writer_client.submit_job(job).await?);
+    }
+
+    Ok(DeleteByQueryResponse {
+        job_ids,
+        matched: doc_ids.len(),
+        dry_run,
+    })
+}
+
+pub struct DeleteByQueryService {
+    schema_loader: Box<dyn SchemaLoader>,
+
+    index_loader: Box<dyn IndexLoader>,
+
+    writer_client: Box<dyn IndexWriterClient>,
+}
+
+#[async_trait]
+impl ServiceHandler<DeleteByQueryRequest, DeleteByQueryResponse> for DeleteByQueryService {
+    async fn handle_request(
+        &self,
+        request: ServiceRequest<DeleteByQueryRequest>,
+    ) -> ServiceResponse<DeleteByQueryResponse> {
+        let index_id = request.index_id()?;
+        let dry_run = request.dry_run()?;
+
+        // The query can be provided as a query string parameter or in the request body.
+        let query = match request.query_param("query") {
+            Some(query) => Query::from(query),
+            None => request.body()?.query,
+        };
+
+        let settings = self.schema_loader.load_settings(&index_id)?;
+
+        // Rolling indexes are deleted from in each of their partitions.
+        let mut response = DeleteByQueryResponse {
+            job_ids: vec![],
+            matched: 0,
+            dry_run,
+        };
+        for index_id in time_partition::resolve_alias(
+            self.schema_loader.as_ref(),
+            self.index_loader.as_ref(),
+            &index_id,
+        )? {
+            let index = self.index_loader.load_index(&index_id, None)?;
+
+            let query = query.compile(&index, &settings)?;
+
+            let deleted = delete_matching(
+                self.writer_client.as_ref(),
+                &index_id,
+                &index,
+                query.as_ref(),
+                dry_run,
+            )
+            .await?;
+            response.job_ids.extend(deleted.job_ids);
+            response.matched += deleted.matched;
+        }
+
+        Ok(response)
+    }
+}
+
+impl DeleteByQueryService {
+    pub async fn create() -> Self {
+        let index_loader = LambdaIndexLoader::create().await;
+        let writer_client = LambdaIndexWriterClient::create(None).await;
+        let schema_loader = SchemaProvider::lambda().await;
+
+        DeleteByQueryService {
+            schema_loader: Box::new(schema_loader),
+            index_loader: Box::new(index_loader),
+            writer_client: Box::new(writer_client),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    fn test_service(ctx: &TestContext) -> DeleteByQueryService {
+        DeleteByQueryService {
+            schema_loader: Box::new(ctx.schema_loader().clone()),
+            index_loader: Box::new(ctx.index_loader().clone()),
+            writer_client: Box::new(ctx.writer_client().clone()),
+        }
+    }
+
+    fn num_docs(ctx: &TestContext) -> u64 {
+        ctx.index_loader()
+            .load_index("test", None)
+            .unwrap()
+            .reader()
+            .unwrap()
+            .searcher()
+            .num_docs()
+    }
+
+    #[tokio::test]
+    async fn delete_by_query_param_takes_precedence_over_body() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![
+                    json!({ "__id": "a", "title": "hello" }),
+                    json!({ "__id": "b", "title": "hello" }),
+                    json!({ "__id": "c", "title": "world" }),
+                ],
+            )
+            .await;
+
+        let service = test_service(&ctx);
+
+        let request = ServiceRequest::create(DeleteByQueryRequest {
+            query: "title:world".into(),
+        })
+        .with_path_param("index_id", "test")
+        .with_query_param("query", "title:hello");
+
+        let response = service.handle_request(request).await.unwrap();
+
+        assert_eq!(2, response.matched);
+        assert_eq!(1, response.job_ids.len());
+        assert_eq!(1, num_docs(&ctx));
+    }
+
+    #[tokio::test]
+    async fn delete_by_query_dry_run_deletes_nothing() {
+        let ctx = setup()
+            .with_documents("test", vec![json!({ "__id": "a", "title": "hello" })])
+            .await;
+
+        let service = test_service(&ctx);
+
+        let request = ServiceRequest::create_raw(r#"{ "query": "title:hello" }"#)
+            .with_path_param("index_id", "test")
+            .with_query_param("dry_run", "true");
+
+        let response = service.handle_request(request).await.unwrap();
+
+        assert_eq!((1, true), (response.matched, response.dry_run));
+        assert!(response.job_ids.is_empty());
+        assert_eq!(1, num_docs(&ctx));
+    }
+
+    #[tokio::test]
+    async fn delete_by_query_body() {
+        let ctx = setup()
+            .with_documents("test", vec![json!({ "__id": "a", "title": "hello" })])
+            .await;
+
+        let service = test_service(&ctx);
+
+        let request = ServiceRequest::create(DeleteByQueryRequest {
+            query: "title:nope".into(),
+        })
+        .with_path_param("index_id", "test");
+
+        let response = service.handle_request(request).await.unwrap();
+
+        assert_eq!(0, response.matched);
+        assert!(response.job_ids.is_empty());
+        assert_eq!(1, num_docs(&ctx));
+    }
+
+    #[tokio::test]
+    async fn delete_by_query_deletes_from_every_partition_of_a_rolling_index() {
+        let today = Utc::now().date_naive();
+        let partitions = [today - Duration::days(1), today]
+            .map(|date| time_partition::partition_id("rolling", date));
+        let ctx = setup()
+            .with_documents(
+                &partitions[0],
+                vec![json!({ "__id": "a", "title": "hello" })],
+            )
+            .await
+            .with_documents(
+                &partitions[1],
+                vec![json!({ "__id": "b", "title": "hello" })],
+            )
+            .await;
+
+        let service = test_service(&ctx);
+
+        let request = ServiceRequest::create(DeleteByQueryRequest {
+            query: "title:hello".into(),
+        })
+        .with_path_param("index_id", "rolling");
+
+        let response = service.handle_request(request).await.unwrap();
+
+        assert_eq!(2, response.matched);
+        assert_eq!(2, response.job_ids.len());
+        for partition in &partitions {
+            let index = ctx.index_loader().load_index(partition, None).unwrap();
+            assert_eq!(0, index.reader().unwrap().searcher().num_docs());
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use async_trait::async_trait;
+use serde::Serialize;
+
+use crate::index::{IndexLoader, LambdaIndexLoader};
+use crate::json;
+use crate::schema::{SchemaLoader, SchemaProvider};
+use crate::service::{ServiceError, ServiceHandler, ServiceRequest, ServiceResponse};
+use crate::worker::index_writer::client::{IndexWriterClient, LambdaIndexWriterClient};
+use crate::worker::index_writer::job::Job;
+
+#[derive(Serialize, Debug)]
+pub struct DeleteIndexResponse {
+    /// The queued deletion, none for a `dry_run`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub job_id: Option<String>,
+
+    /// Documents a `dry_run` would have deleted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_docs: Option<u64>,
+
+    /// Segment files a `dry_run` would have deleted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_files: Option<usize>,
+}
+
+/// Tears down an index. Deletion is queued behind any pending writes for the index, which the
+/// writer discards when it removes the index files.
+pub struct DeleteIndexService {
+    schema_loader: Box<dyn SchemaLoader>,
+
+    index_loader: Box<dyn IndexLoader>,
+
+    writer_client: Box<dyn IndexWriterClient>,
+}
+
+impl DeleteIndexService {
+    /// What deleting `index_id` would delete, without creating indexes that don't exist yet.
+    fn rehearse(&self, index_id: &str) -> ServiceResponse<DeleteIndexResponse> {
+        let (num_docs, num_files) = if self
+            .index_loader
+            .list_indexes()?
+            .iter()
+            .any(|id| id == index_id)
+        {
+            let index = self.index_loader.load_index(index_id, None)?;
+            let num_docs = index
+                .reader()
+                .map_err(ServiceError::internal_error)?
+                .searcher()
+                .num_docs();
+            let num_files = index
+                .searchable_segment_metas()
+                .map_err(ServiceError::internal_error)?
+                .iter()
+                .map(|segment| segment.list_files().len())
+                .sum();
+            (num_docs, num_files)
+        } else {
+            (0, 0)
+        };
+
+        Ok(DeleteIndexResponse {
+            job_id: None,
+            num_docs: Some(num_docs),
+            num_files: Some(num_files),
+        })
+    }
+}
+
+#[async_trait]
+impl ServiceHandler<json::Value, DeleteIndexResponse> for DeleteIndexService {
+    async fn handle_request(
+        &self,
+        request: ServiceRequest<json::Value>,
+    ) -> ServiceResponse<DeleteIndexResponse> {
+        let index_id = request.index_id()?;
+
+        // Ensure the index id matches a configured index.
+        self.schema_loader.load_schema(&index_id)?;
+
+        if request.dry_run()? {
+            return self.rehearse(&index_id);
+        }
+
+        let mut job = Job::create(&index_id);
+        job.delete_index();
+
+        let job_id = self.writer_client.submit_job(job).await?;
+
+        Ok(DeleteIndexResponse {
+            job_id: Some(job_id),
+            num_docs: None,
+            num_files: None,
+        })
+    }
+}
+
+impl DeleteIndexService {
+    pub async fn create() -> Self {
+        let writer_client = LambdaIndexWriterClient::create(None).await;
+        let schema_loader = SchemaProvider::lambda().await;
+        let index_loader = LambdaIndexLoader::create().await;
+
+        DeleteIndexService {
+            writer_client: Box::new(writer_client),
+            schema_loader: Box::new(schema_loader),
+            index_loader: Box::new(index_loader),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::IndexLoader;
+    use crate::lambda::http::{ApiKeyAuth, API_KEY_HEADER};
+    use crate::store::api_key::{ApiKey, ApiKeyScope, IndexGrant, MemoryApiKeyStore};
+    use crate::store::job::{JobState, JobStore};
+    use crate::test_utils::*;
+
+    fn test_service(ctx: &TestContext) -> DeleteIndexService {
+        DeleteIndexService {
+            schema_loader: Box::new(ctx.schema_loader().clone()),
+            index_loader: Box::new(ctx.index_loader().clone()),
+            writer_client: Box::new(ctx.writer_client().clone()),
+        }
+    }
+
+    #[tokio::test]
+    async fn delete_index_removes_documents() {
+        let ctx = setup()
+            .with_documents("test", vec![json!({ "title": "hello" })])
+            .await;
+
+        let service = test_service(&ctx);
+
+        let request = || ServiceRequest::create(json!({})).with_path_param("index_id", "test");
+
+        let dry_run = service
+            .handle_request(request().with_query_param("dry_run", "true"))
+            .await
+            .unwrap();
+        assert_eq!((None, Some(1)), (dry_run.job_id, dry_run.num_docs));
+
+        let response = service.handle_request(request()).await.unwrap();
+        let job_id = response.job_id.unwrap();
+
+        let num_docs = ctx
+            .index_loader()
+            .load_index("test", None)
+            .unwrap()
+            .reader()
+            .unwrap()
+            .searcher()
+            .num_docs();
+
+        assert_eq!(0, num_docs);
+        assert_eq!(
+            JobState::Complete,
+            ctx.job_store()
+                .get_job(&job_id)
+                .await
+                .unwrap()
+                .unwrap()
+                .status
+        );
+    }
+
+    #[tokio::test]
+    async fn delete_index_checks_the_key_index_grants() {
+        let ctx = setup()
+            .with_documents("test", vec![json!({ "title": "hello" })])
+            .await;
+
+        let key_store = MemoryApiKeyStore::create();
+        for (key, pattern) in [("orders-key", "orders-*"), ("test-key", "test*")] {
+            key_store.put_key(
+                key,
+                ApiKey {
+                    name: String::from(key),
+                    scopes: vec![ApiKeyScope::Read],
+                    indexes: vec![IndexGrant {
+                        pattern: String::from(pattern),
+                        scopes: vec![ApiKeyScope::Admin],
+                    }],
+                    tenant_id: None,
+                },
+            );
+        }
+        let service = ApiKeyAuth::new(test_service(&ctx), Box::new(key_store), ApiKeyScope::Admin);
+        let request = |key: &str, index_id: &str| {
+            ServiceRequest::create(json!({}))
+                .with_path_param("index_id", index_id)
+                .with_header(API_KEY_HEADER, key)
+        };
+
+        for index_id in ["products-1", "test"] {
+            let err = service
+                .handle_request(request("orders-key", index_id))
+                .await
+                .unwrap_err();
+            assert_eq!(403, err.status());
+        }
+        let num_docs = || {
+            ctx.index_loader()
+                .load_index("test", None)
+                .unwrap()
+                .reader()
+                .unwrap()
+                .searcher()
+                .num_docs()
+        };
+        assert_eq!(1, num_docs());
+
+        service
+            .handle_request(request("test-key", "test"))
+            .await
+            .unwrap();
+        assert_eq!(0, num_docs());
+    }
+}
@@ -0,0 +1,110 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::json;
+use crate::schema::{SchemaLoader, SchemaProvider};
+use crate::service::{ServiceHandler, ServiceRequest, ServiceResponse};
+use crate::store::field_usage::{DDBFieldUsageStore, FieldUsage, FieldUsageStore};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DeprecationsResponse {
+    pub deprecated_fields: Vec<String>,
+
+    /// The clients that used each deprecated field in the last 30 days.
+    pub usage: Vec<FieldUsage>,
+}
+
+/// Reports which clients still query or write an index's deprecated fields, so the fields can be
+/// removed once nothing uses them.
+pub struct DeprecationsIndexService {
+    schema_loader: Box<dyn SchemaLoader>,
+
+    usage_store: Box<dyn FieldUsageStore>,
+}
+
+#[async_trait]
+impl ServiceHandler<json::Value, DeprecationsResponse> for DeprecationsIndexService {
+    async fn handle_request(
+        &self,
+        request: ServiceRequest<json::Value>,
+    ) -> ServiceResponse<DeprecationsResponse> {
+        let index_id = request.index_id()?;
+        let settings = self.schema_loader.load_settings(&index_id)?;
+
+        // Uses of fields no longer deprecated are kept until they expire.
+        let usage = self
+            .usage_store
+            .list_uses(&index_id)
+            .await?
+            .into_iter()
+            .filter(|usage| settings.deprecated_fields.contains(&usage.field))
+            .collect();
+
+        Ok(DeprecationsResponse {
+            deprecated_fields: settings.deprecated_fields.clone(),
+            usage,
+        })
+    }
+}
+
+impl DeprecationsIndexService {
+    pub async fn create() -> Self {
+        DeprecationsIndexService {
+            schema_loader: Box::new(SchemaProvider::lambda().await),
+            usage_store: Box::new(DDBFieldUsageStore::create(None).await),
+        }
+    }
+
+    pub fn new(
+        schema_loader: Box<dyn SchemaLoader>,
+        usage_store: Box<dyn FieldUsageStore>,
+    ) -> Self {
+        DeprecationsIndexService {
+            schema_loader,
+            usage_store,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::field_usage::test_util::TestFieldUsageStore;
+    use crate::store::field_usage::FieldUse;
+    use crate::test_utils::*;
+
+    #[tokio::test]
+    async fn deprecations_list_clients_of_deprecated_fields() {
+        let schema_loader = SchemaProvider::from_json(json!({
+            "indexes": [{
+                "prefix": "test",
+                "fields": [
+                    { "name": "title", "kind": "text", "flags": ["TEXT"] },
+                    { "name": "subtitle", "kind": "text", "flags": ["TEXT"] }
+                ],
+                "settings": { "deprecated_fields": ["subtitle"] }
+            }]
+        }));
+        let usage_store = TestFieldUsageStore::create();
+        for (field, client) in [
+            ("subtitle", "search-ui"),
+            ("subtitle", "search-ui"),
+            ("title", "etl"),
+        ] {
+            usage_store
+                .record_use("test", field, client, FieldUse::Query)
+                .await
+                .unwrap();
+        }
+
+        let service = DeprecationsIndexService::new(Box::new(schema_loader), Box::new(usage_store));
+        let request = ServiceRequest::create(json!({})).with_path_param("index_id", "test");
+
+        let response = service.handle_request(request).await.unwrap();
+        assert_eq!(vec!["subtitle"], response.deprecated_fields);
+        assert_eq!(1, response.usage.len());
+        assert_eq!("search-ui", response.usage[0].client);
+        assert_eq!(FieldUse::Query, response.usage[0].operation);
+        assert_eq!(2, response.usage[0].count);
+    }
+}
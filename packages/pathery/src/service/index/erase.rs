@@ -0,0 +1,502 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use tantivy::collector::{Count, DocSetCollector};
+use tantivy::Term;
+use tracing::info;
+
+use super::snapshot_index::LEASE_MINUTES;
+use crate::index::{IndexExt, IndexLoader, IndexWriterExt, LambdaIndexLoader};
+use crate::query::Query;
+use crate::schema::{SchemaLoader, SchemaProvider};
+use crate::search_doc::SearchDocId;
+use crate::service::{ServiceError, ServiceHandler, ServiceRequest, ServiceResponse};
+use crate::store::change::{Change, ChangeStore, DDBChangeStore};
+use crate::store::document::{DDBDocumentStore, DocumentStore, SearchDocRef};
+use crate::store::erase::{DDBEraseStore, EraseStore};
+pub use crate::store::erase::{EraseReport, IndexErasure};
+use crate::store::lease::{DDBLeaseStore, LeaseStore};
+use crate::store::token::{DDBTokenStore, TokenStore};
+use crate::worker::merge::merge_index;
+use crate::{json, time_partition, util};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct EraseRequest {
+    /// Field identifying the data to erase, such as `user_id`.
+    pub field: String,
+
+    /// Value of `field` in the documents to erase, matched as an exact term.
+    pub value: json::Value,
+
+    /// Other indexes to erase the same documents from, along with the one in the path.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub indexes: Vec<String>,
+}
+
+/// Erases every document matching a field and value, across one or more indexes, for requests
+/// such as a GDPR erasure. Unlike deletes through the index writer, documents are gone from disk
+/// when the request completes: matches are deleted and committed, then every segment is merged to
+/// purge them and the files they were in are deleted. The stored copies of the documents are
+/// deleted too.
+///
+/// The report of a completed erase is saved, for `GET /index/{index_id}/_erase/{erase_id}`.
+///
+/// Each index's lease is held throughout, acquired for every index before any is erased, so an
+/// erase doesn't start until it can run on all of its indexes. Erasing again is safe, so a failed
+/// erase can be retried.
+pub struct EraseService {
+    schema_loader: Box<dyn SchemaLoader>,
+
+    index_loader: Box<dyn IndexLoader>,
+
+    document_store: Box<dyn DocumentStore>,
+
+    change_store: Box<dyn ChangeStore>,
+
+    token_store: Box<dyn TokenStore>,
+
+    lease_store: Box<dyn LeaseStore>,
+
+    erase_store: Box<dyn EraseStore>,
+}
+
+impl EraseService {
+    /// What erasing `index_id` would do: the matches, and the segments merged and their files
+    /// deleted, which is all of them.
+    fn rehearse(&self, index_id: &str, query: &Query) -> ServiceResponse<IndexErasure> {
+        let settings = self.schema_loader.load_settings(index_id)?;
+        let index = self.index_loader.load_index(index_id, None)?;
+        let query = query.compile(&index, &settings)?;
+
+        let searcher = index
+            .reader()
+            .map_err(ServiceError::internal_error)?
+            .searcher();
+        let erased = searcher
+            .search(query.as_ref(), &Count)
+            .map_err(ServiceError::internal_error)?;
+        let segments = index
+            .searchable_segment_metas()
+            .map_err(ServiceError::internal_error)?;
+
+        Ok(IndexErasure {
+            index_id: index_id.into(),
+            erased,
+            merged_segments: segments.len(),
+            deleted_files: segments
+                .iter()
+                .map(|segment| segment.list_files().len())
+                .sum(),
+        })
+    }
+
+    async fn erase(&self, index_id: &str, query: &Query) -> ServiceResponse<IndexErasure> {
+        let settings = self.schema_loader.load_settings(index_id)?;
+        let index = self.index_loader.load_index(index_id, None)?;
+        let query = query.compile(&index, &settings)?;
+
+        let searcher = index
+            .reader()
+            .map_err(ServiceError::internal_error)?
+            .searcher();
+        let id_field = index.id_field();
+        let mut doc_ids = vec![];
+        for address in searcher
+            .search(query.as_ref(), &DocSetCollector)
+            .map_err(ServiceError::internal_error)?
+        {
+            let document = searcher
+                .doc(address)
+                .map_err(ServiceError::internal_error)?;
+            if let Some(id) = document.get_first(id_field).and_then(|id| id.as_text()) {
+                doc_ids.push(id.to_string());
+            }
+        }
+
+        if !doc_ids.is_empty() {
+            let mut writer = index.foreground_merge_writer();
+            for doc_id in &doc_ids {
+                writer.delete_term(Term::from_field_text(id_field, doc_id));
+            }
+            writer
+                .commit_with_meta()
+                .map_err(ServiceError::internal_error)?;
+        }
+
+        // Merged even when nothing matched, in case an earlier erase failed after committing.
+        let merged = merge_index(self.index_loader.as_ref(), index_id, true)?;
+
+        if !doc_ids.is_empty() {
+            self.document_store
+                .delete_documents(
+                    doc_ids
+                        .iter()
+                        .map(|doc_id| SearchDocRef::from(SearchDocId::parse(doc_id)))
+                        .collect(),
+                )
+                .await?;
+
+            // A redelivered write from before the erase would otherwise bring documents back.
+            let token = util::write_token();
+            let tokens: HashMap<_, _> = doc_ids.iter().map(|id| (id.clone(), token)).collect();
+            self.token_store.put_tokens(index_id, tokens).await?;
+
+            let changes = doc_ids
+                .iter()
+                .map(|doc_id| Change::Delete {
+                    doc_id: doc_id.clone(),
+                })
+                .collect();
+            self.change_store.append_changes(index_id, changes).await?;
+        }
+
+        Ok(IndexErasure {
+            index_id: index_id.into(),
+            erased: doc_ids.len(),
+            merged_segments: merged.merged_segments,
+            deleted_files: merged.deleted_files,
+        })
+    }
+}
+
+#[async_trait]
+impl ServiceHandler<EraseRequest, EraseReport> for EraseService {
+    async fn handle_request(
+        &self,
+        request: ServiceRequest<EraseRequest>,
+    ) -> ServiceResponse<EraseReport> {
+        let index_id = request.index_id()?;
+        let dry_run = request.dry_run()?;
+        let body = request.body()?;
+        let started_at = util::timestamp();
+
+        // Each index is checked against the key's grants, not only the one in the path.
+        let mut index_ids = vec![index_id];
+        for index_id in body.indexes {
+            let index_id = request.authorize_index(&index_id)?;
+            if !index_ids.contains(&index_id) {
+                index_ids.push(index_id);
+            }
+        }
+        // Rolling indexes are erased from each of their partitions.
+        let mut partitions = vec![];
+        for index_id in &index_ids {
+            if self.schema_loader.index_prefix(index_id).is_none() {
+                return Err(ServiceError::not_found(&format!(
+                    "Index [{}] has no configured schema",
+                    index_id
+                )));
+            }
+            for partition in time_partition::resolve_alias(
+                self.schema_loader.as_ref(),
+                self.index_loader.as_ref(),
+                index_id,
+            )? {
+                if !partitions.contains(&partition) {
+                    partitions.push(partition);
+                }
+            }
+        }
+        let index_ids = partitions;
+
+        let erase_id = util::generate_id();
+        let query = Query::Term {
+            field: body.field.clone(),
+            value: body.value,
+        };
+
+        if dry_run {
+            let indexes = index_ids
+                .iter()
+                .map(|index_id| self.rehearse(index_id, &query))
+                .collect::<ServiceResponse<_>>()?;
+            return Ok(EraseReport {
+                erase_id,
+                field: body.field,
+                started_at,
+                completed_at: util::timestamp(),
+                indexes,
+                dry_run,
+            });
+        }
+
+        let expires_at = Utc::now() + Duration::minutes(LEASE_MINUTES);
+        let mut leased = vec![];
+        for index_id in &index_ids {
+            if !self
+                .lease_store
+                .acquire(index_id, &erase_id, expires_at)
+                .await?
+            {
+                for index_id in leased {
+                    self.lease_store.release(index_id, &erase_id).await?;
+                }
+                return Err(ServiceError::unavailable(&format!(
+                    "Index [{}] is being written, try the erase again later",
+                    index_id
+                )));
+            }
+            leased.push(index_id);
+        }
+
+        let mut indexes = vec![];
+        let mut result = Ok(());
+        for index_id in &index_ids {
+            match self.erase(index_id, &query).await {
+                Ok(erasure) => indexes.push(erasure),
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            }
+        }
+        for index_id in leased {
+            self.lease_store.release(index_id, &erase_id).await?;
+        }
+        result?;
+
+        let report = EraseReport {
+            erase_id,
+            field: body.field,
+            started_at,
+            completed_at: util::timestamp(),
+            indexes,
+            dry_run,
+        };
+        for erasure in &report.indexes {
+            info!(
+                message = "docs_erased",
+                erase_id = report.erase_id,
+                index = erasure.index_id,
+                field = report.field,
+                erased = erasure.erased,
+                merged_segments = erasure.merged_segments,
+                deleted_files = erasure.deleted_files
+            );
+        }
+        self.erase_store.put_report(&report).await?;
+
+        Ok(report)
+    }
+}
+
+impl EraseService {
+    pub async fn create() -> Self {
+        EraseService {
+            schema_loader: Box::new(SchemaProvider::lambda().await),
+            index_loader: Box::new(LambdaIndexLoader::create().await),
+            document_store: Box::new(DDBDocumentStore::create(None).await),
+            change_store: Box::new(DDBChangeStore::create(None).await),
+            token_store: Box::new(DDBTokenStore::create(None).await),
+            lease_store: Box::new(DDBLeaseStore::create(None).await),
+            erase_store: Box::new(DDBEraseStore::create(None).await),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lambda::http::Authorization;
+    use crate::store::api_key::{ApiKey, ApiKeyScope, IndexGrant};
+    use crate::store::erase::MemoryEraseStore;
+    use crate::store::lease::test_util::TestLeaseStore;
+    use crate::store::token::MemoryTokenStore;
+    use crate::test_utils::*;
+
+    fn test_service(
+        ctx: &TestContext,
+        lease_store: &TestLeaseStore,
+        erase_store: &MemoryEraseStore,
+    ) -> EraseService {
+        EraseService {
+            schema_loader: Box::new(ctx.schema_loader().clone()),
+            index_loader: Box::new(ctx.index_loader().clone()),
+            document_store: Box::new(ctx.document_store().clone()),
+            change_store: Box::new(ctx.change_store().clone()),
+            token_store: Box::new(MemoryTokenStore::create()),
+            lease_store: Box::new(lease_store.clone()),
+            erase_store: Box::new(erase_store.clone()),
+        }
+    }
+
+    #[tokio::test]
+    async fn erase_purges_matching_documents() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![
+                    json!({ "__id": "a", "title": "a", "isbn": "user-1" }),
+                    json!({ "__id": "b", "title": "b", "isbn": "user-2" }),
+                ],
+            )
+            .await
+            .with_documents(
+                "test-other",
+                vec![json!({ "__id": "c", "title": "c", "isbn": "user-1" })],
+            )
+            .await;
+        let lease_store = TestLeaseStore::create();
+        let erase_store = MemoryEraseStore::create();
+        let service = test_service(&ctx, &lease_store, &erase_store);
+
+        let request = || {
+            ServiceRequest::create(EraseRequest {
+                field: "isbn".into(),
+                value: json!("user-1"),
+                indexes: vec!["test-other".into()],
+            })
+            .with_path_param("index_id", "test")
+        };
+        let erased = |report: &EraseReport| {
+            report
+                .indexes
+                .iter()
+                .map(|erasure| (erasure.index_id.clone(), erasure.erased))
+                .collect::<Vec<_>>()
+        };
+
+        let dry_run = service
+            .handle_request(request().with_query_param("dry_run", "true"))
+            .await
+            .unwrap();
+        assert_eq!(
+            vec![("test".to_string(), 1), ("test-other".to_string(), 1)],
+            erased(&dry_run)
+        );
+        let index = ctx.index_loader().load_index("test", None).unwrap();
+        assert_eq!(2, index.reader().unwrap().searcher().num_docs());
+        assert_eq!(
+            None,
+            erase_store.get_report(&dry_run.erase_id).await.unwrap()
+        );
+
+        lease_store
+            .acquire("test-other", "writer", Utc::now() + Duration::minutes(1))
+            .await
+            .unwrap();
+        let err = service.handle_request(request()).await.unwrap_err();
+        assert_eq!(503, err.status());
+        lease_store.release("test-other", "writer").await.unwrap();
+
+        let report = service.handle_request(request()).await.unwrap();
+        assert_eq!(erased(&dry_run), erased(&report));
+        assert_eq!(
+            Some(&report),
+            erase_store
+                .get_report(&report.erase_id)
+                .await
+                .unwrap()
+                .as_ref()
+        );
+
+        let index = ctx.index_loader().load_index("test", None).unwrap();
+        let segments = index.searchable_segment_metas().unwrap();
+        assert_eq!(1, segments.len());
+        assert_eq!(0, segments[0].num_deleted_docs());
+        assert_eq!(1, index.reader().unwrap().searcher().num_docs());
+
+        let stored = ctx
+            .document_store()
+            .get_documents(vec![
+                SearchDocRef::from(SearchDocId::parse("a")),
+                SearchDocRef::from(SearchDocId::parse("b")),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(
+            vec!["b"],
+            stored.iter().map(|doc| doc.id().id()).collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn erase_checks_the_key_grants_on_every_index() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![json!({ "__id": "a", "title": "a", "isbn": "user-1" })],
+            )
+            .await
+            .with_documents(
+                "test-other",
+                vec![json!({ "__id": "c", "title": "c", "isbn": "user-1" })],
+            )
+            .await;
+        let lease_store = TestLeaseStore::create();
+        let erase_store = MemoryEraseStore::create();
+        let service = test_service(&ctx, &lease_store, &erase_store);
+
+        let request = ServiceRequest::create(EraseRequest {
+            field: "isbn".into(),
+            value: json!("user-1"),
+            indexes: vec!["test-other".into()],
+        })
+        .with_path_param("index_id", "test")
+        .with_authorization(Authorization {
+            api_key: ApiKey {
+                name: String::from("test"),
+                scopes: vec![],
+                indexes: vec![IndexGrant {
+                    pattern: String::from("test"),
+                    scopes: vec![ApiKeyScope::Admin],
+                }],
+                tenant_id: None,
+            },
+            scope: ApiKeyScope::Admin,
+        });
+
+        let err = service.handle_request(request).await.unwrap_err();
+        assert_eq!(403, err.status());
+
+        for index_id in ["test", "test-other"] {
+            let index = ctx.index_loader().load_index(index_id, None).unwrap();
+            assert_eq!(1, index.reader().unwrap().searcher().num_docs());
+        }
+    }
+
+    #[tokio::test]
+    async fn erase_purges_every_partition_of_a_rolling_index() {
+        let today = Utc::now().date_naive();
+        let partitions = [today - Duration::days(1), today]
+            .map(|date| time_partition::partition_id("rolling", date));
+        let ctx = setup()
+            .with_documents(
+                &partitions[0],
+                vec![json!({ "__id": "a", "title": "alice" })],
+            )
+            .await
+            .with_documents(
+                &partitions[1],
+                vec![json!({ "__id": "b", "title": "alice" })],
+            )
+            .await;
+        let lease_store = TestLeaseStore::create();
+        let erase_store = MemoryEraseStore::create();
+        let service = test_service(&ctx, &lease_store, &erase_store);
+
+        let request = ServiceRequest::create(EraseRequest {
+            field: "title".into(),
+            value: json!("alice"),
+            indexes: vec![],
+        })
+        .with_path_param("index_id", "rolling");
+
+        let report = service.handle_request(request).await.unwrap();
+        let erased: Vec<_> = report
+            .indexes
+            .iter()
+            .map(|erasure| (erasure.index_id.as_str(), erasure.erased))
+            .collect();
+        assert_eq!(
+            vec![(partitions[0].as_str(), 1), (partitions[1].as_str(), 1)],
+            erased
+        );
+        for partition in &partitions {
+            let index = ctx.index_loader().load_index(partition, None).unwrap();
+            assert_eq!(0, index.reader().unwrap().searcher().num_docs());
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use async_trait::async_trait;
+
+use crate::schema::{SchemaLoader, SchemaProvider};
+use crate::service::{ServiceError, ServiceHandler, ServiceRequest, ServiceResponse};
+use crate::store::erase::{DDBEraseStore, EraseReport, EraseStore};
+use crate::{json, tenant, time_partition};
+
+/// Serves the report saved by a completed erase, for audits after the erase's own response is
+/// gone.
+pub struct EraseReportService {
+    schema_loader: Box<dyn SchemaLoader>,
+
+    erase_store: Box<dyn EraseStore>,
+}
+
+#[async_trait]
+impl ServiceHandler<json::Value, EraseReport> for EraseReportService {
+    async fn handle_request(
+        &self,
+        request: ServiceRequest<json::Value>,
+    ) -> ServiceResponse<EraseReport> {
+        let index_id = request.index_id()?;
+        let erase_id = request.path_param("erase_id")?;
+        let tenant_id = request.tenant()?;
+        let settings = self.schema_loader.load_settings(&index_id)?;
+        let is_alias =
+            time_partition::alias_config(self.schema_loader.as_ref(), &index_id, &settings)
+                .is_some();
+
+        // Erases that didn't cover the index in the path, or one of its partitions when it's the
+        // alias of a rolling index, are reported as missing. So are other tenants' erases.
+        self.erase_store
+            .get_report(&erase_id)
+            .await?
+            .filter(|report| {
+                report.indexes.iter().any(|erasure| {
+                    erasure.index_id == index_id
+                        || is_alias
+                            && time_partition::partition_date(&index_id, &erasure.index_id)
+                                .is_some()
+                })
+            })
+            .map(|mut report| {
+                for erasure in &mut report.indexes {
+                    if let Some(visible_id) =
+                        tenant::visible_id(tenant_id.as_deref(), &erasure.index_id)
+                    {
+                        erasure.index_id = visible_id.into();
+                    }
+                }
+                report
+            })
+            .ok_or_else(|| ServiceError::not_found(&format!("Erase [{}] not found", erase_id)))
+    }
+}
+
+impl EraseReportService {
+    pub async fn create() -> Self {
+        let erase_store = DDBEraseStore::create(None).await;
+        let schema_loader = SchemaProvider::lambda().await;
+
+        EraseReportService {
+            schema_loader: Box::new(schema_loader),
+            erase_store: Box::new(erase_store),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::erase::{IndexErasure, MemoryEraseStore};
+    use crate::test_utils::*;
+
+    fn report(index_ids: &[&str]) -> EraseReport {
+        EraseReport {
+            erase_id: "erase-1".into(),
+            field: "user_id".into(),
+            started_at: "2022-01-01T00:00:00Z".into(),
+            completed_at: "2022-01-01T00:00:01Z".into(),
+            indexes: index_ids
+                .iter()
+                .map(|index_id| IndexErasure {
+                    index_id: index_id.to_string(),
+                    erased: 1,
+                    merged_segments: 1,
+                    deleted_files: 1,
+                })
+                .collect(),
+            dry_run: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn get_erase_report() {
+        let erase_store = MemoryEraseStore::create();
+        erase_store
+            .put_report(&report(&["test", "test-other"]))
+            .await
+            .unwrap();
+        let service = EraseReportService {
+            schema_loader: Box::new(setup().schema_loader().clone()),
+            erase_store: Box::new(erase_store),
+        };
+
+        let request = |index_id: &str, erase_id: &str| {
+            ServiceRequest::create(json!({}))
+                .with_path_param("index_id", index_id)
+                .with_path_param("erase_id", erase_id)
+        };
+
+        let response = service
+            .handle_request(request("test-other", "erase-1"))
+            .await
+            .unwrap();
+        assert_eq!(report(&["test", "test-other"]), response);
+
+        let err = service
+            .handle_request(request("unrelated", "erase-1"))
+            .await
+            .unwrap_err();
+        assert_eq!(404, err.status());
+
+        let err = service
+            .handle_request(request("test", "missing"))
+            .await
+            .unwrap_err();
+        assert_eq!(404, err.status());
+    }
+
+    #[tokio::test]
+    async fn get_erase_report_of_a_rolling_index() {
+        let erase_store = MemoryEraseStore::create();
+        erase_store
+            .put_report(&report(&["rolling-2022-01-01"]))
+            .await
+            .unwrap();
+        let service = EraseReportService {
+            schema_loader: Box::new(setup().schema_loader().clone()),
+            erase_store: Box::new(erase_store),
+        };
+
+        let request = ServiceRequest::create(json!({}))
+            .with_path_param("index_id", "rolling")
+            .with_path_param("erase_id", "erase-1");
+        let response = service.handle_request(request).await.unwrap();
+        assert_eq!(report(&["rolling-2022-01-01"]), response);
+    }
+}
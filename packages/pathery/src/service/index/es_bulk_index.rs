@@ -0,0 +1,644 @@
+use std::collections::{HashMap, HashSet};
+use std::io::BufRead;
+use std::time::Instant;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use super::bulk_index::{MAX_DOCS_PER_JOB, MAX_DOCS_PER_SAVE};
+use crate::schema::{IndexSettings, SchemaLoader, SchemaProvider};
+use crate::search_doc::{SearchDoc, SearchDocId};
+use crate::service::{ServiceError, ServiceHandler, ServiceRequest, ServiceResponse};
+use crate::store::document::{DDBDocumentStore, DocumentStore, SearchDocRef};
+use crate::time_partition::WriteOp;
+use crate::worker::index_writer::client::{IndexWriterClient, LambdaIndexWriterClient};
+use crate::worker::index_writer::job::Job;
+use crate::{json, time_partition};
+
+/// Metadata line of an action, naming the document it writes. Other metadata, such as routing
+/// or pipelines, is ignored.
+#[derive(Deserialize, Debug, Default)]
+struct ActionMeta {
+    #[serde(rename = "_index")]
+    index: Option<String>,
+
+    #[serde(rename = "_id")]
+    id: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "lowercase")]
+enum BulkAction {
+    Index(ActionMeta),
+    Create(ActionMeta),
+    Update(ActionMeta),
+    Delete(ActionMeta),
+}
+
+impl BulkAction {
+    fn meta(&self) -> &ActionMeta {
+        match self {
+            BulkAction::Index(meta)
+            | BulkAction::Create(meta)
+            | BulkAction::Update(meta)
+            | BulkAction::Delete(meta) => meta,
+        }
+    }
+
+    /// Whether a document line follows the action's metadata.
+    fn has_source(&self) -> bool {
+        !matches!(self, BulkAction::Delete(_))
+    }
+}
+
+/// Document line of an `update` action.
+#[derive(Deserialize, Debug)]
+struct UpdateSource {
+    doc: Option<json::Value>,
+
+    #[serde(default)]
+    doc_as_upsert: bool,
+
+    upsert: Option<json::Value>,
+
+    script: Option<json::Value>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct BulkItemError {
+    #[serde(rename = "type")]
+    pub kind: String,
+
+    pub reason: String,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct BulkItemResult {
+    #[serde(rename = "_index")]
+    pub index: String,
+
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+
+    pub status: u16,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<BulkItemError>,
+}
+
+/// Outcome of one action, keyed by the action like Elasticsearch's.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BulkItem {
+    Index(BulkItemResult),
+    Create(BulkItemResult),
+    Update(BulkItemResult),
+    Delete(BulkItemResult),
+}
+
+impl BulkItem {
+    pub fn result(&self) -> &BulkItemResult {
+        match self {
+            BulkItem::Index(result)
+            | BulkItem::Create(result)
+            | BulkItem::Update(result)
+            | BulkItem::Delete(result) => result,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct EsBulkResponse {
+    pub took: u64,
+
+    /// Whether any action failed.
+    pub errors: bool,
+
+    /// Outcomes of the actions, in order.
+    pub items: Vec<BulkItem>,
+
+    /// Writer jobs applying the actions, for tracking with the job status API.
+    pub job_ids: Vec<String>,
+}
+
+/// An action that failed, as reported in its item.
+struct ItemFailure {
+    status: u16,
+    error: BulkItemError,
+}
+
+impl ItemFailure {
+    fn new(status: u16, kind: &str, reason: impl Into<String>) -> Self {
+        ItemFailure {
+            status,
+            error: BulkItemError {
+                kind: kind.into(),
+                reason: reason.into(),
+            },
+        }
+    }
+}
+
+/// The document written by an action, with the status and result it's reported with.
+type Applied = (String, u16, &'static str);
+
+/// Accepts Elasticsearch's `_bulk` format, newline delimited pairs of action and document lines,
+/// so that ingestion tooling written for Elasticsearch, such as Logstash and Filebeat, can write
+/// to an index unchanged. `index` and `create` actions index their document, `update` merges its
+/// `doc` into the stored document as a patch would, and `delete` deletes. Actions are applied in
+/// order, and reported per item in Elasticsearch's response format.
+pub struct EsBulkIndexService {
+    schema_loader: Box<dyn SchemaLoader>,
+
+    document_store: Box<dyn DocumentStore>,
+
+    writer_client: Box<dyn IndexWriterClient>,
+}
+
+/// Documents written by earlier actions of a request, by id, or None when deleted, so that later
+/// actions see them before they're committed.
+type Written = HashMap<String, Option<SearchDoc>>;
+
+#[async_trait]
+impl ServiceHandler<json::Value, EsBulkResponse> for EsBulkIndexService {
+    async fn handle_request(
+        &self,
+        request: ServiceRequest<json::Value>,
+    ) -> ServiceResponse<EsBulkResponse> {
+        let started = Instant::now();
+        let index_id = request.index_id()?;
+        let index_name = request.path_param("index_id")?;
+        let token = request.write_token()?;
+
+        let schema = self.schema_loader.load_schema(&index_id)?;
+        let settings = self.schema_loader.load_settings(&index_id)?;
+        let writer = BulkWriter {
+            service: self,
+            index_id: &index_id,
+            schema: &schema,
+            settings: &settings,
+        };
+
+        // Read up front, since the reader can't be held across the writes.
+        let lines = request
+            .body_reader()?
+            .lines()
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| {
+                ServiceError::invalid_request(&format!("Unable to read body: {}", err))
+            })?;
+        let mut lines = lines.into_iter().enumerate();
+        let mut items = vec![];
+        let mut ops = vec![];
+        let mut written = Written::new();
+
+        while let Some((idx, line)) = lines.next() {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let action: BulkAction = json::from_str(&line).map_err(|err| {
+                ServiceError::invalid_request(&format!(
+                    "Invalid action on line {}: {}",
+                    idx + 1,
+                    err
+                ))
+            })?;
+            let source = if action.has_source() {
+                let line = lines.next().map(|(_, line)| line).unwrap_or_default();
+                let source = json::from_str(&line).map_err(|err| {
+                    ServiceError::invalid_request(&format!(
+                        "Invalid document for the action on line {}: {}",
+                        idx + 1,
+                        err
+                    ))
+                })?;
+                Some(source)
+            } else {
+                None
+            };
+
+            let meta = action.meta();
+            let outcome = match &meta.index {
+                Some(index) if *index != index_name => Err(ItemFailure::new(
+                    400,
+                    "illegal_argument_exception",
+                    format!(
+                        "Actions can only write to [{}], the index of the request path",
+                        index_name
+                    ),
+                )),
+                _ => {
+                    writer
+                        .apply(&action, source, &mut ops, &mut written)
+                        .await?
+                }
+            };
+
+            let result = match outcome {
+                Ok((id, status, result)) => BulkItemResult {
+                    index: index_name.clone(),
+                    id: Some(id),
+                    status,
+                    result: Some(result.into()),
+                    error: None,
+                },
+                Err(ItemFailure { status, error }) => BulkItemResult {
+                    index: index_name.clone(),
+                    id: meta.id.clone(),
+                    status,
+                    result: None,
+                    error: Some(error),
+                },
+            };
+            items.push(match action {
+                BulkAction::Index(_) => BulkItem::Index(result),
+                BulkAction::Create(_) => BulkItem::Create(result),
+                BulkAction::Update(_) => BulkItem::Update(result),
+                BulkAction::Delete(_) => BulkItem::Delete(result),
+            });
+        }
+
+        // Deletes on the alias of a rolling index are rejected, so none need fanning out.
+        let partitions = time_partition::group_by_target(
+            self.schema_loader.as_ref(),
+            &index_id,
+            &settings,
+            &schema,
+            &[],
+            ops,
+        );
+
+        let mut job_ids = vec![];
+        for (target, ops) in &partitions {
+            for batch in last_writes(ops).chunks(MAX_DOCS_PER_JOB) {
+                let documents: Vec<SearchDoc> = batch
+                    .iter()
+                    .filter_map(WriteOp::document)
+                    .cloned()
+                    .collect();
+                let mut doc_refs = vec![];
+                for chunk in documents.chunks(MAX_DOCS_PER_SAVE) {
+                    doc_refs.extend(self.document_store.save_documents(chunk.to_vec()).await?);
+                }
+
+                let mut doc_refs = doc_refs.into_iter();
+                let mut job = Job::create(target).with_token(token);
+                for op in batch {
+                    match op {
+                        WriteOp::Index(_) => {
+                            job.index_doc(doc_refs.next().expect("every document should be saved"))
+                        }
+                        WriteOp::Delete(doc_id) => job.delete_doc(doc_id.clone()),
+                    }
+                }
+
+                job_ids.push(self.writer_client.submit_job(job).await?);
+            }
+        }
+
+        Ok(EsBulkResponse {
+            took: started.elapsed().as_millis() as u64,
+            errors: items.iter().any(|item| item.result().error.is_some()),
+            items,
+            job_ids,
+        })
+    }
+}
+
+/// The last of `ops` to write each document, in order. Writer jobs apply their deletes before
+/// their documents, so earlier writes of a document could otherwise win.
+fn last_writes(ops: &[WriteOp]) -> Vec<WriteOp> {
+    let mut seen = HashSet::new();
+    let mut last: Vec<WriteOp> = ops
+        .iter()
+        .rev()
+        .filter(|op| seen.insert(op.doc_id()))
+        .cloned()
+        .collect();
+    last.reverse();
+    last
+}
+
+/// Applies the actions of a request to one index.
+struct BulkWriter<'a> {
+    service: &'a EsBulkIndexService,
+    index_id: &'a str,
+    schema: &'a tantivy::schema::Schema,
+    settings: &'a IndexSettings,
+}
+
+impl BulkWriter<'_> {
+    /// Adds the writes of `action` to `ops`, returning the item failure when it can't be applied.
+    async fn apply(
+        &self,
+        action: &BulkAction,
+        source: Option<json::Value>,
+        ops: &mut Vec<WriteOp>,
+        written: &mut Written,
+    ) -> Result<Result<Applied, ItemFailure>, ServiceError> {
+        let meta = action.meta();
+        let source = source.unwrap_or_default();
+
+        let document = match action {
+            BulkAction::Index(_) => self.parse(source, meta.id.as_deref()),
+            BulkAction::Create(_) => match self.parse(source, meta.id.as_deref()) {
+                Ok(document) if self.stored(document.id(), written).await?.is_some() => {
+                    Err(ItemFailure::new(
+                        409,
+                        "version_conflict_engine_exception",
+                        format!(
+                            "[{}]: version conflict, document already exists",
+                            document.id().id()
+                        ),
+                    ))
+                }
+                parsed => parsed,
+            },
+            BulkAction::Update(_) => {
+                let Some(id) = meta.id.as_deref() else {
+                    return Ok(Err(missing_id("update")));
+                };
+                let stored = self.stored(&SearchDocId::parse(id), written).await?;
+                let updated = self.update(id, stored.as_ref(), source);
+                return Ok(updated.map(|(document, status, result)| {
+                    (self.write(document, ops, written), status, result)
+                }));
+            }
+            BulkAction::Delete(_) => {
+                let Some(id) = meta.id.as_deref() else {
+                    return Ok(Err(missing_id("delete")));
+                };
+                let schema_loader = self.service.schema_loader.as_ref();
+                if time_partition::alias_config(schema_loader, self.index_id, self.settings)
+                    .is_some()
+                {
+                    return Ok(Err(ItemFailure::new(
+                        400,
+                        "illegal_argument_exception",
+                        format!(
+                            "[{}] is a rolling index, delete from the partition holding the \
+                             document",
+                            self.index_id
+                        ),
+                    )));
+                }
+                ops.push(WriteOp::Delete(SearchDocId::parse(id)));
+                written.insert(id.into(), None);
+                return Ok(Ok((id.into(), 200, "deleted")));
+            }
+        };
+
+        Ok(document.map(|document| (self.write(document, ops, written), 201, "created")))
+    }
+
+    /// The document of an `update` action, with its status and result.
+    fn update(
+        &self,
+        id: &str,
+        stored: Option<&SearchDoc>,
+        source: json::Value,
+    ) -> Result<(SearchDoc, u16, &'static str), ItemFailure> {
+        let source: UpdateSource = json::from_value(source)
+            .map_err(|err| ItemFailure::new(400, "x_content_parse_exception", err.to_string()))?;
+        if source.script.is_some() {
+            return Err(ItemFailure::new(
+                400,
+                "illegal_argument_exception",
+                "Scripted updates aren't supported",
+            ));
+        }
+
+        match (stored, source.doc, source.upsert) {
+            (Some(stored), Some(doc), _) => stored
+                .merge(self.schema, doc, self.settings)
+                .map(|document| (document, 200, "updated"))
+                .map_err(|err| ItemFailure::new(400, "mapper_parsing_exception", err.to_string())),
+            (Some(_), None, _) => Err(ItemFailure::new(
+                400,
+                "action_request_validation_exception",
+                "Validation Failed: 1: script or doc is missing;",
+            )),
+            (None, _, Some(upsert)) => self
+                .parse(upsert, Some(id))
+                .map(|document| (document, 201, "created")),
+            (None, Some(doc), None) if source.doc_as_upsert => self
+                .parse(doc, Some(id))
+                .map(|document| (document, 201, "created")),
+            (None, _, None) => Err(ItemFailure::new(
+                404,
+                "document_missing_exception",
+                format!("[{}]: document missing", id),
+            )),
+        }
+    }
+
+    /// Parses the document of an action, under `id` when the action names one.
+    fn parse(&self, mut source: json::Value, id: Option<&str>) -> Result<SearchDoc, ItemFailure> {
+        if let (Some(id), Some(object)) = (id, source.as_object_mut()) {
+            object.insert("__id".into(), id.into());
+        }
+
+        SearchDoc::from_json_with_settings(self.schema, source, self.settings)
+            .map_err(|err| ItemFailure::new(400, "mapper_parsing_exception", err.to_string()))
+    }
+
+    /// The document stored under `id`, including those written earlier in the request.
+    async fn stored(
+        &self,
+        id: &SearchDocId,
+        written: &Written,
+    ) -> Result<Option<SearchDoc>, ServiceError> {
+        if let Some(document) = written.get(id.id()) {
+            return Ok(document.clone());
+        }
+
+        let documents = self
+            .service
+            .document_store
+            .get_documents(vec![SearchDocRef::from(id.clone())])
+            .await?;
+        Ok(documents.into_iter().next())
+    }
+
+    /// Queues `document` to be indexed, returning its id.
+    fn write(&self, document: SearchDoc, ops: &mut Vec<WriteOp>, written: &mut Written) -> String {
+        let id = document.id().id().to_string();
+        written.insert(id.clone(), Some(document.clone()));
+        ops.push(WriteOp::Index(document));
+        id
+    }
+}
+
+fn missing_id(action: &str) -> ItemFailure {
+    ItemFailure::new(
+        400,
+        "action_request_validation_exception",
+        format!(
+            "Validation Failed: 1: an id is required for a {} action;",
+            action
+        ),
+    )
+}
+
+impl EsBulkIndexService {
+    pub async fn create() -> Self {
+        let document_store = DDBDocumentStore::create(None).await;
+        let writer_client = LambdaIndexWriterClient::create(None).await;
+        let schema_loader = SchemaProvider::lambda().await;
+
+        EsBulkIndexService {
+            document_store: Box::new(document_store),
+            writer_client: Box::new(writer_client),
+            schema_loader: Box::new(schema_loader),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::IndexLoader;
+    use crate::test_utils::*;
+
+    fn test_service(ctx: &TestContext) -> EsBulkIndexService {
+        EsBulkIndexService {
+            schema_loader: Box::new(ctx.schema_loader().clone()),
+            document_store: Box::new(ctx.document_store().clone()),
+            writer_client: Box::new(ctx.writer_client().clone()),
+        }
+    }
+
+    fn bulk(lines: &[json::Value]) -> ServiceRequest<json::Value> {
+        let body = lines
+            .iter()
+            .map(|line| format!("{}\n", line))
+            .collect::<String>();
+        ServiceRequest::create_raw(&body).with_path_param("index_id", "test")
+    }
+
+    #[tokio::test]
+    async fn es_bulk_applies_actions_in_order() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![json!({ "__id": "a", "title": "hello", "author": "ann" })],
+            )
+            .await;
+        let service = test_service(&ctx);
+
+        let request = bulk(&[
+            json!({ "index": { "_index": "test", "_id": "b" } }),
+            json!({ "title": "world" }),
+            json!({ "create": { "_id": "a" } }),
+            json!({ "title": "again" }),
+            json!({ "update": { "_id": "a" } }),
+            json!({ "doc": { "title": "goodbye" } }),
+            json!({ "update": { "_id": "c" } }),
+            json!({ "doc": { "title": "missing" } }),
+            json!({ "update": { "_id": "d" } }),
+            json!({ "doc": { "title": "upserted" }, "doc_as_upsert": true }),
+            json!({ "delete": { "_id": "b" } }),
+            json!({ "index": { "_index": "other" } }),
+            json!({ "title": "elsewhere" }),
+            json!({ "create": {} }),
+            json!({ "title": 1 }),
+        ]);
+
+        let response = service.handle_request(request).await.unwrap();
+
+        let outcomes: Vec<_> = response
+            .items
+            .iter()
+            .map(|item| {
+                let result = item.result();
+                (
+                    result.id.clone(),
+                    result.status,
+                    result.result.clone(),
+                    result.error.as_ref().map(|error| error.kind.clone()),
+                )
+            })
+            .collect();
+        let ok =
+            |id: &str, status, result: &str| (Some(id.into()), status, Some(result.into()), None);
+        let failed = |id: Option<&str>, status, kind: &str| {
+            (id.map(String::from), status, None, Some(kind.into()))
+        };
+        assert_eq!(
+            vec![
+                ok("b", 201, "created"),
+                failed(Some("a"), 409, "version_conflict_engine_exception"),
+                ok("a", 200, "updated"),
+                failed(Some("c"), 404, "document_missing_exception"),
+                ok("d", 201, "created"),
+                ok("b", 200, "deleted"),
+                failed(None, 400, "illegal_argument_exception"),
+                failed(None, 400, "mapper_parsing_exception"),
+            ],
+            outcomes
+        );
+        assert!(response.errors);
+        assert_eq!(1, response.job_ids.len());
+        assert!(matches!(response.items[0], BulkItem::Index(_)));
+
+        let searcher = ctx
+            .index_loader()
+            .load_index("test", None)
+            .unwrap()
+            .reader()
+            .unwrap()
+            .searcher();
+        assert_eq!(2, searcher.num_docs());
+
+        let stored = ctx
+            .document_store()
+            .get_documents(vec![SearchDocRef::from(SearchDocId::parse("a"))])
+            .await
+            .unwrap();
+        assert_eq!(json!("goodbye"), stored[0].content()["title"]);
+        assert_eq!(json!("ann"), stored[0].content()["author"]);
+    }
+
+    #[tokio::test]
+    async fn es_bulk_rejects_deletes_on_a_rolling_alias() {
+        let ctx = setup();
+        let service = test_service(&ctx);
+
+        let body = [
+            json!({ "index": { "_id": "a" } }),
+            json!({ "title": "hello", "timestamp": "2022-11-14T00:00:00Z" }),
+            json!({ "delete": { "_id": "b" } }),
+        ]
+        .iter()
+        .map(|line| format!("{}\n", line))
+        .collect::<String>();
+        let request = ServiceRequest::create_raw(&body).with_path_param("index_id", "rolling");
+        let response = service.handle_request(request).await.unwrap();
+
+        let statuses: Vec<_> = response
+            .items
+            .iter()
+            .map(|item| item.result().status)
+            .collect();
+        assert_eq!(vec![201, 400], statuses);
+        assert_eq!(1, response.job_ids.len());
+    }
+
+    #[tokio::test]
+    async fn es_bulk_rejects_malformed_bodies() {
+        let ctx = setup();
+        let service = test_service(&ctx);
+
+        let request = bulk(&[json!({ "upsert": { "_id": "a" } })]);
+        let err = service.handle_request(request).await.unwrap_err();
+        assert_eq!(400, err.status());
+
+        let request = bulk(&[json!({ "index": { "_id": "a" } })]);
+        let err = service.handle_request(request).await.unwrap_err();
+        assert_eq!(400, err.status());
+    }
+}
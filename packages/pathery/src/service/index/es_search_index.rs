@@ -0,0 +1,433 @@
+use std::time::Instant;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use super::query_index::{
+    HighlightOptions, QueryIndexService, QueryRequest, SearchHit, SortOptions, SortOrder,
+    TotalHits, TrackTotalHits,
+};
+use crate::json;
+use crate::query::{es, Query};
+use crate::service::{ServiceError, ServiceHandler, ServiceRequest, ServiceResponse};
+
+/// Most matches a search can page through with `from` and `size`, as in OpenSearch's default
+/// `index.max_result_window`.
+const MAX_RESULT_WINDOW: usize = 10_000;
+
+/// Number of matches returned when `size` isn't given.
+const DEFAULT_SIZE: usize = 10;
+
+/// Sorts by relevance.
+const SCORE: &str = "_score";
+
+/// Body of an OpenSearch `_search` request. Options other than these, such as `aggs` or
+/// `highlight`, are rejected.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct EsSearchRequest {
+    /// Query clause translated by [`es::translate`]. Defaults to `match_all`.
+    pub query: Option<json::Value>,
+
+    /// Number of matches to skip.
+    #[serde(default)]
+    pub from: usize,
+
+    /// Number of matches to return. Defaults to 10.
+    pub size: Option<usize>,
+
+    /// A field, `{ field: order }` or `{ field: { "order": order } }`, or an array of them.
+    pub sort: Option<json::Value>,
+
+    /// `false` to leave out documents, or the fields to return of them.
+    #[serde(rename = "_source")]
+    pub source: Option<json::Value>,
+
+    /// Defaults to counting up to 10,000 matches.
+    pub track_total_hits: Option<TrackTotalHits>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct EsHit {
+    #[serde(rename = "_index")]
+    pub index: String,
+
+    #[serde(rename = "_id")]
+    pub id: String,
+
+    /// Relevance score, null for sorted searches.
+    #[serde(rename = "_score")]
+    pub score: Option<f32>,
+
+    #[serde(rename = "_source", skip_serializing_if = "Option::is_none")]
+    pub source: Option<json::Value>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct EsHits {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<TotalHits>,
+
+    pub max_score: Option<f32>,
+
+    pub hits: Vec<EsHit>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct EsSearchResponse {
+    pub took: u64,
+
+    pub timed_out: bool,
+
+    pub hits: EsHits,
+}
+
+/// Accepts a subset of OpenSearch's `_search` request body and answers in its response format,
+/// so that applications migrating from a managed OpenSearch or Elasticsearch cluster can keep
+/// their queries. Queries are translated to the query DSL and run by [`QueryIndexService`].
+pub struct EsSearchIndexService {
+    query_service: QueryIndexService,
+}
+
+#[async_trait]
+impl ServiceHandler<EsSearchRequest, EsSearchResponse> for EsSearchIndexService {
+    async fn handle_request(
+        &self,
+        request: ServiceRequest<EsSearchRequest>,
+    ) -> ServiceResponse<EsSearchResponse> {
+        let started = Instant::now();
+        let index_id = request.index_id()?;
+        let index_name = request.path_param("index_id")?;
+        let body = request.body()?;
+
+        let from = body.from;
+        let with_source = body.source != Some(json::Value::Bool(false));
+        let query = translate_request(body)?;
+        let sorted = query.sort.is_some();
+        let response = self.query_service.query(&index_id, query).await?;
+
+        let hits: Vec<EsHit> = response
+            .matches
+            .into_iter()
+            .skip(from)
+            .map(|hit| es_hit(&index_name, hit, sorted, with_source))
+            .collect();
+        let max_score = hits
+            .iter()
+            .filter_map(|hit| hit.score)
+            .max_by(|a, b| a.total_cmp(b));
+
+        Ok(EsSearchResponse {
+            took: started.elapsed().as_millis() as u64,
+            timed_out: response.timed_out,
+            hits: EsHits {
+                total: response.total,
+                max_score,
+                hits,
+            },
+        })
+    }
+}
+
+impl EsSearchIndexService {
+    pub async fn create() -> Self {
+        EsSearchIndexService::new(QueryIndexService::create().await)
+    }
+
+    pub fn new(query_service: QueryIndexService) -> Self {
+        EsSearchIndexService { query_service }
+    }
+}
+
+/// The query equivalent to `body`. Pages after the first are found by querying for every match
+/// up to the end of the page, since `from` has no cursor to start from.
+fn translate_request(body: EsSearchRequest) -> Result<QueryRequest, ServiceError> {
+    let size = body.size.unwrap_or(DEFAULT_SIZE);
+    let window = body
+        .from
+        .checked_add(size)
+        .filter(|window| *window <= MAX_RESULT_WINDOW)
+        .ok_or_else(|| {
+            ServiceError::invalid_request(&format!(
+                "Result window is too large, from + size must be less than or equal to [{}]",
+                MAX_RESULT_WINDOW
+            ))
+        })?;
+
+    let query = match &body.query {
+        Some(query) => es::translate(query)?,
+        None => Query::MatchAll {},
+    };
+    let (sort, tiebreak) = translate_sort(body.sort.as_ref())?;
+
+    Ok(QueryRequest {
+        query,
+        sort,
+        tiebreak,
+        limit: Some(window),
+        fields: translate_source(body.source.as_ref())?,
+        track_total_hits: body
+            .track_total_hits
+            .unwrap_or(TrackTotalHits::UpTo(MAX_RESULT_WINDOW as u64)),
+        // OpenSearch only highlights when asked to.
+        highlight: HighlightOptions {
+            fields: Some(vec![]),
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+}
+
+/// The sort and tiebreak equivalent to `sort`. Searches can be ordered by relevance or one field,
+/// with ties broken by a second field in ascending order.
+fn translate_sort(
+    sort: Option<&json::Value>,
+) -> Result<(Option<SortOptions>, Option<String>), ServiceError> {
+    let criteria = match sort {
+        None => vec![],
+        Some(json::Value::Array(criteria)) => criteria.iter().map(sort_criterion).collect(),
+        Some(criterion) => vec![sort_criterion(criterion)],
+    }
+    .into_iter()
+    .collect::<Result<Vec<_>, _>>()?;
+
+    let unsupported = || {
+        ServiceError::invalid_request(
+            "Only sorting by _score or one field, then ascending by another field, is supported",
+        )
+    };
+    let (primary, tiebreak) = match criteria.as_slice() {
+        [] => return Ok((None, None)),
+        [primary] => (primary, None),
+        [primary, (field, SortOrder::Asc)] if field != SCORE => (primary, Some(field.clone())),
+        _ => return Err(unsupported()),
+    };
+
+    let sort = match primary {
+        (field, SortOrder::Desc) if field == SCORE => None,
+        (field, _) if field == SCORE => return Err(unsupported()),
+        (field, order) => Some(SortOptions {
+            field: field.clone(),
+            order: *order,
+        }),
+    };
+
+    Ok((sort, tiebreak))
+}
+
+/// A field and its order, which defaults to descending for `_score` and ascending otherwise.
+fn sort_criterion(criterion: &json::Value) -> Result<(String, SortOrder), ServiceError> {
+    let default_order = |field: &str| match field {
+        SCORE => SortOrder::Desc,
+        _ => SortOrder::Asc,
+    };
+    let parse_order = |order: &json::Value| {
+        json::from_value::<SortOrder>(order.clone()).map_err(|_| {
+            ServiceError::invalid_request(&format!("Expected asc or desc for order, got {}", order))
+        })
+    };
+
+    match criterion {
+        json::Value::String(field) => Ok((field.clone(), default_order(field))),
+        json::Value::Object(criterion) if criterion.len() == 1 => {
+            let (field, order) = criterion
+                .iter()
+                .next()
+                .expect("criterion should have a field");
+            let order = match order {
+                json::Value::Object(options) => match (options.len(), options.get("order")) {
+                    (0, None) => default_order(field),
+                    (1, Some(order)) => parse_order(order)?,
+                    _ => {
+                        return Err(ServiceError::invalid_request(&format!(
+                            "Only the order of sort field [{}] is supported",
+                            field
+                        )))
+                    }
+                },
+                order => parse_order(order)?,
+            };
+            Ok((field.clone(), order))
+        }
+        _ => Err(ServiceError::invalid_request(&format!(
+            "Invalid sort criterion {}",
+            criterion
+        ))),
+    }
+}
+
+/// The fields to return of each match, always including the id, or None for every field.
+fn translate_source(source: Option<&json::Value>) -> Result<Option<Vec<String>>, ServiceError> {
+    let mut fields = match source {
+        None | Some(json::Value::Bool(true)) => return Ok(None),
+        Some(json::Value::Bool(false)) => vec![],
+        Some(json::Value::String(field)) => vec![field.clone()],
+        Some(json::Value::Array(fields)) => fields
+            .iter()
+            .map(|field| {
+                field.as_str().map(String::from).ok_or_else(|| {
+                    ServiceError::invalid_request(&format!(
+                        "Expected a field name in _source, got {}",
+                        field
+                    ))
+                })
+            })
+            .collect::<Result<_, _>>()?,
+        Some(source) => {
+            return Err(ServiceError::invalid_request(&format!(
+                "Expected a boolean or field names for _source, got {}",
+                source
+            )))
+        }
+    };
+    fields.push(String::from("__id"));
+
+    Ok(Some(fields))
+}
+
+/// `hit` as OpenSearch returns it, its document as `_source` with single values unwrapped from
+/// their arrays.
+fn es_hit(index_name: &str, hit: SearchHit, sorted: bool, with_source: bool) -> EsHit {
+    let mut doc = match hit.doc {
+        json::Value::Object(doc) => doc,
+        _ => json::Map::new(),
+    };
+    let id = doc
+        .remove("__id")
+        .and_then(|id| id.get(0).and_then(json::Value::as_str).map(String::from))
+        .unwrap_or_default();
+
+    let source = with_source.then(|| {
+        json::Value::Object(
+            doc.into_iter()
+                .map(|(name, value)| match value {
+                    json::Value::Array(mut values) if values.len() == 1 => (name, values.remove(0)),
+                    value => (name, value),
+                })
+                .collect(),
+        )
+    });
+
+    EsHit {
+        index: index_name.into(),
+        id,
+        score: (!sorted).then_some(hit.score),
+        source,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cursor::CursorKey;
+    use crate::service::index::query_index::TotalHitsRelation;
+    use crate::test_utils::*;
+
+    fn test_service(ctx: &TestContext) -> EsSearchIndexService {
+        EsSearchIndexService::new(QueryIndexService::new(
+            Box::new(ctx.schema_loader().clone()),
+            Box::new(ctx.index_loader().clone()),
+            Box::new(ctx.document_store().clone()),
+            CursorKey::new(b"test"),
+        ))
+    }
+
+    fn search(body: json::Value) -> ServiceRequest<EsSearchRequest> {
+        ServiceRequest::create_raw(&body.to_string()).with_path_param("index_id", "test")
+    }
+
+    async fn books() -> TestContext {
+        setup()
+            .with_documents(
+                "test",
+                vec![
+                    json!({ "__id": "zen", "title": "zen and the art", "isbn": "a", "date_added": "2020-01-01T00:00:00Z" }),
+                    json!({ "__id": "war", "title": "the art of war", "isbn": "b", "date_added": "2021-01-01T00:00:00Z" }),
+                    json!({ "__id": "moby", "title": "moby dick", "isbn": "c", "date_added": "2022-01-01T00:00:00Z" }),
+                ],
+            )
+            .await
+    }
+
+    #[tokio::test]
+    async fn es_search_translates_queries_and_pages() {
+        let ctx = books().await;
+        let service = test_service(&ctx);
+
+        let request = search(json!({
+            "query": {
+                "bool": {
+                    "must": { "match": { "title": "art" } },
+                    "must_not": [{ "term": { "isbn": "c" } }]
+                }
+            },
+            "sort": [{ "date_added": { "order": "desc" } }],
+            "from": 1,
+            "size": 5,
+            "_source": ["title"]
+        }));
+        let response = service.handle_request(request).await.unwrap();
+
+        assert_eq!(
+            Some(TotalHits {
+                value: 2,
+                relation: TotalHitsRelation::Eq,
+            }),
+            response.hits.total
+        );
+        assert_eq!(
+            vec![EsHit {
+                index: String::from("test"),
+                id: String::from("zen"),
+                score: None,
+                source: Some(json!({ "title": "zen and the art" })),
+            }],
+            response.hits.hits
+        );
+    }
+
+    #[tokio::test]
+    async fn es_search_responds_like_opensearch() {
+        let ctx = books().await;
+        let service = test_service(&ctx);
+
+        let request = search(json!({ "query": { "match": { "title": "moby" } } }));
+        let response = service.handle_request(request).await.unwrap();
+        let response = json::to_value(response).unwrap();
+
+        assert_eq!(json!(false), response["timed_out"]);
+        assert_eq!(
+            json!({ "value": 1, "relation": "eq" }),
+            response["hits"]["total"]
+        );
+        assert_eq!(
+            response["hits"]["max_score"],
+            response["hits"]["hits"][0]["_score"]
+        );
+        assert_eq!(json!("moby"), response["hits"]["hits"][0]["_id"]);
+        assert_eq!(
+            json!("moby dick"),
+            response["hits"]["hits"][0]["_source"]["title"]
+        );
+    }
+
+    #[test]
+    fn es_search_rejects_unsupported_requests() {
+        let rejected = [
+            json!({ "aggs": {} }),
+            json!({ "from": 9995, "size": 10 }),
+            json!({ "from": usize::MAX, "size": 1 }),
+            json!({ "sort": [{ "_score": "asc" }] }),
+            json!({ "sort": ["date_added", "year", "isbn"] }),
+            json!({ "sort": [{ "date_added": { "order": "desc", "missing": "_last" } }] }),
+            json!({ "_source": { "includes": ["title"] } }),
+        ];
+
+        for body in rejected {
+            let result = json::from_value(body.clone())
+                .map_err(|err| ServiceError::invalid_request(&err.to_string()))
+                .and_then(translate_request);
+            assert!(result.is_err(), "{} should be rejected", body);
+        }
+    }
+}
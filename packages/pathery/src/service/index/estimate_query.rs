@@ -0,0 +1,257 @@
+//! Estimates of what a query costs to run, from the index's term statistics and the shape of the
+//! query, without running it.
+//!
+//! A query's cost is the postings it reads for its terms, every document for each clause that
+//! scans them all, such as ranges and `match_all`, and each of its matches again per
+//! aggregation. Query strings are counted as one clause, reading the postings of their terms.
+
+use std::collections::BTreeMap;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use super::QueryRequest;
+use crate::index::{IndexLoader, LambdaIndexLoader};
+use crate::query::Query;
+use crate::reader_cache::ReaderCache;
+use crate::schema::{SchemaLoader, SchemaProvider};
+use crate::service::{ServiceHandler, ServiceRequest, ServiceResponse};
+use crate::time_partition;
+
+/// Costs below this are [`CostClass::Low`].
+const LOW_COST: u64 = 100_000;
+
+/// Costs from this up are [`CostClass::High`].
+const HIGH_COST: u64 = 10_000_000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CostClass {
+    #[default]
+    Low,
+    Medium,
+    High,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+pub struct QueryEstimate {
+    pub cost: CostClass,
+
+    /// Cost in documents read, including reads of a match by each aggregation.
+    pub cost_units: u64,
+
+    pub num_docs: u64,
+
+    /// Documents the query reads, an upper bound of its matches.
+    pub scanned_docs: u64,
+
+    /// Distinct terms the query looks up.
+    pub terms: usize,
+
+    /// Postings read for the query's terms.
+    pub postings: u64,
+
+    /// Leaf clauses of the query and its filters.
+    pub clauses: usize,
+
+    /// Clauses that read every document.
+    pub full_scans: usize,
+
+    pub aggregations: usize,
+}
+
+impl QueryEstimate {
+    fn add(&mut self, other: QueryEstimate) {
+        self.cost_units += other.cost_units;
+        self.num_docs += other.num_docs;
+        self.scanned_docs += other.scanned_docs;
+        self.terms = self.terms.max(other.terms);
+        self.postings += other.postings;
+        self.clauses = other.clauses;
+        self.full_scans = other.full_scans;
+        self.aggregations = other.aggregations;
+    }
+
+    fn classify(mut self) -> Self {
+        self.cost = match self.cost_units {
+            units if units < LOW_COST => CostClass::Low,
+            units if units < HIGH_COST => CostClass::Medium,
+            _ => CostClass::High,
+        };
+        self
+    }
+}
+
+/// Counts the leaf clauses of `query`, and those among them that read every document.
+fn count_clauses(query: &Query, clauses: &mut usize, full_scans: &mut usize) {
+    match query {
+        Query::Bool(bool_query) => {
+            for clause in bool_query
+                .must
+                .iter()
+                .chain(&bool_query.should)
+                .chain(&bool_query.must_not)
+                .chain(&bool_query.filter)
+            {
+                count_clauses(clause, clauses, full_scans);
+            }
+        }
+        Query::Range { .. } | Query::MatchAll {} => {
+            *clauses += 1;
+            *full_scans += 1;
+        }
+        Query::QueryString(_) | Query::Term { .. } | Query::Match { .. } => *clauses += 1,
+    }
+}
+
+pub struct EstimateQueryService {
+    schema_loader: Box<dyn SchemaLoader>,
+
+    index_loader: Box<dyn IndexLoader>,
+
+    reader_cache: ReaderCache,
+}
+
+#[async_trait]
+impl ServiceHandler<QueryRequest, QueryEstimate> for EstimateQueryService {
+    async fn handle_request(
+        &self,
+        request: ServiceRequest<QueryRequest>,
+    ) -> ServiceResponse<QueryEstimate> {
+        let index_id = request.index_id()?;
+        let body = request.body()?;
+
+        let settings = self.schema_loader.load_settings(&index_id)?;
+        let index_ids =
+            match time_partition::alias_config(self.schema_loader.as_ref(), &index_id, &settings) {
+                Some(config) => time_partition::retained_partitions(
+                    self.index_loader.as_ref(),
+                    &index_id,
+                    config,
+                )?
+                .into_iter()
+                .map(|(_, partition)| partition)
+                .collect(),
+                None => vec![index_id],
+            };
+
+        let mut estimate = QueryEstimate::default();
+        for index_id in index_ids {
+            estimate.add(self.estimate(&index_id, body.clone())?);
+        }
+
+        Ok(estimate.classify())
+    }
+}
+
+impl EstimateQueryService {
+    pub async fn create() -> Self {
+        EstimateQueryService {
+            schema_loader: Box::new(SchemaProvider::lambda().await),
+            index_loader: Box::new(LambdaIndexLoader::create().await),
+            reader_cache: ReaderCache::default(),
+        }
+    }
+
+    fn estimate(&self, index_id: &str, body: QueryRequest) -> ServiceResponse<QueryEstimate> {
+        let settings = self.schema_loader.load_settings(index_id)?;
+        let index = self.index_loader.load_index(index_id, None)?;
+        let searcher = self.reader_cache.reader(index_id, None, &index)?.searcher();
+        let body = body.resolve_aliases(&index.schema(), &settings);
+
+        let mut queries = vec![body.query.clone()];
+        queries.extend(body.post_filter.clone());
+        queries.extend(body.facet_selections(None));
+
+        let mut clauses = 0;
+        let mut full_scans = 0;
+        let mut terms = BTreeMap::new();
+        for query in &queries {
+            count_clauses(query, &mut clauses, &mut full_scans);
+            query.compile(&index, &settings)?.query_terms(&mut terms);
+        }
+
+        let mut postings = 0;
+        for term in terms.keys() {
+            postings += searcher.doc_freq(term).unwrap_or_default();
+        }
+
+        let num_docs = searcher.num_docs();
+        let full_scan_docs = full_scans as u64 * num_docs;
+        let scanned_docs = (postings + full_scan_docs).min(num_docs);
+        let aggregations = body.aggs.len();
+
+        Ok(QueryEstimate {
+            cost: CostClass::default(),
+            cost_units: postings + full_scan_docs + aggregations as u64 * scanned_docs,
+            num_docs,
+            scanned_docs,
+            terms: terms.len(),
+            postings,
+            clauses,
+            full_scans,
+            aggregations,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[tokio::test]
+    async fn estimate_query_cost() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![
+                    json!({ "title": "hello world", "date_added": "2022-11-23T18:24:40Z" }),
+                    json!({ "title": "hello there", "date_added": "2022-11-24T18:24:40Z" }),
+                    json!({ "title": "goodbye", "date_added": "2022-11-25T18:24:40Z" }),
+                ],
+            )
+            .await;
+        let service = EstimateQueryService {
+            schema_loader: Box::new(ctx.schema_loader().clone()),
+            index_loader: Box::new(ctx.index_loader().clone()),
+            reader_cache: ReaderCache::default(),
+        };
+
+        let request = ServiceRequest::create_raw(&json!({ "query": "title:hello" }).to_string())
+            .with_path_param("index_id", "test");
+        let estimate = service.handle_request(request).await.unwrap();
+
+        assert_eq!(
+            QueryEstimate {
+                cost: CostClass::Low,
+                cost_units: 2,
+                num_docs: 3,
+                scanned_docs: 2,
+                terms: 1,
+                postings: 2,
+                clauses: 1,
+                full_scans: 0,
+                aggregations: 0,
+            },
+            estimate
+        );
+
+        let request = ServiceRequest::create_raw(
+            &json!({
+                "query": { "match_all": {} },
+                "aggs": { "daily": { "date_histogram": {
+                    "field": "date_added",
+                    "fixed_interval": 86_400_000
+                } } }
+            })
+            .to_string(),
+        )
+        .with_path_param("index_id", "test");
+        let estimate = service.handle_request(request).await.unwrap();
+
+        assert_eq!(3, estimate.scanned_docs);
+        assert_eq!(1, estimate.full_scans);
+        assert_eq!(6, estimate.cost_units);
+    }
+}
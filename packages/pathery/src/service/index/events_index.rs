@@ -0,0 +1,130 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::json;
+use crate::service::{ServiceError, ServiceHandler, ServiceRequest, ServiceResponse};
+use crate::store::event::{DDBEventStore, EventEntry, EventStore};
+
+/// Events returned when the request doesn't give a limit.
+const DEFAULT_LIMIT: usize = 100;
+
+const MAX_LIMIT: usize = 1000;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct EventsResponse {
+    pub events: Vec<EventEntry>,
+}
+
+/// The timeline of an index: its commits, merges, snapshots, restores, schema extensions and
+/// settings changes, oldest first, for finding what changed around the time its behavior did.
+/// Events are kept for 30 days, including after the index is deleted.
+pub struct EventsIndexService {
+    event_store: Box<dyn EventStore>,
+}
+
+fn time_param(
+    request: &ServiceRequest<json::Value>,
+    name: &str,
+) -> ServiceResponse<Option<DateTime<Utc>>> {
+    request
+        .query_param(name)
+        .map(|value| {
+            DateTime::parse_from_rfc3339(&value)
+                .map(|time| time.with_timezone(&Utc))
+                .map_err(|_| {
+                    ServiceError::invalid_request(&format!(
+                        "Expected {} to be an RFC 3339 timestamp, got [{}]",
+                        name, value
+                    ))
+                })
+        })
+        .transpose()
+}
+
+#[async_trait]
+impl ServiceHandler<json::Value, EventsResponse> for EventsIndexService {
+    async fn handle_request(
+        &self,
+        request: ServiceRequest<json::Value>,
+    ) -> ServiceResponse<EventsResponse> {
+        let index_id = request.index_id()?;
+        let from = time_param(&request, "from")?;
+        let to = time_param(&request, "to")?;
+
+        let limit = request
+            .query_param("limit")
+            .map(|limit| match limit.parse::<usize>() {
+                Ok(limit) if (1..=MAX_LIMIT).contains(&limit) => Ok(limit),
+                _ => Err(ServiceError::invalid_request(&format!(
+                    "Expected limit between 1 and {}, got [{}]",
+                    MAX_LIMIT, limit
+                ))),
+            })
+            .transpose()?
+            .unwrap_or(DEFAULT_LIMIT);
+
+        let events = self
+            .event_store
+            .list_events(&index_id, from, to, limit)
+            .await?;
+
+        Ok(EventsResponse { events })
+    }
+}
+
+impl EventsIndexService {
+    pub async fn create() -> Self {
+        EventsIndexService {
+            event_store: Box::new(DDBEventStore::create(None).await),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::event::IndexEvent;
+    use crate::test_utils::*;
+
+    #[tokio::test]
+    async fn events_list_commits_in_range() {
+        let ctx = setup()
+            .with_documents("test", vec![json!({ "title": "Hello" })])
+            .await;
+        let ctx = ctx
+            .with_documents(
+                "test",
+                vec![json!({ "title": "World" }), json!({ "title": "Again" })],
+            )
+            .await;
+
+        let service = EventsIndexService {
+            event_store: Box::new(ctx.event_store().clone()),
+        };
+        let request = || ServiceRequest::create(json!({})).with_path_param("index_id", "test");
+
+        let response = service.handle_request(request()).await.unwrap();
+        let indexed = response
+            .events
+            .iter()
+            .map(|entry| match &entry.event {
+                IndexEvent::Commit { docs_indexed, .. } => *docs_indexed,
+                event => panic!("Expected a commit, got {:?}", event),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(vec![1, 2], indexed);
+
+        let response = service
+            .handle_request(request().with_query_param("to", "2020-01-01T00:00:00Z"))
+            .await
+            .unwrap();
+        assert!(response.events.is_empty());
+
+        let err = service
+            .handle_request(request().with_query_param("from", "last tuesday"))
+            .await
+            .unwrap_err();
+        assert_eq!(400, err.status());
+    }
+}
@@ -0,0 +1,142 @@
+use async_trait::async_trait;
+use serde::Serialize;
+
+use crate::index::{IndexExt, IndexLoader, LambdaIndexLoader};
+use crate::schema::{SchemaLoader, SchemaProvider};
+use crate::service::{ServiceHandler, ServiceRequest, ServiceResponse};
+use crate::{json, tenant};
+
+#[derive(Serialize, Debug)]
+pub struct IndexSummary {
+    pub index_id: String,
+    /// Configured prefix the index was created from, `None` if it no longer matches a prefix.
+    pub prefix: Option<String>,
+    pub num_docs: u64,
+    pub last_commit: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ListIndexesResponse {
+    pub indexes: Vec<IndexSummary>,
+}
+
+pub struct ListIndexesService {
+    schema_loader: Box<dyn SchemaLoader>,
+
+    index_loader: Box<dyn IndexLoader>,
+}
+
+#[async_trait]
+impl ServiceHandler<json::Value, ListIndexesResponse> for ListIndexesService {
+    async fn handle_request(
+        &self,
+        request: ServiceRequest<json::Value>,
+    ) -> ServiceResponse<ListIndexesResponse> {
+        let tenant_id = request.tenant()?;
+        let mut indexes = vec![];
+
+        for index_id in self.index_loader.list_indexes()? {
+            let Some(visible_id) = tenant::visible_id(tenant_id.as_deref(), &index_id) else {
+                continue;
+            };
+            let prefix = self.schema_loader.index_prefix(&index_id);
+
+            let (num_docs, last_commit) = match prefix {
+                Some(_) => {
+                    let index = self.index_loader.load_index(&index_id, None)?;
+                    let num_docs = index
+                        .reader()
+                        .expect("Reader should load")
+                        .searcher()
+                        .num_docs();
+
+                    (num_docs, index.last_commit().map(|meta| meta.committed_at))
+                }
+                // Without a schema the index cannot be opened.
+                None => (0, None),
+            };
+
+            indexes.push(IndexSummary {
+                index_id: visible_id.to_string(),
+                prefix: prefix.map(|prefix| tenant::unscope(&prefix).to_string()),
+                num_docs,
+                last_commit,
+            });
+        }
+
+        Ok(ListIndexesResponse { indexes })
+    }
+}
+
+impl ListIndexesService {
+    pub async fn create() -> Self {
+        let index_loader = LambdaIndexLoader::create().await;
+        let schema_loader = SchemaProvider::lambda().await;
+
+        ListIndexesService {
+            schema_loader: Box::new(schema_loader),
+            index_loader: Box::new(index_loader),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[tokio::test]
+    async fn list_indexes_with_stats() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![json!({ "title": "hello" }), json!({ "title": "world" })],
+            )
+            .await;
+
+        let service = ListIndexesService {
+            schema_loader: Box::new(ctx.schema_loader().clone()),
+            index_loader: Box::new(ctx.index_loader().clone()),
+        };
+
+        let response = service
+            .handle_request(ServiceRequest::create(json!({})))
+            .await
+            .unwrap();
+
+        assert_eq!(1, response.indexes.len());
+
+        let summary = &response.indexes[0];
+        assert_eq!("test", summary.index_id);
+        assert_eq!(Some("test"), summary.prefix.as_deref());
+        assert_eq!(2, summary.num_docs);
+        assert!(summary.last_commit.is_some());
+    }
+
+    #[tokio::test]
+    async fn list_indexes_of_tenant() {
+        let ctx = setup()
+            .with_documents("test", vec![json!({ "title": "hello" })])
+            .await
+            .with_documents("acme~test", vec![json!({ "title": "world" })])
+            .await;
+
+        let service = ListIndexesService {
+            schema_loader: Box::new(ctx.schema_loader().clone()),
+            index_loader: Box::new(ctx.index_loader().clone()),
+        };
+
+        let request = ServiceRequest::create(json!({})).with_tenant("acme");
+        let response = service.handle_request(request).await.unwrap();
+
+        assert_eq!(1, response.indexes.len());
+        let summary = &response.indexes[0];
+        assert_eq!("test", summary.index_id);
+        assert_eq!(Some("test"), summary.prefix.as_deref());
+        assert_eq!(1, summary.num_docs);
+
+        let request = ServiceRequest::create(json!({})).with_tenant("other");
+        let response = service.handle_request(request).await.unwrap();
+        assert!(response.indexes.is_empty());
+    }
+}
@@ -0,0 +1,70 @@
+use async_trait::async_trait;
+
+use crate::index::{IndexLoader, LambdaIndexLoader};
+use crate::json;
+use crate::manifest::IndexManifest;
+use crate::service::{ServiceHandler, ServiceRequest, ServiceResponse};
+
+/// Lists the files of an index's last commit with their sizes and checksums, for backup,
+/// replication and audit tooling that can't read the index's directory.
+pub struct ManifestIndexService {
+    index_loader: Box<dyn IndexLoader>,
+}
+
+#[async_trait]
+impl ServiceHandler<json::Value, IndexManifest> for ManifestIndexService {
+    async fn handle_request(
+        &self,
+        request: ServiceRequest<json::Value>,
+    ) -> ServiceResponse<IndexManifest> {
+        let index_id = request.index_id()?;
+
+        self.index_loader.manifest(&index_id)
+    }
+}
+
+impl ManifestIndexService {
+    pub async fn create() -> Self {
+        let index_loader = LambdaIndexLoader::create();
+
+        ManifestIndexService {
+            index_loader: Box::new(index_loader.await),
+        }
+    }
+
+    pub fn new(index_loader: Box<dyn IndexLoader>) -> Self {
+        ManifestIndexService { index_loader }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::MANIFEST_VERSION;
+    use crate::test_utils::*;
+
+    #[tokio::test]
+    async fn manifest_lists_segment_files() {
+        let ctx = setup()
+            .with_documents("test", vec![json!({ "title": "hello" })])
+            .await;
+
+        let service = ManifestIndexService::new(Box::new(ctx.index_loader().clone()));
+        let request = || ServiceRequest::create(json!({})).with_path_param("index_id", "test");
+
+        let manifest = service.handle_request(request()).await.unwrap();
+        assert_eq!(MANIFEST_VERSION, manifest.manifest_version);
+        assert_eq!("test", manifest.index_id);
+        assert!(manifest.committed_at.is_some());
+        assert_eq!(1, manifest.segments.len());
+
+        let segment = &manifest.segments[0];
+        assert_eq!(1, segment.max_doc);
+        assert_eq!(None, segment.generation);
+        assert!(!segment.files.is_empty());
+        assert!(segment.files.iter().all(|file| file.size_bytes > 0));
+
+        // The same commit always has the same manifest.
+        assert_eq!(manifest, service.handle_request(request()).await.unwrap());
+    }
+}
@@ -1,9 +1,50 @@
 mod batch_index;
+mod bulk_index;
+mod create_index;
+mod delete_by_query;
+mod delete_index;
+mod deprecations_index;
+mod erase;
+mod erase_report;
+mod es_bulk_index;
+mod es_search_index;
+mod estimate_query;
+mod events_index;
+mod list_indexes;
+mod manifest_index;
 mod post_index;
 mod query_index;
+mod restore_index;
+mod snapshot_index;
 mod stats_index;
+mod suggest_index;
+mod sync_index;
+mod update_by_query;
+mod validate_doc;
 
 pub use batch_index::BatchIndexService;
+pub use bulk_index::BulkIndexService;
+pub(crate) use bulk_index::{MAX_DOCS_PER_JOB, MAX_DOCS_PER_SAVE};
+pub use create_index::CreateIndexService;
+pub use delete_by_query::{delete_matching, DeleteByQueryResponse, DeleteByQueryService};
+pub use delete_index::DeleteIndexService;
+pub use deprecations_index::{DeprecationsIndexService, DeprecationsResponse};
+pub use erase::{EraseReport, EraseRequest, EraseService, IndexErasure};
+pub use erase_report::EraseReportService;
+pub use es_bulk_index::{
+    BulkItem, BulkItemError, BulkItemResult, EsBulkIndexService, EsBulkResponse,
+};
+pub use es_search_index::{EsHit, EsHits, EsSearchIndexService, EsSearchRequest, EsSearchResponse};
+pub use estimate_query::{CostClass, EstimateQueryService, QueryEstimate};
+pub use events_index::{EventsIndexService, EventsResponse};
+pub use list_indexes::ListIndexesService;
+pub use manifest_index::ManifestIndexService;
 pub use post_index::PostIndexService;
-pub use query_index::QueryIndexService;
+pub use query_index::{QueryIndexService, QueryRequest, QueryResponse, SearchHit};
+pub use restore_index::{RestoreIndexService, RestoreRequest, RestoreResponse};
+pub use snapshot_index::{SnapshotIndexService, SnapshotManifest, SnapshotResponse};
 pub use stats_index::StatsIndexService;
+pub use suggest_index::{SuggestIndexService, SuggestResponse};
+pub use sync_index::{SyncChange, SyncIndexService, SyncResponse};
+pub use update_by_query::{UpdateByQueryRequest, UpdateByQueryResponse, UpdateByQueryService};
+pub use validate_doc::ValidateDocService;
@@ -1,3 +1,5 @@
+use std::time::{Duration, Instant};
+
 use async_trait::async_trait;
 use serde::Serialize;
 
@@ -5,9 +7,17 @@ use crate::schema::{SchemaLoader, SchemaProvider};
 use crate::search_doc::SearchDoc;
 use crate::service::{ServiceError, ServiceHandler, ServiceRequest, ServiceResponse};
 use crate::store::document::{DDBDocumentStore, DocumentStore};
+use crate::store::job::{DDBJobStore, JobState, JobStore};
 use crate::worker::index_writer::client::{IndexWriterClient, LambdaIndexWriterClient};
 use crate::worker::index_writer::job::Job;
-use crate::{json, util};
+use crate::{json, time_partition, util};
+
+/// Longest a `refresh=wait_for` request waits for its job, leaving room under API Gateway's 29
+/// second integration timeout.
+const MAX_REFRESH_WAIT: Duration = Duration::from_secs(20);
+
+/// How often a `refresh=wait_for` request checks its job.
+const REFRESH_POLL_INTERVAL: Duration = Duration::from_millis(250);
 
 #[derive(Serialize, Debug)]
 pub struct PostIndexResponse {
@@ -21,6 +31,38 @@ pub struct PostIndexService {
     document_store: Box<dyn DocumentStore>,
 
     writer_client: Box<dyn IndexWriterClient>,
+
+    job_store: Box<dyn JobStore>,
+}
+
+impl PostIndexService {
+    /// Waits until the index writer has committed `job_id`, so the document is searchable once
+    /// the response is sent, or has given up on it.
+    async fn wait_for_job(&self, job_id: &str) -> ServiceResponse<()> {
+        let started = Instant::now();
+        loop {
+            match self.job_store.get_job(job_id).await? {
+                Some(status) if status.status == JobState::Complete => return Ok(()),
+                Some(status) if status.status == JobState::Failed => {
+                    return Err(ServiceError::invalid_request(&format!(
+                        "Job [{}] failed: {}",
+                        job_id,
+                        status.error.unwrap_or_default()
+                    )));
+                }
+                _ => {}
+            }
+            if started.elapsed() >= MAX_REFRESH_WAIT {
+                return Err(ServiceError::unavailable(&format!(
+                    "Job [{}] wasn't committed within {} seconds, its status is at /job/{}",
+                    job_id,
+                    MAX_REFRESH_WAIT.as_secs(),
+                    job_id
+                )));
+            }
+            tokio::time::sleep(REFRESH_POLL_INTERVAL).await;
+        }
+    }
 }
 
 #[async_trait]
@@ -31,16 +73,36 @@ impl ServiceHandler<json::Value, PostIndexResponse> for PostIndexService {
     ) -> ServiceResponse<PostIndexResponse> {
         let body = request.body()?;
 
-        let index_id = request.path_param("index_id")?;
+        let index_id = request.index_id()?;
+        let token = request.write_token()?;
+        let wait_for = match request.query_param("refresh").as_deref() {
+            None | Some("false") => false,
+            Some("wait_for") => true,
+            Some(refresh) => {
+                return Err(ServiceError::invalid_request(&format!(
+                    "Unknown refresh [{}], expected wait_for or false",
+                    refresh
+                )))
+            }
+        };
 
         let schema = self.schema_loader.load_schema(&index_id)?;
+        let settings = self.schema_loader.load_settings(&index_id)?;
 
-        let document = SearchDoc::from_json(&schema, body)
+        let document = SearchDoc::from_json_with_settings(&schema, body, &settings)
             .map_err(|err| ServiceError::invalid_request(&err.to_string()))?;
 
+        let target = time_partition::target_index(
+            self.schema_loader.as_ref(),
+            &index_id,
+            &settings,
+            &schema,
+            &document,
+        );
+
         let doc_refs = self.document_store.save_documents(vec![document]).await?;
 
-        let mut job = Job::create(&index_id);
+        let mut job = Job::create(&target).with_token(token);
 
         for doc_ref in doc_refs {
             job.index_doc(doc_ref);
@@ -48,6 +110,10 @@ impl ServiceHandler<json::Value, PostIndexResponse> for PostIndexService {
 
         let job_id = self.writer_client.submit_job(job).await?;
 
+        if wait_for {
+            self.wait_for_job(&job_id).await?;
+        }
+
         Ok(PostIndexResponse {
             job_id,
             updated_at: util::timestamp(),
@@ -59,12 +125,28 @@ impl PostIndexService {
     pub async fn create() -> Self {
         let document_store = DDBDocumentStore::create(None).await;
         let writer_client = LambdaIndexWriterClient::create(None).await;
-        let schema_loader = SchemaProvider::lambda();
+        let schema_loader = SchemaProvider::lambda().await;
+        let job_store = DDBJobStore::create(None).await;
 
         PostIndexService {
             document_store: Box::new(document_store),
             writer_client: Box::new(writer_client),
             schema_loader: Box::new(schema_loader),
+            job_store: Box::new(job_store),
+        }
+    }
+
+    pub fn new(
+        schema_loader: Box<dyn SchemaLoader>,
+        document_store: Box<dyn DocumentStore>,
+        writer_client: Box<dyn IndexWriterClient>,
+        job_store: Box<dyn JobStore>,
+    ) -> Self {
+        PostIndexService {
+            schema_loader,
+            document_store,
+            writer_client,
+            job_store,
         }
     }
 }
@@ -72,19 +154,24 @@ impl PostIndexService {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::index::IndexLoader;
     use crate::test_utils::*;
 
     pub fn test_service() -> PostIndexService {
-        let ctx = setup();
+        test_service_with(&setup())
+    }
 
+    fn test_service_with(ctx: &TestContext) -> PostIndexService {
         let schema_loader = Box::new(ctx.schema_loader().clone());
         let document_store = Box::new(ctx.document_store().clone());
         let writer_client = Box::new(ctx.writer_client().clone());
+        let job_store = Box::new(ctx.job_store().clone());
 
         PostIndexService {
             schema_loader,
             document_store,
             writer_client,
+            job_store,
         }
     }
 
@@ -104,6 +191,44 @@ mod tests {
         service.handle_request(request).await.unwrap();
     }
 
+    #[tokio::test]
+    async fn post_index_wait_for_refresh() {
+        let ctx = setup();
+        let service = test_service_with(&ctx);
+
+        let doc = json::json!({ "__id": "zen", "title": "Zen" });
+        let request = ServiceRequest::create(doc)
+            .with_path_param("index_id", "test")
+            .with_query_param("refresh", "wait_for");
+        let response = service.handle_request(request).await.unwrap();
+
+        let status = ctx.job_store().get_job(&response.job_id).await.unwrap();
+        assert_eq!(JobState::Complete, status.unwrap().status);
+
+        let request = ServiceRequest::create(json::json!({ "title": "Zen" }))
+            .with_path_param("index_id", "test")
+            .with_query_param("refresh", "now");
+        let err = service.handle_request(request).await.unwrap_err();
+        assert_eq!(400, err.status());
+    }
+
+    #[tokio::test]
+    async fn post_index_routes_to_time_partition() {
+        let ctx = setup();
+        let service = test_service_with(&ctx);
+
+        let doc = json::json!({ "title": "Zen", "timestamp": "2022-11-23T18:24:40Z" });
+        let request = ServiceRequest::create(doc)
+            .with_path_param("index_id", "rolling")
+            .with_query_param("refresh", "wait_for");
+        service.handle_request(request).await.unwrap();
+
+        assert_eq!(
+            vec!["rolling-2022-11-23"],
+            ctx.index_loader().list_indexes().unwrap()
+        );
+    }
+
     #[tokio::test]
     async fn post_index_non_object() {
         let service = test_service();
@@ -1,30 +1,720 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use tantivy::collector::TopDocs;
-use tantivy::query::QueryParser;
-use tantivy::schema::{Field, FieldType};
-use tantivy::{DocAddress, Score, SnippetGenerator, TantivyError};
+use tantivy::fastfield::{FastFieldReader, FastValue};
+use tantivy::query::Query as TantivyQuery;
+use tantivy::schema::{Field, Schema, Type};
+use tantivy::{
+    DocAddress, DocId, DocSet, Score, Searcher, SegmentReader, Snippet,
+    SnippetGenerator, TantivyError, TERMINATED,
+};
 use tracing::info;
 
+use crate::aggregation::{self, Aggregation, AggregationResult};
+use crate::analytics::{Analytics, QueryRecord};
+use crate::cursor::{self, Cursor, CursorKey, MAX_CURSOR_OFFSET};
 use crate::index::{IndexLoader, LambdaIndexLoader};
-use crate::json;
+use crate::lambda::http::FieldReferences;
+use crate::metrics::MetricSet;
+use crate::query::signals::BoostOptions;
+use crate::query::{self, Deadline, GlobalStatsQuery, Query, TerminateAfterQuery, TimeBudgetQuery};
+use crate::reader_cache::ReaderCache;
+use crate::schema::{IndexSettings, SchemaLoader, SchemaProvider, TimePartitionConfig, ID_KEY_FIELD};
 use crate::service::{ServiceError, ServiceHandler, ServiceRequest, ServiceResponse};
 use crate::store::document::{DDBDocumentStore, DocumentStore, SearchDocRef};
+use crate::{json, time_partition, tokenizer};
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct WithPartition {
     partition_n: usize,
 
     total_partitions: usize,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Default number of matches returned.
+const DEFAULT_LIMIT: usize = 10;
+
+/// Most matches a single request can return, as every match up to the limit is ranked in
+/// memory.
+const MAX_LIMIT: usize = 10_000;
+
+/// A generator for snippets of `field` highlighting the terms of `query`, or None when the field
+/// isn't indexed.
+fn snippet_generator(
+    searcher: &Searcher,
+    query: &dyn TantivyQuery,
+    field: Field,
+    fragment_size: Option<usize>,
+) -> Option<SnippetGenerator> {
+    let mut generator = match SnippetGenerator::create(searcher, query, field) {
+        Ok(generator) => generator,
+        // InvalidArgument is returned when field is not indexed
+        Err(TantivyError::InvalidArgument(_)) => return None,
+        Err(err) => panic!("{}", err),
+    };
+
+    if let Some(fragment_size) = fragment_size {
+        generator.set_max_num_chars(fragment_size);
+    }
+
+    Some(generator)
+}
+
+/// Default number of characters of a field analyzed for snippets.
+const DEFAULT_MAX_ANALYZED_CHARS: usize = 100_000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct HighlightOptions {
+    /// Fields to generate snippets for. Defaults to the index's `snippet_fields`, or every indexed
+    /// text field.
+    pub fields: Option<Vec<String>>,
+
+    /// Maximum number of characters in a single fragment.
+    pub fragment_size: Option<usize>,
+
+    /// Number of fragments to return per field. When greater than one the snippet for a field is
+    /// returned as a list of fragments.
+    pub number_of_fragments: Option<usize>,
+
+    /// Tag inserted before each highlighted term, defaults to `<b>`.
+    pub pre_tag: Option<String>,
+
+    /// Tag inserted after each highlighted term, defaults to `</b>`.
+    pub post_tag: Option<String>,
+
+    /// Fields longer than this many characters are highlighted from a truncated prefix. Defaults
+    /// to 100,000.
+    pub max_analyzed_chars: Option<usize>,
+
+    /// Per-field overrides of `max_analyzed_chars`.
+    #[serde(default)]
+    pub field_max_analyzed_chars: HashMap<String, usize>,
+
+    /// Skip snippets for fields over the limit rather than highlighting their prefix.
+    #[serde(default)]
+    pub skip_oversized: bool,
+}
+
+impl HighlightOptions {
+    /// Whether to generate snippets for `field_name`, out of `default_fields` when the request
+    /// doesn't pick its own.
+    fn includes_field(&self, field_name: &str, default_fields: Option<&Vec<String>>) -> bool {
+        self.fields
+            .as_ref()
+            .or(default_fields)
+            .map(|fields| fields.iter().any(|name| name == field_name))
+            .unwrap_or(true)
+    }
+
+    fn max_analyzed_chars(&self, field_name: &str) -> usize {
+        self.field_max_analyzed_chars
+            .get(field_name)
+            .copied()
+            .or(self.max_analyzed_chars)
+            .unwrap_or(DEFAULT_MAX_ANALYZED_CHARS)
+    }
+
+    fn render(&self, snippet: &Snippet) -> String {
+        match (&self.pre_tag, &self.post_tag) {
+            (None, None) => snippet.to_html(),
+            (pre_tag, post_tag) => {
+                let pre_tag = pre_tag.as_deref().unwrap_or("<b>");
+                let post_tag = post_tag.as_deref().unwrap_or("</b>");
+                let fragment = snippet.fragment();
+
+                let mut rendered = String::new();
+                let mut start_from = 0;
+
+                for range in snippet.highlighted() {
+                    rendered.push_str(&escape_html(&fragment[start_from..range.start]));
+                    rendered.push_str(pre_tag);
+                    rendered.push_str(&escape_html(&fragment[range.clone()]));
+                    rendered.push_str(post_tag);
+                    start_from = range.end;
+                }
+                rendered.push_str(&escape_html(&fragment[start_from..]));
+
+                rendered
+            }
+        }
+    }
+
+    /// Generates up to `number_of_fragments` non-overlapping snippets for `text`, best first.
+    fn snippets(&self, generator: &SnippetGenerator, text: &str) -> Vec<Snippet> {
+        let number_of_fragments = self.number_of_fragments.unwrap_or(1);
+
+        let mut regions = vec![text];
+        let mut snippets = vec![];
+
+        while snippets.len() < number_of_fragments {
+            // Pick the region whose best fragment highlights the most terms.
+            let best = regions
+                .iter()
+                .enumerate()
+                .map(|(idx, region)| (idx, generator.snippet(region)))
+                .filter(|(_, snippet)| !snippet.highlighted().is_empty())
+                .max_by_key(|(_, snippet)| snippet.highlighted().len());
+
+            let (idx, snippet) = match best {
+                Some(best) => best,
+                None => break,
+            };
+
+            let region = regions.remove(idx);
+            if let Some(offset) = region.find(snippet.fragment()) {
+                let end = offset + snippet.fragment().len();
+                regions.push(&region[..offset]);
+                regions.push(&region[end..]);
+            }
+
+            snippets.push(snippet);
+        }
+
+        snippets
+    }
+}
+
+/// Returns the first `max_chars` characters of `text`, or `None` if it is already short enough.
+fn truncate(text: &str, max_chars: usize) -> Option<&str> {
+    text.char_indices()
+        .nth(max_chars)
+        .map(|(offset, _)| &text[..offset])
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SortOptions {
+    /// A `FAST` date or numeric field.
+    pub field: String,
+
+    #[serde(default)]
+    pub order: SortOrder,
+}
+
+/// Reads a fast field value as a `u64` that sorts the same way as the original value.
+fn fast_field_sort_key(
+    segment_reader: &SegmentReader,
+    field: Field,
+    value_type: Type,
+) -> Box<dyn Fn(DocId) -> u64> {
+    fn key<T: FastValue>(reader: impl FastFieldReader<T> + 'static) -> Box<dyn Fn(DocId) -> u64> {
+        Box::new(move |doc| reader.get(doc).to_u64())
+    }
+
+    let fast_fields = segment_reader.fast_fields();
+    let expect = "fast field should be readable";
+
+    match value_type {
+        Type::U64 => key(fast_fields.u64(field).expect(expect)),
+        Type::I64 => key(fast_fields.i64(field).expect(expect)),
+        Type::F64 => key(fast_fields.f64(field).expect(expect)),
+        Type::Date => key(fast_fields.date(field).expect(expect)),
+        _ => unreachable!("sort field type is validated"),
+    }
+}
+
+/// Rank of a match: sort field key, relevance score, tiebreak field key, then `__id_key`. Higher
+/// ranks come first.
+type Rank = (u64, Score, u64, u64);
+
+/// Resolves a field that matches are ordered by, which must be a `FAST` date or numeric field.
+fn order_field(schema: &Schema, field_name: &str) -> Result<(Field, Type), ServiceError> {
+    let field = schema.get_field(field_name).ok_or_else(|| {
+        ServiceError::invalid_request(&format!("Field [{}] does not exist", field_name))
+    })?;
+
+    let entry = schema.get_field_entry(field);
+    let value_type = entry.field_type().value_type();
+
+    if !entry.is_fast() || !matches!(value_type, Type::U64 | Type::I64 | Type::F64 | Type::Date) {
+        return Err(ServiceError::invalid_request(&format!(
+            "Field [{}] must be a FAST date or numeric field to sort by",
+            field_name
+        )));
+    }
+
+    Ok((field, value_type))
+}
+
+/// Computes the [`Rank`] of matches.
+#[derive(Clone, Copy)]
+struct Ranker {
+    sort: Option<(Field, Type, SortOrder)>,
+    tiebreak: Option<(Field, Type)>,
+    /// None for indexes built before `__id_key` existed.
+    id_key: Option<Field>,
+}
+
+impl Ranker {
+    fn create(
+        schema: &Schema,
+        sort: Option<&SortOptions>,
+        tiebreak: Option<&str>,
+    ) -> Result<Ranker, ServiceError> {
+        Ok(Ranker {
+            sort: match sort {
+                Some(sort) => {
+                    let (field, value_type) = order_field(schema, &sort.field)?;
+                    Some((field, value_type, sort.order))
+                }
+                None => None,
+            },
+            tiebreak: tiebreak
+                .map(|field_name| order_field(schema, field_name))
+                .transpose()?,
+            id_key: schema.get_field(ID_KEY_FIELD),
+        })
+    }
+
+    fn for_segment(&self, segment_reader: &SegmentReader) -> impl Fn(DocId, Score) -> Rank {
+        let sort = self.sort.map(|(field, value_type, order)| {
+            (
+                fast_field_sort_key(segment_reader, field, value_type),
+                order,
+            )
+        });
+        let tiebreak = self
+            .tiebreak
+            .map(|(field, value_type)| fast_field_sort_key(segment_reader, field, value_type));
+        let id_key = self
+            .id_key
+            .map(|field| fast_field_sort_key(segment_reader, field, Type::U64));
+
+        move |doc: DocId, score: Score| {
+            let sort_key = match &sort {
+                Some((key, SortOrder::Desc)) => key(doc),
+                Some((key, SortOrder::Asc)) => u64::MAX - key(doc),
+                None => 0,
+            };
+            // The lowest tiebreak value ranks first.
+            let tiebreak_key = tiebreak.as_ref().map_or(0, |key| u64::MAX - key(doc));
+            let id_key = id_key.as_ref().map_or(0, |key| u64::MAX - key(doc));
+
+            (sort_key, score, tiebreak_key, id_key)
+        }
+    }
+}
+
+/// Top `limit` matches by rank. Equally ranked matches are ordered by `__id_key`, a hash of their
+/// `__id`, so that pages don't change when segments merge.
+fn ranked_top_docs(
+    searcher: &Searcher,
+    query: &dyn TantivyQuery,
+    ranker: Ranker,
+    limit: usize,
+) -> Result<Vec<(Score, DocAddress)>, ServiceError> {
+    if limit == 0 {
+        return Ok(vec![]);
+    }
+
+    let collector = TopDocs::with_limit(limit)
+        .tweak_score(move |segment_reader: &SegmentReader| ranker.for_segment(segment_reader));
+
+    let top_docs = searcher
+        .search(query, &collector)
+        .map_err(ServiceError::internal_error)?;
+
+    Ok(top_docs
+        .into_iter()
+        .map(|((_, score, _, _), address)| (score, address))
+        .collect())
+}
+
+/// How precisely to count a query's matches: `true` counts every match, `false` skips counting,
+/// and a number counts up to that many matches, reporting a lower bound beyond it.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum TrackTotalHits {
+    Exact(bool),
+    UpTo(u64),
+}
+
+impl Default for TrackTotalHits {
+    fn default() -> Self {
+        TrackTotalHits::Exact(false)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TotalHitsRelation {
+    /// `value` is the exact number of matches.
+    Eq,
+    /// There are at least `value` matches.
+    Gte,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TotalHits {
+    pub value: u64,
+    pub relation: TotalHitsRelation,
+}
+
+/// Counts the matches of `query` as precisely as `track` asks. Exact counts use each segment's
+/// fastest count, bounded counts stop walking matches once past the bound.
+fn count_hits(
+    searcher: &Searcher,
+    query: &dyn TantivyQuery,
+    track: TrackTotalHits,
+) -> Option<TotalHits> {
+    let limit = match track {
+        TrackTotalHits::Exact(false) => return None,
+        TrackTotalHits::Exact(true) => None,
+        TrackTotalHits::UpTo(limit) => Some(limit),
+    };
+
+    let weight = query.weight(searcher, false).expect("weight should build");
+
+    let mut value = 0;
+    for segment_reader in searcher.segment_readers() {
+        match limit {
+            None => value += weight.count(segment_reader).expect("count should succeed") as u64,
+            Some(limit) => {
+                let alive_bitset = segment_reader.alive_bitset();
+                let mut scorer = weight
+                    .scorer(segment_reader, 1.0)
+                    .expect("scorer should build");
+
+                let mut doc = scorer.doc();
+                while doc != TERMINATED && value <= limit {
+                    if alive_bitset.is_none_or(|alive| alive.is_alive(doc)) {
+                        value += 1;
+                    }
+                    doc = scorer.advance();
+                }
+            }
+        }
+    }
+
+    Some(match limit {
+        Some(limit) if value > limit => TotalHits {
+            value: limit,
+            relation: TotalHitsRelation::Gte,
+        },
+        _ => TotalHits {
+            value,
+            relation: TotalHitsRelation::Eq,
+        },
+    })
+}
+
+/// Which page of a query's matches a request is for.
+struct Page {
+    fingerprint: String,
+    offset: usize,
+    limit: usize,
+}
+
+impl Page {
+    fn create(body: &QueryRequest, limit: usize, key: &CursorKey) -> Result<Page, ServiceError> {
+        let fingerprint = body.fingerprint();
+
+        let offset = match &body.cursor {
+            Some(token) => {
+                let cursor = Cursor::decode(token, key)?;
+                if cursor.query != fingerprint {
+                    return Err(ServiceError::invalid_request(&format!(
+                        "Cursor [{}] is for a different query",
+                        token
+                    )));
+                }
+                cursor.offset
+            }
+            None => 0,
+        };
+        if offset.checked_add(limit).and_then(|end| end.checked_add(1)).is_none() {
+            return Err(ServiceError::invalid_request(&format!(
+                "Cursor [{}] pages past the last match",
+                body.cursor.as_deref().unwrap_or_default()
+            )));
+        }
+
+        Ok(Page {
+            fingerprint,
+            offset,
+            limit,
+        })
+    }
+
+    /// Number of top matches to rank: those before the page, the page and one more to tell
+    /// whether there's a next page. [`Page::create`] checked that it fits.
+    fn ranked(&self) -> usize {
+        match self.limit {
+            0 => 0,
+            limit => self.offset + limit + 1,
+        }
+    }
+
+    /// Narrows the ranked `top_docs` to the page, returning cursors for the pages either side.
+    fn cursors<T>(
+        &self,
+        top_docs: &mut Vec<T>,
+        key: &CursorKey,
+    ) -> (Option<String>, Option<String>) {
+        let end = self.offset + self.limit;
+        let has_next = top_docs.len() > end && end < MAX_CURSOR_OFFSET;
+        top_docs.truncate(end);
+        top_docs.drain(..self.offset.min(top_docs.len()));
+
+        let cursor = |offset: usize| {
+            Cursor {
+                offset,
+                query: self.fingerprint.clone(),
+            }
+            .encode(key)
+        };
+
+        let next = has_next.then(|| cursor(end));
+        let prev = (self.offset > 0).then(|| cursor(self.offset.saturating_sub(self.limit)));
+
+        (next, prev)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct QueryRequest {
-    pub query: String,
+    #[serde(deserialize_with = "query::string_or_dsl")]
+    pub query: Query,
 
     pub with_partition: Option<WithPartition>,
+
+    #[serde(default)]
+    pub highlight: HighlightOptions,
+
+    /// Orders matches by a fast field instead of relevance.
+    pub sort: Option<SortOptions>,
+
+    /// Multiplies relevance scores by one of the index's `boost_signals`.
+    pub boost: Option<BoostOptions>,
+
+    /// A `FAST` date or numeric field ordering equally ranked matches, lowest value first. Ties
+    /// it leaves are ordered by a hash of `__id`.
+    pub tiebreak: Option<String>,
+
+    /// Named aggregations over every matching document, not only the returned matches.
+    #[serde(default)]
+    pub aggs: HashMap<String, Aggregation>,
+
+    /// Filters the returned matches after aggregations are computed, so aggregations still
+    /// reflect the whole query.
+    pub post_filter: Option<Query>,
+
+    /// Selected values per field for multi-select faceting. Values for the same field OR
+    /// together and fields AND together. Each aggregation sees every selection except those on
+    /// its own field, so a facet still counts the alternatives to its selected values.
+    #[serde(default)]
+    pub facet_filters: BTreeMap<String, Vec<json::Value>>,
+
+    /// Score a partition's matches with statistics from every segment of the index, so scores
+    /// from different partitions can be merged. Has no effect without `with_partition`.
+    #[serde(default)]
+    pub global_idf: bool,
+
+    /// Whether to count every match of the query and filters, only up to a number, or not at
+    /// all. Defaults to `false`.
+    #[serde(default)]
+    pub track_total_hits: TrackTotalHits,
+
+    /// Stop examining each segment after this many candidate matches, keeping the earliest
+    /// indexed rather than the best. Overrides the index's `terminate_after` setting.
+    pub terminate_after: Option<u32>,
+
+    /// Milliseconds to spend collecting matches before returning those found so far, flagged
+    /// `timed_out`. Overrides the index's `query_limits.timeout_ms`.
+    pub timeout_ms: Option<u64>,
+
+    /// Number of matches to return. Defaults to 10.
+    pub limit: Option<usize>,
+
+    /// A `next` or `prev` cursor from an earlier response to the same query, for the page it
+    /// points to.
+    pub cursor: Option<String>,
+
+    /// Fields to return in each match's `doc`. Defaults to every field.
+    pub fields: Option<Vec<String>>,
+
+    /// Return how the query was executed as `meta`.
+    #[serde(default)]
+    pub verbose: bool,
+}
+
+impl QueryRequest {
+    /// A query given by the `q`, `limit` and `fields` query string parameters, for GET requests.
+    /// `fields` is a comma separated list.
+    fn from_query_params<B>(request: &ServiceRequest<B>) -> Result<Option<Self>, ServiceError>
+    where B: for<'de> Deserialize<'de> {
+        let query = match request.query_param("q") {
+            Some(query) => query,
+            None => return Ok(None),
+        };
+
+        let limit = request
+            .query_param("limit")
+            .map(|limit| {
+                limit.parse::<usize>().map_err(|_| {
+                    ServiceError::invalid_request(&format!(
+                        "Expected a non-negative integer for limit, got [{}]",
+                        limit
+                    ))
+                })
+            })
+            .transpose()?;
+
+        let fields = request.query_param("fields").map(|fields| {
+            fields
+                .split(',')
+                .map(str::trim)
+                .filter(|field| !field.is_empty())
+                .map(String::from)
+                .collect()
+        });
+
+        Ok(Some(QueryRequest {
+            query: query.as_str().into(),
+            limit,
+            fields,
+            cursor: request.query_param("cursor"),
+            ..Default::default()
+        }))
+    }
+
+    /// Number of matches to return, rejected over [`MAX_LIMIT`].
+    fn limit(&self) -> Result<usize, ServiceError> {
+        match self.limit.unwrap_or(DEFAULT_LIMIT) {
+            limit if limit > MAX_LIMIT => Err(ServiceError::invalid_request(&format!(
+                "limit must be less than or equal to [{}], got [{}]",
+                MAX_LIMIT, limit
+            ))),
+            limit => Ok(limit),
+        }
+    }
+
+    /// Identifies the matches of the request and their order, which a cursor pages through.
+    fn fingerprint(&self) -> String {
+        cursor::fingerprint(&(
+            &self.query,
+            &self.with_partition,
+            &self.sort,
+            &self.boost,
+            &self.tiebreak,
+            &self.post_filter,
+            &self.facet_filters,
+        ))
+    }
+
+    /// `doc` restricted to the requested fields.
+    fn select_fields(&self, doc: json::Value) -> json::Value {
+        match (&self.fields, doc) {
+            (Some(fields), json::Value::Object(doc)) => json::Value::Object(
+                doc.into_iter()
+                    .filter(|(name, _)| fields.contains(name))
+                    .collect(),
+            ),
+            (_, doc) => doc,
+        }
+    }
+
+    /// The request with the field aliases of its sort, tiebreak, facet filters and aggregations
+    /// replaced by the fields they stand for. Queries resolve their own aliases when compiled.
+    pub(crate) fn resolve_aliases(mut self, schema: &Schema, settings: &IndexSettings) -> Self {
+        if settings.field_aliases.is_empty() {
+            return self;
+        }
+        let resolve = |field: &str| settings.resolve_field(schema, field).into_owned();
+
+        if let Some(sort) = &mut self.sort {
+            sort.field = resolve(&sort.field);
+        }
+        self.tiebreak = self.tiebreak.map(|field| resolve(&field));
+
+        let mut facet_filters: BTreeMap<String, Vec<json::Value>> = BTreeMap::new();
+        for (field, values) in self.facet_filters {
+            facet_filters
+                .entry(resolve(&field))
+                .or_default()
+                .extend(values);
+        }
+        self.facet_filters = facet_filters;
+
+        for agg in self.aggs.values_mut() {
+            let field = resolve(agg.field());
+            agg.set_field(field);
+        }
+
+        self
+    }
+
+    /// Names of the fields the request queries, filters, sorts, aggregates or returns, with
+    /// field aliases resolved.
+    pub(crate) fn fields_used(&self, schema: &Schema, settings: &IndexSettings) -> Vec<String> {
+        let request = self.clone().resolve_aliases(schema, settings);
+
+        let mut fields: Vec<String> = [Some(&request.query), request.post_filter.as_ref()]
+            .into_iter()
+            .flatten()
+            .flat_map(|query| query.resolve_aliases(schema, settings).fields())
+            .collect();
+        fields.extend(request.sort.map(|sort| sort.field));
+        fields.extend(request.tiebreak);
+        fields.extend(request.facet_filters.into_keys());
+        fields.extend(request.aggs.values().map(|agg| String::from(agg.field())));
+        fields.extend(request.fields.into_iter().flatten());
+        fields
+    }
+
+    /// One filter per field with selected values, skipping `exclude_field`.
+    pub(crate) fn facet_selections(&self, exclude_field: Option<&str>) -> Vec<Query> {
+        self.facet_filters
+            .iter()
+            .filter(|(field, values)| !values.is_empty() && Some(field.as_str()) != exclude_field)
+            .map(|(field, values)| query::any_of(field, values.clone()))
+            .collect()
+    }
+}
+
+impl FieldReferences for QueryRequest {
+    fn field_references(
+        request: &ServiceRequest<Self>,
+        schema: &Schema,
+        settings: &IndexSettings,
+    ) -> Vec<String> {
+        let body = match QueryRequest::from_query_params(request) {
+            Ok(Some(body)) => body,
+            Ok(None) => match request.body() {
+                Ok(body) => body,
+                Err(_) => return vec![],
+            },
+            Err(_) => return vec![],
+        };
+
+        body.fields_used(schema, settings)
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
@@ -32,17 +722,71 @@ pub struct SearchHit {
     pub doc: json::Value,
     pub snippets: json::Value,
     pub score: f32,
+
+    /// Fields whose snippets were generated from a truncated prefix or skipped for being over
+    /// the highlight size limit.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub truncated_fields: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct QueryResponse {
     pub matches: Vec<SearchHit>,
+
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub aggregations: HashMap<String, AggregationResult>,
+
+    /// Number of matches, present when `track_total_hits` isn't `false`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total: Option<TotalHits>,
+
+    /// Cursor for the page after this one, present while there are more matches.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next: Option<String>,
+
+    /// Cursor for the page before this one, present on every page but the first.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prev: Option<String>,
+
+    /// How the query was executed, present for `verbose` queries.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<QueryMeta>,
+
+    /// Whether the query ran out of time, so that the matches, total and aggregations only
+    /// cover the documents examined before it did.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub timed_out: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct QueryMeta {
+    /// Time spent running the query, from loading the index to hydrating the matches.
+    pub took_ms: u64,
+
+    /// Segments searched, only those of the partition for partitioned queries.
+    pub segments: usize,
+
+    /// Opstamp of the commit searched.
+    pub opstamp: u64,
+
+    /// Whether the index reader was reused from an earlier query rather than opened.
+    pub reader_cached: bool,
 }
 
 pub struct QueryIndexService {
+    schema_loader: Box<dyn SchemaLoader>,
+
     index_loader: Box<dyn IndexLoader>,
 
     document_store: Box<dyn DocumentStore>,
+
+    cursor_key: CursorKey,
+
+    reader_cache: ReaderCache,
+
+    /// Where queries to indexes with `settings.analytics` are recorded, `None` to not record
+    /// them.
+    analytics: Option<Analytics>,
 }
 
 #[async_trait]
@@ -51,240 +795,1733 @@ impl ServiceHandler<QueryRequest, QueryResponse> for QueryIndexService {
         &self,
         request: ServiceRequest<QueryRequest>,
     ) -> ServiceResponse<QueryResponse> {
-        let body = request.body()?;
+        let body = match QueryRequest::from_query_params(&request)? {
+            Some(body) => body,
+            None => request.body()?,
+        };
+
+        let index_id = request.index_id()?;
+
+        let analytics = match &self.analytics {
+            Some(analytics) if self.schema_loader.load_settings(&index_id)?.analytics => analytics,
+            _ => return self.query(&index_id, body).await,
+        };
+
+        let started = Instant::now();
+        let response = self.query(&index_id, body.clone()).await?;
+        let record = QueryRecord::create(
+            &index_id,
+            &request.client(),
+            &body,
+            &response,
+            started.elapsed(),
+        );
+        analytics.record(record).await;
+
+        Ok(response)
+    }
+}
+
+impl QueryIndexService {
+    pub async fn create() -> QueryIndexService {
+        let document_store = DDBDocumentStore::create(None).await;
+        let index_loader = LambdaIndexLoader::create();
+        let schema_loader = SchemaProvider::lambda().await;
+
+        QueryIndexService {
+            schema_loader: Box::new(schema_loader),
+            document_store: Box::new(document_store),
+            index_loader: Box::new(index_loader.await),
+            cursor_key: CursorKey::from_env().await,
+            reader_cache: ReaderCache::default(),
+            analytics: Some(Analytics::create().await),
+        }
+    }
+
+    pub fn new(
+        schema_loader: Box<dyn SchemaLoader>,
+        index_loader: Box<dyn IndexLoader>,
+        document_store: Box<dyn DocumentStore>,
+        cursor_key: CursorKey,
+    ) -> Self {
+        QueryIndexService {
+            schema_loader,
+            index_loader,
+            document_store,
+            cursor_key,
+            reader_cache: ReaderCache::default(),
+            analytics: None,
+        }
+    }
+
+    /// Runs a query against an index, hydrating matches from the document store. Queries to the
+    /// alias of a rolling index run against each of its retained partitions. Emits the query's
+    /// latency and matches as metrics of the index.
+    pub async fn query(
+        &self,
+        index_id: &str,
+        body: QueryRequest,
+    ) -> ServiceResponse<QueryResponse> {
+        let started = Instant::now();
+        let settings = self.schema_loader.load_settings(index_id)?;
+
+        let response =
+            match time_partition::alias_config(self.schema_loader.as_ref(), index_id, &settings) {
+                Some(config) => self.query_partitions(index_id, config, body).await?,
+                None => self.query_index(index_id, &settings, body).await?,
+            };
+
+        MetricSet::index(index_id)
+            .duration("QueryLatency", started.elapsed())
+            .count("QueryHits", response.matches.len())
+            .count("QueryTimedOut", response.timed_out as usize)
+            .emit();
+
+        Ok(response)
+    }
+
+    /// Runs a query against each retained partition of `alias`, merging their matches by score,
+    /// or in partition order when sorted by the partition field. Only options that merge that
+    /// way are supported.
+    async fn query_partitions(
+        &self,
+        alias: &str,
+        config: &TimePartitionConfig,
+        body: QueryRequest,
+    ) -> ServiceResponse<QueryResponse> {
+        let started = Instant::now();
+
+        if body.cursor.is_some()
+            || body.with_partition.is_some()
+            || !body.aggs.is_empty()
+            || !body.facet_filters.is_empty()
+        {
+            return Err(ServiceError::invalid_request(&format!(
+                "Queries across the partitions of [{}] don't support cursor, with_partition, aggs \
+                 or facet_filters",
+                alias
+            )));
+        }
+        if let Some(sort) = &body.sort {
+            if config.field.as_ref() != Some(&sort.field) {
+                return Err(ServiceError::invalid_request(&format!(
+                    "Queries across the partitions of [{}] can only sort by the partition field",
+                    alias
+                )));
+            }
+        }
+
+        let mut partitions =
+            time_partition::retained_partitions(self.index_loader.as_ref(), alias, config)?;
+        if !body
+            .sort
+            .as_ref()
+            .is_some_and(|sort| sort.order == SortOrder::Asc)
+        {
+            partitions.reverse();
+        }
+
+        let limit = body.limit()?;
+        let mut matches = vec![];
+        let mut total: Option<TotalHits> = None;
+        let mut meta: Option<QueryMeta> = None;
+        let mut timed_out = false;
+
+        for (_, partition) in partitions {
+            let settings = self.schema_loader.load_settings(&partition)?;
+            let response = self
+                .query_index(&partition, &settings, body.clone())
+                .await?;
+
+            matches.extend(response.matches);
+            timed_out |= response.timed_out;
+            if let Some(hits) = response.total {
+                let merged = total.get_or_insert(TotalHits {
+                    value: 0,
+                    relation: TotalHitsRelation::Eq,
+                });
+                merged.value += hits.value;
+                if hits.relation == TotalHitsRelation::Gte {
+                    merged.relation = TotalHitsRelation::Gte;
+                }
+            }
+            if let Some(partition_meta) = response.meta {
+                let merged = meta.get_or_insert(QueryMeta {
+                    took_ms: 0,
+                    segments: 0,
+                    opstamp: 0,
+                    reader_cached: true,
+                });
+                merged.segments += partition_meta.segments;
+                merged.opstamp = merged.opstamp.max(partition_meta.opstamp);
+                merged.reader_cached &= partition_meta.reader_cached;
+            }
+
+            // Later partitions only hold matches that sort after these.
+            if body.sort.is_some()
+                && matches.len() >= limit
+                && body.track_total_hits == TrackTotalHits::Exact(false)
+            {
+                break;
+            }
+        }
+
+        if body.sort.is_none() {
+            matches.sort_by(|a, b| b.score.total_cmp(&a.score));
+        }
+        matches.truncate(limit);
+        if let Some(meta) = &mut meta {
+            meta.took_ms = started.elapsed().as_millis() as u64;
+        }
+
+        Ok(QueryResponse {
+            matches,
+            aggregations: HashMap::new(),
+            total,
+            next: None,
+            prev: None,
+            meta,
+            timed_out,
+        })
+    }
+
+    async fn query_index(
+        &self,
+        index_id: &str,
+        settings: &IndexSettings,
+        body: QueryRequest,
+    ) -> ServiceResponse<QueryResponse> {
+        let started = Instant::now();
+
+        let with_partition = body
+            .with_partition
+            .as_ref()
+            .map(|x| (x.partition_n, x.total_partitions));
+        let index = self.index_loader.load_index(index_id, with_partition)?;
+        let verbose = body.verbose;
+
+        let (reader, reader_cached) = self.reader_cache.lookup(index_id, with_partition, &index)?;
+
+        info!("ReaderLoaded");
+
+        let searcher = reader.searcher();
+
+        let meta = || -> Result<Option<QueryMeta>, ServiceError> {
+            if !verbose {
+                return Ok(None);
+            }
+            Ok(Some(QueryMeta {
+                took_ms: started.elapsed().as_millis() as u64,
+                segments: searcher.segment_readers().len(),
+                opstamp: index
+                    .load_metas()
+                    .map_err(ServiceError::internal_error)?
+                    .opstamp,
+                reader_cached,
+            }))
+        };
+
+        let schema = index.schema();
+
+        let body = body.resolve_aliases(&schema, settings);
+
+        let terminate_after = body.terminate_after.or(settings.terminate_after);
+        let deadline = body
+            .timeout_ms
+            .or(settings.query_limits.timeout_ms)
+            .map(|timeout_ms| Deadline::after(Duration::from_millis(timeout_ms)));
+        let timed_out = || deadline.as_ref().is_some_and(|deadline| deadline.expired());
+        let compile = |query: &Query| -> Result<Box<dyn TantivyQuery>, ServiceError> {
+            let compiled = query.compile(&index, settings)?;
+            let compiled: Box<dyn TantivyQuery> = match terminate_after {
+                Some(limit) => Box::new(TerminateAfterQuery::new(compiled, limit)),
+                None => compiled,
+            };
+            Ok(match &deadline {
+                Some(deadline) => Box::new(TimeBudgetQuery::new(compiled, deadline.clone())),
+                None => compiled,
+            })
+        };
 
-        let index_id = request.path_param("index_id")?;
+        let query = compile(&body.query)?;
+
+        let filtered = |filters: Vec<Query>| {
+            compile(
+                &filters
+                    .into_iter()
+                    .fold(
+                        Query::bool().must(body.query.clone()),
+                        |bool_query, filter| bool_query.filter(filter),
+                    )
+                    .build(),
+            )
+        };
+
+        // Aggregations count every match of `query`, the post filter only narrows the hits.
+        let mut hits_filters = body.facet_selections(None);
+        hits_filters.extend(body.post_filter.clone());
+
+        let hits_query = if hits_filters.is_empty() {
+            query.box_clone()
+        } else {
+            filtered(hits_filters)?
+        };
+
+        let limit = body.limit()?;
+
+        // A partition only sees its own segments, so its scores are only comparable with other
+        // partitions' when scored with statistics from the whole index. Without hits, nothing is
+        // scored.
+        let hits_query: Box<dyn TantivyQuery> = match (&body.with_partition, body.global_idf) {
+            (Some(_), true) if limit > 0 => {
+                let whole_index = self.index_loader.load_index(index_id, None)?;
+                let stats_searcher = self
+                    .reader_cache
+                    .reader(index_id, None, &whole_index)?
+                    .searcher();
+                Box::new(GlobalStatsQuery::new(hits_query, Arc::new(stats_searcher)))
+            }
+            _ => hits_query,
+        };
+        let hits_query = match &body.boost {
+            Some(boost) => boost.apply(hits_query, &schema, settings)?,
+            None => hits_query,
+        };
+
+        let ranker = Ranker::create(&schema, body.sort.as_ref(), body.tiebreak.as_deref())?;
+        let page = Page::create(&body, limit, &self.cursor_key)?;
+        let mut top_docs = ranked_top_docs(&searcher, &hits_query, ranker, page.ranked())?;
+        let (next, prev) = page.cursors(&mut top_docs, &self.cursor_key);
+        let total = count_hits(&searcher, &hits_query, body.track_total_hits);
+
+        let aggregations = if body.facet_filters.is_empty() {
+            aggregation::aggregate(&searcher, &query, &body.aggs, settings.time_zone())?
+        } else {
+            body.aggs
+                .iter()
+                .map(|(name, agg)| {
+                    let agg_query = filtered(body.facet_selections(Some(agg.field())))?;
+                    let result = agg.run(&searcher, &agg_query, settings.time_zone())?;
+                    Ok((name.clone(), result))
+                })
+                .collect::<Result<_, ServiceError>>()?
+        };
+
+        // Pure aggregation queries only run collectors, without fetching or highlighting docs.
+        if top_docs.is_empty() {
+            return Ok(QueryResponse {
+                matches: vec![],
+                aggregations,
+                total,
+                next,
+                prev,
+                meta: meta()?,
+                timed_out: timed_out(),
+            });
+        }
+
+        if settings.search_only {
+            // Only fields marked `STORED` are kept in the index.
+            let matches = top_docs
+                .into_iter()
+                .map(|(score, address)| {
+                    let document = searcher.doc(address).expect("doc should exist");
+
+                    SearchHit {
+                        doc: body.select_fields(
+                            json::to_value(schema.to_named_doc(&document))
+                                .expect("named doc should serialize"),
+                        ),
+                        snippets: json::json!({}),
+                        score,
+                        truncated_fields: vec![],
+                    }
+                })
+                .collect();
+
+            return Ok(QueryResponse {
+                matches,
+                aggregations,
+                total,
+                next,
+                prev,
+                meta: meta()?,
+                timed_out: timed_out(),
+            });
+        }
+
+        let matches: Vec<_> = top_docs
+            .into_iter()
+            .map(|(score, address)| {
+                let document = searcher.doc(address).expect("doc should exist");
+
+                let named_doc = schema.to_named_doc(&document);
+
+                let stored_ref = SearchDocRef::from(named_doc);
+
+                (score, stored_ref)
+            })
+            .collect();
+
+        let retrieved_matches = self
+            .document_store
+            .get_documents(
+                matches
+                    .iter()
+                    .map(|(_score, doc_ref)| doc_ref.clone())
+                    .collect(),
+            )
+            .await
+            .unwrap();
+
+        // The document store does not guarantee documents are returned in request order.
+        let mut retrieved_matches: HashMap<String, _> = retrieved_matches
+            .into_iter()
+            .map(|search_doc| (search_doc.id().id().to_string(), search_doc))
+            .collect();
+
+        let highlight = &body.highlight;
+        let snippet_start = Instant::now();
+        let mut analyzed_bytes = 0;
+        let mut truncated_count = 0;
+        // Generators only depend on the query and field, so each is built once for all the hits.
+        let mut generators: HashMap<Field, Option<SnippetGenerator>> = HashMap::new();
+
+        let matches: Vec<SearchHit> = matches
+            .into_iter()
+            .filter_map(|(score, doc_ref)| Some((score, retrieved_matches.remove(doc_ref.id())?)))
+            .map(|(score, search_doc)| {
+                let document = search_doc.document(&schema);
+
+                let named_doc = schema.to_named_doc(&document);
+
+                let mut truncated_fields = vec![];
+
+                let snippets: HashMap<String, json::Value> = document
+                    .field_values()
+                    .iter()
+                    .filter_map(|field_value| {
+                        // Only text fields are supported for snippets
+                        let text = field_value.value().as_text()?;
+
+                        let field_name = schema.get_field_name(field_value.field());
+
+                        if !highlight.includes_field(field_name, settings.snippet_fields.as_ref())
+                            || !tokenizer::supports_snippets(
+                                schema.get_field_entry(field_value.field()),
+                            )
+                        {
+                            return None;
+                        }
+
+                        let generator = generators
+                            .entry(field_value.field())
+                            .or_insert_with(|| {
+                                snippet_generator(
+                                    &searcher,
+                                    &query,
+                                    field_value.field(),
+                                    highlight.fragment_size,
+                                )
+                            })
+                            .as_ref()?;
+
+                        let text = match truncate(text, highlight.max_analyzed_chars(field_name)) {
+                            Some(prefix) => {
+                                truncated_fields.push(field_name.to_string());
+                                if highlight.skip_oversized {
+                                    return None;
+                                }
+                                prefix
+                            }
+                            None => text,
+                        };
+                        analyzed_bytes += text.len();
+
+                        let fragments: Vec<String> = highlight
+                            .snippets(generator, text)
+                            .iter()
+                            .map(|snippet| highlight.render(snippet))
+                            .collect();
+
+                        if fragments.is_empty() {
+                            return None;
+                        }
+
+                        let snippet = match highlight.number_of_fragments {
+                            Some(n) if n > 1 => json::to_value(fragments).ok()?,
+                            _ => json::Value::String(fragments.into_iter().next()?),
+                        };
+
+                        Some((field_name.into(), snippet))
+                    })
+                    .collect();
+
+                truncated_count += truncated_fields.len();
+
+                SearchHit {
+                    score,
+                    doc: body.select_fields(
+                        json::to_value(named_doc).expect("named doc should serialize"),
+                    ),
+                    snippets: json::to_value(snippets).expect("snippets should serialize"),
+                    truncated_fields,
+                }
+            })
+            .collect();
+
+        info!(
+            message = "snippets_generated",
+            duration_ms = snippet_start.elapsed().as_millis() as u64,
+            analyzed_bytes,
+            truncated_fields = truncated_count,
+            generators = generators.len()
+        );
+
+        Ok(QueryResponse {
+            matches,
+            aggregations,
+            total,
+            next,
+            prev,
+            meta: meta()?,
+            timed_out: timed_out(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aggregation::Bucket;
+    use crate::schema::id_key;
+    use crate::store::analytics::test_util::TestAnalyticsStore;
+    use crate::test_utils::*;
+
+    fn test_service(ctx: &TestContext) -> QueryIndexService {
+        QueryIndexService {
+            schema_loader: Box::new(ctx.schema_loader().clone()),
+            document_store: Box::new(ctx.document_store().clone()),
+            index_loader: Box::new(ctx.index_loader().clone()),
+            cursor_key: CursorKey::new(b"test"),
+            reader_cache: ReaderCache::default(),
+            analytics: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn query_default_response() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![json!({
+                    "__id": "foobar",
+                    "title": "hello",
+                    "author": "world"
+                })],
+            )
+            .await;
+
+        let service = test_service(&ctx);
+
+        let request = ServiceRequest::create(QueryRequest {
+            query: "hello".into(),
+            ..Default::default()
+        })
+        .with_path_param("index_id", "test");
+
+        let response = service.handle_request(request).await.unwrap();
+
+        assert_eq!(
+            QueryResponse {
+                matches: vec![SearchHit {
+                    doc: json::json!({
+                        "__id": ["foobar"],
+                        "title": ["hello"],
+                        "author": ["world"],
+                    }),
+                    score: 0.28768212,
+                    snippets: json::json!({
+                        "title": "<b>hello</b>"
+                    }),
+                    truncated_fields: vec![],
+                }],
+                aggregations: HashMap::new(),
+                total: None,
+                next: None,
+                prev: None,
+                meta: None,
+                timed_out: false,
+            },
+            response
+        );
+    }
+
+    #[tokio::test]
+    async fn query_out_of_time_returns_partial_results() {
+        let ctx = setup()
+            .with_documents("test", vec![json!({ "title": "hello" })])
+            .await;
+        let service = test_service(&ctx);
+
+        let request = |timeout_ms: u64| {
+            ServiceRequest::create(QueryRequest {
+                query: "hello".into(),
+                timeout_ms: Some(timeout_ms),
+                ..Default::default()
+            })
+            .with_path_param("index_id", "test")
+        };
+
+        let response = service.handle_request(request(60_000)).await.unwrap();
+        assert_eq!(1, response.matches.len());
+        assert!(!response.timed_out);
+
+        let response = service.handle_request(request(0)).await.unwrap();
+        assert!(response.matches.is_empty());
+        assert!(response.timed_out);
+    }
+
+    #[tokio::test]
+    async fn verbose_query_returns_meta() {
+        let ctx = setup()
+            .with_documents("test", vec![json!({ "title": "hello" })])
+            .await;
+        let service = test_service(&ctx);
+
+        let request = || {
+            ServiceRequest::create(QueryRequest {
+                query: "hello".into(),
+                verbose: true,
+                ..Default::default()
+            })
+            .with_path_param("index_id", "test")
+        };
+
+        let meta = service
+            .handle_request(request())
+            .await
+            .unwrap()
+            .meta
+            .unwrap();
+        assert_eq!(1, meta.segments);
+        assert!(!meta.reader_cached);
+
+        let index = ctx.index_loader().load_index("test", None).unwrap();
+        assert_eq!(index.load_metas().unwrap().opstamp, meta.opstamp);
+
+        let meta = service
+            .handle_request(request())
+            .await
+            .unwrap()
+            .meta
+            .unwrap();
+        assert!(meta.reader_cached);
+    }
+
+    #[tokio::test]
+    async fn query_across_time_partitions() {
+        let day = |days_ago: i64| {
+            let date = chrono::Utc::now().date_naive() - chrono::Duration::days(days_ago);
+            (
+                time_partition::partition_id("rolling", date),
+                format!("{}T12:00:00Z", date),
+            )
+        };
+        let mut ctx = setup();
+        for (days_ago, title) in [
+            (0, "hello today"),
+            (1, "hello yesterday"),
+            (30, "hello old"),
+        ] {
+            let (partition, timestamp) = day(days_ago);
+            ctx = ctx
+                .with_documents(
+                    &partition,
+                    vec![json!({ "title": title, "timestamp": timestamp })],
+                )
+                .await;
+        }
+        let service = test_service(&ctx);
+
+        let request = |sort: Option<SortOrder>| {
+            ServiceRequest::create(QueryRequest {
+                query: "hello".into(),
+                sort: sort.map(|order| SortOptions {
+                    field: "timestamp".into(),
+                    order,
+                }),
+                track_total_hits: TrackTotalHits::Exact(true),
+                ..Default::default()
+            })
+            .with_path_param("index_id", "rolling")
+        };
+        let titles = |response: &QueryResponse| {
+            response
+                .matches
+                .iter()
+                .map(|hit| hit.doc["title"][0].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        // The partition from 30 days ago is past the 7 day retention.
+        let response = service
+            .handle_request(request(Some(SortOrder::Asc)))
+            .await
+            .unwrap();
+        assert_eq!(vec!["hello yesterday", "hello today"], titles(&response));
+        assert_eq!(Some(2), response.total.map(|total| total.value));
+
+        let response = service.handle_request(request(None)).await.unwrap();
+        assert_eq!(2, response.matches.len());
+
+        // Aggregations can't be merged across partitions.
+        let request = ServiceRequest::create_raw(
+            &json!({
+                "query": "hello",
+                "aggs": { "daily": { "date_histogram": {
+                    "field": "timestamp",
+                    "fixed_interval": 86_400_000
+                } } }
+            })
+            .to_string(),
+        )
+        .with_path_param("index_id", "rolling");
+        let err = service.handle_request(request).await.unwrap_err();
+        assert_eq!(400, err.status());
+    }
+
+    #[tokio::test]
+    async fn query_document_with_un_indexed_fields() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![json!({
+                    "__id": "foobar",
+                    "title": "hello",
+                    "meta": "world"
+                })],
+            )
+            .await;
+
+        let service = test_service(&ctx);
+
+        let request = ServiceRequest::create(QueryRequest {
+            query: "hello".into(),
+            ..Default::default()
+        })
+        .with_path_param("index_id", "test");
+
+        let response = service.handle_request(request).await.unwrap();
+
+        assert_eq!(1, response.matches.len());
+    }
+
+    #[tokio::test]
+    async fn query_document_with_json_field() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![json!({
+                    "__id": "foobar",
+                    "title": "hello",
+                    "props": {
+                        "foo": "bar"
+                    }
+                })],
+            )
+            .await;
+
+        let service = test_service(&ctx);
+
+        let request = ServiceRequest::create(QueryRequest {
+            query: "props.foo:bar".into(),
+            ..Default::default()
+        })
+        .with_path_param("index_id", "test");
+
+        let response = service.handle_request(request).await.unwrap();
+
+        assert_eq!(1, response.matches.len());
+    }
+
+    #[tokio::test]
+    async fn query_with_highlight_options() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![json!({
+                    "__id": "foobar",
+                    "title": "hello & world",
+                    "author": "hello"
+                })],
+            )
+            .await;
+
+        let service = test_service(&ctx);
+
+        let request = ServiceRequest::create(QueryRequest {
+            query: "hello".into(),
+            highlight: HighlightOptions {
+                fields: Some(vec!["title".into()]),
+                pre_tag: Some("<em>".into()),
+                post_tag: Some("</em>".into()),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with_path_param("index_id", "test");
+
+        let response = service.handle_request(request).await.unwrap();
+
+        assert_eq!(
+            json!({ "title": "<em>hello</em> &amp; world" }),
+            response.matches[0].snippets
+        );
+    }
+
+    #[tokio::test]
+    async fn query_from_query_string() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![
+                    json!({ "__id": "a", "title": "hello", "author": "ann" }),
+                    json!({ "__id": "b", "title": "hello", "author": "bob" }),
+                    json!({ "__id": "c", "title": "hello", "author": "cat" }),
+                ],
+            )
+            .await;
+
+        let service = test_service(&ctx);
+
+        let request = ServiceRequest::create_raw("")
+            .with_path_param("index_id", "test")
+            .with_query_param("q", "title:hello")
+            .with_query_param("limit", "2")
+            .with_query_param("fields", "__id, author");
+
+        let response = service.handle_request(request).await.unwrap();
+
+        assert_eq!(2, response.matches.len());
+        for hit in response.matches {
+            assert_eq!(
+                vec!["__id", "author"],
+                hit.doc.as_object().unwrap().keys().collect::<Vec<_>>()
+            );
+        }
+
+        let request = ServiceRequest::create_raw("")
+            .with_path_param("index_id", "test")
+            .with_query_param("q", "hello")
+            .with_query_param("limit", "many");
+
+        let err = service.handle_request(request).await.unwrap_err();
+        assert_eq!(400, err.status());
+
+        let request = ServiceRequest::create_raw("")
+            .with_path_param("index_id", "test")
+            .with_query_param("q", "hello")
+            .with_query_param("limit", &usize::MAX.to_string());
+
+        let err = service.handle_request(request).await.unwrap_err();
+        assert_eq!(400, err.status());
+    }
+
+    #[tokio::test]
+    async fn query_with_multiple_fragments() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![json!({
+                    "__id": "foobar",
+                    "title": "hello there, general kenobi. you are a bold one, hello",
+                })],
+            )
+            .await;
+
+        let service = test_service(&ctx);
+
+        let request = ServiceRequest::create(QueryRequest {
+            query: "hello".into(),
+            highlight: HighlightOptions {
+                fragment_size: Some(12),
+                number_of_fragments: Some(3),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with_path_param("index_id", "test");
+
+        let response = service.handle_request(request).await.unwrap();
+
+        let fragments = response.matches[0].snippets["title"].as_array().unwrap();
+
+        assert_eq!(2, fragments.len());
+        assert!(fragments
+            .iter()
+            .all(|fragment| fragment.as_str().unwrap().contains("<b>hello</b>")));
+    }
+
+    #[tokio::test]
+    async fn query_with_oversized_field() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![json!({
+                    "__id": "foobar",
+                    "title": "hello there, general kenobi",
+                })],
+            )
+            .await;
+
+        let service = test_service(&ctx);
+
+        let query = |query: &str, skip_oversized| {
+            ServiceRequest::create(QueryRequest {
+                query: query.into(),
+                highlight: HighlightOptions {
+                    field_max_analyzed_chars: HashMap::from([("title".into(), 5)]),
+                    skip_oversized,
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .with_path_param("index_id", "test")
+        };
+
+        let response = service.handle_request(query("hello", false)).await.unwrap();
+
+        assert_eq!(
+            json!({ "title": "<b>hello</b>" }),
+            response.matches[0].snippets
+        );
+        assert_eq!(vec!["title"], response.matches[0].truncated_fields);
+
+        let response = service
+            .handle_request(query("kenobi", false))
+            .await
+            .unwrap();
+
+        assert_eq!(json!({}), response.matches[0].snippets);
+
+        let response = service.handle_request(query("hello", true)).await.unwrap();
+
+        assert_eq!(json!({}), response.matches[0].snippets);
+        assert_eq!(vec!["title"], response.matches[0].truncated_fields);
+    }
+
+    #[test]
+    fn highlight_fields_default_to_snippet_fields() {
+        let snippet_fields = vec![String::from("title")];
+
+        let highlight = HighlightOptions::default();
+        assert!(highlight.includes_field("author", None));
+        assert!(highlight.includes_field("title", Some(&snippet_fields)));
+        assert!(!highlight.includes_field("author", Some(&snippet_fields)));
+
+        let highlight = HighlightOptions {
+            fields: Some(vec!["author".into()]),
+            ..Default::default()
+        };
+        assert!(highlight.includes_field("author", Some(&snippet_fields)));
+        assert!(!highlight.includes_field("title", Some(&snippet_fields)));
+    }
+
+    #[test]
+    fn fields_used_cover_queries_sorts_and_returned_fields() {
+        let schema = setup().schema_loader().load_schema("test").unwrap();
+        let request: QueryRequest = json::from_value(json!({
+            "query": "title:zen AND (author:pirsig OR motorcycle)",
+            "post_filter": { "term": { "field": "year", "value": 1974 } },
+            "sort": { "field": "date_added" },
+            "fields": ["isbn"]
+        }))
+        .unwrap();
+
+        assert_eq!(
+            vec!["title", "author", "year", "date_added", "isbn"],
+            request.fields_used(&schema, &IndexSettings::default())
+        );
+    }
+
+    #[tokio::test]
+    async fn queries_to_indexes_with_analytics_are_recorded() {
+        let ctx = setup()
+            .with_documents("audited", vec![json!({ "__id": "zen", "title": "Zen" })])
+            .await
+            .with_documents("test", vec![json!({ "title": "Zen" })])
+            .await;
+        let store = TestAnalyticsStore::create();
+        let service = QueryIndexService {
+            analytics: Some(Analytics::new(Box::new(store.clone()))),
+            ..test_service(&ctx)
+        };
+
+        for index_id in ["audited", "test"] {
+            let request = ServiceRequest::create(QueryRequest {
+                query: "zen".into(),
+                ..Default::default()
+            })
+            .with_path_param("index_id", index_id);
+            service.handle_request(request).await.unwrap();
+        }
+        service.analytics.as_ref().unwrap().flush().await;
+
+        let batches = store.batches();
+        assert_eq!(1, batches.len());
+        let batch = String::from_utf8(batches.into_values().next().unwrap()).unwrap();
+        let records: Vec<QueryRecord> = batch
+            .lines()
+            .map(|line| json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(1, records.len());
+        assert_eq!("audited", records[0].index_id);
+        assert_eq!(json!({ "query_string": "zen" }), records[0].query["query"]);
+        let ids: Vec<_> = records[0].results.iter().map(|result| &result.id).collect();
+        assert_eq!(vec!["zen"], ids);
+    }
+
+    #[tokio::test]
+    async fn query_search_only_index() {
+        let ctx = setup()
+            .with_documents(
+                "searchonly",
+                vec![json!({
+                    "__id": "foobar",
+                    "title": "hello",
+                    "price": "9.99",
+                })],
+            )
+            .await;
+
+        let service = test_service(&ctx);
+
+        let request = ServiceRequest::create(QueryRequest {
+            query: "hello".into(),
+            ..Default::default()
+        })
+        .with_path_param("index_id", "searchonly");
+
+        let response = service.handle_request(request).await.unwrap();
+
+        assert_eq!(
+            json!({ "__id": ["foobar"], "price": [9.99] }),
+            response.matches[0].doc
+        );
+        assert_eq!(json!({}), response.matches[0].snippets);
+    }
+
+    #[tokio::test]
+    async fn query_pages_with_cursors() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                (0..5)
+                    .map(|n| json!({ "__id": format!("doc-{}", n), "title": "hello" }))
+                    .collect(),
+            )
+            .await;
+
+        let service = test_service(&ctx);
+
+        let page = |query: &str, cursor: Option<String>| {
+            let request = ServiceRequest::create(QueryRequest {
+                query: query.into(),
+                limit: Some(2),
+                cursor,
+                ..Default::default()
+            })
+            .with_path_param("index_id", "test");
+            service.handle_request(request)
+        };
+        let ids = |response: &QueryResponse| {
+            response
+                .matches
+                .iter()
+                .map(|hit| hit.doc["__id"][0].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let first = page("hello", None).await.unwrap();
+        assert_eq!(vec!["doc-0", "doc-1"], ids(&first));
+        assert_eq!(None, first.prev);
+
+        let second = page("hello", first.next.clone()).await.unwrap();
+        assert_eq!(vec!["doc-2", "doc-3"], ids(&second));
+
+        let last = page("hello", second.next.clone()).await.unwrap();
+        assert_eq!(vec!["doc-4"], ids(&last));
+        assert_eq!(None, last.next);
+
+        let back = page("hello", last.prev.clone()).await.unwrap();
+        assert_eq!(ids(&second), ids(&back));
+
+        // A cursor only pages through the query it came from.
+        let err = page("title:hello", first.next).await.unwrap_err();
+        assert_eq!(400, err.status());
+    }
+
+    #[tokio::test]
+    async fn query_sorted_by_date() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![
+                    json!({ "__id": "old", "title": "hello", "date_added": "2020-01-01T00:00:00Z" }),
+                    json!({ "__id": "new", "title": "hello", "date_added": "2022-06-01T00:00:00Z" }),
+                    json!({ "__id": "mid", "title": "hello", "date_added": "2021-03-01T00:00:00Z" }),
+                ],
+            )
+            .await;
+
+        let service = test_service(&ctx);
+
+        let query = |field: &str, order| {
+            ServiceRequest::create(QueryRequest {
+                query: "hello".into(),
+                sort: Some(SortOptions {
+                    field: field.into(),
+                    order,
+                }),
+                ..Default::default()
+            })
+            .with_path_param("index_id", "test")
+        };
+
+        let ids = |response: QueryResponse| {
+            response
+                .matches
+                .iter()
+                .map(|hit| hit.doc["__id"][0].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let response = service
+            .handle_request(query("date_added", SortOrder::Desc))
+            .await
+            .unwrap();
+        assert_eq!(vec!["new", "mid", "old"], ids(response));
+
+        let response = service
+            .handle_request(query("date_added", SortOrder::Asc))
+            .await
+            .unwrap();
+        assert_eq!(vec!["old", "mid", "new"], ids(response));
+
+        // `year` is indexed but not a fast field.
+        let err = service
+            .handle_request(query("year", SortOrder::Asc))
+            .await
+            .unwrap_err();
+        assert_eq!(400, err.status());
+    }
+
+    #[tokio::test]
+    async fn query_with_date_histogram() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![
+                    json!({ "title": "hello", "date_added": "2022-01-31T23:00:00Z" }),
+                    json!({ "title": "hello", "date_added": "2022-02-01T00:00:00Z" }),
+                    json!({ "title": "hello", "date_added": "2022-02-28T12:00:00Z" }),
+                    json!({ "title": "world", "date_added": "2022-02-15T12:00:00Z" }),
+                ],
+            )
+            .await;
+
+        let service = test_service(&ctx);
+
+        let request = ServiceRequest::create(
+            json::from_value::<QueryRequest>(json!({
+                "query": "hello",
+                "aggs": {
+                    "per_month": {
+                        "date_histogram": { "field": "date_added", "calendar_interval": "month" }
+                    }
+                }
+            }))
+            .unwrap(),
+        )
+        .with_path_param("index_id", "test");
+
+        let response = service.handle_request(request).await.unwrap();
+
+        assert_eq!(
+            json!({
+                "buckets": [
+                    { "key": "2022-01-01T00:00:00+00:00", "doc_count": 1 },
+                    { "key": "2022-02-01T00:00:00+00:00", "doc_count": 2 },
+                ]
+            }),
+            json::to_value(&response.aggregations["per_month"]).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn query_with_date_ranges() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![
+                    json!({ "title": "hello", "date_added": "2021-06-01T00:00:00Z" }),
+                    json!({ "title": "hello", "date_added": "2022-01-01T00:00:00Z" }),
+                    json!({ "title": "hello", "date_added": "2022-03-01T00:00:00Z" }),
+                ],
+            )
+            .await;
+
+        let service = test_service(&ctx);
+        let request = |field: &str| {
+            ServiceRequest::create(
+                json::from_value::<QueryRequest>(json!({
+                    "query": "hello",
+                    "limit": 0,
+                    "aggs": {
+                        "added": {
+                            "range": {
+                                "field": field,
+                                "ranges": [
+                                    { "to": "2022-01-01" },
+                                    { "from": "2022-01-01", "to": "2022-02-01" },
+                                    { "from": "2022-02-01", "key": "recent" },
+                                    { "from": "2023-01-01" }
+                                ]
+                            }
+                        }
+                    }
+                }))
+                .unwrap(),
+            )
+            .with_path_param("index_id", "test")
+        };
+
+        let response = service.handle_request(request("date_added")).await.unwrap();
+        assert_eq!(
+            json!({
+                "buckets": [
+                    { "key": "*-2022-01-01", "doc_count": 1 },
+                    { "key": "2022-01-01-2022-02-01", "doc_count": 1 },
+                    { "key": "recent", "doc_count": 1 },
+                    { "key": "2023-01-01-*", "doc_count": 0 },
+                ]
+            }),
+            json::to_value(&response.aggregations["added"]).unwrap()
+        );
 
-        let index = self.index_loader.load_index(
-            &index_id,
-            body.with_partition
-                .map(|x| (x.partition_n, x.total_partitions)),
-        )?;
+        // Ranges need a fast field.
+        let err = service.handle_request(request("year")).await.unwrap_err();
+        assert_eq!(400, err.status());
+    }
 
-        let reader = index.reader().expect("Reader should load");
+    #[tokio::test]
+    async fn aggregation_only_query_returns_no_matches() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![
+                    json!({ "title": "hello", "category": "/books/science", "date_added": "2022-01-31T23:00:00Z" }),
+                    json!({ "title": "hello", "category": "/books/sci-fi", "date_added": "2022-02-01T00:00:00Z" }),
+                    json!({ "title": "hello", "category": "/books/science", "date_added": "2022-02-28T12:00:00Z" }),
+                ],
+            )
+            .await;
 
-        info!("ReaderLoaded");
+        let service = test_service(&ctx);
 
-        let searcher = reader.searcher();
+        let request = ServiceRequest::create(
+            json::from_value::<QueryRequest>(json!({
+                "query": "hello",
+                "limit": 0,
+                "track_total_hits": true,
+                "aggs": {
+                    "per_month": {
+                        "date_histogram": { "field": "date_added", "calendar_interval": "month" }
+                    },
+                    "genres": { "facet": { "field": "category", "path": "/books" } }
+                }
+            }))
+            .unwrap(),
+        )
+        .with_path_param("index_id", "test");
 
-        let schema = index.schema();
+        let response = service.handle_request(request).await.unwrap();
 
-        let query_parser = QueryParser::for_index(
-            &index,
-            schema
-                .fields()
-                .filter_map(|(field, entry)| {
-                    if !entry.is_indexed() {
-                        return None;
-                    }
-                    match entry.field_type() {
-                        FieldType::Str(_) => Some(field),
-                        _ => None,
-                    }
-                })
-                .collect::<Vec<Field>>(),
+        assert!(response.matches.is_empty());
+        assert!(response.next.is_none());
+        assert_eq!(3, response.total.unwrap().value);
+        assert_eq!(
+            json!({
+                "buckets": [
+                    { "key": "2022-01-01T00:00:00+00:00", "doc_count": 1 },
+                    { "key": "2022-02-01T00:00:00+00:00", "doc_count": 2 },
+                ]
+            }),
+            json::to_value(&response.aggregations["per_month"]).unwrap()
         );
+        assert_eq!(
+            json!({
+                "buckets": [
+                    { "key": "/books/science", "doc_count": 2 },
+                    { "key": "/books/sci-fi", "doc_count": 1 },
+                ]
+            }),
+            json::to_value(&response.aggregations["genres"]).unwrap()
+        );
+    }
 
-        let query = query_parser
-            .parse_query(&body.query)
-            .map_err(|err| ServiceError::invalid_request(&err.to_string()))?;
-
-        let top_docs: Vec<(Score, DocAddress)> = searcher
-            .search(&query, &TopDocs::with_limit(10))
-            .expect("search should succeed");
+    #[tokio::test]
+    async fn post_filter_narrows_matches_but_not_aggregations() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![
+                    json!({ "__id": "a", "title": "hello", "year": 2021, "date_added": "2021-06-01T00:00:00Z" }),
+                    json!({ "__id": "b", "title": "hello", "year": 2022, "date_added": "2022-06-01T00:00:00Z" }),
+                ],
+            )
+            .await;
 
-        let matches: Vec<_> = top_docs
-            .into_iter()
-            .map(|(score, address)| {
-                let document = searcher.doc(address).expect("doc should exist");
+        let service = test_service(&ctx);
 
-                let named_doc = schema.to_named_doc(&document);
+        let request = ServiceRequest::create(
+            json::from_value::<QueryRequest>(json!({
+                "query": "hello",
+                "post_filter": { "term": { "field": "year", "value": 2022 } },
+                "aggs": {
+                    "per_year": {
+                        "date_histogram": { "field": "date_added", "calendar_interval": "year" }
+                    }
+                }
+            }))
+            .unwrap(),
+        )
+        .with_path_param("index_id", "test");
 
-                let stored_ref = SearchDocRef::from(named_doc);
+        let response = service.handle_request(request).await.unwrap();
 
-                (score, stored_ref)
-            })
-            .collect();
+        assert_eq!(1, response.matches.len());
+        assert_eq!(json!(["b"]), response.matches[0].doc["__id"]);
 
-        if matches.len() == 0 {
-            return Ok(QueryResponse { matches: vec![] });
-        }
+        assert_eq!(
+            AggregationResult::Buckets {
+                buckets: vec![
+                    Bucket {
+                        key: "2021-01-01T00:00:00+00:00".into(),
+                        doc_count: 1,
+                    },
+                    Bucket {
+                        key: "2022-01-01T00:00:00+00:00".into(),
+                        doc_count: 1,
+                    },
+                ]
+            },
+            response.aggregations["per_year"]
+        );
+    }
 
-        let retrieved_matches = self
-            .document_store
-            .get_documents(
-                matches
-                    .iter()
-                    .map(|(_score, doc_ref)| doc_ref.clone())
-                    .collect(),
+    #[tokio::test]
+    async fn query_fields_derived_by_dynamic_index() {
+        let ctx = setup()
+            .with_documents(
+                "dynamic",
+                vec![json!({ "__id": "a", "title": "hello", "genre": "fiction", "pages": 320 })],
+            )
+            .await
+            .with_documents(
+                "dynamic",
+                vec![json!({ "__id": "b", "title": "hello", "genre": "poetry", "rating": 4.5 })],
             )
+            .await;
+
+        let service = test_service(&ctx);
+
+        let query = |query: json::Value| {
+            ServiceRequest::create(json::from_value::<QueryRequest>(query).unwrap())
+                .with_path_param("index_id", "dynamic")
+        };
+
+        let response = service
+            .handle_request(query(json!({ "query": "genre:poetry" })))
             .await
             .unwrap();
+        assert_eq!(1, response.matches.len());
+        assert_eq!(json!(["b"]), response.matches[0].doc["__id"]);
 
-        let matches = retrieved_matches
-            .iter()
-            .zip(matches)
-            .map(|(search_doc, (score, _))| {
-                let document = search_doc.document(&schema);
+        // Segments written before `rating` existed don't match it.
+        let response = service
+            .handle_request(query(json!({
+                "query": { "range": { "field": "pages", "gte": 300 } }
+            })))
+            .await
+            .unwrap();
+        assert_eq!(1, response.matches.len());
+        assert_eq!(json!(["a"]), response.matches[0].doc["__id"]);
 
-                let named_doc = schema.to_named_doc(&document);
+        let response = service
+            .handle_request(query(json!({ "query": "hello" })))
+            .await
+            .unwrap();
+        assert_eq!(2, response.matches.len());
+    }
 
-                let snippets: HashMap<String, String> = document
-                    .field_values()
-                    .iter()
-                    .filter_map(|field_value| {
-                        // Only text fields are supported for snippets
-                        let text = field_value.value().as_text()?;
+    #[tokio::test]
+    async fn facet_aggregation_filters_values_by_prefix() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![
+                    json!({ "__id": "a", "title": "hello", "category": "/books/science" }),
+                    json!({ "__id": "b", "title": "hello", "category": "/books/science" }),
+                    json!({ "__id": "c", "title": "hello", "category": "/books/sci-fi" }),
+                    json!({ "__id": "d", "title": "hello", "category": "/books/history" }),
+                    json!({ "__id": "e", "title": "other", "category": "/books/scifi" }),
+                ],
+            )
+            .await;
 
-                        let generator = match SnippetGenerator::create(
-                            &searcher,
-                            &query,
-                            field_value.field(),
-                        ) {
-                            Ok(generator) => Some(generator),
-                            // InvalidArgument is returned when field is not indexed
-                            Err(TantivyError::InvalidArgument(_)) => None,
-                            Err(err) => panic!("{}", err.to_string()),
-                        }?;
-
-                        let snippet = generator.snippet(text).to_html();
-
-                        if snippet.is_empty() {
-                            None
-                        } else {
-                            Some((schema.get_field_name(field_value.field()).into(), snippet))
-                        }
-                    })
-                    .collect();
+        let service = test_service(&ctx);
 
-                SearchHit {
-                    score,
-                    doc: json::to_value(named_doc).expect("named doc should serialize"),
-                    snippets: json::to_value(snippets).expect("snippets should serialize"),
+        let request = ServiceRequest::create(
+            json::from_value::<QueryRequest>(json!({
+                "query": "hello",
+                "aggs": {
+                    "genres": {
+                        "facet": { "field": "category", "path": "/books", "prefix": "SCI" }
+                    }
                 }
-            })
-            .collect();
+            }))
+            .unwrap(),
+        )
+        .with_path_param("index_id", "test");
 
-        Ok(QueryResponse { matches })
-    }
-}
+        let response = service.handle_request(request).await.unwrap();
 
-impl QueryIndexService {
-    pub async fn create() -> QueryIndexService {
-        let document_store = DDBDocumentStore::create(None).await;
-        let index_loader = LambdaIndexLoader::create();
+        assert_eq!(
+            AggregationResult::Buckets {
+                buckets: vec![
+                    Bucket {
+                        key: "/books/science".into(),
+                        doc_count: 2,
+                    },
+                    Bucket {
+                        key: "/books/sci-fi".into(),
+                        doc_count: 1,
+                    },
+                ]
+            },
+            response.aggregations["genres"]
+        );
 
-        QueryIndexService {
-            document_store: Box::new(document_store),
-            index_loader: Box::new(index_loader.await),
-        }
+        let request = ServiceRequest::create(
+            json::from_value::<QueryRequest>(json!({
+                "query": { "term": { "field": "category", "value": "/books" } }
+            }))
+            .unwrap(),
+        )
+        .with_path_param("index_id", "test");
+
+        let response = service.handle_request(request).await.unwrap();
+
+        assert_eq!(5, response.matches.len());
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::test_utils::*;
+    #[tokio::test]
+    async fn query_ngram_tokenized_field() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![
+                    json!({ "__id": "a", "handle": "Zenmaster" }),
+                    json!({ "__id": "b", "handle": "artisan" }),
+                ],
+            )
+            .await;
 
-    fn test_service(ctx: &TestContext) -> QueryIndexService {
-        QueryIndexService {
-            document_store: Box::new(ctx.document_store().clone()),
-            index_loader: Box::new(ctx.index_loader().clone()),
-        }
+        let service = test_service(&ctx);
+
+        let request = ServiceRequest::create(
+            json::from_value::<QueryRequest>(json!({ "query": "handle:ZEN" })).unwrap(),
+        )
+        .with_path_param("index_id", "test");
+
+        let response = service.handle_request(request).await.unwrap();
+
+        assert_eq!(1, response.matches.len());
+        assert_eq!(json!(["a"]), response.matches[0].doc["__id"]);
     }
 
     #[tokio::test]
-    async fn query_default_response() {
+    async fn facet_filters_or_within_field_and_across_fields() {
         let ctx = setup()
             .with_documents(
                 "test",
-                vec![json!({
-                    "__id": "foobar",
-                    "title": "hello",
-                    "author": "world"
-                })],
+                vec![
+                    json!({ "__id": "a", "title": "hello", "category": "/books/science", "year": 2021 }),
+                    json!({ "__id": "b", "title": "hello", "category": "/books/history", "year": 2021 }),
+                    json!({ "__id": "c", "title": "hello", "category": "/books/poetry", "year": 2021 }),
+                    json!({ "__id": "d", "title": "hello", "category": "/books/science", "year": 2022 }),
+                ],
             )
             .await;
 
         let service = test_service(&ctx);
 
-        let request = ServiceRequest::create(QueryRequest {
-            query: "hello".into(),
-            with_partition: None,
-        })
+        let request = ServiceRequest::create(
+            json::from_value::<QueryRequest>(json!({
+                "query": "hello",
+                "facet_filters": {
+                    "category": ["/books/science", "/books/history"],
+                    "year": [2021]
+                },
+                "aggs": {
+                    "genres": { "facet": { "field": "category", "path": "/books" } }
+                }
+            }))
+            .unwrap(),
+        )
         .with_path_param("index_id", "test");
 
         let response = service.handle_request(request).await.unwrap();
 
+        let mut ids = response
+            .matches
+            .iter()
+            .map(|hit| hit.doc["__id"][0].as_str().unwrap())
+            .collect::<Vec<_>>();
+        ids.sort();
+        assert_eq!(vec!["a", "b"], ids);
+
+        // Category counts ignore the category selection but respect the year selection.
         assert_eq!(
-            QueryResponse {
-                matches: vec![SearchHit {
-                    doc: json::json!({
-                        "__id": ["foobar"],
-                        "title": ["hello"],
-                        "author": ["world"],
-                    }),
-                    score: 0.28768212,
-                    snippets: json::json!({
-                        "title": "<b>hello</b>"
-                    })
-                }]
+            AggregationResult::Buckets {
+                buckets: vec![
+                    Bucket {
+                        key: "/books/history".into(),
+                        doc_count: 1,
+                    },
+                    Bucket {
+                        key: "/books/poetry".into(),
+                        doc_count: 1,
+                    },
+                    Bucket {
+                        key: "/books/science".into(),
+                        doc_count: 1,
+                    },
+                ]
             },
-            response
+            response.aggregations["genres"]
         );
     }
 
     #[tokio::test]
-    async fn query_document_with_un_indexed_fields() {
+    async fn query_stemmed_field() {
         let ctx = setup()
             .with_documents(
                 "test",
-                vec![json!({
-                    "__id": "foobar",
-                    "title": "hello",
-                    "meta": "world"
-                })],
+                vec![json!({ "__id": "a", "summary": "Gone fishing on the lake" })],
             )
             .await;
 
         let service = test_service(&ctx);
 
-        let request = ServiceRequest::create(QueryRequest {
-            query: "hello".into(),
-            with_partition: None,
-        })
+        let request = ServiceRequest::create(
+            json::from_value::<QueryRequest>(json!({ "query": "summary:fished" })).unwrap(),
+        )
         .with_path_param("index_id", "test");
 
         let response = service.handle_request(request).await.unwrap();
 
         assert_eq!(1, response.matches.len());
+        assert_eq!(
+            json!("Gone <b>fishing</b> on the lake"),
+            response.matches[0].snippets["summary"]
+        );
     }
 
     #[tokio::test]
-    async fn query_document_with_json_field() {
+    async fn query_ignores_stopwords() {
         let ctx = setup()
             .with_documents(
                 "test",
-                vec![json!({
-                    "__id": "foobar",
-                    "title": "hello",
-                    "props": {
-                        "foo": "bar"
-                    }
-                })],
+                vec![
+                    json!({ "__id": "a", "summary": "Gone fishing on the lake" }),
+                    json!({ "__id": "b", "summary": "Boats on the lake" }),
+                ],
             )
             .await;
 
         let service = test_service(&ctx);
 
-        let request = ServiceRequest::create(QueryRequest {
-            query: "props.foo:bar".into(),
-            with_partition: None,
-        })
+        let query = |query: &str| {
+            ServiceRequest::create(
+                json::from_value::<QueryRequest>(json!({ "query": query })).unwrap(),
+            )
+            .with_path_param("index_id", "test")
+        };
+
+        let response = service.handle_request(query("summary:gone")).await.unwrap();
+        assert_eq!(0, response.matches.len());
+
+        let response = service
+            .handle_request(query("summary:\"the lake\""))
+            .await
+            .unwrap();
+        assert_eq!(2, response.matches.len());
+    }
+
+    #[tokio::test]
+    async fn query_ascii_folded_field() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![json!({ "__id": "a", "summary": "Breakfast at the Café" })],
+            )
+            .await;
+
+        let service = test_service(&ctx);
+
+        let request = ServiceRequest::create(
+            json::from_value::<QueryRequest>(json!({ "query": "summary:cafe" })).unwrap(),
+        )
         .with_path_param("index_id", "test");
 
         let response = service.handle_request(request).await.unwrap();
 
         assert_eq!(1, response.matches.len());
+        assert_eq!(
+            json!("Breakfast at the <b>Café</b>"),
+            response.matches[0].snippets["summary"]
+        );
+    }
+
+    #[tokio::test]
+    async fn track_total_hits_counts_exactly_or_up_to_a_bound() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![
+                    json!({ "__id": "a", "title": "hello" }),
+                    json!({ "__id": "b", "title": "hello" }),
+                    json!({ "__id": "c", "title": "hello" }),
+                    json!({ "__id": "d", "title": "goodbye" }),
+                ],
+            )
+            .await;
+
+        let service = test_service(&ctx);
+
+        let total = |track_total_hits: json::Value| {
+            let request = ServiceRequest::create(
+                json::from_value::<QueryRequest>(
+                    json!({ "query": "hello", "track_total_hits": track_total_hits }),
+                )
+                .unwrap(),
+            )
+            .with_path_param("index_id", "test");
+            let service = &service;
+            async move { service.handle_request(request).await.unwrap().total }
+        };
+
+        assert_eq!(None, total(json!(false)).await);
+        assert_eq!(
+            Some(TotalHits {
+                value: 3,
+                relation: TotalHitsRelation::Eq
+            }),
+            total(json!(true)).await
+        );
+        assert_eq!(
+            Some(TotalHits {
+                value: 2,
+                relation: TotalHitsRelation::Gte
+            }),
+            total(json!(2)).await
+        );
+        assert_eq!(
+            Some(TotalHits {
+                value: 3,
+                relation: TotalHitsRelation::Eq
+            }),
+            total(json!(3)).await
+        );
+    }
+
+    #[tokio::test]
+    async fn ties_are_ordered_by_id_key_across_segments() {
+        let ids = |response: QueryResponse| {
+            response
+                .matches
+                .iter()
+                .map(|hit| hit.doc["__id"][0].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let doc = |id: &str, day: u32| json!({ "__id": id, "title": "hello", "date_added": format!("2022-11-{:02}T00:00:00Z", day) });
+
+        // Later segments hold the lowest ids, and there are more ties than fit on a page.
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![
+                    doc("j", 1),
+                    doc("k", 2),
+                    doc("l", 3),
+                    doc("h", 4),
+                    doc("i", 5),
+                ],
+            )
+            .await
+            .with_documents("test", vec![doc("f", 6), doc("g", 7), doc("e", 8)])
+            .await
+            .with_documents(
+                "test",
+                vec![doc("c", 9), doc("d", 10), doc("a", 11), doc("b", 12)],
+            )
+            .await;
+
+        let service = test_service(&ctx);
+
+        let query = |query: json::Value| {
+            ServiceRequest::create(json::from_value::<QueryRequest>(query).unwrap())
+                .with_path_param("index_id", "test")
+        };
+
+        let mut expected = vec!["a", "b", "c", "d", "e", "f", "g", "h", "i", "j", "k", "l"];
+        expected.sort_by_key(|id| id_key(id));
+
+        let response = service
+            .handle_request(query(json!({ "query": "hello" })))
+            .await
+            .unwrap();
+        assert_eq!(expected[..10], ids(response));
+
+        // Pages don't skip or repeat any of the ties.
+        let first = service
+            .handle_request(query(json!({ "query": "hello", "limit": 5 })))
+            .await
+            .unwrap();
+        let cursor = first.next.clone();
+        let mut paged = ids(first);
+        let second = service
+            .handle_request(query(
+                json!({ "query": "hello", "limit": 7, "cursor": cursor }),
+            ))
+            .await
+            .unwrap();
+        paged.extend(ids(second));
+        assert_eq!(expected, paged);
+
+        let response = service
+            .handle_request(query(
+                json!({ "query": { "match_all": {} }, "tiebreak": "date_added" }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(
+            vec!["j", "k", "l", "h", "i", "f", "g", "e", "c", "d"],
+            ids(response)
+        );
     }
 }
@@ -0,0 +1,289 @@
+use std::io::Write;
+use std::path::Path;
+
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use tantivy::directory::TerminatingWrite;
+use tantivy::schema::Schema;
+use tantivy::Directory;
+use tracing::info;
+
+use super::snapshot_index::{snapshot_prefix, SnapshotManifest, LEASE_MINUTES};
+use crate::index::{IndexLoader, LambdaIndexLoader, SCHEMA_VERSION_FILE};
+use crate::schema::{diff_schema, SchemaChange, SchemaLoader, SchemaProvider};
+use crate::service::{ServiceError, ServiceHandler, ServiceRequest, ServiceResponse};
+use crate::store::event::{self, DDBEventStore, EventStore, IndexEvent};
+use crate::store::lease::{DDBLeaseStore, LeaseStore};
+use crate::store::snapshot::{S3SnapshotStore, SnapshotStore};
+use crate::worker::reindex::rebuild_schema;
+use crate::{json, util};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RestoreRequest {
+    pub snapshot_id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RestoreResponse {
+    pub snapshot_id: String,
+    /// Opstamp of the commit the index was restored to.
+    pub opstamp: u64,
+    pub num_files: usize,
+}
+
+/// Replaces an index with a snapshot taken by
+/// [`SnapshotIndexService`](super::SnapshotIndexService). The snapshot is downloaded into a
+/// staging index and swapped in once complete, so queries see either the old index or the
+/// restored one. Like snapshots, restores hold the index's lease while they run.
+pub struct RestoreIndexService {
+    schema_loader: Box<dyn SchemaLoader>,
+
+    index_loader: Box<dyn IndexLoader>,
+
+    lease_store: Box<dyn LeaseStore>,
+
+    snapshot_store: Box<dyn SnapshotStore>,
+
+    event_store: Box<dyn EventStore>,
+}
+
+impl RestoreIndexService {
+    async fn get_file(&self, prefix: &str, name: &str) -> ServiceResponse<Vec<u8>> {
+        self.snapshot_store
+            .get_object(&format!("{}/files/{}", prefix, name))
+            .await?
+            .ok_or_else(|| {
+                ServiceError::internal_error(std::io::Error::other(format!(
+                    "Snapshot file [{}/files/{}] is missing",
+                    prefix, name
+                )))
+            })
+    }
+
+    /// Rejects snapshots whose fields differ from the configured ones, which the index would be
+    /// rebuilt from. Dynamic indexes may also have fields derived since they were configured.
+    fn check_schema(
+        &self,
+        index_id: &str,
+        snapshot_id: &str,
+        schema: &Schema,
+    ) -> ServiceResponse<()> {
+        let settings = self.schema_loader.load_settings(index_id)?;
+        let configured = rebuild_schema(
+            schema,
+            self.schema_loader.load_schema(index_id)?,
+            settings.dynamic,
+        );
+
+        match diff_schema(schema, &configured) {
+            SchemaChange::Unchanged => Ok(()),
+            SchemaChange::ReindexRequired(reasons) => Err(ServiceError::invalid_request(&format!(
+                "Snapshot [{}] doesn't match the configured schema of index [{}]: {}",
+                snapshot_id,
+                index_id,
+                reasons.join(", ")
+            ))),
+        }
+    }
+
+    async fn restore(
+        &self,
+        index_id: &str,
+        snapshot_id: &str,
+    ) -> ServiceResponse<SnapshotManifest> {
+        let prefix = snapshot_prefix(index_id, snapshot_id);
+        let manifest = self
+            .snapshot_store
+            .get_object(&format!("{}/manifest.json", prefix))
+            .await?
+            .ok_or_else(|| {
+                ServiceError::not_found(&format!(
+                    "No complete snapshot [{}] of index [{}]",
+                    snapshot_id, index_id
+                ))
+            })?;
+        let manifest: SnapshotManifest =
+            json::from_slice(&manifest).map_err(ServiceError::internal_error)?;
+
+        let meta = self.get_file(&prefix, "meta.json").await?;
+        let schema: Schema = json::from_slice::<json::Value>(&meta)
+            .and_then(|mut meta| json::from_value(meta["schema"].take()))
+            .map_err(ServiceError::internal_error)?;
+        self.check_schema(index_id, snapshot_id, &schema)?;
+
+        let staging = self.index_loader.create_staging_index(index_id, schema)?;
+        let directory = staging.directory();
+
+        // The staging index records the configured schema version, which the snapshot's fields
+        // were just checked against.
+        for file in &manifest.files {
+            if file.name == "meta.json" || file.name == SCHEMA_VERSION_FILE {
+                continue;
+            }
+            let bytes = self.get_file(&prefix, &file.name).await?;
+            let mut writer = directory
+                .open_write(Path::new(&file.name))
+                .map_err(ServiceError::internal_error)?;
+            writer
+                .write_all(&bytes)
+                .map_err(ServiceError::internal_error)?;
+            writer.terminate().map_err(ServiceError::internal_error)?;
+        }
+
+        // Written last, so the staging index only refers to segments once they're all in place.
+        directory
+            .atomic_write(Path::new("meta.json"), &meta)
+            .map_err(ServiceError::internal_error)?;
+
+        self.index_loader.swap_staging_index(index_id)?;
+
+        Ok(manifest)
+    }
+}
+
+#[async_trait]
+impl ServiceHandler<RestoreRequest, RestoreResponse> for RestoreIndexService {
+    async fn handle_request(
+        &self,
+        request: ServiceRequest<RestoreRequest>,
+    ) -> ServiceResponse<RestoreResponse> {
+        let index_id = request.index_id()?;
+        let snapshot_id = request.body()?.snapshot_id;
+
+        let owner = util::generate_id();
+        let expires_at = Utc::now() + Duration::minutes(LEASE_MINUTES);
+        if !self
+            .lease_store
+            .acquire(&index_id, &owner, expires_at)
+            .await?
+        {
+            return Err(ServiceError::unavailable(&format!(
+                "Index [{}] is being written, try the restore again later",
+                index_id
+            )));
+        }
+
+        let result = self.restore(&index_id, &snapshot_id).await;
+        self.lease_store.release(&index_id, &owner).await?;
+        let manifest = result?;
+
+        info!(
+            message = "index_restored",
+            index = index_id,
+            snapshot_id,
+            opstamp = manifest.opstamp,
+            files = manifest.files.len()
+        );
+        let event = IndexEvent::Restore {
+            snapshot_id: snapshot_id.clone(),
+            opstamp: manifest.opstamp,
+        };
+        event::record(self.event_store.as_ref(), &index_id, event).await;
+
+        Ok(RestoreResponse {
+            snapshot_id,
+            opstamp: manifest.opstamp,
+            num_files: manifest.files.len(),
+        })
+    }
+}
+
+impl RestoreIndexService {
+    pub async fn create() -> Self {
+        RestoreIndexService {
+            schema_loader: Box::new(SchemaProvider::lambda().await),
+            index_loader: Box::new(LambdaIndexLoader::create().await),
+            lease_store: Box::new(DDBLeaseStore::create(None).await),
+            snapshot_store: Box::new(S3SnapshotStore::create(None).await),
+            event_store: Box::new(DDBEventStore::create(None).await),
+        }
+    }
+
+    pub fn new(
+        schema_loader: Box<dyn SchemaLoader>,
+        index_loader: Box<dyn IndexLoader>,
+        lease_store: Box<dyn LeaseStore>,
+        snapshot_store: Box<dyn SnapshotStore>,
+        event_store: Box<dyn EventStore>,
+    ) -> Self {
+        RestoreIndexService {
+            schema_loader,
+            index_loader,
+            lease_store,
+            snapshot_store,
+            event_store,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::index::SnapshotIndexService;
+    use crate::store::lease::test_util::TestLeaseStore;
+    use crate::store::snapshot::test_util::TestSnapshotStore;
+    use crate::test_utils::*;
+
+    #[tokio::test]
+    async fn restore_swaps_in_snapshot_with_matching_schema() {
+        let ctx = setup()
+            .with_documents("test", vec![json!({ "title": "hello" })])
+            .await;
+        let lease_store = TestLeaseStore::create();
+        let snapshot_store = TestSnapshotStore::create();
+
+        let snapshot = SnapshotIndexService::new(
+            Box::new(ctx.index_loader().clone()),
+            Box::new(lease_store.clone()),
+            Box::new(snapshot_store.clone()),
+            Box::new(ctx.event_store().clone()),
+        )
+        .handle_request(ServiceRequest::create(json!({})).with_path_param("index_id", "test"))
+        .await
+        .unwrap();
+
+        let ctx = ctx
+            .with_documents("test", vec![json!({ "title": "world" })])
+            .await;
+
+        let request = || {
+            ServiceRequest::create(RestoreRequest {
+                snapshot_id: snapshot.snapshot_id.clone(),
+            })
+            .with_path_param("index_id", "test")
+        };
+
+        // The title field is now matched as a whole value.
+        let changed = SchemaProvider::from_json(json!({
+            "indexes": [{
+                "prefix": "test",
+                "fields": [{ "name": "title", "kind": "text", "flags": ["STRING"] }]
+            }]
+        }));
+        let err = RestoreIndexService::new(
+            Box::new(changed.clone()),
+            Box::new(ctx.index_loader().with_schema_loader(changed)),
+            Box::new(lease_store.clone()),
+            Box::new(snapshot_store.clone()),
+            Box::new(ctx.event_store().clone()),
+        )
+        .handle_request(request())
+        .await
+        .unwrap_err();
+        assert_eq!(400, err.status());
+
+        let service = RestoreIndexService::new(
+            Box::new(ctx.schema_loader().clone()),
+            Box::new(ctx.index_loader().clone()),
+            Box::new(lease_store),
+            Box::new(snapshot_store),
+            Box::new(ctx.event_store().clone()),
+        );
+        let response = service.handle_request(request()).await.unwrap();
+        assert_eq!(snapshot.opstamp, response.opstamp);
+
+        let index = ctx.index_loader().load_index("test", None).unwrap();
+        assert_eq!(1, index.reader().unwrap().searcher().num_docs());
+    }
+}
@@ -0,0 +1,293 @@
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use tantivy::directory::error::OpenReadError;
+use tantivy::Directory;
+use tracing::info;
+
+use crate::index::{IndexLoader, LambdaIndexLoader, SCHEMA_VERSION_FILE};
+use crate::service::{ServiceError, ServiceHandler, ServiceRequest, ServiceResponse};
+use crate::store::event::{self, DDBEventStore, EventStore, IndexEvent};
+use crate::store::lease::{DDBLeaseStore, LeaseStore};
+use crate::store::snapshot::{S3SnapshotStore, SnapshotStore};
+use crate::{json, util};
+
+/// How long a snapshot holds the index's lease, the longest a Lambda can run. A snapshot that
+/// times out never blocks writers for longer.
+pub(crate) const LEASE_MINUTES: i64 = 15;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct SnapshotFile {
+    pub name: String,
+    pub size_bytes: u64,
+}
+
+/// Written after every file of a snapshot is copied, so a snapshot without a manifest is
+/// incomplete.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct SnapshotManifest {
+    pub snapshot_id: String,
+    pub index_id: String,
+    pub created_at: String,
+    /// Opstamp of the commit the snapshot captured.
+    pub opstamp: u64,
+    pub schema_version: Option<String>,
+    pub files: Vec<SnapshotFile>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SnapshotResponse {
+    pub snapshot_id: String,
+    /// Key prefix in the data bucket holding the snapshot.
+    pub prefix: String,
+    pub opstamp: u64,
+    pub num_files: usize,
+    pub size_bytes: u64,
+}
+
+/// Prefix of a snapshot's objects: the index's files under `files/`, and `manifest.json`.
+pub(crate) fn snapshot_prefix(index_id: &str, snapshot_id: &str) -> String {
+    format!("snapshots/{}/{}", index_id, snapshot_id)
+}
+
+/// Copies the last commit of an index to the snapshot store, for disaster recovery and for
+/// cloning indexes between environments. The snapshot holds the index's lease while it copies, so
+/// no writer commits or merges away the files being copied.
+pub struct SnapshotIndexService {
+    index_loader: Box<dyn IndexLoader>,
+
+    lease_store: Box<dyn LeaseStore>,
+
+    snapshot_store: Box<dyn SnapshotStore>,
+
+    event_store: Box<dyn EventStore>,
+}
+
+impl SnapshotIndexService {
+    async fn put_file(
+        &self,
+        prefix: &str,
+        name: &str,
+        body: Vec<u8>,
+    ) -> ServiceResponse<SnapshotFile> {
+        let size_bytes = body.len() as u64;
+        self.snapshot_store
+            .put_object(&format!("{}/files/{}", prefix, name), body)
+            .await?;
+
+        Ok(SnapshotFile {
+            name: name.into(),
+            size_bytes,
+        })
+    }
+
+    /// Copies the files of the last commit, then the manifest listing them.
+    async fn snapshot(
+        &self,
+        index_id: &str,
+        snapshot_id: &str,
+    ) -> ServiceResponse<SnapshotManifest> {
+        let index = self.index_loader.load_index(index_id, None)?;
+        let metas = index.load_metas().map_err(ServiceError::internal_error)?;
+        let directory = index.directory();
+        let prefix = snapshot_prefix(index_id, snapshot_id);
+
+        let mut paths: Vec<PathBuf> = metas
+            .segments
+            .iter()
+            .flat_map(|segment| segment.list_files())
+            .collect();
+        paths.sort();
+
+        let mut files = vec![];
+        for path in paths {
+            let file = match directory.open_read(&path) {
+                Ok(file) => file,
+                // Not every segment component is written, e.g. deletes.
+                Err(OpenReadError::FileDoesNotExist(_)) => continue,
+                Err(err) => return Err(ServiceError::internal_error(err)),
+            };
+            let bytes = file.read_bytes().map_err(ServiceError::internal_error)?;
+            let name = path.to_string_lossy();
+            files.push(
+                self.put_file(&prefix, &name, bytes.as_slice().to_vec())
+                    .await?,
+            );
+        }
+
+        let schema_version = directory
+            .atomic_read(Path::new(SCHEMA_VERSION_FILE))
+            .ok()
+            .map(String::from_utf8)
+            .transpose()
+            .map_err(ServiceError::internal_error)?;
+        if let Some(version) = &schema_version {
+            files.push(
+                self.put_file(&prefix, SCHEMA_VERSION_FILE, version.clone().into_bytes())
+                    .await?,
+            );
+        }
+
+        // The metas loaded above rather than meta.json as it is now, so the snapshot matches the
+        // segment files listed from them.
+        let mut meta = json::to_vec_pretty(&metas).map_err(ServiceError::internal_error)?;
+        meta.push(b'\n');
+        files.push(self.put_file(&prefix, "meta.json", meta).await?);
+
+        let manifest = SnapshotManifest {
+            snapshot_id: snapshot_id.into(),
+            index_id: index_id.into(),
+            created_at: util::timestamp(),
+            opstamp: metas.opstamp,
+            schema_version,
+            files,
+        };
+        self.snapshot_store
+            .put_object(
+                &format!("{}/manifest.json", prefix),
+                json::to_vec_pretty(&manifest).expect("manifest should serialize"),
+            )
+            .await?;
+
+        Ok(manifest)
+    }
+}
+
+#[async_trait]
+impl ServiceHandler<json::Value, SnapshotResponse> for SnapshotIndexService {
+    async fn handle_request(
+        &self,
+        request: ServiceRequest<json::Value>,
+    ) -> ServiceResponse<SnapshotResponse> {
+        let index_id = request.index_id()?;
+        let snapshot_id = util::generate_id();
+
+        let expires_at = Utc::now() + Duration::minutes(LEASE_MINUTES);
+        if !self
+            .lease_store
+            .acquire(&index_id, &snapshot_id, expires_at)
+            .await?
+        {
+            return Err(ServiceError::unavailable(&format!(
+                "Index [{}] is being written, try the snapshot again later",
+                index_id
+            )));
+        }
+
+        let result = self.snapshot(&index_id, &snapshot_id).await;
+        self.lease_store.release(&index_id, &snapshot_id).await?;
+        let manifest = result?;
+
+        let response = SnapshotResponse {
+            prefix: snapshot_prefix(&index_id, &snapshot_id),
+            snapshot_id,
+            opstamp: manifest.opstamp,
+            num_files: manifest.files.len(),
+            size_bytes: manifest.files.iter().map(|file| file.size_bytes).sum(),
+        };
+        info!(
+            message = "index_snapshotted",
+            index = index_id,
+            snapshot_id = response.snapshot_id,
+            opstamp = response.opstamp,
+            files = response.num_files,
+            size_bytes = response.size_bytes
+        );
+        let event = IndexEvent::Snapshot {
+            snapshot_id: response.snapshot_id.clone(),
+            opstamp: response.opstamp,
+        };
+        event::record(self.event_store.as_ref(), &index_id, event).await;
+
+        Ok(response)
+    }
+}
+
+impl SnapshotIndexService {
+    pub async fn create() -> Self {
+        SnapshotIndexService {
+            index_loader: Box::new(LambdaIndexLoader::create().await),
+            lease_store: Box::new(DDBLeaseStore::create(None).await),
+            snapshot_store: Box::new(S3SnapshotStore::create(None).await),
+            event_store: Box::new(DDBEventStore::create(None).await),
+        }
+    }
+
+    pub fn new(
+        index_loader: Box<dyn IndexLoader>,
+        lease_store: Box<dyn LeaseStore>,
+        snapshot_store: Box<dyn SnapshotStore>,
+        event_store: Box<dyn EventStore>,
+    ) -> Self {
+        SnapshotIndexService {
+            index_loader,
+            lease_store,
+            snapshot_store,
+            event_store,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::lease::test_util::TestLeaseStore;
+    use crate::store::snapshot::test_util::TestSnapshotStore;
+    use crate::test_utils::*;
+
+    #[tokio::test]
+    async fn snapshot_copies_last_commit_and_manifest() {
+        let ctx = setup()
+            .with_documents("test", vec![json!({ "title": "hello" })])
+            .await;
+        let lease_store = TestLeaseStore::create();
+        let snapshot_store = TestSnapshotStore::create();
+        let service = SnapshotIndexService::new(
+            Box::new(ctx.index_loader().clone()),
+            Box::new(lease_store.clone()),
+            Box::new(snapshot_store.clone()),
+            Box::new(ctx.event_store().clone()),
+        );
+
+        let request = || ServiceRequest::create(json!({})).with_path_param("index_id", "test");
+        let response = service.handle_request(request()).await.unwrap();
+
+        let manifest: SnapshotManifest = json::from_slice(
+            &snapshot_store
+                .get_object(&format!("{}/manifest.json", response.prefix))
+                .await
+                .unwrap()
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(response.num_files, manifest.files.len());
+        for file in &manifest.files {
+            let object = snapshot_store
+                .get_object(&format!("{}/files/{}", response.prefix, file.name))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(file.size_bytes, object.len() as u64);
+        }
+
+        let meta: json::Value = json::from_slice(
+            &snapshot_store
+                .get_object(&format!("{}/files/meta.json", response.prefix))
+                .await
+                .unwrap()
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(1, meta["segments"].as_array().unwrap().len());
+
+        // The lease is released once the snapshot is done, and snapshots wait for writers.
+        assert!(lease_store
+            .acquire("test", "writer", Utc::now() + Duration::minutes(1))
+            .await
+            .unwrap());
+        let err = service.handle_request(request()).await.unwrap_err();
+        assert_eq!(503, err.status());
+    }
+}
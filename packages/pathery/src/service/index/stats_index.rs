@@ -1,25 +1,45 @@
-use std::fs;
-
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use serde_json as json;
+use tantivy::directory::Directory;
+use tantivy_common::HasLen;
 
-use crate::index::{IndexLoader, LambdaIndexLoader};
+use crate::index::{IndexExt, IndexLoader, LambdaIndexLoader};
+use crate::schema::schema_fingerprint;
 use crate::service::{ServiceHandler, ServiceRequest, ServiceResponse};
 
+#[derive(Serialize, Deserialize)]
+pub struct FileStats {
+    name: String,
+    size_bytes: u64,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct SegmentStats {
     id: String,
     num_docs: u32,
     num_deleted: u32,
     index_size: f64,
+    files: Vec<FileStats>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct IndexStatsResponse {
+    num_segments: usize,
+    num_docs: u64,
+    num_deleted: u64,
+    index_size: f64,
+    /// Version of the field config the index was built with. Indexes built before versions were
+    /// recorded report a fingerprint of their schema instead.
+    schema_version: String,
+    last_commit: Option<String>,
     segments: Vec<SegmentStats>,
 }
 
+fn bytes_to_mb(bytes: u64) -> f64 {
+    bytes as f64 / 1_000_000f64
+}
+
 pub struct StatsIndexService {
     index_loader: Box<dyn IndexLoader>,
 }
@@ -30,49 +50,55 @@ impl ServiceHandler<json::Value, IndexStatsResponse> for StatsIndexService {
         &self,
         request: ServiceRequest<json::Value>,
     ) -> ServiceResponse<IndexStatsResponse> {
-        let index_id = request.path_param("index_id")?;
+        let index_id = request.index_id()?;
 
         let index = self.index_loader.load_index(&index_id, None)?;
 
         let metas = index.load_metas().unwrap();
 
-        let segment_files = fs::read_dir(format!("/mnt/pathery-data/{index_id}"))
-            .unwrap()
-            .filter_map(|entry| entry.ok())
-            .collect::<Vec<_>>();
-
-        let segments = metas
+        let segments: Vec<SegmentStats> = metas
             .segments
             .iter()
             .map(|s| {
-                let segment_id = s.id().uuid_string();
-
-                let index_size_bytes: u64 = segment_files
-                    .iter()
-                    .filter_map(|entry| {
-                        let filename = entry.file_name();
-                        let filename = filename.to_str()?;
-
-                        filename
-                            .starts_with(&segment_id)
-                            .then(|| entry.metadata())
-                            .and_then(Result::ok)
-                            .map(|m| m.len())
+                let mut files = s
+                    .list_files()
+                    .into_iter()
+                    .filter_map(|path| {
+                        // Not every segment component is written, e.g. deletes.
+                        let size_bytes = index.directory().open_read(&path).ok()?.len() as u64;
+
+                        Some(FileStats {
+                            name: path.to_string_lossy().into_owned(),
+                            size_bytes,
+                        })
                     })
-                    .sum();
+                    .collect::<Vec<_>>();
+
+                files.sort_by(|a, b| a.name.cmp(&b.name));
 
-                let index_size_mb: f64 = index_size_bytes as f64 / 1_000_000f64;
+                let index_size_bytes: u64 = files.iter().map(|file| file.size_bytes).sum();
 
                 SegmentStats {
                     id: s.id().uuid_string(),
                     num_docs: s.num_docs(),
                     num_deleted: s.num_deleted_docs(),
-                    index_size: index_size_mb,
+                    index_size: bytes_to_mb(index_size_bytes),
+                    files,
                 }
             })
             .collect();
 
-        Ok(IndexStatsResponse { segments })
+        Ok(IndexStatsResponse {
+            num_segments: segments.len(),
+            num_docs: segments.iter().map(|s| s.num_docs as u64).sum(),
+            num_deleted: segments.iter().map(|s| s.num_deleted as u64).sum(),
+            index_size: segments.iter().map(|s| s.index_size).sum(),
+            schema_version: index
+                .schema_version()
+                .unwrap_or_else(|| schema_fingerprint(&metas.schema)),
+            last_commit: index.last_commit().map(|meta| meta.committed_at),
+            segments,
+        })
     }
 }
 
@@ -84,4 +110,39 @@ impl StatsIndexService {
             index_loader: Box::new(index_loader.await),
         }
     }
+
+    pub fn new(index_loader: Box<dyn IndexLoader>) -> Self {
+        StatsIndexService { index_loader }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[tokio::test]
+    async fn stats_index_reports_segments() {
+        let ctx = setup()
+            .with_documents("test", vec![json!({ "title": "hello" })])
+            .await
+            .with_documents("test", vec![json!({ "title": "world" })])
+            .await;
+
+        let service = StatsIndexService {
+            index_loader: Box::new(ctx.index_loader().clone()),
+        };
+
+        let request = ServiceRequest::create(json!({})).with_path_param("index_id", "test");
+
+        let response = service.handle_request(request).await.unwrap();
+
+        assert_eq!(response.num_segments, response.segments.len());
+        assert_eq!(2, response.num_docs);
+        assert!(response.last_commit.is_some());
+        assert!(response
+            .segments
+            .iter()
+            .all(|segment| !segment.files.is_empty()));
+    }
 }
@@ -0,0 +1,111 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json as json;
+
+use crate::index::{IndexLoader, LambdaIndexLoader};
+use crate::schema::{SchemaLoader, SchemaProvider};
+use crate::service::{ServiceError, ServiceHandler, ServiceRequest, ServiceResponse};
+use crate::suggest::{SuggestDictionary, Suggestion};
+
+const DEFAULT_LIMIT: usize = 10;
+
+const MAX_LIMIT: usize = 100;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SuggestResponse {
+    pub suggestions: Vec<Suggestion>,
+}
+
+pub struct SuggestIndexService {
+    schema_loader: Box<dyn SchemaLoader>,
+
+    index_loader: Box<dyn IndexLoader>,
+}
+
+#[async_trait]
+impl ServiceHandler<json::Value, SuggestResponse> for SuggestIndexService {
+    async fn handle_request(
+        &self,
+        request: ServiceRequest<json::Value>,
+    ) -> ServiceResponse<SuggestResponse> {
+        let index_id = request.index_id()?;
+        let prefix = request
+            .query_param("q")
+            .ok_or_else(|| ServiceError::invalid_request("Missing q query string parameter"))?;
+        let limit = match request.query_param("limit") {
+            Some(limit) => limit
+                .parse::<usize>()
+                .ok()
+                .filter(|limit| (1..=MAX_LIMIT).contains(limit))
+                .ok_or_else(|| {
+                    ServiceError::invalid_request(&format!(
+                        "limit should be between 1 and {}",
+                        MAX_LIMIT
+                    ))
+                })?,
+            None => DEFAULT_LIMIT,
+        };
+
+        let settings = self.schema_loader.load_settings(&index_id)?;
+        if settings.suggest.is_none() {
+            return Err(ServiceError::invalid_request(&format!(
+                "Index [{}] has no suggest fields",
+                index_id
+            )));
+        }
+
+        let index = self.index_loader.load_index(&index_id, None)?;
+
+        // Indexes aren't given a dictionary until their first commit after suggest is configured.
+        let suggestions = SuggestDictionary::load(&index)
+            .map(|dictionary| dictionary.suggest(&prefix, limit))
+            .unwrap_or_default();
+
+        Ok(SuggestResponse { suggestions })
+    }
+}
+
+impl SuggestIndexService {
+    pub async fn create() -> Self {
+        SuggestIndexService {
+            schema_loader: Box::new(SchemaProvider::lambda().await),
+            index_loader: Box::new(LambdaIndexLoader::create().await),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[tokio::test]
+    async fn suggest_reads_dictionary_built_at_commit() {
+        let ctx = setup()
+            .with_documents(
+                "suggesting",
+                vec![
+                    json!({ "title": "Zen and the art" }),
+                    json!({ "title": "Zen mind" }),
+                    json!({ "title": "Zebra crossings" }),
+                ],
+            )
+            .await;
+        let service = SuggestIndexService {
+            schema_loader: Box::new(ctx.schema_loader().clone()),
+            index_loader: Box::new(ctx.index_loader().clone()),
+        };
+
+        let request = ServiceRequest::create(json!({}))
+            .with_path_param("index_id", "suggesting")
+            .with_query_param("q", "ze");
+        let response = service.handle_request(request).await.unwrap();
+
+        let texts = response
+            .suggestions
+            .iter()
+            .map(|suggestion| suggestion.text.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["zen", "zebra"], texts);
+    }
+}
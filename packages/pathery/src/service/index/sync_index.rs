@@ -0,0 +1,260 @@
+use std::collections::{HashMap, HashSet};
+
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::json;
+use crate::schema::{SchemaLoader, SchemaProvider};
+use crate::search_doc::SearchDocId;
+use crate::service::{ServiceError, ServiceHandler, ServiceRequest, ServiceResponse};
+use crate::store::change::{self, Change, ChangeStore, DDBChangeStore, CHANGE_TTL_DAYS};
+use crate::store::document::{DDBDocumentStore, DocumentStore, SearchDocRef};
+
+/// Log entries read per request when the request doesn't give a limit.
+const DEFAULT_LIMIT: usize = 10;
+
+/// Most log entries read per request, each holding up to 1000 changes.
+const MAX_LIMIT: usize = 100;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum SyncChange {
+    /// The document was indexed. `document` is its latest stored version, omitted for search
+    /// only indexes.
+    Index {
+        #[serde(rename = "__id")]
+        doc_id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        document: Option<json::Value>,
+    },
+    Delete {
+        #[serde(rename = "__id")]
+        doc_id: String,
+    },
+    /// The index was deleted. Every document synced before it is gone.
+    DeleteIndex,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct SyncResponse {
+    pub changes: Vec<SyncChange>,
+
+    /// Token to pass as `since_token` for the changes after these. `None` while the index has no
+    /// changes yet.
+    pub next_token: Option<String>,
+
+    /// Whether more changes were committed after `next_token`, so the caller can fetch them right
+    /// away instead of waiting to poll.
+    pub has_more: bool,
+}
+
+/// Changes committed to an index since a token from an earlier sync, for keeping caches and
+/// materialized views in step with the index without re-exporting it.
+pub struct SyncIndexService {
+    schema_loader: Box<dyn SchemaLoader>,
+
+    change_store: Box<dyn ChangeStore>,
+
+    document_store: Box<dyn DocumentStore>,
+}
+
+/// The latest change to each document in `changes`, in the order of those latest changes. Changes
+/// before an index deletion are dropped, since the deletion supersedes them.
+fn compact_changes(changes: Vec<Change>) -> Vec<Change> {
+    let mut seen = HashSet::new();
+    let mut compacted = vec![];
+
+    for change in changes.into_iter().rev() {
+        match &change {
+            Change::Index { doc_id } | Change::Delete { doc_id } => {
+                if seen.insert(doc_id.clone()) {
+                    compacted.push(change);
+                }
+            }
+            Change::DeleteIndex => {
+                compacted.push(change);
+                break;
+            }
+        }
+    }
+
+    compacted.reverse();
+    compacted
+}
+
+#[async_trait]
+impl ServiceHandler<json::Value, SyncResponse> for SyncIndexService {
+    async fn handle_request(
+        &self,
+        request: ServiceRequest<json::Value>,
+    ) -> ServiceResponse<SyncResponse> {
+        let index_id = request.index_id()?;
+        let settings = self.schema_loader.load_settings(&index_id)?;
+
+        let since_token = request.query_param("since_token");
+        if let Some(token) = &since_token {
+            let committed_at = change::token_time(token).ok_or_else(|| {
+                ServiceError::invalid_request(&format!("Invalid since_token [{}]", token))
+            })?;
+            if committed_at < Utc::now() - Duration::days(CHANGE_TTL_DAYS) {
+                return Err(ServiceError::invalid_request(&format!(
+                    "since_token [{}] is older than the {} days of changes kept, sync again \
+                     without it",
+                    token, CHANGE_TTL_DAYS
+                )));
+            }
+        }
+
+        let limit = request
+            .query_param("limit")
+            .map(|limit| match limit.parse::<usize>() {
+                Ok(limit) if (1..=MAX_LIMIT).contains(&limit) => Ok(limit),
+                _ => Err(ServiceError::invalid_request(&format!(
+                    "Expected limit between 1 and {}, got [{}]",
+                    MAX_LIMIT, limit
+                ))),
+            })
+            .transpose()?
+            .unwrap_or(DEFAULT_LIMIT);
+
+        let entries = self
+            .change_store
+            .list_changes(&index_id, since_token.as_deref(), limit)
+            .await?;
+
+        let has_more = entries.len() == limit;
+        let next_token = entries
+            .last()
+            .map(|entry| entry.token.clone())
+            .or(since_token);
+
+        let changes = compact_changes(
+            entries
+                .into_iter()
+                .flat_map(|entry| entry.changes)
+                .collect(),
+        );
+
+        let mut documents: HashMap<String, json::Value> = HashMap::new();
+        if !settings.search_only {
+            let doc_refs = changes
+                .iter()
+                .filter_map(|change| match change {
+                    Change::Index { doc_id } => {
+                        Some(SearchDocRef::from(SearchDocId::parse(doc_id)))
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+
+            if !doc_refs.is_empty() {
+                for doc in self.document_store.get_documents(doc_refs).await? {
+                    documents.insert(
+                        doc.id().id().to_string(),
+                        json::Value::Object(doc.content().clone()),
+                    );
+                }
+            }
+        }
+
+        let changes = changes
+            .into_iter()
+            .map(|change| match change {
+                Change::Index { doc_id } => SyncChange::Index {
+                    document: documents.remove(&doc_id),
+                    doc_id,
+                },
+                Change::Delete { doc_id } => SyncChange::Delete { doc_id },
+                Change::DeleteIndex => SyncChange::DeleteIndex,
+            })
+            .collect();
+
+        Ok(SyncResponse {
+            changes,
+            next_token,
+            has_more,
+        })
+    }
+}
+
+impl SyncIndexService {
+    pub async fn create() -> Self {
+        SyncIndexService {
+            schema_loader: Box::new(SchemaProvider::lambda().await),
+            change_store: Box::new(DDBChangeStore::create(None).await),
+            document_store: Box::new(DDBDocumentStore::create(None).await),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use crate::worker::index_writer::client::IndexWriterClient;
+    use crate::worker::index_writer::job::Job;
+
+    #[tokio::test]
+    async fn sync_returns_changes_since_the_token() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![
+                    json!({ "__id": "a", "title": "Hello" }),
+                    json!({ "__id": "b", "title": "World" }),
+                ],
+            )
+            .await;
+
+        let service = SyncIndexService {
+            schema_loader: Box::new(ctx.schema_loader().clone()),
+            change_store: Box::new(ctx.change_store().clone()),
+            document_store: Box::new(ctx.document_store().clone()),
+        };
+
+        let sync = |since_token: Option<&str>| {
+            let mut request = ServiceRequest::create(json!({})).with_path_param("index_id", "test");
+            if let Some(since_token) = since_token {
+                request = request.with_query_param("since_token", since_token);
+            }
+            service.handle_request(request)
+        };
+
+        let response = sync(None).await.unwrap();
+        assert_eq!(
+            vec![
+                SyncChange::Index {
+                    doc_id: "a".into(),
+                    document: Some(json!({ "__id": "a", "title": "Hello" })),
+                },
+                SyncChange::Index {
+                    doc_id: "b".into(),
+                    document: Some(json!({ "__id": "b", "title": "World" })),
+                },
+            ],
+            response.changes
+        );
+        let token = response.next_token.unwrap();
+
+        let mut job = Job::create("test");
+        job.delete_doc(SearchDocId::parse("a"));
+        ctx.writer_client().submit_job(job).await.unwrap();
+
+        let response = sync(Some(&token)).await.unwrap();
+        assert_eq!(
+            vec![SyncChange::Delete { doc_id: "a".into() }],
+            response.changes
+        );
+        assert!(!response.has_more);
+
+        // Polling again with the latest token has nothing new, and keeps the token.
+        let token = response.next_token.unwrap();
+        let response = sync(Some(&token)).await.unwrap();
+        assert!(response.changes.is_empty());
+        assert_eq!(Some(token), response.next_token);
+
+        let err = sync(Some("00000000000000000001.0000")).await.unwrap_err();
+        assert_eq!(400, err.status());
+    }
+}
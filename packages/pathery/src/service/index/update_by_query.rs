@@ -0,0 +1,212 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tantivy::collector::Count;
+
+use crate::index::{IndexLoader, LambdaIndexLoader};
+use crate::json;
+use crate::query::{self, Query};
+use crate::schema::{SchemaLoader, SchemaProvider};
+use crate::service::{ServiceError, ServiceHandler, ServiceRequest, ServiceResponse};
+use crate::store::job::{DDBJobStore, JobStatus, JobStore};
+use crate::worker::update_by_query::client::{LambdaUpdateByQueryClient, UpdateByQueryClient};
+use crate::worker::update_by_query::job::UpdateByQueryJob;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UpdateByQueryRequest {
+    #[serde(deserialize_with = "query::string_or_dsl")]
+    pub query: Query,
+
+    pub set: json::Map<String, json::Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct UpdateByQueryResponse {
+    /// The queued job, none for a `dry_run`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job_id: Option<String>,
+
+    /// Documents a `dry_run` would have updated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matched: Option<usize>,
+}
+
+/// Queues an update of every document matching a query, which the update by query worker
+/// applies in the background, reporting progress on the job status.
+pub struct UpdateByQueryService {
+    schema_loader: Box<dyn SchemaLoader>,
+
+    index_loader: Box<dyn IndexLoader>,
+
+    job_store: Box<dyn JobStore>,
+
+    update_client: Box<dyn UpdateByQueryClient>,
+}
+
+#[async_trait]
+impl ServiceHandler<UpdateByQueryRequest, UpdateByQueryResponse> for UpdateByQueryService {
+    async fn handle_request(
+        &self,
+        request: ServiceRequest<UpdateByQueryRequest>,
+    ) -> ServiceResponse<UpdateByQueryResponse> {
+        let index_id = request.index_id()?;
+        let dry_run = request.dry_run()?;
+        let body = request.body()?;
+
+        let settings = self.schema_loader.load_settings(&index_id)?;
+        if settings.search_only {
+            return Err(ServiceError::invalid_request(&format!(
+                "Index [{}] is search only, its documents aren't available to update",
+                index_id
+            )));
+        }
+        if body.set.is_empty() {
+            return Err(ServiceError::invalid_request("Expected fields to `set`"));
+        }
+        if body.set.contains_key("__id") {
+            return Err(ServiceError::invalid_request(
+                "Document ids can't be updated by query",
+            ));
+        }
+
+        // Rejects queries that don't compile before the job is queued.
+        let index = self.index_loader.load_index(&index_id, None)?;
+        let query = body.query.compile(&index, &settings)?;
+
+        if dry_run {
+            let searcher = index
+                .reader()
+                .map_err(ServiceError::internal_error)?
+                .searcher();
+            let matched = searcher
+                .search(query.as_ref(), &Count)
+                .map_err(ServiceError::internal_error)?;
+            return Ok(UpdateByQueryResponse {
+                job_id: None,
+                matched: Some(matched),
+            });
+        }
+
+        let job = UpdateByQueryJob::create(&index_id, body.query, body.set);
+        self.job_store
+            .create_job(JobStatus::pending(&job.job_id, &index_id))
+            .await?;
+        self.update_client.submit_job(&job).await?;
+
+        Ok(UpdateByQueryResponse {
+            job_id: Some(job.job_id),
+            matched: None,
+        })
+    }
+}
+
+impl UpdateByQueryService {
+    pub async fn create() -> Self {
+        UpdateByQueryService {
+            schema_loader: Box::new(SchemaProvider::lambda().await),
+            index_loader: Box::new(LambdaIndexLoader::create().await),
+            job_store: Box::new(DDBJobStore::create(None).await),
+            update_client: Box::new(LambdaUpdateByQueryClient::create(None).await),
+        }
+    }
+
+    pub fn new(
+        schema_loader: Box<dyn SchemaLoader>,
+        index_loader: Box<dyn IndexLoader>,
+        job_store: Box<dyn JobStore>,
+        update_client: Box<dyn UpdateByQueryClient>,
+    ) -> Self {
+        UpdateByQueryService {
+            schema_loader,
+            index_loader,
+            job_store,
+            update_client,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::job::JobState;
+    use crate::test_utils::*;
+    use crate::worker::update_by_query::client::test_util::TestUpdateByQueryClient;
+    use crate::worker::update_by_query::update_by_query;
+
+    #[tokio::test]
+    async fn update_by_query_sets_fields_on_matching_documents() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![
+                    json!({ "__id": "a", "title": "hello", "author": "ann" }),
+                    json!({ "__id": "b", "title": "hello", "author": "bob" }),
+                    json!({ "__id": "c", "title": "world", "author": "bob" }),
+                ],
+            )
+            .await;
+        let update_client = TestUpdateByQueryClient::default();
+        let service = UpdateByQueryService::new(
+            Box::new(ctx.schema_loader().clone()),
+            Box::new(ctx.index_loader().clone()),
+            Box::new(ctx.job_store().clone()),
+            Box::new(update_client.clone()),
+        );
+
+        let request = || {
+            ServiceRequest::create_raw(
+                &json!({
+                    "query": "title:hello",
+                    "set": { "author": "cat" }
+                })
+                .to_string(),
+            )
+            .with_path_param("index_id", "test")
+        };
+
+        let dry_run = service
+            .handle_request(request().with_query_param("dry_run", "true"))
+            .await
+            .unwrap();
+        assert_eq!((None, Some(2)), (dry_run.job_id, dry_run.matched));
+        assert!(update_client.jobs.lock().unwrap().is_empty());
+
+        let response = service.handle_request(request()).await.unwrap();
+
+        let job = update_client.jobs.lock().unwrap().pop().unwrap();
+        assert_eq!(response.job_id, Some(job.job_id.clone()));
+        let progress = update_by_query(
+            ctx.document_store(),
+            ctx.index_loader(),
+            ctx.schema_loader(),
+            ctx.job_store(),
+            ctx.writer_client(),
+            &job,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            (2, 2, 0),
+            (progress.total, progress.processed, progress.failed)
+        );
+
+        let index = ctx.index_loader().load_index("test", None).unwrap();
+        let settings = ctx.schema_loader().load_settings("test").unwrap();
+        let updated = Query::from("author:cat")
+            .compile(&index, &settings)
+            .unwrap();
+        let searcher = index.reader().unwrap().searcher();
+        assert_eq!(2, searcher.search(&updated, &Count).unwrap());
+
+        let status = ctx.job_store().get_job(&job.job_id).await.unwrap().unwrap();
+        assert_eq!(JobState::Pending, status.status);
+        assert_eq!(Some(progress), status.progress);
+
+        let request =
+            ServiceRequest::create_raw(&json!({ "query": "title:hello", "set": {} }).to_string())
+                .with_path_param("index_id", "test");
+        assert_eq!(
+            400,
+            service.handle_request(request).await.unwrap_err().status()
+        );
+    }
+}
@@ -0,0 +1,115 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::json;
+use crate::schema::{SchemaLoader, SchemaProvider};
+use crate::search_doc::{FieldError, SearchDoc};
+use crate::service::{ServiceHandler, ServiceRequest, ServiceResponse};
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct ValidateDocResponse {
+    pub valid: bool,
+
+    pub errors: Vec<FieldError>,
+
+    /// Fields that aren't in the schema and would not be indexed. Always empty for dynamic
+    /// indexes, which add fields as documents arrive.
+    pub ignored_fields: Vec<String>,
+}
+
+/// Checks a document against the index's schema without storing or indexing it.
+pub struct ValidateDocService {
+    schema_loader: Box<dyn SchemaLoader>,
+}
+
+#[async_trait]
+impl ServiceHandler<json::Value, ValidateDocResponse> for ValidateDocService {
+    async fn handle_request(
+        &self,
+        request: ServiceRequest<json::Value>,
+    ) -> ServiceResponse<ValidateDocResponse> {
+        let body = request.body()?;
+
+        let index_id = request.index_id()?;
+
+        let schema = self.schema_loader.load_schema(&index_id)?;
+        let settings = self.schema_loader.load_settings(&index_id)?;
+
+        let errors = SearchDoc::validate_fields(&schema, &body, &settings);
+
+        let ignored_fields = match (&body, settings.dynamic) {
+            (json::Value::Object(object), false) => object
+                .keys()
+                .filter(|name| schema.get_field(name).is_none())
+                .cloned()
+                .collect(),
+            _ => vec![],
+        };
+
+        Ok(ValidateDocResponse {
+            valid: errors.is_empty(),
+            errors,
+            ignored_fields,
+        })
+    }
+}
+
+impl ValidateDocService {
+    pub async fn create() -> Self {
+        ValidateDocService {
+            schema_loader: Box::new(SchemaProvider::lambda().await),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[tokio::test]
+    async fn validate_reports_every_invalid_field() {
+        let ctx = setup();
+
+        let service = ValidateDocService {
+            schema_loader: Box::new(ctx.schema_loader().clone()),
+        };
+
+        let validate = |doc: json::Value| {
+            let request = ServiceRequest::create(doc).with_path_param("index_id", "test");
+            let service = &service;
+            async move { service.handle_request(request).await.unwrap() }
+        };
+
+        let response = validate(json!({
+            "title": "Zen and the Art of Motorcycle Maintenance",
+            "year": "nineteen seventy four",
+            "client_ip": "not an ip",
+            "publisher": "Morrow"
+        }))
+        .await;
+
+        assert!(!response.valid);
+        assert_eq!(
+            vec![Some("client_ip"), Some("year")],
+            response
+                .errors
+                .iter()
+                .map(|err| err.field.as_deref())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(vec![String::from("publisher")], response.ignored_fields);
+
+        let response = validate(json!({ "title": "Zen", "year": "1974" })).await;
+        assert!(response.valid);
+
+        let response = validate(json!({ "publisher": "Morrow" })).await;
+        assert_eq!(
+            vec![FieldError {
+                field: None,
+                message: "cannot index empty document".into()
+            }],
+            response.errors
+        );
+    }
+}
@@ -0,0 +1,89 @@
+use async_trait::async_trait;
+
+use super::{ServiceError, ServiceHandler, ServiceRequest, ServiceResponse};
+use crate::store::job::{DDBJobStore, JobStatus, JobStore};
+use crate::{json, tenant};
+
+pub struct JobStatusService {
+    job_store: Box<dyn JobStore>,
+}
+
+#[async_trait]
+impl ServiceHandler<json::Value, JobStatus> for JobStatusService {
+    async fn handle_request(
+        &self,
+        request: ServiceRequest<json::Value>,
+    ) -> ServiceResponse<JobStatus> {
+        let job_id = request.path_param("job_id")?;
+        let tenant_id = request.tenant()?;
+
+        // Jobs of other tenants' indexes are reported as missing.
+        self.job_store
+            .get_job(&job_id)
+            .await?
+            .and_then(|mut job| {
+                job.index_id = tenant::visible_id(tenant_id.as_deref(), &job.index_id)?.into();
+                Some(job)
+            })
+            .ok_or_else(|| ServiceError::not_found(&format!("Job [{}] not found", job_id)))
+    }
+}
+
+impl JobStatusService {
+    pub async fn create() -> Self {
+        let job_store = DDBJobStore::create(None).await;
+
+        JobStatusService {
+            job_store: Box::new(job_store),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::job::JobState;
+    use crate::test_utils::*;
+    use crate::worker::index_writer::client::IndexWriterClient;
+    use crate::worker::index_writer::job::Job;
+
+    fn test_service(ctx: &TestContext) -> JobStatusService {
+        JobStatusService {
+            job_store: Box::new(ctx.job_store().clone()),
+        }
+    }
+
+    #[tokio::test]
+    async fn get_completed_job() {
+        let ctx = setup();
+
+        let job_id = ctx
+            .writer_client()
+            .submit_job(Job::create("test"))
+            .await
+            .unwrap();
+
+        let service = test_service(&ctx);
+
+        let request = ServiceRequest::create(json!({})).with_path_param("job_id", &job_id);
+
+        let response = service.handle_request(request).await.unwrap();
+
+        assert_eq!(job_id, response.job_id);
+        assert_eq!(JobState::Complete, response.status);
+        assert!(response.completed_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn get_missing_job() {
+        let ctx = setup();
+
+        let service = test_service(&ctx);
+
+        let request = ServiceRequest::create(json!({})).with_path_param("job_id", "missing");
+
+        let err = service.handle_request(request).await.unwrap_err();
+
+        assert_eq!(404, err.status());
+    }
+}
@@ -1,17 +1,31 @@
 use std::collections::HashMap;
 use std::error::Error;
+use std::io::BufRead;
 use std::marker::PhantomData;
 
 use async_trait::async_trait;
+use aws_lambda_events::apigw::ApiGatewayProxyRequestContext;
+use flate2::bufread::GzDecoder;
 use http::Response;
+use lambda_http::request::RequestContext;
 use lambda_http::{Body, RequestExt};
 use serde::{Deserialize, Serialize};
-use tracing::error;
+use tracing::{error, info_span, Instrument};
 
-use crate::util;
+use crate::lambda::http::{
+    extract_request_id, extract_trace_id, warning_header, with_request_id, with_trace_id,
+    with_warnings, Authorization, REQUEST_ID_HEADER, WARNING_HEADER,
+};
+use crate::{lambda, tenant, util};
 
 pub mod doc;
+pub mod graphql;
 pub mod index;
+pub mod job;
+
+/// Request header for a client supplied write token, ordering the request's writes against other
+/// writes of the same docs in place of the time they were submitted.
+pub const WRITE_TOKEN_HEADER: &str = "x-write-token";
 
 #[derive(thiserror::Error, Debug)]
 pub enum ServiceError {
@@ -26,6 +40,15 @@ pub enum ServiceError {
 
     #[error("{0}")]
     NotFound(String),
+
+    #[error("{0}")]
+    Unavailable(String),
+
+    #[error("{0}")]
+    Unauthorized(String),
+
+    #[error("{0}")]
+    Forbidden(String),
 }
 
 impl ServiceError {
@@ -55,6 +78,18 @@ impl ServiceError {
         ServiceError::RateLimit
     }
 
+    pub fn unavailable(message: &str) -> Self {
+        ServiceError::Unavailable(message.into())
+    }
+
+    pub fn unauthorized(message: &str) -> Self {
+        ServiceError::Unauthorized(message.into())
+    }
+
+    pub fn forbidden(message: &str) -> Self {
+        ServiceError::Forbidden(message.into())
+    }
+
     pub fn status(&self) -> u16 {
         use ServiceError::*;
         match self {
@@ -62,6 +97,9 @@ impl ServiceError {
             InternalError { .. } => 500,
             RateLimit => 429,
             NotFound(_) => 404,
+            Unavailable(_) => 503,
+            Unauthorized(_) => 401,
+            Forbidden(_) => 403,
         }
     }
 
@@ -72,6 +110,9 @@ impl ServiceError {
             InvalidRequest(message) => message,
             RateLimit => String::from("Too many requests"),
             NotFound(message) => message,
+            Unavailable(message) => message,
+            Unauthorized(message) => message,
+            Forbidden(message) => message,
         }
     }
 }
@@ -101,25 +142,137 @@ where B: for<'de> Deserialize<'de>
         }
     }
 
+    /// Useful for testing
+    pub fn create_raw(body: &str) -> ServiceRequest<B> {
+        let inner = http::Request::builder()
+            .body(lambda_http::Body::from(body))
+            .unwrap();
+
+        ServiceRequest {
+            inner,
+            body: PhantomData,
+        }
+    }
+
+    /// Useful for testing
+    pub fn create_binary(body: Vec<u8>) -> ServiceRequest<B> {
+        let inner = http::Request::builder()
+            .body(lambda_http::Body::from(body))
+            .unwrap();
+
+        ServiceRequest {
+            inner,
+            body: PhantomData,
+        }
+    }
+
+    /// Useful for testing
+    pub fn with_header(mut self, name: &'static str, value: &str) -> Self {
+        self.inner
+            .headers_mut()
+            .insert(name, value.parse().unwrap());
+
+        self
+    }
+
     /// Useful for testing
     pub fn with_path_param(mut self, name: &str, value: &str) -> Self {
-        let updated = self
+        let mut params: HashMap<String, String> = self
+            .inner
+            .path_parameters()
+            .iter()
+            .map(|(name, value)| (String::from(name), String::from(value)))
+            .collect();
+
+        params.insert(String::from(name), String::from(value));
+
+        self.inner = self.inner.with_path_parameters(params);
+
+        self
+    }
+
+    /// Useful for testing
+    pub fn with_tenant(mut self, tenant_id: &str) -> Self {
+        let mut context = ApiGatewayProxyRequestContext::default();
+        context
+            .authorizer
+            .insert(tenant::TENANT_CLAIM.into(), tenant_id.into());
+
+        self.inner = self
+            .inner
+            .with_request_context(RequestContext::ApiGatewayV1(context));
+
+        self
+    }
+
+    /// Useful for testing
+    pub fn with_query_param(mut self, name: &str, value: &str) -> Self {
+        let mut params: HashMap<String, String> = self
             .inner
-            .with_path_parameters(HashMap::from([(String::from(name), String::from(value))]));
+            .query_string_parameters()
+            .iter()
+            .map(|(name, value)| (String::from(name), String::from(value)))
+            .collect();
 
-        self.inner = updated;
+        params.insert(String::from(name), String::from(value));
+
+        self.inner = self.inner.with_query_string_parameters(params);
 
         self
     }
 
     pub fn body(&self) -> Result<B, ServiceError> {
-        if let Body::Text(body) = self.inner.body() {
-            Ok(serde_json::from_str(body).map_err(|err| {
-                ServiceError::InvalidRequest(format!("Unable to parse body: {}", err.to_string()))
-            })?)
+        if self.is_gzip()? {
+            return serde_json::from_reader(self.body_reader()?).map_err(|err| {
+                ServiceError::InvalidRequest(format!("Unable to parse body: {}", err))
+            });
+        }
+
+        let body = match self.inner.body() {
+            Body::Text(body) => body.as_bytes(),
+            // API Gateway passes bodies through as binary, so that gzip bodies arrive intact.
+            Body::Binary(body) => body,
+            Body::Empty => {
+                return Err(ServiceError::InvalidRequest(String::from(
+                    "Expected string for body",
+                )))
+            }
+        };
+
+        serde_json::from_slice(body)
+            .map_err(|err| ServiceError::InvalidRequest(format!("Unable to parse body: {}", err)))
+    }
+
+    /// The unparsed request body, for handlers that accept formats other than JSON. Bodies sent
+    /// with `Content-Encoding: gzip` are decompressed as they're read.
+    pub fn body_reader(&self) -> Result<Box<dyn BufRead + '_>, ServiceError> {
+        let bytes: &[u8] = match self.inner.body() {
+            Body::Text(body) => body.as_bytes(),
+            Body::Binary(body) => body,
+            Body::Empty => &[],
+        };
+
+        if self.is_gzip()? {
+            Ok(Box::new(std::io::BufReader::new(GzDecoder::new(bytes))))
+        } else {
+            Ok(Box::new(bytes))
+        }
+    }
+
+    fn is_gzip(&self) -> Result<bool, ServiceError> {
+        let encoding = match self.inner.headers().get(http::header::CONTENT_ENCODING) {
+            Some(encoding) => encoding.to_str().unwrap_or_default().trim(),
+            None => return Ok(false),
+        };
+
+        if encoding.eq_ignore_ascii_case("gzip") {
+            Ok(true)
+        } else if encoding.eq_ignore_ascii_case("identity") {
+            Ok(false)
         } else {
-            Err(ServiceError::InvalidRequest(String::from(
-                "Expected string for body",
+            Err(ServiceError::invalid_request(&format!(
+                "Unsupported Content-Encoding [{}], expected gzip",
+                encoding
             )))
         }
     }
@@ -128,13 +281,132 @@ where B: for<'de> Deserialize<'de>
         let path_params = self.inner.path_parameters();
         let value = path_params
             .first(name)
-            .expect(&format!("missing path param: {}", name));
+            .unwrap_or_else(|| panic!("missing path param: {}", name));
 
         Ok(String::from(value))
     }
+
+    /// Useful for testing
+    pub fn with_authorization(mut self, authorization: Authorization) -> Self {
+        self.inner.extensions_mut().insert(authorization);
+
+        self
+    }
+
+    /// The `index_id` path param, scoped to the request's tenant. Requests authorized with an API
+    /// key are refused unless the key has the handler's scope on the index.
+    pub fn index_id(&self) -> Result<String, ServiceError> {
+        self.authorize_index(&self.path_param("index_id")?)
+    }
+
+    /// `index_id`, as named in the request, scoped to the request's tenant. Requests authorized
+    /// with an API key are refused unless the key has the handler's scope on the index, so every
+    /// index a request acts on, not only the one in its path, should be resolved here.
+    pub fn authorize_index(&self, index_id: &str) -> Result<String, ServiceError> {
+        if let Some(authorization) = self.authorization() {
+            authorization.check_index(index_id)?;
+        }
+
+        Ok(tenant::scope(self.tenant()?.as_deref(), index_id))
+    }
+
+    /// The API key the request was authorized with, if any.
+    pub fn authorization(&self) -> Option<&Authorization> {
+        self.inner.extensions().get()
+    }
+
+    /// The tenant the request is made on behalf of: the `tenant_id` of the API key it was
+    /// authorized with, the authorizer's `tenant_id` claim, or the tenant of its API Gateway key.
+    pub fn tenant(&self) -> Result<Option<String>, ServiceError> {
+        let key_tenant = self
+            .inner
+            .extensions()
+            .get::<Authorization>()
+            .and_then(|authorization| authorization.api_key.tenant_id.clone());
+
+        let tenant_id = key_tenant.or_else(|| {
+            let Some(RequestContext::ApiGatewayV1(context)) = self.inner.extensions().get() else {
+                return None;
+            };
+
+            // Lambda authorizers return claims as context, Cognito authorizers under `claims`.
+            let claim = context
+                .authorizer
+                .get(tenant::TENANT_CLAIM)
+                .or_else(|| context.authorizer.get("claims")?.get(tenant::TENANT_CLAIM))
+                .and_then(|claim| claim.as_str())
+                .map(String::from);
+            claim.or_else(|| {
+                let api_key_id = context.identity.api_key_id.as_deref()?;
+                tenant::key_tenant(api_key_id)
+            })
+        });
+
+        match tenant_id {
+            Some(tenant_id) if !tenant::is_valid(&tenant_id) => Err(ServiceError::invalid_request(
+                &format!("Invalid tenant id [{}]", tenant_id),
+            )),
+            tenant_id => Ok(tenant_id),
+        }
+    }
+
+    /// Who made the request, for reports of how indexes are used: the name of the API key it was
+    /// authorized with, or else its user agent.
+    pub fn client(&self) -> String {
+        if let Some(Authorization { api_key, .. }) = self.inner.extensions().get() {
+            return api_key.name.clone();
+        }
+        String::from(self.header("user-agent").unwrap_or("unknown"))
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.inner
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+    }
+
+    pub fn query_param(&self, name: &str) -> Option<String> {
+        self.inner
+            .query_string_parameters()
+            .first(name)
+            .map(String::from)
+    }
+
+    /// Whether the request asks with `dry_run=true` for a report of what it would change, without
+    /// changing anything.
+    pub fn dry_run(&self) -> Result<bool, ServiceError> {
+        match self.query_param("dry_run").as_deref() {
+            None | Some("false") => Ok(false),
+            Some("true") => Ok(true),
+            Some(dry_run) => Err(ServiceError::invalid_request(&format!(
+                "Unknown dry_run [{}], expected true or false",
+                dry_run
+            ))),
+        }
+    }
+
+    /// The client supplied write token in the [`WRITE_TOKEN_HEADER`] header, if any.
+    pub fn write_token(&self) -> Result<Option<u64>, ServiceError> {
+        let Some(token) = self.inner.headers().get(WRITE_TOKEN_HEADER) else {
+            return Ok(None);
+        };
+
+        token
+            .to_str()
+            .ok()
+            .and_then(|token| token.parse().ok())
+            .map(Some)
+            .ok_or_else(|| {
+                ServiceError::invalid_request(&format!(
+                    "{} should be a non-negative integer",
+                    WRITE_TOKEN_HEADER
+                ))
+            })
+    }
 }
 
-fn map_error_response(
+pub(crate) fn map_error_response(
     error: ServiceError,
 ) -> Result<lambda_http::Response<lambda_http::Body>, lambda_http::Error> {
     let status = error.status();
@@ -170,14 +442,41 @@ where
         &self,
         event: lambda_http::Request,
     ) -> Result<lambda_http::Response<lambda_http::Body>, lambda_http::Error> {
+        let request_id = extract_request_id(&event);
+        let trace_id = extract_trace_id(&event);
+        let span = info_span!(
+            "request",
+            request_id = request_id.as_str(),
+            trace_id = trace_id.as_deref()
+        );
+
         let request = ServiceRequest {
             inner: event,
             body: PhantomData,
         };
 
-        self.handle_request(request)
-            .await
-            .map_or_else(map_error_response, map_success_response)
+        let (response, warnings) = with_request_id(
+            request_id.clone(),
+            with_trace_id(
+                trace_id,
+                with_warnings(self.handle_request(request).instrument(span.clone())),
+            ),
+        )
+        .await;
+
+        let mut response =
+            span.in_scope(|| response.map_or_else(map_error_response, map_success_response))?;
+
+        response
+            .headers_mut()
+            .insert(REQUEST_ID_HEADER, request_id.parse()?);
+        for warning in warnings {
+            if let Ok(value) = warning_header(&warning).parse() {
+                response.headers_mut().append(WARNING_HEADER, value);
+            }
+        }
+
+        Ok(response)
     }
 
     async fn handle_request(&self, request: ServiceRequest<B>) -> ServiceResponse<R>;
@@ -190,12 +489,7 @@ where
     B: for<'de> Deserialize<'de> + Send,
     R: Serialize,
 {
-    tracing_subscriber::fmt()
-        .json()
-        .with_max_level(tracing::Level::INFO)
-        .with_target(false)
-        .without_time()
-        .init();
+    lambda::init_tracing();
 
     lambda_http::run(lambda_http::service_fn(|event| async {
         service.handle_event(event).await
@@ -204,3 +498,130 @@ where
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json;
+    use crate::lambda::http::TRACE_ID_HEADER;
+    use crate::worker::index_writer::job::Job;
+
+    struct RequestIdService;
+
+    #[async_trait]
+    impl ServiceHandler<json::Value, Option<String>> for RequestIdService {
+        async fn handle_request(
+            &self,
+            _request: ServiceRequest<json::Value>,
+        ) -> ServiceResponse<Option<String>> {
+            Ok(Job::create("test").request_id)
+        }
+    }
+
+    #[tokio::test]
+    async fn request_id_propagates_to_jobs_and_response() {
+        let event = http::Request::builder()
+            .header(REQUEST_ID_HEADER, "req-123")
+            .body(Body::Empty)
+            .unwrap();
+
+        let response = RequestIdService.handle_event(event).await.unwrap();
+
+        assert_eq!("req-123", response.headers()[REQUEST_ID_HEADER]);
+        assert_eq!(&Body::Text(String::from("\"req-123\"")), response.body());
+    }
+
+    struct TraceIdService;
+
+    #[async_trait]
+    impl ServiceHandler<json::Value, Option<String>> for TraceIdService {
+        async fn handle_request(
+            &self,
+            _request: ServiceRequest<json::Value>,
+        ) -> ServiceResponse<Option<String>> {
+            Ok(Job::create("test").trace_id)
+        }
+    }
+
+    #[tokio::test]
+    async fn trace_id_propagates_to_jobs() {
+        let trace_id = "Root=1-5759e988-bd862e3fe1be46a994272793;Sampled=1";
+        let event = http::Request::builder()
+            .header(TRACE_ID_HEADER, trace_id)
+            .body(Body::Empty)
+            .unwrap();
+
+        let response = TraceIdService.handle_event(event).await.unwrap();
+        assert_eq!(&Body::Text(json::to_string(trace_id).unwrap()), response.body());
+
+        let event = http::Request::builder().body(Body::Empty).unwrap();
+        let response = TraceIdService.handle_event(event).await.unwrap();
+        assert_eq!(&Body::Text(String::from("null")), response.body());
+    }
+
+    #[tokio::test]
+    async fn trace_id_prefers_invocation_and_drops_malformed_headers() {
+        let header = "Root=1-5759e988-bd862e3fe1be46a994272793;Sampled=1";
+        let invocation =
+            "Root=1-63f1e2a0-0123456789abcdef01234567;Parent=53995c3f42cd8ad8;Sampled=1";
+        let mut context = lambda_runtime::Context::default();
+        context.xray_trace_id = invocation.into();
+        let event = http::Request::builder()
+            .header(TRACE_ID_HEADER, header)
+            .extension(context)
+            .body(Body::Empty)
+            .unwrap();
+        let response = TraceIdService.handle_event(event).await.unwrap();
+        assert_eq!(
+            &Body::Text(json::to_string(invocation).unwrap()),
+            response.body()
+        );
+
+        for malformed in [
+            "not a trace",
+            "Root=1-5759e988-bd862e3fe1be46a99427279",
+            "Root=1-5759e988-bd862e3fe1be46a994272793;Sampled=yes",
+            "Parent=53995c3f42cd8ad8;Sampled=1",
+            "Root=1-5759e988-bd862e3fe1be46a994272793; Injected=1",
+        ] {
+            let event = http::Request::builder()
+                .header(TRACE_ID_HEADER, malformed)
+                .body(Body::Empty)
+                .unwrap();
+            let response = TraceIdService.handle_event(event).await.unwrap();
+            assert_eq!(
+                &Body::Text(String::from("null")),
+                response.body(),
+                "{}",
+                malformed
+            );
+        }
+    }
+
+    #[test]
+    fn gzip_body_is_decompressed() {
+        use std::io::Write;
+
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(br#"[{"title": "hello"}]"#).unwrap();
+        let body = encoder.finish().unwrap();
+
+        let request = ServiceRequest::<json::Value>::create_binary(body.clone())
+            .with_header("Content-Encoding", "gzip");
+        assert_eq!(json::json!([{ "title": "hello" }]), request.body().unwrap());
+
+        let request = ServiceRequest::<json::Value>::create_binary(body)
+            .with_header("Content-Encoding", "br");
+        assert_eq!(400, request.body().unwrap_err().status());
+    }
+
+    #[tokio::test]
+    async fn request_id_generated_when_missing() {
+        let event = http::Request::builder().body(Body::Empty).unwrap();
+
+        let response = RequestIdService.handle_event(event).await.unwrap();
+
+        assert!(!response.headers()[REQUEST_ID_HEADER].is_empty());
+        assert_eq!(None, Job::create("test").request_id);
+    }
+}
@@ -0,0 +1,85 @@
+use std::result::Result as StdResult;
+
+use async_trait::async_trait;
+use aws_sdk_s3 as s3;
+use s3::types::ByteStream;
+
+use crate::service::ServiceError;
+use crate::util;
+
+type Result<T> = StdResult<T, ServiceError>;
+
+/// Batches of search analytics records, for relevance tuning and audits outside the API.
+#[async_trait]
+pub trait AnalyticsStore: Send + Sync {
+    /// Saves `batch`, newline delimited JSON records, under `key`.
+    async fn put_batch(&self, key: &str, batch: Vec<u8>) -> Result<()>;
+}
+
+pub struct S3AnalyticsStore {
+    bucket_name: String,
+    client: s3::Client,
+}
+
+#[async_trait]
+impl AnalyticsStore for S3AnalyticsStore {
+    async fn put_batch(&self, key: &str, batch: Vec<u8>) -> Result<()> {
+        self.client
+            .put_object()
+            .bucket(&self.bucket_name)
+            .key(key)
+            .content_type("application/x-ndjson")
+            .body(ByteStream::from(batch))
+            .send()
+            .await?;
+
+        Ok(())
+    }
+}
+
+impl S3AnalyticsStore {
+    pub async fn create(bucket_name: Option<&str>) -> S3AnalyticsStore {
+        let bucket_name = bucket_name
+            .map(String::from)
+            .unwrap_or_else(|| util::require_env("DATA_BUCKET_NAME"));
+        let sdk_config = aws_config::load_from_env().await;
+        let client = s3::Client::new(&sdk_config);
+
+        S3AnalyticsStore {
+            bucket_name,
+            client,
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test_util {
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    #[derive(Clone, Debug, Default)]
+    pub struct TestAnalyticsStore {
+        batches: Arc<Mutex<BTreeMap<String, Vec<u8>>>>,
+    }
+
+    #[async_trait]
+    impl AnalyticsStore for TestAnalyticsStore {
+        async fn put_batch(&self, key: &str, batch: Vec<u8>) -> Result<()> {
+            self.batches.lock().unwrap().insert(key.into(), batch);
+            Ok(())
+        }
+    }
+
+    impl TestAnalyticsStore {
+        pub fn create() -> Self {
+            TestAnalyticsStore::default()
+        }
+
+        /// The saved batches, by key.
+        pub fn batches(&self) -> BTreeMap<String, Vec<u8>> {
+            self.batches.lock().unwrap().clone()
+        }
+    }
+}
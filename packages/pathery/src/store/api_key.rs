@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+use std::result::Result as StdResult;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use aws_sdk_dynamodb as ddb;
+use ring::digest;
+use serde::{Deserialize, Serialize};
+
+use crate::search_doc::DDBKey;
+use crate::service::ServiceError;
+use crate::util;
+
+type Result<T> = StdResult<T, ServiceError>;
+
+/// What an API key may do. `Admin` keys may do anything.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiKeyScope {
+    Read,
+    Write,
+    Admin,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ApiKey {
+    /// Names the key in logs, since the key itself isn't stored.
+    pub name: String,
+
+    /// Scopes the key has on every index.
+    #[serde(default)]
+    pub scopes: Vec<ApiKeyScope>,
+
+    /// Further scopes the key has on the indexes matching each grant.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub indexes: Vec<IndexGrant>,
+
+    /// The tenant requests made with the key act on behalf of, scoping their index ids.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant_id: Option<String>,
+}
+
+/// Scopes on the indexes whose ids match `pattern`: either an exact id, or a prefix followed by
+/// `*`, such as `orders-*`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct IndexGrant {
+    pub pattern: String,
+
+    pub scopes: Vec<ApiKeyScope>,
+}
+
+impl IndexGrant {
+    fn matches(&self, index_id: &str) -> bool {
+        match self.pattern.strip_suffix('*') {
+            Some(prefix) => index_id.starts_with(prefix),
+            None => index_id == self.pattern,
+        }
+    }
+}
+
+fn grants(scopes: &[ApiKeyScope], scope: ApiKeyScope) -> bool {
+    scopes
+        .iter()
+        .any(|granted| *granted == scope || *granted == ApiKeyScope::Admin)
+}
+
+impl ApiKey {
+    /// Whether the key has `scope` on every index.
+    pub fn allows(&self, scope: ApiKeyScope) -> bool {
+        grants(&self.scopes, scope)
+    }
+
+    /// Whether the key has `scope` on any index.
+    pub fn allows_any(&self, scope: ApiKeyScope) -> bool {
+        self.allows(scope) || self.indexes.iter().any(|grant| grants(&grant.scopes, scope))
+    }
+
+    /// Whether the key has `scope` on `index_id`.
+    pub fn allows_index(&self, scope: ApiKeyScope, index_id: &str) -> bool {
+        self.allows(scope)
+            || self
+                .indexes
+                .iter()
+                .any(|grant| grant.matches(index_id) && grants(&grant.scopes, scope))
+    }
+}
+
+/// Keys are stored by their SHA-256 digest, so that the table doesn't hold usable keys.
+fn api_key_key(key: &str) -> DDBKey {
+    let digest = digest::digest(&digest::SHA256, key.as_bytes());
+    let hash: String = digest
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+
+    DDBKey {
+        pk: format!("apikey|{}", hash),
+        sk: format!("apikey|{}", hash),
+    }
+}
+
+#[async_trait]
+pub trait ApiKeyStore: Send + Sync {
+    /// The key a request presented as `key`, if there is one.
+    async fn get_key(&self, key: &str) -> Result<Option<ApiKey>>;
+}
+
+pub struct DDBApiKeyStore {
+    table_name: String,
+    client: ddb::Client,
+}
+
+#[async_trait]
+impl ApiKeyStore for DDBApiKeyStore {
+    async fn get_key(&self, key: &str) -> Result<Option<ApiKey>> {
+        let response = self
+            .client
+            .get_item()
+            .table_name(&self.table_name)
+            .set_key(Some(serde_dynamo::to_item(api_key_key(key))?))
+            .send()
+            .await?;
+
+        Ok(response
+            .item()
+            .map(|item| serde_dynamo::from_item(item.clone()))
+            .transpose()?)
+    }
+}
+
+impl DDBApiKeyStore {
+    pub async fn create(table_name: Option<&str>) -> DDBApiKeyStore {
+        let table_name = table_name
+            .map(String::from)
+            .unwrap_or_else(|| util::require_env("DATA_TABLE_NAME"));
+        let sdk_config = aws_config::load_from_env().await;
+        let client = aws_sdk_dynamodb::Client::new(&sdk_config);
+
+        DDBApiKeyStore { table_name, client }
+    }
+}
+
+/// Holds API keys in memory, for the life of the store and its clones.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryApiKeyStore {
+    db: Arc<Mutex<HashMap<String, ApiKey>>>,
+}
+
+#[async_trait]
+impl ApiKeyStore for MemoryApiKeyStore {
+    async fn get_key(&self, key: &str) -> Result<Option<ApiKey>> {
+        let db = self.db.lock().unwrap();
+        Ok(db.get(&api_key_key(key).pk).cloned())
+    }
+}
+
+impl MemoryApiKeyStore {
+    pub fn create() -> Self {
+        MemoryApiKeyStore::default()
+    }
+
+    pub fn put_key(&self, key: &str, api_key: ApiKey) {
+        let mut db = self.db.lock().unwrap();
+        db.insert(api_key_key(key).pk, api_key);
+    }
+}
@@ -0,0 +1,294 @@
+use std::collections::{BTreeMap, HashMap};
+use std::result::Result as StdResult;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use aws_sdk_dynamodb as ddb;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use ddb::model::{AttributeValue, DeleteRequest, WriteRequest};
+use serde::{Deserialize, Serialize};
+
+use crate::search_doc::DDBKey;
+use crate::service::ServiceError;
+use crate::util;
+
+type Result<T> = StdResult<T, ServiceError>;
+
+/// How long change log entries are kept before DynamoDB expires them.
+pub const CHANGE_TTL_DAYS: i64 = 7;
+
+/// Most changes in one log entry, keeping entries well within DynamoDB's item size limit.
+const MAX_ENTRY_CHANGES: usize = 1000;
+
+/// Most keys in a DynamoDB batch write.
+const MAX_BATCH_WRITE: usize = 25;
+
+/// A change committed to an index.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Change {
+    Index {
+        doc_id: String,
+    },
+    Delete {
+        doc_id: String,
+    },
+    /// The index was deleted, along with every document in it.
+    DeleteIndex,
+}
+
+/// Changes committed together, identified by a token that sorts after those of earlier entries.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChangeEntry {
+    pub token: String,
+    pub changes: Vec<Change>,
+}
+
+/// Tokens for the entries of a commit made at `committed_at`: its time in microseconds, then the
+/// entry's position within the commit.
+fn entry_tokens(committed_at: DateTime<Utc>, count: usize) -> impl Iterator<Item = String> {
+    let micros = committed_at.timestamp_micros();
+    (0..count).map(move |chunk| format!("{:020}.{:04}", micros, chunk))
+}
+
+/// When the entry with `token` was committed, or None if it isn't a change log token.
+pub fn token_time(token: &str) -> Option<DateTime<Utc>> {
+    let (micros, chunk) = token.split_once('.')?;
+    if micros.len() != 20 || chunk.len() != 4 || chunk.parse::<u16>().is_err() {
+        return None;
+    }
+    Utc.timestamp_micros(micros.parse().ok()?).single()
+}
+
+fn change_key(index_id: &str, token: &str) -> DDBKey {
+    DDBKey {
+        pk: format!("changes|{}", index_id),
+        sk: token.into(),
+    }
+}
+
+/// The log of changes committed to each index, for consumers to sync from.
+#[async_trait]
+pub trait ChangeStore: Send + Sync {
+    /// Appends the changes of one commit to `index_id`'s log.
+    async fn append_changes(&self, index_id: &str, changes: Vec<Change>) -> Result<()>;
+
+    /// Up to `limit` entries of `index_id`'s log after the entry with `since_token`, or from the
+    /// oldest kept entry without one, oldest first.
+    async fn list_changes(
+        &self,
+        index_id: &str,
+        since_token: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<ChangeEntry>>;
+
+    /// Deletes every entry of `index_id`'s log.
+    async fn delete_changes(&self, index_id: &str) -> Result<()>;
+}
+
+pub struct DDBChangeStore {
+    table_name: String,
+    client: ddb::Client,
+}
+
+#[async_trait]
+impl ChangeStore for DDBChangeStore {
+    async fn append_changes(&self, index_id: &str, changes: Vec<Change>) -> Result<()> {
+        let now = Utc::now();
+        let expires_at = now + Duration::days(CHANGE_TTL_DAYS);
+        let chunks = changes.chunks(MAX_ENTRY_CHANGES);
+
+        for (token, chunk) in entry_tokens(now, chunks.len()).zip(chunks) {
+            let mut item: HashMap<String, AttributeValue> =
+                serde_dynamo::to_item(change_key(index_id, &token))?;
+            item.insert(
+                String::from("changes"),
+                serde_dynamo::to_attribute_value(chunk)?,
+            );
+            item.insert(
+                String::from("__ttl"),
+                AttributeValue::N(expires_at.timestamp().to_string()),
+            );
+
+            self.client
+                .put_item()
+                .table_name(&self.table_name)
+                .set_item(Some(item))
+                .send()
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn list_changes(
+        &self,
+        index_id: &str,
+        since_token: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<ChangeEntry>> {
+        let key = change_key(index_id, since_token.unwrap_or_default());
+
+        let mut query = self
+            .client
+            .query()
+            .table_name(&self.table_name)
+            .expression_attribute_values(":pk", AttributeValue::S(key.pk))
+            .limit(limit as i32)
+            .consistent_read(true);
+
+        query = match since_token {
+            Some(_) => query
+                .key_condition_expression("pk = :pk AND sk > :sk")
+                .expression_attribute_values(":sk", AttributeValue::S(key.sk)),
+            None => query.key_condition_expression("pk = :pk"),
+        };
+
+        let output = query.send().await?;
+
+        let mut entries = vec![];
+        for item in output.items().unwrap_or_default() {
+            let token = item
+                .get("sk")
+                .and_then(|sk| sk.as_s().ok())
+                .cloned()
+                .unwrap_or_default();
+            let changes = match item.get("changes") {
+                Some(changes) => serde_dynamo::from_attribute_value(changes.clone())?,
+                None => vec![],
+            };
+            entries.push(ChangeEntry { token, changes });
+        }
+
+        Ok(entries)
+    }
+
+    async fn delete_changes(&self, index_id: &str) -> Result<()> {
+        let pk = change_key(index_id, "").pk;
+
+        // Each query reads the entries left after the last round of deletes.
+        loop {
+            let output = self
+                .client
+                .query()
+                .table_name(&self.table_name)
+                .key_condition_expression("pk = :pk")
+                .expression_attribute_values(":pk", AttributeValue::S(pk.clone()))
+                .projection_expression("pk, sk")
+                .consistent_read(true)
+                .send()
+                .await?;
+
+            let keys = output.items().unwrap_or_default();
+            if keys.is_empty() {
+                return Ok(());
+            }
+
+            for chunk in keys.chunks(MAX_BATCH_WRITE) {
+                let writes = chunk
+                    .iter()
+                    .map(|key| {
+                        let delete_request =
+                            DeleteRequest::builder().set_key(Some(key.clone())).build();
+                        WriteRequest::builder()
+                            .delete_request(delete_request)
+                            .build()
+                    })
+                    .collect();
+
+                let response = self
+                    .client
+                    .batch_write_item()
+                    .request_items(&self.table_name, writes)
+                    .send()
+                    .await?;
+
+                if let Some(items) = response.unprocessed_items() {
+                    if items.values().any(|writes| !writes.is_empty()) {
+                        return Err(ServiceError::rate_limit());
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl DDBChangeStore {
+    pub async fn create(table_name: Option<&str>) -> DDBChangeStore {
+        let table_name = table_name
+            .map(String::from)
+            .unwrap_or_else(|| util::require_env("DATA_TABLE_NAME"));
+        let sdk_config = aws_config::load_from_env().await;
+        let client = aws_sdk_dynamodb::Client::new(&sdk_config);
+
+        DDBChangeStore { table_name, client }
+    }
+}
+
+type ChangeLog = BTreeMap<String, Vec<Change>>;
+
+/// Holds change logs in memory, for the life of the store and its clones.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryChangeStore {
+    db: Arc<Mutex<HashMap<String, ChangeLog>>>,
+
+    last_commit: Arc<Mutex<Option<DateTime<Utc>>>>,
+}
+
+#[async_trait]
+impl ChangeStore for MemoryChangeStore {
+    async fn append_changes(&self, index_id: &str, changes: Vec<Change>) -> Result<()> {
+        // Commits in quick succession still get increasing tokens.
+        let mut last_commit = self.last_commit.lock().unwrap();
+        let mut now = Utc::now();
+        if let Some(last) = *last_commit {
+            now = now.max(last + Duration::microseconds(1));
+        }
+        *last_commit = Some(now);
+
+        let mut db = self.db.lock().unwrap();
+        let log = db.entry(index_id.into()).or_default();
+        let chunks = changes.chunks(MAX_ENTRY_CHANGES);
+        for (token, chunk) in entry_tokens(now, chunks.len()).zip(chunks) {
+            log.insert(token, chunk.to_vec());
+        }
+        Ok(())
+    }
+
+    async fn list_changes(
+        &self,
+        index_id: &str,
+        since_token: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<ChangeEntry>> {
+        let db = self.db.lock().unwrap();
+        let entries = db
+            .get(index_id)
+            .into_iter()
+            .flatten()
+            .filter(|(token, _)| since_token.is_none_or(|since| token.as_str() > since))
+            .take(limit)
+            .map(|(token, changes)| ChangeEntry {
+                token: token.clone(),
+                changes: changes.clone(),
+            })
+            .collect();
+        Ok(entries)
+    }
+
+    async fn delete_changes(&self, index_id: &str) -> Result<()> {
+        self.db.lock().unwrap().remove(index_id);
+        Ok(())
+    }
+}
+
+impl MemoryChangeStore {
+    pub fn create() -> Self {
+        MemoryChangeStore::default()
+    }
+}
+
+#[cfg(test)]
+pub mod test_util {
+    pub use super::MemoryChangeStore as TestChangeStore;
+}
@@ -1,10 +1,11 @@
 use std::collections::HashMap;
 use std::error::Error;
 use std::result::Result as StdResult;
+use std::sync::{Arc, Mutex};
 
 use async_trait::async_trait;
 use aws_sdk_dynamodb as ddb;
-use ddb::model::{AttributeValue, KeysAndAttributes, PutRequest, WriteRequest};
+use ddb::model::{AttributeValue, DeleteRequest, KeysAndAttributes, PutRequest, WriteRequest};
 use ddb::types::SdkError;
 use serde::{Deserialize, Serialize};
 use tantivy::schema::NamedFieldDocument;
@@ -47,13 +48,28 @@ impl From<NamedFieldDocument> for SearchDocRef {
     }
 }
 
+impl SearchDocRef {
+    pub fn id(&self) -> &str {
+        self.0.id()
+    }
+}
+
+impl From<SearchDocId> for SearchDocRef {
+    fn from(id: SearchDocId) -> Self {
+        SearchDocRef(id)
+    }
+}
+
 #[async_trait]
 pub trait DocumentStore: Send + Sync {
-    /// Get documents by reference.
+    /// Get documents by reference. References without a stored document are omitted.
     async fn get_documents(&self, refs: Vec<SearchDocRef>) -> Result<Vec<SearchDoc>>;
 
     /// Save a document such that it can be retrieved with get_documents.
     async fn save_documents(&self, documents: Vec<SearchDoc>) -> Result<Vec<SearchDocRef>>;
+
+    /// Delete documents by reference. References without a stored document are ignored.
+    async fn delete_documents(&self, refs: Vec<SearchDocRef>) -> Result<()>;
 }
 
 pub struct DDBDocumentStore {
@@ -79,21 +95,21 @@ impl DocumentStore for DDBDocumentStore {
 
         let documents = response
             .responses()
-            .expect("responses should be present")
-            .values()
+            .into_iter()
+            .flat_map(HashMap::values)
             .flatten()
             .map(|item| serde_dynamo::from_item(item.clone()))
             .collect::<StdResult<Vec<SearchDoc>, _>>()?;
 
         let unprocessed_ids = response
             .unprocessed_keys()
-            .expect("unprocessed keys should be present")
-            .values()
+            .into_iter()
+            .flat_map(HashMap::values)
             .filter_map(KeysAndAttributes::keys)
             .flatten()
             .collect::<Vec<_>>();
 
-        if unprocessed_ids.len() > 0 {
+        if !unprocessed_ids.is_empty() {
             return Err(ServiceError::rate_limit());
         }
 
@@ -131,7 +147,7 @@ impl DocumentStore for DDBDocumentStore {
 
         if let Some(items) = response.unprocessed_items() {
             let unhandled_writes = items.values().flatten().collect::<Vec<_>>();
-            if unhandled_writes.len() > 0 {
+            if !unhandled_writes.is_empty() {
                 return Err(ServiceError::rate_limit());
             }
         };
@@ -141,6 +157,37 @@ impl DocumentStore for DDBDocumentStore {
             .map(|doc| SearchDocRef(doc.id().clone()))
             .collect())
     }
+
+    async fn delete_documents(&self, refs: Vec<SearchDocRef>) -> Result<()> {
+        for chunk in refs.chunks(25) {
+            let mut writes = vec![];
+
+            for doc_ref in chunk {
+                let key = serde_dynamo::to_item(DDBKey::from(doc_ref.0.clone()))?;
+                let delete_request = DeleteRequest::builder().set_key(Some(key)).build();
+                writes.push(
+                    WriteRequest::builder()
+                        .delete_request(delete_request)
+                        .build(),
+                );
+            }
+
+            let response = self
+                .client
+                .batch_write_item()
+                .request_items(&self.table_name, writes)
+                .send()
+                .await?;
+
+            if let Some(items) = response.unprocessed_items() {
+                if items.values().any(|writes| !writes.is_empty()) {
+                    return Err(ServiceError::rate_limit());
+                }
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl DDBDocumentStore {
@@ -155,48 +202,56 @@ impl DDBDocumentStore {
     }
 }
 
-#[cfg(test)]
-pub mod test_util {
-    use std::collections::HashMap;
-    use std::sync::{Arc, Mutex};
+/// Holds documents in memory, for the life of the store and its clones.
+#[derive(Clone, Debug)]
+pub struct MemoryDocumentStore {
+    db: Arc<Mutex<HashMap<SearchDocId, SearchDoc>>>,
+}
+
+#[async_trait]
+impl DocumentStore for MemoryDocumentStore {
+    async fn save_documents(&self, documents: Vec<SearchDoc>) -> Result<Vec<SearchDocRef>> {
+        let mut db = self.db.lock().unwrap();
 
-    use super::*;
+        for document in &documents {
+            (*db).insert(document.id().clone(), document.clone());
+        }
 
-    #[derive(Clone, Debug)]
-    pub struct TestDocumentStore {
-        db: Arc<Mutex<HashMap<SearchDocId, SearchDoc>>>,
+        Ok(documents
+            .iter()
+            .map(|x| SearchDocRef(x.id().clone()))
+            .collect())
     }
 
-    #[async_trait]
-    impl DocumentStore for TestDocumentStore {
-        async fn save_documents(&self, documents: Vec<SearchDoc>) -> Result<Vec<SearchDocRef>> {
-            let mut db = self.db.lock().unwrap();
-
-            for document in &documents {
-                (*db).insert(document.id().clone(), document.clone());
-            }
+    async fn get_documents(&self, refs: Vec<SearchDocRef>) -> Result<Vec<SearchDoc>> {
+        let db = self.db.lock().unwrap();
 
-            Ok(documents
-                .iter()
-                .map(|x| SearchDocRef(x.id().clone()))
-                .collect())
-        }
+        Ok(refs
+            .iter()
+            .filter_map(|doc_ref| (*db).get(&doc_ref.0).cloned())
+            .collect())
+    }
 
-        async fn get_documents(&self, refs: Vec<SearchDocRef>) -> Result<Vec<SearchDoc>> {
-            let db = self.db.lock().unwrap();
+    async fn delete_documents(&self, refs: Vec<SearchDocRef>) -> Result<()> {
+        let mut db = self.db.lock().unwrap();
 
-            Ok(refs
-                .iter()
-                .map(|doc_ref| (*db).get(&doc_ref.0).unwrap().clone())
-                .collect())
+        for doc_ref in refs {
+            (*db).remove(&doc_ref.0);
         }
+
+        Ok(())
     }
+}
 
-    impl TestDocumentStore {
-        pub fn create() -> Self {
-            TestDocumentStore {
-                db: Arc::new(Mutex::new(HashMap::new())),
-            }
+impl MemoryDocumentStore {
+    pub fn create() -> Self {
+        MemoryDocumentStore {
+            db: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 }
+
+#[cfg(test)]
+pub mod test_util {
+    pub use super::MemoryDocumentStore as TestDocumentStore;
+}
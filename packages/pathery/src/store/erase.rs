@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use std::result::Result as StdResult;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use aws_sdk_dynamodb as ddb;
+use ddb::model::AttributeValue;
+use serde::{Deserialize, Serialize};
+
+use crate::search_doc::DDBKey;
+use crate::service::ServiceError;
+use crate::util;
+
+type Result<T> = StdResult<T, ServiceError>;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct IndexErasure {
+    pub index_id: String,
+
+    /// Documents matching the predicate, now deleted.
+    pub erased: usize,
+
+    /// Segments merged to purge the deleted documents from disk.
+    pub merged_segments: usize,
+
+    /// Files of the pre-erase segments deleted once nothing referred to them.
+    pub deleted_files: usize,
+}
+
+/// Record of a completed erasure, for compliance audits. It names the field erased by, but not
+/// the value, so that the report doesn't itself hold the data subject's identifier.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct EraseReport {
+    pub erase_id: String,
+    pub field: String,
+    pub started_at: String,
+    pub completed_at: String,
+    pub indexes: Vec<IndexErasure>,
+    /// Nothing was erased, `indexes` report what would have been.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub dry_run: bool,
+}
+
+fn erase_key(erase_id: &str) -> DDBKey {
+    DDBKey {
+        pk: format!("erase|{}", erase_id),
+        sk: format!("erase|{}", erase_id),
+    }
+}
+
+/// Reports of completed erasures, kept without expiry as evidence the erasures happened.
+#[async_trait]
+pub trait EraseStore: Send + Sync {
+    async fn put_report(&self, report: &EraseReport) -> Result<()>;
+
+    async fn get_report(&self, erase_id: &str) -> Result<Option<EraseReport>>;
+}
+
+pub struct DDBEraseStore {
+    table_name: String,
+    client: ddb::Client,
+}
+
+#[async_trait]
+impl EraseStore for DDBEraseStore {
+    async fn put_report(&self, report: &EraseReport) -> Result<()> {
+        let key: HashMap<String, AttributeValue> =
+            serde_dynamo::to_item(erase_key(&report.erase_id))?;
+
+        let mut item: HashMap<String, AttributeValue> = serde_dynamo::to_item(report)?;
+        item.extend(key);
+
+        self.client
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(item))
+            .send()
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_report(&self, erase_id: &str) -> Result<Option<EraseReport>> {
+        let response = self
+            .client
+            .get_item()
+            .table_name(&self.table_name)
+            .set_key(Some(serde_dynamo::to_item(erase_key(erase_id))?))
+            .send()
+            .await?;
+
+        Ok(response
+            .item()
+            .map(|item| serde_dynamo::from_item(item.clone()))
+            .transpose()?)
+    }
+}
+
+impl DDBEraseStore {
+    pub async fn create(table_name: Option<&str>) -> DDBEraseStore {
+        let table_name = table_name
+            .map(String::from)
+            .unwrap_or_else(|| util::require_env("DATA_TABLE_NAME"));
+        let sdk_config = aws_config::load_from_env().await;
+        let client = aws_sdk_dynamodb::Client::new(&sdk_config);
+
+        DDBEraseStore { table_name, client }
+    }
+}
+
+/// Holds erase reports in memory, for the life of the store and its clones.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryEraseStore {
+    db: Arc<Mutex<HashMap<String, EraseReport>>>,
+}
+
+#[async_trait]
+impl EraseStore for MemoryEraseStore {
+    async fn put_report(&self, report: &EraseReport) -> Result<()> {
+        let mut db = self.db.lock().unwrap();
+        db.insert(report.erase_id.clone(), report.clone());
+        Ok(())
+    }
+
+    async fn get_report(&self, erase_id: &str) -> Result<Option<EraseReport>> {
+        let db = self.db.lock().unwrap();
+        Ok(db.get(erase_id).cloned())
+    }
+}
+
+impl MemoryEraseStore {
+    pub fn create() -> Self {
+        MemoryEraseStore::default()
+    }
+}
@@ -0,0 +1,249 @@
+use std::collections::{BTreeMap, HashMap};
+use std::result::Result as StdResult;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use aws_sdk_dynamodb as ddb;
+use chrono::{DateTime, Duration, SecondsFormat, TimeZone, Utc};
+use ddb::model::AttributeValue;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::search_doc::DDBKey;
+use crate::service::ServiceError;
+use crate::{json, util};
+
+type Result<T> = StdResult<T, ServiceError>;
+
+/// How long timeline events are kept before DynamoDB expires them.
+pub const EVENT_TTL_DAYS: i64 = 30;
+
+/// Something that changed an index, or how it's searched.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum IndexEvent {
+    /// The index writer committed the jobs in `job_ids`.
+    Commit {
+        opstamp: u64,
+        job_ids: Vec<String>,
+        docs_indexed: usize,
+        docs_deleted: usize,
+        /// Searchable segments once the commit's merges finished.
+        segments: usize,
+    },
+    Merge {
+        merged_segments: usize,
+        purged_docs: u64,
+    },
+    Snapshot {
+        snapshot_id: String,
+        opstamp: u64,
+    },
+    Restore {
+        snapshot_id: String,
+        opstamp: u64,
+    },
+    /// Fields were derived for documents of a dynamic index.
+    SchemaExtended {
+        fields: Vec<String>,
+    },
+    /// The index writer found the index's settings changed since its last commit.
+    SettingsChanged {
+        settings: json::Value,
+    },
+    /// The index was deleted, along with every document in it.
+    Deleted,
+    /// The index writer gave up on a job that fails the same way every time it's applied.
+    JobFailed {
+        job_id: String,
+        error: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EventEntry {
+    /// When the event happened, as an RFC 3339 timestamp.
+    pub at: String,
+
+    #[serde(flatten)]
+    pub event: IndexEvent,
+}
+
+/// Sort keys order events by time, then by a random suffix so events recorded in the same
+/// microsecond by different workers don't overwrite each other.
+fn event_sk(at: DateTime<Utc>, suffix: &str) -> String {
+    format!("{:020}.{}", at.timestamp_micros(), suffix)
+}
+
+fn event_time(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+fn event_key(index_id: &str, sk: String) -> DDBKey {
+    DDBKey {
+        pk: format!("events|{}", index_id),
+        sk,
+    }
+}
+
+/// The timeline of what changed each index, for tracking down when its behavior changed.
+#[async_trait]
+pub trait EventStore: Send + Sync {
+    /// Records `event` as having happened to `index_id` now.
+    async fn append_event(&self, index_id: &str, event: IndexEvent) -> Result<()>;
+
+    /// Up to `limit` of `index_id`'s events between `from` and `to`, inclusive, oldest first.
+    async fn list_events(
+        &self,
+        index_id: &str,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        limit: usize,
+    ) -> Result<Vec<EventEntry>>;
+}
+
+/// Appends `event` to the timeline of `index_id`, logging rather than returning failures, since
+/// what the event records has already happened.
+pub async fn record(event_store: &dyn EventStore, index_id: &str, event: IndexEvent) {
+    if let Err(err) = event_store.append_event(index_id, event).await {
+        warn!(message = "event_record_failed", index = index_id, error = %err);
+    }
+}
+
+pub struct DDBEventStore {
+    table_name: String,
+    client: ddb::Client,
+}
+
+#[async_trait]
+impl EventStore for DDBEventStore {
+    async fn append_event(&self, index_id: &str, event: IndexEvent) -> Result<()> {
+        let now = Utc::now();
+        let expires_at = now + Duration::days(EVENT_TTL_DAYS);
+
+        let mut item: HashMap<String, AttributeValue> =
+            serde_dynamo::to_item(event_key(index_id, event_sk(now, &util::generate_id())))?;
+        item.insert(String::from("at"), AttributeValue::S(event_time(now)));
+        item.insert(
+            String::from("event"),
+            serde_dynamo::to_attribute_value(&event)?,
+        );
+        item.insert(
+            String::from("__ttl"),
+            AttributeValue::N(expires_at.timestamp().to_string()),
+        );
+
+        self.client
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(item))
+            .send()
+            .await?;
+
+        Ok(())
+    }
+
+    async fn list_events(
+        &self,
+        index_id: &str,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        limit: usize,
+    ) -> Result<Vec<EventEntry>> {
+        let from = event_sk(from.unwrap_or(DateTime::<Utc>::MIN_UTC), "");
+        // The suffix sorts after that of every event in the last microsecond.
+        let to = event_sk(to.unwrap_or(DateTime::<Utc>::MAX_UTC), "~");
+        let key = event_key(index_id, from);
+
+        let output = self
+            .client
+            .query()
+            .table_name(&self.table_name)
+            .key_condition_expression("pk = :pk AND sk BETWEEN :from AND :to")
+            .expression_attribute_values(":pk", AttributeValue::S(key.pk))
+            .expression_attribute_values(":from", AttributeValue::S(key.sk))
+            .expression_attribute_values(":to", AttributeValue::S(to))
+            .limit(limit as i32)
+            .send()
+            .await?;
+
+        let mut entries = vec![];
+        for item in output.items().unwrap_or_default() {
+            let (Some(at), Some(event)) = (item.get("at"), item.get("event")) else {
+                continue;
+            };
+            entries.push(EventEntry {
+                at: serde_dynamo::from_attribute_value(at.clone())?,
+                event: serde_dynamo::from_attribute_value(event.clone())?,
+            });
+        }
+
+        Ok(entries)
+    }
+}
+
+impl DDBEventStore {
+    pub async fn create(table_name: Option<&str>) -> DDBEventStore {
+        let table_name = table_name
+            .map(String::from)
+            .unwrap_or_else(|| util::require_env("DATA_TABLE_NAME"));
+        let sdk_config = aws_config::load_from_env().await;
+        let client = aws_sdk_dynamodb::Client::new(&sdk_config);
+
+        DDBEventStore { table_name, client }
+    }
+}
+
+type Timeline = BTreeMap<(i64, usize), IndexEvent>;
+
+/// Holds timelines in memory, for the life of the store and its clones.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryEventStore {
+    db: Arc<Mutex<HashMap<String, Timeline>>>,
+}
+
+#[async_trait]
+impl EventStore for MemoryEventStore {
+    async fn append_event(&self, index_id: &str, event: IndexEvent) -> Result<()> {
+        let mut db = self.db.lock().unwrap();
+        let timeline = db.entry(index_id.into()).or_default();
+        timeline.insert((Utc::now().timestamp_micros(), timeline.len()), event);
+        Ok(())
+    }
+
+    async fn list_events(
+        &self,
+        index_id: &str,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        limit: usize,
+    ) -> Result<Vec<EventEntry>> {
+        let from = from.map_or(i64::MIN, |from| from.timestamp_micros());
+        let to = to.map_or(i64::MAX, |to| to.timestamp_micros());
+
+        let db = self.db.lock().unwrap();
+        let entries = db
+            .get(index_id)
+            .into_iter()
+            .flatten()
+            .filter(|((micros, _), _)| (from..=to).contains(micros))
+            .take(limit)
+            .map(|((micros, _), event)| EventEntry {
+                at: event_time(Utc.timestamp_micros(*micros).unwrap()),
+                event: event.clone(),
+            })
+            .collect();
+        Ok(entries)
+    }
+}
+
+impl MemoryEventStore {
+    pub fn create() -> Self {
+        MemoryEventStore::default()
+    }
+}
+
+#[cfg(test)]
+pub mod test_util {
+    pub use super::MemoryEventStore as TestEventStore;
+}
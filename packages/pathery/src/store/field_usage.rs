@@ -0,0 +1,221 @@
+use std::collections::{BTreeMap, HashMap};
+use std::result::Result as StdResult;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use aws_sdk_dynamodb as ddb;
+use chrono::{Duration, Utc};
+use ddb::model::AttributeValue;
+use serde::{Deserialize, Serialize};
+
+use crate::search_doc::DDBKey;
+use crate::service::ServiceError;
+use crate::util;
+
+type Result<T> = StdResult<T, ServiceError>;
+
+/// How long a client's use of a field is remembered after it last used it.
+pub const FIELD_USAGE_TTL_DAYS: i64 = 30;
+
+/// What a request used a field for.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldUse {
+    Query,
+    Write,
+}
+
+impl FieldUse {
+    fn name(self) -> &'static str {
+        match self {
+            FieldUse::Query => "query",
+            FieldUse::Write => "write",
+        }
+    }
+}
+
+/// A client's use of a field of an index.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FieldUsage {
+    pub field: String,
+
+    /// Name of the API key the requests were made with, or else their user agent.
+    pub client: String,
+
+    pub operation: FieldUse,
+
+    /// Requests that used the field, counted until the client goes [`FIELD_USAGE_TTL_DAYS`]
+    /// days without using it.
+    pub count: u64,
+
+    /// When the field was last used, as an RFC 3339 timestamp.
+    pub last_used_at: String,
+}
+
+fn usage_key(index_id: &str, field: &str, operation: FieldUse, client: &str) -> DDBKey {
+    DDBKey {
+        pk: format!("fieldusage|{}", index_id),
+        sk: format!("{}|{}|{}", field, operation.name(), client),
+    }
+}
+
+/// Which clients use which fields of each index, for finding those still using a deprecated
+/// field before it's removed.
+#[async_trait]
+pub trait FieldUsageStore: Send + Sync {
+    /// Counts a use of `field` of `index_id` by `client`.
+    async fn record_use(
+        &self,
+        index_id: &str,
+        field: &str,
+        client: &str,
+        operation: FieldUse,
+    ) -> Result<()>;
+
+    /// The uses of `index_id`'s fields, sorted by field, operation and client.
+    async fn list_uses(&self, index_id: &str) -> Result<Vec<FieldUsage>>;
+}
+
+pub struct DDBFieldUsageStore {
+    table_name: String,
+    client: ddb::Client,
+}
+
+#[async_trait]
+impl FieldUsageStore for DDBFieldUsageStore {
+    async fn record_use(
+        &self,
+        index_id: &str,
+        field: &str,
+        client: &str,
+        operation: FieldUse,
+    ) -> Result<()> {
+        let expires_at = Utc::now() + Duration::days(FIELD_USAGE_TTL_DAYS);
+
+        self.client
+            .update_item()
+            .table_name(&self.table_name)
+            .set_key(Some(serde_dynamo::to_item(usage_key(
+                index_id, field, operation, client,
+            ))?))
+            .update_expression(
+                "ADD #count :one SET #field = :field, #client = :client, #operation = :operation, \
+                 last_used_at = :last_used_at, #ttl = :ttl",
+            )
+            .expression_attribute_names("#count", "count")
+            .expression_attribute_names("#field", "field")
+            .expression_attribute_names("#client", "client")
+            .expression_attribute_names("#operation", "operation")
+            .expression_attribute_names("#ttl", "__ttl")
+            .expression_attribute_values(":one", AttributeValue::N(String::from("1")))
+            .expression_attribute_values(":field", AttributeValue::S(field.into()))
+            .expression_attribute_values(":client", AttributeValue::S(client.into()))
+            .expression_attribute_values(":operation", serde_dynamo::to_attribute_value(operation)?)
+            .expression_attribute_values(":last_used_at", AttributeValue::S(util::timestamp()))
+            .expression_attribute_values(
+                ":ttl",
+                AttributeValue::N(expires_at.timestamp().to_string()),
+            )
+            .send()
+            .await?;
+
+        Ok(())
+    }
+
+    async fn list_uses(&self, index_id: &str) -> Result<Vec<FieldUsage>> {
+        let output = self
+            .client
+            .query()
+            .table_name(&self.table_name)
+            .key_condition_expression("pk = :pk")
+            .expression_attribute_values(
+                ":pk",
+                AttributeValue::S(format!("fieldusage|{}", index_id)),
+            )
+            .send()
+            .await?;
+
+        let mut uses = output
+            .items()
+            .unwrap_or_default()
+            .iter()
+            .map(|item| Ok(serde_dynamo::from_item(item.clone())?))
+            .collect::<Result<Vec<FieldUsage>>>()?;
+        uses.sort_by(|a, b| {
+            (&a.field, a.operation, &a.client).cmp(&(&b.field, b.operation, &b.client))
+        });
+
+        Ok(uses)
+    }
+}
+
+impl DDBFieldUsageStore {
+    pub async fn create(table_name: Option<&str>) -> DDBFieldUsageStore {
+        let table_name = table_name
+            .map(String::from)
+            .unwrap_or_else(|| util::require_env("DATA_TABLE_NAME"));
+        let sdk_config = aws_config::load_from_env().await;
+        let client = aws_sdk_dynamodb::Client::new(&sdk_config);
+
+        DDBFieldUsageStore { table_name, client }
+    }
+}
+
+type IndexUsage = BTreeMap<(String, FieldUse, String), (u64, String)>;
+
+/// Holds field usage in memory, for the life of the store and its clones.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryFieldUsageStore {
+    db: Arc<Mutex<HashMap<String, IndexUsage>>>,
+}
+
+#[async_trait]
+impl FieldUsageStore for MemoryFieldUsageStore {
+    async fn record_use(
+        &self,
+        index_id: &str,
+        field: &str,
+        client: &str,
+        operation: FieldUse,
+    ) -> Result<()> {
+        let mut db = self.db.lock().unwrap();
+        let usage = db
+            .entry(index_id.into())
+            .or_default()
+            .entry((field.into(), operation, client.into()))
+            .or_insert((0, String::new()));
+        usage.0 += 1;
+        usage.1 = util::timestamp();
+        Ok(())
+    }
+
+    async fn list_uses(&self, index_id: &str) -> Result<Vec<FieldUsage>> {
+        let db = self.db.lock().unwrap();
+        let uses = db
+            .get(index_id)
+            .into_iter()
+            .flatten()
+            .map(
+                |((field, operation, client), (count, last_used_at))| FieldUsage {
+                    field: field.clone(),
+                    client: client.clone(),
+                    operation: *operation,
+                    count: *count,
+                    last_used_at: last_used_at.clone(),
+                },
+            )
+            .collect();
+        Ok(uses)
+    }
+}
+
+impl MemoryFieldUsageStore {
+    pub fn create() -> Self {
+        MemoryFieldUsageStore::default()
+    }
+}
+
+#[cfg(test)]
+pub mod test_util {
+    pub use super::MemoryFieldUsageStore as TestFieldUsageStore;
+}
@@ -0,0 +1,320 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::path::Path;
+use std::result::Result as StdResult;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use aws_sdk_s3 as s3;
+use s3::types::{ByteStream, SdkError};
+
+use crate::service::ServiceError;
+use crate::util;
+
+type Result<T> = StdResult<T, ServiceError>;
+
+/// The files of an index, by their path in its directory, for a
+/// [`FileStoreDirectory`](crate::directory::FileStoreDirectory) to open the index from.
+#[async_trait]
+pub trait FileStore: Send + Sync + Debug {
+    /// The contents of `path`, or None when there's no such file.
+    async fn read_file(&self, path: &str) -> Result<Option<Vec<u8>>>;
+
+    /// Copies `path` to the local file `to`, returning false when there's no such file. Stores
+    /// that can should stream the file, since segment files can be larger than memory.
+    async fn download_file(&self, path: &str, to: &Path) -> Result<bool> {
+        match self.read_file(path).await? {
+            Some(contents) => {
+                tokio::fs::write(to, contents)
+                    .await
+                    .map_err(ServiceError::internal_error)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Saves `contents` as `path`, atomically replacing any file already there.
+    async fn write_file(&self, path: &str, contents: Vec<u8>) -> Result<()>;
+
+    /// Saves the local file `from` as `path`.
+    async fn upload_file(&self, path: &str, from: &Path) -> Result<()> {
+        let contents = tokio::fs::read(from)
+            .await
+            .map_err(ServiceError::internal_error)?;
+        self.write_file(path, contents).await
+    }
+
+    async fn delete_file(&self, path: &str) -> Result<()>;
+
+    async fn file_exists(&self, path: &str) -> Result<bool>;
+
+    /// Paths of every file under `prefix`, sorted.
+    async fn list_files(&self, prefix: &str) -> Result<Vec<String>>;
+}
+
+/// Keeps files as objects under a key prefix of a bucket.
+#[derive(Debug)]
+pub struct S3FileStore {
+    bucket_name: String,
+    prefix: String,
+    client: s3::Client,
+}
+
+#[async_trait]
+impl FileStore for S3FileStore {
+    async fn read_file(&self, path: &str) -> Result<Option<Vec<u8>>> {
+        let body = match self.get_object(path).await? {
+            Some(body) => body,
+            None => return Ok(None),
+        };
+        let bytes = body.collect().await.map_err(ServiceError::internal_error)?;
+
+        Ok(Some(bytes.into_bytes().to_vec()))
+    }
+
+    async fn download_file(&self, path: &str, to: &Path) -> Result<bool> {
+        let body = match self.get_object(path).await? {
+            Some(body) => body,
+            None => return Ok(false),
+        };
+
+        let mut file = tokio::fs::File::create(to)
+            .await
+            .map_err(ServiceError::internal_error)?;
+        tokio::io::copy(&mut body.into_async_read(), &mut file)
+            .await
+            .map_err(ServiceError::internal_error)?;
+        file.sync_all()
+            .await
+            .map_err(ServiceError::internal_error)?;
+
+        Ok(true)
+    }
+
+    async fn write_file(&self, path: &str, contents: Vec<u8>) -> Result<()> {
+        self.put_object(path, ByteStream::from(contents)).await
+    }
+
+    async fn upload_file(&self, path: &str, from: &Path) -> Result<()> {
+        let body = ByteStream::from_path(from)
+            .await
+            .map_err(ServiceError::internal_error)?;
+        self.put_object(path, body).await
+    }
+
+    async fn delete_file(&self, path: &str) -> Result<()> {
+        self.client
+            .delete_object()
+            .bucket(&self.bucket_name)
+            .key(self.key(path))
+            .send()
+            .await?;
+
+        Ok(())
+    }
+
+    async fn file_exists(&self, path: &str) -> Result<bool> {
+        let result = self
+            .client
+            .head_object()
+            .bucket(&self.bucket_name)
+            .key(self.key(path))
+            .send()
+            .await;
+
+        match result {
+            Ok(_) => Ok(true),
+            Err(SdkError::ServiceError { err, .. }) if err.is_not_found() => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn list_files(&self, prefix: &str) -> Result<Vec<String>> {
+        let mut paths = vec![];
+        let mut continuation_token = None;
+        loop {
+            let output = self
+                .client
+                .list_objects_v2()
+                .bucket(&self.bucket_name)
+                .prefix(self.key(prefix))
+                .set_continuation_token(continuation_token)
+                .send()
+                .await?;
+
+            let keys = output.contents().unwrap_or_default().iter();
+            paths.extend(
+                keys.filter_map(|object| object.key())
+                    .map(|key| key.strip_prefix(&self.key("")).unwrap_or(key).to_string()),
+            );
+
+            match output.next_continuation_token() {
+                Some(token) if output.is_truncated() => continuation_token = Some(token.into()),
+                _ => break,
+            }
+        }
+
+        paths.sort();
+        Ok(paths)
+    }
+}
+
+impl S3FileStore {
+    /// Keeps files under `prefix` of `bucket_name`, or else of the data bucket.
+    pub async fn create(bucket_name: Option<&str>, prefix: &str) -> S3FileStore {
+        let bucket_name = bucket_name
+            .map(String::from)
+            .unwrap_or_else(|| util::require_env("DATA_BUCKET_NAME"));
+        let sdk_config = aws_config::load_from_env().await;
+        let client = s3::Client::new(&sdk_config);
+
+        S3FileStore {
+            bucket_name,
+            prefix: prefix.trim_end_matches('/').into(),
+            client,
+        }
+    }
+
+    fn key(&self, path: &str) -> String {
+        if self.prefix.is_empty() {
+            return path.into();
+        }
+        format!("{}/{}", self.prefix, path)
+    }
+
+    /// The body of the object for `path`, or None when it doesn't exist.
+    async fn get_object(&self, path: &str) -> Result<Option<ByteStream>> {
+        let result = self
+            .client
+            .get_object()
+            .bucket(&self.bucket_name)
+            .key(self.key(path))
+            .send()
+            .await;
+
+        match result {
+            Ok(output) => Ok(Some(output.body)),
+            Err(SdkError::ServiceError { err, .. }) if err.is_no_such_key() => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn put_object(&self, path: &str, body: ByteStream) -> Result<()> {
+        self.client
+            .put_object()
+            .bucket(&self.bucket_name)
+            .key(self.key(path))
+            .body(body)
+            .send()
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Holds files in memory, for the life of the store and its clones.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryFileStore {
+    files: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+}
+
+#[async_trait]
+impl FileStore for MemoryFileStore {
+    async fn read_file(&self, path: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.files.lock().unwrap().get(path).cloned())
+    }
+
+    async fn write_file(&self, path: &str, contents: Vec<u8>) -> Result<()> {
+        self.files.lock().unwrap().insert(path.into(), contents);
+        Ok(())
+    }
+
+    async fn delete_file(&self, path: &str) -> Result<()> {
+        self.files.lock().unwrap().remove(path);
+        Ok(())
+    }
+
+    async fn file_exists(&self, path: &str) -> Result<bool> {
+        Ok(self.files.lock().unwrap().contains_key(path))
+    }
+
+    async fn list_files(&self, prefix: &str) -> Result<Vec<String>> {
+        let files = self.files.lock().unwrap();
+        let mut paths: Vec<_> = files
+            .keys()
+            .filter(|path| path.starts_with(prefix))
+            .cloned()
+            .collect();
+        paths.sort();
+        Ok(paths)
+    }
+}
+
+impl MemoryFileStore {
+    pub fn create() -> Self {
+        MemoryFileStore::default()
+    }
+}
+
+/// The files under a directory of another store, such as one index's files among those of every
+/// index.
+#[derive(Clone, Debug)]
+pub struct PrefixedFileStore {
+    inner: Arc<dyn FileStore>,
+
+    prefix: String,
+}
+
+#[async_trait]
+impl FileStore for PrefixedFileStore {
+    async fn read_file(&self, path: &str) -> Result<Option<Vec<u8>>> {
+        self.inner.read_file(&self.path(path)).await
+    }
+
+    async fn download_file(&self, path: &str, to: &Path) -> Result<bool> {
+        self.inner.download_file(&self.path(path), to).await
+    }
+
+    async fn write_file(&self, path: &str, contents: Vec<u8>) -> Result<()> {
+        self.inner.write_file(&self.path(path), contents).await
+    }
+
+    async fn upload_file(&self, path: &str, from: &Path) -> Result<()> {
+        self.inner.upload_file(&self.path(path), from).await
+    }
+
+    async fn delete_file(&self, path: &str) -> Result<()> {
+        self.inner.delete_file(&self.path(path)).await
+    }
+
+    async fn file_exists(&self, path: &str) -> Result<bool> {
+        self.inner.file_exists(&self.path(path)).await
+    }
+
+    async fn list_files(&self, prefix: &str) -> Result<Vec<String>> {
+        let paths = self.inner.list_files(&self.path(prefix)).await?;
+        Ok(paths
+            .into_iter()
+            .filter_map(|path| path.strip_prefix(&self.path("")).map(String::from))
+            .collect())
+    }
+}
+
+impl PrefixedFileStore {
+    pub fn new(inner: Arc<dyn FileStore>, prefix: &str) -> Self {
+        PrefixedFileStore {
+            inner,
+            prefix: prefix.trim_end_matches('/').into(),
+        }
+    }
+
+    fn path(&self, path: &str) -> String {
+        format!("{}/{}", self.prefix, path)
+    }
+}
+
+#[cfg(test)]
+pub mod test_util {
+    pub use super::MemoryFileStore as TestFileStore;
+}
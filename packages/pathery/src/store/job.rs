@@ -0,0 +1,292 @@
+use std::collections::HashMap;
+use std::result::Result as StdResult;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use aws_sdk_dynamodb as ddb;
+use chrono::{Duration, Utc};
+use ddb::error::UpdateItemError;
+use ddb::model::AttributeValue;
+use ddb::output::UpdateItemOutput;
+use ddb::types::SdkError;
+use serde::{Deserialize, Serialize};
+
+use crate::search_doc::DDBKey;
+use crate::service::ServiceError;
+use crate::util;
+
+type Result<T> = StdResult<T, ServiceError>;
+
+/// How long job records are kept before DynamoDB expires them.
+const JOB_TTL_DAYS: i64 = 7;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Pending,
+    Complete,
+    /// The index writer gave up on the job, since applying it fails the same way every time.
+    Failed,
+}
+
+/// How far a job that works through many documents has got.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct JobProgress {
+    /// Documents the job will work through.
+    pub total: usize,
+
+    /// Documents worked through so far.
+    pub processed: usize,
+
+    /// Documents the job couldn't apply to, such as those a change would make invalid.
+    pub failed: usize,
+
+    /// Index writer jobs submitted for the documents processed so far.
+    pub job_ids: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct JobStatus {
+    pub job_id: String,
+    pub index_id: String,
+    pub status: JobState,
+    pub created_at: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completed_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub progress: Option<JobProgress>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl JobStatus {
+    pub fn pending(job_id: &str, index_id: &str) -> Self {
+        JobStatus {
+            job_id: job_id.into(),
+            index_id: index_id.into(),
+            status: JobState::Pending,
+            created_at: util::timestamp(),
+            completed_at: None,
+            progress: None,
+            error: None,
+        }
+    }
+}
+
+fn job_key(job_id: &str) -> DDBKey {
+    DDBKey {
+        pk: format!("job|{}", job_id),
+        sk: format!("job|{}", job_id),
+    }
+}
+
+/// Updates are conditional on the job existing, so that a job whose record expired or was never
+/// created isn't recreated without its index or creation time.
+fn ignore_missing_job(
+    result: StdResult<UpdateItemOutput, SdkError<UpdateItemError>>,
+) -> Result<()> {
+    match result {
+        Ok(_) => Ok(()),
+        Err(SdkError::ServiceError { err, .. }) if err.is_conditional_check_failed_exception() => {
+            Ok(())
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[async_trait]
+pub trait JobStore: Send + Sync {
+    /// Record a job that has been submitted but not yet committed.
+    async fn create_job(&self, status: JobStatus) -> Result<()>;
+
+    /// Mark jobs as committed.
+    async fn complete_jobs(&self, job_ids: &[String]) -> Result<()>;
+
+    /// Record how far a pending job has got.
+    async fn update_progress(&self, job_id: &str, progress: JobProgress) -> Result<()>;
+
+    /// Mark a job as failed, with the error that failed it.
+    async fn fail_job(&self, job_id: &str, error: &str) -> Result<()>;
+
+    async fn get_job(&self, job_id: &str) -> Result<Option<JobStatus>>;
+}
+
+pub struct DDBJobStore {
+    table_name: String,
+    client: ddb::Client,
+}
+
+#[async_trait]
+impl JobStore for DDBJobStore {
+    async fn create_job(&self, status: JobStatus) -> Result<()> {
+        let key: HashMap<String, AttributeValue> = serde_dynamo::to_item(job_key(&status.job_id))?;
+
+        let mut item: HashMap<String, AttributeValue> = serde_dynamo::to_item(status)?;
+        item.extend(key);
+
+        let expires_at = Utc::now() + Duration::days(JOB_TTL_DAYS);
+        item.insert(
+            String::from("__ttl"),
+            AttributeValue::N(expires_at.timestamp().to_string()),
+        );
+
+        self.client
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(item))
+            .send()
+            .await?;
+
+        Ok(())
+    }
+
+    async fn complete_jobs(&self, job_ids: &[String]) -> Result<()> {
+        for job_id in job_ids {
+            let result = self
+                .client
+                .update_item()
+                .table_name(&self.table_name)
+                .set_key(Some(serde_dynamo::to_item(job_key(job_id))?))
+                .update_expression("SET #status = :status, completed_at = :completed_at")
+                .condition_expression("attribute_exists(pk)")
+                .expression_attribute_names("#status", "status")
+                .expression_attribute_values(
+                    ":status",
+                    serde_dynamo::to_attribute_value(JobState::Complete)?,
+                )
+                .expression_attribute_values(":completed_at", AttributeValue::S(util::timestamp()))
+                .send()
+                .await;
+
+            ignore_missing_job(result)?;
+        }
+
+        Ok(())
+    }
+
+    async fn update_progress(&self, job_id: &str, progress: JobProgress) -> Result<()> {
+        let result = self
+            .client
+            .update_item()
+            .table_name(&self.table_name)
+            .set_key(Some(serde_dynamo::to_item(job_key(job_id))?))
+            .update_expression("SET progress = :progress")
+            .condition_expression("attribute_exists(pk)")
+            .expression_attribute_values(":progress", serde_dynamo::to_attribute_value(progress)?)
+            .send()
+            .await;
+
+        ignore_missing_job(result)
+    }
+
+    async fn fail_job(&self, job_id: &str, error: &str) -> Result<()> {
+        let result = self
+            .client
+            .update_item()
+            .table_name(&self.table_name)
+            .set_key(Some(serde_dynamo::to_item(job_key(job_id))?))
+            .update_expression(
+                "SET #status = :status, completed_at = :completed_at, #error = :error",
+            )
+            .condition_expression("attribute_exists(pk)")
+            .expression_attribute_names("#status", "status")
+            .expression_attribute_names("#error", "error")
+            .expression_attribute_values(
+                ":status",
+                serde_dynamo::to_attribute_value(JobState::Failed)?,
+            )
+            .expression_attribute_values(":completed_at", AttributeValue::S(util::timestamp()))
+            .expression_attribute_values(":error", AttributeValue::S(error.into()))
+            .send()
+            .await;
+
+        ignore_missing_job(result)
+    }
+
+    async fn get_job(&self, job_id: &str) -> Result<Option<JobStatus>> {
+        let response = self
+            .client
+            .get_item()
+            .table_name(&self.table_name)
+            .set_key(Some(serde_dynamo::to_item(job_key(job_id))?))
+            .send()
+            .await?;
+
+        Ok(response
+            .item()
+            .map(|item| serde_dynamo::from_item(item.clone()))
+            .transpose()?)
+    }
+}
+
+impl DDBJobStore {
+    pub async fn create(table_name: Option<&str>) -> DDBJobStore {
+        let table_name = table_name
+            .map(String::from)
+            .unwrap_or_else(|| util::require_env("DATA_TABLE_NAME"));
+        let sdk_config = aws_config::load_from_env().await;
+        let client = aws_sdk_dynamodb::Client::new(&sdk_config);
+
+        DDBJobStore { table_name, client }
+    }
+}
+
+/// Holds job statuses in memory, for the life of the store and its clones.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryJobStore {
+    db: Arc<Mutex<HashMap<String, JobStatus>>>,
+}
+
+#[async_trait]
+impl JobStore for MemoryJobStore {
+    async fn create_job(&self, status: JobStatus) -> Result<()> {
+        let mut db = self.db.lock().unwrap();
+        db.insert(status.job_id.clone(), status);
+        Ok(())
+    }
+
+    async fn complete_jobs(&self, job_ids: &[String]) -> Result<()> {
+        let mut db = self.db.lock().unwrap();
+        for job_id in job_ids {
+            if let Some(status) = db.get_mut(job_id) {
+                status.status = JobState::Complete;
+                status.completed_at = Some(util::timestamp());
+            }
+        }
+        Ok(())
+    }
+
+    async fn update_progress(&self, job_id: &str, progress: JobProgress) -> Result<()> {
+        let mut db = self.db.lock().unwrap();
+        if let Some(status) = db.get_mut(job_id) {
+            status.progress = Some(progress);
+        }
+        Ok(())
+    }
+
+    async fn fail_job(&self, job_id: &str, error: &str) -> Result<()> {
+        let mut db = self.db.lock().unwrap();
+        if let Some(status) = db.get_mut(job_id) {
+            status.status = JobState::Failed;
+            status.completed_at = Some(util::timestamp());
+            status.error = Some(error.into());
+        }
+        Ok(())
+    }
+
+    async fn get_job(&self, job_id: &str) -> Result<Option<JobStatus>> {
+        let db = self.db.lock().unwrap();
+        Ok(db.get(job_id).cloned())
+    }
+}
+
+impl MemoryJobStore {
+    pub fn create() -> Self {
+        MemoryJobStore::default()
+    }
+}
+
+#[cfg(test)]
+pub mod test_util {
+    pub use super::MemoryJobStore as TestJobStore;
+}
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use std::result::Result as StdResult;
+
+use async_trait::async_trait;
+use aws_sdk_dynamodb as ddb;
+use chrono::{DateTime, Utc};
+use ddb::model::AttributeValue;
+use ddb::types::SdkError;
+
+use crate::search_doc::DDBKey;
+use crate::service::ServiceError;
+use crate::util;
+
+type Result<T> = StdResult<T, ServiceError>;
+
+fn lease_key(index_id: &str) -> DDBKey {
+    DDBKey {
+        pk: format!("lease|{}", index_id),
+        sk: format!("lease|{}", index_id),
+    }
+}
+
+/// Exclusive, expiring claims on an index, so that only one writer works on an index at a time
+/// even when its jobs arrive through more than one queue.
+#[async_trait]
+pub trait LeaseStore: Send + Sync {
+    /// Claim `index_id` for `owner` until `expires_at`. Returns false if another owner holds an
+    /// unexpired lease.
+    async fn acquire(&self, index_id: &str, owner: &str, expires_at: DateTime<Utc>)
+        -> Result<bool>;
+
+    /// Give up a lease held by `owner`. Releasing a lease held by someone else is a no-op.
+    async fn release(&self, index_id: &str, owner: &str) -> Result<()>;
+}
+
+pub struct DDBLeaseStore {
+    table_name: String,
+    client: ddb::Client,
+}
+
+#[async_trait]
+impl LeaseStore for DDBLeaseStore {
+    async fn acquire(
+        &self,
+        index_id: &str,
+        owner: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<bool> {
+        let mut item: HashMap<String, AttributeValue> = serde_dynamo::to_item(lease_key(index_id))?;
+        item.insert(String::from("owner"), AttributeValue::S(owner.into()));
+        item.insert(
+            String::from("__ttl"),
+            AttributeValue::N(expires_at.timestamp().to_string()),
+        );
+
+        let result = self
+            .client
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(item))
+            .condition_expression("attribute_not_exists(pk) OR #owner = :owner OR #ttl < :now")
+            .expression_attribute_names("#owner", "owner")
+            .expression_attribute_names("#ttl", "__ttl")
+            .expression_attribute_values(":owner", AttributeValue::S(owner.into()))
+            .expression_attribute_values(
+                ":now",
+                AttributeValue::N(Utc::now().timestamp().to_string()),
+            )
+            .send()
+            .await;
+
+        match result {
+            Ok(_) => Ok(true),
+            Err(SdkError::ServiceError { err, .. })
+                if err.is_conditional_check_failed_exception() =>
+            {
+                Ok(false)
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn release(&self, index_id: &str, owner: &str) -> Result<()> {
+        let result = self
+            .client
+            .delete_item()
+            .table_name(&self.table_name)
+            .set_key(Some(serde_dynamo::to_item(lease_key(index_id))?))
+            .condition_expression("#owner = :owner")
+            .expression_attribute_names("#owner", "owner")
+            .expression_attribute_values(":owner", AttributeValue::S(owner.into()))
+            .send()
+            .await;
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(SdkError::ServiceError { err, .. })
+                if err.is_conditional_check_failed_exception() =>
+            {
+                Ok(())
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+impl DDBLeaseStore {
+    pub async fn create(table_name: Option<&str>) -> DDBLeaseStore {
+        let table_name = table_name
+            .map(String::from)
+            .unwrap_or_else(|| util::require_env("DATA_TABLE_NAME"));
+        let sdk_config = aws_config::load_from_env().await;
+        let client = aws_sdk_dynamodb::Client::new(&sdk_config);
+
+        DDBLeaseStore { table_name, client }
+    }
+}
+
+#[cfg(test)]
+pub mod test_util {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    #[derive(Clone, Debug)]
+    struct Lease {
+        owner: String,
+        expires_at: DateTime<Utc>,
+    }
+
+    #[derive(Clone, Debug, Default)]
+    pub struct TestLeaseStore {
+        db: Arc<Mutex<HashMap<String, Lease>>>,
+    }
+
+    #[async_trait]
+    impl LeaseStore for TestLeaseStore {
+        async fn acquire(
+            &self,
+            index_id: &str,
+            owner: &str,
+            expires_at: DateTime<Utc>,
+        ) -> Result<bool> {
+            let mut db = self.db.lock().unwrap();
+            match db.get(index_id) {
+                Some(lease) if lease.owner != owner && lease.expires_at >= Utc::now() => Ok(false),
+                _ => {
+                    db.insert(
+                        index_id.into(),
+                        Lease {
+                            owner: owner.into(),
+                            expires_at,
+                        },
+                    );
+                    Ok(true)
+                }
+            }
+        }
+
+        async fn release(&self, index_id: &str, owner: &str) -> Result<()> {
+            let mut db = self.db.lock().unwrap();
+            if matches!(db.get(index_id), Some(lease) if lease.owner == owner) {
+                db.remove(index_id);
+            }
+            Ok(())
+        }
+    }
+
+    impl TestLeaseStore {
+        pub fn create() -> Self {
+            TestLeaseStore::default()
+        }
+    }
+}
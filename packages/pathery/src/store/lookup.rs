@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::result::Result as StdResult;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use aws_sdk_dynamodb as ddb;
+use ddb::model::AttributeValue;
+
+use crate::json;
+use crate::service::ServiceError;
+
+type Result<T> = StdResult<T, ServiceError>;
+
+/// An item of a lookup table, by attribute name.
+pub type LookupItem = json::Map<String, json::Value>;
+
+/// Tables outside Pathery that documents are enriched from, such as a table of category names
+/// keyed by category id.
+#[async_trait]
+pub trait LookupTable: Send + Sync {
+    /// The item of `table` whose `key` attribute is `value`, if there is one.
+    async fn get_item(
+        &self,
+        table: &str,
+        key: &str,
+        value: &json::Value,
+    ) -> Result<Option<LookupItem>>;
+}
+
+pub struct DDBLookupTable {
+    client: ddb::Client,
+}
+
+#[async_trait]
+impl LookupTable for DDBLookupTable {
+    async fn get_item(
+        &self,
+        table: &str,
+        key: &str,
+        value: &json::Value,
+    ) -> Result<Option<LookupItem>> {
+        let key_value = match value {
+            json::Value::String(value) => AttributeValue::S(value.clone()),
+            json::Value::Number(value) => AttributeValue::N(value.to_string()),
+            // Other values can't be DynamoDB keys, so can't match an item.
+            _ => return Ok(None),
+        };
+
+        let output = self
+            .client
+            .get_item()
+            .table_name(table)
+            .set_key(Some(HashMap::from([(key.to_string(), key_value)])))
+            .send()
+            .await?;
+
+        let item = output
+            .item()
+            .map(|item| serde_dynamo::from_item(item.clone()))
+            .transpose()?;
+
+        Ok(item)
+    }
+}
+
+impl DDBLookupTable {
+    pub async fn create() -> DDBLookupTable {
+        let sdk_config = aws_config::load_from_env().await;
+        let client = ddb::Client::new(&sdk_config);
+
+        DDBLookupTable { client }
+    }
+}
+
+/// Holds lookup table items in memory, for the life of the store and its clones.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryLookupTable {
+    items: Arc<Mutex<HashMap<(String, String), LookupItem>>>,
+
+    reads: Arc<Mutex<usize>>,
+}
+
+#[async_trait]
+impl LookupTable for MemoryLookupTable {
+    async fn get_item(
+        &self,
+        table: &str,
+        key: &str,
+        value: &json::Value,
+    ) -> Result<Option<LookupItem>> {
+        *self.reads.lock().unwrap() += 1;
+
+        let item = self
+            .items
+            .lock()
+            .unwrap()
+            .get(&(table.to_string(), value.to_string()))
+            .filter(|item| item.get(key) == Some(value))
+            .cloned();
+
+        Ok(item)
+    }
+}
+
+impl MemoryLookupTable {
+    pub fn create() -> Self {
+        MemoryLookupTable::default()
+    }
+
+    /// Adds `item` to `table`, keyed by the value of its `key` attribute.
+    pub fn put_item(&self, table: &str, key: &str, item: json::Value) {
+        let item = match item {
+            json::Value::Object(item) => item,
+            _ => panic!("item should be an object"),
+        };
+        let value = item.get(key).expect("item should have its key").to_string();
+        self.items
+            .lock()
+            .unwrap()
+            .insert((table.to_string(), value), item);
+    }
+
+    /// Number of items read from the tables so far.
+    pub fn reads(&self) -> usize {
+        *self.reads.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+pub mod test_util {
+    pub use super::MemoryLookupTable as TestLookupTable;
+}
@@ -1 +1,16 @@
+pub mod analytics;
+pub mod api_key;
+pub mod change;
 pub mod document;
+pub mod erase;
+pub mod event;
+pub mod field_usage;
+pub mod file;
+pub mod job;
+pub mod lease;
+pub mod lookup;
+pub mod rate_limit;
+pub mod report;
+pub mod schema;
+pub mod snapshot;
+pub mod token;
@@ -0,0 +1,250 @@
+use std::collections::HashMap;
+use std::result::Result as StdResult;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use aws_sdk_dynamodb as ddb;
+use chrono::Utc;
+use ddb::model::AttributeValue;
+use ddb::types::SdkError;
+use serde::{Deserialize, Serialize};
+
+use crate::schema::RateLimitConfig;
+use crate::search_doc::DDBKey;
+use crate::service::ServiceError;
+use crate::util;
+
+type Result<T> = StdResult<T, ServiceError>;
+
+/// Buckets expire a day after they'd have refilled, when a full bucket takes their place.
+const BUCKET_TTL_SECONDS: i64 = 24 * 60 * 60;
+
+/// Conditional updates of a bucket that lost to a concurrent request are retried this many times
+/// before the request is refused.
+const MAX_ATTEMPTS: usize = 5;
+
+/// A token bucket of `config`, holding up to `burst` tokens and refilled at
+/// `requests_per_second`. Each request takes a token, so requests average out to
+/// `requests_per_second` while still allowing the burst at once after an idle spell.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+struct Bucket {
+    tokens: f64,
+
+    /// Milliseconds since the epoch when `tokens` was counted.
+    updated_at: i64,
+}
+
+impl Bucket {
+    fn full(config: &RateLimitConfig, now: i64) -> Bucket {
+        Bucket {
+            tokens: config.burst() as f64,
+            updated_at: now,
+        }
+    }
+
+    /// The bucket once refilled for the time since it was updated and a token is taken at `now`,
+    /// or None when it's empty.
+    fn take(&self, config: &RateLimitConfig, now: i64) -> Option<Bucket> {
+        let elapsed = (now - self.updated_at).max(0) as f64 / 1000.0;
+        let tokens =
+            (self.tokens + elapsed * config.requests_per_second).min(config.burst() as f64);
+
+        (tokens >= 1.0).then_some(Bucket {
+            tokens: tokens - 1.0,
+            updated_at: now.max(self.updated_at),
+        })
+    }
+
+    fn expires_at(&self, config: &RateLimitConfig) -> i64 {
+        let refill_seconds = (config.burst() as f64 / config.requests_per_second).ceil() as i64;
+        self.updated_at / 1000 + refill_seconds + BUCKET_TTL_SECONDS
+    }
+}
+
+fn bucket_key(key: &str) -> DDBKey {
+    DDBKey {
+        pk: format!("ratelimit|{}", key),
+        sk: String::from("ratelimit|bucket"),
+    }
+}
+
+/// Token buckets limiting how often each key, such as an index id, may be used.
+#[async_trait]
+pub trait RateLimitStore: Send + Sync {
+    /// Takes a token from `key`'s bucket, returning false when it's empty.
+    async fn take_token(&self, key: &str, config: &RateLimitConfig) -> Result<bool>;
+}
+
+pub struct DDBRateLimitStore {
+    table_name: String,
+    client: ddb::Client,
+}
+
+impl DDBRateLimitStore {
+    async fn get_bucket(&self, key: &str) -> Result<Option<Bucket>> {
+        let response = self
+            .client
+            .get_item()
+            .table_name(&self.table_name)
+            .set_key(Some(serde_dynamo::to_item(bucket_key(key))?))
+            .consistent_read(true)
+            .send()
+            .await?;
+
+        Ok(response
+            .item()
+            .map(|item| serde_dynamo::from_item(item.clone()))
+            .transpose()?)
+    }
+
+    /// Saves `bucket`, unless another request updated it since `previous` was read. Returns
+    /// whether it was saved.
+    async fn put_bucket(
+        &self,
+        key: &str,
+        config: &RateLimitConfig,
+        bucket: &Bucket,
+        previous: Option<&Bucket>,
+    ) -> Result<bool> {
+        let mut item: HashMap<String, AttributeValue> = serde_dynamo::to_item(bucket)?;
+        item.extend(serde_dynamo::to_item::<_, HashMap<_, _>>(bucket_key(key))?);
+        item.insert(
+            String::from("__ttl"),
+            AttributeValue::N(bucket.expires_at(config).to_string()),
+        );
+
+        let request = self
+            .client
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(item))
+            .expression_attribute_names("#updated_at", "updated_at");
+        let request = match previous {
+            Some(previous) => request
+                .condition_expression("#updated_at = :updated_at")
+                .expression_attribute_values(
+                    ":updated_at",
+                    AttributeValue::N(previous.updated_at.to_string()),
+                ),
+            None => request.condition_expression("attribute_not_exists(#updated_at)"),
+        };
+
+        match request.send().await {
+            Ok(_) => Ok(true),
+            Err(SdkError::ServiceError { err, .. })
+                if err.is_conditional_check_failed_exception() =>
+            {
+                Ok(false)
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for DDBRateLimitStore {
+    async fn take_token(&self, key: &str, config: &RateLimitConfig) -> Result<bool> {
+        // Refilled from what was read, and saved on the condition that it's unchanged since, so
+        // concurrent requests can't both take the bucket's last token.
+        for _ in 0..MAX_ATTEMPTS {
+            let now = Utc::now().timestamp_millis();
+            let previous = self.get_bucket(key).await?;
+            let bucket = previous.unwrap_or_else(|| Bucket::full(config, now));
+            let Some(taken) = bucket.take(config, now) else {
+                return Ok(false);
+            };
+
+            if self
+                .put_bucket(key, config, &taken, previous.as_ref())
+                .await?
+            {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+}
+
+impl DDBRateLimitStore {
+    pub async fn create(table_name: Option<&str>) -> DDBRateLimitStore {
+        let table_name = table_name
+            .map(String::from)
+            .unwrap_or_else(|| util::require_env("DATA_TABLE_NAME"));
+        let sdk_config = aws_config::load_from_env().await;
+        let client = aws_sdk_dynamodb::Client::new(&sdk_config);
+
+        DDBRateLimitStore { table_name, client }
+    }
+}
+
+/// Holds token buckets in memory, for the life of the store and its clones.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryRateLimitStore {
+    db: Arc<Mutex<HashMap<String, Bucket>>>,
+}
+
+#[async_trait]
+impl RateLimitStore for MemoryRateLimitStore {
+    async fn take_token(&self, key: &str, config: &RateLimitConfig) -> Result<bool> {
+        let mut db = self.db.lock().unwrap();
+        let now = Utc::now().timestamp_millis();
+        let bucket = db
+            .get(key)
+            .copied()
+            .unwrap_or_else(|| Bucket::full(config, now));
+        let Some(taken) = bucket.take(config, now) else {
+            return Ok(false);
+        };
+        db.insert(key.into(), taken);
+        Ok(true)
+    }
+}
+
+impl MemoryRateLimitStore {
+    pub fn create() -> Self {
+        MemoryRateLimitStore::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_refill_at_the_configured_rate() {
+        let config = RateLimitConfig {
+            requests_per_second: 2.0,
+            burst: Some(4),
+        };
+
+        let mut bucket = Bucket::full(&config, 0);
+        for _ in 0..4 {
+            bucket = bucket.take(&config, 1_999).unwrap();
+        }
+        assert_eq!(None, bucket.take(&config, 1_999));
+
+        // Draining the burst just before a second boundary doesn't admit another burst after it.
+        assert_eq!(None, bucket.take(&config, 2_000));
+        let bucket = bucket.take(&config, 2_499).unwrap();
+        assert_eq!(None, bucket.take(&config, 2_500));
+
+        // Idle buckets refill up to the burst, no further.
+        let idle = bucket.take(&config, 60_000).unwrap();
+        assert_eq!(3.0, idle.tokens);
+    }
+
+    #[tokio::test]
+    async fn memory_store_refuses_requests_over_the_burst() {
+        let store = MemoryRateLimitStore::create();
+        let config = RateLimitConfig {
+            requests_per_second: 0.01,
+            burst: Some(2),
+        };
+
+        assert!(store.take_token("test", &config).await.unwrap());
+        assert!(store.take_token("test", &config).await.unwrap());
+        assert!(!store.take_token("test", &config).await.unwrap());
+        assert!(store.take_token("other", &config).await.unwrap());
+    }
+}
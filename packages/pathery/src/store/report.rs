@@ -0,0 +1,89 @@
+use std::result::Result as StdResult;
+
+use async_trait::async_trait;
+use aws_sdk_s3 as s3;
+use s3::types::ByteStream;
+
+use crate::service::ServiceError;
+use crate::{json, util};
+
+type Result<T> = StdResult<T, ServiceError>;
+
+/// Reports produced by offline jobs, for data owners to read outside the API.
+#[async_trait]
+pub trait ReportStore: Send + Sync {
+    /// Saves `report` as JSON under `key`, replacing any report already there.
+    async fn put_report(&self, key: &str, report: &json::Value) -> Result<()>;
+}
+
+pub struct S3ReportStore {
+    bucket_name: String,
+    client: s3::Client,
+}
+
+#[async_trait]
+impl ReportStore for S3ReportStore {
+    async fn put_report(&self, key: &str, report: &json::Value) -> Result<()> {
+        let body = json::to_vec(report).expect("report should serialize");
+
+        self.client
+            .put_object()
+            .bucket(&self.bucket_name)
+            .key(key)
+            .content_type("application/json")
+            .body(ByteStream::from(body))
+            .send()
+            .await?;
+
+        Ok(())
+    }
+}
+
+impl S3ReportStore {
+    pub async fn create(bucket_name: Option<&str>) -> S3ReportStore {
+        let bucket_name = bucket_name
+            .map(String::from)
+            .unwrap_or_else(|| util::require_env("DATA_BUCKET_NAME"));
+        let sdk_config = aws_config::load_from_env().await;
+        let client = s3::Client::new(&sdk_config);
+
+        S3ReportStore {
+            bucket_name,
+            client,
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test_util {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    #[derive(Clone, Debug, Default)]
+    pub struct TestReportStore {
+        reports: Arc<Mutex<HashMap<String, json::Value>>>,
+    }
+
+    #[async_trait]
+    impl ReportStore for TestReportStore {
+        async fn put_report(&self, key: &str, report: &json::Value) -> Result<()> {
+            self.reports
+                .lock()
+                .unwrap()
+                .insert(key.into(), report.clone());
+            Ok(())
+        }
+    }
+
+    impl TestReportStore {
+        pub fn create() -> Self {
+            TestReportStore::default()
+        }
+
+        pub fn get_report(&self, key: &str) -> Option<json::Value> {
+            self.reports.lock().unwrap().get(key).cloned()
+        }
+    }
+}
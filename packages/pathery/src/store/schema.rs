@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+use std::result::Result as StdResult;
+
+use async_trait::async_trait;
+use aws_sdk_dynamodb as ddb;
+use ddb::model::AttributeValue;
+use ddb::types::SdkError;
+
+use crate::search_doc::DDBKey;
+use crate::service::ServiceError;
+use crate::{json, util};
+
+type Result<T> = StdResult<T, ServiceError>;
+
+fn schema_key(index_id: &str) -> DDBKey {
+    DDBKey {
+        pk: format!("schema|{}", index_id),
+        sk: format!("schema|{}", index_id),
+    }
+}
+
+/// Index configs created through the API rather than bundled with the deployment, keyed by the
+/// exact index id.
+#[async_trait]
+pub trait SchemaStore: Send + Sync {
+    /// Saves the config of a new index. Returns false if `index_id` already has one.
+    async fn create_config(&self, index_id: &str, config: &json::Value) -> Result<bool>;
+
+    async fn get_config(&self, index_id: &str) -> Result<Option<json::Value>>;
+
+    /// Deletes the config of `index_id`, if it has one.
+    async fn delete_config(&self, index_id: &str) -> Result<()>;
+}
+
+pub struct DDBSchemaStore {
+    table_name: String,
+    client: ddb::Client,
+}
+
+#[async_trait]
+impl SchemaStore for DDBSchemaStore {
+    async fn create_config(&self, index_id: &str, config: &json::Value) -> Result<bool> {
+        let mut item: HashMap<String, AttributeValue> =
+            serde_dynamo::to_item(schema_key(index_id))?;
+        item.insert(
+            String::from("config"),
+            AttributeValue::S(json::to_string(config).expect("config should serialize")),
+        );
+
+        let result = self
+            .client
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(item))
+            .condition_expression("attribute_not_exists(pk)")
+            .send()
+            .await;
+
+        match result {
+            Ok(_) => Ok(true),
+            Err(SdkError::ServiceError { err, .. })
+                if err.is_conditional_check_failed_exception() =>
+            {
+                Ok(false)
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn get_config(&self, index_id: &str) -> Result<Option<json::Value>> {
+        let output = self
+            .client
+            .get_item()
+            .table_name(&self.table_name)
+            .set_key(Some(serde_dynamo::to_item(schema_key(index_id))?))
+            .consistent_read(true)
+            .send()
+            .await?;
+
+        let config = output
+            .item()
+            .and_then(|item| item.get("config"))
+            .and_then(|config| config.as_s().ok())
+            .map(|config| json::from_str(config).map_err(ServiceError::internal_error))
+            .transpose()?;
+
+        Ok(config)
+    }
+
+    async fn delete_config(&self, index_id: &str) -> Result<()> {
+        self.client
+            .delete_item()
+            .table_name(&self.table_name)
+            .set_key(Some(serde_dynamo::to_item(schema_key(index_id))?))
+            .send()
+            .await?;
+
+        Ok(())
+    }
+}
+
+impl DDBSchemaStore {
+    pub async fn create(table_name: Option<&str>) -> DDBSchemaStore {
+        let table_name = table_name
+            .map(String::from)
+            .unwrap_or_else(|| util::require_env("DATA_TABLE_NAME"));
+        let sdk_config = aws_config::load_from_env().await;
+        let client = aws_sdk_dynamodb::Client::new(&sdk_config);
+
+        DDBSchemaStore { table_name, client }
+    }
+}
+
+#[cfg(test)]
+pub mod test_util {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    #[derive(Clone, Debug, Default)]
+    pub struct TestSchemaStore {
+        db: Arc<Mutex<HashMap<String, json::Value>>>,
+    }
+
+    #[async_trait]
+    impl SchemaStore for TestSchemaStore {
+        async fn create_config(&self, index_id: &str, config: &json::Value) -> Result<bool> {
+            let mut db = self.db.lock().unwrap();
+            if db.contains_key(index_id) {
+                return Ok(false);
+            }
+            db.insert(index_id.into(), config.clone());
+            Ok(true)
+        }
+
+        async fn get_config(&self, index_id: &str) -> Result<Option<json::Value>> {
+            Ok(self.db.lock().unwrap().get(index_id).cloned())
+        }
+
+        async fn delete_config(&self, index_id: &str) -> Result<()> {
+            self.db.lock().unwrap().remove(index_id);
+            Ok(())
+        }
+    }
+
+    impl TestSchemaStore {
+        pub fn create() -> Self {
+            TestSchemaStore::default()
+        }
+    }
+}
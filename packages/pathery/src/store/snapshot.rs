@@ -0,0 +1,109 @@
+use std::result::Result as StdResult;
+
+use async_trait::async_trait;
+use aws_sdk_s3 as s3;
+use s3::types::{ByteStream, SdkError};
+
+use crate::service::ServiceError;
+use crate::util;
+
+type Result<T> = StdResult<T, ServiceError>;
+
+/// Copies of index files taken for backup, kept outside the index's own storage.
+#[async_trait]
+pub trait SnapshotStore: Send + Sync {
+    /// Saves `body` under `key`, replacing any object already there.
+    async fn put_object(&self, key: &str, body: Vec<u8>) -> Result<()>;
+
+    /// The object saved under `key`, or None when there isn't one.
+    async fn get_object(&self, key: &str) -> Result<Option<Vec<u8>>>;
+}
+
+pub struct S3SnapshotStore {
+    bucket_name: String,
+    client: s3::Client,
+}
+
+#[async_trait]
+impl SnapshotStore for S3SnapshotStore {
+    async fn put_object(&self, key: &str, body: Vec<u8>) -> Result<()> {
+        self.client
+            .put_object()
+            .bucket(&self.bucket_name)
+            .key(key)
+            .body(ByteStream::from(body))
+            .send()
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_object(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let result = self
+            .client
+            .get_object()
+            .bucket(&self.bucket_name)
+            .key(key)
+            .send()
+            .await;
+
+        let output = match result {
+            Ok(output) => output,
+            Err(SdkError::ServiceError { err, .. }) if err.is_no_such_key() => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let bytes = output
+            .body
+            .collect()
+            .await
+            .map_err(ServiceError::internal_error)?;
+
+        Ok(Some(bytes.into_bytes().to_vec()))
+    }
+}
+
+impl S3SnapshotStore {
+    pub async fn create(bucket_name: Option<&str>) -> S3SnapshotStore {
+        let bucket_name = bucket_name
+            .map(String::from)
+            .unwrap_or_else(|| util::require_env("DATA_BUCKET_NAME"));
+        let sdk_config = aws_config::load_from_env().await;
+        let client = s3::Client::new(&sdk_config);
+
+        S3SnapshotStore {
+            bucket_name,
+            client,
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test_util {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    #[derive(Clone, Debug, Default)]
+    pub struct TestSnapshotStore {
+        objects: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+    }
+
+    #[async_trait]
+    impl SnapshotStore for TestSnapshotStore {
+        async fn put_object(&self, key: &str, body: Vec<u8>) -> Result<()> {
+            self.objects.lock().unwrap().insert(key.into(), body);
+            Ok(())
+        }
+
+        async fn get_object(&self, key: &str) -> Result<Option<Vec<u8>>> {
+            Ok(self.objects.lock().unwrap().get(key).cloned())
+        }
+    }
+
+    impl TestSnapshotStore {
+        pub fn create() -> Self {
+            TestSnapshotStore::default()
+        }
+    }
+}
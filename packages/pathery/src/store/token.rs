@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+use std::result::Result as StdResult;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use aws_sdk_dynamodb as ddb;
+use chrono::{Duration, Utc};
+use ddb::model::{AttributeValue, DeleteRequest, KeysAndAttributes, PutRequest, WriteRequest};
+
+use crate::search_doc::DDBKey;
+use crate::service::ServiceError;
+use crate::util;
+
+type Result<T> = StdResult<T, ServiceError>;
+
+/// How long the last applied token of a doc is kept, SQS's longest message retention. A message
+/// can't be redelivered once its doc's token has expired.
+pub const TOKEN_TTL_DAYS: i64 = 14;
+
+/// Most keys in a DynamoDB batch get and in a batch write.
+const MAX_BATCH_GET: usize = 100;
+const MAX_BATCH_WRITE: usize = 25;
+
+fn token_key(index_id: &str, doc_id: &str) -> DDBKey {
+    DDBKey {
+        pk: format!("token|{}|{}", index_id, doc_id),
+        sk: format!("token|{}|{}", index_id, doc_id),
+    }
+}
+
+/// The write token of the last op the index writer committed for each doc, so that redelivered
+/// jobs older than a doc's last write are skipped rather than undoing it.
+#[async_trait]
+pub trait TokenStore: Send + Sync {
+    /// The last applied tokens of `doc_ids` in `index_id`. Docs without one are left out.
+    async fn get_tokens(&self, index_id: &str, doc_ids: &[String]) -> Result<HashMap<String, u64>>;
+
+    /// Records `tokens` as the last applied tokens of their docs in `index_id`.
+    async fn put_tokens(&self, index_id: &str, tokens: HashMap<String, u64>) -> Result<()>;
+
+    /// Forgets the last applied tokens of `doc_ids` in `index_id`.
+    async fn delete_tokens(&self, index_id: &str, doc_ids: &[String]) -> Result<()>;
+}
+
+pub struct DDBTokenStore {
+    table_name: String,
+    client: ddb::Client,
+}
+
+#[async_trait]
+impl TokenStore for DDBTokenStore {
+    async fn get_tokens(&self, index_id: &str, doc_ids: &[String]) -> Result<HashMap<String, u64>> {
+        let mut tokens = HashMap::new();
+
+        for chunk in doc_ids.chunks(MAX_BATCH_GET) {
+            let mut keys_and_attrs = KeysAndAttributes::builder();
+            for doc_id in chunk {
+                keys_and_attrs =
+                    keys_and_attrs.keys(serde_dynamo::to_item(token_key(index_id, doc_id))?);
+            }
+
+            let response = self
+                .client
+                .batch_get_item()
+                .request_items(&self.table_name, keys_and_attrs.build())
+                .send()
+                .await?;
+
+            let unprocessed = response
+                .unprocessed_keys()
+                .map(|keys| keys.values().any(|keys| keys.keys().is_some()))
+                .unwrap_or_default();
+            if unprocessed {
+                return Err(ServiceError::rate_limit());
+            }
+
+            for item in response
+                .responses()
+                .into_iter()
+                .flat_map(|responses| responses.values())
+                .flatten()
+            {
+                let doc_id = item.get("doc_id").and_then(|doc_id| doc_id.as_s().ok());
+                let token = item
+                    .get("token")
+                    .and_then(|token| token.as_n().ok())
+                    .and_then(|token| token.parse().ok());
+                if let (Some(doc_id), Some(token)) = (doc_id, token) {
+                    tokens.insert(doc_id.clone(), token);
+                }
+            }
+        }
+
+        Ok(tokens)
+    }
+
+    async fn put_tokens(&self, index_id: &str, tokens: HashMap<String, u64>) -> Result<()> {
+        let expires_at = Utc::now() + Duration::days(TOKEN_TTL_DAYS);
+        let tokens: Vec<_> = tokens.into_iter().collect();
+
+        for chunk in tokens.chunks(MAX_BATCH_WRITE) {
+            let mut writes = vec![];
+            for (doc_id, token) in chunk {
+                let mut item: HashMap<String, AttributeValue> =
+                    serde_dynamo::to_item(token_key(index_id, doc_id))?;
+                item.insert(String::from("doc_id"), AttributeValue::S(doc_id.clone()));
+                item.insert(String::from("token"), AttributeValue::N(token.to_string()));
+                item.insert(
+                    String::from("__ttl"),
+                    AttributeValue::N(expires_at.timestamp().to_string()),
+                );
+
+                let put_request = PutRequest::builder().set_item(Some(item)).build();
+                writes.push(WriteRequest::builder().put_request(put_request).build());
+            }
+
+            // Unconditional, since the writer holds the index's lease while it commits.
+            let response = self
+                .client
+                .batch_write_item()
+                .request_items(&self.table_name, writes)
+                .send()
+                .await?;
+
+            if let Some(items) = response.unprocessed_items() {
+                if items.values().any(|writes| !writes.is_empty()) {
+                    return Err(ServiceError::rate_limit());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn delete_tokens(&self, index_id: &str, doc_ids: &[String]) -> Result<()> {
+        for chunk in doc_ids.chunks(MAX_BATCH_WRITE) {
+            let mut writes = vec![];
+            for doc_id in chunk {
+                let key = serde_dynamo::to_item(token_key(index_id, doc_id))?;
+                let delete_request = DeleteRequest::builder().set_key(Some(key)).build();
+                writes.push(
+                    WriteRequest::builder()
+                        .delete_request(delete_request)
+                        .build(),
+                );
+            }
+
+            let response = self
+                .client
+                .batch_write_item()
+                .request_items(&self.table_name, writes)
+                .send()
+                .await?;
+
+            if let Some(items) = response.unprocessed_items() {
+                if items.values().any(|writes| !writes.is_empty()) {
+                    return Err(ServiceError::rate_limit());
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl DDBTokenStore {
+    pub async fn create(table_name: Option<&str>) -> DDBTokenStore {
+        let table_name = table_name
+            .map(String::from)
+            .unwrap_or_else(|| util::require_env("DATA_TABLE_NAME"));
+        let sdk_config = aws_config::load_from_env().await;
+        let client = aws_sdk_dynamodb::Client::new(&sdk_config);
+
+        DDBTokenStore { table_name, client }
+    }
+}
+
+/// Holds tokens in memory, for the life of the store and its clones.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryTokenStore {
+    db: Arc<Mutex<HashMap<(String, String), u64>>>,
+}
+
+#[async_trait]
+impl TokenStore for MemoryTokenStore {
+    async fn get_tokens(&self, index_id: &str, doc_ids: &[String]) -> Result<HashMap<String, u64>> {
+        let db = self.db.lock().unwrap();
+        Ok(doc_ids
+            .iter()
+            .filter_map(|doc_id| {
+                db.get(&(index_id.to_string(), doc_id.clone()))
+                    .map(|token| (doc_id.clone(), *token))
+            })
+            .collect())
+    }
+
+    async fn put_tokens(&self, index_id: &str, tokens: HashMap<String, u64>) -> Result<()> {
+        let mut db = self.db.lock().unwrap();
+        for (doc_id, token) in tokens {
+            db.insert((index_id.to_string(), doc_id), token);
+        }
+        Ok(())
+    }
+
+    async fn delete_tokens(&self, index_id: &str, doc_ids: &[String]) -> Result<()> {
+        let mut db = self.db.lock().unwrap();
+        for doc_id in doc_ids {
+            db.remove(&(index_id.to_string(), doc_id.clone()));
+        }
+        Ok(())
+    }
+}
+
+impl MemoryTokenStore {
+    pub fn create() -> Self {
+        MemoryTokenStore::default()
+    }
+}
@@ -0,0 +1,203 @@
+//! Suggestion dictionaries of the most frequent terms of an index's `suggest.fields`.
+//!
+//! The index writer rebuilds an index's dictionary after each commit and stores it beside the
+//! index, so that suggestions are a lookup in a sorted list rather than a scan of every
+//! segment's term dictionary. Terms are what the field's tokenizer produces: words for `TEXT`
+//! fields, and whole values such as titles and phrases for `STRING` fields.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use tantivy::schema::FieldType;
+use tantivy::{Directory, Index, ReloadPolicy};
+use tracing::warn;
+
+use crate::index::IndexLoader;
+use crate::json;
+use crate::schema::{IndexSettings, SuggestConfig};
+use crate::service::ServiceError;
+
+pub const SUGGEST_FILE: &str = "pathery-suggest.json";
+
+const DEFAULT_MAX_TERMS: usize = 10_000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Suggestion {
+    pub text: String,
+
+    pub field: String,
+
+    /// Documents with the term, including deleted documents not yet merged away.
+    pub doc_freq: u32,
+}
+
+/// The terms of a suggestion dictionary, sorted by text.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+pub struct SuggestDictionary {
+    terms: Vec<Suggestion>,
+}
+
+impl SuggestDictionary {
+    /// Reads the most frequent terms of the fields in `config` from the last commit of `index`.
+    pub fn build(index: &Index, config: &SuggestConfig) -> tantivy::Result<Self> {
+        let schema = index.schema();
+        let searcher = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()?
+            .searcher();
+
+        let mut terms = vec![];
+        for field_name in &config.fields {
+            let field = match schema.get_field(field_name) {
+                Some(field)
+                    if matches!(
+                        schema.get_field_entry(field).field_type(),
+                        FieldType::Str(_)
+                    ) =>
+                {
+                    field
+                }
+                _ => {
+                    warn!(
+                        message = "suggest_field_skipped",
+                        field = field_name.as_str()
+                    );
+                    continue;
+                }
+            };
+
+            let mut doc_freqs: HashMap<String, u32> = HashMap::new();
+            for segment_reader in searcher.segment_readers() {
+                let inverted_index = segment_reader.inverted_index(field)?;
+                let mut stream = inverted_index.terms().stream()?;
+                while stream.advance() {
+                    if let Ok(text) = std::str::from_utf8(stream.key()) {
+                        *doc_freqs.entry(text.to_string()).or_default() += stream.value().doc_freq;
+                    }
+                }
+            }
+
+            terms.extend(doc_freqs.into_iter().map(|(text, doc_freq)| Suggestion {
+                text,
+                field: field_name.clone(),
+                doc_freq,
+            }));
+        }
+
+        terms.sort_by(|a, b| {
+            b.doc_freq
+                .cmp(&a.doc_freq)
+                .then_with(|| a.text.cmp(&b.text))
+        });
+        terms.truncate(config.max_terms.unwrap_or(DEFAULT_MAX_TERMS));
+        terms.sort_by(|a, b| a.text.cmp(&b.text).then_with(|| a.field.cmp(&b.field)));
+
+        Ok(SuggestDictionary { terms })
+    }
+
+    /// The dictionary stored beside `index`, if one has been built.
+    pub fn load(index: &Index) -> Option<Self> {
+        let bytes = index
+            .directory()
+            .atomic_read(Path::new(SUGGEST_FILE))
+            .ok()?;
+        json::from_slice(&bytes).ok()
+    }
+
+    pub fn save(&self, index_loader: &dyn IndexLoader, index_id: &str) -> Result<(), ServiceError> {
+        let bytes = json::to_vec(self).expect("suggest dictionary should serialize");
+        index_loader.write_index_file(index_id, SUGGEST_FILE, &bytes)
+    }
+
+    /// Up to `limit` terms starting with `prefix`, or with it lowercased, those in the most
+    /// documents first.
+    pub fn suggest(&self, prefix: &str, limit: usize) -> Vec<Suggestion> {
+        let lowercase = prefix.to_lowercase();
+        let mut prefixes = vec![prefix];
+        if lowercase != prefix {
+            prefixes.push(&lowercase);
+        }
+
+        let mut suggestions = vec![];
+        for prefix in prefixes {
+            let start = self
+                .terms
+                .partition_point(|term| term.text.as_str() < prefix);
+            suggestions.extend(
+                self.terms[start..]
+                    .iter()
+                    .take_while(|term| term.text.starts_with(prefix))
+                    .cloned(),
+            );
+        }
+
+        suggestions.sort_by(|a, b| {
+            b.doc_freq
+                .cmp(&a.doc_freq)
+                .then_with(|| a.text.cmp(&b.text))
+        });
+        suggestions.truncate(limit);
+        suggestions
+    }
+}
+
+/// Rebuilds the suggestion dictionary of `index_id` from the last commit of `index`, when
+/// `settings` asks for one.
+pub fn rebuild(
+    index_loader: &dyn IndexLoader,
+    index_id: &str,
+    index: &Index,
+    settings: &IndexSettings,
+) -> Result<(), ServiceError> {
+    let Some(config) = &settings.suggest else {
+        return Ok(());
+    };
+
+    SuggestDictionary::build(index, config)
+        .map_err(ServiceError::internal_error)?
+        .save(index_loader, index_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[tokio::test]
+    async fn dictionary_suggests_frequent_terms() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![
+                    json!({ "title": "Zen and the art", "isbn": "zen-1" }),
+                    json!({ "title": "Zen mind", "isbn": "zen-2" }),
+                    json!({ "title": "Zebra crossings", "isbn": "zebra-1" }),
+                ],
+            )
+            .await;
+        let index = ctx.index_loader().load_index("test", None).unwrap();
+        let config = SuggestConfig {
+            fields: vec![String::from("title"), String::from("isbn")],
+            max_terms: None,
+        };
+
+        SuggestDictionary::build(&index, &config)
+            .unwrap()
+            .save(ctx.index_loader(), "test")
+            .unwrap();
+        let dictionary = SuggestDictionary::load(&index).unwrap();
+
+        let suggestions = dictionary
+            .suggest("Ze", 2)
+            .into_iter()
+            .map(|suggestion| (suggestion.text, suggestion.doc_freq))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![(String::from("zen"), 2), (String::from("zebra"), 1)],
+            suggestions
+        );
+        assert_eq!(2, dictionary.suggest("zen-", 10).len());
+    }
+}
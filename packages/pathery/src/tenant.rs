@@ -0,0 +1,82 @@
+//! Tenants sharing one deployment.
+//!
+//! A request is made on behalf of a tenant when the API's authorizer supplies a `tenant_id`
+//! claim, or when its API key is mapped to a tenant in `PATHERY_TENANT_KEYS`, a JSON list of
+//! `{ "api_key_id": "...", "tenant_id": "..." }` objects. A tenant's index ids are scoped as
+//! `{tenant_id}~{index_id}`, so tenants only see and change their own indexes, jobs and
+//! documents, while each takes its index configs from the bundled prefixes its unscoped ids
+//! match. Requests without a tenant, such as those signed with the deployment's default API key,
+//! address indexes by their full ids.
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+
+pub const TENANT_KEYS_ENV: &str = "PATHERY_TENANT_KEYS";
+
+/// Authorizer claim holding the request's tenant id.
+pub const TENANT_CLAIM: &str = "tenant_id";
+
+const SEPARATOR: char = '~';
+
+static TENANT_KEYS: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+#[derive(Deserialize)]
+struct TenantKey {
+    api_key_id: String,
+    tenant_id: String,
+}
+
+/// The tenant of `api_key_id` in [`TENANT_KEYS_ENV`], if any.
+pub fn key_tenant(api_key_id: &str) -> Option<String> {
+    TENANT_KEYS
+        .get_or_init(|| {
+            std::env::var(TENANT_KEYS_ENV)
+                .map(|keys| {
+                    serde_json::from_str::<Vec<TenantKey>>(&keys)
+                        .unwrap_or_else(|_| panic!("{} should be valid", TENANT_KEYS_ENV))
+                        .into_iter()
+                        .map(|key| (key.api_key_id, key.tenant_id))
+                        .collect()
+                })
+                .unwrap_or_default()
+        })
+        .get(api_key_id)
+        .cloned()
+}
+
+/// Whether `tenant_id` can scope index ids, which name directories.
+pub fn is_valid(tenant_id: &str) -> bool {
+    !tenant_id.is_empty() && !tenant_id.starts_with('.') && !tenant_id.contains([SEPARATOR, '/'])
+}
+
+/// The full id of `index_id` as seen by `tenant_id`.
+pub fn scope(tenant_id: Option<&str>, index_id: &str) -> String {
+    match tenant_id {
+        Some(tenant_id) => format!("{}{}{}", tenant_id, SEPARATOR, index_id),
+        None => index_id.to_string(),
+    }
+}
+
+/// The tenant a full index id belongs to, if any.
+pub fn tenant_of(index_id: &str) -> Option<&str> {
+    index_id
+        .split_once(SEPARATOR)
+        .map(|(tenant_id, _)| tenant_id)
+}
+
+/// The id of a full index id within its tenant.
+pub fn unscope(index_id: &str) -> &str {
+    index_id
+        .split_once(SEPARATOR)
+        .map_or(index_id, |(_, index_id)| index_id)
+}
+
+/// The id `tenant_id` knows a full index id by, `None` when it belongs to another tenant.
+pub fn visible_id<'a>(tenant_id: Option<&str>, index_id: &'a str) -> Option<&'a str> {
+    match tenant_id {
+        Some(tenant_id) => (tenant_of(index_id) == Some(tenant_id)).then(|| unscope(index_id)),
+        None => Some(index_id),
+    }
+}
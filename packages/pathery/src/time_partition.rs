@@ -0,0 +1,172 @@
+//! Rolling indexes split into a partition per day.
+//!
+//! An index config with `time_partition` settings makes its `prefix` an alias: documents written
+//! to the prefix are routed to `{prefix}-YYYY-MM-DD` partitions, which share the config since
+//! they match the prefix, and queries to the prefix fan out across the partitions in the
+//! retention window. Partitions past the window are dropped by the expire worker.
+//!
+//! Partitions are separate indexes, so a document id written on different days is indexed in
+//! each of those days' partitions.
+
+use std::collections::BTreeMap;
+
+use chrono::{Duration, NaiveDate, TimeZone, Utc};
+use tantivy::schema::Schema;
+
+use crate::index::IndexLoader;
+use crate::schema::{IndexSettings, SchemaLoader, TimePartitionConfig};
+use crate::search_doc::{SearchDoc, SearchDocId};
+use crate::service::ServiceError;
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// The time partition config of `index_id` when it's the alias of a rolling index, rather than
+/// one of its partitions or an ordinary index.
+pub fn alias_config<'a>(
+    schema_loader: &dyn SchemaLoader,
+    index_id: &str,
+    settings: &'a IndexSettings,
+) -> Option<&'a TimePartitionConfig> {
+    let config = settings.time_partition.as_ref()?;
+    (schema_loader.index_prefix(index_id).as_deref() == Some(index_id)).then_some(config)
+}
+
+pub fn partition_id(alias: &str, date: NaiveDate) -> String {
+    format!("{}-{}", alias, date.format(DATE_FORMAT))
+}
+
+/// The day of `index_id` when it's a partition of `alias`.
+pub fn partition_date(alias: &str, index_id: &str) -> Option<NaiveDate> {
+    let date = index_id.strip_prefix(alias)?.strip_prefix('-')?;
+    NaiveDate::parse_from_str(date, DATE_FORMAT).ok()
+}
+
+/// Whether the partition of `date` is within the retention window ending `today`.
+pub fn is_retained(config: &TimePartitionConfig, date: NaiveDate, today: NaiveDate) -> bool {
+    config
+        .retention_days
+        .is_none_or(|days| date > today - Duration::days(days as i64))
+}
+
+/// The partitions of `alias` within the retention window ending today, oldest first.
+pub fn retained_partitions(
+    index_loader: &dyn IndexLoader,
+    alias: &str,
+    config: &TimePartitionConfig,
+) -> Result<Vec<(NaiveDate, String)>, ServiceError> {
+    let today = Utc::now().date_naive();
+    let mut partitions = index_loader
+        .list_indexes()?
+        .into_iter()
+        .filter_map(|index_id| {
+            let date = partition_date(alias, &index_id)?;
+            is_retained(config, date, today).then_some((date, index_id))
+        })
+        .collect::<Vec<_>>();
+    partitions.sort();
+    Ok(partitions)
+}
+
+/// The indexes holding the documents of `index_id`: the retained partitions of a rolling index
+/// when it's the alias, otherwise `index_id` itself.
+pub fn resolve_alias(
+    schema_loader: &dyn SchemaLoader,
+    index_loader: &dyn IndexLoader,
+    index_id: &str,
+) -> Result<Vec<String>, ServiceError> {
+    let settings = schema_loader.load_settings(index_id)?;
+    match alias_config(schema_loader, index_id, &settings) {
+        Some(config) => Ok(retained_partitions(index_loader, index_id, config)?
+            .into_iter()
+            .map(|(_, partition)| partition)
+            .collect()),
+        None => Ok(vec![index_id.to_string()]),
+    }
+}
+
+/// The index to write `document` to: its partition when `index_id` is the alias of a rolling
+/// index, otherwise `index_id` itself.
+pub fn target_index(
+    schema_loader: &dyn SchemaLoader,
+    index_id: &str,
+    settings: &IndexSettings,
+    schema: &Schema,
+    document: &SearchDoc,
+) -> String {
+    let Some(config) = alias_config(schema_loader, index_id, settings) else {
+        return index_id.to_string();
+    };
+
+    let date = config
+        .field
+        .as_ref()
+        .and_then(|name| schema.get_field(name))
+        .and_then(|field| {
+            let document = document.document(schema);
+            let timestamp = document.get_first(field)?.as_date()?.into_unix_timestamp();
+            Utc.timestamp_opt(timestamp, 0).single()
+        })
+        .unwrap_or_else(Utc::now)
+        .date_naive();
+
+    partition_id(index_id, date)
+}
+
+/// A write of a document to an index, for [`group_by_target`] to route to partitions.
+#[derive(Clone, Debug)]
+pub enum WriteOp {
+    Index(SearchDoc),
+    Delete(SearchDocId),
+}
+
+impl WriteOp {
+    pub fn doc_id(&self) -> &str {
+        match self {
+            WriteOp::Index(document) => document.id().id(),
+            WriteOp::Delete(doc_id) => doc_id.id(),
+        }
+    }
+
+    /// The document indexed, None for deletes.
+    pub fn document(&self) -> Option<&SearchDoc> {
+        match self {
+            WriteOp::Index(document) => Some(document),
+            WriteOp::Delete(_) => None,
+        }
+    }
+}
+
+/// Groups `ops` written to `index_id` by the index each lands in, keeping their order. Ordinary
+/// indexes have a single partition, the index itself. On the alias of a rolling index, documents
+/// land in the partition of their day, and deletes in each of `partitions`, since which partition
+/// holds the document isn't known.
+pub fn group_by_target(
+    schema_loader: &dyn SchemaLoader,
+    index_id: &str,
+    settings: &IndexSettings,
+    schema: &Schema,
+    partitions: &[String],
+    ops: Vec<WriteOp>,
+) -> BTreeMap<String, Vec<WriteOp>> {
+    let is_alias = alias_config(schema_loader, index_id, settings).is_some();
+
+    let mut grouped: BTreeMap<String, Vec<WriteOp>> = BTreeMap::new();
+    for op in ops {
+        match &op {
+            WriteOp::Index(document) => {
+                let target = target_index(schema_loader, index_id, settings, schema, document);
+                grouped.entry(target).or_default().push(op);
+            }
+            WriteOp::Delete(_) if is_alias => {
+                for partition in partitions {
+                    grouped
+                        .entry(partition.clone())
+                        .or_default()
+                        .push(op.clone());
+                }
+            }
+            WriteOp::Delete(_) => grouped.entry(index_id.into()).or_default().push(op),
+        }
+    }
+    grouped
+}
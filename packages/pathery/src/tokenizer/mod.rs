@@ -0,0 +1,476 @@
+//! Custom tokenizers registered on every index.
+
+pub mod stopwords;
+
+use std::net::{IpAddr, Ipv6Addr};
+
+use serde::{Deserialize, Serialize};
+use tantivy::schema::{FieldEntry, FieldType};
+use tantivy::tokenizer::{
+    self, AsciiFoldingFilter, BoxTokenStream, LowerCaser, NgramTokenizer, RemoveLongFilter,
+    SimpleTokenizer, Stemmer, StopWordFilter, TextAnalyzer, Token, TokenStream, Tokenizer,
+};
+use tantivy::Index;
+
+use crate::json;
+
+/// Tokenizer name for `ip` fields.
+pub const IP_TOKENIZER: &str = "ip";
+
+/// Analyzer for a `text` field, set with `tokenizer` in the field config.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum TokenizerConfig {
+    /// Splits on non-alphanumeric characters, lowercases and drops tokens over 40 bytes.
+    Default,
+    /// The whole value as a single token, for exact matches.
+    Raw,
+    /// Splits on whitespace only, preserving case and punctuation.
+    Whitespace,
+    /// Lowercased character n-grams of the value, for partial matches. With `prefix_only`, only
+    /// n-grams starting at the beginning of the value, for autocompletion.
+    Ngram {
+        min_gram: usize,
+        max_gram: usize,
+        #[serde(default)]
+        prefix_only: bool,
+    },
+}
+
+impl TokenizerConfig {
+    /// Name the tokenizer is registered under, stored in the index schema. N-gram parameters are
+    /// part of the name so that [`register_tokenizers`] can rebuild the tokenizer from the schema
+    /// alone.
+    pub fn name(&self) -> String {
+        match self {
+            TokenizerConfig::Default => String::from("default"),
+            TokenizerConfig::Raw => String::from("raw"),
+            TokenizerConfig::Whitespace => String::from("whitespace"),
+            TokenizerConfig::Ngram {
+                min_gram,
+                max_gram,
+                prefix_only: false,
+            } => format!("ngram_{}_{}", min_gram, max_gram),
+            TokenizerConfig::Ngram {
+                min_gram,
+                max_gram,
+                prefix_only: true,
+            } => format!("edge_ngram_{}_{}", min_gram, max_gram),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            TokenizerConfig::Ngram {
+                min_gram, max_gram, ..
+            } if *min_gram == 0 || min_gram > max_gram => Err(format!(
+                "ngram tokenizer requires 0 < min_gram <= max_gram, found {} and {}",
+                min_gram, max_gram
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Stemming language for a `text` field, set with `language` in the field config.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    Arabic,
+    Danish,
+    Dutch,
+    English,
+    Finnish,
+    French,
+    German,
+    Greek,
+    Hungarian,
+    Italian,
+    Norwegian,
+    Portuguese,
+    Romanian,
+    Russian,
+    Spanish,
+    Swedish,
+    Tamil,
+    Turkish,
+}
+
+impl Language {
+    /// Name of the tokenizer that splits and lowercases like `default`, then stems.
+    pub fn tokenizer_name(self) -> String {
+        let language = json::to_value(self).expect("language should serialize");
+        format!("stem_{}", language.as_str().expect("language is a string"))
+    }
+
+    fn stemmer(self) -> Stemmer {
+        use tokenizer::Language as Stem;
+
+        Stemmer::new(match self {
+            Language::Arabic => Stem::Arabic,
+            Language::Danish => Stem::Danish,
+            Language::Dutch => Stem::Dutch,
+            Language::English => Stem::English,
+            Language::Finnish => Stem::Finnish,
+            Language::French => Stem::French,
+            Language::German => Stem::German,
+            Language::Greek => Stem::Greek,
+            Language::Hungarian => Stem::Hungarian,
+            Language::Italian => Stem::Italian,
+            Language::Norwegian => Stem::Norwegian,
+            Language::Portuguese => Stem::Portuguese,
+            Language::Romanian => Stem::Romanian,
+            Language::Russian => Stem::Russian,
+            Language::Spanish => Stem::Spanish,
+            Language::Swedish => Stem::Swedish,
+            Language::Tamil => Stem::Tamil,
+            Language::Turkish => Stem::Turkish,
+        })
+    }
+}
+
+/// Rebuilds a stemming analyzer from a name produced by [`Language::tokenizer_name`].
+fn stem_analyzer(name: &str) -> Option<TextAnalyzer> {
+    let language = name.strip_prefix("stem_")?;
+    let language: Language = json::from_value(json::Value::String(language.into())).ok()?;
+
+    Some(
+        FieldAnalyzer {
+            language: Some(language),
+            ..Default::default()
+        }
+        .build(),
+    )
+}
+
+/// Words dropped from a `text` field during analysis, set with `stopwords` in the field config.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct StopwordsConfig {
+    /// Starts from the built-in list for this language.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<Language>,
+
+    /// Additional words, matched ignoring case.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub words: Vec<String>,
+}
+
+impl StopwordsConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        match self.language {
+            Some(language) if stopwords::builtin(language).is_none() => Err(format!(
+                "no built-in stopwords for {}",
+                json::to_value(language).expect("language should serialize")
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn words(&self) -> Vec<String> {
+        let builtin = self
+            .language
+            .and_then(stopwords::builtin)
+            .unwrap_or_default();
+
+        builtin
+            .iter()
+            .map(|word| word.to_string())
+            .chain(self.words.iter().map(|word| word.to_lowercase()))
+            .collect()
+    }
+}
+
+/// Analysis of a `text` field that depends on its config rather than only on a tokenizer name:
+/// splits and lowercases like `default`, then drops stopwords, folds to ASCII and stems. Registered
+/// under [`FieldAnalyzer::tokenizer_name`] whenever the index is loaded, so changes to the config
+/// take effect on the next load and require a reindex to apply to existing documents.
+#[derive(Debug, Clone, Default)]
+pub struct FieldAnalyzer {
+    pub language: Option<Language>,
+    pub stopwords: Option<StopwordsConfig>,
+    pub ascii_folding: bool,
+}
+
+impl FieldAnalyzer {
+    /// Whether analysis needs a per-field analyzer, rather than one of the shared analyzers
+    /// named after a language or tokenizer config.
+    pub fn is_custom(&self) -> bool {
+        self.stopwords.is_some() || self.ascii_folding
+    }
+
+    pub fn tokenizer_name(field_name: &str) -> String {
+        format!("field_{}", field_name)
+    }
+
+    pub fn build(&self) -> TextAnalyzer {
+        let mut analyzer = TextAnalyzer::from(SimpleTokenizer)
+            .filter(RemoveLongFilter::limit(40))
+            .filter(LowerCaser);
+
+        // Stopwords are matched before stemming, so lists hold whole words.
+        if let Some(stopwords) = &self.stopwords {
+            analyzer = analyzer.filter(StopWordFilter::remove(stopwords.words()));
+        }
+        // Folded before stemming, so "cafés" and "cafes" stem alike.
+        if self.ascii_folding {
+            analyzer = analyzer.filter(AsciiFoldingFilter);
+        }
+        if let Some(language) = self.language {
+            analyzer = analyzer.filter(language.stemmer());
+        }
+
+        analyzer
+    }
+}
+
+/// Rebuilds an n-gram analyzer from a name produced by [`TokenizerConfig::name`].
+fn ngram_analyzer(name: &str) -> Option<TextAnalyzer> {
+    let (prefix_only, grams) = match name.strip_prefix("edge_ngram_") {
+        Some(grams) => (true, grams),
+        None => (false, name.strip_prefix("ngram_")?),
+    };
+
+    let (min_gram, max_gram) = grams.split_once('_')?;
+    let (min_gram, max_gram) = (min_gram.parse().ok()?, max_gram.parse().ok()?);
+
+    TokenizerConfig::Ngram {
+        min_gram,
+        max_gram,
+        prefix_only,
+    }
+    .validate()
+    .ok()?;
+
+    Some(
+        TextAnalyzer::from(NgramTokenizer::new(min_gram, max_gram, prefix_only)).filter(LowerCaser),
+    )
+}
+
+fn indexing_tokenizer(entry: &FieldEntry) -> Option<&str> {
+    match entry.field_type() {
+        FieldType::Str(options) => options
+            .get_indexing_options()
+            .map(|indexing| indexing.tokenizer()),
+        _ => None,
+    }
+}
+
+/// Whether snippets can be generated for the field. N-gram tokens overlap, which snippet
+/// fragments can't represent.
+pub fn supports_snippets(entry: &FieldEntry) -> bool {
+    indexing_tokenizer(entry).is_none_or(|name| ngram_analyzer(name).is_none())
+}
+
+/// Registers the tokenizers used by the index's fields that tantivy doesn't provide. `analyzers`
+/// are the [`FieldAnalyzer`]s from the index's config.
+pub fn register_tokenizers(index: &Index, analyzers: Vec<(String, TextAnalyzer)>) {
+    let tokenizers = index.tokenizers();
+    tokenizers.register(IP_TOKENIZER, IpTokenizer);
+
+    for (name, analyzer) in analyzers {
+        tokenizers.register(&name, analyzer);
+    }
+
+    let schema = index.schema();
+
+    for (_, entry) in schema.fields() {
+        if let Some(name) = indexing_tokenizer(entry) {
+            if let Some(analyzer) = ngram_analyzer(name).or_else(|| stem_analyzer(name)) {
+                tokenizers.register(name, analyzer);
+            }
+        }
+    }
+}
+
+/// Parses an IPv4 or IPv6 address, with IPv4 addresses mapped into the IPv6 space.
+pub fn parse_ip(text: &str) -> Option<Ipv6Addr> {
+    match text.trim().parse::<IpAddr>().ok()? {
+        IpAddr::V4(addr) => Some(addr.to_ipv6_mapped()),
+        IpAddr::V6(addr) => Some(addr),
+    }
+}
+
+/// Hex encoding of an address whose lexicographic order matches numeric order, so range
+/// queries over the term dictionary are address ranges.
+pub fn ip_term(addr: Ipv6Addr) -> String {
+    format!("{:032x}", u128::from(addr))
+}
+
+/// Parses a CIDR block such as `10.0.0.0/16` into its first and last address.
+pub fn parse_cidr(text: &str) -> Option<(Ipv6Addr, Ipv6Addr)> {
+    let (addr, prefix_len) = text.split_once('/')?;
+    let ipv4 = addr.trim().parse::<IpAddr>().ok()?.is_ipv4();
+    let prefix_len = prefix_len.trim().parse::<u32>().ok()?;
+
+    // IPv4 prefixes apply to the low 32 bits of the mapped address.
+    let prefix_len = if ipv4 { prefix_len + 96 } else { prefix_len };
+    if prefix_len > 128 || (ipv4 && prefix_len < 96) {
+        return None;
+    }
+
+    let host_mask = u128::MAX.checked_shr(prefix_len).unwrap_or(0);
+    let addr = u128::from(parse_ip(addr)?);
+
+    Some(((addr & !host_mask).into(), (addr | host_mask).into()))
+}
+
+/// Emits a single [`ip_term`] for a valid address and nothing otherwise.
+#[derive(Clone)]
+pub struct IpTokenizer;
+
+pub struct IpTokenStream {
+    token: Option<Token>,
+    advanced: bool,
+}
+
+impl Tokenizer for IpTokenizer {
+    fn token_stream<'a>(&self, text: &'a str) -> BoxTokenStream<'a> {
+        let token = parse_ip(text).map(|addr| Token {
+            offset_from: 0,
+            offset_to: text.len(),
+            position: 0,
+            text: ip_term(addr),
+            position_length: 1,
+        });
+
+        BoxTokenStream::from(IpTokenStream {
+            token,
+            advanced: false,
+        })
+    }
+}
+
+impl TokenStream for IpTokenStream {
+    fn advance(&mut self) -> bool {
+        if self.advanced {
+            self.token = None;
+        }
+        self.advanced = true;
+        self.token.is_some()
+    }
+
+    fn token(&self) -> &Token {
+        self.token
+            .as_ref()
+            .expect("token should be available after advance")
+    }
+
+    fn token_mut(&mut self) -> &mut Token {
+        self.token
+            .as_mut()
+            .expect("token should be available after advance")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ip_terms_sort_numerically() {
+        let term = |text: &str| ip_term(parse_ip(text).unwrap());
+
+        assert!(term("9.255.255.255") < term("10.0.0.0"));
+        assert!(term("10.0.0.2") < term("10.0.0.10"));
+        assert_eq!(term("::ffff:10.0.0.1"), term("10.0.0.1"));
+        assert_eq!(None, parse_ip("10.0.0.256"));
+    }
+
+    #[test]
+    fn parse_cidr_blocks() {
+        let (first, last) = parse_cidr("10.0.0.0/16").unwrap();
+        assert_eq!(parse_ip("10.0.0.0").unwrap(), first);
+        assert_eq!(parse_ip("10.0.255.255").unwrap(), last);
+
+        let (first, last) = parse_cidr("2001:db8::/32").unwrap();
+        assert_eq!(parse_ip("2001:db8::").unwrap(), first);
+        assert_eq!(
+            parse_ip("2001:db8:ffff:ffff:ffff:ffff:ffff:ffff").unwrap(),
+            last
+        );
+
+        assert_eq!(None, parse_cidr("10.0.0.0/33"));
+        assert_eq!(None, parse_cidr("10.0.0.0"));
+    }
+
+    #[test]
+    fn ngram_analyzers_round_trip_through_names() {
+        let config = TokenizerConfig::Ngram {
+            min_gram: 2,
+            max_gram: 3,
+            prefix_only: true,
+        };
+
+        let mut tokens = vec![];
+        ngram_analyzer(&config.name())
+            .unwrap()
+            .token_stream("Hello")
+            .process(&mut |token| tokens.push(token.text.clone()));
+
+        assert_eq!(vec!["he", "hel"], tokens);
+        assert!(ngram_analyzer("ngram_3_2").is_none());
+        assert!(ngram_analyzer("default").is_none());
+    }
+
+    #[test]
+    fn stem_analyzers_round_trip_through_names() {
+        let stem = |language: Language, text: &str| {
+            let mut tokens = vec![];
+            stem_analyzer(&language.tokenizer_name())
+                .unwrap()
+                .token_stream(text)
+                .process(&mut |token| tokens.push(token.text.clone()));
+            tokens
+        };
+
+        assert_eq!("stem_english", Language::English.tokenizer_name());
+        assert_eq!(
+            vec!["fish", "fish"],
+            stem(Language::English, "Fishing fished")
+        );
+        assert_eq!(vec!["katz"], stem(Language::German, "Katzen"));
+        assert!(stem_analyzer("stem_klingon").is_none());
+    }
+
+    #[test]
+    fn field_analyzer_drops_stopwords_before_stemming() {
+        let analyzer = FieldAnalyzer {
+            language: Some(Language::English),
+            stopwords: Some(StopwordsConfig {
+                language: Some(Language::English),
+                words: vec!["Fishing".into()],
+            }),
+            ..Default::default()
+        };
+
+        let mut tokens = vec![];
+        analyzer
+            .build()
+            .token_stream("The fishing of the fished")
+            .process(&mut |token| tokens.push(token.text.clone()));
+
+        assert_eq!(vec!["fish"], tokens);
+        assert!(StopwordsConfig {
+            language: Some(Language::Tamil),
+            words: vec![],
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn field_analyzer_folds_to_ascii() {
+        let analyzer = FieldAnalyzer {
+            ascii_folding: true,
+            ..Default::default()
+        };
+
+        let mut tokens = vec![];
+        analyzer
+            .build()
+            .token_stream("Café Müller")
+            .process(&mut |token| tokens.push(token.text.clone()));
+
+        assert_eq!(vec!["cafe", "muller"], tokens);
+    }
+}
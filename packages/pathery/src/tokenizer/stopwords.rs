@@ -0,0 +1,61 @@
+//! Built-in stopword lists, matched against lowercased tokens before stemming.
+
+use super::Language;
+
+const ENGLISH: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "if", "in", "into", "is", "it",
+    "no", "not", "of", "on", "or", "such", "that", "the", "their", "then", "there", "these",
+    "they", "this", "to", "was", "will", "with",
+];
+
+const DUTCH: &[&str] = &[
+    "aan", "al", "als", "bij", "dat", "de", "den", "der", "die", "dit", "een", "en", "er", "het",
+    "hij", "in", "is", "je", "maar", "met", "naar", "niet", "of", "om", "ook", "op", "te", "tot",
+    "uit", "van", "voor", "was", "wat", "ze", "zich", "zijn",
+];
+
+const FRENCH: &[&str] = &[
+    "au", "aux", "avec", "ce", "ces", "dans", "de", "des", "du", "elle", "en", "et", "il", "ils",
+    "je", "la", "le", "les", "leur", "lui", "ma", "mais", "me", "mes", "ne", "nous", "on", "ou",
+    "par", "pas", "pour", "qu", "que", "qui", "sa", "se", "ses", "son", "sur", "ta", "te", "tu",
+    "un", "une", "vous",
+];
+
+const GERMAN: &[&str] = &[
+    "aber", "als", "am", "an", "auch", "auf", "aus", "bei", "das", "dass", "dem", "den", "der",
+    "des", "die", "ein", "eine", "einem", "einen", "einer", "es", "für", "im", "in", "ist", "mit",
+    "nach", "nicht", "noch", "oder", "sich", "sie", "sind", "so", "und", "von", "vor", "war",
+    "wie", "zu", "zum", "zur",
+];
+
+const ITALIAN: &[&str] = &[
+    "a", "al", "alla", "anche", "che", "chi", "con", "da", "dal", "del", "della", "dei", "di", "e",
+    "gli", "ha", "il", "in", "la", "le", "lo", "ma", "nel", "nella", "non", "o", "per", "più",
+    "se", "si", "sono", "su", "sul", "tra", "un", "una", "uno",
+];
+
+const PORTUGUESE: &[&str] = &[
+    "a", "ao", "as", "com", "como", "da", "das", "de", "do", "dos", "e", "ela", "ele", "em",
+    "entre", "era", "foi", "mais", "mas", "na", "nas", "no", "nos", "não", "o", "os", "ou", "para",
+    "por", "que", "se", "sem", "seu", "sua", "um", "uma",
+];
+
+const SPANISH: &[&str] = &[
+    "a", "al", "como", "con", "de", "del", "el", "en", "es", "esta", "este", "la", "las", "le",
+    "lo", "los", "más", "no", "o", "para", "pero", "por", "que", "se", "sin", "su", "sus", "un",
+    "una", "y", "ya",
+];
+
+/// The built-in list for `language`, if there is one.
+pub fn builtin(language: Language) -> Option<&'static [&'static str]> {
+    match language {
+        Language::Dutch => Some(DUTCH),
+        Language::English => Some(ENGLISH),
+        Language::French => Some(FRENCH),
+        Language::German => Some(GERMAN),
+        Language::Italian => Some(ITALIAN),
+        Language::Portuguese => Some(PORTUGUESE),
+        Language::Spanish => Some(SPANISH),
+        _ => None,
+    }
+}
@@ -1,6 +1,9 @@
+use std::future::Future;
 use std::time::SystemTime;
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, Offset, TimeZone, Utc};
+use chrono_tz::Tz;
+use tokio::runtime::Handle;
 
 pub fn generate_id() -> String {
     let id = uuid::Uuid::new_v4();
@@ -13,6 +16,65 @@ pub fn timestamp() -> String {
     now.to_rfc3339()
 }
 
+/// A write token for an op submitted now: the time in microseconds, so later writes get larger
+/// tokens as far as the clocks of the Lambdas submitting them agree.
+pub fn write_token() -> u64 {
+    Utc::now().timestamp_micros() as u64
+}
+
 pub fn require_env(var_name: &str) -> String {
-    std::env::var(var_name).expect(&format!("{var_name:?} should be set"))
+    std::env::var(var_name).unwrap_or_else(|_| panic!("{var_name:?} should be set"))
+}
+
+/// Waits on `future` from synchronous code, such as a trait method that needs a network lookup.
+/// The blocked worker's other tasks move to another thread while it waits, which needs the
+/// multi-threaded runtime.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    tokio::task::block_in_place(|| Handle::current().block_on(future))
+}
+
+/// Parses an RFC 3339 date, or a date or date time without an offset which is interpreted as
+/// local time in `time_zone`. Local times repeated by a daylight saving change resolve to the
+/// earliest instant; local times skipped by one don't parse.
+pub fn parse_date(value: &str, time_zone: Tz) -> Option<DateTime<FixedOffset>> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        return Some(date);
+    }
+
+    let local = ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .ok()?
+                .and_hms_opt(0, 0, 0)
+        })?;
+
+    let date = time_zone.from_local_datetime(&local).earliest()?;
+    Some(date.with_timezone(&date.offset().fix()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_date_uses_time_zone_without_offset() {
+        let tz: Tz = "America/New_York".parse().unwrap();
+
+        assert_eq!(
+            "2022-11-14T21:30:04+00:00",
+            parse_date("2022-11-14T21:30:04Z", tz).unwrap().to_rfc3339()
+        );
+        assert_eq!(
+            "2022-11-14T21:30:04-05:00",
+            parse_date("2022-11-14T21:30:04", tz).unwrap().to_rfc3339()
+        );
+        assert_eq!(
+            "2022-07-01T00:00:00-04:00",
+            parse_date("2022-07-01", tz).unwrap().to_rfc3339()
+        );
+        assert_eq!(None, parse_date("2022-03-13T02:30:00", tz));
+        assert_eq!(None, parse_date("yesterday", tz));
+    }
 }
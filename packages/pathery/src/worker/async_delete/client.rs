@@ -1,4 +1,5 @@
 use std::fmt::Debug;
+use std::{fs, io};
 
 use async_trait::async_trait;
 
@@ -51,3 +52,24 @@ impl LambdaAsyncDeleteClient {
         }
     }
 }
+
+/// Deletes files right away instead of queueing them, for local data directories that no other
+/// instance reads from.
+#[derive(Debug)]
+pub struct LocalDeleteClient;
+
+#[async_trait]
+impl AsyncDeleteClient for LocalDeleteClient {
+    async fn submit_job(&self, job: AsyncDeleteJob) -> Result<String, ServiceError> {
+        match job {
+            AsyncDeleteJob::FSDelete(path) => match fs::remove_file(path) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                    return Err(ServiceError::internal_error(err))
+                }
+                _ => {}
+            },
+        }
+
+        Ok(util::generate_id())
+    }
+}
@@ -0,0 +1,127 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json as json;
+use tracing::warn;
+
+use crate::catalog::{self, CATALOG_INDEX_ID};
+use crate::index::IndexLoader;
+use crate::lambda;
+use crate::lambda::lambda_runtime::LambdaEvent;
+use crate::schema::SchemaLoader;
+use crate::store::lease::LeaseStore;
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct CatalogReport {
+    /// Indexes in the rebuilt catalog, none when another run held the catalog's lease.
+    pub entries: usize,
+}
+
+/// Rebuilds the [catalog](crate::catalog) on a schedule. A run that finds another still holding
+/// the catalog's lease leaves the catalog to it.
+pub async fn handle_event(
+    index_loader: &dyn IndexLoader,
+    schema_loader: &dyn SchemaLoader,
+    lease_store: &dyn LeaseStore,
+    event: LambdaEvent<json::Value>,
+) -> Result<CatalogReport, lambda::Error> {
+    let owner = event.context.request_id.clone();
+    let expires_at =
+        DateTime::<Utc>::from(UNIX_EPOCH + Duration::from_millis(event.context.deadline));
+
+    if !lease_store
+        .acquire(CATALOG_INDEX_ID, &owner, expires_at)
+        .await?
+    {
+        warn!(message = "index_locked", index = CATALOG_INDEX_ID);
+        return Ok(CatalogReport { entries: 0 });
+    }
+
+    let result = catalog::rebuild(index_loader, schema_loader);
+    lease_store.release(CATALOG_INDEX_ID, &owner).await?;
+
+    Ok(CatalogReport { entries: result? })
+}
+
+#[cfg(test)]
+mod tests {
+    use lambda_http::Context;
+
+    use super::*;
+    use crate::cursor::CursorKey;
+    use crate::service::index::QueryIndexService;
+    use crate::service::{ServiceHandler, ServiceRequest};
+    use crate::store::lease::test_util::TestLeaseStore;
+    use crate::test_utils::*;
+
+    #[tokio::test]
+    async fn catalog_lists_indexes_searchable_by_their_fields() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![json!({ "title": "hello" }), json!({ "title": "world" })],
+            )
+            .await
+            .with_documents("acme~searchonly", vec![json!({ "title": "hello" })])
+            .await;
+
+        let report = handle_event(
+            ctx.index_loader(),
+            ctx.schema_loader(),
+            &TestLeaseStore::create(),
+            LambdaEvent::new(json!({}), Context::default()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(2, report.entries);
+
+        let service = QueryIndexService::new(
+            Box::new(ctx.schema_loader().clone()),
+            Box::new(ctx.index_loader().clone()),
+            Box::new(ctx.document_store().clone()),
+            CursorKey::new(b"test"),
+        );
+        let body = json!({ "query": { "term": { "field": "fields", "value": "price" } } });
+        let request = ServiceRequest::create_raw(&body.to_string())
+            .with_path_param("index_id", CATALOG_INDEX_ID);
+        let response = service.handle_request(request).await.unwrap();
+
+        assert_eq!(1, response.matches.len());
+        let doc = &response.matches[0].doc;
+        assert_eq!(json!(["acme~searchonly"]), doc["index_id"]);
+        assert_eq!(json!(["acme"]), doc["tenant"]);
+        assert_eq!(json!(["searchonly"]), doc["prefix"]);
+        assert_eq!(json!(["search-team"]), doc["owner"]);
+        assert_eq!(json!(["products", "pricing"]), doc["tags"]);
+        assert_eq!(json!([1]), doc["num_docs"]);
+        assert_eq!(json!(["title", "price"]), doc["fields"]);
+    }
+
+    #[tokio::test]
+    async fn catalog_is_left_to_the_run_holding_its_lease() {
+        let ctx = setup()
+            .with_documents("test", vec![json!({ "title": "hello" })])
+            .await;
+        let lease_store = TestLeaseStore::create();
+        lease_store
+            .acquire(
+                CATALOG_INDEX_ID,
+                "other",
+                Utc::now() + chrono::Duration::minutes(1),
+            )
+            .await
+            .unwrap();
+
+        let report = handle_event(
+            ctx.index_loader(),
+            ctx.schema_loader(),
+            &lease_store,
+            LambdaEvent::new(json!({}), Context::default()),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(0, report.entries);
+    }
+}
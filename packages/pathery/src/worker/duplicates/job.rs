@@ -0,0 +1,21 @@
+use serde::{Deserialize, Serialize};
+
+/// Reports the groups of documents in an index that share a value.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct DuplicatesJob {
+    pub index_id: String,
+
+    /// Field whose value groups documents. Defaults to grouping documents with the same content
+    /// apart from `__id`.
+    #[serde(default)]
+    pub field: Option<String>,
+}
+
+impl DuplicatesJob {
+    pub fn create(index_id: &str) -> DuplicatesJob {
+        DuplicatesJob {
+            index_id: index_id.into(),
+            field: None,
+        }
+    }
+}
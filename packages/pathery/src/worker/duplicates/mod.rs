@@ -0,0 +1,216 @@
+pub mod job;
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json as json;
+use tracing::info;
+
+use self::job::DuplicatesJob;
+use crate::index::{IndexExt, IndexLoader};
+use crate::lambda::{self, sqs};
+use crate::schema::{fingerprint, SchemaLoader};
+use crate::service::ServiceError;
+use crate::store::document::DocumentStore;
+use crate::store::report::ReportStore;
+use crate::util;
+
+/// Number of documents fetched from the document store at a time.
+const BATCH_SIZE: usize = 100;
+
+/// Documents that share a value.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct DuplicateCluster {
+    /// The shared value, or for documents grouped by content a hash of it.
+    pub key: String,
+
+    pub doc_ids: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct DuplicateReport {
+    pub index_id: String,
+
+    /// The field documents were grouped by, unset when they were grouped by content.
+    pub field: Option<String>,
+
+    /// Number of documents examined.
+    pub documents: usize,
+
+    /// Groups of more than one document, largest first.
+    pub clusters: Vec<DuplicateCluster>,
+
+    pub created_at: String,
+}
+
+/// The key grouping a document. Documents without a value for the field aren't grouped.
+fn group_key(content: &json::Map<String, json::Value>, field: Option<&str>) -> Option<String> {
+    match field {
+        Some(field) => match content.get(field)? {
+            json::Value::String(value) => Some(value.clone()),
+            json::Value::Null => None,
+            value => Some(value.to_string()),
+        },
+        None => {
+            let mut content = content.clone();
+            content.remove("__id");
+            Some(fingerprint(
+                &json::to_vec(&content).expect("content should serialize"),
+            ))
+        }
+    }
+}
+
+/// Groups the documents of an index by `job.field`, or by content, reading every document from
+/// the document store.
+pub async fn find_duplicates(
+    document_store: &dyn DocumentStore,
+    index_loader: &dyn IndexLoader,
+    schema_loader: &dyn SchemaLoader,
+    job: &DuplicatesJob,
+) -> Result<DuplicateReport, ServiceError> {
+    let index_id = job.index_id.as_str();
+    let settings = schema_loader.load_settings(index_id)?;
+    if settings.search_only {
+        return Err(ServiceError::invalid_request(&format!(
+            "Index [{}] is search only, its documents aren't available to compare",
+            index_id
+        )));
+    }
+
+    let index = index_loader.load_index(index_id, None)?;
+    if let Some(field) = &job.field {
+        if !settings.dynamic && index.schema().get_field(field).is_none() {
+            return Err(ServiceError::invalid_request(&format!(
+                "Field [{}] is not in the schema of index [{}]",
+                field, index_id
+            )));
+        }
+    }
+
+    let doc_refs = index.doc_refs()?;
+
+    let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for batch in doc_refs.chunks(BATCH_SIZE) {
+        for doc in document_store.get_documents(batch.to_vec()).await? {
+            if let Some(key) = group_key(doc.content(), job.field.as_deref()) {
+                groups
+                    .entry(key)
+                    .or_default()
+                    .push(doc.id().id().to_string());
+            }
+        }
+    }
+
+    let mut clusters = groups
+        .into_iter()
+        .filter(|(_, doc_ids)| doc_ids.len() > 1)
+        .map(|(key, mut doc_ids)| {
+            doc_ids.sort();
+            DuplicateCluster { key, doc_ids }
+        })
+        .collect::<Vec<_>>();
+    clusters.sort_by_key(|cluster| std::cmp::Reverse(cluster.doc_ids.len()));
+
+    Ok(DuplicateReport {
+        index_id: index_id.into(),
+        field: job.field.clone(),
+        documents: doc_refs.len(),
+        clusters,
+        created_at: util::timestamp(),
+    })
+}
+
+/// Runs each duplicate detection job, saving its report as
+/// `reports/{index_id}/duplicates-{created_at}.json`.
+pub async fn handle_event(
+    document_store: &dyn DocumentStore,
+    index_loader: &dyn IndexLoader,
+    schema_loader: &dyn SchemaLoader,
+    report_store: &dyn ReportStore,
+    event: sqs::SqsEvent,
+) -> Result<(), lambda::Error> {
+    for message in event.payload.records {
+        let body = message.body.as_ref().expect("Body should be present");
+        let job = json::from_str::<DuplicatesJob>(body.as_str())
+            .expect("Message should be deserializable");
+
+        let report = find_duplicates(document_store, index_loader, schema_loader, &job).await?;
+
+        let key = format!(
+            "reports/{}/duplicates-{}.json",
+            report.index_id, report.created_at
+        );
+        report_store
+            .put_report(
+                &key,
+                &json::to_value(&report).expect("report should serialize"),
+            )
+            .await?;
+
+        info!(
+            message = "duplicates_reported",
+            index = report.index_id,
+            documents = report.documents,
+            clusters = report.clusters.len(),
+            key
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[tokio::test]
+    async fn duplicates_are_grouped_by_field_or_content() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![
+                    json!({ "__id": "a", "title": "Hello", "author": "ann" }),
+                    json!({ "__id": "b", "title": "Hello", "author": "bob" }),
+                    json!({ "__id": "c", "title": "Hello", "author": "bob" }),
+                    json!({ "__id": "d", "title": "Goodbye", "author": "bob" }),
+                ],
+            )
+            .await;
+
+        let find = |field: Option<&str>| {
+            let job = DuplicatesJob {
+                index_id: "test".into(),
+                field: field.map(String::from),
+            };
+            let ctx = &ctx;
+            async move {
+                find_duplicates(
+                    ctx.document_store(),
+                    ctx.index_loader(),
+                    ctx.schema_loader(),
+                    &job,
+                )
+                .await
+            }
+        };
+
+        let report = find(Some("author")).await.unwrap();
+        assert_eq!(4, report.documents);
+        assert_eq!(
+            vec![DuplicateCluster {
+                key: "bob".into(),
+                doc_ids: vec!["b".into(), "c".into(), "d".into()],
+            }],
+            report.clusters
+        );
+
+        let report = find(None).await.unwrap();
+        assert_eq!(1, report.clusters.len());
+        assert_eq!(vec!["b", "c"], report.clusters[0].doc_ids);
+
+        let err = find(Some("missing")).await.unwrap_err();
+        assert_eq!(400, err.status());
+    }
+}
@@ -0,0 +1,494 @@
+//! Keeps an index in step with a DynamoDB table by consuming the table's stream. Items the
+//! stream inserts or modifies are indexed under an id made of their key, and those it removes
+//! are deleted, as configured by the index's `dynamodb_stream` setting:
+//!
+//! ```json
+//! {
+//!   "dynamodb_stream": {
+//!     "id_attributes": ["pk", "sk"],
+//!     "attributes": { "Title": "title", "Author.Name": "author" }
+//!   }
+//! }
+//! ```
+//!
+//! The stream must include new images. Items that don't make valid documents are logged and
+//! left out rather than holding up the rest of the stream.
+//!
+//! On the alias of a rolling index, items are indexed into the partition of their day. Removed
+//! items are deleted from every retained partition, as are the old copies of modified items,
+//! since the day an item was indexed under isn't known.
+
+use std::collections::{BTreeMap, HashMap};
+
+use aws_lambda_events::event::dynamodb::attributes::AttributeValue;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::index::IndexLoader;
+use crate::lambda::dynamodb::DynamoDbEvent;
+use crate::schema::SchemaLoader;
+use crate::search_doc::{SearchDoc, SearchDocId};
+use crate::service::index::{MAX_DOCS_PER_JOB, MAX_DOCS_PER_SAVE};
+use crate::service::ServiceError;
+use crate::store::document::DocumentStore;
+use crate::time_partition::WriteOp;
+use crate::worker::index_writer::client::IndexWriterClient;
+use crate::worker::index_writer::job::Job;
+use crate::{json, lambda, time_partition};
+
+/// Maps the items of a DynamoDB table to the documents of the index its stream is consumed into.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DynamoDbStreamConfig {
+    /// Key attributes whose values, joined by `#`, make up each document's `__id`, e.g.
+    /// `["pk", "sk"]`.
+    pub id_attributes: Vec<String>,
+
+    /// Attributes to index, to the document field each is mapped to, e.g. `{ "Title": "title" }`.
+    /// Attributes of maps are named by their path, e.g. `Author.Name`. Defaults to every
+    /// top-level attribute, under its own name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub attributes: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct StreamReport {
+    pub index_id: String,
+
+    /// Documents indexed for inserted and modified items.
+    pub indexed: usize,
+
+    /// Documents deleted for removed items.
+    pub deleted: usize,
+
+    /// Records whose items couldn't be mapped to documents.
+    pub skipped: usize,
+
+    pub job_ids: Vec<String>,
+}
+
+/// Indexes the changes of a batch of stream records into `index_id`. Only the last change to
+/// each item is applied, as writer jobs apply their deletes before their documents.
+pub async fn handle_event(
+    schema_loader: &dyn SchemaLoader,
+    index_loader: &dyn IndexLoader,
+    document_store: &dyn DocumentStore,
+    writer_client: &dyn IndexWriterClient,
+    index_id: &str,
+    event: DynamoDbEvent,
+) -> Result<StreamReport, lambda::Error> {
+    let schema = schema_loader.load_schema(index_id)?;
+    let settings = schema_loader.load_settings(index_id)?;
+    let config = settings.dynamodb_stream.as_ref().ok_or_else(|| {
+        ServiceError::invalid_request(&format!(
+            "Index [{}] has no dynamodb_stream setting",
+            index_id
+        ))
+    })?;
+
+    let rolling = time_partition::alias_config(schema_loader, index_id, &settings);
+
+    let mut changes: BTreeMap<String, Vec<WriteOp>> = BTreeMap::new();
+    let mut skipped = 0;
+
+    for record in event.payload.records {
+        let op = document_id(config, &record.change.keys).and_then(|id| {
+            match record.event_name.as_str() {
+                name @ ("INSERT" | "MODIFY") => {
+                    let mut content = document(config, &record.change.new_image)?;
+                    content.insert("__id".into(), id.clone().into());
+                    let document = SearchDoc::from_json_with_settings(
+                        &schema,
+                        json::Value::Object(content),
+                        &settings,
+                    )
+                    .map_err(|err| err.to_string())?;
+                    // A modified item may have moved to another day's partition.
+                    let mut ops = vec![];
+                    if name == "MODIFY" && rolling.is_some() {
+                        ops.push(WriteOp::Delete(SearchDocId::parse(&id)));
+                    }
+                    ops.push(WriteOp::Index(document));
+                    Ok((id, ops))
+                }
+                "REMOVE" => Ok((id.clone(), vec![WriteOp::Delete(SearchDocId::parse(&id))])),
+                name => Err(format!("Unknown event [{}]", name)),
+            }
+        });
+
+        match op {
+            Ok((id, ops)) => {
+                changes.insert(id, ops);
+            }
+            Err(error) => {
+                warn!(
+                    message = "stream_record_skipped",
+                    event_id = record.event_id,
+                    error
+                );
+                skipped += 1;
+            }
+        }
+    }
+
+    let indexed = changes
+        .values()
+        .filter(|ops| ops.iter().any(|op| op.document().is_some()))
+        .count();
+    let deleted = changes.len() - indexed;
+
+    let retained = match rolling {
+        Some(config) => time_partition::retained_partitions(index_loader, index_id, config)?
+            .into_iter()
+            .map(|(_, partition)| partition)
+            .collect(),
+        None => vec![],
+    };
+    let partitions = time_partition::group_by_target(
+        schema_loader,
+        index_id,
+        &settings,
+        &schema,
+        &retained,
+        changes.into_values().flatten().collect(),
+    );
+
+    let mut job_ids = vec![];
+    for (target, ops) in &partitions {
+        for batch in ops.chunks(MAX_DOCS_PER_JOB) {
+            let documents: Vec<SearchDoc> = batch
+                .iter()
+                .filter_map(WriteOp::document)
+                .cloned()
+                .collect();
+            let mut doc_refs = vec![];
+            for chunk in documents.chunks(MAX_DOCS_PER_SAVE) {
+                doc_refs.extend(document_store.save_documents(chunk.to_vec()).await?);
+            }
+
+            let mut job = Job::create(target);
+            for doc_ref in doc_refs {
+                job.index_doc(doc_ref);
+            }
+            for op in batch {
+                if let WriteOp::Delete(doc_id) = op {
+                    job.delete_doc(doc_id.clone());
+                }
+            }
+
+            job_ids.push(writer_client.submit_job(job).await?);
+        }
+    }
+
+    info!(
+        message = "stream_indexed",
+        index = index_id,
+        indexed,
+        deleted,
+        skipped
+    );
+
+    Ok(StreamReport {
+        index_id: index_id.to_string(),
+        indexed,
+        deleted,
+        skipped,
+        job_ids,
+    })
+}
+
+/// The `__id` of the item with `keys`, its `id_attributes` joined by `#`.
+fn document_id(
+    config: &DynamoDbStreamConfig,
+    keys: &HashMap<String, AttributeValue>,
+) -> Result<String, String> {
+    let parts = config
+        .id_attributes
+        .iter()
+        .map(|attribute| match keys.get(attribute) {
+            Some(AttributeValue::String(value)) => Ok(value.clone()),
+            Some(AttributeValue::Number(value)) => Ok(number(*value).to_string()),
+            Some(_) => Err(format!(
+                "Key attribute [{}] isn't a string or number",
+                attribute
+            )),
+            None => Err(format!("Missing key attribute [{}]", attribute)),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(parts.join("#"))
+}
+
+/// The document content of `item`, with the configured attributes under their fields.
+fn document(
+    config: &DynamoDbStreamConfig,
+    item: &HashMap<String, AttributeValue>,
+) -> Result<json::Map<String, json::Value>, String> {
+    if item.is_empty() {
+        return Err("Record has no new image, the stream should include them".into());
+    }
+
+    if config.attributes.is_empty() {
+        return Ok(item
+            .iter()
+            .map(|(name, value)| (name.clone(), to_json(value)))
+            .collect());
+    }
+
+    let mut content = json::Map::new();
+    for (path, field) in &config.attributes {
+        let mut names = path.split('.');
+        let mut value = names.next().and_then(|name| item.get(name));
+        for name in names {
+            value = match value {
+                Some(AttributeValue::AttributeMap(map)) => map.get(name),
+                _ => None,
+            };
+        }
+        if let Some(value) = value {
+            content.insert(field.clone(), to_json(value));
+        }
+    }
+
+    Ok(content)
+}
+
+/// `value` as JSON. Sets are arrays and binary values are base64.
+fn to_json(value: &AttributeValue) -> json::Value {
+    match value {
+        AttributeValue::Null => json::Value::Null,
+        AttributeValue::String(value) => value.clone().into(),
+        AttributeValue::Number(value) => number(*value),
+        AttributeValue::Boolean(value) => (*value).into(),
+        AttributeValue::Binary(value) => base64::encode(value).into(),
+        AttributeValue::StringSet(values) => values.clone().into(),
+        AttributeValue::NumberSet(values) => values.iter().copied().map(number).collect(),
+        AttributeValue::BinarySet(values) => values.iter().map(base64::encode).collect(),
+        AttributeValue::AttributeList(values) => values.iter().map(to_json).collect(),
+        AttributeValue::AttributeMap(map) => json::Value::Object(
+            map.iter()
+                .map(|(name, value)| (name.clone(), to_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+/// DynamoDB numbers arrive as floats, so whole numbers are turned back into integers.
+fn number(value: f64) -> json::Value {
+    if value.fract() == 0.0 && value.abs() < i64::MAX as f64 {
+        (value as i64).into()
+    } else {
+        value.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDate, Utc};
+    use lambda_http::Context;
+
+    use super::*;
+    use crate::lambda::lambda_runtime::LambdaEvent;
+    use crate::store::document::SearchDocRef;
+    use crate::test_utils::*;
+
+    fn record(event_name: &str, keys: json::Value, new_image: json::Value) -> json::Value {
+        json!({
+            "awsRegion": "us-east-1",
+            "eventID": format!("{}-{}", event_name, keys),
+            "eventName": event_name,
+            "dynamodb": {
+                "ApproximateCreationDateTime": 1668000000.0,
+                "Keys": keys,
+                "NewImage": new_image,
+                "SizeBytes": 100,
+            },
+        })
+    }
+
+    fn event(records: Vec<json::Value>) -> DynamoDbEvent {
+        let payload = json::from_value(json!({ "Records": records })).unwrap();
+        LambdaEvent::new(payload, Context::default())
+    }
+
+    #[tokio::test]
+    async fn stream_indexes_mapped_items() {
+        let ctx = setup();
+
+        let report = handle_event(
+            ctx.schema_loader(),
+            ctx.index_loader(),
+            ctx.document_store(),
+            ctx.writer_client(),
+            "streamed",
+            event(vec![
+                record(
+                    "INSERT",
+                    json!({ "pk": { "S": "book" }, "sk": { "N": "1" } }),
+                    json!({
+                        "pk": { "S": "book" },
+                        "sk": { "N": "1" },
+                        "Title": { "S": "zen" },
+                        "Details": { "M": { "Year": { "N": "1974" } } },
+                    }),
+                ),
+                record(
+                    "INSERT",
+                    json!({ "pk": { "S": "book" }, "sk": { "N": "2" } }),
+                    json!({
+                        "pk": { "S": "book" },
+                        "sk": { "N": "2" },
+                        "Title": { "S": "art" },
+                    }),
+                ),
+                record(
+                    "MODIFY",
+                    json!({ "pk": { "S": "book" }, "sk": { "N": "1" } }),
+                    json!({
+                        "pk": { "S": "book" },
+                        "sk": { "N": "1" },
+                        "Title": { "S": "zen and the art" },
+                        "Details": { "M": { "Year": { "N": "1974" } } },
+                    }),
+                ),
+                record(
+                    "INSERT",
+                    json!({ "pk": { "S": "book" }, "sk": { "N": "3" } }),
+                    json!({
+                        "pk": { "S": "book" },
+                        "sk": { "N": "3" },
+                        "Title": { "N": "3" },
+                    }),
+                ),
+            ]),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(2, report.indexed);
+        assert_eq!(1, report.skipped);
+        assert_eq!(1, report.job_ids.len());
+
+        let stored = ctx
+            .document_store()
+            .get_documents(vec![SearchDocRef::from(SearchDocId::parse("book#1"))])
+            .await
+            .unwrap();
+        assert_eq!(json!("zen and the art"), stored[0].content()["title"]);
+        assert_eq!(json!(1974), stored[0].content()["year"]);
+
+        let num_docs = ctx
+            .index_loader()
+            .load_index("streamed", None)
+            .unwrap()
+            .reader()
+            .unwrap()
+            .searcher()
+            .num_docs();
+        assert_eq!(2, num_docs);
+    }
+
+    #[tokio::test]
+    async fn stream_deletes_removed_items() {
+        let ctx = setup();
+        let keys = json!({ "pk": { "S": "book" }, "sk": { "N": "1" } });
+        let image = json!({
+            "pk": { "S": "book" },
+            "sk": { "N": "1" },
+            "Title": { "S": "zen" },
+        });
+
+        handle_event(
+            ctx.schema_loader(),
+            ctx.index_loader(),
+            ctx.document_store(),
+            ctx.writer_client(),
+            "streamed",
+            event(vec![record("INSERT", keys.clone(), image)]),
+        )
+        .await
+        .unwrap();
+
+        let report = handle_event(
+            ctx.schema_loader(),
+            ctx.index_loader(),
+            ctx.document_store(),
+            ctx.writer_client(),
+            "streamed",
+            event(vec![record("REMOVE", keys, json!({}))]),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(1, report.deleted);
+        let num_docs = ctx
+            .index_loader()
+            .load_index("streamed", None)
+            .unwrap()
+            .reader()
+            .unwrap()
+            .searcher()
+            .num_docs();
+        assert_eq!(0, num_docs);
+    }
+
+    #[tokio::test]
+    async fn stream_deletes_from_every_partition_of_a_rolling_index() {
+        let ctx = setup();
+        let today = Utc::now().date_naive();
+        let yesterday = today - Duration::days(1);
+        let item = |pk: &str, date: NaiveDate| {
+            json!({
+                "pk": { "S": pk },
+                "Title": { "S": "zen" },
+                "Timestamp": { "S": format!("{}T12:00:00Z", date) },
+            })
+        };
+        let stream = |records: Vec<json::Value>| async {
+            handle_event(
+                ctx.schema_loader(),
+                ctx.index_loader(),
+                ctx.document_store(),
+                ctx.writer_client(),
+                "rolling",
+                event(records),
+            )
+            .await
+            .unwrap()
+        };
+
+        stream(vec![
+            record(
+                "INSERT",
+                json!({ "pk": { "S": "a" } }),
+                item("a", yesterday),
+            ),
+            record(
+                "INSERT",
+                json!({ "pk": { "S": "b" } }),
+                item("b", yesterday),
+            ),
+        ])
+        .await;
+
+        // Moving an item to another day leaves no copy in its old partition, and removing one
+        // deletes it from whichever partition holds it.
+        let report = stream(vec![
+            record("MODIFY", json!({ "pk": { "S": "a" } }), item("a", today)),
+            record("REMOVE", json!({ "pk": { "S": "b" } }), json!({})),
+        ])
+        .await;
+        assert_eq!((1, 1), (report.indexed, report.deleted));
+
+        let num_docs = |date| {
+            ctx.index_loader()
+                .load_index(&time_partition::partition_id("rolling", date), None)
+                .unwrap()
+                .reader()
+                .unwrap()
+                .searcher()
+                .num_docs()
+        };
+        assert_eq!(0, num_docs(yesterday));
+        assert_eq!(1, num_docs(today));
+    }
+}
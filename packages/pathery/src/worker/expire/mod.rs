@@ -0,0 +1,238 @@
+use std::ops::Bound;
+
+use chrono::Utc;
+use serde::Serialize;
+use serde_json as json;
+use tantivy::query::RangeQuery;
+use tantivy::schema::Type;
+use tantivy::{DateTime, Term};
+use tracing::{info, warn};
+
+use crate::index::IndexLoader;
+use crate::lambda::lambda_runtime::LambdaEvent;
+use crate::schema::SchemaLoader;
+use crate::service::index::delete_matching;
+use crate::service::ServiceError;
+use crate::worker::index_writer::client::IndexWriterClient;
+use crate::worker::index_writer::job::Job;
+use crate::{lambda, time_partition};
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct ExpireReport {
+    pub index_id: String,
+
+    /// Documents whose `ttl_field` had passed.
+    pub expired: usize,
+
+    /// Index writer jobs deleting them.
+    pub job_ids: Vec<String>,
+
+    /// The index was a partition past its rolling index's retention, deleted whole along with
+    /// its `expired` documents.
+    pub dropped: bool,
+}
+
+/// Submits a job deleting `index_id` when it's a partition of a rolling index that's past the
+/// retention window. `None` for other indexes.
+pub async fn drop_partition(
+    index_loader: &dyn IndexLoader,
+    schema_loader: &dyn SchemaLoader,
+    writer_client: &dyn IndexWriterClient,
+    index_id: &str,
+) -> Result<Option<ExpireReport>, ServiceError> {
+    let Some(prefix) = schema_loader.index_prefix(index_id) else {
+        return Ok(None);
+    };
+    let settings = schema_loader.load_settings(index_id)?;
+    let (Some(config), Some(date)) = (
+        settings.time_partition,
+        time_partition::partition_date(&prefix, index_id),
+    ) else {
+        return Ok(None);
+    };
+    if time_partition::is_retained(&config, date, Utc::now().date_naive()) {
+        return Ok(None);
+    }
+
+    let expired = index_loader
+        .load_index(index_id, None)?
+        .reader()
+        .map_err(ServiceError::internal_error)?
+        .searcher()
+        .num_docs() as usize;
+
+    let mut job = Job::create(index_id);
+    job.delete_index();
+    let job_id = writer_client.submit_job(job).await?;
+
+    Ok(Some(ExpireReport {
+        index_id: index_id.into(),
+        expired,
+        job_ids: vec![job_id],
+        dropped: true,
+    }))
+}
+
+/// Submits jobs deleting the documents of `index_id` whose `ttl_field` is before now. `None`
+/// for indexes without a `ttl_field`.
+pub async fn expire_index(
+    index_loader: &dyn IndexLoader,
+    schema_loader: &dyn SchemaLoader,
+    writer_client: &dyn IndexWriterClient,
+    index_id: &str,
+) -> Result<Option<ExpireReport>, ServiceError> {
+    let settings = schema_loader.load_settings(index_id)?;
+    let Some(field_name) = settings.ttl_field else {
+        return Ok(None);
+    };
+
+    let index = index_loader.load_index(index_id, None)?;
+    let schema = index.schema();
+    let field = schema
+        .get_field(&field_name)
+        .filter(|field| {
+            let entry = schema.get_field_entry(*field);
+            entry.is_indexed() && entry.field_type().value_type() == Type::Date
+        })
+        .ok_or_else(|| {
+            ServiceError::invalid_request(&format!(
+                "ttl_field [{}] must be an INDEXED date field",
+                field_name
+            ))
+        })?;
+
+    let now = DateTime::from_unix_timestamp(Utc::now().timestamp());
+    let query = RangeQuery::new_term_bounds(
+        field,
+        Type::Date,
+        &Bound::Unbounded,
+        &Bound::Excluded(Term::from_field_date(field, now)),
+    );
+
+    let response = delete_matching(writer_client, index_id, &index, &query, false).await?;
+
+    Ok(Some(ExpireReport {
+        index_id: index_id.into(),
+        expired: response.matched,
+        job_ids: response.job_ids,
+        dropped: false,
+    }))
+}
+
+/// Scans every index on a schedule, dropping the partitions of rolling indexes past their
+/// retention and deleting the documents whose `ttl_field` has passed. Indexes whose `ttl_field`
+/// isn't usable are skipped, so one misconfigured index doesn't hold up the rest.
+pub async fn handle_event(
+    index_loader: &dyn IndexLoader,
+    schema_loader: &dyn SchemaLoader,
+    writer_client: &dyn IndexWriterClient,
+    _event: LambdaEvent<json::Value>,
+) -> Result<Vec<ExpireReport>, lambda::Error> {
+    let mut reports = vec![];
+
+    for index_id in index_loader.list_indexes()? {
+        // Without a schema the index cannot be opened.
+        if schema_loader.index_prefix(&index_id).is_none() {
+            continue;
+        }
+
+        if let Some(report) =
+            drop_partition(index_loader, schema_loader, writer_client, &index_id).await?
+        {
+            info!(
+                message = "partition_dropped",
+                index = index_id,
+                expired = report.expired
+            );
+            reports.push(report);
+            continue;
+        }
+
+        let report = match expire_index(index_loader, schema_loader, writer_client, &index_id).await
+        {
+            Ok(Some(report)) => report,
+            Ok(None) => continue,
+            Err(ServiceError::InvalidRequest(reason)) => {
+                warn!(message = "ttl_field_invalid", index = index_id, reason);
+                continue;
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        info!(
+            message = "index_expired",
+            index = index_id,
+            expired = report.expired,
+            jobs = report.job_ids.len()
+        );
+        reports.push(report);
+    }
+
+    Ok(reports)
+}
+
+#[cfg(test)]
+mod tests {
+    use lambda_http::Context;
+
+    use super::*;
+    use crate::test_utils::*;
+
+    #[tokio::test]
+    async fn expired_documents_are_deleted() {
+        let ctx = setup()
+            .with_documents(
+                "expiring",
+                vec![
+                    json!({ "__id": "expired", "title": "a", "expires_at": "2020-01-01T00:00:00Z" }),
+                    json!({ "__id": "live", "title": "b", "expires_at": "2999-01-01T00:00:00Z" }),
+                    json!({ "__id": "forever", "title": "c" }),
+                ],
+            )
+            .await
+            .with_documents("test", vec![json!({ "title": "hello" })])
+            .await;
+
+        let reports = handle_event(
+            ctx.index_loader(),
+            ctx.schema_loader(),
+            ctx.writer_client(),
+            LambdaEvent::new(json!({}), Context::default()),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(1, reports.len());
+        assert_eq!("expiring", reports[0].index_id);
+        assert_eq!(1, reports[0].expired);
+
+        let index = ctx.index_loader().load_index("expiring", None).unwrap();
+        assert_eq!(2, index.reader().unwrap().searcher().num_docs());
+    }
+
+    #[tokio::test]
+    async fn partitions_past_retention_are_dropped() {
+        let today = time_partition::partition_id("rolling", Utc::now().date_naive());
+        let ctx = setup()
+            .with_documents("rolling-2020-01-01", vec![json!({ "title": "old" })])
+            .await
+            .with_documents(&today, vec![json!({ "title": "new" })])
+            .await;
+
+        let reports = handle_event(
+            ctx.index_loader(),
+            ctx.schema_loader(),
+            ctx.writer_client(),
+            LambdaEvent::new(json!({}), Context::default()),
+        )
+        .await
+        .unwrap();
+
+        let dropped = reports
+            .iter()
+            .map(|report| (report.index_id.as_str(), report.expired, report.dropped))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![("rolling-2020-01-01", 1, true)], dropped);
+        assert_eq!(vec![today], ctx.index_loader().list_indexes().unwrap());
+    }
+}
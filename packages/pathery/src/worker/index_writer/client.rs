@@ -1,21 +1,86 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
 use async_trait::async_trait;
+use aws_sdk_sqs::model::{MessageSystemAttributeNameForSends, MessageSystemAttributeValue};
+use aws_sdk_sqs::types::SdkError;
+use serde::Deserialize;
 use thiserror::Error;
+use tracing::{error, warn};
 
 use super::job::Job;
+use super::pool::WriterPool;
+use super::process_jobs;
+use crate::enrich::Enricher;
+use crate::index::IndexLoader;
+use crate::retry::{Backoff, CircuitBreaker};
+use crate::schema::SchemaProvider;
 use crate::service::ServiceError;
-use crate::util;
+use crate::store::change::MemoryChangeStore;
+use crate::store::document::MemoryDocumentStore;
+use crate::store::event::MemoryEventStore;
+use crate::store::job::{DDBJobStore, JobStatus, JobStore, MemoryJobStore};
+use crate::store::lookup::MemoryLookupTable;
+use crate::store::token::MemoryTokenStore;
+use crate::{tenant, util};
 
 #[derive(Debug, Error)]
-pub enum IndexWriterClientError {}
+pub enum IndexWriterClientError {
+    #[error("Failed to queue index writer job: {0}")]
+    Queue(String),
+
+    #[error("Index writer queue is unavailable")]
+    CircuitOpen,
+}
+
+impl From<IndexWriterClientError> for ServiceError {
+    fn from(err: IndexWriterClientError) -> Self {
+        error!(
+            message = "index_writer_client_error",
+            error = err.to_string()
+        );
+        ServiceError::unavailable("Index writer is temporarily unavailable, try again later")
+    }
+}
+
+/// Whether a failed SQS call is worth retrying. Requests that could not be built will fail the
+/// same way every time.
+fn is_retryable<E>(err: &SdkError<E>) -> bool {
+    !matches!(err, SdkError::ConstructionFailure(_))
+}
+
+/// Sends jobs for indexes matching `prefix` to a dedicated writer queue instead of the shared
+/// one, so heavy writes to those indexes don't delay everyone else.
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+pub struct QueueRoute {
+    pub prefix: String,
+    pub queue_url: String,
+}
+
+/// The queue for `index_id`: the route with the longest prefix matching its id within its tenant,
+/// or `default`.
+fn route_queue<'a>(routes: &'a [QueueRoute], default: &'a str, index_id: &str) -> &'a str {
+    routes
+        .iter()
+        .filter(|route| tenant::unscope(index_id).starts_with(&route.prefix))
+        .max_by_key(|route| route.prefix.len())
+        .map(|route| route.queue_url.as_str())
+        .unwrap_or(default)
+}
 
 #[async_trait]
 pub trait IndexWriterClient: Sync + Send {
+    /// Queue a job for the index writer, returning the job id used to track its status.
     async fn submit_job(&self, job: Job) -> Result<String, ServiceError>;
 }
 
 pub struct LambdaIndexWriterClient {
     queue_url: String,
+    routes: Vec<QueueRoute>,
     client: aws_sdk_sqs::Client,
+    job_store: DDBJobStore,
+    backoff: Backoff,
+    circuit_breaker: CircuitBreaker,
 }
 
 #[async_trait]
@@ -23,20 +88,65 @@ impl IndexWriterClient for LambdaIndexWriterClient {
     async fn submit_job(&self, job: Job) -> Result<String, ServiceError> {
         let body = serde_json::to_string(&job).expect("job should serialize");
 
-        let response = self
-            .client
-            .send_message()
-            .queue_url(&self.queue_url)
-            .message_body(body)
-            .message_group_id(job.index_id)
-            .send()
-            .await
-            .expect("job should queue");
-
-        Ok(response
-            .message_id()
-            .expect("message id should exist")
-            .to_string())
+        if !self.circuit_breaker.allow() {
+            return Err(IndexWriterClientError::CircuitOpen.into());
+        }
+
+        self.job_store
+            .create_job(JobStatus::pending(&job.job_id, &job.index_id))
+            .await?;
+
+        let queue_url = route_queue(&self.routes, &self.queue_url, &job.index_id);
+        // Links the job's processing to the request that submitted it in X-Ray.
+        let system_attributes = job.trace_id.as_ref().map(|trace_id| {
+            let header = MessageSystemAttributeValue::builder()
+                .data_type("String")
+                .string_value(trace_id)
+                .build();
+            HashMap::from([(MessageSystemAttributeNameForSends::AwsTraceHeader, header)])
+        });
+
+        let sent = self
+            .backoff
+            .retry(
+                || {
+                    self.client
+                        .send_message()
+                        .queue_url(queue_url)
+                        .message_body(&body)
+                        .message_group_id(&job.index_id)
+                        .set_message_system_attributes(system_attributes.clone())
+                        .send()
+                },
+                |err| {
+                    warn!(message = "job_submit_failed", job_id = job.job_id, error = %err);
+                    is_retryable(err)
+                },
+            )
+            .await;
+
+        if let Err(err) = sent {
+            self.circuit_breaker.record_failure();
+            let err = IndexWriterClientError::Queue(err.to_string());
+
+            // The record was created before sending, so that the writer can't complete the job
+            // before it exists. Fail it rather than leave it pending for a job that never ran.
+            if let Err(fail_err) = self.job_store.fail_job(&job.job_id, &err.to_string()).await {
+                error!(message = "job_fail_failed", job_id = job.job_id, error = %fail_err);
+            }
+
+            return Err(err.into());
+        }
+
+        self.circuit_breaker.record_success();
+
+        tracing::info!(
+            message = "job_submitted",
+            job_id = job.job_id,
+            index_id = job.index_id
+        );
+
+        Ok(job.job_id)
     }
 }
 
@@ -48,48 +158,121 @@ impl LambdaIndexWriterClient {
             queue_url: queue_url
                 .map(String::from)
                 .unwrap_or_else(|| util::require_env("INDEX_WRITER_QUEUE_URL")),
+            routes: std::env::var("INDEX_WRITER_QUEUE_ROUTES")
+                .map(|routes| {
+                    serde_json::from_str(&routes)
+                        .expect("INDEX_WRITER_QUEUE_ROUTES should be valid")
+                })
+                .unwrap_or_default(),
             client: aws_sdk_sqs::Client::new(&sdk_config),
+            job_store: DDBJobStore::create(None).await,
+            backoff: Backoff::default(),
+            circuit_breaker: CircuitBreaker::default(),
         }
     }
 }
 
-#[cfg(test)]
-pub mod test_utils {
-    use super::*;
-    use crate::index::test_util::TestIndexLoader;
-    use crate::index::{IndexExt, IndexLoader};
-    use crate::store::document::test_util::TestDocumentStore;
-    use crate::util;
-    use crate::worker::index_writer::handle_job;
+/// Runs jobs in-process as they're submitted, against stores held in memory and indexes from any
+/// loader, for local development and tests. Writes are committed by the time `submit_job` returns.
+#[derive(Clone)]
+pub struct LocalIndexWriterClient {
+    index_loader: Arc<dyn IndexLoader>,
 
-    #[derive(Clone)]
-    pub struct TestIndexWriterClient {
-        index_loader: TestIndexLoader,
+    schema_loader: SchemaProvider,
 
-        document_store: TestDocumentStore,
-    }
+    document_store: MemoryDocumentStore,
+
+    job_store: MemoryJobStore,
+
+    change_store: MemoryChangeStore,
 
-    #[async_trait]
-    impl IndexWriterClient for TestIndexWriterClient {
-        async fn submit_job(&self, job: Job) -> Result<String, ServiceError> {
-            let index = self.index_loader.load_index(&job.index_id, None)?;
+    event_store: MemoryEventStore,
 
-            let mut writer = index.default_writer();
+    token_store: MemoryTokenStore,
 
-            handle_job(&mut writer, &self.document_store, job).await;
+    enricher: Arc<Enricher>,
+}
+
+#[async_trait]
+impl IndexWriterClient for LocalIndexWriterClient {
+    async fn submit_job(&self, job: Job) -> Result<String, ServiceError> {
+        let job_id = job.job_id.clone();
+
+        self.job_store
+            .create_job(JobStatus::pending(&job_id, &job.index_id))
+            .await?;
 
-            writer.commit().unwrap();
+        let processed = process_jobs(
+            &self.document_store,
+            &*self.index_loader,
+            &self.schema_loader,
+            &self.job_store,
+            &self.change_store,
+            &self.event_store,
+            &self.token_store,
+            &WriterPool::default(),
+            &self.enricher,
+            vec![job],
+        )
+        .await;
 
-            Ok(util::generate_id())
+        let mut errors = processed
+            .failed
+            .into_values()
+            .chain(processed.quarantined.into_values());
+        match errors.next() {
+            Some(err) => Err(err),
+            None => Ok(job_id),
         }
     }
+}
 
-    impl TestIndexWriterClient {
-        pub fn create(index_loader: TestIndexLoader, document_store: TestDocumentStore) -> Self {
-            TestIndexWriterClient {
-                index_loader,
-                document_store,
-            }
+impl LocalIndexWriterClient {
+    pub fn create(
+        index_loader: impl IndexLoader + 'static,
+        schema_loader: SchemaProvider,
+        document_store: MemoryDocumentStore,
+        job_store: MemoryJobStore,
+        change_store: MemoryChangeStore,
+        event_store: MemoryEventStore,
+    ) -> Self {
+        LocalIndexWriterClient {
+            index_loader: Arc::new(index_loader),
+            schema_loader,
+            document_store,
+            job_store,
+            change_store,
+            event_store,
+            token_store: MemoryTokenStore::create(),
+            enricher: Arc::new(Enricher::new(MemoryLookupTable::create())),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn route_queue_prefers_longest_prefix() {
+        let routes = vec![
+            QueueRoute {
+                prefix: "logs".into(),
+                queue_url: "logs-queue".into(),
+            },
+            QueueRoute {
+                prefix: "logs-bulk".into(),
+                queue_url: "bulk-queue".into(),
+            },
+        ];
+
+        assert_eq!("bulk-queue", route_queue(&routes, "shared", "logs-bulk-1"));
+        assert_eq!("logs-queue", route_queue(&routes, "shared", "logs-app"));
+        assert_eq!("shared", route_queue(&routes, "shared", "books"));
+    }
+}
+
+#[cfg(test)]
+pub mod test_utils {
+    pub use super::LocalIndexWriterClient as TestIndexWriterClient;
+}
@@ -1,29 +1,73 @@
 use serde::{Deserialize, Serialize};
 
+use crate::lambda::http::{current_request_id, current_trace_id};
 use crate::search_doc::SearchDocId;
 use crate::store::document::SearchDocRef;
+use crate::util;
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub enum IndexWriterOp {
-    IndexDoc { doc_ref: SearchDocRef },
+    IndexDoc {
+        doc_ref: SearchDocRef,
+    },
 
-    DeleteDoc { doc_id: SearchDocId },
+    DeleteDoc {
+        doc_id: SearchDocId,
+    },
+
+    /// Removes the index and all of its files.
+    DeleteIndex,
+}
+
+impl IndexWriterOp {
+    /// The doc the op writes, or None for ops on the whole index.
+    pub fn doc_id(&self) -> Option<&str> {
+        match self {
+            IndexWriterOp::IndexDoc { doc_ref } => Some(doc_ref.id()),
+            IndexWriterOp::DeleteDoc { doc_id } => Some(doc_id.id()),
+            IndexWriterOp::DeleteIndex => None,
+        }
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct Job {
+    #[serde(default = "util::generate_id")]
+    pub job_id: String,
     pub index_id: String,
+    /// Id of the API request that submitted the job, for correlating worker logs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// X-Ray trace id of the API request that submitted the job, when it was traced.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+    /// Orders the job's doc ops against other writes of the same docs. Ops with a smaller token
+    /// than a doc's last applied one are stale and skipped. Jobs queued without one always apply.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<u64>,
     pub ops: Vec<IndexWriterOp>,
 }
 
 impl Job {
     pub fn create(index_id: &str) -> Job {
         Job {
+            job_id: util::generate_id(),
             index_id: index_id.into(),
+            request_id: current_request_id(),
+            trace_id: current_trace_id(),
+            token: Some(util::write_token()),
             ops: vec![],
         }
     }
 
+    /// Uses a client supplied `token` in place of the generated one.
+    pub fn with_token(mut self, token: Option<u64>) -> Job {
+        if token.is_some() {
+            self.token = token;
+        }
+        self
+    }
+
     pub fn index_doc(&mut self, doc_ref: SearchDocRef) {
         self.ops.push(IndexWriterOp::IndexDoc { doc_ref })
     }
@@ -31,4 +75,14 @@ impl Job {
     pub fn delete_doc(&mut self, doc_id: SearchDocId) {
         self.ops.push(IndexWriterOp::DeleteDoc { doc_id })
     }
+
+    pub fn delete_index(&mut self) {
+        self.ops.push(IndexWriterOp::DeleteIndex)
+    }
+
+    pub fn deletes_index(&self) -> bool {
+        self.ops
+            .iter()
+            .any(|op| matches!(op, IndexWriterOp::DeleteIndex))
+    }
 }
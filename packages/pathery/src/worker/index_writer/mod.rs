@@ -1,16 +1,34 @@
 pub mod client;
 pub mod job;
+pub mod pool;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::time::{Duration, Instant, UNIX_EPOCH};
 
+use chrono::{DateTime, Utc};
 use serde_json as json;
-use tantivy::{Document, IndexWriter, Term};
-use tracing::info;
+use tantivy::{Directory, Document, IndexWriter, TantivyError, Term};
+use tracing::{info, info_span, warn, Instrument};
 
 use self::job::{IndexWriterOp, Job};
-use crate::index::{IndexExt, IndexLoader};
+use self::pool::WriterPool;
+use crate::enrich::{EnrichConfig, Enricher};
+use crate::index::{IndexExt, IndexLoader, IndexWriterExt};
+use crate::lambda::sqs::{BatchItemFailure, SqsBatchResponse};
 use crate::lambda::{self, sqs};
+use crate::metrics::MetricSet;
+use crate::retry::Backoff;
+use crate::schema::{derive_fields, IndexSettings, SchemaLoader};
+use crate::search_doc::SearchDoc;
+use crate::service::ServiceError;
+use crate::store::change::{Change, ChangeStore};
 use crate::store::document::{DocumentStore, SearchDocRef};
+use crate::store::event::{self, EventStore, IndexEvent};
+use crate::store::job::JobStore;
+use crate::store::lease::LeaseStore;
+use crate::store::token::TokenStore;
+use crate::suggest;
 
 fn delete_doc(writer: &IndexWriter, doc_id: &str) {
     let index = writer.index();
@@ -20,85 +38,817 @@ fn delete_doc(writer: &IndexWriter, doc_id: &str) {
     tracing::info!(message = "doc_deleted", doc_id);
 }
 
-fn index_doc(writer: &IndexWriter, doc: Document) {
+fn index_doc(writer: &IndexWriter, doc: Document) -> Result<(), ServiceError> {
     let index = writer.index();
     let id_field = index.id_field();
     let doc_id = doc
         .get_first(id_field)
         .and_then(|id| id.as_text())
-        .expect("__id field should be present")
+        .ok_or_else(|| ServiceError::invalid_request("Document has no __id"))?
         .to_string();
 
     delete_doc(writer, &doc_id);
     writer
         .add_document(doc)
-        .expect("Adding a document should not error");
+        .map_err(ServiceError::internal_error)?;
     tracing::info!(message = "doc_indexed", doc_id);
+    Ok(())
+}
+
+/// Enriches `docs` with the attributes `enrichments` look up for them.
+pub(crate) async fn enrich_docs(
+    enricher: &Enricher,
+    enrichments: &[EnrichConfig],
+    docs: &mut [SearchDoc],
+) -> Result<(), ServiceError> {
+    if enrichments.is_empty() {
+        return Ok(());
+    }
+    for doc in docs {
+        enricher.enrich(enrichments, doc.content_mut()).await?;
+    }
+    Ok(())
 }
 
-pub async fn handle_job(writer: &mut IndexWriter, document_store: &dyn DocumentStore, job: Job) {
+/// Applies the ops of `job` to `writer`, returning the changes to record in the index's log once
+/// they're committed.
+pub async fn handle_job(
+    writer: &mut IndexWriter,
+    document_store: &dyn DocumentStore,
+    enricher: &Enricher,
+    settings: &IndexSettings,
+    job: Job,
+) -> Result<Vec<Change>, ServiceError> {
     let schema = writer.index().schema();
 
     let mut doc_refs: Vec<SearchDocRef> = vec![];
+    let mut changes = vec![];
 
     for op in job.ops {
         match op {
             IndexWriterOp::IndexDoc { doc_ref } => doc_refs.push(doc_ref),
 
-            IndexWriterOp::DeleteDoc { doc_id } => delete_doc(writer, doc_id.id()),
+            IndexWriterOp::DeleteDoc { doc_id } => {
+                delete_doc(writer, doc_id.id());
+                changes.push(Change::Delete {
+                    doc_id: doc_id.id().into(),
+                });
+            }
+
+            // Index deletion is applied by `process_jobs` since it needs the index loader.
+            IndexWriterOp::DeleteIndex => {}
         }
     }
 
-    let docs = document_store.get_documents(doc_refs).await.unwrap();
+    let mut docs = document_store.get_documents(doc_refs).await?;
+    enrich_docs(enricher, &settings.enrich, &mut docs).await?;
 
     for doc in docs {
-        let document = doc.document(&schema);
-        index_doc(writer, document);
+        changes.push(Change::Index {
+            doc_id: doc.id().id().into(),
+        });
+        let document = doc.document_with_settings(&schema, settings);
+        index_doc(writer, document)?;
+    }
+
+    Ok(changes)
+}
+
+/// Drops the doc ops of `job` older than the last applied token of their doc, known from earlier
+/// jobs in `tokens` or else loaded from `token_store`. The token of each op kept is recorded in
+/// `tokens` and `applied`, for the commit to save.
+async fn skip_stale_ops(
+    token_store: &dyn TokenStore,
+    tokens: &mut HashMap<String, u64>,
+    applied: &mut HashMap<String, u64>,
+    job: &mut Job,
+) -> Result<(), ServiceError> {
+    let Some(token) = job.token else {
+        return Ok(());
+    };
+
+    let unknown: Vec<String> = job
+        .ops
+        .iter()
+        .filter_map(IndexWriterOp::doc_id)
+        .filter(|doc_id| !tokens.contains_key(*doc_id))
+        .map(String::from)
+        .collect();
+    if !unknown.is_empty() {
+        tokens.extend(token_store.get_tokens(&job.index_id, &unknown).await?);
+    }
+
+    job.ops.retain(|op| {
+        let Some(doc_id) = op.doc_id() else {
+            return true;
+        };
+        match tokens.get(doc_id) {
+            Some(last) if *last > token => {
+                info!(message = "op_stale", doc_id, token, last_token = last);
+                false
+            }
+            _ => true,
+        }
+    });
+
+    for doc_id in job.ops.iter().filter_map(IndexWriterOp::doc_id) {
+        tokens.insert(doc_id.into(), token);
+        applied.insert(doc_id.into(), token);
+    }
+
+    Ok(())
+}
+
+/// Extends the schema of a dynamic index with fields for the documents in `jobs` that it doesn't
+/// define yet, returning the names of the fields added. Must run before a writer is opened on the
+/// index, since writers fix the schema.
+async fn extend_dynamic_schema<'a>(
+    document_store: &dyn DocumentStore,
+    index_loader: &dyn IndexLoader,
+    enricher: &Enricher,
+    enrichments: &[EnrichConfig],
+    index_id: &str,
+    jobs: impl Iterator<Item = &'a Job>,
+) -> Result<Vec<String>, ServiceError> {
+    // Documents after a deletion of the index belong to the recreated index.
+    let doc_refs = jobs
+        .filter(|job| job.index_id == index_id)
+        .take_while(|job| !job.deletes_index())
+        .flat_map(|job| &job.ops)
+        .filter_map(|op| match op {
+            IndexWriterOp::IndexDoc { doc_ref } => Some(doc_ref.clone()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    if doc_refs.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let schema = index_loader.load_index(index_id, None)?.schema();
+    let mut docs = document_store.get_documents(doc_refs).await?;
+    enrich_docs(enricher, enrichments, &mut docs).await?;
+    let fields = derive_fields(&schema, docs.iter().map(|doc| doc.content()));
+
+    let names = fields
+        .iter()
+        .map(|field| field.name().to_string())
+        .collect::<Vec<_>>();
+    if !fields.is_empty() {
+        index_loader.extend_schema(index_id, fields)?;
+        info!(message = "schema_extended", index = index_id, fields = ?names);
     }
+
+    Ok(names)
+}
+
+/// SQS system attribute carrying the X-Ray trace header of a message's sender.
+const AWS_TRACE_HEADER: &str = "AWSTraceHeader";
+
+/// Indexes whose jobs failed, with the error, so that only their messages are retried.
+pub type FailedIndexes = HashMap<String, ServiceError>;
+
+/// What came of a batch of jobs, for [`process_jobs`].
+#[derive(Debug, Default)]
+pub struct ProcessedJobs {
+    pub failed: FailedIndexes,
+
+    /// Jobs given up on, by job id, with the error that fails them every time.
+    pub quarantined: HashMap<String, ServiceError>,
 }
 
+/// Applies a batch of writer jobs. Messages are reported as batch item failures, for SQS to
+/// redeliver, when they can't be parsed, when their index's lease can't be taken, or when
+/// applying or committing their index's jobs still fails after retrying. Messages of quarantined
+/// jobs are not redelivered. Messages for every other index in the batch still commit.
+#[allow(clippy::too_many_arguments)]
 pub async fn handle_event(
     document_store: &dyn DocumentStore,
     index_loader: &dyn IndexLoader,
+    schema_loader: &dyn SchemaLoader,
+    job_store: &dyn JobStore,
+    change_store: &dyn ChangeStore,
+    event_store: &dyn EventStore,
+    token_store: &dyn TokenStore,
+    lease_store: &dyn LeaseStore,
+    writer_pool: &WriterPool,
+    enricher: &Enricher,
     event: sqs::SqsEvent,
-) -> Result<(), lambda::Error> {
-    let records = event.payload.records;
+) -> Result<SqsBatchResponse, lambda::Error> {
+    let owner = event.context.request_id.clone();
+    // The lease can't outlive this invocation, so a worker that crashes or times out never
+    // blocks an index for longer than its own timeout.
+    let expires_at =
+        DateTime::<Utc>::from(UNIX_EPOCH + Duration::from_millis(event.context.deadline));
 
-    let jobs = records
-        .iter()
-        .map(|message| message.body.as_ref().expect("Body should be present"))
-        .map(|body| {
-            let msg =
-                json::from_str::<Job>(body.as_str()).expect("Message should be deserializable");
-            msg
+    let mut response = SqsBatchResponse::default();
+    let mut fail = |message_id: String| {
+        response.batch_item_failures.push(BatchItemFailure {
+            item_identifier: message_id,
         })
-        .collect::<Vec<_>>();
+    };
 
-    let mut writers: HashMap<String, IndexWriter> = HashMap::new();
+    let mut messages = vec![];
+    for message in event.payload.records {
+        let message_id = message.message_id.clone().unwrap_or_default();
+        let job = message
+            .body
+            .as_deref()
+            .ok_or_else(|| String::from("message has no body"))
+            .and_then(|body| json::from_str::<Job>(body).map_err(|err| err.to_string()));
 
-    for job in jobs {
+        match job {
+            Ok(mut job) => {
+                // Jobs queued outside the API can still be traced by the queue.
+                if job.trace_id.is_none() {
+                    job.trace_id = message.attributes.get(AWS_TRACE_HEADER).cloned();
+                }
+                messages.push((message_id, job))
+            }
+            Err(error) => {
+                warn!(message = "message_invalid", message_id, error);
+                fail(message_id);
+            }
+        }
+    }
+
+    let mut leased: Vec<String> = vec![];
+    let mut locked: HashSet<String> = HashSet::new();
+    let mut jobs = vec![];
+    let mut job_messages = vec![];
+
+    for (message_id, job) in messages {
         let index_id = &job.index_id;
-        let mut writer = writers.entry(index_id.to_string()).or_insert_with(|| {
-            index_loader
-                .load_index(&index_id, None)
-                .unwrap()
-                .default_writer()
+
+        if !leased.contains(index_id) && !locked.contains(index_id) {
+            match lease_store.acquire(index_id, &owner, expires_at).await {
+                Ok(true) => leased.push(index_id.clone()),
+                Ok(false) => {
+                    warn!(message = "index_locked", index = index_id);
+                    locked.insert(index_id.clone());
+                }
+                Err(err) => {
+                    warn!(message = "lease_acquire_failed", index = index_id, error = %err);
+                    locked.insert(index_id.clone());
+                }
+            }
+        }
+
+        if locked.contains(index_id) {
+            fail(message_id);
+        } else {
+            job_messages.push((message_id, index_id.clone()));
+            jobs.push(job);
+        }
+    }
+
+    let processed = process_jobs(
+        document_store,
+        index_loader,
+        schema_loader,
+        job_store,
+        change_store,
+        event_store,
+        token_store,
+        writer_pool,
+        enricher,
+        jobs,
+    )
+    .await;
+
+    // An unreleased lease expires with this invocation, so the batch's commits stand without it.
+    for index_id in &leased {
+        if let Err(err) = lease_store.release(index_id, &owner).await {
+            warn!(message = "lease_release_failed", index = index_id, error = %err);
+        }
+    }
+
+    for (message_id, index_id) in job_messages {
+        if processed.failed.contains_key(&index_id) {
+            fail(message_id);
+        }
+    }
+
+    Ok(response)
+}
+
+/// Uncommitted writes of a batch of jobs, by index id.
+#[derive(Default)]
+struct Pending {
+    writers: HashMap<String, IndexWriter>,
+
+    changes: HashMap<String, Vec<Change>>,
+
+    job_ids: HashMap<String, Vec<String>>,
+
+    /// Last known token of each doc, applied or saved.
+    tokens: HashMap<String, HashMap<String, u64>>,
+
+    /// Tokens of the doc ops applied, to save once committed.
+    applied: HashMap<String, HashMap<String, u64>>,
+}
+
+/// Deletes `index_id` along with what's kept for it outside its files: the stored documents it
+/// indexes and their write tokens, its change log and, for an index created through the API, its
+/// config. Documents indexed again by `queued` jobs belong to the recreated index and are kept.
+/// Files go last, so that a deletion retried after failing part way still finds the documents.
+async fn purge_index(
+    document_store: &dyn DocumentStore,
+    index_loader: &dyn IndexLoader,
+    schema_loader: &dyn SchemaLoader,
+    change_store: &dyn ChangeStore,
+    token_store: &dyn TokenStore,
+    index_id: &str,
+    queued: &[Job],
+) -> Result<(), ServiceError> {
+    // Loading an index that doesn't exist would create it.
+    let doc_refs = if index_loader.list_indexes()?.iter().any(|id| id == index_id) {
+        index_loader.load_index(index_id, None)?.doc_refs()?
+    } else {
+        vec![]
+    };
+
+    let recreated: HashSet<&str> = queued
+        .iter()
+        .filter(|job| job.index_id == index_id)
+        .flat_map(|job| &job.ops)
+        .filter_map(|op| match op {
+            IndexWriterOp::IndexDoc { doc_ref } => Some(doc_ref.id()),
+            _ => None,
+        })
+        .collect();
+    let doc_refs: Vec<SearchDocRef> = doc_refs
+        .into_iter()
+        .filter(|doc_ref| !recreated.contains(doc_ref.id()))
+        .collect();
+    let doc_ids: Vec<String> = doc_refs.iter().map(|doc_ref| doc_ref.id().into()).collect();
+
+    document_store.delete_documents(doc_refs).await?;
+    token_store.delete_tokens(index_id, &doc_ids).await?;
+    change_store.delete_changes(index_id).await?;
+    schema_loader.delete_config(index_id).await?;
+    index_loader.delete_index(index_id)
+}
+
+/// Applies `job` to its index, opening the index's writer if it isn't open yet, for
+/// [`process_jobs`].
+#[allow(clippy::too_many_arguments)]
+async fn apply_job(
+    document_store: &dyn DocumentStore,
+    index_loader: &dyn IndexLoader,
+    schema_loader: &dyn SchemaLoader,
+    job_store: &dyn JobStore,
+    change_store: &dyn ChangeStore,
+    event_store: &dyn EventStore,
+    token_store: &dyn TokenStore,
+    writer_pool: &WriterPool,
+    enricher: &Enricher,
+    pending: &mut Pending,
+    mut job: Job,
+    queued: &[Job],
+) -> Result<(), ServiceError> {
+    let index_id = job.index_id.clone();
+
+    let span = info_span!(
+        "job",
+        job_id = job.job_id.as_str(),
+        request_id = job.request_id.as_deref(),
+        trace_id = job.trace_id.as_deref()
+    );
+
+    if job.deletes_index() {
+        // Dropping the writer discards any uncommitted ops queued before the deletion.
+        pending.writers.remove(&index_id);
+        pending.changes.remove(&index_id);
+        writer_pool.evict(&index_id);
+        purge_index(
+            document_store,
+            index_loader,
+            schema_loader,
+            change_store,
+            token_store,
+            &index_id,
+            queued,
+        )
+        .instrument(span.clone())
+        .await?;
+        span.in_scope(|| info!(message = "index_deleted", index = index_id));
+        change_store
+            .append_changes(&index_id, vec![Change::DeleteIndex])
+            .await?;
+        event::record(event_store, &index_id, IndexEvent::Deleted).await;
+
+        let job_ids = pending.job_ids.remove(&index_id).unwrap_or_default();
+        job_store.complete_jobs(&job_ids).await?;
+        return Ok(());
+    }
+
+    skip_stale_ops(
+        token_store,
+        pending.tokens.entry(index_id.clone()).or_default(),
+        pending.applied.entry(index_id.clone()).or_default(),
+        &mut job,
+    )
+    .instrument(span.clone())
+    .await?;
+
+    let settings = schema_loader.load_settings(&index_id)?;
+
+    if !pending.writers.contains_key(&index_id) {
+        if settings.dynamic {
+            let jobs = std::iter::once(&job).chain(queued);
+            let fields = extend_dynamic_schema(
+                document_store,
+                index_loader,
+                enricher,
+                &settings.enrich,
+                &index_id,
+                jobs,
+            )
+            .await?;
+            if !fields.is_empty() {
+                let event = IndexEvent::SchemaExtended { fields };
+                event::record(event_store, &index_id, event).await;
+            }
+        }
+
+        let writer = writer_pool.checkout(index_loader, schema_loader, &index_id)?;
+        pending.writers.insert(index_id.clone(), writer);
+    }
+    let writer = pending
+        .writers
+        .get_mut(&index_id)
+        .expect("writer was just opened");
+
+    let job_changes = handle_job(writer, document_store, enricher, &settings, job)
+        .instrument(span)
+        .await?;
+    pending
+        .changes
+        .entry(index_id)
+        .or_default()
+        .extend(job_changes);
+
+    Ok(())
+}
+
+/// Commits `writer`, then records the changes and tokens of the jobs committed and completes
+/// them, before merging, rebuilding the index's suggestion dictionary and closing the writer,
+/// whose index is pooled in `writer_pool`. The commit, its merges, and any change of settings are added to the index's
+/// timeline, and their durations emitted as metrics. For [`process_jobs`].
+#[allow(clippy::too_many_arguments)]
+async fn commit_index(
+    index_loader: &dyn IndexLoader,
+    schema_loader: &dyn SchemaLoader,
+    job_store: &dyn JobStore,
+    change_store: &dyn ChangeStore,
+    event_store: &dyn EventStore,
+    token_store: &dyn TokenStore,
+    writer_pool: &WriterPool,
+    index: &str,
+    mut writer: IndexWriter,
+    pending: &mut Pending,
+) -> Result<(), ServiceError> {
+    let commit_started = Instant::now();
+    let opstamp = writer
+        .commit_with_meta()
+        .map_err(ServiceError::internal_error)?;
+    let commit_duration = commit_started.elapsed();
+    let job_ids = pending.job_ids.remove(index).unwrap_or_default();
+    info!(message = "index_commit", index, job_ids = ?job_ids);
+    let index_changes = pending.changes.remove(index).unwrap_or_default();
+    let count = |op: fn(&Change) -> bool| index_changes.iter().filter(|c| op(c)).count();
+    let docs_indexed = count(|change| matches!(change, Change::Index { .. }));
+    let docs_deleted = count(|change| matches!(change, Change::Delete { .. }));
+    if !index_changes.is_empty() {
+        change_store.append_changes(index, index_changes).await?;
+    }
+    let applied = pending.applied.remove(index).unwrap_or_default();
+    if !applied.is_empty() {
+        token_store.put_tokens(index, applied).await?;
+    }
+    job_store.complete_jobs(&job_ids).await?;
+    let settings = schema_loader.load_settings(index)?;
+    let merge_started = Instant::now();
+    let merged = writer
+        .merge_now(&settings.merge_policy)
+        .map_err(ServiceError::internal_error)?;
+    let merge_duration = merge_started.elapsed();
+    // The dictionary is rebuilt by the next commit, so the commit stands without it.
+    if let Err(err) = suggest::rebuild(index_loader, index, writer.index(), &settings) {
+        warn!(message = "suggest_rebuild_failed", index, error = %err);
+    }
+    let segments = writer
+        .index()
+        .searchable_segment_metas()
+        .map_err(ServiceError::internal_error)?
+        .len();
+    writer_pool.checkin(schema_loader, index, writer)?;
+
+    MetricSet::index(index)
+        .duration("CommitDuration", commit_duration)
+        .duration("MergeDuration", merge_duration)
+        .count("DocsIndexed", docs_indexed)
+        .count("DocsDeleted", docs_deleted)
+        .count("Segments", segments)
+        .emit();
+
+    let mut events = vec![IndexEvent::Commit {
+        opstamp,
+        job_ids,
+        docs_indexed,
+        docs_deleted,
+        segments,
+    }];
+    if !merged.is_empty() {
+        events.push(IndexEvent::Merge {
+            merged_segments: merged.len(),
+            purged_docs: merged
+                .iter()
+                .map(|segment| segment.num_deleted_docs() as u64)
+                .sum(),
         });
+    }
+    if let Some(settings) = record_settings(index_loader, index, &settings)? {
+        events.push(IndexEvent::SettingsChanged { settings });
+    }
+    for event in events {
+        event::record(event_store, index, event).await;
+    }
+
+    Ok(())
+}
+
+/// File in each index directory holding the settings of its last commit.
+const SETTINGS_FILE: &str = "pathery-settings.json";
 
-        handle_job(&mut writer, document_store, job).await;
+/// Saves `settings` beside the index, returning them when they differ from those of its last
+/// commit. Indexes committed before settings were saved have nothing to compare against.
+fn record_settings(
+    index_loader: &dyn IndexLoader,
+    index_id: &str,
+    settings: &IndexSettings,
+) -> Result<Option<json::Value>, ServiceError> {
+    let settings = json::to_value(settings).expect("settings should serialize");
+    let previous = index_loader
+        .load_index(index_id, None)?
+        .directory()
+        .atomic_read(Path::new(SETTINGS_FILE))
+        .ok()
+        .and_then(|bytes| json::from_slice::<json::Value>(&bytes).ok());
+
+    if previous.as_ref() == Some(&settings) {
+        return Ok(None);
     }
+    let bytes = json::to_vec(&settings).expect("settings should serialize");
+    index_loader.write_index_file(index_id, SETTINGS_FILE, &bytes)?;
 
-    for (index, mut writer) in writers.into_iter() {
-        writer.commit().expect("commit should succeed");
-        info!(message = "index_commit", index);
-        writer
-            .wait_merging_threads()
-            .expect("merge should finish without error");
+    Ok(previous.map(|_| settings))
+}
+
+/// Whether `err` fails a job the same way however often it's retried, such as a document the
+/// index can't take or an index that no longer exists. Other errors, such as EFS I/O errors, a
+/// lock still held by a writer shutting down or throttled DynamoDB requests, are worth retrying.
+fn is_permanent(err: &ServiceError) -> bool {
+    match err {
+        ServiceError::InvalidRequest(_) | ServiceError::NotFound(_) => true,
+        ServiceError::InternalError { source, .. } => source.chain().any(|cause| {
+            matches!(
+                cause.downcast_ref::<TantivyError>(),
+                Some(
+                    TantivyError::SchemaError(_)
+                        | TantivyError::InvalidArgument(_)
+                        | TantivyError::FieldNotFound(_)
+                )
+            ) || cause.is::<json::Error>()
+        }),
+        _ => false,
+    }
+}
+
+/// A failure applying or committing the jobs of an index, with the job it came from when it
+/// came from applying one.
+struct IndexFailure {
+    job_id: Option<String>,
+    error: ServiceError,
+}
+
+/// Applies `jobs`, all of one index, in order and commits them, for [`process_index`].
+#[allow(clippy::too_many_arguments)]
+async fn apply_index(
+    document_store: &dyn DocumentStore,
+    index_loader: &dyn IndexLoader,
+    schema_loader: &dyn SchemaLoader,
+    job_store: &dyn JobStore,
+    change_store: &dyn ChangeStore,
+    event_store: &dyn EventStore,
+    token_store: &dyn TokenStore,
+    writer_pool: &WriterPool,
+    enricher: &Enricher,
+    index_id: &str,
+    jobs: &[Job],
+) -> Result<(), IndexFailure> {
+    // Dropped on failure along with its writer, discarding the index's uncommitted ops.
+    let mut pending = Pending::default();
+
+    for (i, job) in jobs.iter().enumerate() {
+        pending
+            .job_ids
+            .entry(index_id.into())
+            .or_default()
+            .push(job.job_id.clone());
+
+        apply_job(
+            document_store,
+            index_loader,
+            schema_loader,
+            job_store,
+            change_store,
+            event_store,
+            token_store,
+            writer_pool,
+            enricher,
+            &mut pending,
+            job.clone(),
+            &jobs[i + 1..],
+        )
+        .await
+        .map_err(|error| IndexFailure {
+            job_id: Some(job.job_id.clone()),
+            error,
+        })?;
+    }
+
+    if let Some(writer) = pending.writers.remove(index_id) {
+        commit_index(
+            index_loader,
+            schema_loader,
+            job_store,
+            change_store,
+            event_store,
+            token_store,
+            writer_pool,
+            index_id,
+            writer,
+            &mut pending,
+        )
+        .await
+        .map_err(|error| IndexFailure {
+            job_id: None,
+            error,
+        })?;
     }
 
     Ok(())
 }
 
+/// Applies and commits the jobs of `index_id`, for [`process_jobs`]. Transient failures start
+/// the index's jobs over after a backoff. A job that fails permanently is quarantined: marked
+/// failed, added to the index's timeline and dropped, and the index's other jobs start over
+/// without it, so one bad job doesn't hold up the rest.
+#[allow(clippy::too_many_arguments)]
+async fn process_index(
+    document_store: &dyn DocumentStore,
+    index_loader: &dyn IndexLoader,
+    schema_loader: &dyn SchemaLoader,
+    job_store: &dyn JobStore,
+    change_store: &dyn ChangeStore,
+    event_store: &dyn EventStore,
+    token_store: &dyn TokenStore,
+    writer_pool: &WriterPool,
+    enricher: &Enricher,
+    index_id: &str,
+    mut jobs: Vec<Job>,
+    quarantined: &mut HashMap<String, ServiceError>,
+) -> Result<(), ServiceError> {
+    MetricSet::index(index_id)
+        .count("BatchSize", jobs.len())
+        .emit();
+
+    // Commits, retries and quarantines are logged with the requests of every job they cover.
+    let span = info_span!(
+        "index_jobs",
+        index = index_id,
+        job_ids = ?jobs.iter().map(|job| job.job_id.as_str()).collect::<Vec<_>>(),
+        request_ids = ?jobs.iter().filter_map(|job| job.request_id.as_deref()).collect::<Vec<_>>(),
+        trace_ids = ?jobs.iter().filter_map(|job| job.trace_id.as_deref()).collect::<Vec<_>>()
+    );
+
+    let backoff = Backoff::default();
+    let mut attempt = 0;
+
+    async {
+        loop {
+            let result = apply_index(
+                document_store,
+                index_loader,
+                schema_loader,
+                job_store,
+                change_store,
+                event_store,
+                token_store,
+                writer_pool,
+                enricher,
+                index_id,
+                &jobs,
+            )
+            .await;
+
+            let IndexFailure { job_id, error } = match result {
+                Ok(()) => return Ok(()),
+                Err(failure) => failure,
+            };
+
+            if !is_permanent(&error) {
+                if attempt + 1 >= backoff.max_attempts {
+                    return Err(error);
+                }
+                warn!(message = "index_jobs_retried", index = index_id, attempt, error = %error);
+                tokio::time::sleep(backoff.delay(attempt)).await;
+                attempt += 1;
+                continue;
+            }
+
+            // Commits fail for the whole index, so there's no one job to quarantine.
+            let Some(job_id) = job_id else {
+                return Err(error);
+            };
+            warn!(message = "job_quarantined", index = index_id, job_id, error = %error);
+            job_store.fail_job(&job_id, &error.to_string()).await?;
+            let event = IndexEvent::JobFailed {
+                job_id: job_id.clone(),
+                error: error.to_string(),
+            };
+            event::record(event_store, index_id, event).await;
+            jobs.retain(|job| job.job_id != job_id);
+            quarantined.insert(job_id, error);
+        }
+    }
+    .instrument(span)
+    .await
+}
+
+/// Applies `jobs` in order, committing each index once its jobs are applied. Dynamic indexes
+/// have their schema extended for new fields before their writer opens. Writers are opened from
+/// the indexes in `writer_pool` and closed once committed, so that no index's directory lock is
+/// held between batches.
+///
+/// Doc ops older than their doc's last applied token are skipped, so a redelivered or retried job
+/// doesn't undo later writes. An index whose jobs keep failing is reported in `failed`, its
+/// uncommitted ops dropped, so that retrying its messages applies them in order. Jobs that fail
+/// permanently are reported in `quarantined`. Other indexes are unaffected.
+#[allow(clippy::too_many_arguments)]
+pub async fn process_jobs(
+    document_store: &dyn DocumentStore,
+    index_loader: &dyn IndexLoader,
+    schema_loader: &dyn SchemaLoader,
+    job_store: &dyn JobStore,
+    change_store: &dyn ChangeStore,
+    event_store: &dyn EventStore,
+    token_store: &dyn TokenStore,
+    writer_pool: &WriterPool,
+    enricher: &Enricher,
+    jobs: Vec<Job>,
+) -> ProcessedJobs {
+    let mut processed = ProcessedJobs::default();
+
+    let mut by_index: Vec<(String, Vec<Job>)> = vec![];
+    for job in jobs {
+        match by_index
+            .iter_mut()
+            .find(|(index_id, _)| *index_id == job.index_id)
+        {
+            Some((_, index_jobs)) => index_jobs.push(job),
+            None => by_index.push((job.index_id.clone(), vec![job])),
+        }
+    }
+
+    for (index_id, jobs) in by_index {
+        let result = process_index(
+            document_store,
+            index_loader,
+            schema_loader,
+            job_store,
+            change_store,
+            event_store,
+            token_store,
+            writer_pool,
+            enricher,
+            &index_id,
+            jobs,
+            &mut processed.quarantined,
+        )
+        .await;
+
+        if let Err(err) = result {
+            warn!(message = "index_jobs_failed", index = index_id, error = %err);
+            processed.failed.insert(index_id, err);
+        }
+    }
+
+    processed
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -109,7 +859,13 @@ mod tests {
     use super::job::Job;
     use super::{handle_event, *};
     use crate::schema::SchemaLoader;
-    use crate::search_doc::SearchDoc;
+    use crate::search_doc::{SearchDoc, SearchDocId};
+    use crate::store::job::{JobState, JobStatus};
+    use crate::store::lease::test_util::TestLeaseStore;
+    use crate::store::lookup::test_util::TestLookupTable;
+    use crate::store::schema::test_util::TestSchemaStore;
+    use crate::store::schema::SchemaStore;
+    use crate::store::token::MemoryTokenStore;
     use crate::test_utils::*;
 
     #[tokio::test]
@@ -138,6 +894,13 @@ mod tests {
             job.index_doc(doc_ref);
         }
 
+        let job_id = job.job_id.clone();
+
+        ctx.job_store()
+            .create_job(JobStatus::pending(&job_id, "test"))
+            .await
+            .unwrap();
+
         let message = SqsMessage {
             body: Some(json::to_string(&job).unwrap()),
             ..Default::default()
@@ -147,14 +910,218 @@ mod tests {
             records: vec![message],
         };
 
-        handle_event(
+        let response = handle_event(
             ctx.document_store(),
             ctx.index_loader(),
+            ctx.schema_loader(),
+            ctx.job_store(),
+            ctx.change_store(),
+            ctx.event_store(),
+            &MemoryTokenStore::create(),
+            &TestLeaseStore::create(),
+            &WriterPool::default(),
+            &Enricher::new(TestLookupTable::create()),
             LambdaEvent::new(event, Context::default()),
         )
         .await
         .unwrap();
 
+        assert!(response.batch_item_failures.is_empty());
+
+        assert_eq!(
+            1,
+            ctx.index_loader()
+                .load_index("test", None)
+                .unwrap()
+                .reader()
+                .unwrap()
+                .searcher()
+                .num_docs()
+        );
+
+        assert_eq!(
+            JobState::Complete,
+            ctx.job_store()
+                .get_job(&job_id)
+                .await
+                .unwrap()
+                .unwrap()
+                .status
+        );
+    }
+
+    #[tokio::test]
+    async fn locked_index_is_returned_to_queue() {
+        let ctx = setup();
+
+        let lease_store = TestLeaseStore::create();
+        lease_store
+            .acquire(
+                "test",
+                "other-worker",
+                Utc::now() + chrono::Duration::minutes(1),
+            )
+            .await
+            .unwrap();
+
+        let mut job = Job::create("test");
+        job.delete_index();
+
+        let message = SqsMessage {
+            message_id: Some("message-1".into()),
+            body: Some(json::to_string(&job).unwrap()),
+            ..Default::default()
+        };
+
+        let event = sqs::SqsEvent {
+            records: vec![message],
+        };
+
+        let response = handle_event(
+            ctx.document_store(),
+            ctx.index_loader(),
+            ctx.schema_loader(),
+            ctx.job_store(),
+            ctx.change_store(),
+            ctx.event_store(),
+            &MemoryTokenStore::create(),
+            &lease_store,
+            &WriterPool::default(),
+            &Enricher::new(TestLookupTable::create()),
+            LambdaEvent::new(event, Context::default()),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            vec![BatchItemFailure {
+                item_identifier: "message-1".into()
+            }],
+            response.batch_item_failures
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_messages_do_not_fail_the_batch() {
+        let ctx = setup();
+
+        let document = SearchDoc::from_json(
+            &ctx.schema_loader().load_schema("test").unwrap(),
+            json!({ "year": 1989 }),
+        )
+        .unwrap();
+        let mut job = Job::create("test");
+        for doc_ref in ctx
+            .document_store()
+            .save_documents(vec![document])
+            .await
+            .unwrap()
+        {
+            job.index_doc(doc_ref);
+        }
+
+        let message = |id: &str, body: String| SqsMessage {
+            message_id: Some(id.into()),
+            body: Some(body),
+            ..Default::default()
+        };
+        let event = sqs::SqsEvent {
+            records: vec![
+                message("invalid", "not a job".into()),
+                message(
+                    "unknown-index",
+                    json::to_string(&Job::create("unknown")).unwrap(),
+                ),
+                message("valid", json::to_string(&job).unwrap()),
+            ],
+        };
+
+        let response = handle_event(
+            ctx.document_store(),
+            ctx.index_loader(),
+            ctx.schema_loader(),
+            ctx.job_store(),
+            ctx.change_store(),
+            ctx.event_store(),
+            &MemoryTokenStore::create(),
+            &TestLeaseStore::create(),
+            &WriterPool::default(),
+            &Enricher::new(TestLookupTable::create()),
+            LambdaEvent::new(event, Context::default()),
+        )
+        .await
+        .unwrap();
+
+        let failed: Vec<_> = response
+            .batch_item_failures
+            .iter()
+            .map(|failure| failure.item_identifier.as_str())
+            .collect();
+        // Jobs for an index that doesn't exist are quarantined rather than redelivered.
+        assert_eq!(vec!["invalid"], failed);
+
+        assert_eq!(
+            1,
+            ctx.index_loader()
+                .load_index("test", None)
+                .unwrap()
+                .reader()
+                .unwrap()
+                .searcher()
+                .num_docs()
+        );
+    }
+
+    #[tokio::test]
+    async fn permanently_failing_jobs_are_quarantined() {
+        let ctx = setup();
+        let schema = ctx.schema_loader().load_schema("test").unwrap();
+
+        let mut docs = vec![];
+        for year in [1989, 1990] {
+            docs.push(SearchDoc::from_json(&schema, json!({ "year": year })).unwrap());
+        }
+        docs[0].content_mut().remove("__id");
+        let doc_refs = ctx.document_store().save_documents(docs).await.unwrap();
+
+        let mut jobs = vec![];
+        for doc_ref in doc_refs {
+            let mut job = Job::create("test");
+            job.index_doc(doc_ref);
+            ctx.job_store()
+                .create_job(JobStatus::pending(&job.job_id, "test"))
+                .await
+                .unwrap();
+            jobs.push(job);
+        }
+        let job_ids: Vec<String> = jobs.iter().map(|job| job.job_id.clone()).collect();
+
+        let processed = process_jobs(
+            ctx.document_store(),
+            ctx.index_loader(),
+            ctx.schema_loader(),
+            ctx.job_store(),
+            ctx.change_store(),
+            ctx.event_store(),
+            &MemoryTokenStore::create(),
+            &WriterPool::default(),
+            &Enricher::new(TestLookupTable::create()),
+            jobs,
+        )
+        .await;
+
+        assert!(processed.failed.is_empty());
+        assert_eq!(
+            vec![&job_ids[0]],
+            processed.quarantined.keys().collect::<Vec<_>>()
+        );
+
+        let poisoned = ctx.job_store().get_job(&job_ids[0]).await.unwrap().unwrap();
+        assert_eq!(JobState::Failed, poisoned.status);
+        assert_eq!(Some(String::from("Document has no __id")), poisoned.error);
+        let healthy = ctx.job_store().get_job(&job_ids[1]).await.unwrap().unwrap();
+        assert_eq!(JobState::Complete, healthy.status);
+
         assert_eq!(
             1,
             ctx.index_loader()
@@ -166,4 +1133,165 @@ mod tests {
                 .num_docs()
         );
     }
+
+    #[test]
+    fn transient_errors_are_not_permanent() {
+        let io = std::io::Error::new(std::io::ErrorKind::TimedOut, "EFS timed out");
+        assert!(!is_permanent(&ServiceError::internal_error(
+            TantivyError::from(io)
+        )));
+        assert!(!is_permanent(&ServiceError::rate_limit()));
+
+        let schema = TantivyError::SchemaError(String::from("unknown field"));
+        assert!(is_permanent(&ServiceError::internal_error(schema)));
+        assert!(is_permanent(&ServiceError::not_found("no index")));
+    }
+
+    #[tokio::test]
+    async fn stale_ops_are_skipped() {
+        let ctx = setup();
+        let token_store = MemoryTokenStore::create();
+
+        let document = SearchDoc::from_json(
+            &ctx.schema_loader().load_schema("test").unwrap(),
+            json!({ "year": 1989 }),
+        )
+        .unwrap();
+        let doc_ref = ctx
+            .document_store()
+            .save_documents(vec![document])
+            .await
+            .unwrap()
+            .remove(0);
+        let doc_id = SearchDocId::parse(doc_ref.id());
+
+        let writer_pool = WriterPool::default();
+        let enricher = Enricher::new(TestLookupTable::create());
+        let process = |job: Job| {
+            process_jobs(
+                ctx.document_store(),
+                ctx.index_loader(),
+                ctx.schema_loader(),
+                ctx.job_store(),
+                ctx.change_store(),
+                ctx.event_store(),
+                &token_store,
+                &writer_pool,
+                &enricher,
+                vec![job],
+            )
+        };
+        let num_docs = || {
+            ctx.index_loader()
+                .load_index("test", None)
+                .unwrap()
+                .reader()
+                .unwrap()
+                .searcher()
+                .num_docs()
+        };
+
+        let mut index = Job::create("test").with_token(Some(2));
+        index.index_doc(doc_ref);
+        assert!(process(index).await.failed.is_empty());
+        assert_eq!(1, num_docs());
+
+        // A redelivered delete submitted before the doc was indexed.
+        let mut stale = Job::create("test").with_token(Some(1));
+        stale.delete_doc(doc_id.clone());
+        assert!(process(stale).await.failed.is_empty());
+        assert_eq!(1, num_docs());
+
+        let mut delete = Job::create("test").with_token(Some(3));
+        delete.delete_doc(doc_id);
+        assert!(process(delete).await.failed.is_empty());
+        assert_eq!(0, num_docs());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn deleted_indexes_leave_no_stored_data() {
+        let ctx = setup();
+        let token_store = MemoryTokenStore::create();
+        let schema_store = TestSchemaStore::create();
+        schema_store
+            .create_config(
+                "created",
+                &json!({ "fields": [{ "name": "title", "kind": "text", "flags": ["TEXT"] }] }),
+            )
+            .await
+            .unwrap();
+        let schema_loader = ctx.schema_loader().clone().with_store(schema_store.clone());
+        let index_loader = ctx.index_loader().with_schema_loader(schema_loader.clone());
+
+        let document = SearchDoc::from_json(
+            &schema_loader.load_schema("created").unwrap(),
+            json!({ "title": "hello" }),
+        )
+        .unwrap();
+        let doc_refs = ctx
+            .document_store()
+            .save_documents(vec![document])
+            .await
+            .unwrap();
+        let doc_ids: Vec<String> = doc_refs.iter().map(|doc_ref| doc_ref.id().into()).collect();
+
+        let writer_pool = WriterPool::default();
+        let enricher = Enricher::new(TestLookupTable::create());
+        let process = |job: Job| {
+            process_jobs(
+                ctx.document_store(),
+                &index_loader,
+                &schema_loader,
+                ctx.job_store(),
+                ctx.change_store(),
+                ctx.event_store(),
+                &token_store,
+                &writer_pool,
+                &enricher,
+                vec![job],
+            )
+        };
+
+        let mut index = Job::create("created").with_token(Some(1));
+        index.index_doc(doc_refs[0].clone());
+        assert!(process(index).await.failed.is_empty());
+        assert_eq!(
+            1,
+            token_store
+                .get_tokens("created", &doc_ids)
+                .await
+                .unwrap()
+                .len()
+        );
+
+        let mut delete = Job::create("created");
+        delete.delete_index();
+        assert!(process(delete).await.failed.is_empty());
+
+        assert!(ctx
+            .document_store()
+            .get_documents(doc_refs)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(token_store
+            .get_tokens("created", &doc_ids)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(schema_store.get_config("created").await.unwrap().is_none());
+        assert!(!index_loader
+            .list_indexes()
+            .unwrap()
+            .contains(&String::from("created")));
+
+        // Only the deletion is left for consumers syncing from the change log.
+        let changes = ctx
+            .change_store()
+            .list_changes("created", None, 10)
+            .await
+            .unwrap();
+        assert_eq!(1, changes.len());
+        assert_eq!(vec![Change::DeleteIndex], changes[0].changes);
+    }
 }
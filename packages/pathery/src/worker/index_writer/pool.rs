@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use tantivy::{Index, IndexWriter};
+use tracing::info;
+
+use crate::index::{IndexExt, IndexLoader};
+use crate::json;
+use crate::schema::{schema_fingerprint, SchemaLoader};
+use crate::service::ServiceError;
+
+/// Most indexes kept open at once.
+const MAX_POOLED_INDEXES: usize = 16;
+
+/// What a pooled index was opened against. An index is only reused while all of it still
+/// matches, since an opened index fixes the schema, tokenizers and directory settings that
+/// writers opened from it use.
+#[derive(Debug, PartialEq, Eq)]
+struct IndexKey {
+    schema_version: String,
+    settings: String,
+    schema: String,
+}
+
+impl IndexKey {
+    fn create(
+        schema_loader: &dyn SchemaLoader,
+        index_id: &str,
+        index: &Index,
+    ) -> Result<IndexKey, ServiceError> {
+        let metas = index.load_metas().map_err(ServiceError::internal_error)?;
+
+        Ok(IndexKey {
+            schema_version: schema_loader.load_schema_version(index_id)?,
+            settings: json::to_string(&schema_loader.load_settings(index_id)?)
+                .expect("settings should serialize"),
+            schema: schema_fingerprint(&metas.schema),
+        })
+    }
+}
+
+struct PooledIndex {
+    index: Index,
+    key: IndexKey,
+    checked_in_at: Instant,
+}
+
+/// Indexes kept open between the batches a warm worker instance handles, so that hot indexes
+/// skip opening their directory and registering their tokenizers. Only the index is pooled:
+/// each batch opens its own writer and drops it once committed, since a writer holds the
+/// directory lock that other workers and handlers writing to the index need between batches.
+#[derive(Default)]
+pub struct WriterPool {
+    indexes: Mutex<HashMap<String, PooledIndex>>,
+}
+
+impl WriterPool {
+    /// A writer for `index_id`, opened from the pooled index when the index's schema and
+    /// settings haven't changed since it was checked in. Writers merge in the foreground so that
+    /// no work continues once the batch, and its lease, is over.
+    pub fn checkout(
+        &self,
+        index_loader: &dyn IndexLoader,
+        schema_loader: &dyn SchemaLoader,
+        index_id: &str,
+    ) -> Result<IndexWriter, ServiceError> {
+        let pooled = self.indexes.lock().unwrap().remove(index_id);
+
+        // An index whose version was swapped out by a rebuild no longer loads, and is reopened.
+        let index = match pooled {
+            Some(pooled)
+                if IndexKey::create(schema_loader, index_id, &pooled.index)
+                    .ok()
+                    .as_ref()
+                    == Some(&pooled.key) =>
+            {
+                info!(message = "index_reused", index = index_id);
+                pooled.index
+            }
+            Some(_) => {
+                info!(message = "index_invalidated", index = index_id);
+                index_loader.load_index(index_id, None)?
+            }
+            None => index_loader.load_index(index_id, None)?,
+        };
+
+        Ok(index.foreground_merge_writer())
+    }
+
+    /// Closes a writer with no uncommitted changes, releasing the index's directory lock, and
+    /// pools the index it was opened from, evicting the least recently used index when the pool
+    /// is full.
+    pub fn checkin(
+        &self,
+        schema_loader: &dyn SchemaLoader,
+        index_id: &str,
+        writer: IndexWriter,
+    ) -> Result<(), ServiceError> {
+        let index = writer.index().clone();
+        writer
+            .wait_merging_threads()
+            .map_err(ServiceError::internal_error)?;
+        let key = IndexKey::create(schema_loader, index_id, &index)?;
+
+        let mut indexes = self.indexes.lock().unwrap();
+        if indexes.len() >= MAX_POOLED_INDEXES && !indexes.contains_key(index_id) {
+            let oldest = indexes
+                .iter()
+                .min_by_key(|(_, pooled)| pooled.checked_in_at)
+                .map(|(index_id, _)| index_id.clone());
+            if let Some(oldest) = oldest {
+                indexes.remove(&oldest);
+            }
+        }
+
+        indexes.insert(
+            index_id.into(),
+            PooledIndex {
+                index,
+                key,
+                checked_in_at: Instant::now(),
+            },
+        );
+        Ok(())
+    }
+
+    /// Drops the pooled index for `index_id`, such as when the index is deleted.
+    pub fn evict(&self, index_id: &str) {
+        self.indexes.lock().unwrap().remove(index_id);
+    }
+
+    pub fn len(&self) -> usize {
+        self.indexes.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tantivy::doc;
+    use tantivy::schema::{FieldEntry, TEXT};
+
+    use super::*;
+    use crate::index::IndexWriterExt;
+    use crate::test_utils::*;
+
+    fn add_doc(writer: &mut IndexWriter, id: &str) {
+        let id_field = writer.index().schema().get_field("__id").unwrap();
+        writer.add_document(doc!(id_field => id)).unwrap();
+        writer.commit_with_meta().unwrap();
+    }
+
+    #[tokio::test]
+    async fn indexes_are_invalidated_by_changes_to_the_schema() {
+        let ctx = setup();
+        let pool = WriterPool::default();
+        let index_loader = ctx.index_loader();
+        let schema_loader = ctx.schema_loader();
+
+        let mut writer = pool.checkout(index_loader, schema_loader, "test").unwrap();
+        add_doc(&mut writer, "a");
+        pool.checkin(schema_loader, "test", writer).unwrap();
+        assert_eq!(1, pool.len());
+
+        // The schema changes without the pooled index, as when a dynamic index is extended.
+        index_loader
+            .extend_schema("test", vec![FieldEntry::new_text("notes".into(), TEXT)])
+            .unwrap();
+
+        let mut writer = pool.checkout(index_loader, schema_loader, "test").unwrap();
+        assert!(pool.is_empty());
+        assert!(writer.index().schema().get_field("notes").is_some());
+        add_doc(&mut writer, "b");
+
+        let index = index_loader.load_index("test", None).unwrap();
+        assert!(index.schema().get_field("notes").is_some());
+        assert_eq!(2, index.reader().unwrap().searcher().num_docs());
+    }
+
+    #[tokio::test]
+    async fn checked_in_writers_release_the_directory_lock() {
+        let ctx = setup();
+        let pool = WriterPool::default();
+        let index_loader = ctx.index_loader();
+        let schema_loader = ctx.schema_loader();
+
+        let mut writer = pool.checkout(index_loader, schema_loader, "test").unwrap();
+        add_doc(&mut writer, "a");
+        pool.checkin(schema_loader, "test", writer).unwrap();
+
+        // Another worker, such as the merge worker, writes between batches.
+        let index = index_loader.load_index("test", None).unwrap();
+        let mut other = index.writer(15_000_000).unwrap();
+        add_doc(&mut other, "b");
+        drop(other);
+
+        let mut writer = pool.checkout(index_loader, schema_loader, "test").unwrap();
+        add_doc(&mut writer, "c");
+        pool.checkin(schema_loader, "test", writer).unwrap();
+
+        let index = index_loader.load_index("test", None).unwrap();
+        assert_eq!(3, index.reader().unwrap().searcher().num_docs());
+    }
+}
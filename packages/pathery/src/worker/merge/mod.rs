@@ -0,0 +1,224 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json as json;
+use tantivy::{SegmentId, SegmentMeta};
+use tracing::{info, warn};
+
+use crate::index::{IndexExt, IndexLoader};
+use crate::lambda::lambda_runtime::LambdaEvent;
+use crate::schema::SchemaLoader;
+use crate::service::ServiceError;
+use crate::store::event::{self, EventStore, IndexEvent};
+use crate::store::lease::LeaseStore;
+use crate::{compat, lambda};
+
+/// Searchable segments an index can have before it's merged.
+const MAX_SEGMENTS: usize = 10;
+
+/// Share of an index's documents that can be deleted before it's merged to purge them.
+const MAX_DELETED_RATIO: f64 = 0.2;
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct MergeReport {
+    pub index_id: String,
+
+    /// Segments merged into one, none when the index didn't need merging.
+    pub merged_segments: usize,
+
+    /// Deleted documents the merge purged.
+    pub purged_docs: u64,
+
+    /// Segments in an older format than the current one, which the merge rewrote.
+    pub outdated_segments: usize,
+
+    /// Files no longer referenced by the index, such as those left by a writer that crashed.
+    pub deleted_files: usize,
+}
+
+/// Whether an index has more segments, or more deleted documents, than merges after each
+/// commit leave it with.
+fn needs_merge(segments: &[SegmentMeta]) -> bool {
+    let max_doc: u64 = segments
+        .iter()
+        .map(|segment| segment.max_doc() as u64)
+        .sum();
+    let deleted: u64 = segments
+        .iter()
+        .map(|segment| segment.num_deleted_docs() as u64)
+        .sum();
+
+    segments.len() > MAX_SEGMENTS
+        || (max_doc > 0 && deleted as f64 / max_doc as f64 > MAX_DELETED_RATIO)
+}
+
+/// Merges every segment of `index_id` into one when it needs merging, when any segment is in
+/// an older format, which the merge rewrites in the current one, or when `force`d. Then deletes
+/// the files no commit refers to. The caller holds the index's lease.
+pub fn merge_index(
+    index_loader: &dyn IndexLoader,
+    index_id: &str,
+    force: bool,
+) -> Result<MergeReport, ServiceError> {
+    let index = index_loader.load_index(index_id, None)?;
+    let segments = index
+        .searchable_segment_metas()
+        .map_err(ServiceError::internal_error)?;
+    let outdated_segments = index_loader
+        .segment_formats(index_id)?
+        .iter()
+        .filter(|format| format.format_version < compat::format_version())
+        .count();
+    let mut writer = index.foreground_merge_writer();
+
+    let merge = force || needs_merge(&segments) || outdated_segments > 0;
+    let (merged_segments, purged_docs) = if merge && !segments.is_empty() {
+        let segment_ids = segments
+            .iter()
+            .map(SegmentMeta::id)
+            .collect::<Vec<SegmentId>>();
+        writer
+            .merge(&segment_ids)
+            .wait()
+            .map_err(ServiceError::internal_error)?;
+        let purged_docs = segments
+            .iter()
+            .map(|segment| segment.num_deleted_docs() as u64)
+            .sum();
+        (segments.len(), purged_docs)
+    } else {
+        (0, 0)
+    };
+
+    let collected = writer
+        .garbage_collect_files()
+        .wait()
+        .map_err(ServiceError::internal_error)?;
+
+    Ok(MergeReport {
+        index_id: index_id.into(),
+        merged_segments,
+        purged_docs,
+        outdated_segments,
+        deleted_files: collected.deleted_files.len(),
+    })
+}
+
+/// Scans every index on a schedule, merging those whose segments have piled up and collecting
+/// unreferenced files, so the index writer's merges after each commit can stay small. Indexes a
+/// writer holds the lease on are skipped until the next run. Merges are added to the timeline
+/// of the index merged.
+pub async fn handle_event(
+    index_loader: &dyn IndexLoader,
+    schema_loader: &dyn SchemaLoader,
+    lease_store: &dyn LeaseStore,
+    event_store: &dyn EventStore,
+    event: LambdaEvent<json::Value>,
+) -> Result<Vec<MergeReport>, lambda::Error> {
+    let owner = event.context.request_id.clone();
+    let expires_at =
+        DateTime::<Utc>::from(UNIX_EPOCH + Duration::from_millis(event.context.deadline));
+
+    let mut reports = vec![];
+
+    for index_id in index_loader.list_indexes()? {
+        // Without a schema the index cannot be opened.
+        if schema_loader.index_prefix(&index_id).is_none() {
+            continue;
+        }
+
+        if !lease_store.acquire(&index_id, &owner, expires_at).await? {
+            warn!(message = "index_locked", index = index_id);
+            continue;
+        }
+
+        let result = merge_index(index_loader, &index_id, false);
+        lease_store.release(&index_id, &owner).await?;
+        let report = result?;
+
+        info!(
+            message = "index_merged",
+            index = index_id,
+            merged_segments = report.merged_segments,
+            purged_docs = report.purged_docs,
+            outdated_segments = report.outdated_segments,
+            deleted_files = report.deleted_files
+        );
+        if report.merged_segments > 0 {
+            let event = IndexEvent::Merge {
+                merged_segments: report.merged_segments,
+                purged_docs: report.purged_docs,
+            };
+            event::record(event_store, &index_id, event).await;
+        }
+        reports.push(report);
+    }
+
+    Ok(reports)
+}
+
+#[cfg(test)]
+mod tests {
+    use lambda_http::Context;
+    use tantivy::doc;
+
+    use super::*;
+    use crate::index::IndexWriterExt;
+    use crate::store::lease::test_util::TestLeaseStore;
+    use crate::test_utils::*;
+
+    #[tokio::test]
+    async fn indexes_with_too_many_segments_are_merged() {
+        let ctx = setup();
+        for index_id in ["test", "test-quiet"] {
+            let index = ctx.index_loader().load_index(index_id, None).unwrap();
+            let mut writer = index.foreground_merge_writer();
+            let commits = if index_id == "test" {
+                MAX_SEGMENTS + 1
+            } else {
+                2
+            };
+            for n in 0..commits {
+                writer
+                    .add_document(doc!(index.id_field() => n.to_string()))
+                    .unwrap();
+                writer.commit_with_meta().unwrap();
+            }
+        }
+
+        let lease_store = TestLeaseStore::create();
+        lease_store
+            .acquire(
+                "test-locked",
+                "writer",
+                Utc::now() + chrono::Duration::minutes(1),
+            )
+            .await
+            .unwrap();
+        ctx.index_loader().load_index("test-locked", None).unwrap();
+
+        let reports = handle_event(
+            ctx.index_loader(),
+            ctx.schema_loader(),
+            &lease_store,
+            ctx.event_store(),
+            LambdaEvent::new(json!({}), Context::default()),
+        )
+        .await
+        .unwrap();
+
+        let merged = reports
+            .iter()
+            .map(|report| (report.index_id.as_str(), report.merged_segments))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![("test", MAX_SEGMENTS + 1), ("test-quiet", 0)], merged);
+
+        let index = ctx.index_loader().load_index("test", None).unwrap();
+        assert_eq!(1, index.searchable_segment_metas().unwrap().len());
+        assert_eq!(
+            MAX_SEGMENTS as u64 + 1,
+            index.reader().unwrap().searcher().num_docs()
+        );
+    }
+}
@@ -1,2 +1,9 @@
 pub mod async_delete;
+pub mod catalog;
+pub mod duplicates;
+pub mod dynamodb_stream;
+pub mod expire;
 pub mod index_writer;
+pub mod merge;
+pub mod reindex;
+pub mod update_by_query;
@@ -0,0 +1,20 @@
+use serde::{Deserialize, Serialize};
+
+/// Rebuilds an index under its configured schema.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct ReindexJob {
+    pub index_id: String,
+
+    /// Rebuild even when the index was built from the configured schema version.
+    #[serde(default)]
+    pub force: bool,
+}
+
+impl ReindexJob {
+    pub fn create(index_id: &str) -> ReindexJob {
+        ReindexJob {
+            index_id: index_id.into(),
+            force: false,
+        }
+    }
+}
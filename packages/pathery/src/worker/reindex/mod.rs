@@ -0,0 +1,285 @@
+pub mod job;
+
+use std::time::{Duration, UNIX_EPOCH};
+
+use chrono::{DateTime, Utc};
+use serde_json as json;
+use tantivy::schema::Schema;
+use tracing::{error, info, warn};
+
+use self::job::ReindexJob;
+use crate::enrich::Enricher;
+use crate::index::{IndexExt, IndexLoader, IndexWriterExt};
+use crate::lambda::sqs::{BatchItemFailure, SqsBatchResponse};
+use crate::lambda::{self, sqs};
+use crate::schema::SchemaLoader;
+use crate::service::ServiceError;
+use crate::store::document::DocumentStore;
+use crate::store::lease::LeaseStore;
+use crate::worker::index_writer::enrich_docs;
+
+/// Number of documents fetched from the document store at a time.
+const BATCH_SIZE: usize = 100;
+
+/// The schema to rebuild into: the configured fields, plus for dynamic indexes the fields
+/// derived since the index was built, which aren't in the config.
+pub(crate) fn rebuild_schema(current: &Schema, configured: Schema, dynamic: bool) -> Schema {
+    if !dynamic {
+        return configured;
+    }
+
+    let mut schema = Schema::builder();
+    for (_, entry) in configured.fields() {
+        schema.add_field(entry.clone());
+    }
+    for (_, entry) in current.fields() {
+        if configured.get_field(entry.name()).is_none() {
+            schema.add_field(entry.clone());
+        }
+    }
+    schema.build()
+}
+
+/// Rebuilds `index_id` from the document store into a staging index under the configured
+/// schema, then swaps it in. Returns false when the index was already built from the configured
+/// schema version and `force` isn't set. The caller must hold the index's lease, so that no
+/// writer changes the index during the rebuild.
+pub async fn reindex(
+    document_store: &dyn DocumentStore,
+    index_loader: &dyn IndexLoader,
+    schema_loader: &dyn SchemaLoader,
+    enricher: &Enricher,
+    index_id: &str,
+    force: bool,
+) -> Result<bool, ServiceError> {
+    let index = index_loader.load_index(index_id, None)?;
+    let version = schema_loader.load_schema_version(index_id)?;
+
+    if !force && index.schema_version().as_deref() == Some(version.as_str()) {
+        info!(message = "reindex_skipped", index = index_id, version);
+        return Ok(false);
+    }
+
+    let settings = schema_loader.load_settings(index_id)?;
+    if settings.search_only {
+        return Err(ServiceError::invalid_request(&format!(
+            "Index [{}] is search only, its documents aren't available to rebuild it from",
+            index_id
+        )));
+    }
+
+    let current = index.schema();
+    let schema = rebuild_schema(
+        &current,
+        schema_loader.load_schema(index_id)?,
+        settings.dynamic,
+    );
+
+    let doc_refs = index.doc_refs()?;
+
+    let staging = index_loader.create_staging_index(index_id, schema.clone())?;
+    let mut writer = staging.default_writer(&settings.merge_policy);
+
+    for batch in doc_refs.chunks(BATCH_SIZE) {
+        let mut docs = document_store.get_documents(batch.to_vec()).await?;
+        enrich_docs(enricher, &settings.enrich, &mut docs).await?;
+        for doc in docs {
+            writer
+                .add_document(doc.document_with_settings(&schema, &settings))
+                .map_err(ServiceError::internal_error)?;
+        }
+    }
+
+    writer
+        .commit_with_meta()
+        .map_err(ServiceError::internal_error)?;
+    writer
+        .wait_merging_threads()
+        .map_err(ServiceError::internal_error)?;
+
+    index_loader.swap_staging_index(index_id)?;
+    info!(
+        message = "index_rebuilt",
+        index = index_id,
+        version,
+        documents = doc_refs.len()
+    );
+
+    Ok(true)
+}
+
+/// Applies a batch of reindex jobs. Indexes that another worker holds a lease on, or that fail to
+/// rebuild, are reported as batch item failures, so SQS redelivers only them.
+pub async fn handle_event(
+    document_store: &dyn DocumentStore,
+    index_loader: &dyn IndexLoader,
+    schema_loader: &dyn SchemaLoader,
+    lease_store: &dyn LeaseStore,
+    enricher: &Enricher,
+    event: sqs::SqsEvent,
+) -> Result<SqsBatchResponse, lambda::Error> {
+    let owner = event.context.request_id.clone();
+    let expires_at =
+        DateTime::<Utc>::from(UNIX_EPOCH + Duration::from_millis(event.context.deadline));
+
+    let mut response = SqsBatchResponse::default();
+
+    for message in event.payload.records {
+        let body = message.body.as_ref().expect("Body should be present");
+        let job =
+            json::from_str::<ReindexJob>(body.as_str()).expect("Message should be deserializable");
+        let index_id = job.index_id.as_str();
+
+        if !lease_store.acquire(index_id, &owner, expires_at).await? {
+            warn!(message = "index_locked", index = index_id);
+            response.batch_item_failures.push(BatchItemFailure {
+                item_identifier: message.message_id.clone().unwrap_or_default(),
+            });
+            continue;
+        }
+
+        let result = reindex(
+            document_store,
+            index_loader,
+            schema_loader,
+            enricher,
+            index_id,
+            job.force,
+        )
+        .await;
+
+        lease_store.release(index_id, &owner).await?;
+
+        // Only the failed job is redelivered, not the indexes rebuilt alongside it.
+        if let Err(err) = result {
+            error!(message = "reindex_failed", index = index_id, error = %err);
+            response.batch_item_failures.push(BatchItemFailure {
+                item_identifier: message.message_id.clone().unwrap_or_default(),
+            });
+        }
+    }
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use tantivy::collector::Count;
+    use tantivy::query::TermQuery;
+    use tantivy::schema::IndexRecordOption;
+    use tantivy::Term;
+
+    use aws_lambda_events::sqs::{SqsEvent, SqsMessage};
+    use lambda_http::Context;
+    use lambda_runtime::LambdaEvent;
+
+    use super::*;
+    use crate::schema::SchemaProvider;
+    use crate::store::lease::test_util::TestLeaseStore;
+    use crate::store::lookup::test_util::TestLookupTable;
+    use crate::test_utils::*;
+
+    #[tokio::test]
+    async fn reindex_rebuilds_under_the_configured_schema() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![
+                    json!({ "__id": "a", "title": "Hello World" }),
+                    json!({ "__id": "b", "title": "Goodbye" }),
+                ],
+            )
+            .await;
+
+        // The title field is now matched as a whole value.
+        let schema_loader = SchemaProvider::from_json(json!({
+            "indexes": [{
+                "prefix": "test",
+                "fields": [{ "name": "title", "kind": "text", "flags": ["STRING"] }]
+            }]
+        }));
+        let index_loader = ctx.index_loader().with_schema_loader(schema_loader.clone());
+
+        let rebuilt = reindex(
+            ctx.document_store(),
+            &index_loader,
+            &schema_loader,
+            &Enricher::new(TestLookupTable::create()),
+            "test",
+            false,
+        )
+        .await
+        .unwrap();
+        assert!(rebuilt);
+
+        let index = index_loader.load_index("test", None).unwrap();
+        assert_eq!(
+            Some(schema_loader.load_schema_version("test").unwrap()),
+            index.schema_version()
+        );
+
+        let title = index.schema().get_field("title").unwrap();
+        let query = TermQuery::new(
+            Term::from_field_text(title, "Hello World"),
+            IndexRecordOption::Basic,
+        );
+        let searcher = index.reader().unwrap().searcher();
+        assert_eq!(2, searcher.num_docs());
+        assert_eq!(1, searcher.search(&query, &Count).unwrap());
+
+        let rebuilt = reindex(
+            ctx.document_store(),
+            &index_loader,
+            &schema_loader,
+            &Enricher::new(TestLookupTable::create()),
+            "test",
+            false,
+        )
+        .await
+        .unwrap();
+        assert!(!rebuilt);
+    }
+
+    #[tokio::test]
+    async fn failed_jobs_are_returned_to_queue_alone() {
+        let ctx = setup()
+            .with_documents("test", vec![json!({ "__id": "a", "title": "Hello" })])
+            .await;
+
+        // Search only indexes can't be rebuilt, which fails their job but not the batch.
+        let message = |message_id: &str, index_id: &str| SqsMessage {
+            message_id: Some(message_id.into()),
+            body: Some(
+                json::to_string(&ReindexJob {
+                    index_id: index_id.into(),
+                    force: true,
+                })
+                .unwrap(),
+            ),
+            ..Default::default()
+        };
+        let event = SqsEvent {
+            records: vec![message("1", "searchonly"), message("2", "test")],
+        };
+
+        let response = handle_event(
+            ctx.document_store(),
+            ctx.index_loader(),
+            ctx.schema_loader(),
+            &TestLeaseStore::create(),
+            &Enricher::new(TestLookupTable::create()),
+            LambdaEvent::new(event, Context::default()),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            vec!["1"],
+            response
+                .batch_item_failures
+                .iter()
+                .map(|failure| failure.item_identifier.as_str())
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,67 @@
+use async_trait::async_trait;
+
+use super::job::UpdateByQueryJob;
+use crate::service::ServiceError;
+use crate::util;
+
+#[async_trait]
+pub trait UpdateByQueryClient: Sync + Send {
+    /// Queue a job for the update by query worker.
+    async fn submit_job(&self, job: &UpdateByQueryJob) -> Result<(), ServiceError>;
+}
+
+pub struct LambdaUpdateByQueryClient {
+    queue_url: String,
+
+    client: aws_sdk_sqs::Client,
+}
+
+#[async_trait]
+impl UpdateByQueryClient for LambdaUpdateByQueryClient {
+    async fn submit_job(&self, job: &UpdateByQueryJob) -> Result<(), ServiceError> {
+        let body = serde_json::to_string(job).expect("job should serialize");
+
+        self.client
+            .send_message()
+            .queue_url(&self.queue_url)
+            .message_body(body)
+            .send()
+            .await?;
+
+        Ok(())
+    }
+}
+
+impl LambdaUpdateByQueryClient {
+    pub async fn create(queue_url: Option<&str>) -> LambdaUpdateByQueryClient {
+        let sdk_config = aws_config::load_from_env().await;
+
+        LambdaUpdateByQueryClient {
+            queue_url: queue_url
+                .map(String::from)
+                .unwrap_or_else(|| util::require_env("UPDATE_BY_QUERY_QUEUE_URL")),
+            client: aws_sdk_sqs::Client::new(&sdk_config),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test_util {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    /// Holds submitted jobs for the test to run.
+    #[derive(Clone, Default)]
+    pub struct TestUpdateByQueryClient {
+        pub jobs: Arc<Mutex<Vec<UpdateByQueryJob>>>,
+    }
+
+    #[async_trait]
+    impl UpdateByQueryClient for TestUpdateByQueryClient {
+        async fn submit_job(&self, job: &UpdateByQueryJob) -> Result<(), ServiceError> {
+            self.jobs.lock().unwrap().push(job.clone());
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,29 @@
+use serde::{Deserialize, Serialize};
+
+use crate::query::Query;
+use crate::{json, util};
+
+/// Applies `set` to every document of an index matching `query`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UpdateByQueryJob {
+    pub job_id: String,
+
+    pub index_id: String,
+
+    pub query: Query,
+
+    /// Fields to set on each matching document, with null values removing the field, as a
+    /// document patch would.
+    pub set: json::Map<String, json::Value>,
+}
+
+impl UpdateByQueryJob {
+    pub fn create(index_id: &str, query: Query, set: json::Map<String, json::Value>) -> Self {
+        UpdateByQueryJob {
+            job_id: util::generate_id(),
+            index_id: index_id.into(),
+            query,
+            set,
+        }
+    }
+}
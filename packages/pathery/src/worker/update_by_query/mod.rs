@@ -0,0 +1,170 @@
+pub mod client;
+pub mod job;
+
+use serde_json as json;
+use tantivy::collector::DocSetCollector;
+use tracing::{info, warn};
+
+use self::job::UpdateByQueryJob;
+use crate::index::{IndexExt, IndexLoader};
+use crate::lambda::{self, sqs};
+use crate::schema::SchemaLoader;
+use crate::search_doc::SearchDocId;
+use crate::service::ServiceError;
+use crate::store::document::{DocumentStore, SearchDocRef};
+use crate::store::job::{JobProgress, JobStore};
+use crate::worker::index_writer::client::IndexWriterClient;
+use crate::worker::index_writer::job::Job;
+
+/// Maximum number of documents the document store returns from a single get.
+const MAX_DOCS_PER_GET: usize = 100;
+
+/// Maximum number of documents the document store accepts in a single save.
+const MAX_DOCS_PER_SAVE: usize = 25;
+
+/// Documents updated by each index writer job, progress being recorded after each.
+const MAX_DOCS_PER_JOB: usize = 250;
+
+/// The ids of the documents of `index_id` matching the job's query, as of the last commit.
+fn matching_doc_ids(
+    index_loader: &dyn IndexLoader,
+    schema_loader: &dyn SchemaLoader,
+    job: &UpdateByQueryJob,
+) -> Result<Vec<SearchDocId>, ServiceError> {
+    let settings = schema_loader.load_settings(&job.index_id)?;
+    let index = index_loader.load_index(&job.index_id, None)?;
+    let query = job.query.compile(&index, &settings)?;
+
+    let searcher = index
+        .reader()
+        .map_err(ServiceError::internal_error)?
+        .searcher();
+    let addresses = searcher
+        .search(&query, &DocSetCollector)
+        .map_err(ServiceError::internal_error)?;
+
+    let id_field = index.id_field();
+    addresses
+        .into_iter()
+        .map(|address| {
+            let document = searcher
+                .doc(address)
+                .map_err(ServiceError::internal_error)?;
+            let id = document
+                .get_first(id_field)
+                .and_then(|id| id.as_text())
+                .expect("__id should be stored");
+            Ok(SearchDocId::parse(id))
+        })
+        .collect()
+}
+
+/// Patches every document matching the job's query with its `set` fields, reading each from the
+/// document store and reindexing it through the index writer, which applies the index's enrich
+/// settings again. Progress is recorded against the job after each writer job is submitted.
+pub async fn update_by_query(
+    document_store: &dyn DocumentStore,
+    index_loader: &dyn IndexLoader,
+    schema_loader: &dyn SchemaLoader,
+    job_store: &dyn JobStore,
+    writer_client: &dyn IndexWriterClient,
+    job: &UpdateByQueryJob,
+) -> Result<JobProgress, ServiceError> {
+    let schema = schema_loader.load_schema(&job.index_id)?;
+    let settings = schema_loader.load_settings(&job.index_id)?;
+    let doc_ids = matching_doc_ids(index_loader, schema_loader, job)?;
+
+    let mut progress = JobProgress {
+        total: doc_ids.len(),
+        ..JobProgress::default()
+    };
+    job_store
+        .update_progress(&job.job_id, progress.clone())
+        .await?;
+
+    for batch in doc_ids.chunks(MAX_DOCS_PER_JOB) {
+        let mut existing = vec![];
+        for chunk in batch.chunks(MAX_DOCS_PER_GET) {
+            let doc_refs = chunk.iter().cloned().map(SearchDocRef::from).collect();
+            existing.extend(document_store.get_documents(doc_refs).await?);
+        }
+
+        let mut documents = vec![];
+        for existing in existing {
+            match existing.merge(&schema, json::Value::Object(job.set.clone()), &settings) {
+                Ok(document) => documents.push(document),
+                Err(err) => {
+                    warn!(
+                        message = "update_by_query_doc_failed",
+                        job_id = job.job_id,
+                        doc_id = existing.id().id(),
+                        error = err.to_string()
+                    );
+                    progress.failed += 1;
+                }
+            }
+        }
+
+        let mut writer_job = Job::create(&job.index_id);
+        for chunk in documents.chunks(MAX_DOCS_PER_SAVE) {
+            for doc_ref in document_store.save_documents(chunk.to_vec()).await? {
+                writer_job.index_doc(doc_ref);
+            }
+        }
+        if !documents.is_empty() {
+            progress
+                .job_ids
+                .push(writer_client.submit_job(writer_job).await?);
+        }
+
+        // Documents deleted since they matched are counted as processed rather than recreated.
+        progress.processed += batch.len();
+        job_store
+            .update_progress(&job.job_id, progress.clone())
+            .await?;
+    }
+
+    Ok(progress)
+}
+
+/// Runs each update by query job, marking it complete once every matching document has been
+/// submitted to the index writer.
+#[allow(clippy::too_many_arguments)]
+pub async fn handle_event(
+    document_store: &dyn DocumentStore,
+    index_loader: &dyn IndexLoader,
+    schema_loader: &dyn SchemaLoader,
+    job_store: &dyn JobStore,
+    writer_client: &dyn IndexWriterClient,
+    event: sqs::SqsEvent,
+) -> Result<(), lambda::Error> {
+    for message in event.payload.records {
+        let body = message.body.as_ref().expect("Body should be present");
+        let job = json::from_str::<UpdateByQueryJob>(body.as_str())
+            .expect("Message should be deserializable");
+
+        let progress = update_by_query(
+            document_store,
+            index_loader,
+            schema_loader,
+            job_store,
+            writer_client,
+            &job,
+        )
+        .await?;
+        job_store
+            .complete_jobs(std::slice::from_ref(&job.job_id))
+            .await?;
+
+        info!(
+            message = "update_by_query_complete",
+            job_id = job.job_id,
+            index = job.index_id,
+            total = progress.total,
+            failed = progress.failed,
+            writer_jobs = progress.job_ids.len()
+        );
+    }
+
+    Ok(())
+}